@@ -0,0 +1,64 @@
+//! Compile-time benchmarks.
+//!
+//! Run with `cargo bench`. The functions in `corpus.cton` are parsed and then compiled for 64-bit
+//! Intel a number of times, and the time spent parsing, in each compiler pass, and emitting
+//! machine code is reported separately.
+//!
+//! The number of iterations can be given on the command line: `cargo bench -- 100`.
+
+extern crate cretonne;
+extern crate cton_reader;
+
+use cretonne::{isa, settings, Context};
+use cretonne::settings::Configurable;
+use std::env;
+use std::time::Instant;
+
+const CORPUS: &'static str = include_str!("corpus.cton");
+
+fn main() {
+    // Cargo passes a `--bench` flag which we ignore.
+    let iterations = env::args()
+        .skip(1)
+        .find(|arg| !arg.starts_with('-'))
+        .map(|arg| arg.parse().expect("the iteration count must be a number"))
+        .unwrap_or(20);
+
+    let mut flag_builder = settings::builder();
+    flag_builder.enable("is_64bit").unwrap();
+    let isa = isa::lookup("intel")
+        .expect("the Intel ISA is not available")
+        .finish(settings::Flags::new(&flag_builder));
+
+    // The pass times accumulate in the context since it is only cleared between functions.
+    let mut ctx = Context::new();
+    let mut funcs = Vec::new();
+    for _ in 0..iterations {
+        let start = Instant::now();
+        funcs = cton_reader::parse_functions(CORPUS).unwrap();
+        ctx.timing.add("parse", start.elapsed());
+    }
+
+    let mut code = Vec::new();
+    let mut relocs = Vec::new();
+    let mut traps = Vec::new();
+    for _ in 0..iterations {
+        for func in &funcs {
+            ctx.clear();
+            ctx.func = func.clone();
+            let size = ctx.compile(&*isa)
+                .unwrap_or_else(|e| panic!("{}: {}", func.name, e));
+
+            let start = Instant::now();
+            code.resize(size as usize, 0);
+            relocs.clear();
+            traps.clear();
+            ctx.emit_to_memory(&mut code, &mut relocs, &mut traps, &*isa)
+                .unwrap();
+            ctx.timing.add("emit", start.elapsed());
+        }
+    }
+
+    println!("{} functions, {} iterations", funcs.len(), iterations);
+    print!("{}", ctx.timing);
+}
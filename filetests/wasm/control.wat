@@ -0,0 +1,28 @@
+;; test cat
+;; test verifier
+;; Structured control flow is translated to EBBs with arguments.
+(module
+  (func $fac (param i64) (result i64)
+    ;; check: function wasm_0(i64) -> i64 {
+    ;; check: brz
+    ;; check: $(prod=v\d+) = imul
+    ;; check: jump $(exit=ebb\d+)($prod)
+    ;; check: $exit($(res=v\d+): i64):
+    ;; nextln: jump $(ret=ebb\d+)($res)
+    ;; check: $ret($(rv=v\d+): i64):
+    ;; nextln: return $rv
+    (if (result i64) (i64.eqz (local.get 0))
+      (then (i64.const 1))
+      (else
+        (i64.mul (local.get 0)
+                 (call $fac (i64.sub (local.get 0) (i64.const 1)))))))
+
+  (func (param i32) (result i32)
+    ;; check: function wasm_1(i32) -> i32 {
+    ;; check: br_table v0, jt0
+    ;; check: return
+    (block
+      (block
+        (br_table 0 1 0 (local.get 0)))
+      (return (i32.const 1)))
+    (i32.const 0)))
@@ -0,0 +1,16 @@
+;; test legalizer
+;; set is_64bit
+;; isa intel
+;; The translated functions can be legalized for a real ISA.
+(module
+  (func (param i64 i64) (result i64)
+    ;; check: function wasm_0(i64 [%rdi], i64 [%rsi]) -> i64 [%rax]
+    (local i64)
+    (local.set 2 (i64.const 0))
+    (block
+      (loop
+        (br_if 1 (i64.eqz (local.get 0)))
+        (local.set 2 (i64.add (local.get 2) (local.get 1)))
+        (local.set 0 (i64.sub (local.get 0) (i64.const 1)))
+        (br 0)))
+    (local.get 2)))
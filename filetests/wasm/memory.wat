@@ -0,0 +1,17 @@
+;; test cat
+;; test verifier
+;; set is_64bit
+;; Memory accesses get their address from `heap_addr`, and the static offset is folded into
+;; the load or store.
+(module
+  (memory 1)
+  (func (param i32) (result i32)
+    ;; check: $(idx=v\d+) = uextend.i64 v0
+    ;; check: $(addr=v\d+) = heap_addr $idx+12
+    ;; check: load.i32 $addr+8
+    (i32.load offset=8 (local.get 0)))
+
+  (func (param i32 i64)
+    ;; check: $(addr=v\d+) = heap_addr
+    ;; check: istore8 v1, $addr
+    (i64.store8 (local.get 0) (local.get 1))))
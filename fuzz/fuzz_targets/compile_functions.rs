@@ -0,0 +1,84 @@
+//! Fuzz the whole compiler pipeline.
+//!
+//! Functions that parse and pass the verifier are compiled for every ISA. The verifier is enabled
+//! between passes, so a verifier error after any pass is a compiler bug, just like a panic.
+//!
+//! Each function is compiled twice, once in a reused context and once in a new context, and the
+//! two compilations must produce identical machine code, relocations, and trap sites.
+
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate cretonne;
+extern crate cton_reader;
+
+use cretonne::{isa, settings, verify_function, CompiledFunction, Context};
+use cretonne::ir::Function;
+use cretonne::isa::TargetIsa;
+use cretonne::result::{CtonError, ErrorKind};
+use cretonne::settings::Configurable;
+use std::str;
+
+/// The ISAs to compile for, with the shared settings to use for each.
+///
+/// The ARM ISAs are not included since they don't implement the ABI yet.
+const TARGETS: [(&'static str, &'static str); 4] = [("riscv", ""),
+                                                    ("riscv", "is_64bit"),
+                                                    ("intel", ""),
+                                                    ("intel", "is_64bit")];
+
+fuzz_target!(|data: &[u8]| {
+    let text = match str::from_utf8(data) {
+        Ok(text) => text,
+        Err(_) => return,
+    };
+    let funcs = match cton_reader::parse_functions(text) {
+        Ok(funcs) => funcs,
+        Err(_) => return,
+    };
+
+    let mut ctx = Context::new();
+    for func in funcs {
+        // Invalid input functions are not interesting.
+        if verify_function(&func).is_err() {
+            continue;
+        }
+
+        for &(name, shared) in &TARGETS {
+            let isa_builder = match isa::lookup(name) {
+                Ok(b) => b,
+                Err(_) => continue,
+            };
+            let mut flag_builder = settings::builder();
+            flag_builder.enable_all("enable_verifier").unwrap();
+            flag_builder.enable_all(shared).unwrap();
+            let isa = isa_builder.finish(settings::Flags::new(&flag_builder));
+
+            let first = match compile(&mut ctx, &func, &*isa) {
+                Some(output) => output,
+                None => continue,
+            };
+            let second = compile(&mut Context::new(), &func, &*isa);
+            assert!(second == Some(first),
+                    "{}: compilation is not deterministic\n{}",
+                    name,
+                    func.display(None));
+        }
+    }
+});
+
+/// Compile and emit a copy of `func`, returning `None` if it hits an implementation limit or
+/// something the ISA doesn't support.
+fn compile(ctx: &mut Context,
+           func: &Function,
+           isa: &TargetIsa)
+           -> Option<CompiledFunction> {
+    ctx.func = func.clone();
+    match ctx.compile_and_emit(isa) {
+        Ok(compiled) => Some(compiled),
+        Err(CtonError { kind: ErrorKind::ImplLimitExceeded, .. }) |
+        Err(CtonError { kind: ErrorKind::CodeTooLarge, .. }) |
+        Err(CtonError { kind: ErrorKind::Unsupported, .. }) => None,
+        Err(e) => panic!("{}: {}\n{}", isa.name(), e, ctx.func.display(isa)),
+    }
+}
@@ -0,0 +1,15 @@
+//! Fuzz the lexer and parser with arbitrary text.
+
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate cton_reader;
+
+use std::str;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(text) = str::from_utf8(data) {
+        // Parse errors are expected, but the parser must not panic.
+        let _ = cton_reader::parse_functions(text);
+    }
+});
@@ -0,0 +1,102 @@
+/*
+ * C API for the Cretonne code generator.
+ *
+ * Link with the `cton_c` static or shared library built by the `cretonne-c` crate. See
+ * `lib/capi/src/lib.rs` for the documentation of each function.
+ *
+ * EBBs and values are identified by their entity numbers. Functions returning an entity number
+ * return CTON_INVALID on failure, and functions returning an `int` return CTON_OK or CTON_ERROR.
+ * The message describing the last failure is available from cton_context_error().
+ */
+
+#ifndef CRETONNE_H
+#define CRETONNE_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define CTON_OK 0
+#define CTON_ERROR (-1)
+#define CTON_INVALID UINT32_MAX
+
+/* Value type codes. */
+#define CTON_TYPE_B1 1
+#define CTON_TYPE_I8 2
+#define CTON_TYPE_I16 3
+#define CTON_TYPE_I32 4
+#define CTON_TYPE_I64 5
+#define CTON_TYPE_F32 6
+#define CTON_TYPE_F64 7
+
+/* Relocation target kinds. */
+#define CTON_RELOC_EBB 0
+#define CTON_RELOC_FUNC 1
+#define CTON_RELOC_EXTERNAL 2
+#define CTON_RELOC_JUMP_TABLE 3
+
+typedef struct CtonContext CtonContext;
+typedef struct CtonIsa CtonIsa;
+
+typedef struct CtonReloc {
+    uint32_t offset;
+    uint16_t kind;
+    uint32_t target_kind;
+    uint32_t target;
+    const char *name;
+    int64_t addend;
+} CtonReloc;
+
+/* Contexts. */
+CtonContext *cton_context_create(void);
+void cton_context_free(CtonContext *ctx);
+const char *cton_context_error(const CtonContext *ctx);
+void cton_context_clear(CtonContext *ctx);
+
+/* Target ISAs. `settings` is a comma-separated list like "is_64bit,opt_level=best". */
+CtonIsa *cton_isa_create(CtonContext *ctx, const char *target, const char *settings);
+void cton_isa_free(CtonIsa *isa);
+
+/* Building the function. */
+int cton_context_parse(CtonContext *ctx, const char *text);
+int cton_function_set_name(CtonContext *ctx, const char *name);
+int cton_function_append_param(CtonContext *ctx, uint32_t ty);
+int cton_function_append_return(CtonContext *ctx, uint32_t ty);
+uint32_t cton_ebb_create(CtonContext *ctx);
+uint32_t cton_ebb_append_param(CtonContext *ctx, uint32_t ebb, uint32_t ty);
+
+/* Instructions are appended to the end of `ebb`. */
+uint32_t cton_ins_iconst(CtonContext *ctx, uint32_t ebb, uint32_t ty, int64_t imm);
+uint32_t cton_ins_unary(CtonContext *ctx, uint32_t ebb, const char *opcode, uint32_t ty,
+                        uint32_t arg);
+uint32_t cton_ins_binary(CtonContext *ctx, uint32_t ebb, const char *opcode, uint32_t lhs,
+                         uint32_t rhs);
+uint32_t cton_ins_binary_imm(CtonContext *ctx, uint32_t ebb, const char *opcode, uint32_t lhs,
+                             int64_t imm);
+uint32_t cton_ins_icmp(CtonContext *ctx, uint32_t ebb, const char *cond, uint32_t lhs,
+                       uint32_t rhs);
+int cton_ins_jump(CtonContext *ctx, uint32_t ebb, uint32_t dest, const uint32_t *args,
+                  size_t count);
+int cton_ins_branch(CtonContext *ctx, uint32_t ebb, int nonzero, uint32_t cond, uint32_t dest,
+                    const uint32_t *args, size_t count);
+int cton_ins_return(CtonContext *ctx, uint32_t ebb, const uint32_t *values, size_t count);
+
+/* Compiling. */
+int cton_context_compile(CtonContext *ctx, const CtonIsa *isa);
+const uint8_t *cton_context_code(const CtonContext *ctx);
+size_t cton_context_code_size(const CtonContext *ctx);
+size_t cton_context_num_relocs(const CtonContext *ctx);
+int cton_context_reloc(CtonContext *ctx, size_t index, CtonReloc *out);
+
+/* Printing. The returned string must be freed with cton_string_free(). `isa` may be NULL. */
+char *cton_context_print(const CtonContext *ctx, const CtonIsa *isa);
+void cton_string_free(char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* CRETONNE_H */
@@ -0,0 +1,867 @@
+//! C API for Cretonne.
+//!
+//! This crate exposes the Cretonne code generator through a set of `extern "C"` functions so it
+//! can be embedded in virtual machines written in C or C++. The declarations are in
+//! `include/cretonne.h`, and the crate builds both a static and a shared library.
+//!
+//! A host creates a `CtonContext` holding the function being compiled and a `CtonIsa` describing
+//! the target. The function is either parsed from Cretonne IL text or built one instruction at a
+//! time with the `cton_ins_*` functions. After `cton_context_compile()`, the machine code and the
+//! relocations can be read back from the context.
+//!
+//! EBBs and values are referred to by their entity numbers, and types by the `CTON_TYPE_*` codes.
+//! Functions that can fail return `CTON_ERROR` or `CTON_INVALID` and record a message that can be
+//! retrieved with `cton_context_error()`. Invalid entity references are reported as errors rather
+//! than panics since unwinding into C is not allowed.
+//!
+//! The functions taking pointers are unsafe: contexts and ISAs must come from the corresponding
+//! `create` function and not be used after they are freed, strings must be NUL-terminated, and
+//! arrays must hold at least `count` elements.
+
+#![deny(missing_docs)]
+
+extern crate cretonne;
+extern crate cton_reader;
+
+use cretonne::Context;
+use cretonne::binemit::{RelocRecord, RelocTarget, TrapRecord};
+use cretonne::entity_map::EntityRef;
+use cretonne::ir::{Ebb, Value, Type, Opcode, InstBuilder, Cursor, ArgumentType, FunctionName};
+use cretonne::ir::instructions::InstructionFormat;
+use cretonne::ir::condcodes::IntCC;
+use cretonne::ir::types;
+use cretonne::isa::{self, TargetIsa};
+use cretonne::print_errors::pretty_error;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::{ptr, slice, u32};
+
+/// Status returned by functions that succeed.
+pub const CTON_OK: c_int = 0;
+
+/// Status returned by functions that fail.
+pub const CTON_ERROR: c_int = -1;
+
+/// Entity number returned instead of an EBB or a value when a function fails.
+pub const CTON_INVALID: u32 = u32::MAX;
+
+/// Type code for `b1`.
+pub const CTON_TYPE_B1: u32 = 1;
+/// Type code for `i8`.
+pub const CTON_TYPE_I8: u32 = 2;
+/// Type code for `i16`.
+pub const CTON_TYPE_I16: u32 = 3;
+/// Type code for `i32`.
+pub const CTON_TYPE_I32: u32 = 4;
+/// Type code for `i64`.
+pub const CTON_TYPE_I64: u32 = 5;
+/// Type code for `f32`.
+pub const CTON_TYPE_F32: u32 = 6;
+/// Type code for `f64`.
+pub const CTON_TYPE_F64: u32 = 7;
+
+/// Relocation target kind: an EBB in the same function.
+pub const CTON_RELOC_EBB: u32 = 0;
+/// Relocation target kind: a function declared in the function preamble.
+pub const CTON_RELOC_FUNC: u32 = 1;
+/// Relocation target kind: an external symbol.
+pub const CTON_RELOC_EXTERNAL: u32 = 2;
+/// Relocation target kind: a jump table.
+pub const CTON_RELOC_JUMP_TABLE: u32 = 3;
+
+/// A target ISA with its settings.
+pub struct CtonIsa {
+    isa: Box<TargetIsa>,
+}
+
+/// A compilation context holding the function being compiled and the compiled code.
+pub struct CtonContext {
+    ctx: Context,
+    code: Vec<u8>,
+    relocs: Vec<RelocRecord>,
+    // Names of the `External` relocation targets, indexed like `relocs`.
+    reloc_names: Vec<Option<CString>>,
+    traps: Vec<TrapRecord>,
+    error: CString,
+}
+
+/// A relocation in the compiled code, as returned by `cton_context_reloc()`.
+#[repr(C)]
+pub struct CtonReloc {
+    /// Offset of the relocated location from the beginning of the function.
+    pub offset: u32,
+    /// The ISA-specific relocation kind.
+    pub kind: u16,
+    /// One of the `CTON_RELOC_*` target kinds.
+    pub target_kind: u32,
+    /// The entity number of the target EBB, function, or jump table.
+    pub target: u32,
+    /// Symbol name of an external target, or null. Owned by the context.
+    pub name: *const c_char,
+    /// Addend to add to the target address.
+    pub addend: i64,
+}
+
+impl CtonContext {
+    /// Record an error message and return `CTON_ERROR`.
+    fn fail<S: Into<String>>(&mut self, msg: S) -> c_int {
+        let msg = msg.into().replace('\0', " ");
+        self.error = CString::new(msg).expect("NUL bytes were removed");
+        CTON_ERROR
+    }
+
+    /// Record an error message and return `CTON_INVALID`.
+    fn fail_invalid<S: Into<String>>(&mut self, msg: S) -> u32 {
+        self.fail(msg);
+        CTON_INVALID
+    }
+
+    /// Check that `ebb` refers to an existing EBB.
+    fn ebb(&mut self, ebb: u32) -> Result<Ebb, ()> {
+        // `Ebb::new()` panics on `CTON_INVALID`.
+        if ebb != CTON_INVALID {
+            let e = Ebb::new(ebb as usize);
+            if self.ctx.func.dfg.ebb_is_valid(e) {
+                return Ok(e);
+            }
+        }
+        self.fail(format!("invalid EBB number {}", ebb));
+        Err(())
+    }
+
+    /// Check that `value` refers to an existing value.
+    fn value(&mut self, value: u32) -> Result<Value, ()> {
+        // `Value::new()` panics on `CTON_INVALID`.
+        if value != CTON_INVALID {
+            let v = Value::new(value as usize);
+            if self.ctx.func.dfg.value_is_valid(v) {
+                return Ok(v);
+            }
+        }
+        self.fail(format!("invalid value number {}", value));
+        Err(())
+    }
+
+    /// Check a list of values.
+    fn values(&mut self, values: *const u32, count: usize) -> Result<Vec<Value>, ()> {
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+        if values.is_null() {
+            self.fail("null value list");
+            return Err(());
+        }
+        let values = unsafe { slice::from_raw_parts(values, count) };
+        values.iter().map(|&v| self.value(v)).collect()
+    }
+
+    /// Decode a `CTON_TYPE_*` code.
+    fn ty(&mut self, code: u32) -> Result<Type, ()> {
+        match code {
+            CTON_TYPE_B1 => Ok(types::B1),
+            CTON_TYPE_I8 => Ok(types::I8),
+            CTON_TYPE_I16 => Ok(types::I16),
+            CTON_TYPE_I32 => Ok(types::I32),
+            CTON_TYPE_I64 => Ok(types::I64),
+            CTON_TYPE_F32 => Ok(types::F32),
+            CTON_TYPE_F64 => Ok(types::F64),
+            _ => {
+                self.fail(format!("invalid type code {}", code));
+                Err(())
+            }
+        }
+    }
+
+    /// Look up an opcode by name and check its instruction format.
+    fn opcode(&mut self, name: *const c_char, format: InstructionFormat) -> Result<Opcode, ()> {
+        let opcode = match c_str(name).map(str::parse::<Opcode>) {
+            Ok(Ok(opcode)) => opcode,
+            _ => {
+                self.fail("unknown opcode");
+                return Err(());
+            }
+        };
+        if opcode.format() != format {
+            self.fail(format!("{} is not a {:?} instruction", opcode, format));
+            return Err(());
+        }
+        Ok(opcode)
+    }
+}
+
+/// Convert a C string argument.
+fn c_str<'a>(s: *const c_char) -> Result<&'a str, String> {
+    if s.is_null() {
+        return Err("null string".to_string());
+    }
+    unsafe { CStr::from_ptr(s) }
+        .to_str()
+        .map_err(|_| "string is not valid UTF-8".to_string())
+}
+
+/// Create a new compilation context with an empty function.
+///
+/// The context must be freed with `cton_context_free()`.
+#[no_mangle]
+pub extern "C" fn cton_context_create() -> *mut CtonContext {
+    Box::into_raw(Box::new(CtonContext {
+                               ctx: Context::new(),
+                               code: Vec::new(),
+                               relocs: Vec::new(),
+                               reloc_names: Vec::new(),
+                               traps: Vec::new(),
+                               error: CString::default(),
+                           }))
+}
+
+/// Free a context created by `cton_context_create()`.
+#[no_mangle]
+pub unsafe extern "C" fn cton_context_free(ctx: *mut CtonContext) {
+    if !ctx.is_null() {
+        drop(Box::from_raw(ctx));
+    }
+}
+
+/// Get the message describing the last error reported by a function taking `ctx`.
+///
+/// The string is owned by the context and is valid until the next error.
+#[no_mangle]
+pub unsafe extern "C" fn cton_context_error(ctx: *const CtonContext) -> *const c_char {
+    (*ctx).error.as_ptr()
+}
+
+/// Start over with an empty function, keeping the allocated memory.
+#[no_mangle]
+pub unsafe extern "C" fn cton_context_clear(ctx: *mut CtonContext) {
+    let ctx = &mut *ctx;
+    ctx.ctx.clear();
+    ctx.code.clear();
+    ctx.relocs.clear();
+    ctx.reloc_names.clear();
+    ctx.traps.clear();
+}
+
+/// Create a target ISA from a target name or triple and a comma-separated list of settings.
+///
+/// The settings are `name=value` pairs or boolean flag names, and `settings` may be null. Returns
+/// null and reports an error in `ctx` if the target is unknown or a setting is invalid. The ISA
+/// must be freed with `cton_isa_free()`.
+#[no_mangle]
+pub unsafe extern "C" fn cton_isa_create(ctx: *mut CtonContext,
+                                         target: *const c_char,
+                                         settings: *const c_char)
+                                         -> *mut CtonIsa {
+    let ctx = &mut *ctx;
+    let set = if settings.is_null() {
+        Ok("")
+    } else {
+        c_str(settings)
+    };
+    let isa = match (c_str(target), set) {
+        (Ok(t), Ok(s)) => isa::build(t, s).map_err(|e| e.to_string()),
+        (Err(msg), _) |
+        (_, Err(msg)) => Err(msg),
+    };
+    match isa {
+        Ok(isa) => Box::into_raw(Box::new(CtonIsa { isa: isa })),
+        Err(msg) => {
+            ctx.fail(msg);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Free an ISA created by `cton_isa_create()`.
+#[no_mangle]
+pub unsafe extern "C" fn cton_isa_free(isa: *mut CtonIsa) {
+    if !isa.is_null() {
+        drop(Box::from_raw(isa));
+    }
+}
+
+/// Replace the function in `ctx` with the first function in the Cretonne IL `text`.
+#[no_mangle]
+pub unsafe extern "C" fn cton_context_parse(ctx: *mut CtonContext, text: *const c_char) -> c_int {
+    let ctx = &mut *ctx;
+    let text = match c_str(text) {
+        Ok(t) => t,
+        Err(msg) => return ctx.fail(msg),
+    };
+    match cton_reader::parse_functions(text) {
+        Ok(funcs) => {
+            match funcs.into_iter().next() {
+                Some(func) => {
+                    ctx.ctx.clear();
+                    ctx.ctx.func = func;
+                    CTON_OK
+                }
+                None => ctx.fail("no function found"),
+            }
+        }
+        Err(e) => ctx.fail(e.to_string()),
+    }
+}
+
+/// Set the name of the function in `ctx`.
+#[no_mangle]
+pub unsafe extern "C" fn cton_function_set_name(ctx: *mut CtonContext,
+                                                name: *const c_char)
+                                                -> c_int {
+    let ctx = &mut *ctx;
+    match c_str(name) {
+        Ok(name) => {
+            ctx.ctx.func.name = FunctionName::new(name);
+            CTON_OK
+        }
+        Err(msg) => ctx.fail(msg),
+    }
+}
+
+/// Append an argument of type `ty` to the function signature.
+///
+/// The entry EBB must have matching arguments.
+#[no_mangle]
+pub unsafe extern "C" fn cton_function_append_param(ctx: *mut CtonContext, ty: u32) -> c_int {
+    let ctx = &mut *ctx;
+    match ctx.ty(ty) {
+        Ok(ty) => {
+            ctx.ctx
+                .func
+                .signature
+                .argument_types
+                .push(ArgumentType::new(ty));
+            CTON_OK
+        }
+        Err(()) => CTON_ERROR,
+    }
+}
+
+/// Append a return value of type `ty` to the function signature.
+#[no_mangle]
+pub unsafe extern "C" fn cton_function_append_return(ctx: *mut CtonContext, ty: u32) -> c_int {
+    let ctx = &mut *ctx;
+    match ctx.ty(ty) {
+        Ok(ty) => {
+            ctx.ctx
+                .func
+                .signature
+                .return_types
+                .push(ArgumentType::new(ty));
+            CTON_OK
+        }
+        Err(()) => CTON_ERROR,
+    }
+}
+
+/// Create a new EBB and append it to the function layout.
+///
+/// The first EBB created is the entry block.
+#[no_mangle]
+pub unsafe extern "C" fn cton_ebb_create(ctx: *mut CtonContext) -> u32 {
+    let func = &mut (*ctx).ctx.func;
+    let ebb = func.dfg.make_ebb();
+    func.layout.append_ebb(ebb);
+    ebb.index() as u32
+}
+
+/// Append an argument of type `ty` to `ebb` and return the new value.
+#[no_mangle]
+pub unsafe extern "C" fn cton_ebb_append_param(ctx: *mut CtonContext, ebb: u32, ty: u32) -> u32 {
+    let ctx = &mut *ctx;
+    let (ebb, ty) = match (ctx.ebb(ebb), ctx.ty(ty)) {
+        (Ok(ebb), Ok(ty)) => (ebb, ty),
+        _ => return CTON_INVALID,
+    };
+    ctx.ctx.func.dfg.append_ebb_arg(ebb, ty).index() as u32
+}
+
+/// Insert an `iconst` instruction of type `ty` at the end of `ebb`.
+///
+/// The type must be an integer type.
+#[no_mangle]
+pub unsafe extern "C" fn cton_ins_iconst(ctx: *mut CtonContext,
+                                         ebb: u32,
+                                         ty: u32,
+                                         imm: i64)
+                                         -> u32 {
+    let ctx = &mut *ctx;
+    let (ebb, ty) = match (ctx.ebb(ebb), ctx.ty(ty)) {
+        (Ok(ebb), Ok(ty)) => (ebb, ty),
+        _ => return CTON_INVALID,
+    };
+    if !ty.is_int() {
+        return ctx.fail_invalid(format!("iconst needs an integer type, not {}", ty));
+    }
+    let func = &mut ctx.ctx.func;
+    let pos = &mut Cursor::new(&mut func.layout);
+    pos.goto_bottom(ebb);
+    func.dfg.ins(pos).iconst(ty, imm).index() as u32
+}
+
+/// Insert the unary instruction `opcode` at the end of `ebb` and return its first result.
+///
+/// The result type `ty` is only used by instructions like `uextend` that can't infer their
+/// controlling type from the argument.
+#[no_mangle]
+pub unsafe extern "C" fn cton_ins_unary(ctx: *mut CtonContext,
+                                        ebb: u32,
+                                        opcode: *const c_char,
+                                        ty: u32,
+                                        arg: u32)
+                                        -> u32 {
+    let ctx = &mut *ctx;
+    let (ebb, opcode, arg) = match (ctx.ebb(ebb),
+                                    ctx.opcode(opcode, InstructionFormat::Unary),
+                                    ctx.value(arg)) {
+        (Ok(ebb), Ok(opcode), Ok(arg)) => (ebb, opcode, arg),
+        _ => return CTON_INVALID,
+    };
+    let ctrl_type = if opcode.constraints().use_typevar_operand() {
+        ctx.ctx.func.dfg.value_type(arg)
+    } else {
+        match ctx.ty(ty) {
+            Ok(ty) => ty,
+            Err(()) => return CTON_INVALID,
+        }
+    };
+    let func = &mut ctx.ctx.func;
+    let pos = &mut Cursor::new(&mut func.layout);
+    pos.goto_bottom(ebb);
+    let (inst, dfg) = func.dfg.ins(pos).Unary(opcode, ctrl_type, arg);
+    match dfg.inst_results(inst).first() {
+        Some(res) => res.index() as u32,
+        None => CTON_INVALID,
+    }
+}
+
+/// Insert the binary instruction `opcode` at the end of `ebb` and return its first result.
+#[no_mangle]
+pub unsafe extern "C" fn cton_ins_binary(ctx: *mut CtonContext,
+                                         ebb: u32,
+                                         opcode: *const c_char,
+                                         lhs: u32,
+                                         rhs: u32)
+                                         -> u32 {
+    let ctx = &mut *ctx;
+    let (ebb, opcode, lhs, rhs) = match (ctx.ebb(ebb),
+                                         ctx.opcode(opcode, InstructionFormat::Binary),
+                                         ctx.value(lhs),
+                                         ctx.value(rhs)) {
+        (Ok(ebb), Ok(opcode), Ok(lhs), Ok(rhs)) => (ebb, opcode, lhs, rhs),
+        _ => return CTON_INVALID,
+    };
+    let func = &mut ctx.ctx.func;
+    let ctrl_type = func.dfg.value_type(lhs);
+    let pos = &mut Cursor::new(&mut func.layout);
+    pos.goto_bottom(ebb);
+    let (inst, dfg) = func.dfg.ins(pos).Binary(opcode, ctrl_type, lhs, rhs);
+    match dfg.inst_results(inst).first() {
+        Some(res) => res.index() as u32,
+        None => CTON_INVALID,
+    }
+}
+
+/// Insert the binary instruction `opcode` with an immediate right-hand side at the end of `ebb`.
+#[no_mangle]
+pub unsafe extern "C" fn cton_ins_binary_imm(ctx: *mut CtonContext,
+                                             ebb: u32,
+                                             opcode: *const c_char,
+                                             lhs: u32,
+                                             imm: i64)
+                                             -> u32 {
+    let ctx = &mut *ctx;
+    let (ebb, opcode, lhs) = match (ctx.ebb(ebb),
+                                    ctx.opcode(opcode, InstructionFormat::BinaryImm),
+                                    ctx.value(lhs)) {
+        (Ok(ebb), Ok(opcode), Ok(lhs)) => (ebb, opcode, lhs),
+        _ => return CTON_INVALID,
+    };
+    let func = &mut ctx.ctx.func;
+    let ctrl_type = func.dfg.value_type(lhs);
+    let pos = &mut Cursor::new(&mut func.layout);
+    pos.goto_bottom(ebb);
+    let (inst, dfg) = func.dfg
+        .ins(pos)
+        .BinaryImm(opcode, ctrl_type, imm.into(), lhs);
+    match dfg.inst_results(inst).first() {
+        Some(res) => res.index() as u32,
+        None => CTON_INVALID,
+    }
+}
+
+/// Insert an `icmp` instruction with the condition code `cond`, like "slt", at the end of `ebb`.
+#[no_mangle]
+pub unsafe extern "C" fn cton_ins_icmp(ctx: *mut CtonContext,
+                                       ebb: u32,
+                                       cond: *const c_char,
+                                       lhs: u32,
+                                       rhs: u32)
+                                       -> u32 {
+    let ctx = &mut *ctx;
+    let cond = match c_str(cond).map(str::parse::<IntCC>) {
+        Ok(Ok(cond)) => cond,
+        _ => return ctx.fail_invalid("unknown condition code"),
+    };
+    let (ebb, lhs, rhs) = match (ctx.ebb(ebb), ctx.value(lhs), ctx.value(rhs)) {
+        (Ok(ebb), Ok(lhs), Ok(rhs)) => (ebb, lhs, rhs),
+        _ => return CTON_INVALID,
+    };
+    let func = &mut ctx.ctx.func;
+    let pos = &mut Cursor::new(&mut func.layout);
+    pos.goto_bottom(ebb);
+    func.dfg.ins(pos).icmp(cond, lhs, rhs).index() as u32
+}
+
+/// Insert a `jump` to `dest` passing `count` arguments at the end of `ebb`.
+#[no_mangle]
+pub unsafe extern "C" fn cton_ins_jump(ctx: *mut CtonContext,
+                                       ebb: u32,
+                                       dest: u32,
+                                       args: *const u32,
+                                       count: usize)
+                                       -> c_int {
+    let ctx = &mut *ctx;
+    let (ebb, dest, args) = match (ctx.ebb(ebb), ctx.ebb(dest), ctx.values(args, count)) {
+        (Ok(ebb), Ok(dest), Ok(args)) => (ebb, dest, args),
+        _ => return CTON_ERROR,
+    };
+    let func = &mut ctx.ctx.func;
+    let pos = &mut Cursor::new(&mut func.layout);
+    pos.goto_bottom(ebb);
+    func.dfg.ins(pos).jump(dest, &args);
+    CTON_OK
+}
+
+/// Insert a branch to `dest` at the end of `ebb`, taken when `cond` is non-zero if `nonzero` is
+/// set, or when it is zero otherwise.
+#[no_mangle]
+pub unsafe extern "C" fn cton_ins_branch(ctx: *mut CtonContext,
+                                         ebb: u32,
+                                         nonzero: c_int,
+                                         cond: u32,
+                                         dest: u32,
+                                         args: *const u32,
+                                         count: usize)
+                                         -> c_int {
+    let ctx = &mut *ctx;
+    let (ebb, cond, dest, args) =
+        match (ctx.ebb(ebb), ctx.value(cond), ctx.ebb(dest), ctx.values(args, count)) {
+            (Ok(ebb), Ok(cond), Ok(dest), Ok(args)) => (ebb, cond, dest, args),
+            _ => return CTON_ERROR,
+        };
+    let func = &mut ctx.ctx.func;
+    let pos = &mut Cursor::new(&mut func.layout);
+    pos.goto_bottom(ebb);
+    if nonzero != 0 {
+        func.dfg.ins(pos).brnz(cond, dest, &args);
+    } else {
+        func.dfg.ins(pos).brz(cond, dest, &args);
+    }
+    CTON_OK
+}
+
+/// Insert a `return` of `count` values at the end of `ebb`.
+#[no_mangle]
+pub unsafe extern "C" fn cton_ins_return(ctx: *mut CtonContext,
+                                         ebb: u32,
+                                         values: *const u32,
+                                         count: usize)
+                                         -> c_int {
+    let ctx = &mut *ctx;
+    let (ebb, values) = match (ctx.ebb(ebb), ctx.values(values, count)) {
+        (Ok(ebb), Ok(values)) => (ebb, values),
+        _ => return CTON_ERROR,
+    };
+    let func = &mut ctx.ctx.func;
+    let pos = &mut Cursor::new(&mut func.layout);
+    pos.goto_bottom(ebb);
+    func.dfg.ins(pos).return_(&values);
+    CTON_OK
+}
+
+/// Compile the function in `ctx` for `isa` and emit the machine code into the context.
+///
+/// On success, the code can be read with `cton_context_code()` and the relocations with
+/// `cton_context_reloc()`. A verifier error is reported with the IL surrounding the offending
+/// instruction. If the code generator panics, the function in the context is cleared and an
+/// internal compiler error is reported.
+#[no_mangle]
+pub unsafe extern "C" fn cton_context_compile(ctx: *mut CtonContext, isa: *const CtonIsa) -> c_int {
+    let ctx = &mut *ctx;
+    if isa.is_null() {
+        return ctx.fail("null ISA");
+    }
+    let isa = &*(*isa).isa;
+    ctx.code.clear();
+    ctx.relocs.clear();
+    ctx.reloc_names.clear();
+    ctx.traps.clear();
+
+    // A panic in the code generator must not unwind into the host.
+    let result = {
+        let (comp_ctx, code, relocs, traps) =
+            (&mut ctx.ctx, &mut ctx.code, &mut ctx.relocs, &mut ctx.traps);
+        panic::catch_unwind(AssertUnwindSafe(|| -> Result<(), String> {
+            let compiled = comp_ctx
+                .compile_and_emit(isa)
+                .map_err(|e| pretty_error(&comp_ctx.func, Some(isa), e))?;
+            *code = compiled.code;
+            *relocs = compiled.relocs;
+            *traps = compiled.traps;
+            Ok(())
+        }))
+    };
+    match result {
+        Ok(Ok(())) => {}
+        Ok(Err(msg)) => return ctx.fail(msg),
+        Err(_) => {
+            ctx.ctx.clear();
+            ctx.code.clear();
+            ctx.relocs.clear();
+            ctx.traps.clear();
+            return ctx.fail("internal compiler error");
+        }
+    }
+    ctx.reloc_names = ctx.relocs
+        .iter()
+        .map(|r| match r.target {
+                 RelocTarget::External(ref name) => {
+                     Some(CString::new(name.as_str().replace('\0', " "))
+                              .expect("NUL bytes were removed"))
+                 }
+                 _ => None,
+             })
+        .collect();
+    CTON_OK
+}
+
+/// Get a pointer to the machine code emitted by the last `cton_context_compile()`.
+///
+/// The code is `cton_context_code_size()` bytes long and owned by the context.
+#[no_mangle]
+pub unsafe extern "C" fn cton_context_code(ctx: *const CtonContext) -> *const u8 {
+    (*ctx).code.as_ptr()
+}
+
+/// Get the size in bytes of the machine code emitted by the last `cton_context_compile()`.
+#[no_mangle]
+pub unsafe extern "C" fn cton_context_code_size(ctx: *const CtonContext) -> usize {
+    (*ctx).code.len()
+}
+
+/// Get the number of relocations in the machine code.
+#[no_mangle]
+pub unsafe extern "C" fn cton_context_num_relocs(ctx: *const CtonContext) -> usize {
+    (*ctx).relocs.len()
+}
+
+/// Get the relocation number `index` in `*out`.
+#[no_mangle]
+pub unsafe extern "C" fn cton_context_reloc(ctx: *mut CtonContext,
+                                            index: usize,
+                                            out: *mut CtonReloc)
+                                            -> c_int {
+    let ctx = &mut *ctx;
+    if index >= ctx.relocs.len() {
+        return ctx.fail(format!("relocation {} out of range", index));
+    }
+    let reloc = &ctx.relocs[index];
+    let (target_kind, target) = match reloc.target {
+        RelocTarget::Ebb(ebb) => (CTON_RELOC_EBB, ebb.index() as u32),
+        RelocTarget::Func(func) => (CTON_RELOC_FUNC, func.index() as u32),
+        RelocTarget::External(_) => (CTON_RELOC_EXTERNAL, CTON_INVALID),
+        RelocTarget::JumpTable(jt) => (CTON_RELOC_JUMP_TABLE, jt.index() as u32),
+    };
+    *out = CtonReloc {
+        offset: reloc.offset,
+        kind: reloc.kind.0,
+        target_kind: target_kind,
+        target: target,
+        name: ctx.reloc_names[index]
+            .as_ref()
+            .map_or(ptr::null(), |n| n.as_ptr()),
+        addend: reloc.addend,
+    };
+    CTON_OK
+}
+
+/// Print the function in `ctx` as Cretonne IL.
+///
+/// If `isa` is not null, the text is annotated with encodings and value locations. The returned
+/// string must be freed with `cton_string_free()`.
+#[no_mangle]
+pub unsafe extern "C" fn cton_context_print(ctx: *const CtonContext,
+                                            isa: *const CtonIsa)
+                                            -> *mut c_char {
+    let func = &(*ctx).ctx.func;
+    let text = if isa.is_null() {
+        func.to_string()
+    } else {
+        func.display(&*(*isa).isa).to_string()
+    };
+    CString::new(text.replace('\0', " "))
+        .expect("NUL bytes were removed")
+        .into_raw()
+}
+
+/// Free a string returned by `cton_context_print()`.
+#[no_mangle]
+pub unsafe extern "C" fn cton_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::{CStr, CString};
+    use std::ptr;
+
+    fn error(ctx: *mut CtonContext) -> String {
+        unsafe { CStr::from_ptr(cton_context_error(ctx)) }
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn build_and_compile() {
+        let target = CString::new("intel").unwrap();
+        let settings = CString::new("is_64bit").unwrap();
+        let iadd = CString::new("iadd").unwrap();
+        let slt = CString::new("slt").unwrap();
+        unsafe {
+            let ctx = cton_context_create();
+            let isa = cton_isa_create(ctx, target.as_ptr(), settings.as_ptr());
+            assert!(!isa.is_null(), "{}", error(ctx));
+
+            // Return `y` if `x < y`, otherwise `x + 1`.
+            assert_eq!(cton_function_append_param(ctx, CTON_TYPE_I32), CTON_OK);
+            assert_eq!(cton_function_append_param(ctx, CTON_TYPE_I32), CTON_OK);
+            assert_eq!(cton_function_append_return(ctx, CTON_TYPE_I32), CTON_OK);
+            let ebb0 = cton_ebb_create(ctx);
+            let ebb1 = cton_ebb_create(ctx);
+            let ebb2 = cton_ebb_create(ctx);
+            let x = cton_ebb_append_param(ctx, ebb0, CTON_TYPE_I32);
+            let y = cton_ebb_append_param(ctx, ebb0, CTON_TYPE_I32);
+            let less = cton_ins_icmp(ctx, ebb0, slt.as_ptr(), x, y);
+            assert_ne!(less, CTON_INVALID, "{}", error(ctx));
+            assert_eq!(cton_ins_branch(ctx, ebb0, 1, less, ebb1, ptr::null(), 0), CTON_OK);
+            assert_eq!(cton_ins_jump(ctx, ebb0, ebb2, ptr::null(), 0), CTON_OK);
+            assert_eq!(cton_ins_return(ctx, ebb1, &y, 1), CTON_OK);
+            let one = cton_ins_iconst(ctx, ebb2, CTON_TYPE_I32, 1);
+            let sum = cton_ins_binary(ctx, ebb2, iadd.as_ptr(), x, one);
+            assert_ne!(sum, CTON_INVALID, "{}", error(ctx));
+            assert_eq!(cton_ins_return(ctx, ebb2, &sum, 1), CTON_OK);
+
+            assert_eq!(cton_context_compile(ctx, isa), CTON_OK, "{}", error(ctx));
+            assert!(cton_context_code_size(ctx) > 0);
+            assert!(!cton_context_code(ctx).is_null());
+            assert_eq!(cton_context_num_relocs(ctx), 0);
+
+            let text = cton_context_print(ctx, ptr::null());
+            assert!(CStr::from_ptr(text)
+                        .to_str()
+                        .unwrap()
+                        .contains("v4 = iadd"));
+            cton_string_free(text);
+
+            cton_isa_free(isa);
+            cton_context_free(ctx);
+        }
+    }
+
+    #[test]
+    fn parse_with_relocs() {
+        let target = CString::new("intel").unwrap();
+        let settings = CString::new("is_64bit").unwrap();
+        let text = CString::new("function caller() {\n\
+                                     fn0 = function callee()\n\
+                                 ebb0:\n\
+                                     call fn0()\n\
+                                     return\n\
+                                 }")
+                .unwrap();
+        unsafe {
+            let ctx = cton_context_create();
+            let isa = cton_isa_create(ctx, target.as_ptr(), settings.as_ptr());
+            assert_eq!(cton_context_parse(ctx, text.as_ptr()), CTON_OK, "{}", error(ctx));
+            assert_eq!(cton_context_compile(ctx, isa), CTON_OK, "{}", error(ctx));
+            assert_eq!(cton_context_num_relocs(ctx), 1);
+
+            let mut reloc = CtonReloc {
+                offset: 0,
+                kind: 0,
+                target_kind: CTON_RELOC_EBB,
+                target: 0,
+                name: ptr::null(),
+                addend: 0,
+            };
+            assert_eq!(cton_context_reloc(ctx, 0, &mut reloc), CTON_OK);
+            assert_eq!(reloc.target_kind, CTON_RELOC_FUNC);
+            assert_eq!(reloc.target, 0);
+            assert!(reloc.offset > 0);
+            assert_eq!(cton_context_reloc(ctx, 1, &mut reloc), CTON_ERROR);
+
+            cton_isa_free(isa);
+            cton_context_free(ctx);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let target = CString::new("vax").unwrap();
+        let fadd = CString::new("fadd").unwrap();
+        let bogus = CString::new("bogus").unwrap();
+        unsafe {
+            let ctx = cton_context_create();
+            assert!(cton_isa_create(ctx, target.as_ptr(), ptr::null()).is_null());
+            assert_eq!(error(ctx), "unknown target 'vax'");
+
+            assert_eq!(cton_function_append_param(ctx, 99), CTON_ERROR);
+            assert_eq!(error(ctx), "invalid type code 99");
+
+            let ebb0 = cton_ebb_create(ctx);
+            let x = cton_ebb_append_param(ctx, ebb0, CTON_TYPE_F32);
+            assert_eq!(cton_ins_binary(ctx, ebb0, bogus.as_ptr(), x, x), CTON_INVALID);
+            assert_eq!(error(ctx), "unknown opcode");
+            assert_eq!(cton_ins_binary_imm(ctx, ebb0, fadd.as_ptr(), x, 1), CTON_INVALID);
+            assert_eq!(error(ctx), "fadd is not a BinaryImm instruction");
+            assert_eq!(cton_ins_binary(ctx, ebb0, fadd.as_ptr(), x, 7), CTON_INVALID);
+            assert_eq!(error(ctx), "invalid value number 7");
+            assert_eq!(cton_ins_return(ctx, 5, ptr::null(), 0), CTON_ERROR);
+            assert_eq!(error(ctx), "invalid EBB number 5");
+            assert_eq!(cton_ins_iconst(ctx, ebb0, CTON_TYPE_F64, 1), CTON_INVALID);
+            assert_eq!(error(ctx), "iconst needs an integer type, not f64");
+            assert_eq!(cton_ins_iconst(ctx, ebb0, CTON_TYPE_B1, 1), CTON_INVALID);
+            assert_eq!(error(ctx), "iconst needs an integer type, not b1");
+            assert_eq!(cton_context_compile(ctx, ptr::null()), CTON_ERROR);
+            assert_eq!(error(ctx), "null ISA");
+
+            cton_context_free(ctx);
+        }
+    }
+
+    #[test]
+    fn invalid_results() {
+        let iadd = CString::new("iadd").unwrap();
+        unsafe {
+            let ctx = cton_context_create();
+            let ebb0 = cton_ebb_create(ctx);
+            let x = cton_ebb_append_param(ctx, ebb0, CTON_TYPE_I32);
+
+            // The results of failed calls can be passed on without crashing.
+            assert_eq!(cton_ins_binary(ctx, ebb0, iadd.as_ptr(), x, CTON_INVALID),
+                       CTON_INVALID);
+            assert_eq!(error(ctx), "invalid value number 4294967295");
+            assert_eq!(cton_ins_iconst(ctx, CTON_INVALID, CTON_TYPE_I32, 1), CTON_INVALID);
+            assert_eq!(error(ctx), "invalid EBB number 4294967295");
+            assert_eq!(cton_ebb_append_param(ctx, CTON_INVALID, CTON_TYPE_I32), CTON_INVALID);
+            assert_eq!(cton_ins_return(ctx, ebb0, &CTON_INVALID, 1), CTON_ERROR);
+
+            cton_context_free(ctx);
+        }
+    }
+}
@@ -16,6 +16,13 @@ ebb = EntityRefKind(
 #: A reference to a stack slot declared in the function preamble.
 stack_slot = EntityRefKind('stack_slot', 'A stack slot.')
 
+#: A reference to a global variable declared in the function preamble.
+global_var = EntityRefKind('global_var', 'A global variable.')
+
+#: A reference to a table declared in the function preamble.
+#: This is used to compute the address of a table element.
+table = EntityRefKind('table', 'A table of elements in memory.')
+
 #: A reference to a function sugnature declared in the function preamble.
 #: Tbis is used to provide the call signature in an indirect call instruction.
 sig_ref = EntityRefKind('sig_ref', 'A function signature.')
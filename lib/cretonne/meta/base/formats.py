@@ -3,14 +3,16 @@ The cretonne.formats defines all instruction formats.
 
 Every instruction format has a corresponding `InstructionData` variant in the
 Rust representation of cretonne IL, so all instruction formats must be defined
-in this module.
+in this module. An `InstructionData` variant must fit in 16 bytes, so formats
+with more than three value operands should use `VARIABLE_ARGS` instead.
 """
 from __future__ import absolute_import
 from cdsl.formats import InstructionFormat
 from cdsl.operands import VALUE, VARIABLE_ARGS
 from .immediates import imm64, uimm8, ieee32, ieee64, offset32, uoffset32
-from .immediates import intcc, floatcc, memflags
+from .immediates import intcc, floatcc, memflags, memorder, regunit
 from .entities import ebb, sig_ref, func_ref, jump_table, stack_slot
+from .entities import global_var, table
 
 Nullary = InstructionFormat()
 
@@ -18,6 +20,7 @@ Unary = InstructionFormat(VALUE)
 UnaryImm = InstructionFormat(imm64)
 UnaryIeee32 = InstructionFormat(ieee32)
 UnaryIeee64 = InstructionFormat(ieee64)
+UnaryGlobalVar = InstructionFormat(global_var)
 
 Binary = InstructionFormat(VALUE, VALUE)
 BinaryImm = InstructionFormat(VALUE, imm64)
@@ -42,9 +45,12 @@ Jump = InstructionFormat(ebb, VARIABLE_ARGS)
 Branch = InstructionFormat(VALUE, ebb, VARIABLE_ARGS)
 BranchIcmp = InstructionFormat(intcc, VALUE, VALUE, ebb, VARIABLE_ARGS)
 BranchTable = InstructionFormat(VALUE, jump_table)
+BranchTableBase = InstructionFormat(jump_table)
+BranchTableEntry = InstructionFormat(VALUE, VALUE, uimm8, jump_table)
 
 Call = InstructionFormat(func_ref, VARIABLE_ARGS)
 IndirectCall = InstructionFormat(sig_ref, VALUE, VARIABLE_ARGS)
+FuncAddr = InstructionFormat(func_ref)
 
 Load = InstructionFormat(memflags, VALUE, offset32)
 Store = InstructionFormat(memflags, VALUE, VALUE, offset32)
@@ -57,5 +63,19 @@ StackStore = InstructionFormat(VALUE, stack_slot, offset32)
 HeapLoad = InstructionFormat(VALUE, uoffset32)
 HeapStore = InstructionFormat(VALUE, VALUE, uoffset32)
 
+# Accessing an element of a table declared in the preamble.
+TableAddr = InstructionFormat(table, VALUE, offset32)
+
+# Atomic memory operations. The atomic stores and read-modify-write
+# instructions share a format, with the stored value first and the address
+# second.
+AtomicLoad = InstructionFormat(memorder, VALUE)
+AtomicRmw = InstructionFormat(memorder, VALUE, VALUE)
+AtomicCas = InstructionFormat(memorder, VALUE, VALUE, VALUE)
+Fence = InstructionFormat(memorder)
+
+# Temporarily move a value to another register.
+RegMove = InstructionFormat(VALUE, ('src', regunit), ('dst', regunit))
+
 # Finally extract the names of global variables in this module.
 InstructionFormat.extract_names(globals())
@@ -91,8 +91,33 @@ floatcc = ImmediateKind(
             'uge': 'UnorderedOrGreaterThanOrEqual',
         })
 
+#: A register unit in the target ISA.
+#:
+#: This is used by the :cton:inst:`regmove` instruction to name registers
+#: directly after register allocation.
+regunit = ImmediateKind(
+        'regunit',
+        'A register unit in the target ISA',
+        rust_type='RegUnit')
+
 #: Flags for memory operations like :cton:inst:`load` and :cton:inst:`store`.
 memflags = ImmediateKind(
         'memflags',
         'Memory operation flags',
         default_member='flags', rust_type='MemFlags')
+
+#: A memory ordering constraint for atomic memory operations.
+#:
+#: This enumerated operand kind is used for the atomic instructions like
+#: :cton:inst:`atomic_load` and corresponds to the `MemOrder` Rust type.
+memorder = ImmediateKind(
+        'memorder',
+        'A memory ordering for atomic operations.',
+        default_member='order', rust_type='MemOrder',
+        values={
+            'relaxed': 'Relaxed',
+            'acquire': 'Acquire',
+            'release': 'Release',
+            'acq_rel': 'AcqRel',
+            'seq_cst': 'SeqCst',
+        })
@@ -10,7 +10,7 @@ from cdsl.typevar import TypeVar
 from cdsl.instructions import Instruction, InstructionGroup
 from base.types import i8, f32, f64, b1
 from base.immediates import imm64, uimm8, ieee32, ieee64, offset32, uoffset32
-from base.immediates import intcc, floatcc, memflags
+from base.immediates import intcc, floatcc, memflags, memorder, regunit
 from base import entities
 import base.formats  # noqa
 
@@ -121,6 +121,43 @@ br_table = Instruction(
         """,
         ins=(x, JT), is_branch=True)
 
+x = Operand('x', iAddr, doc='index into jump table')
+Size = Operand('Size', uimm8, 'Size in bytes')
+addr = Operand('addr', iAddr)
+entry = Operand('entry', iAddr, doc='entry of jump table')
+jump_table_entry = Instruction(
+        'jump_table_entry', r"""
+        Get an entry from a jump table.
+
+        Load a sign-extended entry of ``Size`` bytes from the jump table
+        ``JT`` at ``addr``, using ``x`` as an index which must be in range.
+        The entries are offsets of the destination EBBs relative to the start
+        of the table.
+
+        This instruction is produced by the legalizer when it expands
+        :inst:`br_table` into a table lookup.
+        """,
+        ins=(x, addr, Size, JT), outs=entry)
+
+jump_table_base = Instruction(
+        'jump_table_base', r"""
+        Get the address of a jump table.
+
+        Jump tables are emitted in a read-only data area following the code of
+        the function, and the address is computed relative to the instruction.
+        """,
+        ins=JT, outs=addr)
+
+indirect_jump_table_br = Instruction(
+        'indirect_jump_table_br', r"""
+        Branch indirectly via a jump table entry.
+
+        Unconditionally jump to ``addr`` which must be one of the destinations
+        in the jump table ``JT``. The jump table is only used to determine the
+        possible destinations for the control flow graph.
+        """,
+        ins=(addr, JT), is_branch=True, is_terminator=True)
+
 trap = Instruction(
         'trap', r"""
         Terminate execution unconditionally.
@@ -182,6 +219,19 @@ call_indirect = Instruction(
         """,
         ins=(SIG, callee, args), outs=rvals, is_call=True)
 
+func_addr = Instruction(
+        'func_addr', r"""
+        Get the address of a function.
+
+        Compute the absolute address of a function declared in the preamble.
+        The returned address can be used as a ``callee`` argument to
+        :inst:`call_indirect`.
+
+        When the ``is_pic`` setting is enabled, the address is loaded from the
+        global offset table instead of being encoded as a constant.
+        """,
+        ins=FN, outs=callee)
+
 #
 # Memory operations
 #
@@ -201,7 +251,7 @@ load = Instruction(
         This is a polymorphic instruction that can load any value type which
         has a memory representation.
         """,
-        ins=(Flags, p, Offset), outs=a)
+        ins=(Flags, p, Offset), outs=a, can_load=True)
 
 store = Instruction(
         'store', r"""
@@ -210,7 +260,7 @@ store = Instruction(
         This is a polymorphic instruction that can store any value type with a
         memory representation.
         """,
-        ins=(Flags, x, p, Offset))
+        ins=(Flags, x, p, Offset), can_store=True)
 
 iExt8 = TypeVar(
         'iExt8', 'An integer type with more than 8 bits',
@@ -224,7 +274,7 @@ uload8 = Instruction(
 
         This is equivalent to ``load.i8`` followed by ``uextend``.
         """,
-        ins=(Flags, p, Offset), outs=a)
+        ins=(Flags, p, Offset), outs=a, can_load=True)
 
 sload8 = Instruction(
         'sload8', r"""
@@ -232,7 +282,7 @@ sload8 = Instruction(
 
         This is equivalent to ``load.i8`` followed by ``uextend``.
         """,
-        ins=(Flags, p, Offset), outs=a)
+        ins=(Flags, p, Offset), outs=a, can_load=True)
 
 istore8 = Instruction(
         'istore8', r"""
@@ -240,7 +290,7 @@ istore8 = Instruction(
 
         This is equivalent to ``ireduce.i8`` followed by ``store.i8``.
         """,
-        ins=(Flags, x, p, Offset))
+        ins=(Flags, x, p, Offset), can_store=True)
 
 iExt16 = TypeVar(
         'iExt16', 'An integer type with more than 16 bits',
@@ -254,7 +304,7 @@ uload16 = Instruction(
 
         This is equivalent to ``load.i16`` followed by ``uextend``.
         """,
-        ins=(Flags, p, Offset), outs=a)
+        ins=(Flags, p, Offset), outs=a, can_load=True)
 
 sload16 = Instruction(
         'sload16', r"""
@@ -262,7 +312,7 @@ sload16 = Instruction(
 
         This is equivalent to ``load.i16`` followed by ``uextend``.
         """,
-        ins=(Flags, p, Offset), outs=a)
+        ins=(Flags, p, Offset), outs=a, can_load=True)
 
 istore16 = Instruction(
         'istore16', r"""
@@ -270,7 +320,7 @@ istore16 = Instruction(
 
         This is equivalent to ``ireduce.i16`` followed by ``store.i8``.
         """,
-        ins=(Flags, x, p, Offset))
+        ins=(Flags, x, p, Offset), can_store=True)
 
 iExt32 = TypeVar(
         'iExt32', 'An integer type with more than 32 bits',
@@ -284,7 +334,7 @@ uload32 = Instruction(
 
         This is equivalent to ``load.i32`` followed by ``uextend``.
         """,
-        ins=(Flags, p, Offset), outs=a)
+        ins=(Flags, p, Offset), outs=a, can_load=True)
 
 sload32 = Instruction(
         'sload32', r"""
@@ -292,7 +342,7 @@ sload32 = Instruction(
 
         This is equivalent to ``load.i32`` followed by ``uextend``.
         """,
-        ins=(Flags, p, Offset), outs=a)
+        ins=(Flags, p, Offset), outs=a, can_load=True)
 
 istore32 = Instruction(
         'istore32', r"""
@@ -300,7 +350,7 @@ istore32 = Instruction(
 
         This is equivalent to ``ireduce.i32`` followed by ``store.i8``.
         """,
-        ins=(Flags, x, p, Offset))
+        ins=(Flags, x, p, Offset), can_store=True)
 
 x = Operand('x', Mem, doc='Value to be stored')
 a = Operand('a', Mem, doc='Value loaded')
@@ -316,7 +366,7 @@ stack_load = Instruction(
         access cannot go out of bounds, i.e.
         :math:`sizeof(a) + Offset <= sizeof(SS)`.
         """,
-        ins=(SS, Offset), outs=a)
+        ins=(SS, Offset), outs=a, can_load=True)
 
 stack_store = Instruction(
         'stack_store', r"""
@@ -329,7 +379,7 @@ stack_store = Instruction(
         access cannot go out of bounds, i.e.
         :math:`sizeof(a) + Offset <= sizeof(SS)`.
         """,
-        ins=(x, SS, Offset))
+        ins=(x, SS, Offset), can_store=True)
 
 stack_addr = Instruction(
         'stack_addr', r"""
@@ -357,7 +407,7 @@ heap_load = Instruction(
 
         Trap if the heap access would be out of bounds.
         """,
-        ins=(p, Offset), outs=a)
+        ins=(p, Offset), outs=a, can_load=True, can_trap=True)
 
 heap_store = Instruction(
         'heap_store', r"""
@@ -365,7 +415,7 @@ heap_store = Instruction(
 
         Trap if the heap access would be out of bounds.
         """,
-        ins=(x, p, Offset))
+        ins=(x, p, Offset), can_store=True, can_trap=True)
 
 heap_addr = Instruction(
         'heap_addr', r"""
@@ -377,7 +427,140 @@ heap_addr = Instruction(
         Convert the heap-relative address in ``p`` to a real absolute address
         and return it.
         """,
-        ins=(p, Offset), outs=addr)
+        ins=(p, Offset), outs=addr, can_trap=True)
+
+#
+# Tables.
+#
+
+iIdx = TypeVar('iIdx', 'An integer table index type', ints=(32, 64))
+T = Operand('T', entities.table, doc='A table declared in the preamble')
+idx = Operand('idx', iIdx, doc='Index of the table element')
+Offset = Operand('Offset', offset32, 'Byte offset into the table element')
+
+table_addr = Instruction(
+        'table_addr', r"""
+        Bounds check and compute the absolute address of a table element.
+
+        Verify that ``idx`` is less than the bound of the table T, and trap if
+        not. Then compute the address of the element ``idx`` of T, plus
+        ``Offset`` bytes.
+
+        The index is zero-extended to the address type if it is narrower. The
+        legalizer expands this instruction using the base address, bound, and
+        element size declared for the table.
+        """,
+        ins=(T, idx, Offset), outs=addr, can_trap=True)
+
+#
+# Atomic memory operations.
+#
+
+iAtomic = TypeVar(
+        'iAtomic', 'An integer type that can be accessed atomically',
+        ints=(32, 64))
+Order = Operand('Order', memorder)
+x = Operand('x', iAtomic, doc='Value to be stored')
+e = Operand('e', iAtomic, doc='Expected value')
+a = Operand('a', iAtomic, doc='Value previously in memory')
+
+atomic_load = Instruction(
+        'atomic_load', r"""
+        Atomically load from memory at ``p``.
+
+        The address must be naturally aligned. The ``release`` and
+        ``acq_rel`` orderings are not allowed on a load.
+        """,
+        ins=(Order, p), outs=a, other_side_effects=True, can_load=True)
+
+atomic_store = Instruction(
+        'atomic_store', r"""
+        Atomically store ``x`` to memory at ``p``.
+
+        The address must be naturally aligned. The ``acquire`` and ``acq_rel``
+        orderings are not allowed on a store.
+        """,
+        ins=(Order, x, p), other_side_effects=True, can_store=True)
+
+atomic_add = Instruction(
+        'atomic_add', r"""
+        Atomically add ``x`` to the value in memory at ``p``.
+
+        Return the value that was in memory before the addition.
+        """,
+        ins=(Order, x, p), outs=a,
+        other_side_effects=True, can_load=True, can_store=True)
+
+atomic_sub = Instruction(
+        'atomic_sub', r"""
+        Atomically subtract ``x`` from the value in memory at ``p``.
+
+        Return the value that was in memory before the subtraction.
+        """,
+        ins=(Order, x, p), outs=a,
+        other_side_effects=True, can_load=True, can_store=True)
+
+atomic_and = Instruction(
+        'atomic_and', r"""
+        Atomically bitwise and ``x`` into the value in memory at ``p``.
+
+        Return the value that was in memory before the operation.
+        """,
+        ins=(Order, x, p), outs=a,
+        other_side_effects=True, can_load=True, can_store=True)
+
+atomic_or = Instruction(
+        'atomic_or', r"""
+        Atomically bitwise or ``x`` into the value in memory at ``p``.
+
+        Return the value that was in memory before the operation.
+        """,
+        ins=(Order, x, p), outs=a,
+        other_side_effects=True, can_load=True, can_store=True)
+
+atomic_xor = Instruction(
+        'atomic_xor', r"""
+        Atomically bitwise xor ``x`` into the value in memory at ``p``.
+
+        Return the value that was in memory before the operation.
+        """,
+        ins=(Order, x, p), outs=a,
+        other_side_effects=True, can_load=True, can_store=True)
+
+atomic_xchg = Instruction(
+        'atomic_xchg', r"""
+        Atomically replace the value in memory at ``p`` with ``x``.
+
+        Return the value that was in memory before the exchange.
+        """,
+        ins=(Order, x, p), outs=a,
+        other_side_effects=True, can_load=True, can_store=True)
+
+atomic_cas = Instruction(
+        'atomic_cas', r"""
+        Atomic compare-and-swap.
+
+        If the value in memory at ``p`` is equal to ``e``, replace it with
+        ``x``. Return the value that was in memory before the operation,
+        which is equal to ``e`` if the swap happened.
+        """,
+        ins=(Order, e, x, p), outs=a,
+        other_side_effects=True, can_load=True, can_store=True)
+
+fence = Instruction(
+        'fence', r"""
+        Memory fence.
+
+        Order the memory accesses before and after the fence according to
+        ``Order``. An ``acquire`` fence prevents loads before it from being
+        reordered with memory accesses after it, and a ``release`` fence
+        prevents memory accesses before it from being reordered with stores
+        after it. The ``relaxed`` ordering is not allowed on a fence.
+
+        The fence is a barrier to all code motion of memory accesses, even
+        when the target doesn't need a machine instruction for it.
+        """,
+        ins=Order, other_side_effects=True)
 
 #
 # Materializing constants.
@@ -467,6 +650,122 @@ fill = Instruction(
         """,
         ins=x, outs=a)
 
+src = Operand('src', regunit)
+dst = Operand('dst', regunit)
+
+regmove = Instruction(
+        'regmove', r"""
+        Temporarily divert ``x`` from ``src`` to ``dst``.
+
+        This instruction moves the location of a value from one register to
+        another without creating a new SSA value. It is used by the register
+        allocator to temporarily rearrange register assignments in order to
+        satisfy instruction constraints.
+
+        The register diversions created by this instruction must be undone
+        before the value leaves the EBB. At the entry to a new EBB, all live
+        values must be in their originally assigned registers.
+        """,
+        ins=(x, src, dst))
+
+Offset = Operand('Offset', imm64, 'Offset from current stack pointer')
+adjust_sp_imm = Instruction(
+        'adjust_sp_imm', r"""
+        Adds ``Offset`` immediate offset value to the stack pointer register.
+
+        This instruction is used to adjust the stack pointer in function
+        prologues and epilogues. The stack pointer is not represented as an
+        SSA value, so this instruction is only inserted after register
+        allocation.
+        """,
+        ins=(Offset,))
+
+x = Operand('x', iAddr, doc='Stack limit')
+stack_check = Instruction(
+        'stack_check', r"""
+        Trap if the stack pointer is below the stack limit ``x``.
+
+        This instruction is inserted in the function prologue right after the
+        stack frame has been allocated when the function has a
+        ``stack_limit`` argument. The stack pointer is compared as an unsigned
+        address.
+        """,
+        ins=x, can_trap=True)
+
+stack_probe = Instruction(
+        'stack_probe', r"""
+        Read the memory at the stack pointer.
+
+        The function prologue allocates large stack frames one page at a time
+        and probes each new page so any guard page below the stack is hit in
+        order.
+        """,
+        can_trap=True)
+
+a = Operand('a', iAddr, doc='Value of the pinned register')
+get_pinned_reg = Instruction(
+        'get_pinned_reg', r"""
+        Read the value of the pinned register.
+
+        When the ``enable_pinned_reg`` setting is enabled, the target ISA
+        reserves a register that the register allocator never uses. The
+        embedder can keep a global value like the WebAssembly heap base or a
+        VM context pointer in it for the whole execution of the function.
+
+        This instruction copies the pinned register into a new SSA value.
+        """,
+        outs=a)
+
+#
+# Thread-local storage
+#
+
+GV = Operand(
+        'GV', entities.global_var,
+        doc='thread-local variable, declared by :inst:`global`')
+addr = Operand('addr', iAddr)
+
+tls_value = Instruction(
+        'tls_value', r"""
+        Compute the address of a thread-local variable.
+
+        The variable ``GV`` must be declared with the ``tls`` flag. This
+        instruction has no encodings. The legalizer expands it into an access
+        sequence for the ``tls_model`` setting, using :inst:`thread_pointer`
+        and :inst:`tls_ie_offset` or :inst:`tls_gd_addr`.
+        """,
+        ins=GV, outs=addr)
+
+thread_pointer = Instruction(
+        'thread_pointer', r"""
+        Read the thread pointer.
+
+        The thread pointer is the base address of the thread control block of
+        the current thread. Variables in the initial-exec TLS model are
+        located at a fixed offset from it.
+        """,
+        outs=addr)
+
+tls_ie_offset = Instruction(
+        'tls_ie_offset', r"""
+        Load the offset of a thread-local variable from the thread pointer.
+
+        The offset is read from a global offset table entry that the dynamic
+        linker fills in with an initial-exec TLS relocation.
+        """,
+        ins=GV, outs=addr)
+
+tls_gd_addr = Instruction(
+        'tls_gd_addr', r"""
+        Compute the address of a thread-local variable by calling
+        ``__tls_get_addr``.
+
+        This is the general-dynamic TLS model. The access sequence contains a
+        call, so it clobbers the caller-saved registers like a normal
+        :inst:`call`.
+        """,
+        ins=GV, outs=addr)
+
 
 #
 # Vector operations
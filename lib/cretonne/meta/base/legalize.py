@@ -9,9 +9,12 @@ instructions that are legal.
 from __future__ import absolute_import
 from .immediates import intcc
 from .instructions import iadd, iadd_cout, iadd_cin, iadd_carry, iadd_imm
-from .instructions import isub, isub_bin, isub_bout, isub_borrow
-from .instructions import band, bor, bxor, isplit, iconcat
-from .instructions import icmp, iconst, bint
+from .instructions import isub, isub_bin, isub_bout, isub_borrow, irsub_imm
+from .instructions import imul, imul_imm, udiv, udiv_imm, sdiv, sdiv_imm
+from .instructions import urem, urem_imm, srem, srem_imm
+from .instructions import band, bor, bxor, bnot, isplit, iconcat
+from .instructions import band_imm, bor_imm, bxor_imm
+from .instructions import icmp, iconst, bint, select
 from cdsl.ast import Var
 from cdsl.xform import Rtl, XFormGroup
 
@@ -85,6 +88,51 @@ for bitop in [band, bor, bxor]:
                 a << iconcat(al, ah)
             ))
 
+narrow.legalize(
+        a << bnot(x),
+        Rtl(
+            (xl, xh) << isplit(x),
+            al << bnot(xl),
+            ah << bnot(xh),
+            a << iconcat(al, ah)
+        ))
+
+narrow.legalize(
+        a << select(c, x, y),
+        Rtl(
+            (xl, xh) << isplit(x),
+            (yl, yh) << isplit(y),
+            al << select(c, xl, yl),
+            ah << select(c, xh, yh),
+            a << iconcat(al, ah)
+        ))
+
+# Wide immediate operands are materialized so the narrowing patterns above
+# and in `legalizer/narrow_int.rs` apply to the register form.
+for inst_imm, inst in [
+        (iadd_imm, iadd),
+        (imul_imm, imul),
+        (udiv_imm, udiv),
+        (sdiv_imm, sdiv),
+        (urem_imm, urem),
+        (srem_imm, srem),
+        (band_imm, band),
+        (bor_imm, bor),
+        (bxor_imm, bxor)]:
+    narrow.legalize(
+            a << inst_imm(x, y),
+            Rtl(
+                a1 << iconst(y),
+                a << inst(x, a1)
+            ))
+
+narrow.legalize(
+        a << irsub_imm(x, y),
+        Rtl(
+            a1 << iconst(y),
+            a << isub(a1, x)
+        ))
+
 # Expand integer operations with carry for RISC architectures that don't have
 # the flags.
 expand.legalize(
@@ -4,7 +4,7 @@ Cretonne shared settings.
 This module defines settings are are relevant for all code generators.
 """
 from __future__ import absolute_import
-from cdsl.settings import SettingGroup, BoolSetting, EnumSetting
+from cdsl.settings import SettingGroup, BoolSetting, EnumSetting, NumSetting
 
 group = SettingGroup('shared')
 
@@ -18,6 +18,18 @@ opt_level = EnumSetting(
         """,
         'default', 'best', 'fastest')
 
+regalloc = EnumSetting(
+        """
+        Register allocator to use:
+
+        - coloring: SSA-based register allocator that keeps values in
+          registers as much as possible.
+        - spill_all: Keep every value in a stack slot and only load operands
+          into registers around each instruction. This produces slow code, but
+          it is very fast to compile.
+        """,
+        'coloring', 'spill_all')
+
 enable_verifier = BoolSetting(
         """
         Run the Cretonne IL verifier at strategic times during compilation.
@@ -30,16 +42,146 @@ is_64bit = BoolSetting("Enable 64-bit code generation")
 
 is_compressed = BoolSetting("Enable compressed instructions")
 
+is_pic = BoolSetting(
+        """
+        Enable Position-Independent Code generation.
+
+        Calls use PLT relocations and function addresses are loaded from the
+        global offset table, so the generated code can be linked into a
+        shared object.
+        """)
+
+tls_model = EnumSetting(
+        """
+        Thread-local storage access model:
+
+        - initial_exec: Add the thread pointer to an offset loaded from the
+          global offset table. This only works for variables defined in the
+          executable or in a library loaded at startup.
+        - general_dynamic: Call ``__tls_get_addr`` to compute the address.
+          This works for any variable, including variables in libraries
+          loaded with ``dlopen``.
+        """,
+        'initial_exec', 'general_dynamic')
+
 enable_float = BoolSetting(
-        """Enable the use of floating-point instructions""",
+        """
+        Enable the use of floating-point instructions.
+
+        When disabled, the verifier rejects functions using float types.
+        """,
         default=True)
 
 enable_simd = BoolSetting(
-        """Enable the use of SIMD instructions.""",
+        """
+        Enable the use of SIMD instructions.
+
+        When disabled, the verifier rejects functions using vector types.
+        """,
         default=True)
 
 enable_atomics = BoolSetting(
-        """Enable the use of atomic instructions""",
+        """
+        Enable the use of atomic instructions.
+
+        When disabled, the verifier rejects functions using atomic memory
+        operations. Fences are still allowed.
+        """,
         default=True)
 
+enable_pinned_reg = BoolSetting(
+        """
+        Reserve a pinned register that is never used by the register
+        allocator.
+
+        The pinned register can hold a global value like a heap base or a VM
+        context pointer which is provided by the embedder. Its value is read
+        with the `get_pinned_reg` instruction. The target ISA determines which
+        register is pinned.
+        """)
+
+heap_style = EnumSetting(
+        """
+        Heap bounds checking strategy used when the ``heap_addr`` instruction
+        is legalized.
+
+        The heap base address is held in the pinned register, so the built-in
+        legalization is only used when ``enable_pinned_reg`` is set, and no
+        legalization hook has been registered for ``heap_addr``.
+
+        - dynamic: The heap can grow at run time. Its current size in bytes is
+          stored in the pointer-sized word below the heap base, and every
+          access is compared to it.
+        - static: The heap is a fixed region of address space with
+          ``static_heap_bound_log2`` bytes, followed by an unmapped guard
+          region of ``static_heap_guard_log2`` bytes. Accesses that can only
+          hit the heap or the guard region are not checked at all, so an
+          out-of-bounds access faults and is reported through the trap table.
+        """,
+        'dynamic', 'static')
+
+static_heap_bound_log2 = NumSetting(
+        """
+        The log2 of the size in bytes of a static heap. The default is 32,
+        so any 32-bit index is below the bound.
+        """,
+        default=32)
+
+static_heap_guard_log2 = NumSetting(
+        """
+        The log2 of the size in bytes of the guard region following a static
+        heap, or 0 for no guard region. The default is 31 which is a 2 GB
+        guard region.
+        """,
+        default=31)
+
+enable_probestack = BoolSetting(
+        """
+        Probe the stack when allocating large stack frames.
+
+        Stack frames larger than the probe size are allocated one probe-sized
+        page at a time, and each page is touched before the next one is
+        allocated. This guarantees that a guard page below the stack is hit
+        before any memory beyond it is accessed.
+
+        Functions with a `stack_limit` argument check the stack pointer
+        against the limit instead of probing.
+        """,
+        default=True)
+
+probestack_size_log2 = NumSetting(
+        """
+        The log2 of the stack probe size in bytes.
+
+        This should be no larger than the size of the guard page below the
+        stack. The default is 12 which is a 4 KB page.
+        """,
+        default=12)
+
+function_alignment_log2 = NumSetting(
+        """
+        The log2 of the alignment of function entry points in bytes.
+
+        The code emitted for a function assumes that it is placed at an
+        address with this alignment. The default is 4 which is a 16-byte
+        alignment.
+        """,
+        default=4)
+
+align_loops = BoolSetting(
+        """
+        Align loop headers by inserting padding before them.
+
+        An EBB is treated as a loop header when it is the destination of a
+        branch from itself or from an EBB later in the layout.
+        """)
+
+loop_alignment_log2 = NumSetting(
+        """
+        The log2 of the alignment of loop headers in bytes when the
+        `align_loops` setting is on. The alignment is capped by the function
+        alignment.
+        """,
+        default=4)
+
 group.close(globals())
@@ -85,6 +85,11 @@ class Instruction(object):
     :param is_call: This is a call instruction.
     :param is_return: This is a return instruction.
     :param can_trap: This instruction can trap.
+    :param can_load: This instruction can load from memory.
+    :param can_store: This instruction can store to memory.
+    :param other_side_effects: Instruction has other side effects, so it must
+                               not be removed or reordered with other memory
+                               accesses.
     """
 
     # Boolean instruction attributes that can be passed as keyword arguments to
@@ -96,6 +101,10 @@ class Instruction(object):
             'is_call': 'Is this a call instruction?',
             'is_return': 'Is this a return instruction?',
             'can_trap': 'Can this instruction cause a trap?',
+            'can_load': 'Can this instruction read from memory?',
+            'can_store': 'Can this instruction write to memory?',
+            'other_side_effects':
+            'Does this instruction have side effects other than trapping?',
             }
 
     def __init__(self, name, doc, ins=(), outs=(), **kwargs):
@@ -1,7 +1,7 @@
 """Defining instruction set architectures."""
 from __future__ import absolute_import
-from .predicates import And
-from .registers import RegClass, Register
+from .predicates import And, TypePredicate
+from .registers import RegClass, Register, Stack
 from .ast import Apply
 
 # The typing module is only required by mypy, and we don't use these imports
@@ -14,7 +14,7 @@ try:
         from .settings import SettingGroup  # noqa
         from .types import ValueType  # noqa
         from .registers import RegBank  # noqa
-        OperandConstraint = Union[RegClass, Register, int]
+        OperandConstraint = Union[RegClass, Register, int, Stack]
         ConstraintSeq = Union[OperandConstraint, Tuple[OperandConstraint, ...]]
         # Instruction specification for encodings. Allows for predicated
         # instructions.
@@ -162,8 +162,14 @@ class EncRecipe(object):
 
     - A `RegClass` specifying the set of allowed registers.
     - A `Register` specifying a fixed-register operand.
+    - A `Stack` specifying a value in a stack slot.
     - An integer indicating that this result is tied to a value operand, so
-      they must use the same register.
+      they must use the same register. Only results can be tied, and the tied
+      value operand must have a `RegClass` or `Register` constraint.
+
+    The `clobbers` argument is a tuple of registers that are overwritten by the
+    encoded instruction without being operands or results. Values that are live
+    across the instruction can't be assigned to these registers.
 
     The `branch_range` argument must be provided for recipes that can encode
     branch instructions. It is an `(origin, bits)` tuple describing the exact
@@ -175,6 +181,7 @@ class EncRecipe(object):
     :param size: Number of bytes in the binary encoded instruction.
     :param: ins Tuple of register constraints for value operands.
     :param: outs Tuple of register constraints for results.
+    :param: clobbers Tuple of registers clobbered by the instruction.
     :param: branch_range `(origin, bits)` range for branches.
     :param: instp Instruction predicate.
     :param: isap ISA predicate.
@@ -187,6 +194,7 @@ class EncRecipe(object):
             size,               # type: int
             ins,                # type: ConstraintSeq
             outs,               # type: ConstraintSeq
+            clobbers=(),        # type: Sequence[Register]
             branch_range=None,  # type: BranchRange
             instp=None,         # type: PredNode
             isap=None           # type: PredNode
@@ -206,7 +214,12 @@ class EncRecipe(object):
         self.ins = self._verify_constraints(ins)
         if not format.has_value_list:
             assert len(self.ins) == format.num_value_operands
+        for c in self.ins:
+            assert not isinstance(c, int), "Value operands can't be tied"
         self.outs = self._verify_constraints(outs)
+        for reg in clobbers:
+            assert isinstance(reg, Register), "Clobbers must be registers"
+        self.clobbers = tuple(clobbers)
 
     def __str__(self):
         # type: () -> str
@@ -223,8 +236,13 @@ class EncRecipe(object):
                 assert c >= 0
                 if not self.format.has_value_list:
                     assert c < self.format.num_value_operands
+                assert (isinstance(self.ins[c], RegClass) or
+                        isinstance(self.ins[c], Register)), \
+                    "Result can only be tied to a register operand"
             else:
-                assert isinstance(c, RegClass) or isinstance(c, Register)
+                assert (isinstance(c, RegClass) or
+                        isinstance(c, Register) or
+                        isinstance(c, Stack))
         return seq
 
 
@@ -265,6 +283,14 @@ class Encoding(object):
         else:
             self.inst, self.typevars = inst.fully_bound()
 
+        # Add type checks for the secondary type variables to the instruction
+        # predicate. The encoding tables are only keyed by the controlling
+        # type variable.
+        if len(self.typevars) > 1:
+            for tv, vt in zip(self.inst.other_typevars, self.typevars[1:]):
+                typred = TypePredicate.typevar_check(self.inst, tv, vt)
+                instp = And.combine(instp, typred)
+
         self.cpumode = cpumode
         assert self.inst.format == recipe.format, (
                 "Format {} must match recipe: {}".format(
@@ -28,9 +28,12 @@ try:
     from typing import Sequence, Tuple, Set, Any, Union, TYPE_CHECKING  # noqa
     if TYPE_CHECKING:
         from .formats import InstructionFormat, FormatField  # noqa
+        from .instructions import Instruction  # noqa
         from .settings import BoolSetting, SettingGroup  # noqa
+        from .types import ValueType  # noqa
+        from .typevar import TypeVar  # noqa
         PredContext = Union[SettingGroup, InstructionFormat]
-        PredLeaf = Union[BoolSetting, 'FieldPredicate']
+        PredLeaf = Union[BoolSetting, 'FieldPredicate', 'TypePredicate']
         PredNode = Union[PredLeaf, 'Predicate']
 except ImportError:
     pass
@@ -293,3 +296,84 @@ class IsUnsignedInt(FieldPredicate):
         self.scale = scale
         assert width >= 0 and width <= 64
         assert scale >= 0 and scale < width
+
+
+class IsColocatedFunc(FieldPredicate):
+    """
+    Instruction predicate that checks if a `func_ref` field refers to an
+    external function declared as `colocated`, meaning that it will be placed
+    near the calling function.
+
+    :param field: `FormatField` to be checked.
+    """
+
+    def __init__(self, field):
+        # type: (FormatField) -> None
+        super(IsColocatedFunc, self).__init__(
+                field, 'is_colocated_func', ('dfg',))
+
+
+class TypePredicate(object):
+    """
+    An instruction predicate that checks the type of an SSA argument value.
+
+    Type predicates are used to implement encodings for instructions with
+    multiple type variables. The encoding tables are keyed by the controlling
+    type variable, type predicates check any secondary type variables.
+
+    :param iform: The `InstructionFormat` of the instruction being checked.
+    :param value_arg: Index of the value argument to type check.
+    :param value_type: The required value type.
+    """
+
+    def __init__(self, iform, value_arg, value_type):
+        # type: (InstructionFormat, int, ValueType) -> None
+        assert value_arg >= 0
+        self.number = None  # type: int
+        self.iform = iform
+        self.value_arg = value_arg
+        self.value_type = value_type
+        # All PredNode members must have a name field. This will never be set.
+        self.name = None  # type: str
+
+    def __str__(self):
+        # type: () -> str
+        return 'args[{}]:{}'.format(self.value_arg, self.value_type)
+
+    @staticmethod
+    def typevar_check(inst, typevar, value_type):
+        # type: (Instruction, TypeVar, ValueType) -> TypePredicate
+        """
+        Return a type check predicate for the given type variable in `inst`.
+
+        The type variable must appear directly as the type of one of the
+        value operands.
+        """
+        for value_arg, opnum in enumerate(inst.value_opnums):
+            if inst.ins[opnum].typevar is typevar:
+                return TypePredicate(inst.format, value_arg, value_type)
+        raise AssertionError(
+                "Type variable {} is not bound to a value operand of {}"
+                .format(typevar, inst))
+
+    def predicate_context(self):
+        # type: () -> PredContext
+        """
+        This predicate can be evaluated in the context of an instruction
+        format.
+        """
+        return self.iform
+
+    def predicate_leafs(self, leafs):
+        # type: (Set[PredLeaf]) -> None
+        leafs.add(self)
+
+    def rust_predicate(self, prec):
+        # type: (int) -> str
+        """
+        Return Rust code for evaluating this predicate.
+
+        It is assumed that the context has `dfg` and `args` variables.
+        """
+        return 'dfg.value_type(args[{}]) == {}'.format(
+                self.value_arg, self.value_type.rust_name())
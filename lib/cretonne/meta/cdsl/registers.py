@@ -20,6 +20,12 @@ more register units at a time. A register class allocates more than one
 register unit at a time when its registers are composed of smaller alocatable
 units. For example, the ARM double precision floating point registers are
 composed of two single precision registers.
+
+A register class can also allocate *register pairs*. This is used on 32-bit
+targets to keep a 64-bit value in two adjacent 32-bit registers, holding the
+low and high halves of the value respectively. A register pair is always two
+register units wide, and values assigned to it get a `RegPair` value location
+instead of a plain register location.
 """
 from __future__ import absolute_import
 from . import is_power_of_two, next_power_of_two
@@ -30,8 +36,8 @@ try:
     if TYPE_CHECKING:
         from .isa import TargetISA  # noqa
         # A tuple uniquely identifying a register class inside a register bank.
-        # (count, width, start)
-        RCTup = Tuple[int, int, int]
+        # (count, width, start, pairs)
+        RCTup = Tuple[int, int, int, bool]
 except ImportError:
     pass
 
@@ -94,6 +100,25 @@ class RegBank(object):
         return ('RegBank({}, units={}, first_unit={})'
                 .format(self.name, self.units, self.first_unit))
 
+    def unit_by_name(self, name):
+        # type: (str) -> int
+        """
+        Get a register unit in this bank by name.
+
+        The unit number is relative to the whole ISA, so it includes
+        `first_unit`.
+        """
+        suffix = name[len(self.prefix):]
+        if name in self.names:
+            r = self.names.index(name)
+        elif name.startswith(self.prefix) and suffix.isdigit():
+            r = int(suffix)
+        else:
+            raise AttributeError("No register named '{}' in {}"
+                                 .format(name, self.name))
+        assert r < self.units
+        return self.first_unit + r
+
     def finish_regclasses(self, first_index):
         # type: (int) -> None
         """
@@ -167,20 +192,24 @@ class RegClass(object):
                   default, the whole register bank can be allocated.
     :param width: How many units to allocate at a time.
     :param start: The first unit to allocate, relative to `bank.first.unit`.
+    :param pairs: Each allocation is a pair of adjacent registers holding the
+                  low and high halves of a wide value. Requires `width=2`.
     """
 
-    def __init__(self, bank, count=None, width=1, start=0):
-        # type: (RegBank, int, int, int) -> None
+    def __init__(self, bank, count=None, width=1, start=0, pairs=False):
+        # type: (RegBank, int, int, int, bool) -> None
         self.name = None  # type: str
         self.index = None  # type: int
         self.bank = bank
         self.start = start
         self.width = width
+        self.pairs = pairs
 
         # This is computed later in `finish_regclasses()`.
         self.subclasses = list()  # type: List[RegClass]
 
         assert width > 0
+        assert width == 2 or not pairs, "Register pairs must be 2 units wide"
         assert start >= 0 and start < bank.units
 
         if count is None:
@@ -201,7 +230,7 @@ class RegClass(object):
         The tuple can be used as a dictionary key to ensure that there are no
         duplicate register classes.
         """
-        return (self.count, self.width, self.start)
+        return (self.count, self.width, self.start, self.pairs)
 
     def intersect(self, other):
         # type: (RegClass) -> RCTup
@@ -210,7 +239,7 @@ class RegClass(object):
 
         Returns `None` if the two classes are disjoint.
         """
-        if self.width != other.width:
+        if self.width != other.width or self.pairs != other.pairs:
             return None
         s_end = self.start + self.count * self.width
         o_end = other.start + other.count * other.width
@@ -222,7 +251,22 @@ class RegClass(object):
         end = min(s_end, o_end)
         count = (end - start) // self.width
         assert count > 0
-        return (count, self.width, start)
+        return (count, self.width, start, self.pairs)
+
+    def __getattr__(self, attr):
+        # type: (str) -> Register
+        """
+        Get a specific register in the class by name.
+
+        For example: `GPR.r5`.
+        """
+        # Don't look up private and special attributes as register names.
+        if attr.startswith('_'):
+            raise AttributeError(attr)
+        reg = Register(self, self.bank.unit_by_name(attr))
+        # Save this register so we won't have to create it again.
+        setattr(self, attr, reg)
+        return reg
 
     def __getitem__(self, sliced):
         # type: (slice) -> RegClass
@@ -240,7 +284,8 @@ class RegClass(object):
         c = sliced.stop - sliced.start
         assert c > 1, "Can't have single-register classes"
 
-        return RegClass(self.bank, count=c, width=w, start=s)
+        return RegClass(
+                self.bank, count=c, width=w, start=s, pairs=self.pairs)
 
     def mask(self):
         # type: () -> List[int]
@@ -305,3 +350,16 @@ class Register(object):
         # type: (RegClass, int) -> None
         self.regclass = rc
         self.unit = unit
+
+
+class Stack(object):
+    """
+    An operand that must be in a stack slot.
+
+    A `Stack` object can be used to indicate an operand constraint for a value
+    operand that must live in a stack slot. The register class determines
+    which registers the value could be moved to when it is filled.
+    """
+    def __init__(self, rc):
+        # type: (RegClass) -> None
+        self.regclass = rc
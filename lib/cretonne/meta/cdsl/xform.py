@@ -6,6 +6,7 @@ from .ast import Def, Var, Apply
 
 try:
     from typing import Union, Iterator, Sequence, Iterable, List, Dict  # noqa
+    from typing import Optional  # noqa
     from .ast import Expr  # noqa
     DefApply = Union[Def, Apply]
 except ImportError:
@@ -72,6 +73,8 @@ class XForm(object):
         # type: (Rtl, Rtl) -> None
         self.src = src
         self.dst = dst
+        # Name of the transformation, assigned when it is added to a group.
+        self.name = None  # type: Optional[str]
         # Variables that are inputs to the source pattern.
         self.inputs = list()  # type: List[Var]
         # Variables defined in either src or dst.
@@ -276,9 +279,14 @@ class XFormGroup(object):
         """
         Add a legalization pattern to this group.
 
+        The pattern is named after the group and the instruction it
+        legalizes, like `narrow.iadd`.
+
         :param src: Single `Def` or `Apply` to be legalized.
         :param dst: `Rtl` list of replacement instructions.
         """
         xform = XForm(Rtl(src), dst)
         xform.verify_legalize()
+        xform.name = '{}.{}'.format(
+                self.name, xform.src.rtl[0].expr.inst.name)
         self.xforms.append(xform)
@@ -25,15 +25,17 @@ def gen_isa(isa, fmt):
         # No encoding recipes: Emit a stub.
         with fmt.indented(
                 'pub fn emit_inst<CS: CodeSink + ?Sized>'
-                '(func: &Function, inst: Inst, _sink: &mut CS) {', '}'):
+                '(func: &Function, inst: Inst, '
+                '_divert: &mut RegDiversions, _sink: &mut CS) {', '}'):
             fmt.line('bad_encoding(func, inst)')
     else:
         with fmt.indented(
                 'pub fn emit_inst<CS: CodeSink + ?Sized>'
-                '(func: &Function, inst: Inst, sink: &mut CS) {', '}'):
+                '(func: &Function, inst: Inst, '
+                'divert: &mut RegDiversions, sink: &mut CS) {', '}'):
             with fmt.indented('match func.encodings[inst].recipe() {', '}'):
                 for i, recipe in enumerate(isa.all_recipes):
-                    fmt.line('{} => recipe_{}(func, inst, sink),'.format(
+                    fmt.line('{} => recipe_{}(func, inst, divert, sink),'.format(
                         i, recipe.name.lower()))
                 fmt.line('_ => bad_encoding(func, inst),')
 
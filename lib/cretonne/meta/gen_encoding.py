@@ -56,8 +56,8 @@ from unique_table import UniqueSeqTable
 from collections import OrderedDict, defaultdict
 import math
 import itertools
-from cdsl.registers import RegClass, Register
-from cdsl.predicates import FieldPredicate
+from cdsl.registers import RegClass, Register, Stack
+from cdsl.predicates import FieldPredicate, TypePredicate
 
 try:
     from typing import Sequence, Set, Tuple, List, Iterable, DefaultDict, TYPE_CHECKING  # noqa
@@ -82,21 +82,27 @@ def emit_instp(instp, fmt):
     iform = instp.predicate_context()
 
     # Which fields do we need in the InstructionData pattern match?
+    has_type_check = False
     # Collect the leaf predicates.
     leafs = set()  # type: Set[PredLeaf]
     instp.predicate_leafs(leafs)
-    # All the leafs are FieldPredicate instances. Here we just care about
-    # the field names.
+    # All the leafs are FieldPredicate or TypePredicate instances. Here we
+    # just care about the field names.
     fnames = set()  # type: Set[str]
     for p in leafs:
-        assert isinstance(p, FieldPredicate)
-        fnames.add(p.field.rust_name())
-    fields = ', '.join(sorted(fnames))
+        if isinstance(p, FieldPredicate):
+            fnames.add(p.field.rust_name())
+        else:
+            assert isinstance(p, TypePredicate)
+            has_type_check = True
+    fields = ', '.join(sorted(fnames) + ['..'])
 
     with fmt.indented('{} => {{'.format(instp.number), '}'):
         with fmt.indented(
-                'if let InstructionData::{} {{ {}, .. }} = *inst {{'
+                'if let InstructionData::{} {{ {} }} = *inst {{'
                 .format(iform.name, fields), '}'):
+            if has_type_check:
+                fmt.line('let args = inst.arguments(&dfg.value_lists);')
             fmt.line('return {};'.format(instp.rust_predicate(0)))
 
 
@@ -109,14 +115,16 @@ def emit_instps(instps, fmt):
     if not instps:
         # If the ISA has no predicates, just emit a stub.
         with fmt.indented(
-                'pub fn check_instp(_: &InstructionData, _: u16) ' +
-                '-> bool {', '}'):
+                'pub fn check_instp(_: &InstructionData, _: u16, ' +
+                '_: &DataFlowGraph) -> bool {', '}'):
             fmt.line('unimplemented!()')
         return
 
+    # Not all ISAs have type predicates that use the `dfg` argument.
+    fmt.line('#[allow(unused_variables)]')
     with fmt.indented(
-            'pub fn check_instp(inst: &InstructionData, instp_idx: u16) ' +
-            '-> bool {', '}'):
+            'pub fn check_instp(inst: &InstructionData, instp_idx: u16, ' +
+            'dfg: &DataFlowGraph) -> bool {', '}'):
         # The matches emitted by `emit_instp` need this.
         fmt.line('use ir::instructions::InstructionFormat;')
         with fmt.indented('match instp_idx {', '}'):
@@ -463,7 +471,7 @@ def emit_recipe_constraints(isa, fmt):
     Emit a table of encoding recipe operand constraints keyed by recipe number.
 
     These are used by the register allocator to pick registers that can be
-    properly encoded.
+    properly encoded, and by the verifier to check its work.
     """
     with fmt.indented(
             'static RECIPE_CONSTRAINTS: [RecipeConstraints; {}] = ['
@@ -472,13 +480,17 @@ def emit_recipe_constraints(isa, fmt):
             fmt.comment(r.name)
             with fmt.indented('RecipeConstraints {', '},'):
                 emit_operand_constraints(r.ins, 'ins', fmt)
-                emit_operand_constraints(r.outs, 'outs', fmt)
+                emit_operand_constraints(r.outs, 'outs', fmt, r.ins)
+                fmt.line('clobbers: &[{}],'.format(
+                    ', '.join(str(reg.unit) for reg in r.clobbers)))
 
 
-def emit_operand_constraints(seq, field, fmt):
-    # type: (Sequence[OperandConstraint], str, srcgen.Formatter) -> None
+def emit_operand_constraints(seq, field, fmt, ins=()):
+    # type: (Sequence[OperandConstraint], str, srcgen.Formatter, Sequence[OperandConstraint]) -> None # noqa
     """
     Emit a struct field initializer for an array of operand constraints.
+
+    Tied result constraints refer to the value operand constraints in `ins`.
     """
     if len(seq) == 0:
         fmt.line('{}: &[],'.format(field))
@@ -494,6 +506,15 @@ def emit_operand_constraints(seq, field, fmt):
                             'kind: ConstraintKind::FixedReg({}),'
                             .format(cons.unit))
                     fmt.line('regclass: {},'.format(cons.regclass))
+                elif isinstance(cons, Stack):
+                    fmt.line('kind: ConstraintKind::Stack,')
+                    fmt.line('regclass: {},'.format(cons.regclass))
+                elif isinstance(cons, int):
+                    tied = ins[cons]
+                    if isinstance(tied, Register):
+                        tied = tied.regclass
+                    fmt.line('kind: ConstraintKind::Tied({}),'.format(cons))
+                    fmt.line('regclass: {},'.format(tied))
                 else:
                     raise AssertionError(
                             'Unsupported constraint {}'.format(cons))
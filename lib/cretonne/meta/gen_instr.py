@@ -54,6 +54,103 @@ def gen_formats(fmt):
                              .format(f.name, f.name))
     fmt.line()
 
+    gen_format_info(fmt)
+
+
+def gen_format_info(fmt):
+    # type: (srcgen.Formatter) -> None
+    """
+    Generate the `OperandKind` enumeration and the `impl InstructionFormat`
+    methods describing the operands of each format.
+    """
+    kinds = sorted(
+            set(f.kind for iform in InstructionFormat.all_formats
+                for f in iform.imm_fields),
+            key=lambda k: k.name)
+
+    fmt.doc_comment('The kind of an immediate or entity reference operand')
+    fmt.doc_comment('stored in an instruction format.')
+    fmt.line('#[derive(Copy, Clone, PartialEq, Eq, Debug)]')
+    with fmt.indented('pub enum OperandKind {', '}'):
+        for k in kinds:
+            fmt.doc_comment('`{}`: {}'.format(
+                k.rust_type, k.__doc__.strip().split('\n')[0]))
+            fmt.line(camel_case(k.name) + ',')
+    fmt.line()
+
+    with fmt.indented('impl InstructionFormat {', '}'):
+        fmt.doc_comment('Get a list of all the instruction formats.')
+        with fmt.indented(
+                "pub fn all() -> &'static [InstructionFormat] {", '}'):
+            fmt.line('&INSTRUCTION_FORMATS')
+        fmt.line()
+
+        fmt.doc_comment(
+                """
+                Get the number of fixed value operands.
+
+                For formats with a value list, these are the first values in
+                the list.
+                """)
+        with fmt.indented('pub fn num_value_operands(self) -> usize {', '}'):
+            with fmt.indented('match self {', '}'):
+                for f in InstructionFormat.all_formats:
+                    fmt.line('InstructionFormat::{} => {},'
+                             .format(f.name, f.num_value_operands))
+        fmt.line()
+
+        fmt.doc_comment(
+                'Does this format store its value operands in a value list ' +
+                'that can hold a variable number of arguments?')
+        with fmt.indented('pub fn has_value_list(self) -> bool {', '}'):
+            with fmt.indented('match self {', '}'):
+                for f in InstructionFormat.all_formats:
+                    if f.has_value_list:
+                        fmt.line('InstructionFormat::{} => true,'
+                                 .format(f.name))
+                fmt.line('_ => false,')
+        fmt.line()
+
+        fmt.doc_comment(
+                """
+                Get the index of the value operand used to infer the
+                controlling type variable, if any.
+                """)
+        with fmt.indented(
+                'pub fn typevar_operand(self) -> Option<usize> {', '}'):
+            with fmt.indented('match self {', '}'):
+                for f in InstructionFormat.all_formats:
+                    if f.typevar_operand is not None:
+                        fmt.line('InstructionFormat::{} => Some({}),'
+                                 .format(f.name, f.typevar_operand))
+                fmt.line('_ => None,')
+        fmt.line()
+
+        fmt.doc_comment(
+                """
+                Get the immediate and entity reference fields, in the order
+                they appear in the format.
+                """)
+        with fmt.indented(
+                "pub fn imm_fields(self) -> &'static [FormatField] {", '}'):
+            with fmt.indented('match self {', '}'):
+                for f in InstructionFormat.all_formats:
+                    fields = ', '.join(
+                            'FormatField {{ member: "{}", kind: '
+                            'OperandKind::{} }}'
+                            .format(ff.member, camel_case(ff.kind.name))
+                            for ff in f.imm_fields)
+                    fmt.line('InstructionFormat::{} => &[{}],'
+                             .format(f.name, fields))
+    fmt.line()
+
+    with fmt.indented(
+            'const INSTRUCTION_FORMATS: [InstructionFormat; {}] = ['
+            .format(len(InstructionFormat.all_formats)), '];'):
+        for f in InstructionFormat.all_formats:
+            fmt.line('InstructionFormat::{},'.format(f.name))
+    fmt.line()
+
 
 def gen_arguments_method(fmt, is_mut):
     # type: (srcgen.Formatter, bool) -> None
@@ -205,6 +302,68 @@ def gen_instruction_data_impl(fmt):
             fmt.line('*args = vlist;')
 
 
+def gen_serializer(fmt):
+    # type: (srcgen.Formatter) -> None
+    """
+    Generate the functions encoding and decoding the operands of an
+    `InstructionData` for the binary function format in `serialize.rs`.
+
+    The immediate fields are written in format order followed by the value
+    operands.
+    """
+    fmt.doc_comment('Encode the operands of `data`, excluding the opcode.')
+    with fmt.indented(
+            'fn encode_inst_data(data: &InstructionData, '
+            'pool: &ValueListPool, w: &mut Writer) {', '}'):
+        with fmt.indented('match *data {', '}'):
+            for f in InstructionFormat.all_formats:
+                members = [ff.member for ff in f.imm_fields]
+                binds = list(members)
+                if f.has_value_list or f.num_value_operands > 1:
+                    binds.append('ref args')
+                elif f.num_value_operands == 1:
+                    binds.append('arg')
+                binds.append('..')
+                with fmt.indented(
+                        'InstructionData::{} {{ {} }} => {{'
+                        .format(f.name, ', '.join(binds)), '}'):
+                    for m in members:
+                        fmt.line('{}.encode(w);'.format(m))
+                    if f.has_value_list:
+                        fmt.line('w.value_list(args.as_slice(pool));')
+                    elif f.num_value_operands == 1:
+                        fmt.line('arg.encode(w);')
+                    else:
+                        for i in range(f.num_value_operands):
+                            fmt.line('args[{}].encode(w);'.format(i))
+    fmt.line()
+
+    fmt.doc_comment(
+            'Decode the operands of an `opcode` instruction written by ' +
+            '`encode_inst_data()`.')
+    with fmt.indented(
+            'fn decode_inst_data(opcode: Opcode, pool: &mut ValueListPool, '
+            'r: &mut Reader) -> Result<InstructionData> {', '}'):
+        with fmt.indented('Ok(match opcode.format() {', '})'):
+            for f in InstructionFormat.all_formats:
+                with fmt.indented(
+                        'InstructionFormat::{} => {{'.format(f.name), '}'):
+                    with fmt.indented(
+                            'InstructionData::{} {{'.format(f.name), '}'):
+                        fmt.line('opcode: opcode,')
+                        for ff in f.imm_fields:
+                            fmt.line('{}: Decode::decode(r)?,'
+                                     .format(ff.member))
+                        if f.has_value_list:
+                            fmt.line('args: r.value_list(pool)?,')
+                        elif f.num_value_operands == 1:
+                            fmt.line('arg: Decode::decode(r)?,')
+                        elif f.num_value_operands > 1:
+                            fmt.line('args: [{}],'.format(', '.join(
+                                ['Decode::decode(r)?'] *
+                                f.num_value_operands)))
+
+
 def collect_instr_groups(isas):
     # type: (Sequence[TargetISA]) -> List[InstructionGroup]
     seen = set()  # type: Set[InstructionGroup]
@@ -228,7 +387,7 @@ def gen_opcodes(groups, fmt):
     fmt.doc_comment('An instruction opcode.')
     fmt.doc_comment('')
     fmt.doc_comment('All instructions from all supported ISAs are present.')
-    fmt.line('#[derive(Copy, Clone, PartialEq, Eq, Debug)]')
+    fmt.line('#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]')
     instrs = []
 
     # We explicitly set the discriminant of the first variant to 1, which
@@ -662,3 +821,8 @@ def generate(isas, out_dir):
     fmt = srcgen.Formatter()
     gen_builder(instrs, fmt)
     fmt.update_file('builder.rs', out_dir)
+
+    # inst_serialize.rs
+    fmt = srcgen.Formatter()
+    gen_serializer(fmt)
+    fmt.update_file('inst_serialize.rs', out_dir)
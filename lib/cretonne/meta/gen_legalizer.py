@@ -215,12 +215,15 @@ def gen_xform(xform, fmt):
 
 def gen_xform_group(xgrp, fmt):
     # type: (XFormGroup, Formatter) -> None
-    fmt.doc_comment("Legalize the instruction pointed to by `pos`.")
+    fmt.doc_comment(
+            "Legalize the instruction pointed to by `pos`, and return the "
+            "name of the\ntransformation that was applied, or `None` if "
+            "no transformation matched.")
     fmt.line('#[allow(unused_variables,unused_assignments)]')
     with fmt.indented(
             'fn {}(dfg: &mut DataFlowGraph, '
             'cfg: &mut ControlFlowGraph, pos: &mut Cursor) -> '
-            'bool {{'.format(xgrp.name), '}'):
+            'Option<&\'static str> {{'.format(xgrp.name), '}'):
 
         # Gen the instruction to be legalized. The cursor we're passed must be
         # pointing at an instruction.
@@ -232,9 +235,9 @@ def gen_xform_group(xgrp, fmt):
                 with fmt.indented(
                         'Opcode::{} => {{'.format(inst.camel_name), '}'):
                     gen_xform(xform, fmt)
+                    fmt.line('Some("{}")'.format(xform.name))
             # We'll assume there are uncovered opcodes.
-            fmt.line('_ => return false,')
-        fmt.line('true')
+            fmt.line('_ => None,')
 
 
 def generate(isas, out_dir):
@@ -252,6 +252,21 @@ def gen_constructor(sgrp, parent, fmt):
             fmt.line(sgrp.name)
 
 
+def gen_settings_bytes(sgrp, fmt):
+    # type: (SettingGroup, srcgen.Formatter) -> None
+    """
+    Generate a getter for the raw settings bytes.
+    """
+    with fmt.indented('impl Flags {', '}'):
+        fmt.doc_comment(
+                'Get the values of all the settings in this group as bytes.\n'
+                '\n'
+                'The computed predicates are not included since they are '
+                'derived from the settings.')
+        with fmt.indented('pub fn settings_bytes(&self) -> &[u8] {', '}'):
+            fmt.line('&self.bytes[..{}]'.format(sgrp.settings_size))
+
+
 def gen_group(sgrp, fmt):
     # type: (SettingGroup, srcgen.Formatter) -> None
     """
@@ -263,6 +278,7 @@ def gen_group(sgrp, fmt):
         fmt.line('bytes: [u8; {}],'.format(sgrp.byte_size()))
 
     gen_constructor(sgrp, None, fmt)
+    gen_settings_bytes(sgrp, fmt)
     gen_enum_types(sgrp, fmt)
     gen_getters(sgrp, fmt)
     gen_descriptors(sgrp, fmt)
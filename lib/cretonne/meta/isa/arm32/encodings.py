@@ -0,0 +1,82 @@
+"""
+ARM32 Encodings.
+"""
+from __future__ import absolute_import
+from base import instructions as base
+from .defs import A32, T32
+from .recipes import ADP, TDP, LSL, LSR, ASR, EQ, NE, ISH
+from .recipes import Arr, Ari, Arsh, Amul, Amov, Armov, Amovw
+from .recipes import Ab, Abl, Abrz, Abricmp, Abx, Admb
+from .recipes import Trr, Tri, Trsh, Tmul, Tmov, Trmov, Tmovw
+from .recipes import Tb, Tbl, Tbrz, Tbricmp, Tbx, Tdmb
+
+# Basic arithmetic binary instructions.
+for inst,           inst_imm,      op in [
+        (base.iadd, base.iadd_imm, 'add'),
+        (base.isub, None,          'sub'),
+        (base.band, base.band_imm, 'and'),
+        (base.bor,  base.bor_imm,  'orr'),
+        (base.bxor, base.bxor_imm, 'eor')
+        ]:
+    A32.enc(inst.i32, Arr, ADP(op))
+    T32.enc(inst.i32, Trr, TDP(op))
+
+    # Immediate versions. Only the unrotated 8-bit immediates are supported.
+    if inst_imm:
+        A32.enc(inst_imm.i32, Ari, ADP(op))
+        T32.enc(inst_imm.i32, Tri, TDP(op))
+
+A32.enc(base.imul.i32, Amul, 0)
+T32.enc(base.imul.i32, Tmul, 0)
+
+# Register shifts use the low byte of the shift amount register, so they don't
+# have the masking semantics of the cton base instructions. Only immediate
+# shifts are supported.
+for inst,               shift in [
+        (base.ishl_imm, LSL),
+        (base.ushr_imm, LSR),
+        (base.sshr_imm, ASR)
+        ]:
+    A32.enc(inst.i32, Arsh, shift)
+    T32.enc(inst.i32, Trsh, shift)
+
+# Register copies.
+for ty in [base.copy.i32, base.copy.b1]:
+    A32.enc(ty, Amov, ADP('mov'))
+    T32.enc(ty, Tmov, TDP('orr'))
+for ty in [base.regmove.i32, base.regmove.b1]:
+    A32.enc(ty, Armov, ADP('mov'))
+    T32.enc(ty, Trmov, TDP('orr'))
+
+# Integer constants.
+A32.enc(base.iconst.i32, Amovw, 0)
+T32.enc(base.iconst.i32, Tmovw, 0)
+
+# Memory fences.
+A32.enc(base.fence, Admb, ISH)
+T32.enc(base.fence, Tdmb, ISH)
+
+# Control flow.
+
+# Unconditional branches.
+A32.enc(base.jump, Ab, 0)
+T32.enc(base.jump, Tb, 0)
+A32.enc(base.call, Abl, 0)
+T32.enc(base.call, Tbl, 0)
+
+# Conditional branches.
+for inst,           cond in [
+        (base.brz,  EQ),
+        (base.brnz, NE)
+        ]:
+    A32.enc(inst.i32, Abrz, cond)
+    T32.enc(inst.i32, Tbrz, cond)
+    A32.enc(inst.b1, Abrz, cond)
+    T32.enc(inst.b1, Tbrz, cond)
+
+A32.enc(base.br_icmp.i32, Abricmp, 0)
+T32.enc(base.br_icmp.i32, Tbricmp, 0)
+
+# Returns branch to the address in the link register.
+A32.enc(base.x_return, Abx, 0)
+T32.enc(base.x_return, Tbx, 0)
@@ -0,0 +1,170 @@
+"""
+ARM32 Encoding recipes.
+
+The encoding recipes defined here correspond to the A32 and T32 instruction
+encodings described in the reference:
+
+    ARM Architecture Reference Manual
+    ARMv7-A and ARMv7-R edition
+    Chapter A5: ARM Instruction Set Encoding
+    Chapter A6: Thumb Instruction Set Encoding
+
+Recipes prefixed with `A` are 32-bit A32 instructions which are always
+executed unconditionally. Recipes prefixed with `T` use the 32-bit Thumb-2
+encodings, except for the 16-bit `bx lr` return.
+"""
+from __future__ import absolute_import
+from cdsl.isa import EncRecipe
+from cdsl.predicates import IsUnsignedInt
+from base.formats import Binary, BinaryImm, MultiAry, Unary, UnaryImm
+from base.formats import BranchIcmp, Branch, Jump, Call, RegMove, Fence
+from .registers import GPR
+
+# Data-processing opcodes.
+#
+# The A32 and T32 encodings number the data-processing opcodes differently.
+A32_OPCODES = {
+        'and': 0b0000,
+        'eor': 0b0001,
+        'sub': 0b0010,
+        'add': 0b0100,
+        'orr': 0b1100,
+        'mov': 0b1101,
+        }
+
+T32_OPCODES = {
+        'and': 0b0000,
+        'orr': 0b0010,
+        'eor': 0b0100,
+        'add': 0b1000,
+        'sub': 0b1101,
+        }
+
+
+def ADP(op):
+    # type: (str) -> int
+    """A32 data-processing instruction. Encbits are the 4-bit opcode."""
+    return A32_OPCODES[op]
+
+
+def TDP(op):
+    # type: (str) -> int
+    """T32 data-processing instruction. Encbits are the 4-bit opcode."""
+    return T32_OPCODES[op]
+
+
+# Shift types for immediate shifts. Encbits are the 2-bit shift type.
+LSL = 0b00
+LSR = 0b01
+ASR = 0b10
+
+# Barrier option for the inner shareable domain. ARMv7 doesn't have the
+# load-only barriers, so all fences use the full barrier.
+ISH = 0b1011
+
+# Condition codes for `brz` and `brnz`.
+EQ = 0b0000
+NE = 0b0001
+
+# A32 recipes.
+
+# Data-processing (register) with an unshifted register operand.
+Arr = EncRecipe('Arr', Binary, size=4, ins=(GPR, GPR), outs=GPR)
+
+# Data-processing (immediate) with an unrotated 8-bit immediate.
+Ari = EncRecipe(
+        'Ari', BinaryImm, size=4, ins=GPR, outs=GPR,
+        instp=IsUnsignedInt(BinaryImm.imm, 8))
+
+# Immediate shift as `mov rd, rm, <shift> #imm`.
+Arsh = EncRecipe(
+        'Arsh', BinaryImm, size=4, ins=GPR, outs=GPR,
+        instp=IsUnsignedInt(BinaryImm.imm, 5))
+
+# Multiply as `mul rd, rn, rm`.
+Amul = EncRecipe('Amul', Binary, size=4, ins=(GPR, GPR), outs=GPR)
+
+# Register copy as `mov rd, rm`.
+Amov = EncRecipe('Amov', Unary, size=4, ins=GPR, outs=GPR)
+
+# Register move as `mov dst, src`.
+Armov = EncRecipe('Armov', RegMove, size=4, ins=GPR, outs=())
+
+# Integer constant as `movw rd, #imm16`.
+Amovw = EncRecipe(
+        'Amovw', UnaryImm, size=4, ins=(), outs=GPR,
+        instp=IsUnsignedInt(UnaryImm.imm, 16))
+
+# Unconditional branch. The 24-bit word offset is relative to the
+# instruction address + 8.
+Ab = EncRecipe('Ab', Jump, size=4, ins=(), outs=(), branch_range=(8, 26))
+
+# Direct call as `bl` with a relocation.
+Abl = EncRecipe('Abl', Call, size=4, ins=(), outs=())
+
+# `cmp rn, #0` followed by `beq` or `bne`. Encbits are the condition.
+Abrz = EncRecipe(
+        'Abrz', Branch, size=8, ins=GPR, outs=(), branch_range=(12, 26))
+
+# `cmp rn, rm` followed by a conditional branch.
+Abricmp = EncRecipe(
+        'Abricmp', BranchIcmp, size=8, ins=(GPR, GPR), outs=(),
+        branch_range=(12, 26))
+
+# Data memory barrier. Encbits are the barrier option.
+Admb = EncRecipe('Admb', Fence, size=4, ins=(), outs=())
+
+# Return as `bx lr`.
+# The variable return values are not encoded.
+Abx = EncRecipe('Abx', MultiAry, size=4, ins=(), outs=())
+
+# T32 recipes.
+
+# Data-processing (shifted register) with a zero shift.
+Trr = EncRecipe('Trr', Binary, size=4, ins=(GPR, GPR), outs=GPR)
+
+# Data-processing (modified immediate) with a plain 8-bit immediate.
+Tri = EncRecipe(
+        'Tri', BinaryImm, size=4, ins=GPR, outs=GPR,
+        instp=IsUnsignedInt(BinaryImm.imm, 8))
+
+# Immediate shift as `mov.w rd, rm, <shift> #imm`.
+Trsh = EncRecipe(
+        'Trsh', BinaryImm, size=4, ins=GPR, outs=GPR,
+        instp=IsUnsignedInt(BinaryImm.imm, 5))
+
+# Multiply as `mul.w rd, rn, rm`.
+Tmul = EncRecipe('Tmul', Binary, size=4, ins=(GPR, GPR), outs=GPR)
+
+# Register copy as `mov.w rd, rm`.
+Tmov = EncRecipe('Tmov', Unary, size=4, ins=GPR, outs=GPR)
+
+# Register move as `mov.w dst, src`.
+Trmov = EncRecipe('Trmov', RegMove, size=4, ins=GPR, outs=())
+
+# Integer constant as `movw rd, #imm16`.
+Tmovw = EncRecipe(
+        'Tmovw', UnaryImm, size=4, ins=(), outs=GPR,
+        instp=IsUnsignedInt(UnaryImm.imm, 16))
+
+# Unconditional branch as `b.w`. The offset is relative to the instruction
+# address + 4.
+Tb = EncRecipe('Tb', Jump, size=4, ins=(), outs=(), branch_range=(4, 25))
+
+# Direct call as `bl` with a relocation.
+Tbl = EncRecipe('Tbl', Call, size=4, ins=(), outs=())
+
+# `cmp.w rn, #0` followed by `beq.w` or `bne.w`. Encbits are the condition.
+Tbrz = EncRecipe(
+        'Tbrz', Branch, size=8, ins=GPR, outs=(), branch_range=(8, 21))
+
+# `cmp.w rn, rm` followed by a conditional `b.w`.
+Tbricmp = EncRecipe(
+        'Tbricmp', BranchIcmp, size=8, ins=(GPR, GPR), outs=(),
+        branch_range=(8, 21))
+
+# Data memory barrier. Encbits are the barrier option.
+Tdmb = EncRecipe('Tdmb', Fence, size=4, ins=(), outs=())
+
+# Return as the 16-bit `bx lr`.
+Tbx = EncRecipe('Tbx', MultiAry, size=2, ins=(), outs=())
@@ -30,6 +30,8 @@ IntRegs = RegBank(
         units=16, prefix='r')
 
 GPR = RegClass(IntRegs)
+# Even-odd register pairs as used by `ldrd` and `strd`.
+GPR2 = RegClass(IntRegs, width=2, pairs=True)
 S = RegClass(FloatRegs, count=32)
 D = RegClass(FloatRegs, width=2)
 Q = RegClass(FloatRegs, width=4)
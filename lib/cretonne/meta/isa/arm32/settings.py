@@ -2,10 +2,20 @@
 ARM32 settings.
 """
 from __future__ import absolute_import
-from cdsl.settings import SettingGroup
+from cdsl.settings import SettingGroup, BoolSetting
+from cdsl.predicates import Or, Not
 import base.settings as shared
 from .defs import ISA
 
 ISA.settings = SettingGroup('arm32', parent=shared.group)
 
+# The ARMv7-M and ARMv8-M microcontroller profiles only support Thumb code.
+has_a32 = BoolSetting(
+        "CPU supports the A32 (ARM) instruction set",
+        default=True)
+
+# Thumb-2 code is generated when compressed instructions are requested, or
+# when the CPU can't execute ARM code.
+use_t32 = Or(shared.is_compressed, Not(has_a32))
+
 ISA.settings.close(globals())
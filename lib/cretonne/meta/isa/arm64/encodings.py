@@ -0,0 +1,175 @@
+"""
+ARM64 Encodings.
+"""
+from __future__ import absolute_import
+from base import instructions as base
+from base.formats import UnaryImm, Load, Store
+from cdsl.predicates import IsUnsignedInt
+from .defs import A64
+from .recipes import ADDSUB, LOGIC, DP2, MUL, FP2, FP1, ADDSUBI, MOVZ
+from .recipes import LDST, CB, BIMM, BREG, BARRIER
+from .recipes import R, Rcopy, Rrmov, Ricmp, I, Iicmp, Iadjsp, Umovz
+from .recipes import FR, FRu, FRrmov, Ld, St, FLd, FSt
+from .recipes import Spill, Fill, FSpill, FFill
+from .recipes import B, Bcall, CBz, Bicmp, Ret, Dmb
+
+# Basic arithmetic binary instructions use the shifted register forms with a
+# zero shift. The `sf` bit selects 64-bit operation.
+for inst,           bits32,           bits64 in [
+        (base.iadd, ADDSUB(0, 0),     ADDSUB(1, 0)),
+        (base.isub, ADDSUB(0, 1),     ADDSUB(1, 1)),
+        (base.band, LOGIC(0, 0b00),   LOGIC(1, 0b00)),
+        (base.bor,  LOGIC(0, 0b01),   LOGIC(1, 0b01)),
+        (base.bxor, LOGIC(0, 0b10),   LOGIC(1, 0b10)),
+        (base.imul, MUL(0),           MUL(1))
+        ]:
+    A64.enc(inst.i32, R, bits32)
+    A64.enc(inst.i64, R, bits64)
+
+# Logical immediates use a bitmask encoding which isn't supported yet, so only
+# `iadd_imm` has an immediate form.
+A64.enc(base.iadd_imm.i32, I, ADDSUBI(0, 0))
+A64.enc(base.iadd_imm.i64, I, ADDSUBI(1, 0))
+
+# Variable shifts mask the shift amount to the register width, just like the
+# cton base instructions.
+for inst,           opcode in [
+        (base.ishl, 0b01000),
+        (base.ushr, 0b01001),
+        (base.sshr, 0b01010)
+        ]:
+    A64.enc(inst.i32.i32, R, DP2(0, opcode))
+    A64.enc(inst.i64.i64, R, DP2(1, opcode))
+
+# Integer comparisons set the flags and materialize the condition with
+# `cset`, so all condition codes are supported.
+A64.enc(base.icmp.i32, Ricmp, ADDSUB(0, 1, S=1))
+A64.enc(base.icmp.i64, Ricmp, ADDSUB(1, 1, S=1))
+A64.enc(base.icmp_imm.i32, Iicmp, ADDSUBI(0, 1, S=1))
+A64.enc(base.icmp_imm.i64, Iicmp, ADDSUBI(1, 1, S=1))
+
+# Register copies are encoded as `orr rd, zr, rm`.
+for ty in [base.copy.i32, base.copy.b1]:
+    A64.enc(ty, Rcopy, LOGIC(0, 0b01))
+for ty in [base.regmove.i32, base.regmove.b1]:
+    A64.enc(ty, Rrmov, LOGIC(0, 0b01))
+A64.enc(base.copy.i64, Rcopy, LOGIC(1, 0b01))
+A64.enc(base.regmove.i64, Rrmov, LOGIC(1, 0b01))
+
+# Integer constants are materialized by a single `movz` when only one 16-bit
+# chunk is non-zero. The unshifted form comes last so it is preferred.
+for hw in reversed(range(2)):
+    A64.enc(
+            base.iconst.i32, Umovz, MOVZ(0, hw),
+            instp=IsUnsignedInt(UnaryImm.imm, 16 * (hw + 1), 16 * hw))
+for hw in reversed(range(3)):
+    A64.enc(
+            base.iconst.i64, Umovz, MOVZ(1, hw),
+            instp=IsUnsignedInt(UnaryImm.imm, 16 * (hw + 1), 16 * hw))
+
+# Loads and stores. The unsigned 12-bit offset is scaled by the access size.
+for inst,                size, opc in [
+        (base.load.i32.i64,    0b10, 0b01),
+        (base.load.i64.i64,    0b11, 0b01),
+        (base.uload8.i32.i64,  0b00, 0b01),
+        (base.uload8.i64.i64,  0b00, 0b01),
+        (base.sload8.i32.i64,  0b00, 0b11),
+        (base.sload8.i64.i64,  0b00, 0b10),
+        (base.uload16.i32.i64, 0b01, 0b01),
+        (base.uload16.i64.i64, 0b01, 0b01),
+        (base.sload16.i32.i64, 0b01, 0b11),
+        (base.sload16.i64.i64, 0b01, 0b10),
+        (base.uload32.i64.i64, 0b10, 0b01),
+        (base.sload32.i64.i64, 0b10, 0b10)
+        ]:
+    A64.enc(inst, Ld, LDST(size, opc),
+            instp=IsUnsignedInt(Load.offset, 12 + size, size))
+
+for inst,                 size in [
+        (base.store.i32.i64,    0b10),
+        (base.store.i64.i64,    0b11),
+        (base.istore8.i32.i64,  0b00),
+        (base.istore8.i64.i64,  0b00),
+        (base.istore16.i32.i64, 0b01),
+        (base.istore16.i64.i64, 0b01),
+        (base.istore32.i64.i64, 0b10)
+        ]:
+    A64.enc(inst, St, LDST(size, 0b00),
+            instp=IsUnsignedInt(Store.offset, 12 + size, size))
+
+A64.enc(base.load.f32.i64, FLd, LDST(0b10, 0b01, V=1),
+        instp=IsUnsignedInt(Load.offset, 14, 2))
+A64.enc(base.load.f64.i64, FLd, LDST(0b11, 0b01, V=1),
+        instp=IsUnsignedInt(Load.offset, 15, 3))
+A64.enc(base.store.f32.i64, FSt, LDST(0b10, 0b00, V=1),
+        instp=IsUnsignedInt(Store.offset, 14, 2))
+A64.enc(base.store.f64.i64, FSt, LDST(0b11, 0b00, V=1),
+        instp=IsUnsignedInt(Store.offset, 15, 3))
+
+# Spill and fill use SP-relative stores and loads.
+for ty in [base.spill.i32, base.spill.b1]:
+    A64.enc(ty, Spill, LDST(0b10, 0b00))
+for ty in [base.fill.i32, base.fill.b1]:
+    A64.enc(ty, Fill, LDST(0b10, 0b01))
+A64.enc(base.spill.i64, Spill, LDST(0b11, 0b00))
+A64.enc(base.fill.i64, Fill, LDST(0b11, 0b01))
+A64.enc(base.spill.f32, FSpill, LDST(0b10, 0b00, V=1))
+A64.enc(base.fill.f32, FFill, LDST(0b10, 0b01, V=1))
+A64.enc(base.spill.f64, FSpill, LDST(0b11, 0b00, V=1))
+A64.enc(base.fill.f64, FFill, LDST(0b11, 0b01, V=1))
+
+# Stack pointer adjustments in the prologue and epilogue.
+A64.enc(base.adjust_sp_imm, Iadjsp, ADDSUBI(1, 0))
+
+# Floating point arithmetic. The `ty` field is 0 for single precision and 1
+# for double precision.
+for inst,           opcode in [
+        (base.fmul, 0b0000),
+        (base.fdiv, 0b0001),
+        (base.fadd, 0b0010),
+        (base.fsub, 0b0011)
+        ]:
+    A64.enc(inst.f32, FR, FP2(0b00, opcode))
+    A64.enc(inst.f64, FR, FP2(0b01, opcode))
+
+for inst,           opcode in [
+        (base.copy, 0b000000),
+        (base.fabs, 0b000001),
+        (base.fneg, 0b000010),
+        (base.sqrt, 0b000011)
+        ]:
+    A64.enc(inst.f32, FRu, FP1(0b00, opcode))
+    A64.enc(inst.f64, FRu, FP1(0b01, opcode))
+
+A64.enc(base.regmove.f32, FRrmov, FP1(0b00, 0b000000))
+A64.enc(base.regmove.f64, FRrmov, FP1(0b01, 0b000000))
+
+# The `ty` field of `fcvt` is the source type.
+A64.enc(base.fpromote.f64.f32, FRu, FP1(0b00, 0b000101))
+A64.enc(base.fdemote.f32.f64, FRu, FP1(0b01, 0b000100))
+
+# Memory fences are `dmb` instructions in the inner shareable domain.
+A64.enc(base.fence, Dmb, BARRIER(0b101))
+
+# Control flow.
+
+# Unconditional branches.
+A64.enc(base.jump, B, BIMM(0))
+A64.enc(base.call, Bcall, BIMM(1))
+
+# Conditional branches.
+A64.enc(base.br_icmp.i32, Bicmp, ADDSUB(0, 1, S=1))
+A64.enc(base.br_icmp.i64, Bicmp, ADDSUB(1, 1, S=1))
+
+# Boolean values are always 0 or 1 in a register, so `cbz` on the 32-bit
+# register works.
+for inst,           op in [
+        (base.brz,  0),
+        (base.brnz, 1)
+        ]:
+    A64.enc(inst.i32, CBz, CB(0, op))
+    A64.enc(inst.i64, CBz, CB(1, op))
+    A64.enc(inst.b1, CBz, CB(0, op))
+
+# Returns use `%x30` to hold the return address.
+A64.enc(base.x_return, Ret, BREG(0b0010))
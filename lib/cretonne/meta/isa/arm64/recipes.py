@@ -0,0 +1,235 @@
+"""
+ARM64 Encoding recipes.
+
+All A64 instructions are 32 bits wide. The encoding recipes defined here
+correspond to the instruction classes described in the reference:
+
+    ARM Architecture Reference Manual
+    ARMv8, for ARMv8-A architecture profile
+    Chapter C4: A64 Instruction Set Encoding
+
+The 16 encoding bits can't hold a full instruction word, so each recipe family
+stores the fixed opcode bits that vary between instructions. The functions
+below compute the encbits for the different instruction classes.
+"""
+from __future__ import absolute_import
+from cdsl.isa import EncRecipe
+from cdsl.predicates import IsUnsignedInt, IsSignedInt
+from base.formats import Binary, BinaryImm, MultiAry, IntCompare, IntCompareImm
+from base.formats import Unary, UnaryImm, BranchIcmp, Branch, Jump, Call
+from base.formats import RegMove, Load, Store, Fence
+from cdsl.registers import Stack
+from .registers import GPR, FPR
+
+# Three-register instructions.
+#
+# Encbits are `insn[31:21] | (insn[14:10] << 11)`. This covers the shifted
+# register forms with a zero shift, the data processing instructions which
+# put a secondary opcode in bits 10-14, and the floating point arithmetic.
+
+
+def R3(top11, op5=0):
+    # type: (int, int) -> int
+    assert top11 <= 0x7ff
+    assert op5 <= 0b11111
+    return top11 | (op5 << 11)
+
+
+def ADDSUB(sf, op, S=0):
+    # type: (int, int, int) -> int
+    """Add/subtract (shifted register)."""
+    return R3((sf << 10) | (op << 9) | (S << 8) | (0b01011 << 3))
+
+
+def LOGIC(sf, opc):
+    # type: (int, int) -> int
+    """Logical (shifted register)."""
+    assert opc <= 0b11
+    return R3((sf << 10) | (opc << 8) | (0b01010 << 3))
+
+
+def DP2(sf, opcode):
+    # type: (int, int) -> int
+    """Data-processing (2 source)."""
+    return R3((sf << 10) | 0b0011010110, opcode)
+
+
+def MUL(sf):
+    # type: (int) -> int
+    """Multiply as `madd` with `Ra = zr`."""
+    return R3((sf << 10) | 0b0011011000, 0b11111)
+
+
+def FP2(ty, opcode):
+    # type: (int, int) -> int
+    """Floating-point data-processing (2 source)."""
+    assert opcode <= 0b0111
+    return R3((0b00011110 << 3) | (ty << 1) | 1, (opcode << 2) | 0b10)
+
+
+# Floating-point data-processing (1 source).
+#
+# Encbits are `ty | (opcode << 2)`.
+
+
+def FP1(ty, opcode):
+    # type: (int, int) -> int
+    assert ty <= 0b11
+    assert opcode <= 0b111111
+    return ty | (opcode << 2)
+
+
+# Add/subtract (immediate).
+#
+# Encbits are `insn[31:22]`.
+
+
+def ADDSUBI(sf, op, S=0):
+    # type: (int, int, int) -> int
+    return (sf << 9) | (op << 8) | (S << 7) | (0b100010 << 1)
+
+
+# Move wide (immediate).
+#
+# Encbits are `insn[31:21]`, including the `hw` shift amount.
+
+
+def MOVZ(sf, hw):
+    # type: (int, int) -> int
+    assert hw < 2 + 2 * sf
+    return (sf << 10) | (0b10 << 8) | (0b100101 << 2) | hw
+
+
+# Load/store register (unsigned immediate).
+#
+# Encbits are `insn[31:22]`. The `size` field doubles as the scale of the
+# unsigned 12-bit offset.
+
+
+def LDST(size, opc, V=0):
+    # type: (int, int, int) -> int
+    assert size <= 0b11
+    assert opc <= 0b11
+    return (size << 8) | (0b111 << 5) | (V << 4) | (0b01 << 2) | opc
+
+
+# Compare and branch (immediate).
+#
+# Encbits are `insn[31:24]`.
+
+
+def CB(sf, op):
+    # type: (int, int) -> int
+    return (sf << 7) | (0b011010 << 1) | op
+
+
+# Unconditional branch (immediate).
+#
+# Encbits are `insn[31:26]`.
+
+
+def BIMM(op):
+    # type: (int) -> int
+    return (op << 5) | 0b00101
+
+
+# Unconditional branch (register).
+#
+# Encbits are the `opc` field in `insn[24:21]`.
+
+
+def BREG(opc):
+    # type: (int) -> int
+    assert opc <= 0b1111
+    return opc
+
+
+# Barriers.
+#
+# Encbits are the `opc` field in `insn[7:5]`.
+
+
+def BARRIER(opc):
+    # type: (int) -> int
+    assert opc <= 0b111
+    return opc
+
+
+# Three-register integer instructions.
+R = EncRecipe('R', Binary, size=4, ins=(GPR, GPR), outs=GPR)
+
+# Register copy encoded as `orr rd, zr, rm`.
+Rcopy = EncRecipe('Rcopy', Unary, size=4, ins=GPR, outs=GPR)
+
+# Register move encoded as `orr dst, zr, src`.
+Rrmov = EncRecipe('Rrmov', RegMove, size=4, ins=GPR, outs=())
+
+# Integer comparison as `subs zr, rn, rm` followed by `cset rd, cond`.
+Ricmp = EncRecipe('Ricmp', IntCompare, size=8, ins=(GPR, GPR), outs=GPR)
+
+# Add immediate with an unsigned 12-bit immediate.
+I = EncRecipe(
+        'I', BinaryImm, size=4, ins=GPR, outs=GPR,
+        instp=IsUnsignedInt(BinaryImm.imm, 12))
+
+# Integer comparison as `subs zr, rn, #imm` followed by `cset rd, cond`.
+Iicmp = EncRecipe(
+        'Iicmp', IntCompareImm, size=8, ins=GPR, outs=GPR,
+        instp=IsUnsignedInt(IntCompareImm.imm, 12))
+
+# Stack pointer adjustment as `add sp, sp, #imm` or `sub sp, sp, #imm`.
+# The magnitude 4096 is encoded with a 12-bit shift.
+Iadjsp = EncRecipe(
+        'Iadjsp', UnaryImm, size=4, ins=(), outs=(),
+        instp=IsSignedInt(UnaryImm.imm, 13))
+
+# Integer constant as a `movz` of a 16-bit chunk. The encodings provide the
+# instruction predicate matching the `hw` shift in the encbits.
+Umovz = EncRecipe('Umovz', UnaryImm, size=4, ins=(), outs=GPR)
+
+# Floating point arithmetic with two register operands.
+FR = EncRecipe('FR', Binary, size=4, ins=(FPR, FPR), outs=FPR)
+
+# Floating point unary operations, including `fmov` copies and `fcvt`.
+FRu = EncRecipe('FRu', Unary, size=4, ins=FPR, outs=FPR)
+
+# Floating point register move as `fmov dst, src`.
+FRrmov = EncRecipe('FRrmov', RegMove, size=4, ins=FPR, outs=())
+
+# Loads and stores with a scaled unsigned 12-bit offset. The offset
+# predicate depends on the access size, so it is provided by the encodings.
+Ld = EncRecipe('Ld', Load, size=4, ins=GPR, outs=GPR)
+St = EncRecipe('St', Store, size=4, ins=(GPR, GPR), outs=())
+FLd = EncRecipe('FLd', Load, size=4, ins=GPR, outs=FPR)
+FSt = EncRecipe('FSt', Store, size=4, ins=(FPR, GPR), outs=())
+
+# Spill and fill with SP-relative stores and loads.
+Spill = EncRecipe('Spill', Unary, size=4, ins=GPR, outs=Stack(GPR))
+Fill = EncRecipe('Fill', Unary, size=4, ins=Stack(GPR), outs=GPR)
+FSpill = EncRecipe('FSpill', Unary, size=4, ins=FPR, outs=Stack(FPR))
+FFill = EncRecipe('FFill', Unary, size=4, ins=Stack(FPR), outs=FPR)
+
+# Unconditional branch with a 26-bit word offset.
+B = EncRecipe('B', Jump, size=4, ins=(), outs=(), branch_range=(0, 28))
+
+# Direct call as `bl` with a relocation.
+Bcall = EncRecipe('Bcall', Call, size=4, ins=(), outs=())
+
+# Compare and branch on zero with a 19-bit word offset.
+CBz = EncRecipe(
+        'CBz', Branch, size=4, ins=GPR, outs=(), branch_range=(0, 21))
+
+# Integer comparison as `subs zr, rn, rm` followed by `b.cond`. The branch
+# offset is relative to the `b.cond` instruction.
+Bicmp = EncRecipe(
+        'Bicmp', BranchIcmp, size=8, ins=(GPR, GPR), outs=(),
+        branch_range=(4, 21))
+
+# Data memory barrier. The barrier type in CRm is derived from the memory
+# ordering.
+Dmb = EncRecipe('Dmb', Fence, size=4, ins=(), outs=())
+
+# Return as `ret x30`. The return address is provided by a special-purpose
+# `link` return value that is added by legalize_signature().
+# The variable return values are not encoded.
+Ret = EncRecipe('Ret', MultiAry, size=4, ins=(), outs=())
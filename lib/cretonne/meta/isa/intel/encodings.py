@@ -0,0 +1,337 @@
+"""
+Intel Encodings.
+"""
+from __future__ import absolute_import
+from base import instructions as base
+from base.immediates import memorder
+from base.formats import Call, FuncAddr
+from cdsl.ast import Var
+from cdsl.predicates import IsColocatedFunc
+from .defs import I32, I64
+from . import recipes as r
+from .recipes import OP, OP0F, OP0F3A
+from .settings import use_sse2, use_sse41, use_popcnt, use_bmi1, use_lzcnt
+from .settings import use_pic, not_pic, use_atomics
+
+try:
+    from typing import Any  # noqa
+    from cdsl.isa import EncRecipe  # noqa
+except ImportError:
+    pass
+
+# Dummies for instruction predicates.
+x = Var('x')
+p = Var('p')
+
+
+def enc_both(inst, plain, rex, bits, **kwargs):
+    # type: (Any, EncRecipe, EncRecipe, int, **Any) -> None
+    """
+    Add encodings of `inst` for both 32-bit and 64-bit modes.
+
+    The 32-bit mode can't use REX prefixes, so it gets the `plain` recipe.
+    The 64-bit mode uses the `rex` recipe to access all the registers.
+    """
+    I32.enc(inst, plain, bits, **kwargs)
+    I64.enc(inst, rex, bits, **kwargs)
+
+
+# Two-address integer ALU instructions. The 64-bit versions set REX.W.
+for inst,           inst_imm,      op,   rrr in [
+        (base.iadd, base.iadd_imm, 0x01, 0),
+        (base.isub, None,          0x29, 5),
+        (base.band, base.band_imm, 0x21, 4),
+        (base.bor,  base.bor_imm,  0x09, 1),
+        (base.bxor, base.bxor_imm, 0x31, 6)
+        ]:
+    enc_both(inst.i32, r.Op1rr, r.RexOp1rr, OP(op))
+    I64.enc(inst.i64, r.RexOp1rr, OP(op, w=1))
+
+    # Immediate versions use the 0x83 and 0x81 group-1 opcodes.
+    if inst_imm:
+        enc_both(inst_imm.i32, r.Op1rib, r.RexOp1rib, OP(0x83, rrr=rrr))
+        enc_both(inst_imm.i32, r.Op1rid, r.RexOp1rid, OP(0x81, rrr=rrr))
+        I64.enc(inst_imm.i64, r.RexOp1rib, OP(0x83, rrr=rrr, w=1))
+        I64.enc(inst_imm.i64, r.RexOp1rid, OP(0x81, rrr=rrr, w=1))
+
+enc_both(base.imul.i32, r.Op2rrx, r.RexOp2rrx, OP0F(0xaf))
+I64.enc(base.imul.i64, r.RexOp2rrx, OP0F(0xaf, w=1))
+
+# Dynamic shifts take the shift amount in %rcx. The hardware masks the shift
+# amount the same way as the Cretonne instructions.
+for inst,           inst_imm,      rrr in [
+        (base.rotl, base.rotl_imm, 0),
+        (base.rotr, base.rotr_imm, 1),
+        (base.ishl, base.ishl_imm, 4),
+        (base.ushr, base.ushr_imm, 5),
+        (base.sshr, base.sshr_imm, 7)
+        ]:
+    enc_both(inst.i32.i32, r.Op1rc, r.RexOp1rc, OP(0xd3, rrr=rrr))
+    I64.enc(inst.i64.i64, r.RexOp1rc, OP(0xd3, rrr=rrr, w=1))
+    I64.enc(inst.i64.i32, r.RexOp1rc, OP(0xd3, rrr=rrr, w=1))
+    I64.enc(inst.i32.i64, r.RexOp1rc, OP(0xd3, rrr=rrr))
+
+    enc_both(inst_imm.i32, r.Op1rishb, r.RexOp1rishb, OP(0xc1, rrr=rrr))
+    I64.enc(inst_imm.i64, r.RexOp1rishb, OP(0xc1, rrr=rrr, w=1))
+
+# Integer constants. A 32-bit `mov` clears the high bits of the register.
+enc_both(base.iconst.i32, r.Op1puid, r.RexOp1puid, OP(0xb8))
+I64.enc(base.iconst.i64, r.RexOp1uid, OP(0xc7, rrr=0, w=1))
+I64.enc(base.iconst.i64, r.RexOp1puiq, OP(0xb8, w=1))
+
+# Register copies and moves.
+for ty in [base.copy.i32, base.copy.b1]:
+    enc_both(ty, r.Op1umr, r.RexOp1umr, OP(0x89))
+for ty in [base.regmove.i32, base.regmove.b1]:
+    enc_both(ty, r.Op1rmov, r.RexOp1rmov, OP(0x89))
+I64.enc(base.copy.i64, r.RexOp1umr, OP(0x89, w=1))
+I64.enc(base.regmove.i64, r.RexOp1rmov, OP(0x89, w=1))
+
+# Boolean values are always 0 or 1 in a register, so `bint` is a copy. A
+# 32-bit `mov` clears the high bits of a 64-bit register.
+enc_both(base.bint.i32.b1, r.Op1umr, r.RexOp1umr, OP(0x89))
+I64.enc(base.bint.i64.b1, r.RexOp1umr, OP(0x89))
+
+# A 32-bit `mov` also zero-extends a 32-bit value to 64 bits, and it copies the
+# low half of a 64-bit value.
+I64.enc(base.uextend.i64.i32, r.RexOp1umr, OP(0x89))
+I64.enc(base.ireduce.i32.i64, r.RexOp1umr, OP(0x89))
+
+# Integer comparisons produce a 0 or 1 value in a register.
+enc_both(base.icmp.i32, r.Op1icscc, r.RexOp1icscc, OP(0x39))
+enc_both(base.icmp_imm.i32, r.Op1icsccib, r.RexOp1icsccib, OP(0x83, rrr=7))
+enc_both(base.icmp_imm.i32, r.Op1icsccid, r.RexOp1icsccid, OP(0x81, rrr=7))
+I64.enc(base.icmp.i64, r.RexOp1icscc, OP(0x39, w=1))
+I64.enc(base.icmp_imm.i64, r.RexOp1icsccib, OP(0x83, rrr=7, w=1))
+I64.enc(base.icmp_imm.i64, r.RexOp1icsccid, OP(0x81, rrr=7, w=1))
+
+# Conditional selects use `cmovnz`, which keeps the `y` operand when the
+# condition is zero. The width of the `test` is determined by the condition
+# type. Selects without an encoding are expanded into branches.
+for inst in [base.select.i32.b1, base.select.i32.i32, base.select.b1.b1,
+             base.select.b1.i32]:
+    enc_both(inst, r.Op2tcmov, r.RexOp2tcmov, OP0F(0x45))
+I64.enc(base.select.i32.i64, r.RexOp2tcmov, OP0F(0x45))
+I64.enc(base.select.b1.i64, r.RexOp2tcmov, OP0F(0x45))
+for inst in [base.select.i64.b1, base.select.i64.i32, base.select.i64.i64]:
+    I64.enc(inst, r.RexOp2tcmov, OP0F(0x45, w=1))
+
+# Bit counting instructions.
+for inst,       op,   isap in [
+        (base.popcnt, 0xb8, use_popcnt),
+        (base.clz,    0xbd, use_lzcnt),
+        (base.ctz,    0xbc, use_bmi1)
+        ]:
+    enc_both(inst.i32, r.Mp2urm, r.RexMp2urm, OP0F(op, pp=0xf3), isap=isap)
+    I64.enc(inst.i64, r.RexMp2urm, OP0F(op, pp=0xf3, w=1), isap=isap)
+
+# Loads and stores. Addresses are 32 bits in 32-bit mode and 64 bits in 64-bit
+# mode.
+I32.enc(base.load.i32.i32, r.Op1ld, OP(0x8b))
+I32.enc(base.uload16.i32.i32, r.Op2ld, OP0F(0xb7))
+I32.enc(base.sload16.i32.i32, r.Op2ld, OP0F(0xbf))
+I32.enc(base.uload8.i32.i32, r.Op2ld, OP0F(0xb6))
+I32.enc(base.sload8.i32.i32, r.Op2ld, OP0F(0xbe))
+
+I32.enc(base.store.i32.i32, r.Op1st, OP(0x89))
+I32.enc(base.istore16.i32.i32, r.Mp1st, OP(0x89, pp=0x66))
+I32.enc(base.istore8.i32.i32, r.Op1st_abcd, OP(0x88))
+
+I64.enc(base.load.i64.i64, r.RexOp1ld, OP(0x8b, w=1))
+I64.enc(base.load.i32.i64, r.RexOp1ld, OP(0x8b))
+I64.enc(base.uload32.i64.i64, r.RexOp1ld, OP(0x8b))
+I64.enc(base.sload32.i64.i64, r.RexOp1ld, OP(0x63, w=1))
+I64.enc(base.uload16.i64.i64, r.RexOp2ld, OP0F(0xb7, w=1))
+I64.enc(base.uload16.i32.i64, r.RexOp2ld, OP0F(0xb7))
+I64.enc(base.sload16.i64.i64, r.RexOp2ld, OP0F(0xbf, w=1))
+I64.enc(base.sload16.i32.i64, r.RexOp2ld, OP0F(0xbf))
+I64.enc(base.uload8.i64.i64, r.RexOp2ld, OP0F(0xb6, w=1))
+I64.enc(base.uload8.i32.i64, r.RexOp2ld, OP0F(0xb6))
+I64.enc(base.sload8.i64.i64, r.RexOp2ld, OP0F(0xbe, w=1))
+I64.enc(base.sload8.i32.i64, r.RexOp2ld, OP0F(0xbe))
+
+I64.enc(base.store.i64.i64, r.RexOp1st, OP(0x89, w=1))
+I64.enc(base.store.i32.i64, r.RexOp1st, OP(0x89))
+I64.enc(base.istore32.i64.i64, r.RexOp1st, OP(0x89))
+I64.enc(base.istore16.i64.i64, r.RexMp1st, OP(0x89, pp=0x66))
+I64.enc(base.istore16.i32.i64, r.RexMp1st, OP(0x89, pp=0x66))
+I64.enc(base.istore8.i64.i64, r.RexOp1st, OP(0x88))
+I64.enc(base.istore8.i32.i64, r.RexOp1st, OP(0x88))
+
+# Spill and fill use SP-relative stores and loads.
+for ty in [base.spill.i32, base.spill.b1]:
+    enc_both(ty, r.Op1spill, r.RexOp1spill, OP(0x89))
+for ty in [base.fill.i32, base.fill.b1]:
+    enc_both(ty, r.Op1fill, r.RexOp1fill, OP(0x8b))
+I64.enc(base.spill.i64, r.RexOp1spill, OP(0x89, w=1))
+I64.enc(base.fill.i64, r.RexOp1fill, OP(0x8b, w=1))
+
+# Stack pointer adjustments in the prologue and epilogue: `add rsp, imm`.
+I32.enc(base.adjust_sp_imm, r.Op1adjustsp_ib, OP(0x83, rrr=0))
+I32.enc(base.adjust_sp_imm, r.Op1adjustsp_id, OP(0x81, rrr=0))
+I64.enc(base.adjust_sp_imm, r.RexOp1adjustsp_ib, OP(0x83, rrr=0, w=1))
+I64.enc(base.adjust_sp_imm, r.RexOp1adjustsp_id, OP(0x81, rrr=0, w=1))
+
+# Function addresses are constants, or loaded from the GOT in PIC mode.
+# 32-bit PIC would need a GOT base register which isn't supported.
+I32.enc(base.func_addr.i32, r.Op1fnaddr4, OP(0xb8), isap=not_pic)
+I64.enc(base.func_addr.i64, r.RexOp1fnaddr8, OP(0xb8, w=1), isap=not_pic)
+I64.enc(base.func_addr.i64, r.RexOp1gotpcrel, OP(0x8b, w=1), isap=use_pic)
+# Colocated functions are addressed relative to %rip instead of through the GOT.
+I64.enc(base.func_addr.i64, r.RexOp1pcrel_fnaddr, OP(0x8d, w=1),
+        isap=use_pic, instp=IsColocatedFunc(FuncAddr.func_ref))
+
+# Thread-local storage. The thread pointer is the base of the %gs segment in
+# 32-bit mode and the %fs segment in 64-bit mode.
+# 32-bit general-dynamic TLS would need a GOT base register which isn't
+# supported.
+I32.enc(base.thread_pointer.i32, r.Op1tp_gs, OP(0x8b))
+I64.enc(base.thread_pointer.i64, r.RexOp1tp_fs, OP(0x8b, w=1))
+I32.enc(base.tls_ie_offset.i32, r.Op1tlsie, OP(0x8b))
+I64.enc(base.tls_ie_offset.i64, r.RexOp1gottpoff, OP(0x8b, w=1))
+I64.enc(base.tls_gd_addr.i64, r.RexOp1tlsgd, OP(0x8d, pp=0x66, w=1))
+
+# Atomic memory operations, gated by the `enable_atomics` setting.
+#
+# Sequentially consistent stores need a trailing `mfence`. That encoding has a
+# predicate on the ordering, so it is listed last to take precedence.
+for inst,               recipe,     rex_recipe,    mm,   op in [
+        (base.atomic_load,  r.Op1ald,   r.RexOp1ald,   0,    0x8b),
+        (base.atomic_store, r.Op1ast,   r.RexOp1ast,   0,    0x89),
+        (base.atomic_xchg,  r.Op1axchg, r.RexOp1axchg, 0,    0x87),
+        (base.atomic_add,   r.Op2axadd, r.RexOp2axadd, 0x0f, 0xc1),
+        (base.atomic_sub,   r.Op2axsub, r.RexOp2axsub, 0x0f, 0xc1),
+        (base.atomic_cas,   r.Op2acas,  r.RexOp2acas,  0x0f, 0xb1),
+        (base.atomic_and,   r.Op1armw,  r.RexOp1armw,  0,    0x23),
+        (base.atomic_or,    r.Op1armw,  r.RexOp1armw,  0,    0x0b),
+        (base.atomic_xor,   r.Op1armw,  r.RexOp1armw,  0,    0x33)
+        ]:
+    I32.enc(inst.i32.i32, recipe, OP(op, mm=mm), isap=use_atomics)
+    I64.enc(inst.i32.i64, rex_recipe, OP(op, mm=mm), isap=use_atomics)
+    I64.enc(inst.i64.i64, rex_recipe, OP(op, mm=mm, w=1), isap=use_atomics)
+
+I32.enc(
+        base.atomic_store.i32.i32(memorder.seq_cst, x, p),
+        r.Op1ast_fence, OP(0x89), isap=use_atomics)
+I64.enc(
+        base.atomic_store.i32.i64(memorder.seq_cst, x, p),
+        r.RexOp1ast_fence, OP(0x89), isap=use_atomics)
+I64.enc(
+        base.atomic_store.i64.i64(memorder.seq_cst, x, p),
+        r.RexOp1ast_fence, OP(0x89, w=1), isap=use_atomics)
+
+# Memory fences. Only a sequentially consistent fence needs an `mfence`
+# instruction.
+for mode in [I32, I64]:
+    mode.enc(base.fence, r.Op0fence, 0)
+    mode.enc(base.fence(memorder.seq_cst), r.Op2mfence, OP(0xae, mm=0x0f))
+
+# Stack overflow checks and probes in the prologue.
+I32.enc(base.stack_check.i32, r.Op1stkchk, OP(0x39))
+I64.enc(base.stack_check.i64, r.RexOp1stkchk, OP(0x39, w=1))
+I32.enc(base.stack_probe, r.Op1stkprobe, OP(0x85))
+I64.enc(base.stack_probe, r.Op1stkprobe, OP(0x85))
+
+# Control flow.
+#
+# Branches have a short form with an 8-bit displacement which comes last so it
+# is preferred. Branch relaxation switches to the 32-bit displacement when the
+# destination is out of range.
+for mode in [I32, I64]:
+    mode.enc(base.jump, r.Op1jmpd, OP(0xe9))
+    mode.enc(base.jump, r.Op1jmpb, OP(0xeb))
+    mode.enc(base.call, r.Op1call_id, OP(0xe8), isap=not_pic)
+    mode.enc(base.call, r.Op1call_plt_id, OP(0xe8), isap=use_pic)
+    # Colocated functions are called directly without going through the PLT.
+    mode.enc(base.call, r.Op1call_id, OP(0xe8),
+             isap=use_pic, instp=IsColocatedFunc(Call.func_ref))
+    mode.enc(base.x_return, r.Op1ret, OP(0xc3))
+
+# Jump tables are addressed relative to %rip, so they are only supported in
+# 64-bit mode. The entries are sign-extended with `movslq`.
+I64.enc(base.jump_table_base.i64, r.RexOp1jt_base, OP(0x8d, w=1))
+I64.enc(base.jump_table_entry.i64, r.RexOp1jt_entry, OP(0x63, w=1))
+I64.enc(base.indirect_jump_table_br.i64, r.RexOp1jt_br, OP(0xff, rrr=4))
+
+# `test r, r` followed by `jz` or `jnz`. The condition code is determined by
+# the branch opcode.
+for inst in [base.brz, base.brnz]:
+    for recipe, rex_recipe in [
+            (r.Op1tjccd, r.RexOp1tjccd),
+            (r.Op1tjccb, r.RexOp1tjccb)
+            ]:
+        enc_both(inst.i32, recipe, rex_recipe, OP(0x85))
+        enc_both(inst.b1, recipe, rex_recipe, OP(0x85))
+        I64.enc(inst.i64, rex_recipe, OP(0x85, w=1))
+
+# `cmp r, r` followed by `jcc`. The condition code is determined by the
+# `intcc` operand.
+for recipe, rex_recipe in [
+        (r.Op1icjccd, r.RexOp1icjccd),
+        (r.Op1icjccb, r.RexOp1icjccb)
+        ]:
+    enc_both(base.br_icmp.i32, recipe, rex_recipe, OP(0x39))
+    I64.enc(base.br_icmp.i64, rex_recipe, OP(0x39, w=1))
+
+# SSE floating point.
+for inst,           op in [
+        (base.fadd, 0x58),
+        (base.fsub, 0x5c),
+        (base.fmul, 0x59),
+        (base.fdiv, 0x5e)
+        ]:
+    enc_both(inst.f32, r.Mp2fa, r.RexMp2fa, OP0F(op, pp=0xf3), isap=use_sse2)
+    enc_both(inst.f64, r.Mp2fa, r.RexMp2fa, OP0F(op, pp=0xf2), isap=use_sse2)
+
+enc_both(base.sqrt.f32, r.Mp2furm, r.RexMp2furm, OP0F(0x51, pp=0xf3),
+         isap=use_sse2)
+enc_both(base.sqrt.f64, r.Mp2furm, r.RexMp2furm, OP0F(0x51, pp=0xf2),
+         isap=use_sse2)
+
+# Rounding. The rounding mode is determined by the opcode.
+for inst in [base.nearest, base.floor, base.ceil, base.trunc]:
+    enc_both(inst.f32, r.Mp3furmi_rnd, r.RexMp3furmi_rnd,
+             OP0F3A(0x0a, pp=0x66), isap=use_sse41)
+    enc_both(inst.f64, r.Mp3furmi_rnd, r.RexMp3furmi_rnd,
+             OP0F3A(0x0b, pp=0x66), isap=use_sse41)
+
+# Conversions.
+enc_both(base.fpromote.f64.f32, r.Mp2furm, r.RexMp2furm,
+         OP0F(0x5a, pp=0xf3), isap=use_sse2)
+enc_both(base.fdemote.f32.f64, r.Mp2furm, r.RexMp2furm,
+         OP0F(0x5a, pp=0xf2), isap=use_sse2)
+enc_both(base.fcvt_from_sint.f32.i32, r.Mp2frurm, r.RexMp2frurm,
+         OP0F(0x2a, pp=0xf3), isap=use_sse2)
+enc_both(base.fcvt_from_sint.f64.i32, r.Mp2frurm, r.RexMp2frurm,
+         OP0F(0x2a, pp=0xf2), isap=use_sse2)
+I64.enc(base.fcvt_from_sint.f32.i64, r.RexMp2frurm,
+        OP0F(0x2a, pp=0xf3, w=1), isap=use_sse2)
+I64.enc(base.fcvt_from_sint.f64.i64, r.RexMp2frurm,
+        OP0F(0x2a, pp=0xf2, w=1), isap=use_sse2)
+
+# Bit casts between the integer and floating point registers use `movd` and
+# `movq`.
+enc_both(base.bitcast.f32.i32, r.Mp2frurm, r.RexMp2frurm,
+         OP0F(0x6e, pp=0x66), isap=use_sse2)
+enc_both(base.bitcast.i32.f32, r.Mp2rfumr, r.RexMp2rfumr,
+         OP0F(0x7e, pp=0x66), isap=use_sse2)
+I64.enc(base.bitcast.f64.i64, r.RexMp2frurm,
+        OP0F(0x6e, pp=0x66, w=1), isap=use_sse2)
+I64.enc(base.bitcast.i64.f64, r.RexMp2rfumr,
+        OP0F(0x7e, pp=0x66, w=1), isap=use_sse2)
+
+# Floating point copies use `movaps` which copies the whole register.
+for ty in [base.copy.f32, base.copy.f64]:
+    enc_both(ty, r.Op2furm, r.RexOp2furm, OP0F(0x28), isap=use_sse2)
+for ty in [base.regmove.f32, base.regmove.f64]:
+    enc_both(ty, r.Op2frmov, r.RexOp2frmov, OP0F(0x28), isap=use_sse2)
+
+# Floating point spill and fill use `movss` and `movsd`.
+enc_both(base.spill.f32, r.Mp2fspill, r.RexMp2fspill, OP0F(0x11, pp=0xf3),
+         isap=use_sse2)
+enc_both(base.spill.f64, r.Mp2fspill, r.RexMp2fspill, OP0F(0x11, pp=0xf2),
+         isap=use_sse2)
+enc_both(base.fill.f32, r.Mp2ffill, r.RexMp2ffill, OP0F(0x10, pp=0xf3),
+         isap=use_sse2)
+enc_both(base.fill.f64, r.Mp2ffill, r.RexMp2ffill, OP0F(0x10, pp=0xf2),
+         isap=use_sse2)
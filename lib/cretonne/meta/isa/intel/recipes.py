@@ -0,0 +1,431 @@
+"""
+Intel Encoding recipes.
+
+Intel instructions have a variable length encoding, but each encoding recipe
+must have a fixed size. The recipes are named after the opcode prefixes they
+emit, followed by a description of the operands:
+
+    Op1*     <op>
+    Op2*     0F <op>
+    Mp2*     <pp> 0F <op>
+    Mp3*     <pp> 0F <mm> <op>
+
+Here, `<pp>` is one of the legacy prefixes 66, F3, or F2 and `<mm>` is an
+additional opcode escape byte, 38 or 3A. The `Rex` variants of the recipes
+emit a REX prefix between the legacy prefix and the opcode. The REX prefix is
+required to access the registers `%r8`-`%r15` and `%xmm8`-`%xmm15`, and it
+holds the `W` bit selecting 64-bit operands.
+"""
+from __future__ import absolute_import
+from cdsl.isa import EncRecipe
+from cdsl.predicates import IsSignedInt, IsEqual
+from cdsl.registers import RegClass, Register, Stack
+from base.formats import Nullary, Unary, UnaryImm, UnaryGlobalVar, Binary
+from base.formats import BinaryImm, Ternary
+from base.formats import MultiAry, IntCompare, IntCompareImm, Jump, Branch
+from base.formats import BranchIcmp, BranchTable, BranchTableBase
+from base.formats import BranchTableEntry, Call, FuncAddr, Load, Store, RegMove
+from base.formats import AtomicLoad, AtomicRmw, AtomicCas, Fence
+from .registers import GPR, GPR8, ABCD, FPR, FPR8
+
+try:
+    from typing import Tuple, Any, TYPE_CHECKING  # noqa
+    if TYPE_CHECKING:
+        from cdsl.isa import ConstraintSeq, OperandConstraint  # noqa
+        from cdsl.formats import InstructionFormat  # noqa
+except ImportError:
+    pass
+
+# Encbits for the Intel recipes are:
+#
+#   Bits 0-7:   The final opcode byte.
+#   Bits 8-9:   pp, the legacy prefix: 00 none, 01 66, 10 F3, 11 F2.
+#   Bits 10-11: mm, the opcode escape: 00 none, 01 0F, 10 0F 38, 11 0F 3A.
+#   Bits 12-14: rrr, an opcode extension in the ModR/M reg field.
+#   Bit 15:     REX.W, selecting a 64-bit operand size.
+#
+# The functions below encode the encbits.
+
+PREFIX = {0x66: 1, 0xf3: 2, 0xf2: 3}
+ESCAPE = {0x0f: 1, 0x0f38: 2, 0x0f3a: 3}
+
+
+def OP(op, pp=0, mm=0, rrr=0, w=0):
+    # type: (int, int, int, int, int) -> int
+    assert op <= 0xff
+    assert rrr <= 0b111
+    assert w <= 1
+    bits = op | (rrr << 12) | (w << 15)
+    if pp:
+        bits |= PREFIX[pp] << 8
+    if mm:
+        bits |= ESCAPE[mm] << 10
+    return bits
+
+
+def OP0F(op, pp=0, rrr=0, w=0):
+    # type: (int, int, int, int) -> int
+    return OP(op, pp=pp, mm=0x0f, rrr=rrr, w=w)
+
+
+def OP0F3A(op, pp=0, rrr=0, w=0):
+    # type: (int, int, int, int) -> int
+    return OP(op, pp=pp, mm=0x0f3a, rrr=rrr, w=w)
+
+
+# Register classes available without a REX prefix.
+NOREX = {GPR: GPR8, FPR: FPR8}
+
+
+def norex(c):
+    # type: (OperandConstraint) -> OperandConstraint
+    """
+    Get the version of the constraint `c` that doesn't need a REX prefix.
+    """
+    if isinstance(c, RegClass):
+        return NOREX.get(c, c)
+    if isinstance(c, Stack):
+        return Stack(norex(c.regclass))
+    assert isinstance(c, int) or isinstance(c, Register)
+    return c
+
+
+def rex_pair(name, format, size, ins, outs, **kwargs):
+    # type: (str, InstructionFormat, int, ConstraintSeq, ConstraintSeq, **Any) -> Tuple[EncRecipe, EncRecipe] # noqa
+    """
+    Create a pair of recipes without and with a REX prefix.
+
+    The plain recipe is called `name` and its operands are limited to the
+    registers that can be encoded without a REX prefix. The `Rex` recipe is
+    one byte larger.
+    """
+    if not isinstance(ins, tuple):
+        ins = (ins,)
+    if not isinstance(outs, tuple):
+        outs = (outs,)
+    plain = EncRecipe(
+            name, format, size,
+            ins=tuple(map(norex, ins)), outs=tuple(map(norex, outs)),
+            **kwargs)
+    rex = EncRecipe('Rex' + name, format, size + 1, ins, outs, **kwargs)
+    return (plain, rex)
+
+
+# XX /r with the first operand in r/m and the second in reg. The result is
+# tied to the first operand.
+# This is the two-address form of most integer ALU instructions.
+Op1rr, RexOp1rr = rex_pair(
+        'Op1rr', Binary, size=2, ins=(GPR, GPR), outs=0)
+
+# 0F XX /r with the first operand in reg and the second in r/m. The result is
+# tied to the first operand.
+Op2rrx, RexOp2rrx = rex_pair(
+        'Op2rrx', Binary, size=3, ins=(GPR, GPR), outs=0)
+
+# XX /n with the shift count in %rcx.
+Op1rc, RexOp1rc = rex_pair(
+        'Op1rc', Binary, size=2, ins=(GPR, GPR.rcx), outs=0)
+
+# XX /n ib with an 8-bit signed immediate.
+Op1rib, RexOp1rib = rex_pair(
+        'Op1rib', BinaryImm, size=3, ins=GPR, outs=0,
+        instp=IsSignedInt(BinaryImm.imm, 8))
+
+# XX /n id with a 32-bit signed immediate.
+Op1rid, RexOp1rid = rex_pair(
+        'Op1rid', BinaryImm, size=6, ins=GPR, outs=0,
+        instp=IsSignedInt(BinaryImm.imm, 32))
+
+# XX /n ib with an 8-bit shift amount. The hardware masks the shift amount the
+# same way as the Cretonne shift instructions.
+Op1rishb, RexOp1rishb = rex_pair(
+        'Op1rishb', BinaryImm, size=3, ins=GPR, outs=0)
+
+# XX /r with the argument in reg and the result in r/m.
+# This is `mov r/m, reg` used for register copies.
+Op1umr, RexOp1umr = rex_pair('Op1umr', Unary, size=2, ins=GPR, outs=GPR)
+
+# XX /r register move with the source in reg and the destination in r/m.
+Op1rmov, RexOp1rmov = rex_pair(
+        'Op1rmov', RegMove, size=2, ins=GPR, outs=())
+
+# XX+rd id materializing a 32-bit immediate.
+Op1puid, RexOp1puid = rex_pair(
+        'Op1puid', UnaryImm, size=5, ins=(), outs=GPR)
+
+# XX /n id with a 32-bit immediate sign-extended to 64 bits.
+RexOp1uid = EncRecipe(
+        'RexOp1uid', UnaryImm, size=7, ins=(), outs=GPR,
+        instp=IsSignedInt(UnaryImm.imm, 32))
+
+# XX+rd iq materializing a 64-bit immediate.
+RexOp1puiq = EncRecipe('RexOp1puiq', UnaryImm, size=10, ins=(), outs=GPR)
+
+# Integer comparison: XX /r `cmp` followed by `setcc` and `movzx` to produce a
+# 0 or 1 result in a register.
+#
+# Without a REX prefix, `setcc` can only address the low byte of the ABCD
+# registers. The `Rex` recipes use a REX prefix on all three instructions.
+Op1icscc = EncRecipe(
+        'Op1icscc', IntCompare, size=8, ins=(GPR8, GPR8), outs=ABCD)
+RexOp1icscc = EncRecipe(
+        'RexOp1icscc', IntCompare, size=11, ins=(GPR, GPR), outs=GPR)
+
+# Integer comparison with an 8-bit immediate: XX /n ib, setcc, movzx.
+Op1icsccib = EncRecipe(
+        'Op1icsccib', IntCompareImm, size=9, ins=GPR8, outs=ABCD,
+        instp=IsSignedInt(IntCompareImm.imm, 8))
+RexOp1icsccib = EncRecipe(
+        'RexOp1icsccib', IntCompareImm, size=12, ins=GPR, outs=GPR,
+        instp=IsSignedInt(IntCompareImm.imm, 8))
+
+# Integer comparison with a 32-bit immediate: XX /n id, setcc, movzx.
+Op1icsccid = EncRecipe(
+        'Op1icsccid', IntCompareImm, size=12, ins=GPR8, outs=ABCD,
+        instp=IsSignedInt(IntCompareImm.imm, 32))
+RexOp1icsccid = EncRecipe(
+        'RexOp1icsccid', IntCompareImm, size=15, ins=GPR, outs=GPR,
+        instp=IsSignedInt(IntCompareImm.imm, 32))
+
+# Conditional move: `test c, c` followed by a 0F XX /r `cmovnz` of the second
+# operand into the result, which is tied to the third operand. The `Rex`
+# recipe uses a REX prefix on both instructions.
+Op2tcmov = EncRecipe(
+        'Op2tcmov', Ternary, size=5, ins=(GPR8, GPR8, GPR8), outs=2)
+RexOp2tcmov = EncRecipe(
+        'RexOp2tcmov', Ternary, size=7, ins=(GPR, GPR, GPR), outs=2)
+
+# Unconditional jump with a 32-bit displacement relative to the end of the
+# instruction.
+Op1jmpd = EncRecipe(
+        'Op1jmpd', Jump, size=5, ins=(), outs=(), branch_range=(5, 32))
+
+# `test r, r` followed by a `jz` or `jnz` with a 32-bit displacement.
+Op1tjccd = EncRecipe(
+        'Op1tjccd', Branch, size=8, ins=GPR8, outs=(),
+        branch_range=(8, 32))
+RexOp1tjccd = EncRecipe(
+        'RexOp1tjccd', Branch, size=9, ins=GPR, outs=(),
+        branch_range=(9, 32))
+
+# `cmp r, r` followed by a `jcc` with a 32-bit displacement.
+Op1icjccd = EncRecipe(
+        'Op1icjccd', BranchIcmp, size=8, ins=(GPR8, GPR8), outs=(),
+        branch_range=(8, 32))
+RexOp1icjccd = EncRecipe(
+        'RexOp1icjccd', BranchIcmp, size=9, ins=(GPR, GPR), outs=(),
+        branch_range=(9, 32))
+
+# Short unconditional jump with an 8-bit displacement.
+Op1jmpb = EncRecipe(
+        'Op1jmpb', Jump, size=2, ins=(), outs=(), branch_range=(2, 8))
+
+# `test r, r` followed by a `jz` or `jnz` with an 8-bit displacement.
+Op1tjccb = EncRecipe(
+        'Op1tjccb', Branch, size=4, ins=GPR8, outs=(),
+        branch_range=(4, 8))
+RexOp1tjccb = EncRecipe(
+        'RexOp1tjccb', Branch, size=5, ins=GPR, outs=(),
+        branch_range=(5, 8))
+
+# `cmp r, r` followed by a `jcc` with an 8-bit displacement.
+Op1icjccb = EncRecipe(
+        'Op1icjccb', BranchIcmp, size=4, ins=(GPR8, GPR8), outs=(),
+        branch_range=(4, 8))
+RexOp1icjccb = EncRecipe(
+        'RexOp1icjccb', BranchIcmp, size=5, ins=(GPR, GPR), outs=(),
+        branch_range=(5, 8))
+
+# `lea disp32(%rip), r` computing the address of a jump table.
+RexOp1jt_base = EncRecipe(
+        'RexOp1jt_base', BranchTableBase, size=7, ins=(), outs=GPR)
+
+# XX /r loading a jump table entry with a SIB byte addressing `(base, x, 4)`
+# and a zero 8-bit displacement. The first operand is the index.
+RexOp1jt_entry = EncRecipe(
+        'RexOp1jt_entry', BranchTableEntry, size=5, ins=(GPR, GPR), outs=GPR,
+        instp=IsEqual(BranchTableEntry.imm, 4))
+
+# XX /n indirect jump to the address in r/m. It can reach any destination in
+# the function.
+RexOp1jt_br = EncRecipe(
+        'RexOp1jt_br', BranchTable, size=3, ins=GPR, outs=(),
+        branch_range=(3, 32))
+
+# Direct call with a 32-bit PC-relative displacement.
+Op1call_id = EncRecipe('Op1call_id', Call, size=5, ins=(), outs=())
+
+# Direct call through the PLT for position-independent code.
+Op1call_plt_id = EncRecipe('Op1call_plt_id', Call, size=5, ins=(), outs=())
+
+# XX+rd id materializing an absolute 32-bit function address.
+Op1fnaddr4 = EncRecipe(
+        'Op1fnaddr4', FuncAddr, size=5, ins=(), outs=norex(GPR))
+
+# XX+rd iq materializing an absolute 64-bit function address.
+RexOp1fnaddr8 = EncRecipe(
+        'RexOp1fnaddr8', FuncAddr, size=10, ins=(), outs=GPR)
+
+# XX /r loading a function address from the GOT with a RIP-relative address.
+RexOp1gotpcrel = EncRecipe(
+        'RexOp1gotpcrel', FuncAddr, size=7, ins=(), outs=GPR)
+
+# XX /r computing the address of a colocated function with a %rip-relative
+# `lea`.
+RexOp1pcrel_fnaddr = EncRecipe(
+        'RexOp1pcrel_fnaddr', FuncAddr, size=7, ins=(), outs=GPR)
+
+# Thread pointer load: `mov %gs:0, r` with an absolute disp32 address.
+Op1tp_gs = EncRecipe('Op1tp_gs', Nullary, size=7, ins=(), outs=norex(GPR))
+
+# Thread pointer load: `mov %fs:0, r` with a SIB byte selecting an absolute
+# disp32 address.
+RexOp1tp_fs = EncRecipe('RexOp1tp_fs', Nullary, size=9, ins=(), outs=GPR)
+
+# XX /r loading the initial-exec offset of a thread-local variable from the
+# GOT with an absolute disp32 address.
+Op1tlsie = EncRecipe(
+        'Op1tlsie', UnaryGlobalVar, size=6, ins=(), outs=norex(GPR))
+
+# XX /r loading the initial-exec offset of a thread-local variable from the
+# GOT with a RIP-relative address.
+RexOp1gottpoff = EncRecipe(
+        'RexOp1gottpoff', UnaryGlobalVar, size=7, ins=(), outs=GPR)
+
+# General-dynamic TLS sequence: `lea x@tlsgd(%rip), %rdi` and a padded call to
+# `__tls_get_addr@plt` followed by a `mov %rax, r`. The call clobbers the
+# caller-saved registers.
+RexOp1tlsgd = EncRecipe(
+        'RexOp1tlsgd', UnaryGlobalVar, size=19, ins=(), outs=GPR,
+        clobbers=(
+            GPR.rax, GPR.rcx, GPR.rdx, GPR.rsi, GPR.rdi,
+            GPR.r8, GPR.r9, GPR.r10, GPR.r11) +
+        tuple(getattr(FPR, 'xmm{}'.format(i)) for i in range(16)))
+
+# Return instruction. The return values are not encoded.
+Op1ret = EncRecipe('Op1ret', MultiAry, size=1, ins=(), outs=())
+
+# Spill a register to a stack slot: XX /r with an SP-relative address.
+Op1spill, RexOp1spill = rex_pair(
+        'Op1spill', Unary, size=7, ins=GPR, outs=Stack(GPR))
+
+# Fill a register from a stack slot: XX /r with an SP-relative address.
+Op1fill, RexOp1fill = rex_pair(
+        'Op1fill', Unary, size=7, ins=Stack(GPR), outs=GPR)
+
+# Stack pointer adjustment: XX /n ib or XX /n id with %rsp in r/m.
+Op1adjustsp_ib, RexOp1adjustsp_ib = rex_pair(
+        'Op1adjustsp_ib', UnaryImm, size=3, ins=(), outs=(),
+        instp=IsSignedInt(UnaryImm.imm, 8))
+Op1adjustsp_id, RexOp1adjustsp_id = rex_pair(
+        'Op1adjustsp_id', UnaryImm, size=6, ins=(), outs=(),
+        instp=IsSignedInt(UnaryImm.imm, 32))
+
+# Stack limit check: `cmp %rsp, x; jae 1f; ud2; 1:`.
+Op1stkchk, RexOp1stkchk = rex_pair(
+        'Op1stkchk', Unary, size=6, ins=GPR, outs=())
+
+# Stack probe: `test %esp, (%rsp)`.
+Op1stkprobe = EncRecipe('Op1stkprobe', Nullary, size=3, ins=(), outs=())
+
+# Loads and stores with a 32-bit displacement from a base register. The
+# address is always encoded with a SIB byte, so any base register works.
+Op1ld, RexOp1ld = rex_pair('Op1ld', Load, size=7, ins=GPR, outs=GPR)
+Op2ld, RexOp2ld = rex_pair('Op2ld', Load, size=8, ins=GPR, outs=GPR)
+Op1st, RexOp1st = rex_pair('Op1st', Store, size=7, ins=(GPR, GPR), outs=())
+Mp1st, RexMp1st = rex_pair('Mp1st', Store, size=8, ins=(GPR, GPR), outs=())
+
+# Atomic loads and stores are plain `mov` instructions addressing `(p)` with a
+# SIB byte and a zero 32-bit displacement. All loads have acquire semantics and
+# all stores have release semantics in the Intel memory model.
+Op1ald, RexOp1ald = rex_pair(
+        'Op1ald', AtomicLoad, size=7, ins=GPR, outs=GPR)
+Op1ast, RexOp1ast = rex_pair(
+        'Op1ast', AtomicRmw, size=7, ins=(GPR, GPR), outs=())
+
+# Sequentially consistent atomic store: `mov` followed by `mfence`.
+Op1ast_fence, RexOp1ast_fence = rex_pair(
+        'Op1ast_fence', AtomicRmw, size=10, ins=(GPR, GPR), outs=())
+
+# `xchg r, (p)` which is implicitly locked. The result is tied to `x`.
+Op1axchg, RexOp1axchg = rex_pair(
+        'Op1axchg', AtomicRmw, size=7, ins=(GPR, GPR), outs=0)
+
+# `lock xadd r, (p)` with the result tied to `x`.
+Op2axadd, RexOp2axadd = rex_pair(
+        'Op2axadd', AtomicRmw, size=9, ins=(GPR, GPR), outs=0)
+
+# `neg r` followed by `lock xadd r, (p)` for atomic subtraction. The `Rex`
+# recipe emits a REX prefix on both instructions.
+Op2axsub = EncRecipe(
+        'Op2axsub', AtomicRmw, size=11, ins=(GPR8, GPR8), outs=0)
+RexOp2axsub = EncRecipe(
+        'RexOp2axsub', AtomicRmw, size=13, ins=(GPR, GPR), outs=0)
+
+# Compare-and-swap with `lock cmpxchg x, (p)` which needs the expected value
+# in `%rax`. The result is tied to `e`, and `e` is swapped with `%rax` before
+# and after the `cmpxchg`. The swaps always use the full 64-bit registers, so
+# the `Rex` recipe emits a REX prefix on all three instructions.
+Op2acas = EncRecipe(
+        'Op2acas', AtomicCas, size=13, ins=(GPR8, GPR8, GPR8), outs=0)
+RexOp2acas = EncRecipe(
+        'RexOp2acas', AtomicCas, size=16, ins=(GPR, GPR, GPR), outs=0)
+
+# Atomic bitwise operations don't have a fetching instruction, so they are
+# implemented with a `lock cmpxchg` loop. The operands are pushed on the stack
+# to free up `%rax`, `%rcx`, and `%rdx` for the loop, and the result is popped
+# into `x` at the end. The `Rex` recipe always emits REX prefixes so the size
+# doesn't depend on the registers.
+Op1armw = EncRecipe(
+        'Op1armw', AtomicRmw, size=34, ins=(GPR8, GPR8), outs=0)
+RexOp1armw = EncRecipe(
+        'RexOp1armw', AtomicRmw, size=44, ins=(GPR, GPR), outs=0)
+
+# Sequentially consistent fence: `mfence`.
+Op2mfence = EncRecipe('Op2mfence', Fence, size=3, ins=(), outs=())
+
+# Loads and stores are already ordered by the Intel memory model, so weaker
+# fences don't need an instruction. They still constrain code motion.
+Op0fence = EncRecipe('Op0fence', Fence, size=0, ins=(), outs=())
+
+# Byte stores without a REX prefix can only access the low byte of the ABCD
+# registers.
+Op1st_abcd = EncRecipe(
+        'Op1st_abcd', Store, size=7, ins=(ABCD, GPR8), outs=())
+
+# <pp> 0F XX /r with the result in reg and the argument in r/m.
+# This is used for bit counting instructions.
+Mp2urm, RexMp2urm = rex_pair('Mp2urm', Unary, size=4, ins=GPR, outs=GPR)
+
+# SSE floating point arithmetic: <pp> 0F XX /r with the first operand in reg
+# and the second in r/m. The result is tied to the first operand.
+Mp2fa, RexMp2fa = rex_pair(
+        'Mp2fa', Binary, size=4, ins=(FPR, FPR), outs=0)
+
+# <pp> 0F XX /r floating point unary operations with the result in reg.
+Mp2furm, RexMp2furm = rex_pair('Mp2furm', Unary, size=4, ins=FPR, outs=FPR)
+
+# <pp> 0F XX /r converting an integer register to a float register.
+Mp2frurm, RexMp2frurm = rex_pair(
+        'Mp2frurm', Unary, size=4, ins=GPR, outs=FPR)
+
+# <pp> 0F XX /r moving a float register in reg to an integer register in r/m.
+Mp2rfumr, RexMp2rfumr = rex_pair(
+        'Mp2rfumr', Unary, size=4, ins=FPR, outs=GPR)
+
+# 0F XX /r floating point register copy with the result in reg.
+Op2furm, RexOp2furm = rex_pair('Op2furm', Unary, size=3, ins=FPR, outs=FPR)
+
+# 0F XX /r floating point register move with the destination in reg.
+Op2frmov, RexOp2frmov = rex_pair(
+        'Op2frmov', RegMove, size=3, ins=FPR, outs=())
+
+# <pp> 0F 3A XX /r ib rounding with the rounding mode in the immediate.
+Mp3furmi_rnd, RexMp3furmi_rnd = rex_pair(
+        'Mp3furmi_rnd', Unary, size=6, ins=FPR, outs=FPR)
+
+# Spill and fill floating point registers with SP-relative addresses.
+Mp2fspill, RexMp2fspill = rex_pair(
+        'Mp2fspill', Unary, size=9, ins=FPR, outs=Stack(FPR))
+Mp2ffill, RexMp2ffill = rex_pair(
+        'Mp2ffill', Unary, size=9, ins=Stack(FPR), outs=FPR)
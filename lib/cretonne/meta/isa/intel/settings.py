@@ -2,10 +2,46 @@
 Intel settings.
 """
 from __future__ import absolute_import
-from cdsl.settings import SettingGroup
+from cdsl.settings import SettingGroup, BoolSetting
+from cdsl.predicates import And, Not
 import base.settings as shared
 from .defs import ISA
 
 ISA.settings = SettingGroup('intel', parent=shared.group)
 
+# The has_* settings here correspond to CPUID bits.
+
+# CPUID.01H:EDX
+has_sse2 = BoolSetting("SSE2: CPUID.01H:EDX.SSE2[bit 26]", default=True)
+
+# CPUID.01H:ECX
+has_sse3 = BoolSetting("SSE3: CPUID.01H:ECX.SSE3[bit 0]")
+has_ssse3 = BoolSetting("SSSE3: CPUID.01H:ECX.SSSE3[bit 9]")
+has_sse41 = BoolSetting("SSE4.1: CPUID.01H:ECX.SSE4_1[bit 19]")
+has_sse42 = BoolSetting("SSE4.2: CPUID.01H:ECX.SSE4_2[bit 20]")
+has_popcnt = BoolSetting("POPCNT: CPUID.01H:ECX.POPCNT[bit 23]")
+
+# CPUID.(EAX=07H, ECX=0H):EBX
+has_bmi1 = BoolSetting("BMI1: CPUID.(EAX=07H, ECX=0H):EBX.BMI1[bit 3]")
+
+# CPUID.EAX=80000001H:ECX
+has_lzcnt = BoolSetting("LZCNT: CPUID.EAX=80000001H:ECX.LZCNT[bit 5]")
+
+# The shared floating point setting must be enabled to use SSE instructions
+# for floating point arithmetic.
+use_sse2 = And(has_sse2, shared.enable_float)
+use_sse41 = And(has_sse41, use_sse2)
+use_popcnt = And(has_popcnt, has_sse42)
+use_bmi1 = And(has_bmi1)
+use_lzcnt = And(has_lzcnt)
+
+# Atomic instructions are always available, but they can be disabled by the
+# shared setting.
+use_atomics = And(shared.enable_atomics)
+
+# Position-independent code uses different relocations for calls and function
+# addresses.
+use_pic = And(shared.is_pic)
+not_pic = Not(shared.is_pic)
+
 ISA.settings.close(globals())
@@ -3,13 +3,21 @@ RISC-V Encodings.
 """
 from __future__ import absolute_import
 from base import instructions as base
+from base.formats import Call
 from base.immediates import intcc
 from .defs import RV32, RV64
+from .recipes import LOAD, STORE, AMO, MISCMEM
 from .recipes import OPIMM, OPIMM32, OP, OP32, LUI, BRANCH, JALR, JAL
-from .recipes import R, Rshamt, Ricmp, I, Iicmp, Iret
+from .recipes import R, Rshamt, Ricmp, I, Iicmp, Icopy, Irmov, Iadjsp, Ipinned
+from .recipes import Iret
+from .recipes import GPsp, GPfi
 from .recipes import U, UJ, UJcall, SB, SBzero
-from .settings import use_m
+from .recipes import Icallplt, Ifnaddr, Igotpcrel, Itp, Itlsie, Itlsgd
+from .recipes import Rlr, Ramost, Ramo, Ramosub, Rcas, Ifence
+from .settings import use_m, use_a, use_pinned_reg, use_pic, not_pic
+from .settings import use_soft_float
 from cdsl.ast import Var
+from cdsl.predicates import IsColocatedFunc
 
 # Dummies for instruction predicates.
 x = Var('x')
@@ -69,6 +77,55 @@ RV64.enc(base.icmp_imm.i64(intcc.slt, x, y), Iicmp, OPIMM(0b010))
 RV32.enc(base.icmp_imm.i32(intcc.ult, x, y), Iicmp, OPIMM(0b011))
 RV64.enc(base.icmp_imm.i64(intcc.ult, x, y), Iicmp, OPIMM(0b011))
 
+# Register copies are encoded as `addi rd, rs, 0`.
+RV32.enc(base.copy.i32, Icopy, OPIMM(0b000))
+RV64.enc(base.copy.i64, Icopy, OPIMM(0b000))
+RV64.enc(base.copy.i32, Icopy, OPIMM(0b000))
+
+RV32.enc(base.regmove.i32, Irmov, OPIMM(0b000))
+RV64.enc(base.regmove.i64, Irmov, OPIMM(0b000))
+RV64.enc(base.regmove.i32, Irmov, OPIMM(0b000))
+
+# Spill and fill use SP-relative stores and loads.
+RV32.enc(base.spill.i32, GPsp, STORE(0b010))
+RV64.enc(base.spill.i32, GPsp, STORE(0b010))
+RV64.enc(base.spill.i64, GPsp, STORE(0b011))
+RV32.enc(base.fill.i32, GPfi, LOAD(0b010))
+RV64.enc(base.fill.i32, GPfi, LOAD(0b010))
+RV64.enc(base.fill.i64, GPfi, LOAD(0b011))
+
+# With soft float, floating point values are moved around in integer
+# registers just like integers of the same size. Only `f32` fits in a register
+# on RV32.
+RV32.enc(base.copy.f32, Icopy, OPIMM(0b000), isap=use_soft_float)
+RV32.enc(base.regmove.f32, Irmov, OPIMM(0b000), isap=use_soft_float)
+RV32.enc(base.spill.f32, GPsp, STORE(0b010), isap=use_soft_float)
+RV32.enc(base.fill.f32, GPfi, LOAD(0b010), isap=use_soft_float)
+RV64.enc(base.copy.f32, Icopy, OPIMM(0b000), isap=use_soft_float)
+RV64.enc(base.copy.f64, Icopy, OPIMM(0b000), isap=use_soft_float)
+RV64.enc(base.regmove.f32, Irmov, OPIMM(0b000), isap=use_soft_float)
+RV64.enc(base.regmove.f64, Irmov, OPIMM(0b000), isap=use_soft_float)
+RV64.enc(base.spill.f32, GPsp, STORE(0b010), isap=use_soft_float)
+RV64.enc(base.spill.f64, GPsp, STORE(0b011), isap=use_soft_float)
+RV64.enc(base.fill.f32, GPfi, LOAD(0b010), isap=use_soft_float)
+RV64.enc(base.fill.f64, GPfi, LOAD(0b011), isap=use_soft_float)
+
+# A bit cast between a soft float and an integer of the same size is a copy.
+RV32.enc(base.bitcast.f32.i32, Icopy, OPIMM(0b000), isap=use_soft_float)
+RV32.enc(base.bitcast.i32.f32, Icopy, OPIMM(0b000), isap=use_soft_float)
+RV64.enc(base.bitcast.f32.i32, Icopy, OPIMM(0b000), isap=use_soft_float)
+RV64.enc(base.bitcast.i32.f32, Icopy, OPIMM(0b000), isap=use_soft_float)
+RV64.enc(base.bitcast.f64.i64, Icopy, OPIMM(0b000), isap=use_soft_float)
+RV64.enc(base.bitcast.i64.f64, Icopy, OPIMM(0b000), isap=use_soft_float)
+
+# Stack pointer adjustments in the prologue and epilogue.
+RV32.enc(base.adjust_sp_imm, Iadjsp, OPIMM(0b000))
+RV64.enc(base.adjust_sp_imm, Iadjsp, OPIMM(0b000))
+
+# The pinned register is only reserved when the setting is enabled.
+RV32.enc(base.get_pinned_reg.i32, Ipinned, OPIMM(0b000), isap=use_pinned_reg)
+RV64.enc(base.get_pinned_reg.i64, Ipinned, OPIMM(0b000), isap=use_pinned_reg)
+
 # Integer constants with the low 12 bits clear are materialized by lui.
 RV32.enc(base.iconst.i32, U, LUI())
 RV64.enc(base.iconst.i32, U, LUI())
@@ -80,13 +137,60 @@ RV32.enc(base.imul.i32, R, OP(0b000, 0b0000001), isap=use_m)
 RV64.enc(base.imul.i64, R, OP(0b000, 0b0000001), isap=use_m)
 RV64.enc(base.imul.i32, R, OP32(0b000, 0b0000001), isap=use_m)
 
+# "A" Standard Extension for Atomic Instructions.
+# Gated by the `use_a` flag.
+for inst,               recipe,  f5 in [
+        (base.atomic_load,  Rlr,     0b00010),
+        (base.atomic_store, Ramost,  0b00001),
+        (base.atomic_xchg,  Ramo,    0b00001),
+        (base.atomic_add,   Ramo,    0b00000),
+        (base.atomic_sub,   Ramosub, 0b00000),
+        (base.atomic_xor,   Ramo,    0b00100),
+        (base.atomic_or,    Ramo,    0b01000),
+        (base.atomic_and,   Ramo,    0b01100)
+        ]:
+    RV32.enc(inst.i32.i32, recipe, AMO(0b010, f5), isap=use_a)
+    RV64.enc(inst.i32.i64, recipe, AMO(0b010, f5), isap=use_a)
+    RV64.enc(inst.i64.i64, recipe, AMO(0b011, f5), isap=use_a)
+
+# The `lr`/`sc` loop compares full registers, so it only works for XLEN
+# values.
+RV32.enc(base.atomic_cas.i32.i32, Rcas, AMO(0b010, 0b00010), isap=use_a)
+RV64.enc(base.atomic_cas.i64.i64, Rcas, AMO(0b011, 0b00010), isap=use_a)
+
+# Memory fences are part of the base ISA.
+RV32.enc(base.fence, Ifence, MISCMEM(0b000))
+RV64.enc(base.fence, Ifence, MISCMEM(0b000))
+
 # Control flow.
 
 # Unconditional branches.
 RV32.enc(base.jump, UJ, JAL())
 RV64.enc(base.jump, UJ, JAL())
-RV32.enc(base.call, UJcall, JAL())
-RV64.enc(base.call, UJcall, JAL())
+RV32.enc(base.call, UJcall, JAL(), isap=not_pic)
+RV64.enc(base.call, UJcall, JAL(), isap=not_pic)
+RV32.enc(base.call, Icallplt, JALR(), isap=use_pic)
+RV64.enc(base.call, Icallplt, JALR(), isap=use_pic)
+# Colocated functions are in range of a `jal` even in PIC mode.
+RV32.enc(base.call, UJcall, JAL(),
+         isap=use_pic, instp=IsColocatedFunc(Call.func_ref))
+RV64.enc(base.call, UJcall, JAL(),
+         isap=use_pic, instp=IsColocatedFunc(Call.func_ref))
+
+# Function addresses are materialized with `lui`+`addi`, or loaded from the GOT
+# in PIC mode.
+RV32.enc(base.func_addr.i32, Ifnaddr, OPIMM(0b000), isap=not_pic)
+RV64.enc(base.func_addr.i64, Ifnaddr, OPIMM(0b000), isap=not_pic)
+RV32.enc(base.func_addr.i32, Igotpcrel, LOAD(0b010), isap=use_pic)
+RV64.enc(base.func_addr.i64, Igotpcrel, LOAD(0b011), isap=use_pic)
+
+# Thread-local storage. The thread pointer is kept in the `tp` register.
+RV32.enc(base.thread_pointer.i32, Itp, OPIMM(0b000))
+RV64.enc(base.thread_pointer.i64, Itp, OPIMM(0b000))
+RV32.enc(base.tls_ie_offset.i32, Itlsie, LOAD(0b010))
+RV64.enc(base.tls_ie_offset.i64, Itlsie, LOAD(0b011))
+RV32.enc(base.tls_gd_addr.i32, Itlsgd, OPIMM(0b000))
+RV64.enc(base.tls_gd_addr.i64, Itlsgd, OPIMM(0b000))
 
 # Conditional branches.
 for cond,           f3 in [
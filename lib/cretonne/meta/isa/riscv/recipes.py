@@ -12,8 +12,12 @@ from __future__ import absolute_import
 from cdsl.isa import EncRecipe
 from cdsl.predicates import IsSignedInt
 from base.formats import Binary, BinaryImm, MultiAry, IntCompare, IntCompareImm
-from base.formats import UnaryImm, BranchIcmp, Branch, Jump, Call
-from .registers import GPR
+from base.formats import Unary, UnaryImm, BranchIcmp, Branch, Jump, Call
+from base.formats import FuncAddr, UnaryGlobalVar
+from base.formats import AtomicLoad, AtomicRmw, AtomicCas, Fence
+from base.formats import Nullary, RegMove
+from cdsl.registers import Stack
+from .registers import GPR, FPR
 
 # The low 7 bits of a RISC-V instruction is the base opcode. All 32-bit
 # instructions have 11 as the two low bits, with bits 6:2 determining the base
@@ -78,6 +82,19 @@ def OP32(funct3, funct7):
     return 0b01110 | (funct3 << 5) | (funct7 << 8)
 
 
+def AMO(funct3, funct5):
+    # type: (int, int) -> int
+    assert funct3 <= 0b111
+    assert funct5 <= 0b11111
+    return 0b01011 | (funct3 << 5) | (funct5 << 10)
+
+
+def MISCMEM(funct3):
+    # type: (int) -> int
+    assert funct3 <= 0b111
+    return 0b00011 | (funct3 << 5)
+
+
 def AIUPC():
     # type: () -> int
     return 0b00101
@@ -107,11 +124,31 @@ Iicmp = EncRecipe(
         'Iicmp', IntCompareImm, size=4, ins=GPR, outs=GPR,
         instp=IsSignedInt(IntCompareImm.imm, 12))
 
+# I-type encoding of a register copy as `addi rd, rs, 0`.
+Icopy = EncRecipe('Icopy', Unary, size=4, ins=GPR, outs=GPR)
+
+# I-type encoding of a register move as `addi dst, src, 0`.
+Irmov = EncRecipe('Irmov', RegMove, size=4, ins=GPR, outs=())
+
+# I-type encoding of a stack pointer adjustment as `addi sp, sp, imm`.
+Iadjsp = EncRecipe(
+        'Iadjsp', UnaryImm, size=4, ins=(), outs=(),
+        instp=IsSignedInt(UnaryImm.imm, 12))
+
+# I-type encoding of a pinned register read as `addi rd, s11, 0`.
+Ipinned = EncRecipe('Ipinned', Nullary, size=4, ins=(), outs=GPR)
+
 # I-type encoding for `jalr` as a return instruction. We won't use the
 # immediate offset.
 # The variable return values are not encoded.
 Iret = EncRecipe('Iret', MultiAry, size=4, ins=(), outs=())
 
+# Spill a register to a stack slot with an SP-relative S-type store.
+GPsp = EncRecipe('GPsp', Unary, size=4, ins=GPR, outs=Stack(GPR))
+
+# Fill a register from a stack slot with an SP-relative I-type load.
+GPfi = EncRecipe('GPfi', Unary, size=4, ins=Stack(GPR), outs=GPR)
+
 # U-type instructions have a 20-bit immediate that targets bits 12-31.
 U = EncRecipe(
         'U', UnaryImm, size=4, ins=(), outs=GPR,
@@ -121,6 +158,63 @@ U = EncRecipe(
 UJ = EncRecipe('UJ', Jump, size=4, ins=(), outs=(), branch_range=(0, 21))
 UJcall = EncRecipe('UJcall', Call, size=4, ins=(), outs=())
 
+# Call through the PLT for position-independent code: `auipc ra, 0` followed by
+# the I-type `jalr ra, ra, 0`, with a single relocation covering both.
+Icallplt = EncRecipe('Icallplt', Call, size=8, ins=(), outs=())
+
+# Absolute function address: `lui rd, %hi(fn)` followed by the I-type
+# `addi rd, rd, %lo(fn)`.
+Ifnaddr = EncRecipe('Ifnaddr', FuncAddr, size=8, ins=(), outs=GPR)
+
+# Function address loaded from the GOT: `auipc rd, %got_pcrel_hi(fn)` followed
+# by an I-type load from `%pcrel_lo(rd)`.
+Igotpcrel = EncRecipe('Igotpcrel', FuncAddr, size=8, ins=(), outs=GPR)
+
+# I-type encoding of a thread pointer read as `addi rd, tp, 0`.
+Itp = EncRecipe('Itp', Nullary, size=4, ins=(), outs=GPR)
+
+# Initial-exec TLS offset loaded from the GOT: `auipc rd, %tls_ie_pcrel_hi(x)`
+# followed by an I-type load from `%pcrel_lo(rd)`.
+Itlsie = EncRecipe('Itlsie', UnaryGlobalVar, size=8, ins=(), outs=GPR)
+
+# General-dynamic TLS sequence: `auipc a0, %tls_gd_pcrel_hi(x)`,
+# `addi a0, a0, %pcrel_lo`, a PLT call to `__tls_get_addr`, and an I-type
+# `addi rd, a0, 0` copying the result. The call clobbers the link register and
+# the other caller-saved registers.
+Itlsgd = EncRecipe(
+        'Itlsgd', UnaryGlobalVar, size=20, ins=(), outs=GPR,
+        clobbers=tuple(
+            getattr(GPR, 'x{}'.format(i))
+            for i in [1, 5, 6, 7] + list(range(10, 18)) + list(range(28, 32))) +
+        tuple(
+            getattr(FPR, 'f{}'.format(i))
+            for i in list(range(0, 8)) + list(range(10, 18)) +
+            list(range(28, 32))))
+
+# R-type atomic memory operations from the 'A' extension. The `aq` and `rl`
+# bits in funct7 are derived from the memory ordering immediate, so the
+# encbits only hold funct5.
+#
+# Atomic loads are `lr` instructions without a following `sc`.
+Rlr = EncRecipe('Rlr', AtomicLoad, size=4, ins=GPR, outs=GPR)
+
+# Atomic stores are `amoswap` instructions discarding the result in `x0`.
+Ramost = EncRecipe('Ramost', AtomicRmw, size=4, ins=(GPR, GPR), outs=())
+
+# AMO read-modify-write instructions.
+Ramo = EncRecipe('Ramo', AtomicRmw, size=4, ins=(GPR, GPR), outs=GPR)
+
+# Atomic subtraction negating `x` with `sub x, zero, x` before an `amoadd`.
+Ramosub = EncRecipe('Ramosub', AtomicRmw, size=8, ins=(GPR, GPR), outs=0)
+
+# Compare-and-swap as an `lr`/`sc` loop. The loop needs a scratch register
+# which is saved on the stack around it. The result is tied to `e`.
+Rcas = EncRecipe('Rcas', AtomicCas, size=40, ins=(GPR, GPR, GPR), outs=0)
+
+# I-type encoding of a `fence` instruction. The predecessor and successor sets
+# in the immediate are derived from the memory ordering.
+Ifence = EncRecipe('Ifence', Fence, size=4, ins=(), outs=())
+
 # SB-type branch instructions.
 # TODO: These instructions have a +/- 4 KB branch range. How to encode that
 # constraint?
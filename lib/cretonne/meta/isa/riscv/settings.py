@@ -3,7 +3,7 @@ RISC-V settings.
 """
 from __future__ import absolute_import
 from cdsl.settings import SettingGroup, BoolSetting
-from cdsl.predicates import And
+from cdsl.predicates import And, Not
 import base.settings as shared
 from .defs import ISA
 
@@ -18,6 +18,15 @@ enable_m = BoolSetting(
         "Enable the use of 'M' instructions if available",
         default=True)
 
+soft_float = BoolSetting(
+        """
+        Use the soft-float ABI, and call runtime library routines for floating
+        point arithmetic.
+
+        Floating point values are kept in integer registers, so this works on
+        CPUs without the 'F' and 'D' extensions.
+        """)
+
 use_m = And(supports_m, enable_m)
 use_a = And(supports_a, shared.enable_atomics)
 use_f = And(supports_f, shared.enable_float)
@@ -25,4 +34,14 @@ use_d = And(supports_d, shared.enable_float)
 
 full_float = And(shared.enable_simd, supports_f, supports_d)
 
+use_pinned_reg = And(shared.enable_pinned_reg)
+
+# Floating point values in integer registers.
+use_soft_float = And(soft_float)
+
+# Position-independent code uses different relocations for calls and function
+# addresses.
+use_pic = And(shared.is_pic)
+not_pic = Not(shared.is_pic)
+
 ISA.settings.close(globals())
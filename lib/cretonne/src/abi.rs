@@ -1,9 +1,10 @@
 //! Common helper code for ABI lowering.
 //!
 //! This module provides functions and data structures that are useful for implementing the
-//! `TargetIsa::legalize_signature()` method.
+//! `TargetIsa::legalize_signature()` and `TargetIsa::classify_aggregate()` methods.
 
 use ir::{ArgumentLoc, ArgumentType, ArgumentExtension, Type};
+use ir::types;
 use std::cmp::Ordering;
 
 /// Legalization action to perform on a single argument or return value when converting a
@@ -182,12 +183,74 @@ pub fn legalize_abi_value(have: Type, arg: &ArgumentType) -> ValueConversion {
     }
 }
 
+/// How a by-value aggregate is passed to or returned from a function.
+///
+/// Cretonne IL has no aggregate types, so frontends for C-like languages use the classification
+/// from `TargetIsa::classify_aggregate()` to lower aggregates to the values in a signature.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AggregateClass {
+    /// The aggregate is passed as integer values with these types, loaded from consecutive
+    /// offsets in its memory.
+    Scalars(Vec<Type>),
+
+    /// The aggregate is passed in memory.
+    ///
+    /// An argument is passed as a pointer to a copy made by the caller. A return value is written
+    /// to a return area pointed to by an `sret` argument.
+    Memory,
+}
+
+/// Classify an aggregate of `size` bytes following the rules shared by most C ABIs.
+///
+/// Aggregates no larger than two registers of `reg_bytes` bytes are passed as integer values, and
+/// larger aggregates are passed in memory.
+pub fn classify_aggregate(size: u32, reg_bytes: u32) -> AggregateClass {
+    if size <= 2 * reg_bytes {
+        AggregateClass::Scalars(aggregate_scalars(size, reg_bytes))
+    } else {
+        AggregateClass::Memory
+    }
+}
+
+/// Get the integer types covering an aggregate of `size` bytes, none of them wider than
+/// `reg_bytes` bytes.
+pub fn aggregate_scalars(size: u32, reg_bytes: u32) -> Vec<Type> {
+    let mut tys = Vec::new();
+    let mut left = size;
+    while left > 0 {
+        let ty = if left >= 8 && reg_bytes >= 8 {
+            types::I64
+        } else if left >= 4 && reg_bytes >= 4 {
+            types::I32
+        } else if left >= 2 {
+            types::I16
+        } else {
+            types::I8
+        };
+        left -= ty.bytes();
+        tys.push(ty);
+    }
+    tys
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use ir::types;
     use ir::ArgumentType;
 
+    #[test]
+    fn aggregates() {
+        assert_eq!(classify_aggregate(12, 8),
+                   AggregateClass::Scalars(vec![types::I64, types::I32]));
+        assert_eq!(classify_aggregate(7, 4),
+                   AggregateClass::Scalars(vec![types::I32, types::I16, types::I8]));
+        assert_eq!(classify_aggregate(16, 8),
+                   AggregateClass::Scalars(vec![types::I64, types::I64]));
+        assert_eq!(classify_aggregate(17, 8), AggregateClass::Memory);
+        assert_eq!(classify_aggregate(12, 4), AggregateClass::Memory);
+    }
+
     #[test]
     fn legalize() {
         let mut arg = ArgumentType::new(types::I32);
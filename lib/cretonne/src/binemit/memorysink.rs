@@ -0,0 +1,331 @@
+//! Code sink that writes binary machine code into contiguous memory.
+//!
+//! The `CodeSink` trait is the most general way of extracting binary machine code from Cretonne,
+//! and it is implemented by things like the `test binemit` file test driver to generate
+//! hexadecimal machine code. The `CodeSink` has some undesirable performance properties because of
+//! the dual abstraction: `TargetIsa` is a trait object implemented by each supported ISA, so it
+//! can't have any generic functions that could be specialized for each `CodeSink` implementation.
+//! This results in many virtual function callbacks (one per `put*` call) when
+//! `TargetIsa::emit_inst()` is used.
+//!
+//! The `MemoryCodeSink` type fixes the performance problem because it is a type known to
+//! `TargetIsa` so it can specialize its machine code generation for the type. The trade-off is
+//! that a `MemoryCodeSink` will always write binary machine code to a caller-provided buffer, and
+//! relocations are reported through a `RelocSink` trait object.
+//!
+//! A `Vec<RelocRecord>` can be used as a `RelocSink` to collect all the relocations for a function
+//! so they can be applied after the code has been placed in its final location. Similarly, a
+//! `Vec<TrapRecord>` can be used as a `TrapSink` to collect the trap sites of a function.
+//!
+//! The `put2`, `put4`, and `put8` methods write their values in the byte order of the target ISA,
+//! which is little-endian unless the sink was created by `MemoryCodeSink::with_endianness()`.
+
+use std::fmt;
+use super::{CodeSink, CodeOffset, Reloc, Addend};
+use ir::{Ebb, FuncRef, FunctionName, JumpTable, TrapCode};
+use isa::Endianness;
+
+/// A `CodeSink` that writes binary machine code directly into a caller-provided buffer.
+pub struct MemoryCodeSink<'a> {
+    data: &'a mut [u8],
+    offset: usize,
+    endianness: Endianness,
+    relocs: &'a mut RelocSink,
+    traps: &'a mut TrapSink,
+}
+
+impl<'a> MemoryCodeSink<'a> {
+    /// Create a new memory code sink that writes a function to the buffer `data`.
+    ///
+    /// The buffer must be large enough to hold the whole function. Its size can be determined by
+    /// `binemit::relax_branches()`. Writing past the end of the buffer will panic.
+    pub fn new(data: &'a mut [u8],
+               relocs: &'a mut RelocSink,
+               traps: &'a mut TrapSink)
+               -> MemoryCodeSink<'a> {
+        MemoryCodeSink::with_endianness(data, relocs, traps, Endianness::Little)
+    }
+
+    /// Create a new memory code sink that writes multi-byte values in the `endianness` byte order.
+    ///
+    /// Use `TargetIsa::endianness()` to get the byte order of the target.
+    pub fn with_endianness(data: &'a mut [u8],
+                           relocs: &'a mut RelocSink,
+                           traps: &'a mut TrapSink,
+                           endianness: Endianness)
+                           -> MemoryCodeSink<'a> {
+        MemoryCodeSink {
+            data: data,
+            offset: 0,
+            endianness: endianness,
+            relocs: relocs,
+            traps: traps,
+        }
+    }
+
+    /// Write the `n` low bytes of `x` in the byte order of the sink.
+    fn put(&mut self, x: u64, n: usize) {
+        let endianness = self.endianness;
+        let bytes = &mut self.data[self.offset..self.offset + n];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            let shift = match endianness {
+                Endianness::Little => 8 * i,
+                Endianness::Big => 8 * (n - 1 - i),
+            };
+            *byte = (x >> shift) as u8;
+        }
+        self.offset += n;
+    }
+}
+
+/// A trait for receiving relocations for code that is emitted directly into memory.
+pub trait RelocSink {
+    /// Add a relocation referencing an EBB at the current offset.
+    fn reloc_ebb(&mut self, CodeOffset, Reloc, Ebb);
+
+    /// Add a relocation referencing an external function at the current offset.
+    fn reloc_func(&mut self, CodeOffset, Reloc, FuncRef, Addend);
+
+    /// Add a relocation referencing an external symbol by name at the current offset.
+    fn reloc_external(&mut self, CodeOffset, Reloc, &FunctionName, Addend);
+
+    /// Add a relocation referencing a jump table.
+    fn reloc_jt(&mut self, CodeOffset, Reloc, JumpTable);
+}
+
+/// A trait for receiving trap codes and offsets.
+///
+/// If the embedder uses signal handlers to handle traps, this information can be used to map a
+/// faulting program counter back to a `TrapCode`.
+pub trait TrapSink {
+    /// Add trap information for a machine instruction at `offset`.
+    fn trap(&mut self, CodeOffset, TrapCode);
+}
+
+impl<'a> CodeSink for MemoryCodeSink<'a> {
+    fn offset(&self) -> CodeOffset {
+        self.offset as CodeOffset
+    }
+
+    fn put1(&mut self, x: u8) {
+        self.put(x as u64, 1);
+    }
+
+    fn put2(&mut self, x: u16) {
+        self.put(x as u64, 2);
+    }
+
+    fn put4(&mut self, x: u32) {
+        self.put(x as u64, 4);
+    }
+
+    fn put8(&mut self, x: u64) {
+        self.put(x, 8);
+    }
+
+    fn reloc_ebb(&mut self, rel: Reloc, ebb: Ebb) {
+        let ofs = self.offset();
+        self.relocs.reloc_ebb(ofs, rel, ebb);
+    }
+
+    fn reloc_func(&mut self, rel: Reloc, func: FuncRef, addend: Addend) {
+        let ofs = self.offset();
+        self.relocs.reloc_func(ofs, rel, func, addend);
+    }
+
+    fn reloc_external(&mut self, rel: Reloc, name: &FunctionName, addend: Addend) {
+        let ofs = self.offset();
+        self.relocs.reloc_external(ofs, rel, name, addend);
+    }
+
+    fn reloc_jt(&mut self, rel: Reloc, jt: JumpTable) {
+        let ofs = self.offset();
+        self.relocs.reloc_jt(ofs, rel, jt);
+    }
+
+    fn trap(&mut self, code: TrapCode) {
+        let ofs = self.offset();
+        self.traps.trap(ofs, code);
+    }
+
+    fn begin_rodata(&mut self) {}
+}
+
+/// The target of a relocation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RelocTarget {
+    /// The address of an EBB in the same function.
+    Ebb(Ebb),
+    /// The address of an external function declared in the function preamble.
+    Func(FuncRef),
+    /// The address of an external symbol.
+    External(FunctionName),
+    /// The address of a jump table.
+    JumpTable(JumpTable),
+}
+
+impl fmt::Display for RelocTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RelocTarget::Ebb(ebb) => write!(f, "{}", ebb),
+            RelocTarget::Func(fref) => write!(f, "{}", fref),
+            RelocTarget::External(ref name) => write!(f, "{}", name),
+            RelocTarget::JumpTable(jt) => write!(f, "{}", jt),
+        }
+    }
+}
+
+/// A relocation record collected by a `Vec<RelocRecord>` relocation sink.
+///
+/// The relocation applies to the code at `offset` from the start of the function. The ISA-specific
+/// `kind` determines how the address of `target` plus `addend` is encoded at that location.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RelocRecord {
+    /// Offset of the relocated location from the beginning of the function.
+    pub offset: CodeOffset,
+    /// The ISA-specific relocation kind.
+    pub kind: Reloc,
+    /// The relocation target.
+    pub target: RelocTarget,
+    /// Addend to add to the target address.
+    pub addend: Addend,
+}
+
+impl RelocSink for Vec<RelocRecord> {
+    fn reloc_ebb(&mut self, offset: CodeOffset, kind: Reloc, ebb: Ebb) {
+        self.push(RelocRecord {
+                      offset: offset,
+                      kind: kind,
+                      target: RelocTarget::Ebb(ebb),
+                      addend: 0,
+                  });
+    }
+
+    fn reloc_func(&mut self, offset: CodeOffset, kind: Reloc, func: FuncRef, addend: Addend) {
+        self.push(RelocRecord {
+                      offset: offset,
+                      kind: kind,
+                      target: RelocTarget::Func(func),
+                      addend: addend,
+                  });
+    }
+
+    fn reloc_external(&mut self,
+                      offset: CodeOffset,
+                      kind: Reloc,
+                      name: &FunctionName,
+                      addend: Addend) {
+        self.push(RelocRecord {
+                      offset: offset,
+                      kind: kind,
+                      target: RelocTarget::External(name.clone()),
+                      addend: addend,
+                  });
+    }
+
+    fn reloc_jt(&mut self, offset: CodeOffset, kind: Reloc, jt: JumpTable) {
+        self.push(RelocRecord {
+                      offset: offset,
+                      kind: kind,
+                      target: RelocTarget::JumpTable(jt),
+                      addend: 0,
+                  });
+    }
+}
+
+/// A trap site collected by a `Vec<TrapRecord>` trap sink.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TrapRecord {
+    /// Offset of the trapping machine instruction from the beginning of the function.
+    pub offset: CodeOffset,
+    /// The reason for the trap.
+    pub code: TrapCode,
+}
+
+impl TrapSink for Vec<TrapRecord> {
+    fn trap(&mut self, offset: CodeOffset, code: TrapCode) {
+        self.push(TrapRecord {
+                      offset: offset,
+                      code: code,
+                  });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MemoryCodeSink, RelocRecord, RelocTarget, TrapRecord};
+    use binemit::{CodeSink, Reloc};
+    use entity_map::EntityRef;
+    use ir::{Ebb, FuncRef, FunctionName, JumpTable, TrapCode};
+    use isa::Endianness;
+
+    #[test]
+    fn little_endian() {
+        let mut buf = [0u8; 16];
+        let mut relocs: Vec<RelocRecord> = Vec::new();
+        let mut traps: Vec<TrapRecord> = Vec::new();
+        {
+            let mut sink = MemoryCodeSink::new(&mut buf, &mut relocs, &mut traps);
+            sink.put1(0x01);
+            sink.trap(TrapCode::HeapOutOfBounds);
+            sink.put2(0x0302);
+            sink.reloc_func(Reloc(1), FuncRef::new(2), -4);
+            sink.put4(0x07060504);
+            sink.reloc_ebb(Reloc(0), Ebb::new(3));
+            sink.put8(0x0f0e0d0c0b0a0908);
+            sink.reloc_external(Reloc(3), &FunctionName::new("data"), 8);
+            sink.reloc_jt(Reloc(2), JumpTable::new(0));
+            sink.put1(0x10);
+            assert_eq!(sink.offset(), 16);
+        }
+        assert_eq!(buf,
+                   [0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d,
+                    0x0e, 0x0f, 0x10]);
+
+        let relocs: Vec<_> = relocs
+            .iter()
+            .map(|r| (r.offset, r.kind, r.target.to_string(), r.addend))
+            .collect();
+        assert_eq!(relocs,
+                   [(3, Reloc(1), "fn2".to_string(), -4),
+                    (7, Reloc(0), "ebb3".to_string(), 0),
+                    (15, Reloc(3), "data".to_string(), 8),
+                    (15, Reloc(2), "jt0".to_string(), 0)]);
+        assert_eq!(RelocTarget::Func(FuncRef::new(2)).to_string(), "fn2");
+        assert_eq!(traps,
+                   [TrapRecord {
+                        offset: 1,
+                        code: TrapCode::HeapOutOfBounds,
+                    }]);
+    }
+
+    #[test]
+    fn big_endian() {
+        let mut buf = [0u8; 15];
+        let mut relocs: Vec<RelocRecord> = Vec::new();
+        let mut traps: Vec<TrapRecord> = Vec::new();
+        {
+            let mut sink =
+                MemoryCodeSink::with_endianness(&mut buf, &mut relocs, &mut traps, Endianness::Big);
+            sink.put1(0x01);
+            sink.put2(0x0203);
+            sink.put4(0x04050607);
+            sink.put8(0x08090a0b0c0d0e0f);
+            assert_eq!(sink.offset(), 15);
+        }
+        assert_eq!(buf,
+                   [0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d,
+                    0x0e, 0x0f]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn overflow() {
+        let mut buf = [0u8; 3];
+        let mut relocs: Vec<RelocRecord> = Vec::new();
+        let mut traps: Vec<TrapRecord> = Vec::new();
+        let mut sink = MemoryCodeSink::new(&mut buf, &mut relocs, &mut traps);
+        sink.put2(0);
+        sink.put2(0);
+    }
+}
@@ -3,11 +3,20 @@
 //! The `binemit` module contains code for translating Cretonne's intermediate representation into
 //! binary machine code.
 
+mod memorysink;
 mod relaxation;
+mod srclocs;
+mod unwind;
 
-pub use self::relaxation::relax_branches;
+pub use self::memorysink::{MemoryCodeSink, RelocSink, RelocRecord, RelocTarget, TrapSink,
+                           TrapRecord};
+pub use self::relaxation::{relax_branches, code_size_estimate};
+pub use self::srclocs::{SourceLocRecord, source_locations};
+pub use self::unwind::{UnwindInfo, unwind_info};
 
-use ir::{Ebb, FuncRef, JumpTable, Function, Inst};
+use ir::{Ebb, FuncRef, FunctionName, JumpTable, Function, Inst, Opcode, TrapCode};
+use isa::TargetIsa;
+use regalloc::RegDiversions;
 
 /// Offset in bytes from the beginning of the function.
 ///
@@ -15,13 +24,31 @@ use ir::{Ebb, FuncRef, JumpTable, Function, Inst};
 /// depends on the *host* platform, not the *target* platform.
 pub type CodeOffset = u32;
 
+/// Addend to add to the symbol value when resolving a relocation.
+pub type Addend = i64;
+
+/// Size in bytes of a jump table entry.
+///
+/// Jump table entries are signed 32-bit offsets of the destination EBBs relative to the start of
+/// the table.
+pub const JUMP_TABLE_ENTRY_SIZE: CodeOffset = 4;
+
 /// Relocation kinds depend on the current ISA.
+///
+/// The names of the relocation kinds are available from `TargetIsa::reloc_names()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Reloc(pub u16);
 
 /// Abstract interface for adding bytes to the code segment.
 ///
 /// A `CodeSink` will receive all of the machine code for a function. It also accepts relocations
 /// which are locations in the code section that need to be fixed up when linking.
+///
+/// A relocation is always added immediately before the bytes it applies to are emitted, so the
+/// relocated location is the current offset. The addend is the value to add to the address of the
+/// target when resolving the relocation. PC-relative relocations are relative to the relocated
+/// location itself, so the addend accounts for any difference between that and the PC value used
+/// by the instruction.
 pub trait CodeSink {
     /// Get the current position.
     fn offset(&self) -> CodeOffset;
@@ -42,10 +69,28 @@ pub trait CodeSink {
     fn reloc_ebb(&mut self, Reloc, Ebb);
 
     /// Add a relocation referencing an external function at the current offset.
-    fn reloc_func(&mut self, Reloc, FuncRef);
+    fn reloc_func(&mut self, Reloc, FuncRef, Addend);
+
+    /// Add a relocation referencing an external symbol by name at the current offset.
+    ///
+    /// This is used for references to global data and other symbols that are not declared as
+    /// entities in the function.
+    fn reloc_external(&mut self, Reloc, &FunctionName, Addend);
 
     /// Add a relocation referencing a jump table.
     fn reloc_jt(&mut self, Reloc, JumpTable);
+
+    /// Add trap information for the machine instruction at the current offset.
+    ///
+    /// This is called before emitting a machine instruction that can trap.
+    fn trap(&mut self, TrapCode);
+
+    /// Mark the end of the code and the start of the read-only data.
+    ///
+    /// Everything emitted after this call is data like jump tables which is never executed. The
+    /// data is position independent, so it can be moved to a separate read-only section as long
+    /// as the relocations referencing it are resolved.
+    fn begin_rodata(&mut self);
 }
 
 /// Report a bad encoding error.
@@ -55,3 +100,75 @@ pub fn bad_encoding(func: &Function, inst: Inst) -> ! {
            func.encodings[inst],
            func.dfg.display_inst(inst));
 }
+
+/// Get the alignment of function entry points in bytes.
+///
+/// This is controlled by the `function_alignment_log2` setting. The emitted code assumes that it
+/// is placed at an address with this alignment, which is required for the loop header alignment
+/// to be effective.
+pub fn function_alignment(isa: &TargetIsa) -> CodeOffset {
+    1 << isa.flags().function_alignment_log2()
+}
+
+/// Emit a function to `sink`, given an instruction emitter function and a padding emitter function.
+///
+/// This function is called from the `TargetIsa::emit_function()` implementations with the
+/// appropriate instruction emitter. The function must have been prepared by `relax_branches()` so
+/// the EBB header offsets are known. The padding emitter fills the gaps before aligned EBB headers.
+pub fn emit_function<CS, EI, EP>(func: &Function, emit_inst: EI, emit_padding: EP, sink: &mut CS)
+    where CS: CodeSink,
+          EI: Fn(&Function, Inst, &mut RegDiversions, &mut CS),
+          EP: Fn(CodeOffset, &mut CS)
+{
+    let mut divert = RegDiversions::new();
+    for ebb in func.layout.ebbs() {
+        divert.clear();
+        let offset = sink.offset();
+        assert!(func.offsets[ebb] >= offset,
+                "Inconsistent {} header offset",
+                ebb);
+        if func.offsets[ebb] > offset {
+            emit_padding(func.offsets[ebb] - offset, sink);
+        }
+        assert_eq!(func.offsets[ebb],
+                   sink.offset(),
+                   "Inconsistent {} header offset",
+                   ebb);
+        for inst in func.layout.ebb_insts(ebb) {
+            // The `fallthrough` instructions created by `relax_branches()` don't emit any code.
+            if func.dfg[inst].opcode() == Opcode::Fallthrough &&
+               !func.encodings.get(inst).map_or(false, |enc| enc.is_legal()) {
+                continue;
+            }
+            emit_inst(func, inst, &mut divert, sink);
+        }
+    }
+
+    emit_jump_tables(func, sink);
+}
+
+/// Emit the read-only data following the code of `func`, starting with a `begin_rodata()` call.
+///
+/// Each jump table is placed at the offset computed by `relax_branches()`, and its entries are the
+/// 32-bit offsets of the destination EBBs relative to the start of the table, written in the byte
+/// order of `sink`. The legalizer fills in any missing entries, so they are only emitted as 0 for
+/// functions that weren't legalized.
+pub fn emit_jump_tables<CS: CodeSink + ?Sized>(func: &Function, sink: &mut CS) {
+    sink.begin_rodata();
+    for jt in func.jump_tables.keys() {
+        let table = &func.jump_tables[jt];
+        if table.is_empty() {
+            continue;
+        }
+        let base = func.jt_offsets[jt];
+        while sink.offset() < base {
+            sink.put1(0);
+        }
+        for idx in 0..table.len() {
+            let entry = table
+                .get_entry(idx)
+                .map_or(0, |ebb| func.offsets[ebb].wrapping_sub(base));
+            sink.put4(entry);
+        }
+    }
+}
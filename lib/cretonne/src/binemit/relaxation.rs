@@ -12,6 +12,10 @@
 //! range to encode their destination. It is common to have multiple branch encodings in an ISA.
 //! For example, Intel branches can have either an 8-bit or a 32-bit displacement.
 //!
+//! The encoding lists are ordered so that the preferred encoding of a branch is the one with the
+//! shortest range. A branch whose destination is out of range is first changed to another legal
+//! encoding with enough range.
+//!
 //! On RISC architectures, it can happen that conditional branches have a shorter range than
 //! unconditional branches:
 //!
@@ -26,17 +30,45 @@
 //!     jump ebb17
 //! ebb23:
 //! ```
+//!
+//! This splits the EBB containing the branch, so it should only be done when no register
+//! diversions are active at the branch.
+//!
+//! # Loop alignment
+//!
+//! When the `align_loops` setting is on, padding is inserted before the loop headers so they
+//! start at an aligned offset. The padding is accounted for in the EBB header offsets, and the
+//! emitter fills it with no-op instructions. The code before a loop header can only grow during
+//! relaxation, so the padding may shrink, but the loop header offsets never decrease.
+//!
+//! # Jump tables
+//!
+//! The jump tables are placed in a read-only data area after the code, aligned to their 4-byte
+//! entries. Their offsets are recorded in the `func.jt_offsets` table.
+//!
+//! # Code size estimates
+//!
+//! The `code_size_estimate()` function computes an upper bound on the size returned by
+//! `relax_branches()` without changing the function. It assumes that every branch gets its longest
+//! encoding, that conditional branches are inverted when even that can't span the function, and
+//! that loop headers need the most padding.
 
-use binemit::CodeOffset;
+use binemit::{CodeOffset, JUMP_TABLE_ENTRY_SIZE, function_alignment};
+use branches::{invert_branch, jump_to_fallthrough};
 use entity_map::EntityMap;
-use ir::{Function, DataFlowGraph, Cursor, Inst, InstructionData, Opcode};
+use ir::{Function, DataFlowGraph, Cursor, Ebb, Inst, InstructionData, InstBuilder, Opcode, Value,
+         ValueList};
+use ir::types::VOID;
 use isa::{TargetIsa, EncInfo, Encoding};
 use iterators::IteratorExtras;
+use regalloc::RegDiversions;
 
 /// Relax branches and compute the final layout of EBB headers in `func`.
 ///
-/// Fill in the `func.offsets` table so the function is ready for binary emission.
-pub fn relax_branches(func: &mut Function, isa: &TargetIsa) {
+/// Fill in the `func.offsets` and `func.jt_offsets` tables so the function is ready for binary
+/// emission. Return the total size of the function in bytes, including any padding inserted before
+/// loop headers and the jump tables following the code.
+pub fn relax_branches(func: &mut Function, isa: &TargetIsa) -> CodeOffset {
     let encinfo = isa.encoding_info();
 
     // Clear all offsets so we can recognize EBBs that haven't been visited yet.
@@ -46,15 +78,30 @@ pub fn relax_branches(func: &mut Function, isa: &TargetIsa) {
     // Start by inserting fall through instructions.
     fallthroughs(func);
 
+    // The loop headers are identified before relaxation inserts any new EBBs.
+    let loop_align = loop_alignment(isa);
+    let loop_headers = if loop_align > 1 {
+        loop_headers(func)
+    } else {
+        EntityMap::new()
+    };
+
     // The relaxation algorithm iterates to convergence.
+    let mut offset = 0;
+    let mut divert = RegDiversions::new();
     let mut go_again = true;
     while go_again {
         go_again = false;
 
         // Visit all instructions in layout order
-        let mut offset = 0;
+        offset = 0;
         let mut pos = Cursor::new(&mut func.layout);
         while let Some(ebb) = pos.next_ebb() {
+            divert.clear();
+            if loop_headers.get(ebb).cloned().unwrap_or(false) {
+                offset = (offset + loop_align - 1) & !(loop_align - 1);
+            }
+
             // Record the offset for `ebb` and make sure we iterate until offsets are stable.
             if func.offsets[ebb] != offset {
                 assert!(func.offsets[ebb] < offset,
@@ -77,7 +124,10 @@ pub fn relax_branches(func: &mut Function, isa: &TargetIsa) {
                             if dest_offset != 0 || Some(dest) == pos.layout.entry_block() {
                                 offset += relax_branch(&mut func.dfg,
                                                        &mut func.encodings,
+                                                       &mut func.offsets,
+                                                       isa,
                                                        &encinfo,
+                                                       &divert,
                                                        &mut pos,
                                                        offset,
                                                        dest_offset);
@@ -87,38 +137,182 @@ pub fn relax_branches(func: &mut Function, isa: &TargetIsa) {
                     }
                 }
 
+                divert.apply(&func.dfg[inst]);
                 offset += size;
             }
         }
     }
+
+    jump_table_offsets(func, offset)
+}
+
+/// Assign offsets to the jump tables in `func`, starting after `code_size` bytes of code.
+///
+/// Return the total size of the code and the jump tables.
+fn jump_table_offsets(func: &mut Function, code_size: CodeOffset) -> CodeOffset {
+    func.jt_offsets.clear();
+    if func.jump_tables
+           .keys()
+           .all(|jt| func.jump_tables[jt].is_empty()) {
+        return code_size;
+    }
+
+    func.jt_offsets.resize(func.jump_tables.len());
+    let mut offset = (code_size + JUMP_TABLE_ENTRY_SIZE - 1) & !(JUMP_TABLE_ENTRY_SIZE - 1);
+    for jt in func.jump_tables.keys() {
+        func.jt_offsets[jt] = offset;
+        offset += JUMP_TABLE_ENTRY_SIZE * func.jump_tables[jt].len() as CodeOffset;
+    }
+    offset
+}
+
+/// Estimate the size of `func` in bytes as returned by `relax_branches()`.
+///
+/// An instruction that hasn't been assigned an encoding yet is counted at the size of the largest
+/// encoding the ISA has for it, and an instruction that the ISA can't encode without legalization
+/// is counted as 0 bytes. The estimate is only an upper bound for functions that have been
+/// legalized, so run the compiler passes up to register allocation and prologue insertion first.
+pub fn code_size_estimate(func: &Function, isa: &TargetIsa) -> CodeOffset {
+    let encinfo = isa.encoding_info();
+    let loop_align = loop_alignment(isa);
+    let loop_headers = if loop_align > 1 {
+        loop_headers(func)
+    } else {
+        EntityMap::new()
+    };
+
+    // Conditional branches that may have to be inverted around a jump, with the range of their
+    // longest encoding and the size of the jump.
+    let mut invertible = Vec::new();
+    let mut code_size = 0;
+    for ebb in func.layout.ebbs() {
+        if loop_headers.get(ebb).cloned().unwrap_or(false) {
+            code_size += loop_align - 1;
+        }
+        for inst in func.layout.ebb_insts(ebb) {
+            let data = &func.dfg[inst];
+            let ctrl_typevar = func.dfg.ctrl_typevar(inst);
+            let enc = func.inst_encoding(inst);
+            if enc.is_legal() && encinfo.branch_range(enc).is_none() {
+                code_size += encinfo.bytes(enc);
+                continue;
+            }
+
+            // A branch can be relaxed to any of its encodings.
+            let encs: Vec<Encoding> = match isa.legal_encodings(&func.dfg, data, ctrl_typevar) {
+                Ok(encs) => encs.collect(),
+                Err(_) => Vec::new(),
+            };
+            code_size += encs.iter().map(|&e| encinfo.bytes(e)).max().unwrap_or(0);
+
+            let range = encs.iter()
+                .filter_map(|&e| encinfo.branch_range(e))
+                .max_by_key(|range| range.bits);
+            if let (Some(range), Some(dest)) = (range, data.branch_destination()) {
+                match *data {
+                    InstructionData::Branch { .. } |
+                    InstructionData::BranchIcmp { .. } => {
+                        invertible.push((range, jump_size(func, isa, &encinfo, dest)))
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    // Inverting a branch makes the function larger, so iterate until the set of branches that
+    // may be out of range is stable.
+    let mut inverted = vec![false; invertible.len()];
+    let mut go_again = true;
+    while go_again {
+        go_again = false;
+        for (&(range, jump), inverted) in invertible.iter().zip(inverted.iter_mut()) {
+            if !*inverted && !(range.contains(0, code_size) && range.contains(code_size, 0)) {
+                *inverted = true;
+                code_size += jump;
+                go_again = true;
+            }
+        }
+    }
+
+    // Jump tables are aligned to their entries after the code.
+    let mut size = code_size;
+    let mut tables = 0;
+    for jt in func.jump_tables.keys() {
+        tables += JUMP_TABLE_ENTRY_SIZE * func.jump_tables[jt].len() as CodeOffset;
+    }
+    if tables > 0 {
+        size = (size + JUMP_TABLE_ENTRY_SIZE - 1) & !(JUMP_TABLE_ENTRY_SIZE - 1);
+        size += tables;
+    }
+    size
+}
+
+/// Get the size of the largest encoding of a `jump` to `dest`.
+fn jump_size(func: &Function, isa: &TargetIsa, encinfo: &EncInfo, dest: Ebb) -> CodeOffset {
+    let jump = InstructionData::Jump {
+        opcode: Opcode::Jump,
+        destination: dest,
+        args: ValueList::default(),
+    };
+    isa.legal_encodings(&func.dfg, &jump, VOID)
+        .ok()
+        .and_then(|encs| encs.map(|e| encinfo.bytes(e)).max())
+        .unwrap_or(0)
+}
+
+/// Get the alignment of loop headers in bytes, or 1 if loop headers should not be aligned.
+fn loop_alignment(isa: &TargetIsa) -> CodeOffset {
+    if isa.flags().align_loops() {
+        let align = 1 << isa.flags().loop_alignment_log2();
+        align.min(function_alignment(isa))
+    } else {
+        1
+    }
+}
+
+/// Identify the loop headers in `func`.
+///
+/// An EBB is considered a loop header when it is the destination of a branch from itself or from
+/// an EBB later in the layout. This doesn't need a loop analysis, and it identifies the branch
+/// targets that benefit from alignment.
+fn loop_headers(func: &Function) -> EntityMap<Ebb, bool> {
+    let mut visited = EntityMap::with_capacity(func.dfg.num_ebbs());
+    let mut headers = EntityMap::with_capacity(func.dfg.num_ebbs());
+    for ebb in func.layout.ebbs() {
+        visited[ebb] = true;
+        for inst in func.layout.ebb_insts(ebb) {
+            if let Some(dest) = func.dfg[inst].branch_destination() {
+                if visited[dest] {
+                    headers[dest] = true;
+                }
+            }
+        }
+    }
+    headers
 }
 
 /// Convert `jump` instructions to `fallthrough` instructions where possible and verify that any
 /// existing `fallthrough` instructions are correct.
 fn fallthroughs(func: &mut Function) {
-    for (ebb, succ) in func.layout.ebbs().adjacent_pairs() {
+    let pairs: Vec<(Ebb, Ebb)> = func.layout.ebbs().adjacent_pairs().collect();
+    for (ebb, succ) in pairs {
         let term = func.layout
             .last_inst(ebb)
             .expect("EBB has no terminator.");
-        if let InstructionData::Jump {
-                   ref mut opcode,
-                   destination,
-                   ..
-               } = func.dfg[term] {
-            match *opcode {
-                Opcode::Fallthrough => {
-                    // Somebody used a fall-through instruction before the branch relaxation pass.
-                    // Make sure it is correct, i.e. the destination is the layout successor.
-                    assert_eq!(destination, succ, "Illegal fall-through in {}", ebb)
-                }
-                Opcode::Jump => {
-                    // If this is a jump to the successor EBB, change it to a fall-through.
-                    if destination == succ {
-                        *opcode = Opcode::Fallthrough;
-                        func.encodings[term] = Default::default();
-                    }
-                }
-                _ => {}
+        match func.dfg[term] {
+            InstructionData::Jump {
+                opcode: Opcode::Fallthrough,
+                destination,
+                ..
+            } => {
+                // Somebody used a fall-through instruction before the branch relaxation pass.
+                // Make sure it is correct, i.e. the destination is the layout successor.
+                assert_eq!(destination, succ, "Illegal fall-through in {}", ebb)
+            }
+            // If this is a jump to the successor EBB, change it to a fall-through.
+            _ => {
+                jump_to_fallthrough(func, term);
             }
         }
     }
@@ -130,16 +324,186 @@ fn fallthroughs(func: &mut Function) {
 /// left.
 fn relax_branch(dfg: &mut DataFlowGraph,
                 encodings: &mut EntityMap<Inst, Encoding>,
+                offsets: &mut EntityMap<Ebb, CodeOffset>,
+                isa: &TargetIsa,
                 encinfo: &EncInfo,
+                divert: &RegDiversions,
                 pos: &mut Cursor,
                 offset: CodeOffset,
                 dest_offset: CodeOffset)
                 -> CodeOffset {
     let inst = pos.current_inst().unwrap();
-    dbg!("Relaxing [{}] {} for {:#x}-{:#x} range",
-         encinfo.display(encodings[inst]),
-         dfg.display_inst(inst),
-         offset,
-         dest_offset);
-    unimplemented!();
+    trace!("Relaxing [{}] {} for {:#x}-{:#x} range",
+            encinfo.display(encodings[inst]),
+            dfg.display_inst(inst),
+            offset,
+            dest_offset);
+
+    // Pick the smallest legal encoding that can reach the destination.
+    let best_enc = isa.legal_encodings(dfg, &dfg[inst], dfg.ctrl_typevar(inst))
+        .ok()
+        .and_then(|encs| {
+                      encs.filter(|&enc| {
+                                      encinfo
+                                          .branch_range(enc)
+                                          .map_or(false,
+                                                  |range| range.contains(offset, dest_offset))
+                                  })
+                          .min_by_key(|&enc| encinfo.bytes(enc))
+                  });
+    if let Some(enc) = best_enc {
+        encodings[inst] = enc;
+        return encinfo.bytes(enc);
+    }
+
+    // There is no encoding with enough range. Invert the branch so it skips over a new
+    // unconditional jump to the original destination.
+    // The new EBB starts with no diversions, so none can be active at the branch.
+    debug_assert!(divert.is_empty(),
+                  "Can't split the EBB at {} with active register diversions",
+                  dfg.display_inst(inst));
+    let num_fixed = match dfg[inst] {
+        InstructionData::Branch { .. } => 1,
+        InstructionData::BranchIcmp { .. } => 2,
+        _ => panic!("Can't relax {}", dfg.display_inst(inst)),
+    };
+    let dest = dfg[inst].branch_destination().unwrap();
+    let new_ebb = dfg.make_ebb();
+
+    // Move the EBB arguments from the branch to the jump.
+    let mut vlist = dfg[inst].take_value_list().unwrap();
+    let args: Vec<Value> = vlist.as_slice(&dfg.value_lists).to_vec();
+    vlist.clear(&mut dfg.value_lists);
+    vlist.extend(args[0..num_fixed].iter().cloned(), &mut dfg.value_lists);
+    dfg[inst].put_value_list(vlist);
+
+    invert_branch(dfg, inst);
+    *dfg[inst].branch_destination_mut().unwrap() = new_ebb;
+    let branch_enc = isa.encode(dfg, &dfg[inst], dfg.ctrl_typevar(inst))
+        .expect("Can't encode inverted branch");
+    encodings[inst] = branch_enc;
+
+    // Insert the jump after the branch, and split the EBB after the jump.
+    pos.next_inst()
+        .expect("A conditional branch can't terminate an EBB");
+    let jump = dfg.ins(pos).jump(dest, &args[num_fixed..]);
+    let jump_enc = isa.encode(dfg, &dfg[jump], dfg.ctrl_typevar(jump))
+        .expect("Can't encode jump");
+    *encodings.ensure(jump) = jump_enc;
+    pos.insert_ebb(new_ebb);
+    *offsets.ensure(new_ebb) = 0;
+    pos.goto_inst(jump);
+
+    encinfo.bytes(branch_enc) + encinfo.bytes(jump_enc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{relax_branches, code_size_estimate};
+    use ir::{Function, Ebb, Cursor, InstBuilder, Opcode, types};
+    use isa::{self, TargetIsa};
+    use settings;
+
+    /// Build a function where `ebb0` begins with a `brz` to `ebb1` which is `count` `iadd_imm`
+    /// instructions away. Every instruction is given its default encoding.
+    fn far_branch(isa: &TargetIsa, count: usize) -> (Function, Ebb, Ebb) {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        let arg = func.dfg.append_ebb_arg(ebb0, types::I32);
+        {
+            let dfg = &mut func.dfg;
+            let cur = &mut Cursor::new(&mut func.layout);
+            cur.insert_ebb(ebb0);
+            dfg.ins(cur).brz(arg, ebb1, &[]);
+            for _ in 0..count {
+                dfg.ins(cur).iadd_imm(arg, 1);
+            }
+            dfg.ins(cur).jump(ebb1, &[]);
+            cur.insert_ebb(ebb1);
+            dfg.ins(cur).jump(ebb0, &[arg]);
+        }
+        let insts: Vec<_> = func.layout
+            .ebbs()
+            .flat_map(|ebb| func.layout.ebb_insts(ebb))
+            .collect();
+        for inst in insts {
+            let enc = isa.encode(&func.dfg, &func.dfg[inst], func.dfg.ctrl_typevar(inst))
+                .unwrap();
+            *func.encodings.ensure(inst) = enc;
+        }
+        (func, ebb0, ebb1)
+    }
+
+    #[test]
+    fn longer_encoding() {
+        let isa = isa::lookup("intel")
+            .unwrap()
+            .finish(settings::Flags::new(&settings::builder()));
+        let encinfo = isa.encoding_info();
+
+        // 50 `add $1, %reg` instructions are too far for an 8-bit displacement.
+        let (mut func, ebb0, ebb1) = far_branch(&*isa, 50);
+        let brz = func.layout.ebb_insts(ebb0).next().unwrap();
+        let add = func.layout.ebb_insts(ebb0).nth(1).unwrap();
+        let body = 50 * func.inst_bytes(add, &encinfo);
+        assert_eq!(func.inst_recipe_name(brz, &encinfo), Some("Op1tjccb"));
+        assert_eq!(func.inst_bytes(brz, &encinfo), 4);
+
+        let size = relax_branches(&mut func, &*isa);
+        assert_eq!(func.inst_recipe_name(brz, &encinfo), Some("Op1tjccd"));
+        assert_eq!(func.inst_bytes(brz, &encinfo), 8);
+        assert_eq!(func.offsets[ebb1], 8 + body);
+        assert_eq!(size, 8 + body + 5);
+    }
+
+    #[test]
+    fn inverted_branch() {
+        let isa = isa::lookup("riscv")
+            .unwrap()
+            .finish(settings::Flags::new(&settings::builder()));
+
+        // 1100 4-byte instructions is beyond the 4 KB range of a conditional branch.
+        let (mut func, ebb0, ebb1) = far_branch(&*isa, 1100);
+        let brz = func.layout.ebb_insts(ebb0).next().unwrap();
+
+        let size = relax_branches(&mut func, &*isa);
+
+        // The `brz` is inverted to skip over a `jump` to the original destination.
+        assert_eq!(func.dfg[brz].opcode(), Opcode::Brnz);
+        let jump = func.layout.last_inst(ebb0).unwrap();
+        assert_eq!(func.dfg[jump].opcode(), Opcode::Jump);
+        assert_eq!(func.dfg[jump].branch_destination(), Some(ebb1));
+        let split = func.layout.next_ebb(ebb0).unwrap();
+        assert_eq!(func.dfg[brz].branch_destination(), Some(split));
+        assert_eq!(func.offsets[split], 8);
+        assert_eq!(func.offsets[ebb1], 8 + 4400);
+        assert_eq!(size, 8 + 4400 + 4);
+    }
+
+    #[test]
+    fn estimate() {
+        let isa = isa::lookup("intel")
+            .unwrap()
+            .finish(settings::Flags::new(&settings::builder()));
+
+        // A short branch is estimated at its longest encoding.
+        let (mut func, _, _) = far_branch(&*isa, 2);
+        let estimate = func.code_size_estimate(&*isa);
+        assert!(estimate > relax_branches(&mut func, &*isa));
+
+        // The final `jump` in `ebb0` becomes a 0-byte fall-through.
+        let (mut func, _, _) = far_branch(&*isa, 50);
+        let estimate = code_size_estimate(&func, &*isa);
+        assert_eq!(estimate, relax_branches(&mut func, &*isa) + 5);
+
+        // The estimate includes the jump of an inverted RISC-V branch.
+        let isa = isa::lookup("riscv")
+            .unwrap()
+            .finish(settings::Flags::new(&settings::builder()));
+        let (mut func, _, _) = far_branch(&*isa, 1100);
+        let estimate = code_size_estimate(&func, &*isa);
+        assert_eq!(estimate, 4 + 4400 + 4 + 4 + 4);
+        assert_eq!(estimate, relax_branches(&mut func, &*isa) + 4);
+    }
 }
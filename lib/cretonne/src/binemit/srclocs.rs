@@ -0,0 +1,114 @@
+//! Source location tables.
+//!
+//! After binary emission, the source locations of the instructions can be mapped to code offsets.
+//! The mapping is produced in the form of a DWARF line table: A sequence of rows with increasing
+//! code offsets where each row applies from its offset up to the offset of the next row.
+
+use binemit::CodeOffset;
+use ir::{Function, SourceLoc};
+use isa::TargetIsa;
+
+/// A row in the source location table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SourceLocRecord {
+    /// Code offset of the first byte with this source location.
+    pub offset: CodeOffset,
+
+    /// The source location of the code from `offset` up to the next record.
+    pub loc: SourceLoc,
+}
+
+/// Compute the source location table for `func`.
+///
+/// The function must have been prepared by `binemit::relax_branches()`. The returned records have
+/// strictly increasing code offsets, and adjacent records have different source locations. The
+/// last record extends to the end of the function's code.
+///
+/// Instructions without a source location, like the ones inserted by the register allocator, are
+/// given the source location of the preceding instruction. The code before the first instruction
+/// with a source location gets the default source location.
+pub fn source_locations(func: &Function, isa: &TargetIsa) -> Vec<SourceLocRecord> {
+    let encinfo = isa.encoding_info();
+    let mut records: Vec<SourceLocRecord> = Vec::new();
+
+    for ebb in func.layout.ebbs() {
+        let mut offset = func.offsets[ebb];
+        for inst in func.layout.ebb_insts(ebb) {
+            let size = func.inst_bytes(inst, &encinfo);
+            let loc = func.srclocs.get(inst).cloned().unwrap_or_default();
+
+            // Instructions that don't produce any code can't be found by a debugger.
+            if size > 0 && !loc.is_default() && records.last().map_or(true, |r| r.loc != loc) {
+                records.push(SourceLocRecord {
+                                 offset: offset,
+                                 loc: loc,
+                             });
+            }
+            offset += size;
+        }
+    }
+
+    // The prologue comes before any instruction with a source location.
+    if records.first().map_or(false, |r| r.offset > 0) {
+        records.insert(0,
+                       SourceLocRecord {
+                           offset: 0,
+                           loc: SourceLoc::default(),
+                       });
+    }
+
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use binemit::relax_branches;
+    use ir::{Function, InstBuilder, Cursor, types};
+    use isa;
+    use settings;
+
+    #[test]
+    fn merged() {
+        let shared_builder = settings::builder();
+        let shared_flags = settings::Flags::new(&shared_builder);
+        let isa = isa::lookup("riscv").unwrap().finish(shared_flags);
+
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        func.layout.append_ebb(ebb0);
+        let mut insts = Vec::new();
+        {
+            let mut pos = Cursor::new(&mut func.layout);
+            pos.goto_bottom(ebb0);
+            for _ in 0..6 {
+                let v = func.dfg.ins(&mut pos).iconst(types::I32, 0x1000);
+                insts.push(func.dfg.value_def(v).unwrap_inst());
+            }
+        }
+        for &inst in &insts {
+            let ctrl_type = func.dfg.ctrl_typevar(inst);
+            let enc = isa.encode(&func.dfg, &func.dfg[inst], ctrl_type).unwrap();
+            *func.encodings.ensure(inst) = enc;
+        }
+        *func.srclocs.ensure(insts[1]) = SourceLoc::new(7);
+        *func.srclocs.ensure(insts[2]) = SourceLoc::new(7);
+        *func.srclocs.ensure(insts[4]) = SourceLoc::new(9);
+        let size = relax_branches(&mut func, &*isa);
+        assert_eq!(size, 24);
+
+        assert_eq!(source_locations(&func, &*isa),
+                   [SourceLocRecord {
+                        offset: 0,
+                        loc: SourceLoc::default(),
+                    },
+                    SourceLocRecord {
+                        offset: 4,
+                        loc: SourceLoc::new(7),
+                    },
+                    SourceLocRecord {
+                        offset: 16,
+                        loc: SourceLoc::new(9),
+                    }]);
+    }
+}
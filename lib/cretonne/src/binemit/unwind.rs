@@ -0,0 +1,382 @@
+//! Unwind information.
+//!
+//! The frame layout changes recorded by the prologue and epilogue insertion are translated into
+//! DWARF call frame information (CFI) here. The CFI describes how to find the caller's frame and
+//! the saved registers at every instruction in the function, so an embedder can generate
+//! backtraces and unwind through Cretonne frames when handling exceptions.
+//!
+//! The CFI instructions are produced separately for the common information entry (CIE) which
+//! holds the frame layout on entry to the function, and the frame description entry (FDE) which
+//! holds the changes made by the function's code. `UnwindInfo::write_eh_frame()` can wrap them in
+//! the `.eh_frame` format used by the system unwinder.
+
+use binemit::CodeOffset;
+use entity_map::EntityMap;
+use ir::{Function, FrameLayoutChange};
+use isa::{TargetIsa, RegUnit};
+use std::mem;
+
+// DWARF call frame instruction opcodes.
+const DW_CFA_ADVANCE_LOC: u8 = 0x40;
+const DW_CFA_OFFSET: u8 = 0x80;
+const DW_CFA_RESTORE: u8 = 0xc0;
+const DW_CFA_NOP: u8 = 0x00;
+const DW_CFA_ADVANCE_LOC1: u8 = 0x02;
+const DW_CFA_ADVANCE_LOC2: u8 = 0x03;
+const DW_CFA_ADVANCE_LOC4: u8 = 0x04;
+const DW_CFA_OFFSET_EXTENDED: u8 = 0x05;
+const DW_CFA_RESTORE_EXTENDED: u8 = 0x06;
+const DW_CFA_REMEMBER_STATE: u8 = 0x0a;
+const DW_CFA_RESTORE_STATE: u8 = 0x0b;
+const DW_CFA_DEF_CFA: u8 = 0x0c;
+const DW_CFA_DEF_CFA_OFFSET: u8 = 0x0e;
+
+// Pointer encoding for the `.eh_frame` FDE: a signed 32-bit PC-relative address.
+const DW_EH_PE_PCREL_SDATA4: u8 = 0x1b;
+
+/// DWARF call frame information for a single function.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnwindInfo {
+    /// Factor applied to the code offsets in `DW_CFA_advance_loc` instructions.
+    pub code_alignment_factor: u32,
+
+    /// Factor applied to the offsets of saved registers.
+    pub data_alignment_factor: i32,
+
+    /// The DWARF register number of the return address column.
+    pub return_address_column: u16,
+
+    /// CFI instructions describing the frame layout on entry to the function.
+    pub cie_instructions: Vec<u8>,
+
+    /// CFI instructions describing the frame layout changes in the function's code.
+    pub fde_instructions: Vec<u8>,
+
+    /// Size of the function's code in bytes, not including any read-only data following it.
+    pub code_size: CodeOffset,
+}
+
+/// Compute the unwind information for `func`.
+///
+/// The function must have been prepared by `TargetIsa::prologue_epilogue()` and
+/// `binemit::relax_branches()`. Returns `None` if the ISA doesn't support unwind information or
+/// no frame layout was recorded.
+pub fn unwind_info(func: &Function, isa: &TargetIsa) -> Option<UnwindInfo> {
+    if func.frame_layout.is_empty() {
+        return None;
+    }
+    let return_address_column = isa.dwarf_return_address_column()?;
+    let data_alignment_factor = if isa.flags().is_64bit() { -8 } else { -4 };
+
+    // Find the code offset following each instruction.
+    let encinfo = isa.encoding_info();
+    let mut inst_ends = EntityMap::with_capacity(func.dfg.num_insts());
+    let mut code_size = 0;
+    for ebb in func.layout.ebbs() {
+        code_size = func.offsets[ebb];
+        for inst in func.layout.ebb_insts(ebb) {
+            code_size += func.inst_bytes(inst, &encinfo);
+            inst_ends[inst] = code_size;
+        }
+    }
+
+    let mut cfi = CfiWriter::new(isa, data_alignment_factor, return_address_column);
+    for &change in &func.frame_layout.initial {
+        cfi.change(change)?;
+    }
+    let cie_instructions = mem::replace(&mut cfi.bytes, Vec::new());
+
+    // The instructions inserted in the epilogue come before the prologue instructions in the
+    // recorded changes, so sort them by code offset, keeping the order of changes for the same
+    // instruction.
+    let mut changes: Vec<_> = func.frame_layout
+        .changes
+        .iter()
+        .map(|&(inst, change)| (inst_ends[inst], change))
+        .collect();
+    changes.sort_by_key(|&(offset, _)| offset);
+
+    let mut loc = 0;
+    for (offset, change) in changes {
+        // A change after the final return doesn't affect any code.
+        if offset >= code_size {
+            continue;
+        }
+        cfi.advance(offset - loc);
+        loc = offset;
+        cfi.change(change)?;
+    }
+
+    Some(UnwindInfo {
+             code_alignment_factor: 1,
+             data_alignment_factor: data_alignment_factor,
+             return_address_column: return_address_column,
+             cie_instructions: cie_instructions,
+             fde_instructions: cfi.bytes,
+             code_size: code_size,
+         })
+}
+
+impl UnwindInfo {
+    /// Append a CIE and an FDE in the `.eh_frame` format to `out`.
+    ///
+    /// The FDE refers to the function with a 32-bit PC-relative address which is left as 0.
+    /// Return the offset into `out` of that address so the caller can fill it in or emit a
+    /// relocation for it.
+    pub fn write_eh_frame(&self, out: &mut Vec<u8>) -> usize {
+        let align = self.data_alignment_factor.abs() as usize;
+
+        // Common information entry.
+        let cie_start = out.len();
+        put_u32(out, 0);
+        put_u32(out, 0);
+        out.push(1);
+        out.extend_from_slice(b"zR\0");
+        put_uleb128(out, self.code_alignment_factor as u64);
+        put_sleb128(out, self.data_alignment_factor as i64);
+        out.push(self.return_address_column as u8);
+        put_uleb128(out, 1);
+        out.push(DW_EH_PE_PCREL_SDATA4);
+        out.extend_from_slice(&self.cie_instructions);
+        finish_entry(out, cie_start, align);
+
+        // Frame description entry.
+        let fde_start = out.len();
+        put_u32(out, 0);
+        let cie_pointer = out.len() - cie_start;
+        put_u32(out, cie_pointer as u32);
+        let pc_begin = out.len();
+        put_u32(out, 0);
+        put_u32(out, self.code_size);
+        put_uleb128(out, 0);
+        out.extend_from_slice(&self.fde_instructions);
+        finish_entry(out, fde_start, align);
+
+        pc_begin
+    }
+}
+
+/// Pad the entry starting at `start` with `DW_CFA_nop` to a multiple of `align` bytes and fill in
+/// its length field.
+fn finish_entry(out: &mut Vec<u8>, start: usize, align: usize) {
+    while (out.len() - start) % align != 0 {
+        out.push(DW_CFA_NOP);
+    }
+    let length = (out.len() - start - 4) as u32;
+    for (i, byte) in out[start..start + 4].iter_mut().enumerate() {
+        *byte = (length >> (8 * i)) as u8;
+    }
+}
+
+/// Encoder of DWARF call frame instructions.
+struct CfiWriter<'a> {
+    isa: &'a TargetIsa,
+    data_alignment_factor: i32,
+    return_address_column: u16,
+    cfa_reg: Option<RegUnit>,
+    saved_cfa_regs: Vec<Option<RegUnit>>,
+    bytes: Vec<u8>,
+}
+
+impl<'a> CfiWriter<'a> {
+    fn new(isa: &'a TargetIsa,
+           data_alignment_factor: i32,
+           return_address_column: u16)
+           -> CfiWriter<'a> {
+        CfiWriter {
+            isa: isa,
+            data_alignment_factor: data_alignment_factor,
+            return_address_column: return_address_column,
+            cfa_reg: None,
+            saved_cfa_regs: Vec::new(),
+            bytes: Vec::new(),
+        }
+    }
+
+    /// Advance the location by `delta` bytes of code.
+    fn advance(&mut self, delta: CodeOffset) {
+        if delta == 0 {
+            return;
+        }
+        if delta < 0x40 {
+            self.bytes.push(DW_CFA_ADVANCE_LOC | delta as u8);
+        } else if delta <= 0xff {
+            self.bytes.push(DW_CFA_ADVANCE_LOC1);
+            self.bytes.push(delta as u8);
+        } else if delta <= 0xffff {
+            self.bytes.push(DW_CFA_ADVANCE_LOC2);
+            self.bytes.push(delta as u8);
+            self.bytes.push((delta >> 8) as u8);
+        } else {
+            self.bytes.push(DW_CFA_ADVANCE_LOC4);
+            put_u32(&mut self.bytes, delta);
+        }
+    }
+
+    /// Encode a single frame layout change.
+    ///
+    /// Returns `None` if a register doesn't have a DWARF number.
+    fn change(&mut self, change: FrameLayoutChange) -> Option<()> {
+        match change {
+            FrameLayoutChange::CallFrameAddressAt { reg, offset } => {
+                debug_assert!(offset >= 0, "CFA below the stack pointer");
+                if self.cfa_reg == Some(reg) {
+                    self.bytes.push(DW_CFA_DEF_CFA_OFFSET);
+                } else {
+                    let column = self.isa.map_dwarf_register(reg)?;
+                    self.bytes.push(DW_CFA_DEF_CFA);
+                    put_uleb128(&mut self.bytes, column as u64);
+                    self.cfa_reg = Some(reg);
+                }
+                put_uleb128(&mut self.bytes, offset as u64);
+            }
+            FrameLayoutChange::RegAt { reg, cfa_offset } => {
+                let column = self.isa.map_dwarf_register(reg)?;
+                self.offset(column, cfa_offset);
+            }
+            FrameLayoutChange::ReturnAddressAt { cfa_offset } => {
+                let column = self.return_address_column;
+                self.offset(column, cfa_offset);
+            }
+            FrameLayoutChange::Restore { reg } => {
+                let column = self.isa.map_dwarf_register(reg)?;
+                if column < 0x40 {
+                    self.bytes.push(DW_CFA_RESTORE | column as u8);
+                } else {
+                    self.bytes.push(DW_CFA_RESTORE_EXTENDED);
+                    put_uleb128(&mut self.bytes, column as u64);
+                }
+            }
+            FrameLayoutChange::RememberState => {
+                self.saved_cfa_regs.push(self.cfa_reg);
+                self.bytes.push(DW_CFA_REMEMBER_STATE);
+            }
+            FrameLayoutChange::RestoreState => {
+                self.cfa_reg = self.saved_cfa_regs
+                    .pop()
+                    .expect("RestoreState without RememberState");
+                self.bytes.push(DW_CFA_RESTORE_STATE);
+            }
+        }
+        Some(())
+    }
+
+    /// Record that `column` is saved at `cfa_offset`.
+    fn offset(&mut self, column: u16, cfa_offset: i32) {
+        debug_assert_eq!(cfa_offset % self.data_alignment_factor,
+                         0,
+                         "Misaligned save slot");
+        let factored = cfa_offset / self.data_alignment_factor;
+        debug_assert!(factored >= 0, "Save slot above the CFA");
+        if column < 0x40 {
+            self.bytes.push(DW_CFA_OFFSET | column as u8);
+        } else {
+            self.bytes.push(DW_CFA_OFFSET_EXTENDED);
+            put_uleb128(&mut self.bytes, column as u64);
+        }
+        put_uleb128(&mut self.bytes, factored as u64);
+    }
+}
+
+fn put_u32(out: &mut Vec<u8>, x: u32) {
+    for i in 0..4 {
+        out.push((x >> (8 * i)) as u8);
+    }
+}
+
+fn put_uleb128(out: &mut Vec<u8>, mut x: u64) {
+    loop {
+        let byte = (x & 0x7f) as u8;
+        x >>= 7;
+        if x == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn put_sleb128(out: &mut Vec<u8>, mut x: i64) {
+    loop {
+        let byte = (x & 0x7f) as u8;
+        x >>= 7;
+        if (x == 0 && byte & 0x40 == 0) || (x == -1 && byte & 0x40 != 0) {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{put_uleb128, put_sleb128};
+    use Context;
+    use ir::{Function, FunctionName, Signature, ExtFuncData, Cursor, InstBuilder};
+    use isa;
+    use settings::{self, Configurable};
+
+    #[test]
+    fn leb128() {
+        let mut out = Vec::new();
+        put_uleb128(&mut out, 2);
+        put_uleb128(&mut out, 624485);
+        assert_eq!(out, [0x02, 0xe5, 0x8e, 0x26]);
+
+        out.clear();
+        put_sleb128(&mut out, -8);
+        put_sleb128(&mut out, 63);
+        put_sleb128(&mut out, 64);
+        put_sleb128(&mut out, -123456);
+        assert_eq!(out, [0x78, 0x3f, 0xc0, 0x00, 0xc0, 0xbb, 0x78]);
+    }
+
+    #[test]
+    fn intel_call() {
+        let mut shared_builder = settings::builder();
+        shared_builder.set_bool("is_64bit", true).unwrap();
+        let shared_flags = settings::Flags::new(&shared_builder);
+        let isa = isa::lookup("intel").unwrap().finish(shared_flags);
+
+        // A non-leaf function allocates a stack frame to keep the stack aligned for the call.
+        let mut func = Function::new();
+        let sig = func.dfg.signatures.push(Signature::new());
+        let callee = func.dfg
+            .ext_funcs
+            .push(ExtFuncData {
+                      name: FunctionName::new("foo"),
+                      signature: sig,
+                      colocated: false,
+                  });
+        let ebb0 = func.dfg.make_ebb();
+        {
+            let mut pos = Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            func.dfg.ins(&mut pos).call(callee, &[]);
+            func.dfg.ins(&mut pos).return_(&[]);
+        }
+
+        let mut ctx = Context::new();
+        ctx.func = func;
+        let code_size = ctx.compile(&*isa).unwrap();
+
+        // subq $8, %rsp; call foo; addq $8, %rsp; ret
+        let info = ctx.unwind_info(&*isa).unwrap();
+        assert_eq!(info.code_size, code_size);
+        assert_eq!(info.return_address_column, 16);
+        // DW_CFA_def_cfa %rsp+8, DW_CFA_offset RA at CFA-8.
+        assert_eq!(info.cie_instructions, [0x0c, 0x07, 0x08, 0x90, 0x01]);
+        // After the `subq`: DW_CFA_def_cfa_offset 16.
+        // After the `addq`: DW_CFA_remember_state, DW_CFA_def_cfa_offset 8.
+        assert_eq!(info.fde_instructions,
+                   [0x47, 0x0e, 0x10, 0x4c, 0x0a, 0x0e, 0x08]);
+
+        let mut eh_frame = Vec::new();
+        let pc_begin = info.write_eh_frame(&mut eh_frame);
+        assert_eq!(eh_frame.len() % 8, 0);
+        // The CIE is 24 bytes, and the FDE begins with its length and CIE pointer.
+        assert_eq!(&eh_frame[..4], &[20, 0, 0, 0]);
+        assert_eq!(&eh_frame[28..32], &[28, 0, 0, 0]);
+        assert_eq!(pc_begin, 32);
+    }
+}
@@ -0,0 +1,145 @@
+//! Branch rewriting.
+//!
+//! Passes that change the control flow, like jump threading, EBB layout, and branch relaxation,
+//! need to make the same small edits to branch instructions. The functions in this module make
+//! those edits consistently:
+//!
+//! - `redirect_branch()` changes the destination of a branch and updates the control flow graph.
+//! - `invert_branch()` inverts the condition of a conditional branch.
+//! - `jump_to_fallthrough()` and `fallthrough_to_jump()` convert between the two forms of an
+//!   unconditional jump. A `fallthrough` must jump to the next EBB in the layout.
+//!
+//! Only `redirect_branch()` changes the edges of the control flow graph.
+
+use flowgraph::ControlFlowGraph;
+use ir::{Function, DataFlowGraph, Ebb, Inst, InstructionData, Opcode};
+use ir::condcodes::CondCode;
+
+/// Change the destination of the branch or jump `inst` to `dest`, and update `cfg`.
+///
+/// The EBB arguments passed by the branch are not changed, so they must be valid for `dest`.
+pub fn redirect_branch(func: &mut Function, cfg: &mut ControlFlowGraph, inst: Inst, dest: Ebb) {
+    match func.dfg[inst].branch_destination_mut() {
+        Some(destination) => *destination = dest,
+        None => panic!("Can't redirect {}", func.dfg.display_inst(inst)),
+    }
+    let ebb = func.layout
+        .inst_ebb(inst)
+        .expect("The branch must be inserted in the layout");
+    cfg.recompute_ebb(func, ebb);
+}
+
+/// Invert the condition of the conditional branch `inst`.
+///
+/// The destination is not changed, so the branch is now taken when it used to fall through to the
+/// next instruction.
+pub fn invert_branch(dfg: &mut DataFlowGraph, inst: Inst) {
+    let opcode = dfg[inst].opcode();
+    match dfg[inst] {
+        InstructionData::Branch { ref mut opcode, .. } => {
+            *opcode = match *opcode {
+                Opcode::Brz => Opcode::Brnz,
+                Opcode::Brnz => Opcode::Brz,
+                op => panic!("Can't invert {}", op),
+            };
+        }
+        InstructionData::BranchIcmp { ref mut cond, .. } => *cond = cond.inverse(),
+        _ => panic!("Can't invert {}", opcode),
+    }
+}
+
+/// Convert the `jump` instruction `inst` to a `fallthrough` if its destination is the next EBB in
+/// the layout.
+///
+/// A `fallthrough` doesn't need an encoding, so any encoding of the jump is cleared. Returns true
+/// if the jump was converted.
+pub fn jump_to_fallthrough(func: &mut Function, inst: Inst) -> bool {
+    let next = func.layout
+        .inst_ebb(inst)
+        .and_then(|ebb| func.layout.next_ebb(ebb));
+    match func.dfg[inst] {
+        InstructionData::Jump {
+            ref mut opcode,
+            destination,
+            ..
+        } if *opcode == Opcode::Jump && Some(destination) == next => {
+            *opcode = Opcode::Fallthrough;
+        }
+        _ => return false,
+    }
+    if func.encodings.get(inst).is_some() {
+        func.encodings[inst] = Default::default();
+    }
+    true
+}
+
+/// Convert the `fallthrough` instruction `inst` to a `jump`.
+///
+/// This is needed before the EBBs are moved around in the layout. The new jump doesn't have an
+/// encoding, so it must be encoded if the function has already been legalized.
+pub fn fallthrough_to_jump(func: &mut Function, inst: Inst) {
+    match func.dfg[inst] {
+        InstructionData::Jump { ref mut opcode, .. } if *opcode == Opcode::Fallthrough => {
+            *opcode = Opcode::Jump;
+        }
+        _ => panic!("Expected fallthrough: {}", func.dfg.display_inst(inst)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{redirect_branch, invert_branch, jump_to_fallthrough, fallthrough_to_jump};
+    use flowgraph::ControlFlowGraph;
+    use ir::{Function, Cursor, InstBuilder, InstructionData, Opcode};
+    use ir::condcodes::IntCC;
+    use ir::types::I32;
+
+    #[test]
+    fn rewrite() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        let ebb2 = func.dfg.make_ebb();
+        let v0 = func.dfg.append_ebb_arg(ebb0, I32);
+        let (brz, icmp, jump) = {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            let brz = dfg.ins(pos).brz(v0, ebb2, &[]);
+            let icmp = dfg.ins(pos).br_icmp(IntCC::SignedLessThan, v0, v0, ebb2, &[]);
+            let jump = dfg.ins(pos).jump(ebb1, &[]);
+            pos.insert_ebb(ebb1);
+            dfg.ins(pos).jump(ebb2, &[]);
+            pos.insert_ebb(ebb2);
+            dfg.ins(pos).return_(&[]);
+            (brz, icmp, jump)
+        };
+        let mut cfg = ControlFlowGraph::with_function(&func);
+
+        redirect_branch(&mut func, &mut cfg, brz, ebb1);
+        assert_eq!(func.dfg[brz].branch_destination(), Some(ebb1));
+        assert_eq!(cfg.get_predecessors(ebb1).len(), 2);
+        assert_eq!(cfg.get_predecessors(ebb2).len(), 2);
+
+        invert_branch(&mut func.dfg, brz);
+        assert_eq!(func.dfg[brz].opcode(), Opcode::Brnz);
+        invert_branch(&mut func.dfg, icmp);
+        match func.dfg[icmp] {
+            InstructionData::BranchIcmp { cond, .. } => {
+                assert_eq!(cond, IntCC::SignedGreaterThanOrEqual)
+            }
+            _ => panic!("not a br_icmp"),
+        }
+
+        assert!(jump_to_fallthrough(&mut func, jump));
+        assert_eq!(func.dfg[jump].opcode(), Opcode::Fallthrough);
+        assert!(!jump_to_fallthrough(&mut func, jump));
+        let last = func.layout.last_inst(ebb1).unwrap();
+        assert!(jump_to_fallthrough(&mut func, last));
+
+        func.layout.move_ebb_to_end(ebb1);
+        fallthrough_to_jump(&mut func, jump);
+        assert_eq!(func.dfg[jump].opcode(), Opcode::Jump);
+        assert!(!jump_to_fallthrough(&mut func, jump));
+    }
+}
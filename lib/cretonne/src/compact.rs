@@ -0,0 +1,188 @@
+//! Entity compaction.
+//!
+//! Passes like dead code elimination leave unused entries behind in the entity tables: dead
+//! instructions and values that are no longer referenced, EBBs that were removed from the layout,
+//! and value aliases. The compaction pass renumbers the EBBs, instructions, and values that are
+//! still in use densely in layout order and rewrites all references to them. This keeps the memory
+//! usage and the printed IL of a function tidy when it goes through many transformations.
+//!
+//! The function's data flow graph and layout are rebuilt, and the tables indexed by EBB,
+//! instruction, or value are remapped. Value aliases are resolved. The stack slots, jump tables,
+//! global variables, signatures, and external functions keep their numbers.
+//!
+//! Any analysis computed for the function, like the control flow graph or the live ranges, refers
+//! to the old numbering and must be recomputed.
+
+use entity_map::EntityMap;
+use ir::{Function, DataFlowGraph, Layout, Ebb, Inst, ValueList, ValueMap};
+use packed_option::PackedOption;
+use std::mem;
+
+/// Renumber the EBBs, instructions, and values in `func` densely in layout order.
+pub fn do_compact(func: &mut Function) {
+    let mut dfg = DataFlowGraph::new();
+    let mut layout = Layout::new();
+    dfg.signatures = mem::replace(&mut func.dfg.signatures, EntityMap::new());
+    dfg.ext_funcs = mem::replace(&mut func.dfg.ext_funcs, EntityMap::new());
+
+    let mut ebb_map = EntityMap::<Ebb, PackedOption<Ebb>>::new();
+    let mut inst_map = EntityMap::<Inst, PackedOption<Inst>>::new();
+    let mut value_map = ValueMap::new();
+    let mut encodings = EntityMap::new();
+    let mut locations = EntityMap::new();
+    let mut srclocs = EntityMap::new();
+    let mut cold_ebbs = EntityMap::new();
+    let mut offsets = EntityMap::new();
+
+    // Create the EBBs first, so branches can refer to them.
+    for ebb in func.layout.ebbs() {
+        let new_ebb = dfg.make_ebb();
+        layout.append_ebb(new_ebb);
+        *ebb_map.ensure(ebb) = new_ebb.into();
+        if func.is_cold(ebb) {
+            *cold_ebbs.ensure(new_ebb) = true;
+        }
+        if let Some(&offset) = func.offsets.get(ebb) {
+            *offsets.ensure(new_ebb) = offset;
+        }
+    }
+    let map_ebb = |ebb: Ebb| {
+        ebb_map
+            .get(ebb)
+            .and_then(|e| e.expand())
+            .unwrap_or_else(|| panic!("{} is not in the layout", ebb))
+    };
+
+    // Create the EBB arguments and copy the instructions with their results, so the values are
+    // numbered in the order they appear in the printed IL. A value can be used in an EBB that
+    // appears before its definition in the layout, so the arguments are rewritten in a second pass.
+    for ebb in func.layout.ebbs() {
+        let new_ebb = map_ebb(ebb);
+        for &arg in func.dfg.ebb_args(ebb) {
+            let new_arg = dfg.append_ebb_arg(new_ebb, func.dfg.value_type(arg));
+            value_map.insert(arg, new_arg);
+        }
+        for inst in func.layout.ebb_insts(ebb) {
+            let mut data = func.dfg[inst].clone();
+            if let Some(args) = data.take_value_list() {
+                let mut copy = ValueList::new();
+                copy.extend(args.as_slice(&func.dfg.value_lists).iter().cloned(),
+                            &mut dfg.value_lists);
+                data.put_value_list(copy);
+            }
+            if let Some(dest) = data.branch_destination_mut() {
+                *dest = map_ebb(*dest);
+            }
+            let new_inst = dfg.make_inst(data);
+            layout.append_inst(new_inst, new_ebb);
+            *inst_map.ensure(inst) = new_inst.into();
+            for &res in func.dfg.inst_results(inst) {
+                let new_res = dfg.append_result(new_inst, func.dfg.value_type(res));
+                value_map.insert(res, new_res);
+            }
+
+            if let Some(&enc) = func.encodings.get(inst) {
+                *encodings.ensure(new_inst) = enc;
+            }
+            if let Some(&srcloc) = func.srclocs.get(inst) {
+                *srclocs.ensure(new_inst) = srcloc;
+            }
+        }
+    }
+
+    // Rewrite the instruction arguments.
+    for ebb in layout.ebbs() {
+        for inst in layout.ebb_insts(ebb) {
+            for arg in dfg.inst_args_mut(inst) {
+                let old = func.dfg.resolve_aliases(*arg);
+                *arg = value_map
+                    .get(old)
+                    .unwrap_or_else(|| panic!("{} is not defined in the layout", old));
+            }
+        }
+    }
+
+    // Remap the value locations. Values that were not copied are dropped.
+    for ebb in func.layout.ebbs() {
+        let args = func.dfg.ebb_args(ebb).iter();
+        let results = func.layout
+            .ebb_insts(ebb)
+            .flat_map(|inst| func.dfg.inst_results(inst).iter());
+        for &value in args.chain(results) {
+            if let Some(&loc) = func.locations.get(value) {
+                *locations.ensure(value_map.map(value)) = loc;
+            }
+        }
+    }
+
+    for jt in func.jump_tables.keys() {
+        for entry in func.jump_tables[jt].as_mut_slice() {
+            if let Some(ebb) = entry.expand() {
+                *entry = map_ebb(ebb).into();
+            }
+        }
+    }
+
+    for change in &mut func.frame_layout.changes {
+        change.0 = inst_map[change.0].expect("Frame layout change at a removed instruction");
+    }
+
+    func.dfg = dfg;
+    func.layout = layout;
+    func.encodings = encodings;
+    func.locations = locations;
+    func.srclocs = srclocs;
+    func.cold_ebbs = cold_ebbs;
+    func.offsets = offsets;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::do_compact;
+    use dce::do_dce;
+    use ir::{Function, Cursor, InstBuilder, ValueLoc, StackSlot};
+    use ir::types::I32;
+    use entity_map::EntityRef;
+
+    #[test]
+    fn compact() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        // An EBB that is never inserted in the layout.
+        func.dfg.make_ebb();
+        let ebb2 = func.dfg.make_ebb();
+        let arg = func.dfg.append_ebb_arg(ebb0, I32);
+        let ebb2_arg = func.dfg.append_ebb_arg(ebb2, I32);
+        let sum;
+        {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            let dead = dfg.ins(pos).iconst(I32, 7);
+            dfg.ins(pos).imul(dead, arg);
+            sum = dfg.ins(pos).iadd_imm(arg, 1);
+            dfg.ins(pos).jump(ebb2, &[sum]);
+            pos.insert_ebb(ebb2);
+            let alias = dfg.make_value_alias(ebb2_arg);
+            dfg.ins(pos).return_(&[alias]);
+        }
+        func.set_cold(ebb2);
+        *func.locations.ensure(sum) = ValueLoc::Stack(StackSlot::new(0));
+
+        do_dce(&mut func);
+        do_compact(&mut func);
+
+        assert_eq!(func.dfg.num_ebbs(), 2);
+        assert_eq!(func.dfg.num_insts(), 3);
+        assert_eq!(func.to_string(),
+                   "function \"\"() {\n\
+                    ebb0(v0: i32):\n    \
+                        v1 = iadd_imm v0, 1\n    \
+                        jump ebb1(v1)\n\n\
+                    ebb1(v2: i32) cold:\n    \
+                        return v2\n\
+                    }\n");
+        let v1 = func.dfg.first_result(func.layout.ebb_insts(ebb0).next().unwrap());
+        assert_eq!(func.locations[v1], ValueLoc::Stack(StackSlot::new(0)));
+    }
+}
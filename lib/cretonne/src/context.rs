@@ -9,15 +9,41 @@
 //! contexts concurrently. Typically, you would have one context per compilation thread and only a
 //! single ISA instance.
 
+use binemit::{CodeOffset, relax_branches, MemoryCodeSink, RelocRecord, RelocSink, TrapRecord,
+              TrapSink, UnwindInfo, unwind_info, SourceLocRecord, source_locations};
+use compact::do_compact;
+use dce::do_dce;
+use diagnostics::Diagnostics;
+use ebb_layout::do_ebb_layout;
+use heap_checks::do_heap_checks;
 use dominator_tree::DominatorTree;
 use flowgraph::ControlFlowGraph;
-use ir::Function;
+use ir::{Function, Inst};
+use ir::entities::AnyEntity;
 use isa::TargetIsa;
-use legalize_function;
+use legalizer::{legalize_function, lowering, LegalizeHooks, LegalizeTrace, Lowering};
+use mem_usage::MemUsage;
+use opcode_stats::OpcodeStats;
+use pipeline::{Pipeline, Pass};
+use preopt::do_preopt;
 use regalloc;
-use result::CtonResult;
+use stack_layout::frame_size;
+use result::{CtonError, CtonResult, ErrorKind};
+use std::time::Instant;
+use timing::PassTimes;
 use verifier;
 
+/// The machine code and tables produced for a function by `Context::compile_and_emit()`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompiledFunction {
+    /// The machine code followed by any read-only data, like jump tables.
+    pub code: Vec<u8>,
+    /// Relocations to apply to `code`.
+    pub relocs: Vec<RelocRecord>,
+    /// The instructions in `code` that can trap.
+    pub traps: Vec<TrapRecord>,
+}
+
 /// Persistent data structures and compilation pipeline.
 pub struct Context {
     /// The function we're compiling.
@@ -31,30 +57,145 @@ pub struct Context {
 
     /// Register allocation context.
     pub regalloc: regalloc::Context,
+
+    /// Custom legalization hooks registered by the embedder.
+    pub legalize_hooks: LegalizeHooks,
+
+    /// Set this to an empty trace to record the legalization action applied to each instruction
+    /// by `legalize()`, or leave it as `None` to avoid the overhead.
+    pub legalize_trace: Option<LegalizeTrace>,
+
+    /// The passes run by `run_pipeline()`, or `None` to use the default pipeline for the
+    /// `opt_level` setting.
+    pub pipeline: Option<Pipeline>,
+
+    /// Time spent in each pass, accumulated over all the functions compiled.
+    pub timing: PassTimes,
+
+    /// Set this to empty statistics to count the instructions by opcode before and after each
+    /// pass run by `run_pipeline()`. The counts are accumulated over all the functions compiled.
+    pub opcode_stats: Option<OpcodeStats>,
+
+    /// Warnings produced by the passes run on `func`.
+    ///
+    /// This is cleared by `flowgraph()` along with `state`.
+    pub diagnostics: Diagnostics,
+
+    /// The passes that have been run on `func`.
+    ///
+    /// This is reset by `flowgraph()` which must be the first thing run on a new function. An
+    /// embedder that runs passes on the function by other means can update it.
+    pub state: PassState,
+}
+
+/// The passes that have been run on the function in a `Context`.
+///
+/// The `Context` methods use this to return an `ErrorKind::MissingPass` error when a pass is run
+/// before the passes it depends on.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct PassState {
+    /// The control flow graph and dominator tree have been computed.
+    pub flowgraph: bool,
+    /// The function has been legalized.
+    pub legalized: bool,
+    /// Registers have been allocated.
+    pub regalloc: bool,
+    /// The prologue and epilogue have been inserted.
+    pub prologue_epilogue: bool,
+    /// Branches have been relaxed and the code size is known.
+    pub relaxed: bool,
+}
+
+/// Stack frames larger than this get a warning since they may overflow the stack.
+const LARGE_FRAME_SIZE: u32 = 1 << 20;
+
+/// Check that the pass `requires` has been run before `pass`.
+fn require(done: bool, pass: &'static str, requires: &'static str) -> CtonResult {
+    if done {
+        Ok(())
+    } else {
+        Err(ErrorKind::MissingPass {
+                    pass: pass,
+                    requires: requires,
+                }
+                .into())
+    }
 }
 
 impl Context {
     /// Allocate a new compilation context.
     ///
     /// The returned instance should be reused for compiling multiple functions in order to avoid
-    /// needless allocator thrashing.
+    /// needless allocator thrashing. Call `clear()` before building the next function in
+    /// `self.func`.
     pub fn new() -> Context {
         Context {
             func: Function::new(),
             cfg: ControlFlowGraph::new(),
             domtree: DominatorTree::new(),
             regalloc: regalloc::Context::new(),
+            legalize_hooks: LegalizeHooks::new(),
+            legalize_trace: None,
+            pipeline: None,
+            timing: PassTimes::new(),
+            opcode_stats: None,
+            diagnostics: Diagnostics::new(),
+            state: PassState::default(),
         }
     }
 
+    /// Clear all data structures in this context so it can compile a new function.
+    ///
+    /// The memory allocated for the previous function is kept, so building the next function
+    /// directly in `self.func` avoids most allocations. The `legalize_hooks` and `pipeline`
+    /// configuration is kept, and so are the accumulated pass times in `timing` and the
+    /// `opcode_stats`. A legalization trace is emptied, but tracing stays enabled.
+    pub fn clear(&mut self) {
+        self.func.clear();
+        if let Some(ref mut trace) = self.legalize_trace {
+            trace.clear();
+        }
+        self.cfg.clear();
+        self.domtree.clear();
+        self.regalloc.clear();
+        self.diagnostics.clear();
+        self.state = PassState::default();
+    }
+
+    /// Get a breakdown of the heap memory allocated by the function and the compiler's side
+    /// tables in this context.
+    ///
+    /// After compiling a function, this shows how much memory is kept around for the next
+    /// function when the context is reused.
+    pub fn mem_usage(&self) -> MemUsage {
+        let mut usage = self.func.mem_usage();
+        usage.add("cfg", self.cfg.mem_usage());
+        usage.add("domtree", self.domtree.mem_usage());
+        self.regalloc.mem_usage(&mut usage);
+        usage
+    }
+
+    /// Determine how `legalize()` would handle the instruction `inst` in the function.
+    ///
+    /// This takes the registered `legalize_hooks` into account. Use `cretonne::lowering()` to
+    /// ask about an instruction before inserting it.
+    pub fn lowering(&self, inst: Inst, isa: &TargetIsa) -> Lowering {
+        let dfg = &self.func.dfg;
+        lowering(dfg, &dfg[inst], dfg.ctrl_typevar(inst), isa, &self.legalize_hooks)
+    }
+
     /// Run the verifier on the function.
     ///
     /// Also check that the dominator tree and control flow graph are consistent with the function.
     ///
-    /// The `TargetIsa` argument is currently unused, but the verifier will soon be able to also
-    /// check ISA-dependent constraints.
-    pub fn verify<'a, ISA: Into<Option<&'a TargetIsa>>>(&self, _isa: ISA) -> verifier::Result {
-        verifier::verify_context(&self.func, &self.cfg, &self.domtree)
+    /// When a `TargetIsa` is given, also check that the function only uses the IL features
+    /// enabled by its settings.
+    pub fn verify<'a, ISA: Into<Option<&'a TargetIsa>>>(&self, isa: ISA) -> verifier::Result {
+        verifier::verify_context(&self.func, &self.cfg, &self.domtree)?;
+        match isa.into() {
+            Some(isa) => verifier::verify_features(&self.func, isa.flags()),
+            None => Ok(()),
+        }
     }
 
     /// Run the verifier only if the `enable_verifier` setting is true.
@@ -66,21 +207,441 @@ impl Context {
         }
     }
 
+    /// Compile the function.
+    ///
+    /// Run all the passes in the pipeline followed by branch relaxation, so the function is ready
+    /// for `emit_to_memory()`. Returns the size of the machine code in bytes.
+    ///
+    /// This is the same as calling `run_pipeline()` and `relax_branches()`.
+    pub fn compile(&mut self, isa: &TargetIsa) -> Result<CodeOffset, CtonError> {
+        self.run_pipeline(isa)?;
+        self.relax_branches(isa)
+    }
+
+    /// Compile the function and emit its machine code, relocations, and trap sites.
+    ///
+    /// This is the same as calling `compile()` and `emit_to_memory()` with a buffer of the
+    /// returned size.
+    pub fn compile_and_emit(&mut self, isa: &TargetIsa) -> Result<CompiledFunction, CtonError> {
+        let size = self.compile(isa)?;
+        let mut compiled = CompiledFunction {
+            code: vec![0; size as usize],
+            relocs: Vec::new(),
+            traps: Vec::new(),
+        };
+        let start = Instant::now();
+        self.emit_to_memory(&mut compiled.code,
+                            &mut compiled.relocs,
+                            &mut compiled.traps,
+                            isa)?;
+        self.timing.add("emit", start.elapsed());
+        Ok(compiled)
+    }
+
+    /// Compute the flow graph and run all the passes in the pipeline.
+    ///
+    /// After this, the function is ready for `relax_branches()`.
+    pub fn run_pipeline(&mut self, isa: &TargetIsa) -> CtonResult {
+        let pipeline = match self.pipeline {
+            Some(ref pipeline) => pipeline.clone(),
+            None => Pipeline::for_opt_level(isa.flags().opt_level()),
+        };
+        self.flowgraph();
+        if let Some(ref mut stats) = self.opcode_stats {
+            stats.record("input", &self.func);
+        }
+        for &pass in pipeline.passes() {
+            self.run_pass(pass, isa)?;
+            if let Some(ref mut stats) = self.opcode_stats {
+                stats.record(pass.name(), &self.func);
+            }
+        }
+        Ok(())
+    }
+
+    /// Run a single pass.
+    pub fn run_pass(&mut self, pass: Pass, isa: &TargetIsa) -> CtonResult {
+        match pass {
+            Pass::Preopt => self.preopt(isa),
+            Pass::Dce => self.dce(isa),
+            Pass::HeapChecks => self.heap_checks(isa),
+            Pass::Legalize => self.legalize(isa),
+            Pass::EbbLayout => self.ebb_layout(isa),
+            Pass::Regalloc => self.regalloc(isa),
+            Pass::PrologueEpilogue => self.prologue_epilogue(isa),
+            Pass::Compact => self.compact(isa),
+        }
+    }
+
+    /// Fold integer conversion chains and merge extensions into loads.
+    ///
+    /// The bypassed conversions are left for `dce()` to remove.
+    pub fn preopt(&mut self, isa: &TargetIsa) -> CtonResult {
+        let start = Instant::now();
+        do_preopt(&mut self.func, isa);
+        self.timing.add("preopt", start.elapsed());
+        self.verify_if(isa).map_err(|e| e.in_pass("preopt"))
+    }
+
+    /// Remove dead instructions.
+    pub fn dce(&mut self, isa: &TargetIsa) -> CtonResult {
+        let start = Instant::now();
+        do_dce(&mut self.func);
+        self.timing.add("dce", start.elapsed());
+        self.verify_if(isa).map_err(|e| e.in_pass("dce"))
+    }
+
+    /// Hoist loop invariant heap bounds checks and remove redundant ones.
+    ///
+    /// This must run before `legalize()` which expands the `heap_addr` instructions.
+    pub fn heap_checks(&mut self, isa: &TargetIsa) -> CtonResult {
+        require(self.state.flowgraph, "heap_checks", "flowgraph")?;
+        let start = Instant::now();
+        do_heap_checks(&mut self.func, &self.cfg, &self.domtree, isa);
+        self.timing.add("heap_checks", start.elapsed());
+        self.verify_if(isa).map_err(|e| e.in_pass("heap_checks"))
+    }
+
     /// Run the legalizer for `isa` on the function.
+    ///
+    /// The hooks in `legalize_hooks` run before the built-in legalization actions.
     pub fn legalize(&mut self, isa: &TargetIsa) -> CtonResult {
-        legalize_function(&mut self.func, &mut self.cfg, isa);
-        self.verify_if(isa)
+        require(self.state.flowgraph, "legalize", "flowgraph")?;
+        let start = Instant::now();
+        if let Some(ref mut trace) = self.legalize_trace {
+            trace.clear();
+        }
+        legalize_function(&mut self.func,
+                          &mut self.cfg,
+                          isa,
+                          &self.legalize_hooks,
+                          &mut self.diagnostics,
+                          self.legalize_trace.as_mut());
+        // Expanding `br_table` instructions can split EBBs.
+        self.domtree.compute(&self.func, &self.cfg);
+        self.timing.add("legalize", start.elapsed());
+        self.state.legalized = true;
+        self.verify_if(isa).map_err(|e| e.in_pass("legalize"))
+    }
+
+    /// Move cold EBBs to the end of the layout.
+    pub fn ebb_layout(&mut self, isa: &TargetIsa) -> CtonResult {
+        let start = Instant::now();
+        do_ebb_layout(&mut self.func);
+        self.timing.add("ebb_layout", start.elapsed());
+        self.verify_if(isa).map_err(|e| e.in_pass("ebb_layout"))
+    }
+
+    /// Renumber the EBBs, instructions, and values in the function densely.
+    ///
+    /// This is not part of the default pipelines, but it can be added to a pipeline that leaves a
+    /// lot of unused entities behind. The control flow graph and dominator tree are recomputed for
+    /// the new numbering.
+    pub fn compact(&mut self, isa: &TargetIsa) -> CtonResult {
+        let start = Instant::now();
+        do_compact(&mut self.func);
+        self.cfg.compute(&self.func);
+        self.domtree.compute(&self.func, &self.cfg);
+        self.timing.add("compact", start.elapsed());
+        self.verify_if(isa).map_err(|e| e.in_pass("compact"))
     }
 
     /// Recompute the control flow graph and dominator tree.
+    ///
+    /// This also resets `state` and `diagnostics` since it is the first pass run on a new function.
     pub fn flowgraph(&mut self) {
+        let start = Instant::now();
         self.cfg.compute(&self.func);
         self.domtree.compute(&self.func, &self.cfg);
+        self.timing.add("flowgraph", start.elapsed());
+        self.diagnostics.clear();
+        self.state = PassState {
+            flowgraph: true,
+            ..PassState::default()
+        };
     }
 
     /// Run the register allocator.
+    ///
+    /// The function must have been legalized since the register allocator gets its constraints
+    /// from the instruction encodings.
     pub fn regalloc(&mut self, isa: &TargetIsa) -> CtonResult {
-        self.regalloc
-            .run(isa, &mut self.func, &self.cfg, &self.domtree)
+        require(self.state.legalized, "regalloc", "legalize")?;
+        let start = Instant::now();
+        let result = self.regalloc
+            .run(isa, &mut self.func, &self.cfg, &self.domtree);
+        self.timing.add("regalloc", start.elapsed());
+        self.state.regalloc = result.is_ok();
+        result.map_err(|e| e.in_pass("regalloc"))
+    }
+
+    /// Insert the prologue and epilogue for `isa` after register allocation.
+    pub fn prologue_epilogue(&mut self, isa: &TargetIsa) -> CtonResult {
+        require(self.state.regalloc, "prologue_epilogue", "regalloc")?;
+        let start = Instant::now();
+        let result = isa.prologue_epilogue(&mut self.func);
+        self.timing.add("prologue_epilogue", start.elapsed());
+        result.map_err(|e| e.in_pass("prologue_epilogue"))?;
+        self.state.prologue_epilogue = true;
+        let size = frame_size(&self.func.stack_slots);
+        if size > LARGE_FRAME_SIZE {
+            self.diagnostics
+                .warn("prologue_epilogue",
+                      AnyEntity::Function,
+                      format!("stack frame of {} bytes exceeds 1 MB", size));
+        }
+        self.verify_if(isa)
+            .map_err(|e| e.in_pass("prologue_epilogue"))
+    }
+
+    /// Run the branch relaxation pass and return the final code size in bytes.
+    ///
+    /// This must be done after register allocation and the prologue and epilogue insertion. The
+    /// returned code size is the size of the buffer needed for `emit_to_memory()`.
+    pub fn relax_branches(&mut self, isa: &TargetIsa) -> Result<CodeOffset, CtonError> {
+        require(self.state.regalloc, "relax_branches", "regalloc")?;
+        let start = Instant::now();
+        let code_size = relax_branches(&mut self.func, isa);
+        self.timing.add("relax_branches", start.elapsed());
+        self.state.relaxed = true;
+        self.verify_if(isa)
+            .map_err(|e| e.in_pass("relax_branches"))?;
+        Ok(code_size)
+    }
+
+    /// Emit machine code directly into the buffer `mem`.
+    ///
+    /// The buffer must be at least as large as the code size returned by `compile()` or
+    /// `relax_branches()`.
+    /// Relocations are reported to `relocs`, and the offsets and codes of the machine instructions
+    /// that can trap are reported to `traps`.
+    pub fn emit_to_memory(&self,
+                          mem: &mut [u8],
+                          relocs: &mut RelocSink,
+                          traps: &mut TrapSink,
+                          isa: &TargetIsa)
+                          -> CtonResult {
+        require(self.state.relaxed, "emit_to_memory", "relax_branches")?;
+        let mut sink = MemoryCodeSink::with_endianness(mem, relocs, traps, isa.endianness());
+        isa.emit_function(&self.func, &mut sink);
+        Ok(())
+    }
+
+    /// Get the source location table for the compiled function.
+    ///
+    /// This can be called after `relax_branches()`. The records map code offsets to source
+    /// locations in a form suitable for building a DWARF line table.
+    pub fn source_locations(&self, isa: &TargetIsa) -> Vec<SourceLocRecord> {
+        source_locations(&self.func, isa)
+    }
+
+    /// Get the DWARF call frame information for the compiled function.
+    ///
+    /// This can be called after `relax_branches()`. Returns `None` if `isa` doesn't support unwind
+    /// information.
+    pub fn unwind_info(&self, isa: &TargetIsa) -> Option<UnwindInfo> {
+        unwind_info(&self.func, isa)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Context, PassState};
+    use binemit::{RelocRecord, TrapRecord};
+    use ir::{Function, Cursor, InstBuilder, ArgumentType, StackSlotData, StackSlotKind};
+    use ir::entities::AnyEntity;
+    use ir::types::I32;
+    use isa;
+    use result::{CtonError, ErrorKind};
+    use settings::{self, Configurable};
+
+    #[test]
+    fn pass_order() {
+        let isa_builder = isa::lookup("riscv").unwrap();
+        let isa = isa_builder.finish(settings::Flags::new(&settings::builder()));
+
+        let mut ctx = Context::new();
+        ctx.func = Function::new();
+        let ebb0 = ctx.func.dfg.make_ebb();
+        {
+            let dfg = &mut ctx.func.dfg;
+            let pos = &mut Cursor::new(&mut ctx.func.layout);
+            pos.insert_ebb(ebb0);
+            dfg.ins(pos).return_(&[]);
+        }
+
+        match ctx.regalloc(&*isa) {
+            Err(CtonError { kind: ErrorKind::MissingPass { pass, requires }, .. }) => {
+                assert_eq!(pass, "regalloc");
+                assert_eq!(requires, "legalize");
+            }
+            r => panic!("unexpected {:?}", r),
+        }
+        assert_eq!(ctx.legalize(&*isa).unwrap_err().to_string(),
+                   "Can't run legalize before flowgraph");
+
+        ctx.flowgraph();
+        ctx.legalize(&*isa).unwrap();
+        ctx.regalloc(&*isa).unwrap();
+        let mut code = [0; 16];
+        let mut relocs: Vec<RelocRecord> = Vec::new();
+        let mut traps: Vec<TrapRecord> = Vec::new();
+        assert_eq!(ctx.emit_to_memory(&mut code, &mut relocs, &mut traps, &*isa)
+                       .unwrap_err()
+                       .to_string(),
+                   "Can't run emit_to_memory before relax_branches");
+        assert_eq!(ctx.state,
+                   PassState {
+                       flowgraph: true,
+                       legalized: true,
+                       regalloc: true,
+                       ..PassState::default()
+                   });
+
+        // Starting over with a new function resets the state.
+        ctx.flowgraph();
+        assert!(!ctx.state.legalized);
+    }
+
+    #[test]
+    fn clear() {
+        let isa_builder = isa::lookup("riscv").unwrap();
+        let isa = isa_builder.finish(settings::Flags::new(&settings::builder()));
+
+        // Build a function directly in `ctx.func`, the way a JIT reusing the context would.
+        fn build(func: &mut Function) {
+            let ebb0 = func.dfg.make_ebb();
+            let arg = func.dfg.append_ebb_arg(ebb0, I32);
+            func.signature.argument_types.push(ArgumentType::new(I32));
+            func.signature.return_types.push(ArgumentType::new(I32));
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            let v = dfg.ins(pos).iadd_imm(arg, 3);
+            dfg.ins(pos).return_(&[v]);
+        }
+
+        let mut ctx = Context::new();
+        build(&mut ctx.func);
+        let size = ctx.compile(&*isa).unwrap();
+        let text = ctx.func.display(&*isa).to_string();
+        let usage = ctx.mem_usage();
+        assert!(usage.get("dfg.insts").unwrap() > 0);
+        assert!(usage.get("regalloc.liveness").unwrap() > 0);
+
+        // Clearing the context keeps all the memory.
+        ctx.clear();
+        assert_eq!(ctx.mem_usage().total(), usage.total());
+        assert_eq!(ctx.state, PassState::default());
+        assert_eq!(ctx.func.layout.entry_block(), None);
+        assert_eq!(ctx.func.dfg.num_insts(), 0);
+        assert!(ctx.func.signature.argument_types.is_empty());
+        assert!(ctx.func.locations.is_empty());
+
+        build(&mut ctx.func);
+        assert_eq!(ctx.compile(&*isa).unwrap(), size);
+        assert_eq!(ctx.func.display(&*isa).to_string(), text);
+    }
+
+    #[test]
+    fn two_functions() {
+        let mut flag_builder = settings::builder();
+        flag_builder.set_bool("is_64bit", true).unwrap();
+        let isa_builder = isa::lookup("intel").unwrap();
+        let isa = isa_builder.finish(settings::Flags::new(&flag_builder));
+
+        fn build(func: &mut Function) {
+            let ebb0 = func.dfg.make_ebb();
+            let ebb1 = func.dfg.make_ebb();
+            let arg = func.dfg.append_ebb_arg(ebb0, I32);
+            func.signature.argument_types.push(ArgumentType::new(I32));
+            func.signature.return_types.push(ArgumentType::new(I32));
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            dfg.ins(pos).brz(arg, ebb1, &[]);
+            let v = dfg.ins(pos).iadd_imm(arg, 3);
+            dfg.ins(pos).return_(&[v]);
+            pos.insert_ebb(ebb1);
+            dfg.ins(pos).return_(&[arg]);
+        }
+
+        let mut ctx = Context::new();
+        build(&mut ctx.func);
+        let size = ctx.compile(&*isa).unwrap();
+        let text = ctx.func.display(&*isa).to_string();
+
+        // The passes don't depend on `clear()` to forget the previous function.
+        ctx.func = Function::new();
+        build(&mut ctx.func);
+        assert_eq!(ctx.compile(&*isa).unwrap(), size);
+        assert_eq!(ctx.func.display(&*isa).to_string(), text);
+    }
+
+    #[test]
+    fn diagnostics() {
+        let mut flag_builder = settings::builder();
+        flag_builder.set_bool("is_64bit", true).unwrap();
+        let isa_builder = isa::lookup("intel").unwrap();
+        let isa = isa_builder.finish(settings::Flags::new(&flag_builder));
+
+        let mut ctx = Context::new();
+        ctx.func
+            .stack_slots
+            .push(StackSlotData::new(StackSlotKind::Local, 2 << 20));
+        let ebb0 = ctx.func.dfg.make_ebb();
+        {
+            let dfg = &mut ctx.func.dfg;
+            let pos = &mut Cursor::new(&mut ctx.func.layout);
+            pos.insert_ebb(ebb0);
+            dfg.ins(pos).return_(&[]);
+        }
+
+        ctx.compile(&*isa).unwrap();
+        assert_eq!(ctx.diagnostics.len(), 1);
+        let diag = ctx.diagnostics.iter().next().unwrap();
+        assert_eq!(diag.pass, "prologue_epilogue");
+        assert_eq!(diag.entity, AnyEntity::Function);
+        assert_eq!(diag.message, "stack frame of 2097152 bytes exceeds 1 MB");
+
+        // The warnings are cleared for the next function.
+        ctx.flowgraph();
+        assert!(ctx.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn emit_fallthrough() {
+        let mut flag_builder = settings::builder();
+        flag_builder.set_bool("is_64bit", true).unwrap();
+        let isa_builder = isa::lookup("intel").unwrap();
+        let isa = isa_builder.finish(settings::Flags::new(&flag_builder));
+
+        // The jump to the next EBB becomes a `fallthrough` without an encoding.
+        let mut ctx = Context::new();
+        let ebb0 = ctx.func.dfg.make_ebb();
+        let ebb1 = ctx.func.dfg.make_ebb();
+        let ebb2 = ctx.func.dfg.make_ebb();
+        let arg = ctx.func.dfg.append_ebb_arg(ebb0, I32);
+        ctx.func.signature.argument_types.push(ArgumentType::new(I32));
+        {
+            let dfg = &mut ctx.func.dfg;
+            let pos = &mut Cursor::new(&mut ctx.func.layout);
+            pos.insert_ebb(ebb0);
+            dfg.ins(pos).brz(arg, ebb2, &[]);
+            dfg.ins(pos).jump(ebb1, &[]);
+            pos.insert_ebb(ebb1);
+            dfg.ins(pos).return_(&[]);
+            pos.insert_ebb(ebb2);
+            dfg.ins(pos).return_(&[]);
+        }
+
+        let size = ctx.compile(&*isa).unwrap();
+        let mut code = vec![0; size as usize];
+        let mut relocs: Vec<RelocRecord> = Vec::new();
+        let mut traps: Vec<TrapRecord> = Vec::new();
+        ctx.emit_to_memory(&mut code, &mut relocs, &mut traps, &*isa)
+            .unwrap();
+        // The function ends with the two returns.
+        assert_eq!(&code[code.len() - 2..], &[0xc3, 0xc3]);
     }
 }
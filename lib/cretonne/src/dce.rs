@@ -0,0 +1,96 @@
+//! Dead code elimination.
+//!
+//! Remove instructions whose results are never used, as long as the instruction has no other
+//! effects. This is done with a worklist so a chain of dead instructions is removed in a single
+//! pass.
+
+use entity_map::EntityMap;
+use ir::{Function, DataFlowGraph, Inst, Value, ValueDef};
+
+/// Remove dead instructions from `func`.
+///
+/// The control flow graph is not affected since branches are never removed.
+pub fn do_dce(func: &mut Function) {
+    // Count the uses of every value in the layout.
+    let mut uses = EntityMap::<Value, u32>::new();
+    for ebb in func.layout.ebbs() {
+        for inst in func.layout.ebb_insts(ebb) {
+            for &arg in func.dfg.inst_args(inst) {
+                *uses.ensure(func.dfg.resolve_aliases(arg)) += 1;
+            }
+        }
+    }
+
+    let mut worklist: Vec<Inst> = func.layout
+        .ebbs()
+        .flat_map(|ebb| func.layout.ebb_insts(ebb))
+        .collect();
+    while let Some(inst) = worklist.pop() {
+        if func.layout.inst_ebb(inst).is_none() || !is_removable(&func.dfg, inst) ||
+           func.dfg
+               .inst_results(inst)
+               .iter()
+               .any(|&v| uses.get(v).map_or(false, |&n| n > 0)) {
+            continue;
+        }
+
+        func.layout.remove_inst(inst);
+        // The instructions computing our arguments may be dead now.
+        for &arg in func.dfg.inst_args(inst) {
+            let arg = func.dfg.resolve_aliases(arg);
+            uses[arg] -= 1;
+            if uses[arg] == 0 {
+                if let ValueDef::Res(def, _) = func.dfg.value_def(arg) {
+                    worklist.push(def);
+                }
+            }
+        }
+    }
+}
+
+/// Can `inst` be removed when its results are unused?
+fn is_removable(dfg: &DataFlowGraph, inst: Inst) -> bool {
+    let opcode = dfg[inst].opcode();
+    // Loads can trap on an invalid address.
+    if opcode.is_branch() || opcode.is_terminator() || opcode.is_call() || opcode.can_trap() ||
+       opcode.can_load() || opcode.can_store() || opcode.other_side_effects() {
+        return false;
+    }
+    // Instructions without results are only there for their side effects.
+    dfg.has_results(inst)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::do_dce;
+    use ir::{Function, Cursor, InstBuilder, ValueDef};
+    use ir::types::I32;
+
+    #[test]
+    fn chain() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let arg0 = func.dfg.append_ebb_arg(ebb0, I32);
+        let (v2, q) = {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+
+            // A dead chain, a dead division that can trap, and a live value.
+            let v0 = dfg.ins(pos).iconst(I32, 3);
+            let v1 = dfg.ins(pos).iadd(arg0, v0);
+            dfg.ins(pos).imul(v1, v1);
+            let q = dfg.ins(pos).udiv(arg0, v0);
+            let v2 = dfg.ins(pos).iadd_imm(arg0, 1);
+            dfg.ins(pos).return_(&[v2]);
+            (v2, q)
+        };
+
+        do_dce(&mut func);
+        let insts: Vec<_> = func.layout.ebb_insts(ebb0).collect();
+        // The constant is still used by the division.
+        assert_eq!(insts.len(), 4);
+        assert_eq!(func.dfg.value_def(q), ValueDef::Res(insts[1], 0));
+        assert_eq!(func.dfg.value_def(v2), ValueDef::Res(insts[2], 0));
+    }
+}
@@ -0,0 +1,121 @@
+//! Non-fatal diagnostics produced by the compilation passes.
+//!
+//! Some things that a pass notices while compiling a function are worth telling the embedder
+//! about without failing the compilation: A jump table that is mostly holes, a huge stack frame,
+//! or an instruction that had to be replaced by a library call. The passes run by a `Context`
+//! push these warnings into its `Diagnostics`, where the embedder can retrieve them after
+//! compiling the function.
+
+use ir::entities::AnyEntity;
+use std::fmt;
+use std::slice;
+
+/// A warning about a function produced by a compiler pass.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Diagnostic {
+    /// The name of the pass that produced the warning, like `"legalize"`.
+    pub pass: &'static str,
+    /// The entity the warning is about.
+    pub entity: AnyEntity,
+    /// Description of the problem.
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "warning: {}: {}: {}", self.pass, self.entity, self.message)
+    }
+}
+
+/// The warnings produced while compiling a function.
+#[derive(Clone, Debug, Default)]
+pub struct Diagnostics {
+    // Warnings in the order they were produced.
+    list: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    /// Create an empty set of diagnostics.
+    pub fn new() -> Diagnostics {
+        Diagnostics { list: Vec::new() }
+    }
+
+    /// Forget all the warnings.
+    pub fn clear(&mut self) {
+        self.list.clear();
+    }
+
+    /// Add a warning from `pass` about `entity`.
+    pub fn warn<E, S>(&mut self, pass: &'static str, entity: E, message: S)
+        where E: Into<AnyEntity>,
+              S: Into<String>
+    {
+        let diag = Diagnostic {
+            pass: pass,
+            entity: entity.into(),
+            message: message.into(),
+        };
+        warn!("{}", diag);
+        self.list.push(diag);
+    }
+
+    /// Get the warnings in the order they were produced.
+    pub fn iter(&self) -> slice::Iter<Diagnostic> {
+        self.list.iter()
+    }
+
+    /// Get the number of warnings.
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    /// Are there no warnings?
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+}
+
+impl<'a> IntoIterator for &'a Diagnostics {
+    type Item = &'a Diagnostic;
+    type IntoIter = slice::Iter<'a, Diagnostic>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Print one warning per line.
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for diag in &self.list {
+            writeln!(f, "{}", diag)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Diagnostics;
+    use entity_map::EntityRef;
+    use ir::{Inst, JumpTable};
+    use ir::entities::AnyEntity;
+
+    #[test]
+    fn warnings() {
+        let mut diags = Diagnostics::new();
+        assert!(diags.is_empty());
+        diags.warn("legalize", JumpTable::new(2), "jump table is 95% holes");
+        diags.warn("legalize", Inst::new(7), format!("{} replaced by a library call", "fadd"));
+        diags.warn("prologue_epilogue", AnyEntity::Function, "frame exceeds 1 MB");
+        assert_eq!(diags.len(), 3);
+        assert_eq!(diags.iter().next().unwrap().entity, JumpTable::new(2).into());
+        assert_eq!(diags.to_string(),
+                   "warning: legalize: jt2: jump table is 95% holes\n\
+                    warning: legalize: inst7: fadd replaced by a library call\n\
+                    warning: prologue_epilogue: function: frame exceeds 1 MB\n");
+
+        diags.clear();
+        assert!(diags.is_empty());
+    }
+}
@@ -138,6 +138,16 @@ impl DominatorTree {
         DominatorTree { nodes: EntityMap::new() }
     }
 
+    /// Clear the dominator tree, keeping the allocated memory.
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+    }
+
+    /// Get the number of bytes of heap memory allocated by this dominator tree.
+    pub fn mem_usage(&self) -> usize {
+        self.nodes.mem_usage()
+    }
+
     /// Allocate and compute a dominator tree.
     pub fn with_function(func: &Function, cfg: &ControlFlowGraph) -> DominatorTree {
         let mut domtree = DominatorTree::new();
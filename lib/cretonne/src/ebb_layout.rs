@@ -0,0 +1,83 @@
+//! EBB layout.
+//!
+//! Move cold EBBs to the end of the function so the hot code is contiguous. Since every EBB ends
+//! in a terminator, the layout order doesn't change the semantics of the function, but it decides
+//! which branches become fallthroughs during branch relaxation. Keeping the cold EBBs out of the
+//! way lets the hot path fall through and improves instruction cache density.
+//!
+//! Besides the cold hints set by the frontend in `Function::cold_ebbs`, any EBB that ends in a
+//! `trap` instruction is marked as cold.
+//!
+//! The entry block is never moved, and the relative order of the cold EBBs is preserved.
+
+use ir::{Function, Opcode};
+
+/// Mark trapping EBBs as cold and move all the cold EBBs to the end of the layout.
+///
+/// The control flow graph and dominator tree are not affected.
+pub fn do_ebb_layout(func: &mut Function) {
+    infer_cold_ebbs(func);
+
+    let entry = func.layout.entry_block();
+    let cold: Vec<_> = func.layout
+        .ebbs()
+        .filter(|&ebb| Some(ebb) != entry && func.is_cold(ebb))
+        .collect();
+    for ebb in cold {
+        func.layout.move_ebb_to_end(ebb);
+    }
+}
+
+/// Mark the EBBs that end in a `trap` instruction as cold.
+fn infer_cold_ebbs(func: &mut Function) {
+    let trapping: Vec<_> = func.layout
+        .ebbs()
+        .filter(|&ebb| {
+                    func.layout
+                        .last_inst(ebb)
+                        .map_or(false, |inst| func.dfg[inst].opcode() == Opcode::Trap)
+                })
+        .collect();
+    for ebb in trapping {
+        func.set_cold(ebb);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::do_ebb_layout;
+    use ir::{Function, Cursor, InstBuilder, Ebb};
+    use ir::types::I32;
+
+    #[test]
+    fn cold_ebbs() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        let ebb2 = func.dfg.make_ebb();
+        let ebb3 = func.dfg.make_ebb();
+        let arg = func.dfg.append_ebb_arg(ebb0, I32);
+        {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            dfg.ins(pos).brz(arg, ebb1, &[]);
+            dfg.ins(pos).jump(ebb2, &[]);
+            pos.insert_ebb(ebb1);
+            dfg.ins(pos).trap();
+            pos.insert_ebb(ebb2);
+            dfg.ins(pos).brnz(arg, ebb3, &[]);
+            dfg.ins(pos).return_(&[]);
+            pos.insert_ebb(ebb3);
+            dfg.ins(pos).return_(&[]);
+        }
+        // Set by the frontend.
+        func.set_cold(ebb2);
+
+        do_ebb_layout(&mut func);
+        assert!(func.is_cold(ebb1) && func.is_cold(ebb2));
+        assert!(!func.is_cold(ebb0) && !func.is_cold(ebb3));
+        let ebbs: Vec<Ebb> = func.layout.ebbs().collect();
+        assert_eq!(ebbs, [ebb0, ebb3, ebb1, ebb2]);
+    }
+}
@@ -126,6 +126,11 @@ impl<T: EntityRef> ListPool<T> {
         self.free.clear();
     }
 
+    /// Get the number of bytes of heap memory allocated by this pool.
+    pub fn mem_usage(&self) -> usize {
+        self.data.capacity() * mem::size_of::<T>() + self.free.capacity() * mem::size_of::<usize>()
+    }
+
     /// Read the length of a list field, if it exists.
     fn len_of(&self, list: &EntityList<T>) -> Option<usize> {
         let idx = list.index as usize;
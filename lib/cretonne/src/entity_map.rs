@@ -13,6 +13,7 @@
 use std::vec::Vec;
 use std::default::Default;
 use std::marker::PhantomData;
+use std::mem;
 use std::ops::{Index, IndexMut};
 
 /// A type wrapping a small integer index should implement `EntityRef` so it can be used as the key
@@ -67,6 +68,11 @@ impl<K, V> EntityMap<K, V>
         self.elems.clear()
     }
 
+    /// Get the number of bytes of heap memory allocated by this map.
+    pub fn mem_usage(&self) -> usize {
+        self.elems.capacity() * mem::size_of::<V>()
+    }
+
     /// Iterate over all the keys in this map.
     pub fn keys(&self) -> Keys<K> {
         Keys {
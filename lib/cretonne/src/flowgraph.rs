@@ -59,6 +59,18 @@ impl ControlFlowGraph {
         }
     }
 
+    /// Clear all data structures in this control flow graph, keeping the allocated memory.
+    pub fn clear(&mut self) {
+        self.entry_block = None;
+        self.data.clear();
+    }
+
+    /// Get the number of bytes of heap memory allocated by this control flow graph, not counting
+    /// the predecessor and successor lists.
+    pub fn mem_usage(&self) -> usize {
+        self.data.mem_usage()
+    }
+
     /// Allocate and compute the control flow graph for `func`.
     pub fn with_function(func: &Function) -> ControlFlowGraph {
         let mut cfg = ControlFlowGraph::new();
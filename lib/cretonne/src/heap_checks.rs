@@ -0,0 +1,159 @@
+//! Heap bounds check optimization.
+//!
+//! A WebAssembly translator emits a `heap_addr` instruction for every memory access, and each of
+//! them is expanded into a bounds check by the legalizer. Many of the checks are redundant:
+//!
+//! - A `heap_addr` is redundant when it is dominated by a `heap_addr` of the same heap address
+//!   covering at least as many bytes. The heap never shrinks, so the dominating check succeeding
+//!   implies that this one would succeed too, and it computes the same address.
+//! - A `heap_addr` in a loop header is loop invariant when its heap address is defined outside the
+//!   loop. If nothing with side effects precedes it in the loop header, it can be hoisted into the
+//!   loop preheader where it is only checked once.
+//!
+//! Growing a `dynamic` heap may move it to a new base address, and any call could grow the heap.
+//! Unless the `heap_style` setting is `static`, the checks in a function containing calls are only
+//! merged within an EBB when no call separates them, and they are not hoisted.
+//!
+//! This pass must run before the legalizer expands the `heap_addr` instructions.
+
+use dominator_tree::DominatorTree;
+use flowgraph::ControlFlowGraph;
+use ir::{Function, DataFlowGraph, Ebb, Inst, InstructionData, Opcode, Value, ValueDef};
+use isa::TargetIsa;
+use settings::HeapStyle;
+use std::collections::HashMap;
+
+/// Hoist loop invariant heap checks and remove the redundant ones in `func`.
+///
+/// The control flow graph and dominator tree are not affected.
+pub fn do_heap_checks(func: &mut Function,
+                      cfg: &ControlFlowGraph,
+                      domtree: &DominatorTree,
+                      isa: &TargetIsa) {
+    let fixed_base = isa.flags().heap_style() == HeapStyle::Static || !has_calls(func);
+
+    // Visit inner loops before the loops containing them so a check can be hoisted out of a loop
+    // nest one level at a time.
+    let postorder = cfg.postorder_ebbs();
+    if fixed_base {
+        for &ebb in &postorder {
+            hoist_loop_invariants(func, cfg, domtree, ebb);
+        }
+    }
+
+    // Visit the EBBs in a reverse post-order so dominating checks are seen first.
+    let mut checks: HashMap<Value, Vec<Inst>> = HashMap::new();
+    for &ebb in postorder.iter().rev() {
+        if !fixed_base {
+            checks.clear();
+        }
+        let insts: Vec<Inst> = func.layout.ebb_insts(ebb).collect();
+        for inst in insts {
+            if !fixed_base && func.dfg[inst].opcode().makes_call() {
+                checks.clear();
+                continue;
+            }
+            let (addr, size) = match heap_check(&func.dfg, inst) {
+                Some(check) => check,
+                None => continue,
+            };
+            let dominating = checks
+                .get(&addr)
+                .and_then(|insts| {
+                    insts
+                        .iter()
+                        .cloned()
+                        .find(|&other| {
+                                  heap_check(&func.dfg, other).unwrap().1 >= size &&
+                                  domtree.dominates(other, inst, &func.layout)
+                              })
+                });
+            match dominating {
+                Some(other) => {
+                    let result = func.dfg.first_result(inst);
+                    let replacement = func.dfg.first_result(other);
+                    func.dfg.clear_results(inst);
+                    func.dfg.change_to_alias(result, replacement);
+                    func.layout.remove_inst(inst);
+                }
+                None => checks.entry(addr).or_insert_with(Vec::new).push(inst),
+            }
+        }
+    }
+}
+
+/// Does `func` contain any calls?
+fn has_calls(func: &Function) -> bool {
+    func.layout
+        .ebbs()
+        .flat_map(|ebb| func.layout.ebb_insts(ebb))
+        .any(|inst| func.dfg[inst].opcode().makes_call())
+}
+
+/// Get the heap address and size checked by `inst` if it is a `heap_addr` instruction.
+fn heap_check(dfg: &DataFlowGraph, inst: Inst) -> Option<(Value, u32)> {
+    match dfg[inst] {
+        InstructionData::HeapLoad { opcode: Opcode::HeapAddr, arg, offset } => {
+            Some((dfg.resolve_aliases(arg), offset.into()))
+        }
+        _ => None,
+    }
+}
+
+/// If `header` is a loop header with a preheader, move its loop invariant heap checks there.
+fn hoist_loop_invariants(func: &mut Function,
+                         cfg: &ControlFlowGraph,
+                         domtree: &DominatorTree,
+                         header: Ebb) {
+    // The preheader is the only predecessor outside the loop. The checks are inserted before its
+    // final jump, so they are only executed when the loop is entered.
+    let mut preheader_jump = None;
+    let mut back_edges = 0;
+    for &(_, branch) in cfg.get_predecessors(header) {
+        if domtree.ebb_dominates(header, branch, &func.layout) {
+            back_edges += 1;
+        } else if preheader_jump.is_some() {
+            return;
+        } else {
+            preheader_jump = Some(branch);
+        }
+    }
+    let jump = match preheader_jump {
+        Some(jump) if back_edges > 0 => jump,
+        _ => return,
+    };
+    let preheader = func.layout.inst_ebb(jump).unwrap();
+    if func.dfg[jump].opcode() != Opcode::Jump || func.layout.last_inst(preheader) != Some(jump) {
+        return;
+    }
+
+    // Instructions without side effects can be moved past, but the header must be entered
+    // before anything observable happens, or the hoisted check could trap too early.
+    let insts: Vec<Inst> = func.layout.ebb_insts(header).collect();
+    for inst in insts {
+        if let Some((addr, _)) = heap_check(&func.dfg, inst) {
+            if is_available(func, domtree, addr, jump) {
+                func.layout.remove_inst(inst);
+                func.layout.insert_inst(inst, jump);
+                continue;
+            }
+        }
+        let opcode = func.dfg[inst].opcode();
+        if opcode.is_branch() || opcode.is_terminator() || opcode.makes_call() ||
+           opcode.can_trap() || opcode.can_load() || opcode.can_store() ||
+           opcode.other_side_effects() {
+            return;
+        }
+    }
+}
+
+/// Is `value` defined at the instruction `inst`?
+fn is_available(func: &Function, domtree: &DominatorTree, value: Value, inst: Inst) -> bool {
+    match func.dfg.value_def(value) {
+        ValueDef::Res(def, _) => {
+            func.layout.inst_ebb(def).is_some() && def != inst &&
+            domtree.dominates(def, inst, &func.layout)
+        }
+        ValueDef::Arg(ebb, _) => domtree.ebb_dominates(ebb, inst, &func.layout),
+    }
+}
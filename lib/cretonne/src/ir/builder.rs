@@ -5,10 +5,11 @@
 
 use ir::types;
 use ir::{InstructionData, DataFlowGraph, Cursor};
-use ir::{Opcode, Type, Inst, Value, Ebb, JumpTable, SigRef, FuncRef, StackSlot, ValueList,
-         MemFlags};
+use ir::{Opcode, Type, Inst, Value, Ebb, JumpTable, GlobalVar, Table, SigRef, FuncRef,
+         StackSlot, ValueList, MemFlags, MemOrder};
 use ir::immediates::{Imm64, Uimm8, Ieee32, Ieee64, Offset32, Uoffset32};
 use ir::condcodes::{IntCC, FloatCC};
+use isa::RegUnit;
 
 /// Base trait for instruction builders.
 ///
@@ -22,6 +23,9 @@ pub trait InstBuilderBase<'f>: Sized {
     /// Get an immutable reference to the data flow graph that will hold the constructed
     /// instructions.
     fn data_flow_graph(&self) -> &DataFlowGraph;
+
+    /// Get a mutable reference to the data flow graph that will hold the constructed
+    /// instructions.
     fn data_flow_graph_mut(&mut self) -> &mut DataFlowGraph;
 
     /// Insert an instruction and return a reference to it, consuming the builder.
@@ -6,7 +6,8 @@ use ir::extfunc::ExtFuncData;
 use ir::instructions::{Opcode, InstructionData, CallInfo};
 use ir::layout::Cursor;
 use ir::types;
-use ir::{Ebb, Inst, Value, Type, SigRef, Signature, FuncRef, ValueList, ValueListPool};
+use ir::{Ebb, Inst, Value, Type, SigRef, Signature, FuncRef, ValueList, ValueListPool, ValueMap};
+use mem_usage::MemUsage;
 use write::write_operands;
 
 use std::fmt;
@@ -79,6 +80,30 @@ impl DataFlowGraph {
         }
     }
 
+    /// Clear everything, keeping the allocated memory.
+    pub fn clear(&mut self) {
+        self.insts.clear();
+        self.results.clear();
+        self.ebbs.clear();
+        self.value_lists.clear();
+        self.values.clear();
+        self.signatures.clear();
+        self.ext_funcs.clear();
+    }
+
+    /// Add the heap memory allocated by the data flow graph to `usage`.
+    ///
+    /// The `signatures` and `ext_funcs` tables are reported together as `"dfg.other"`.
+    pub fn mem_usage(&self, usage: &mut MemUsage) {
+        usage.add("dfg.insts", self.insts.mem_usage());
+        usage.add("dfg.results", self.results.mem_usage());
+        usage.add("dfg.ebbs", self.ebbs.mem_usage());
+        usage.add("dfg.values", self.values.mem_usage());
+        usage.add("dfg.value_lists", self.value_lists.mem_usage());
+        usage.add("dfg.other",
+                  self.signatures.mem_usage() + self.ext_funcs.mem_usage());
+    }
+
     /// Get the total number of instructions created in this function, whether they are currently
     /// inserted in the layout or not.
     ///
@@ -269,6 +294,16 @@ pub enum ValueDef {
     Arg(Ebb, usize),
 }
 
+impl ValueDef {
+    /// Unwrap the instruction where the value was defined, or panic.
+    pub fn unwrap_inst(&self) -> Inst {
+        match *self {
+            ValueDef::Res(inst, _) => inst,
+            ValueDef::Arg(..) => panic!("Value is not an instruction result"),
+        }
+    }
+}
+
 // Internal table storage for extended values.
 #[derive(Clone, Debug)]
 enum ValueData {
@@ -297,6 +332,43 @@ impl DataFlowGraph {
         self.insts.push(data)
     }
 
+    /// Create a copy of `inst` with its arguments rewritten through `map`.
+    ///
+    /// The copy gets its own value list and new result values with the same types as the
+    /// results of `inst`. Each argument is replaced by the value it is mapped to in `map`. An
+    /// unmapped alias is looked up again after resolving it, and arguments that still aren't
+    /// mapped are kept. The new results are added to `map`, so later
+    /// copies of instructions using the results of `inst` will use the new results instead.
+    ///
+    /// The new instruction is not inserted in the layout.
+    pub fn clone_inst(&mut self, inst: Inst, map: &mut ValueMap) -> Inst {
+        let mut data = self.insts[inst].clone();
+        if let Some(args) = data.take_value_list() {
+            // The cloned `data` shares its value list with `inst`. Give the copy its own list.
+            let mut copy = ValueList::new();
+            copy.extend(args.as_slice(&self.value_lists).to_vec(),
+                        &mut self.value_lists);
+            data.put_value_list(copy);
+        }
+        let new_inst = self.make_inst(data);
+
+        for i in 0..self.inst_args(new_inst).len() {
+            let arg = self.inst_args(new_inst)[i];
+            self.inst_args_mut(new_inst)[i] = match map.get(arg) {
+                Some(new) => new,
+                None => map.map(self.resolve_aliases(arg)),
+            };
+        }
+
+        for i in 0..self.inst_results(inst).len() {
+            let old = self.inst_results(inst)[i];
+            let ty = self.value_type(old);
+            let new = self.append_result(new_inst, ty);
+            map.insert(old, new);
+        }
+        new_inst
+    }
+
     /// Get the instruction reference that will be assigned to the next instruction created by
     /// `make_inst`.
     ///
@@ -356,6 +428,30 @@ impl DataFlowGraph {
         &mut self.inst_args_mut(inst)[fixed_args..]
     }
 
+    /// Append a new value argument to the variable arguments of `inst`.
+    ///
+    /// This is used to pass an additional argument to the destination of a branch. Panics if the
+    /// instruction doesn't have a value list.
+    pub fn append_inst_arg(&mut self, inst: Inst, new_arg: Value) {
+        let mut args = self.insts[inst]
+            .take_value_list()
+            .expect("the instruction doesn't have variable arguments");
+        args.push(new_arg, &mut self.value_lists);
+        self.insts[inst].put_value_list(args)
+    }
+
+    /// Remove the value argument at `index` in `inst_args(inst)`.
+    ///
+    /// The following arguments are moved down one position. Panics if the instruction doesn't
+    /// have a value list.
+    pub fn remove_inst_arg(&mut self, inst: Inst, index: usize) {
+        let mut args = self.insts[inst]
+            .take_value_list()
+            .expect("the instruction doesn't have a value list");
+        args.remove(index, &mut self.value_lists);
+        self.insts[inst].put_value_list(args)
+    }
+
     /// Create result values for an instruction that produces multiple results.
     ///
     /// Instructions that produce no result values only need to be created with `make_inst`,
@@ -643,6 +739,27 @@ impl DataFlowGraph {
         new_arg
     }
 
+    /// Remove the EBB argument `arg` from its EBB.
+    ///
+    /// The following arguments are moved down one position, and the removed value is left
+    /// detached, so it should probably be changed into something else.
+    pub fn remove_ebb_arg(&mut self, arg: Value) {
+        let (ebb, num) = if let ValueData::Arg { num, ebb, .. } = self.values[arg] {
+            (ebb, num)
+        } else {
+            panic!("{} must be an EBB argument", arg);
+        };
+        self.ebbs[ebb]
+            .args
+            .remove(num as usize, &mut self.value_lists);
+        for index in num as usize..self.num_ebb_args(ebb) {
+            let moved = self.ebbs[ebb].args.as_slice(&self.value_lists)[index];
+            if let ValueData::Arg { ref mut num, .. } = self.values[moved] {
+                *num -= 1;
+            }
+        }
+    }
+
     /// Detach all the arguments from `ebb` and return them as a `ValueList`.
     ///
     /// This is a quite low-level operation. Sensible things to do with the detached EBB arguments
@@ -693,7 +810,7 @@ impl<'a> fmt::Display for DisplayInst<'a> {
         } else {
             write!(f, "{}.{}", inst.opcode(), typevar)?;
         }
-        write_operands(f, dfg, self.1)
+        write_operands(f, dfg, None, self.1)
     }
 }
 
@@ -778,6 +895,29 @@ mod tests {
         assert_eq!(dfg.ebb_args(ebb), &[arg2, arg3, arg1]);
     }
 
+    #[test]
+    fn remove_ebb_arguments() {
+        let mut dfg = DataFlowGraph::new();
+
+        let ebb = dfg.make_ebb();
+        let arg1 = dfg.append_ebb_arg(ebb, types::F32);
+        let arg2 = dfg.append_ebb_arg(ebb, types::I16);
+        let arg3 = dfg.append_ebb_arg(ebb, types::F32);
+
+        dfg.remove_ebb_arg(arg2);
+        assert_eq!(dfg.ebb_args(ebb), &[arg1, arg3]);
+        assert!(!dfg.value_is_attached(arg2));
+        assert_eq!(dfg.value_def(arg1), ValueDef::Arg(ebb, 0));
+        assert_eq!(dfg.value_def(arg3), ValueDef::Arg(ebb, 1));
+
+        dfg.remove_ebb_arg(arg1);
+        assert_eq!(dfg.ebb_args(ebb), &[arg3]);
+        assert_eq!(dfg.value_def(arg3), ValueDef::Arg(ebb, 0));
+
+        dfg.change_to_alias(arg1, arg3);
+        assert_eq!(dfg.resolve_aliases(arg1), arg3);
+    }
+
     #[test]
     fn replace_ebb_arguments() {
         let mut dfg = DataFlowGraph::new();
@@ -851,4 +991,61 @@ mod tests {
         // But this goes through both copies and aliases.
         assert_eq!(dfg.resolve_copies(c3), c2);
     }
+
+    #[test]
+    fn clone_inst() {
+        use ir::{InstBuilder, ValueMap};
+
+        let mut func = Function::new();
+        let dfg = &mut func.dfg;
+        let ebb0 = dfg.make_ebb();
+        let ebb1 = dfg.make_ebb();
+        let arg0 = dfg.append_ebb_arg(ebb0, types::I32);
+        let pos = &mut Cursor::new(&mut func.layout);
+        pos.insert_ebb(ebb0);
+
+        let v1 = dfg.ins(pos).iadd_imm(arg0, 1);
+        let (s, c) = dfg.ins(pos).iadd_cout(v1, arg0);
+        dfg.ins(pos).jump(ebb1, &[v1, s]);
+        let insts: Vec<Inst> = pos.layout.ebb_insts(ebb0).collect();
+
+        // Duplicate the EBB body with a new argument.
+        let arg1 = dfg.append_ebb_arg(ebb1, types::I32);
+        let mut map = ValueMap::new();
+        map.insert(arg0, arg1);
+        let copies: Vec<Inst> = insts.iter().map(|&inst| dfg.clone_inst(inst, &mut map)).collect();
+
+        let v1_copy = map.get(v1).unwrap();
+        let s_copy = map.get(s).unwrap();
+        let c_copy = map.get(c).unwrap();
+        assert_eq!(dfg.value_type(c_copy), dfg.value_type(c));
+        assert_eq!(dfg.value_def(c_copy), ValueDef::Res(copies[1], 1));
+        assert_eq!(dfg.inst_args(copies[0]), &[arg1]);
+        assert_eq!(dfg.inst_args(copies[1]), &[v1_copy, arg1]);
+        assert_eq!(dfg.inst_args(copies[2]), &[v1_copy, s_copy]);
+        assert_eq!(dfg.display_inst(copies[2]).to_string(),
+                   format!("jump ebb1({}, {})", v1_copy, s_copy));
+
+        // The copy has its own value list.
+        dfg.inst_args_mut(copies[2])[0] = arg1;
+        assert_eq!(dfg.inst_args(insts[2]), &[v1, s]);
+
+        // A mapped alias is used as is, and an unmapped alias is resolved first.
+        let x = dfg.ins(pos).iconst(types::I32, 7);
+        if let ValueDef::Res(inst, _) = dfg.value_def(x) {
+            dfg.clear_results(inst);
+        }
+        dfg.change_to_alias(x, arg0);
+        dfg.ins(pos).iadd(x, x);
+        let add = pos.prev_inst().unwrap();
+
+        map.insert(x, v1_copy);
+        let add_copy = dfg.clone_inst(add, &mut map);
+        assert_eq!(dfg.inst_args(add_copy), &[v1_copy, v1_copy]);
+
+        map.clear();
+        map.insert(arg0, arg1);
+        let add_copy = dfg.clone_inst(add, &mut map);
+        assert_eq!(dfg.inst_args(add_copy), &[arg1, arg1]);
+    }
 }
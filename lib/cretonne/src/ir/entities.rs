@@ -74,7 +74,7 @@ impl Ebb {
 }
 
 /// An opaque reference to an SSA value.
-#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, PartialOrd, Ord)]
 pub struct Value(u32);
 entity_impl!(Value, "v");
 
@@ -107,6 +107,16 @@ entity_impl!(StackSlot, "ss");
 pub struct JumpTable(u32);
 entity_impl!(JumpTable, "jt");
 
+/// A reference to a global variable.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct GlobalVar(u32);
+entity_impl!(GlobalVar, "gv");
+
+/// An opaque reference to a table.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Table(u32);
+entity_impl!(Table, "table");
+
 /// A reference to an external function.
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
 pub struct FuncRef(u32);
@@ -132,6 +142,10 @@ pub enum AnyEntity {
     StackSlot(StackSlot),
     /// A jump table.
     JumpTable(JumpTable),
+    /// A global variable.
+    GlobalVar(GlobalVar),
+    /// A table.
+    Table(Table),
     /// An external function.
     FuncRef(FuncRef),
     /// A function call signature.
@@ -147,6 +161,8 @@ impl Display for AnyEntity {
             AnyEntity::Value(r) => r.fmt(fmt),
             AnyEntity::StackSlot(r) => r.fmt(fmt),
             AnyEntity::JumpTable(r) => r.fmt(fmt),
+            AnyEntity::GlobalVar(r) => r.fmt(fmt),
+            AnyEntity::Table(r) => r.fmt(fmt),
             AnyEntity::FuncRef(r) => r.fmt(fmt),
             AnyEntity::SigRef(r) => r.fmt(fmt),
         }
@@ -183,6 +199,18 @@ impl From<JumpTable> for AnyEntity {
     }
 }
 
+impl From<GlobalVar> for AnyEntity {
+    fn from(r: GlobalVar) -> AnyEntity {
+        AnyEntity::GlobalVar(r)
+    }
+}
+
+impl From<Table> for AnyEntity {
+    fn from(r: Table) -> AnyEntity {
+        AnyEntity::Table(r)
+    }
+}
+
 impl From<FuncRef> for AnyEntity {
     fn from(r: FuncRef) -> AnyEntity {
         AnyEntity::FuncRef(r)
@@ -31,6 +31,9 @@ pub struct Signature {
     /// This can be computed from the legalized `argument_types` array as the maximum (offset plus
     /// byte size) of the `ArgumentLoc::Stack(offset)` argument.
     pub argument_bytes: Option<u32>,
+
+    /// Calling convention.
+    pub call_conv: CallConv,
 }
 
 impl Signature {
@@ -40,9 +43,18 @@ impl Signature {
             argument_types: Vec::new(),
             return_types: Vec::new(),
             argument_bytes: None,
+            call_conv: CallConv::SystemV,
         }
     }
 
+    /// Clear the signature so it is identical to a fresh one returned by `new()`.
+    pub fn clear(&mut self) {
+        self.argument_types.clear();
+        self.return_types.clear();
+        self.argument_bytes = None;
+        self.call_conv = CallConv::SystemV;
+    }
+
     /// Compute the size of the stack arguments and mark signature as legalized.
     ///
     /// Even if there are no stack arguments, this will set `argument_types` to `Some(0)` instead
@@ -94,6 +106,9 @@ impl<'a> fmt::Display for DisplaySignature<'a> {
             write!(f, " -> ")?;
             write_list(f, &self.0.return_types, self.1)?;
         }
+        if self.0.call_conv != CallConv::SystemV {
+            write!(f, " {}", self.0.call_conv)?;
+        }
         Ok(())
     }
 }
@@ -104,6 +119,39 @@ impl fmt::Display for Signature {
     }
 }
 
+/// Calling convention identifiers.
+///
+/// The calling convention determines which registers are used to pass arguments and return
+/// values, and which registers are preserved across calls. Only the Intel ISA implements more than
+/// one convention. The other ISAs always use their System V-style native convention.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum CallConv {
+    /// The System V convention used by Unix-like systems. This is the default.
+    SystemV,
+    /// The Windows x64 convention, also known as "fastcall".
+    WindowsFastcall,
+}
+
+impl fmt::Display for CallConv {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+                        CallConv::SystemV => "system_v",
+                        CallConv::WindowsFastcall => "windows_fastcall",
+                    })
+    }
+}
+
+impl FromStr for CallConv {
+    type Err = ();
+    fn from_str(s: &str) -> Result<CallConv, ()> {
+        match s {
+            "system_v" => Ok(CallConv::SystemV),
+            "windows_fastcall" => Ok(CallConv::WindowsFastcall),
+            _ => Err(()),
+        }
+    }
+}
+
 /// Function argument or return value type.
 ///
 /// This describes the value type being passed to or from a function along with flags that affect
@@ -236,10 +284,24 @@ pub enum ArgumentPurpose {
     /// Some calling conventions have registers that must be saved by the callee. These registers
     /// are represented as `CalleeSaved` arguments and return values.
     CalleeSaved,
+
+    /// The stack limit.
+    ///
+    /// The function prologue traps if the stack frame would extend below this address. This is
+    /// an ordinary argument provided by the caller, but it is only used by the prologue.
+    StackLimit,
+
+    /// A VM context pointer.
+    ///
+    /// This is a pointer to the instance data of a runtime such as a WebAssembly virtual
+    /// machine. It is an ordinary pointer argument provided by the caller, but frontends use the
+    /// purpose to find it among the user program arguments.
+    VMContext,
 }
 
 /// Text format names of the `ArgumentPurpose` variants.
-static PURPOSE_NAMES: [&'static str; 5] = ["normal", "sret", "link", "fp", "csr"];
+static PURPOSE_NAMES: [&'static str; 7] = ["normal", "sret", "link", "fp", "csr", "stack_limit",
+                                            "vmctx"];
 
 impl fmt::Display for ArgumentPurpose {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -256,6 +318,8 @@ impl FromStr for ArgumentPurpose {
             "link" => Ok(ArgumentPurpose::Link),
             "fp" => Ok(ArgumentPurpose::FramePointer),
             "csr" => Ok(ArgumentPurpose::CalleeSaved),
+            "stack_limit" => Ok(ArgumentPurpose::StackLimit),
+            "vmctx" => Ok(ArgumentPurpose::VMContext),
             _ => Err(()),
         }
     }
@@ -270,10 +334,19 @@ pub struct ExtFuncData {
     pub name: FunctionName,
     /// Call signature of function.
     pub signature: SigRef,
+    /// Will this function be placed near the caller?
+    ///
+    /// A colocated function is defined in the same module as the caller, and it is close enough
+    /// to be reached with PC-relative calls and addresses. This makes it possible to avoid the
+    /// PLT and GOT indirections in position-independent code.
+    pub colocated: bool,
 }
 
 impl fmt::Display for ExtFuncData {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.colocated {
+            write!(f, "colocated ")?;
+        }
         write!(f, "{} {}", self.signature, self.name)
     }
 }
@@ -299,13 +372,22 @@ mod tests {
                            ArgumentPurpose::StructReturn,
                            ArgumentPurpose::Link,
                            ArgumentPurpose::FramePointer,
-                           ArgumentPurpose::CalleeSaved];
+                           ArgumentPurpose::CalleeSaved,
+                           ArgumentPurpose::StackLimit,
+                           ArgumentPurpose::VMContext];
         for (&e, &n) in all_purpose.iter().zip(PURPOSE_NAMES.iter()) {
             assert_eq!(e.to_string(), n);
             assert_eq!(Ok(e), n.parse());
         }
     }
 
+    #[test]
+    fn call_conv() {
+        for &cc in &[CallConv::SystemV, CallConv::WindowsFastcall] {
+            assert_eq!(Ok(cc), cc.to_string().parse());
+        }
+    }
+
     #[test]
     fn signatures() {
         let mut sig = Signature::new();
@@ -333,5 +415,10 @@ mod tests {
 
         // Writing ABI-annotated signatures.
         assert_eq!(sig.to_string(), "(i32 [24], i32x4 [8]) -> f32, b8");
+
+        // Only non-default calling conventions are written.
+        sig.call_conv = CallConv::WindowsFastcall;
+        assert_eq!(sig.to_string(),
+                   "(i32 [24], i32x4 [8]) -> f32, b8 windows_fastcall");
     }
 }
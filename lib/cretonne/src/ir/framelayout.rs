@@ -0,0 +1,87 @@
+//! Frame layout changes.
+//!
+//! The function prologue and epilogue change where the caller's frame and the saved registers can
+//! be found. These changes are recorded while the prologue and epilogue are inserted so they can
+//! be turned into unwind information like DWARF call frame information (CFI) after binary
+//! emission.
+
+use ir::Inst;
+use isa::RegUnit;
+
+/// A change in the frame layout.
+///
+/// Offsets are in bytes relative to the canonical frame address (CFA) which is the value of the
+/// stack pointer in the caller before the call instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameLayoutChange {
+    /// The CFA is at `offset` bytes above the value of the register `reg`.
+    CallFrameAddressAt {
+        /// The register the CFA is computed from, usually the stack pointer.
+        reg: RegUnit,
+        /// Offset added to the register value.
+        offset: i32,
+    },
+
+    /// The callee-saved register `reg` is saved in the stack frame at `cfa_offset`.
+    RegAt {
+        /// The saved register.
+        reg: RegUnit,
+        /// Offset of the save slot from the CFA.
+        cfa_offset: i32,
+    },
+
+    /// The return address is saved in the stack frame at `cfa_offset`.
+    ReturnAddressAt {
+        /// Offset of the return address from the CFA.
+        cfa_offset: i32,
+    },
+
+    /// The register `reg` has been restored to the value it had on entry.
+    Restore {
+        /// The restored register.
+        reg: RegUnit,
+    },
+
+    /// Remember the current frame layout so it can be restored after an epilogue.
+    RememberState,
+
+    /// Return to the frame layout saved by the last `RememberState`.
+    RestoreState,
+}
+
+/// The frame layout of a function.
+///
+/// The changes are recorded in the order they were made, and each change takes effect after its
+/// instruction. Functions with several returns have one epilogue per return, so the epilogue
+/// changes are bracketed by `RememberState` and `RestoreState` changes.
+#[derive(Clone, Debug, Default)]
+pub struct FrameLayout {
+    /// The frame layout on entry to the function, before the first instruction.
+    pub initial: Vec<FrameLayoutChange>,
+
+    /// The changes made by the prologue and epilogue instructions.
+    pub changes: Vec<(Inst, FrameLayoutChange)>,
+}
+
+impl FrameLayout {
+    /// Create an empty frame layout.
+    pub fn new() -> FrameLayout {
+        FrameLayout::default()
+    }
+
+    /// Clear all the recorded changes.
+    pub fn clear(&mut self) {
+        self.initial.clear();
+        self.changes.clear();
+    }
+
+    /// Has the frame layout been recorded?
+    pub fn is_empty(&self) -> bool {
+        self.initial.is_empty()
+    }
+
+    /// Record `change` taking effect after `inst`.
+    pub fn push(&mut self, inst: Inst, change: FrameLayoutChange) {
+        self.changes.push((inst, change));
+    }
+}
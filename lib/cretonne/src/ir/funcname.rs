@@ -18,6 +18,11 @@ impl FunctionName {
     pub fn new<S: Into<String>>(s: S) -> FunctionName {
         FunctionName(s.into())
     }
+
+    /// Get the name as a string slice, without any quoting.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
 }
 
 fn is_id_start(c: char) -> bool {
@@ -3,11 +3,14 @@
 //! The `Function` struct defined in this module owns all of its extended basic blocks and
 //! instructions.
 
-use binemit::CodeOffset;
+use binemit::{CodeOffset, code_size_estimate};
 use entity_map::{EntityMap, PrimaryEntityData};
 use ir::{FunctionName, Signature, Value, Inst, Ebb, StackSlot, StackSlotData, JumpTable,
-         JumpTableData, ValueLoc, DataFlowGraph, Layout};
-use isa::{TargetIsa, Encoding};
+         JumpTableData, GlobalVar, GlobalVarData, Table, TableData, ValueLoc, DataFlowGraph, Layout, FrameLayout,
+         SourceLoc, Type, ValueDef};
+use ir::instructions::BranchInfo;
+use isa::{TargetIsa, Encoding, EncInfo};
+use mem_usage::MemUsage;
 use std::fmt::{self, Display, Debug, Formatter};
 use write::write_function;
 
@@ -29,6 +32,12 @@ pub struct Function {
     /// Jump tables used in this function.
     pub jump_tables: EntityMap<JumpTable, JumpTableData>,
 
+    /// Global variables referenced by this function.
+    pub global_vars: EntityMap<GlobalVar, GlobalVarData>,
+
+    /// Tables referenced by this function.
+    pub tables: EntityMap<Table, TableData>,
+
     /// Data flow graph containing the primary definition of all instructions, EBBs and values.
     pub dfg: DataFlowGraph,
 
@@ -42,16 +51,43 @@ pub struct Function {
     /// Location assigned to every value.
     pub locations: EntityMap<Value, ValueLoc>,
 
+    /// Source locations.
+    ///
+    /// Track the original source location for each instruction. The source locations are not
+    /// interpreted by Cretonne, only preserved.
+    pub srclocs: EntityMap<Inst, SourceLoc>,
+
+    /// EBBs that are expected to execute rarely, like trap and slow paths.
+    ///
+    /// The cold hints can be set by the frontend, and the `ebb_layout` pass adds EBBs ending in a
+    /// `trap` instruction. Cold EBBs are moved to the end of the layout. Use `is_cold()` and
+    /// `set_cold()` to access this map.
+    pub cold_ebbs: EntityMap<Ebb, bool>,
+
     /// Code offsets of the EBB headers.
     ///
     /// This information is only transiently available after the `binemit::relax_branches` function
     /// computes it, and it can easily be recomputed by calling that function. It is not included
     /// in the textual IL format.
     pub offsets: EntityMap<Ebb, CodeOffset>,
+
+    /// Code offsets of the jump tables.
+    ///
+    /// The jump tables are emitted in a read-only data area after the code. Like `offsets`, this
+    /// is computed by `binemit::relax_branches`.
+    pub jt_offsets: EntityMap<JumpTable, CodeOffset>,
+
+    /// Frame layout changes made by the prologue and epilogue.
+    ///
+    /// This is recorded by `TargetIsa::prologue_epilogue()` and used to generate unwind
+    /// information. It is not included in the textual IL format.
+    pub frame_layout: FrameLayout,
 }
 
 impl PrimaryEntityData for StackSlotData {}
 impl PrimaryEntityData for JumpTableData {}
+impl PrimaryEntityData for GlobalVarData {}
+impl PrimaryEntityData for TableData {}
 
 impl Function {
     /// Create a function with the given name and signature.
@@ -61,11 +97,17 @@ impl Function {
             signature: sig,
             stack_slots: EntityMap::new(),
             jump_tables: EntityMap::new(),
+            global_vars: EntityMap::new(),
+            tables: EntityMap::new(),
             dfg: DataFlowGraph::new(),
             layout: Layout::new(),
             encodings: EntityMap::new(),
             locations: EntityMap::new(),
+            srclocs: EntityMap::new(),
+            cold_ebbs: EntityMap::new(),
             offsets: EntityMap::new(),
+            jt_offsets: EntityMap::new(),
+            frame_layout: FrameLayout::new(),
         }
     }
 
@@ -74,10 +116,153 @@ impl Function {
         Self::with_name_signature(FunctionName::default(), Signature::new())
     }
 
+    /// Clear all data structures in this function, making it identical to `Function::new()`.
+    ///
+    /// The memory allocated by the function is kept, so building a new function in a cleared one
+    /// avoids most allocations.
+    pub fn clear(&mut self) {
+        self.name = FunctionName::default();
+        self.signature.clear();
+        self.stack_slots.clear();
+        self.jump_tables.clear();
+        self.global_vars.clear();
+        self.tables.clear();
+        self.dfg.clear();
+        self.layout.clear();
+        self.encodings.clear();
+        self.locations.clear();
+        self.srclocs.clear();
+        self.cold_ebbs.clear();
+        self.offsets.clear();
+        self.jt_offsets.clear();
+        self.frame_layout.clear();
+    }
+
+    /// Get a breakdown of the heap memory allocated by this function.
+    ///
+    /// See the `mem_usage` module for what is counted.
+    pub fn mem_usage(&self) -> MemUsage {
+        let mut usage = MemUsage::new();
+        self.dfg.mem_usage(&mut usage);
+        usage.add("layout", self.layout.mem_usage());
+        usage.add("encodings", self.encodings.mem_usage());
+        usage.add("locations", self.locations.mem_usage());
+        usage.add("func.other",
+                  self.srclocs.mem_usage() + self.cold_ebbs.mem_usage() +
+                  self.offsets.mem_usage() + self.jt_offsets.mem_usage() +
+                  self.stack_slots.mem_usage() + self.jump_tables.mem_usage() +
+                  self.global_vars.mem_usage() + self.tables.mem_usage());
+        usage
+    }
+
     /// Return an object that can display this function with correct ISA-specific annotations.
     pub fn display<'a, I: Into<Option<&'a TargetIsa>>>(&'a self, isa: I) -> DisplayFunction<'a> {
         DisplayFunction(self, isa.into())
     }
+
+    /// Is `ebb` expected to execute rarely?
+    pub fn is_cold(&self, ebb: Ebb) -> bool {
+        self.cold_ebbs.get(ebb).cloned().unwrap_or(false)
+    }
+
+    /// Mark `ebb` as cold.
+    pub fn set_cold(&mut self, ebb: Ebb) {
+        *self.cold_ebbs.ensure(ebb) = true;
+    }
+
+    /// Is this a leaf function that doesn't contain any call instructions?
+    ///
+    /// The return address of a leaf function can stay in the link register on ISAs that have one.
+    pub fn is_leaf(&self) -> bool {
+        !self.layout
+             .ebbs()
+             .flat_map(|ebb| self.layout.ebb_insts(ebb))
+             .any(|inst| self.dfg[inst].opcode().makes_call())
+    }
+
+    /// Get the encoding assigned to `inst`, or the illegal `Encoding::default()` if it has none.
+    pub fn inst_encoding(&self, inst: Inst) -> Encoding {
+        self.encodings.get(inst).cloned().unwrap_or_default()
+    }
+
+    /// Get the size in bytes of the machine code for `inst`.
+    ///
+    /// This is the size of the encoding assigned by the legalizer, or 0 if `inst` doesn't have a
+    /// legal encoding.
+    pub fn inst_bytes(&self, inst: Inst, encinfo: &EncInfo) -> CodeOffset {
+        encinfo.bytes(self.inst_encoding(inst))
+    }
+
+    /// Get the name of the encoding recipe assigned to `inst`, or `None` if it doesn't have a legal
+    /// encoding.
+    pub fn inst_recipe_name(&self, inst: Inst, encinfo: &EncInfo) -> Option<&'static str> {
+        encinfo.recipe_name(self.inst_encoding(inst))
+    }
+
+    /// Estimate the size of the machine code for this function without emitting it.
+    ///
+    /// For a legalized function, the estimate is an upper bound on the size
+    /// `Context::relax_branches()` will return, including padding and jump tables, so it can be
+    /// used to allocate code memory before emission. See `binemit::code_size_estimate()` for how
+    /// unencoded instructions are counted.
+    pub fn code_size_estimate(&self, isa: &TargetIsa) -> CodeOffset {
+        code_size_estimate(self, isa)
+    }
+
+    /// Append an argument with type `ty` to `ebb`, and pass a value for it from every branch to
+    /// `ebb` in the layout.
+    ///
+    /// The `branch_arg` closure is called with each branch instruction, and it returns the value
+    /// to append to the branch's arguments. Panics if `ebb` is a jump table destination, since
+    /// jump tables can't pass arguments.
+    ///
+    /// Returns the new EBB argument.
+    pub fn append_ebb_arg_with_fixup<F>(&mut self, ebb: Ebb, ty: Type, mut branch_arg: F) -> Value
+        where F: FnMut(&mut Function, Inst) -> Value
+    {
+        assert!(!self.jump_tables
+                     .keys()
+                     .any(|jt| self.jump_tables[jt].branches_to(ebb)),
+                "{} is a jump table destination",
+                ebb);
+        let arg = self.dfg.append_ebb_arg(ebb, ty);
+        for inst in self.branches_to(ebb) {
+            let value = branch_arg(self, inst);
+            self.dfg.append_inst_arg(inst, value);
+        }
+        arg
+    }
+
+    /// Remove the EBB argument `arg` from its EBB, and remove the corresponding argument from
+    /// every branch to the EBB in the layout.
+    ///
+    /// The removed value is left detached, so it should probably be changed into something else.
+    pub fn remove_ebb_arg(&mut self, arg: Value) {
+        let (ebb, num) = match self.dfg.value_def(arg) {
+            ValueDef::Arg(ebb, num) => (ebb, num),
+            ValueDef::Res(..) => panic!("{} must be an EBB argument", arg),
+        };
+        self.dfg.remove_ebb_arg(arg);
+        for inst in self.branches_to(ebb) {
+            let fixed_args = self.dfg[inst]
+                .opcode()
+                .constraints()
+                .fixed_value_arguments();
+            self.dfg.remove_inst_arg(inst, fixed_args + num);
+        }
+    }
+
+    /// Get the branch instructions in the layout that pass arguments to `ebb`.
+    fn branches_to(&self, ebb: Ebb) -> Vec<Inst> {
+        self.layout
+            .ebbs()
+            .flat_map(|e| self.layout.ebb_insts(e))
+            .filter(|&inst| match self.dfg[inst].analyze_branch(&self.dfg.value_lists) {
+                        BranchInfo::SingleDest(dest, _) => dest == ebb,
+                        _ => false,
+                    })
+            .collect()
+    }
 }
 
 /// Wrapper type capable of displaying a `Function` with correct ISA annotations.
@@ -100,3 +285,47 @@ impl Debug for Function {
         write_function(fmt, self, None)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use ir::{Function, Cursor, InstBuilder};
+    use ir::types::{I32, I64};
+
+    #[test]
+    fn ebb_arg_fixup() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        let v0 = func.dfg.append_ebb_arg(ebb0, I32);
+        let v1 = func.dfg.append_ebb_arg(ebb1, I32);
+        let (brz, jump) = {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            let brz = dfg.ins(pos).brz(v0, ebb1, &[v0]);
+            let jump = dfg.ins(pos).jump(ebb1, &[v0]);
+            pos.insert_ebb(ebb1);
+            dfg.ins(pos).return_(&[v1]);
+            (brz, jump)
+        };
+
+        let v2 = func.append_ebb_arg_with_fixup(ebb1, I64, |func, inst| {
+            let mut pos = Cursor::new(&mut func.layout);
+            pos.goto_inst(inst);
+            func.dfg.ins(&mut pos).iconst(I64, 0)
+        });
+        assert_eq!(func.dfg.ebb_args(ebb1), &[v1, v2]);
+        assert_eq!(func.dfg.inst_args(brz).len(), 3);
+        assert_eq!(func.dfg.inst_args(jump).len(), 2);
+        assert_eq!(func.dfg.inst_args(brz)[..2], [v0, v0]);
+        assert_eq!(func.dfg.inst_args(jump)[0], v0);
+
+        func.remove_ebb_arg(v1);
+        assert_eq!(func.dfg.ebb_args(ebb1), &[v2]);
+        assert_eq!(func.dfg.inst_args(brz)[0], v0);
+        assert_eq!(func.dfg.inst_variable_args(brz).len(), 1);
+        assert_eq!(func.dfg.inst_variable_args(jump).len(), 1);
+        assert!(func.dfg.inst_variable_args(jump)[0] != v0);
+        assert_eq!(func.dfg.value_type(func.dfg.inst_variable_args(jump)[0]), I64);
+    }
+}
@@ -0,0 +1,55 @@
+//! Global variables.
+//!
+//! Global variables are declared in the preamble and assigned an `ir::entities::GlobalVar`
+//! reference. They represent data symbols defined outside the function, and the only property
+//! Cretonne needs to know about them is how to compute their address.
+
+use ir::FunctionName;
+use std::fmt;
+
+/// Information about a global variable declaration.
+#[derive(Clone, Debug)]
+pub struct GlobalVarData {
+    /// Name of the global variable symbol.
+    pub name: FunctionName,
+
+    /// Is this a thread-local variable?
+    ///
+    /// The address of a thread-local variable is computed with the `tls_value` instruction which
+    /// is legalized into a code sequence that depends on the `tls_model` setting.
+    pub tls: bool,
+}
+
+impl GlobalVarData {
+    /// Create a new global variable declaration.
+    pub fn new(name: FunctionName, tls: bool) -> GlobalVarData {
+        GlobalVarData {
+            name: name,
+            tls: tls,
+        }
+    }
+}
+
+impl fmt::Display for GlobalVarData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "global ")?;
+        if self.tls {
+            write!(f, "tls ")?;
+        }
+        write!(f, "{}", self.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GlobalVarData;
+    use ir::FunctionName;
+
+    #[test]
+    fn display() {
+        let gv = GlobalVarData::new(FunctionName::new("counter"), false);
+        assert_eq!(gv.to_string(), "global counter");
+        let gv = GlobalVarData::new(FunctionName::new("errno"), true);
+        assert_eq!(gv.to_string(), "global tls errno");
+    }
+}
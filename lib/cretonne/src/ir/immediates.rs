@@ -538,6 +538,11 @@ impl Ieee32 {
     pub fn from_bits(x: u32) -> Ieee32 {
         Ieee32(unsafe { mem::transmute(x) })
     }
+
+    /// Get the bitwise representation.
+    pub fn bits(self) -> u32 {
+        unsafe { mem::transmute(self.0) }
+    }
 }
 
 impl Display for Ieee32 {
@@ -568,6 +573,11 @@ impl Ieee64 {
     pub fn from_bits(x: u64) -> Ieee64 {
         Ieee64(unsafe { mem::transmute(x) })
     }
+
+    /// Get the bitwise representation.
+    pub fn bits(self) -> u64 {
+        unsafe { mem::transmute(self.0) }
+    }
 }
 
 impl Display for Ieee64 {
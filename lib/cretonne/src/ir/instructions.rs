@@ -10,10 +10,12 @@ use std::fmt::{self, Display, Formatter};
 use std::str::FromStr;
 use std::ops::{Deref, DerefMut};
 
-use ir::{Value, Type, Ebb, JumpTable, SigRef, FuncRef, StackSlot, MemFlags};
+use ir::{Value, Type, Ebb, JumpTable, GlobalVar, Table, SigRef, FuncRef, StackSlot, MemFlags,
+         MemOrder};
 use ir::immediates::{Imm64, Uimm8, Ieee32, Ieee64, Offset32, Uoffset32};
 use ir::condcodes::*;
 use ir::types;
+use isa::RegUnit;
 
 use entity_list;
 use ref_slice::{ref_slice, ref_slice_mut};
@@ -29,6 +31,8 @@ pub type ValueListPool = entity_list::ListPool<Value>;
 // Include code generated by `lib/cretonne/meta/gen_instr.py`. This file contains:
 //
 // - The `pub enum InstructionFormat` enum with all the instruction formats.
+// - The `pub enum OperandKind` enum and the `impl InstructionFormat` methods describing the
+//   operands of each format.
 // - The `pub enum Opcode` definition with all known opcodes,
 // - The `const OPCODE_FORMAT: [InstructionFormat; N]` table.
 // - The private `fn opcode_name(Opcode) -> &'static str` function, and
@@ -42,6 +46,18 @@ pub type ValueListPool = entity_list::ListPool<Value>;
 //
 include!(concat!(env!("OUT_DIR"), "/opcodes.rs"));
 
+/// An immediate or entity reference field in an instruction format.
+///
+/// This corresponds to a member of an `InstructionData` variant, see
+/// `InstructionFormat::imm_fields()`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct FormatField {
+    /// Name of the `InstructionData` member holding the operand.
+    pub member: &'static str,
+    /// Kind of operand stored in the member.
+    pub kind: OperandKind,
+}
+
 impl Display for Opcode {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         write!(f, "{}", opcode_name(*self))
@@ -59,6 +75,14 @@ impl Opcode {
     pub fn constraints(self) -> OpcodeConstraints {
         OPCODE_CONSTRAINTS[self as usize - 1]
     }
+
+    /// Does this instruction call a function?
+    ///
+    /// This includes the `tls_gd_addr` access sequence which calls `__tls_get_addr` without being
+    /// a call instruction itself.
+    pub fn makes_call(self) -> bool {
+        self.is_call() || self == Opcode::TlsGdAddr
+    }
 }
 
 // This trait really belongs in lib/reader where it is used by the `.cton` file parser, but since
@@ -93,10 +117,14 @@ impl FromStr for Opcode {
 
 /// Contents on an instruction.
 ///
-/// Every variant must contain `opcode` and `ty` fields. An instruction that doesn't produce a
-/// value should have its `ty` field set to `VOID`. The size of `InstructionData` should be kept at
-/// 16 bytes on 64-bit architectures. If more space is needed to represent an instruction, use a
-/// `Box<AuxData>` to store the additional information out of line.
+/// Every variant must contain an `opcode` field. The result types are stored separately in the
+/// data flow graph.
+///
+/// The size of `InstructionData` is checked at compile time to be 16 bytes. The largest variants
+/// hold a 64-bit immediate or up to three value operands next to the opcode. Formats that need
+/// more space must store their value operands out of line in a pooled `ValueList`, and their
+/// immediates should be encoded as compactly as possible or stored in a table owned by the
+/// function, like jump tables.
 #[derive(Clone, Debug)]
 #[allow(missing_docs)]
 pub enum InstructionData {
@@ -105,6 +133,10 @@ pub enum InstructionData {
     UnaryImm { opcode: Opcode, imm: Imm64 },
     UnaryIeee32 { opcode: Opcode, imm: Ieee32 },
     UnaryIeee64 { opcode: Opcode, imm: Ieee64 },
+    UnaryGlobalVar {
+        opcode: Opcode,
+        global_var: GlobalVar,
+    },
     Binary { opcode: Opcode, args: [Value; 2] },
     BinaryImm {
         opcode: Opcode,
@@ -160,6 +192,13 @@ pub enum InstructionData {
         arg: Value,
         table: JumpTable,
     },
+    BranchTableBase { opcode: Opcode, table: JumpTable },
+    BranchTableEntry {
+        opcode: Opcode,
+        args: [Value; 2],
+        imm: Uimm8,
+        table: JumpTable,
+    },
     Call {
         opcode: Opcode,
         func_ref: FuncRef,
@@ -170,6 +209,7 @@ pub enum InstructionData {
         sig_ref: SigRef,
         args: ValueList,
     },
+    FuncAddr { opcode: Opcode, func_ref: FuncRef },
     StackLoad {
         opcode: Opcode,
         stack_slot: StackSlot,
@@ -191,6 +231,12 @@ pub enum InstructionData {
         args: [Value; 2],
         offset: Uoffset32,
     },
+    TableAddr {
+        opcode: Opcode,
+        table: Table,
+        arg: Value,
+        offset: Offset32,
+    },
     Load {
         opcode: Opcode,
         flags: MemFlags,
@@ -203,8 +249,35 @@ pub enum InstructionData {
         args: [Value; 2],
         offset: Offset32,
     },
+    AtomicLoad {
+        opcode: Opcode,
+        order: MemOrder,
+        arg: Value,
+    },
+    AtomicRmw {
+        opcode: Opcode,
+        order: MemOrder,
+        args: [Value; 2],
+    },
+    AtomicCas {
+        opcode: Opcode,
+        order: MemOrder,
+        args: [Value; 3],
+    },
+    Fence { opcode: Opcode, order: MemOrder },
+    RegMove {
+        opcode: Opcode,
+        arg: Value,
+        src: RegUnit,
+        dst: RegUnit,
+    },
 }
 
+// Fail the build if a variant makes `InstructionData` larger than 16 bytes. Every instruction in
+// a function is stored in this representation, so its size matters for compile time.
+#[allow(dead_code)]
+const INSTRUCTION_DATA_SIZE: [(); 16] = [(); ::std::mem::size_of::<InstructionData>()];
+
 /// A variable list of `Value` operands used for function call arguments and passing arguments to
 /// basic blocks.
 #[derive(Clone, Debug)]
@@ -643,6 +716,22 @@ mod tests {
         assert_eq!("".parse::<Opcode>(), Err("Unknown opcode"));
         assert_eq!("\0".parse::<Opcode>(), Err("Unknown opcode"));
 
+        // Fences and atomics must not be moved or removed.
+        assert!(Opcode::Fence.other_side_effects());
+        assert!(Opcode::AtomicLoad.other_side_effects());
+        assert!(!Opcode::Load.other_side_effects());
+
+        // Memory accesses.
+        assert!(Opcode::Load.can_load() && !Opcode::Load.can_store());
+        assert!(Opcode::Istore8.can_store() && !Opcode::Istore8.can_load());
+        assert!(Opcode::StackLoad.can_load());
+        assert!(Opcode::HeapStore.can_store() && Opcode::HeapStore.can_trap());
+        assert!(Opcode::AtomicCas.can_load() && Opcode::AtomicCas.can_store());
+        assert!(!Opcode::Iadd.can_load() && !Opcode::Iadd.can_store());
+        assert!(!Opcode::Iadd.can_trap() && Opcode::Udiv.can_trap());
+        assert!(Opcode::Brz.is_branch() && !Opcode::Brz.is_terminator());
+        assert!(Opcode::Jump.is_branch() && Opcode::Jump.is_terminator());
+
         // Opcode is a single byte, and because Option<Opcode> originally came to 2 bytes, early on
         // Opcode included a variant NotAnOpcode to avoid the unnecessary bloat. Since then the Rust
         // compiler has brought in NonZero optimization, meaning that an enum not using the 0 value
@@ -650,13 +739,43 @@ mod tests {
         assert_eq!(mem::size_of::<Opcode>(), mem::size_of::<Option<Opcode>>());
     }
 
+    #[test]
+    fn formats() {
+        assert_eq!(InstructionFormat::Binary.num_value_operands(), 2);
+        assert!(!InstructionFormat::Binary.has_value_list());
+        assert_eq!(InstructionFormat::Binary.typevar_operand(), Some(0));
+        assert_eq!(InstructionFormat::Binary.imm_fields(), &[]);
+
+        assert_eq!(InstructionFormat::Ternary.typevar_operand(), Some(1));
+        assert_eq!(InstructionFormat::Nullary.typevar_operand(), None);
+
+        // The controlling value of a branch goes in front of the EBB arguments.
+        assert_eq!(InstructionFormat::Branch.num_value_operands(), 1);
+        assert!(InstructionFormat::Branch.has_value_list());
+        assert_eq!(InstructionFormat::Branch.imm_fields(),
+                   &[FormatField {
+                         member: "destination",
+                         kind: OperandKind::Ebb,
+                     }]);
+
+        let fields = InstructionFormat::Store.imm_fields();
+        assert_eq!(fields.len(), 2);
+        assert_eq!(fields[0].kind, OperandKind::Memflags);
+        assert_eq!(fields[1].member, "offset");
+        assert_eq!(fields[1].kind, OperandKind::Offset32);
+
+        // Every opcode's format is listed.
+        assert!(InstructionFormat::all().contains(&Opcode::Iadd.format()));
+        assert!(InstructionFormat::all().contains(&Opcode::Trap.format()));
+    }
+
     #[test]
     fn instruction_data() {
         use std::mem;
         // The size of the `InstructionData` enum is important for performance. It should not
-        // exceed 16 bytes. Use `Box<FooData>` out-of-line payloads for instruction formats that
-        // require more space than that. It would be fine with a data structure smaller than 16
-        // bytes, but what are the odds of that?
+        // exceed 16 bytes. Use pooled value lists for instruction formats that require more space
+        // than that. It would be fine with a data structure smaller than 16 bytes, but what are
+        // the odds of that?
         assert_eq!(mem::size_of::<InstructionData>(), 16);
     }
 
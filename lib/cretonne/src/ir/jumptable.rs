@@ -62,6 +62,21 @@ impl JumpTableData {
         self.table.get(idx).and_then(|e| e.expand())
     }
 
+    /// Get the number of entries in the table, including any missing entries.
+    pub fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    /// Is the table empty?
+    pub fn is_empty(&self) -> bool {
+        self.table.is_empty()
+    }
+
+    /// Does the table have any missing entries?
+    pub fn has_holes(&self) -> bool {
+        self.holes != 0
+    }
+
     /// Enumerate over all `(idx, dest)` pairs in the table in order.
     ///
     /// This returns an iterator that skips any empty slots in the table.
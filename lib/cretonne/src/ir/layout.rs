@@ -50,6 +50,19 @@ impl Layout {
             last_ebb: None,
         }
     }
+
+    /// Clear the layout, keeping the allocated memory.
+    pub fn clear(&mut self) {
+        self.ebbs.clear();
+        self.insts.clear();
+        self.first_ebb = None;
+        self.last_ebb = None;
+    }
+
+    /// Get the number of bytes of heap memory allocated by this layout.
+    pub fn mem_usage(&self) -> usize {
+        self.ebbs.mem_usage() + self.insts.mem_usage()
+    }
 }
 
 // Sequence numbers.
@@ -339,6 +352,60 @@ impl Layout {
         self.assign_ebb_seq(ebb);
     }
 
+    /// Remove `ebb` from the layout. The EBB must be empty.
+    pub fn remove_ebb(&mut self, ebb: Ebb) {
+        assert!(self.is_ebb_inserted(ebb), "EBB not in the layout");
+        assert!(self.ebbs[ebb].first_inst.is_none(),
+                "Cannot remove EBB with instructions");
+        self.unlink_ebb(ebb);
+    }
+
+    /// Move `ebb` and all its instructions to the end of the layout.
+    pub fn move_ebb_to_end(&mut self, ebb: Ebb) {
+        assert!(self.is_ebb_inserted(ebb), "EBB not in the layout");
+        if self.last_ebb == Some(ebb) {
+            return;
+        }
+        self.unlink_ebb(ebb);
+        let last = self.last_ebb.expect("Layout can't be empty");
+        self.ebbs[ebb].prev = last.into();
+        self.ebbs[last].next = ebb.into();
+        self.last_ebb = Some(ebb);
+
+        // Nothing follows `ebb` now, so it can be numbered with major strides.
+        let mut seq = self.last_ebb_seq(last) + MAJOR_STRIDE;
+        self.ebbs[ebb].seq = seq;
+        let mut next = self.ebbs[ebb].first_inst.expand();
+        while let Some(inst) = next {
+            seq += MAJOR_STRIDE;
+            self.insts[inst].seq = seq;
+            next = self.insts[inst].next.expand();
+        }
+    }
+
+    /// Unlink `ebb` from its neighbors in the layout, leaving its instructions in place.
+    fn unlink_ebb(&mut self, ebb: Ebb) {
+        // Clear the `ebb` node and extract links.
+        let prev;
+        let next;
+        {
+            let n = &mut self.ebbs[ebb];
+            prev = n.prev;
+            next = n.next;
+            n.prev = None.into();
+            n.next = None.into();
+        }
+        // Fix up links to `ebb`.
+        match prev.expand() {
+            None => self.first_ebb = next.expand(),
+            Some(p) => self.ebbs[p].next = next,
+        }
+        match next.expand() {
+            None => self.last_ebb = prev.expand(),
+            Some(n) => self.ebbs[n].prev = prev,
+        }
+    }
+
     /// Return an iterator over all EBBs in layout order.
     pub fn ebbs<'f>(&'f self) -> Ebbs<'f> {
         Ebbs {
@@ -955,7 +1022,7 @@ impl<'f> Cursor<'f> {
 mod tests {
     use super::{Layout, Cursor, CursorPosition};
     use entity_map::EntityRef;
-    use ir::{Ebb, Inst, ProgramOrder};
+    use ir::{Ebb, Inst, ProgramOrder, ProgramPoint, ExpandedProgramPoint};
     use std::cmp::Ordering;
 
     fn verify(layout: &mut Layout, ebbs: &[(Ebb, &[Inst])]) {
@@ -1119,6 +1186,64 @@ mod tests {
         verify(&mut layout, &[(e1, &[]), (e0, &[]), (e2, &[])]);
     }
 
+    #[test]
+    fn remove_ebb() {
+        let mut layout = Layout::new();
+        let e0 = Ebb::new(0);
+        let e1 = Ebb::new(1);
+        let e2 = Ebb::new(2);
+
+        layout.append_ebb(e0);
+        layout.append_ebb(e1);
+        layout.append_ebb(e2);
+
+        layout.remove_ebb(e1);
+        assert!(!layout.is_ebb_inserted(e1));
+        verify(&mut layout, &[(e0, &[]), (e2, &[])]);
+
+        layout.remove_ebb(e0);
+        assert!(!layout.is_ebb_inserted(e0));
+        assert_eq!(layout.entry_block(), Some(e2));
+        verify(&mut layout, &[(e2, &[])]);
+
+        layout.remove_ebb(e2);
+        assert_eq!(layout.entry_block(), None);
+        verify(&mut layout, &[]);
+
+        // A removed EBB can be inserted again.
+        layout.append_ebb(e1);
+        verify(&mut layout, &[(e1, &[])]);
+    }
+
+    #[test]
+    fn move_ebb_to_end() {
+        let mut layout = Layout::new();
+        let e0 = Ebb::new(0);
+        let e1 = Ebb::new(1);
+        let e2 = Ebb::new(2);
+        let i0 = Inst::new(0);
+        let i1 = Inst::new(1);
+        let i2 = Inst::new(2);
+
+        layout.append_ebb(e0);
+        layout.append_ebb(e1);
+        layout.append_ebb(e2);
+        layout.append_inst(i0, e0);
+        layout.append_inst(i1, e1);
+        layout.append_inst(i2, e1);
+
+        layout.move_ebb_to_end(e1);
+        verify(&mut layout, &[(e0, &[i0]), (e2, &[]), (e1, &[i1, i2])]);
+
+        layout.move_ebb_to_end(e0);
+        assert_eq!(layout.entry_block(), Some(e2));
+        verify(&mut layout, &[(e2, &[]), (e1, &[i1, i2]), (e0, &[i0])]);
+
+        // Moving the last EBB does nothing.
+        layout.move_ebb_to_end(e0);
+        verify(&mut layout, &[(e2, &[]), (e1, &[i1, i2]), (e0, &[i0])]);
+    }
+
     #[test]
     fn append_inst() {
         let mut layout = Layout::new();
@@ -1230,6 +1355,47 @@ mod tests {
         verify(&mut layout, &[(e1, &[i2, i0, i1])]);
     }
 
+    #[test]
+    fn program_order() {
+        let mut layout = Layout::new();
+        let e0 = Ebb::new(0);
+        let e1 = Ebb::new(1);
+        let first = Inst::new(0);
+        let last = Inst::new(1);
+        layout.append_ebb(e0);
+        layout.append_inst(first, e0);
+        layout.append_inst(last, e0);
+        layout.append_ebb(e1);
+
+        // Fill the gap between `first` and `last` until the sequence numbers run out and the
+        // instructions have to be renumbered.
+        let mut insts = vec![first];
+        for i in 2..100 {
+            let inst = Inst::new(i);
+            layout.insert_inst(inst, last);
+            insts.push(inst);
+        }
+        insts.push(last);
+
+        for w in insts.windows(2) {
+            assert_eq!(layout.cmp(w[0], w[1]), Ordering::Less);
+            assert_eq!(layout.cmp(w[1], w[0]), Ordering::Greater);
+        }
+        for &inst in &insts {
+            assert_eq!(layout.cmp(e0, inst), Ordering::Less);
+            assert_eq!(layout.cmp(inst, e1), Ordering::Less);
+        }
+
+        // Program points mixing EBBs and instructions.
+        let pp0: ProgramPoint = e0.into();
+        let pp1: ProgramPoint = last.into();
+        let pp2: ProgramPoint = ExpandedProgramPoint::Ebb(e1).into();
+        assert_eq!(layout.cmp(pp0, pp1), Ordering::Less);
+        assert_eq!(layout.cmp(pp2, pp1), Ordering::Greater);
+        assert_eq!(layout.cmp(pp2, e1), Ordering::Equal);
+        assert!(layout.is_ebb_gap(last, e1));
+    }
+
     #[test]
     fn multiple_ebbs() {
         let mut layout = Layout::new();
@@ -0,0 +1,99 @@
+//! Memory orderings for atomic operations.
+
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+/// Memory ordering constraint on an atomic memory operation.
+///
+/// The orderings correspond to the C++11 memory model. An atomic operation is always indivisible,
+/// and the ordering determines how other memory operations in the same thread can be reordered
+/// around it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MemOrder {
+    /// No ordering constraints, only atomicity.
+    Relaxed,
+    /// Later memory operations can't be moved before this one.
+    Acquire,
+    /// Earlier memory operations can't be moved after this one.
+    Release,
+    /// Both `Acquire` and `Release`.
+    AcqRel,
+    /// `AcqRel` and a single total order of all sequentially consistent operations.
+    SeqCst,
+}
+
+impl MemOrder {
+    /// Does this ordering have acquire semantics?
+    pub fn is_acquire(self) -> bool {
+        match self {
+            MemOrder::Acquire | MemOrder::AcqRel | MemOrder::SeqCst => true,
+            MemOrder::Relaxed | MemOrder::Release => false,
+        }
+    }
+
+    /// Does this ordering have release semantics?
+    pub fn is_release(self) -> bool {
+        match self {
+            MemOrder::Release | MemOrder::AcqRel | MemOrder::SeqCst => true,
+            MemOrder::Relaxed | MemOrder::Acquire => false,
+        }
+    }
+}
+
+impl Display for MemOrder {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        use self::MemOrder::*;
+        f.write_str(match self {
+                        &Relaxed => "relaxed",
+                        &Acquire => "acquire",
+                        &Release => "release",
+                        &AcqRel => "acq_rel",
+                        &SeqCst => "seq_cst",
+                    })
+    }
+}
+
+impl FromStr for MemOrder {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use self::MemOrder::*;
+        match s {
+            "relaxed" => Ok(Relaxed),
+            "acquire" => Ok(Acquire),
+            "release" => Ok(Release),
+            "acq_rel" => Ok(AcqRel),
+            "seq_cst" => Ok(SeqCst),
+            _ => Err(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static ORDERS: [MemOrder; 5] = [MemOrder::Relaxed,
+                                    MemOrder::Acquire,
+                                    MemOrder::Release,
+                                    MemOrder::AcqRel,
+                                    MemOrder::SeqCst];
+
+    #[test]
+    fn display_and_parse() {
+        for &order in ORDERS.iter() {
+            assert_eq!(order.to_string().parse(), Ok(order));
+        }
+        assert_eq!("bogus".parse::<MemOrder>(), Err(()));
+    }
+
+    #[test]
+    fn semantics() {
+        assert!(!MemOrder::Relaxed.is_acquire());
+        assert!(!MemOrder::Relaxed.is_release());
+        assert!(MemOrder::Acquire.is_acquire());
+        assert!(!MemOrder::Acquire.is_release());
+        assert!(MemOrder::Release.is_release());
+        assert!(MemOrder::SeqCst.is_acquire() && MemOrder::SeqCst.is_release());
+    }
+}
@@ -12,22 +12,38 @@ pub mod layout;
 pub mod function;
 mod builder;
 mod extfunc;
+mod framelayout;
 mod funcname;
+mod globalvar;
 mod memflags;
+mod memorder;
 mod progpoint;
+mod sourceloc;
+mod table;
+mod trapcode;
 mod valueloc;
+mod valuemap;
 
 pub use ir::funcname::FunctionName;
-pub use ir::extfunc::{Signature, ArgumentType, ArgumentExtension, ArgumentPurpose, ExtFuncData};
+pub use ir::extfunc::{Signature, CallConv, ArgumentType, ArgumentExtension, ArgumentPurpose,
+                      ExtFuncData};
 pub use ir::types::Type;
-pub use ir::entities::{Ebb, Inst, Value, StackSlot, JumpTable, FuncRef, SigRef};
+pub use ir::entities::{Ebb, Inst, Value, StackSlot, JumpTable, GlobalVar, Table, FuncRef,
+                       SigRef};
 pub use ir::instructions::{Opcode, InstructionData, VariableArgs, ValueList, ValueListPool};
-pub use ir::stackslot::StackSlotData;
+pub use ir::stackslot::{StackSlotData, StackSlotKind};
 pub use ir::jumptable::JumpTableData;
+pub use ir::framelayout::{FrameLayout, FrameLayoutChange};
+pub use ir::globalvar::GlobalVarData;
+pub use ir::table::TableData;
 pub use ir::valueloc::{ValueLoc, ArgumentLoc};
+pub use ir::valuemap::ValueMap;
 pub use ir::dfg::{DataFlowGraph, ValueDef};
 pub use ir::layout::{Layout, Cursor};
 pub use ir::function::Function;
-pub use ir::builder::InstBuilder;
+pub use ir::builder::{InstBuilder, InstBuilderBase};
 pub use ir::progpoint::{ProgramPoint, ProgramOrder, ExpandedProgramPoint};
 pub use ir::memflags::MemFlags;
+pub use ir::memorder::MemOrder;
+pub use ir::trapcode::TrapCode;
+pub use ir::sourceloc::SourceLoc;
@@ -32,6 +32,15 @@ impl From<Ebb> for ProgramPoint {
     }
 }
 
+impl From<ExpandedProgramPoint> for ProgramPoint {
+    fn from(pp: ExpandedProgramPoint) -> ProgramPoint {
+        match pp {
+            ExpandedProgramPoint::Inst(inst) => inst.into(),
+            ExpandedProgramPoint::Ebb(ebb) => ebb.into(),
+        }
+    }
+}
+
 /// An expanded program point directly exposes the variants, but takes twice the space to
 /// represent.
 #[derive(PartialEq, Eq, Clone, Copy)]
@@ -83,6 +92,9 @@ impl fmt::Debug for ProgramPoint {
 ///
 /// `ProgramPoint` objects don't carry enough information to be ordered independently, they need a
 /// context providing the program order.
+///
+/// The function `Layout` implements this trait with the sequence numbers it maintains for all
+/// EBBs and instructions, so comparing two program points takes constant time.
 pub trait ProgramOrder {
     /// Compare the program points `a` and `b` relative to this program order.
     ///
@@ -117,5 +129,10 @@ mod tests {
 
         assert_eq!(pp1.to_string(), "inst5");
         assert_eq!(pp2.to_string(), "ebb3");
+
+        let epp: ExpandedProgramPoint = pp2.into();
+        assert!(epp == ExpandedProgramPoint::Ebb(b3));
+        assert!(ProgramPoint::from(epp) == pp2);
+        assert!(ProgramPoint::from(ExpandedProgramPoint::Inst(i5)) == pp1);
     }
 }
@@ -4,42 +4,154 @@
 //!
 
 use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+/// The kind of a stack slot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StackSlotKind {
+    /// A local variable slot declared explicitly in the function, accessed with `stack_load` and
+    /// `stack_store` instructions.
+    Local,
+
+    /// A spill slot created by the register allocator. Spill slots can be shared by multiple
+    /// values whose live ranges don't overlap.
+    SpillSlot,
+
+    /// An outgoing function argument passed on the stack.
+    ///
+    /// Outgoing argument slots have a fixed offset from the stack pointer given by the calling
+    /// convention. They are placed at the bottom of the stack frame where the callee expects to
+    /// find its stack arguments.
+    OutgoingArg,
+
+    /// An incoming function argument passed on the stack by the caller.
+    ///
+    /// Incoming argument slots are above the stack frame. Their offset is initially relative to
+    /// the stack pointer at the call, as given by the calling convention. The prologue insertion
+    /// makes it relative to the stack pointer after the prologue like the other slots.
+    IncomingArg,
+}
+
+impl Display for StackSlotKind {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        use self::StackSlotKind::*;
+        f.write_str(match *self {
+                        Local => "stack_slot",
+                        SpillSlot => "spill_slot",
+                        OutgoingArg => "outgoing_arg",
+                        IncomingArg => "incoming_arg",
+                    })
+    }
+}
+
+impl FromStr for StackSlotKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<StackSlotKind, ()> {
+        use self::StackSlotKind::*;
+        match s {
+            "stack_slot" => Ok(Local),
+            "spill_slot" => Ok(SpillSlot),
+            "outgoing_arg" => Ok(OutgoingArg),
+            "incoming_arg" => Ok(IncomingArg),
+            _ => Err(()),
+        }
+    }
+}
 
 /// Contents of a stack slot.
 #[derive(Clone, Debug)]
 pub struct StackSlotData {
+    /// The kind of stack slot.
+    pub kind: StackSlotKind,
+
     /// Size of stack slot in bytes.
     pub size: u32,
+
+    /// Offset of the stack slot relative to the stack pointer after the function prologue.
+    ///
+    /// This is assigned when the stack frame is laid out after register allocation, except for
+    /// outgoing and incoming arguments which get their offset from the calling convention.
+    pub offset: i32,
 }
 
 impl StackSlotData {
-    /// Create a stack slot with the specified byte size.
-    pub fn new(size: u32) -> StackSlotData {
-        StackSlotData { size: size }
+    /// Create a stack slot with the specified kind and byte size.
+    pub fn new(kind: StackSlotKind, size: u32) -> StackSlotData {
+        StackSlotData {
+            kind: kind,
+            size: size,
+            offset: 0,
+        }
+    }
+
+    /// Create an outgoing argument slot of `size` bytes at `offset` from the stack pointer.
+    pub fn outgoing_arg(size: u32, offset: i32) -> StackSlotData {
+        StackSlotData {
+            kind: StackSlotKind::OutgoingArg,
+            size: size,
+            offset: offset,
+        }
+    }
+
+    /// Create an incoming argument slot of `size` bytes at `offset` from the stack pointer at the
+    /// call.
+    pub fn incoming_arg(size: u32, offset: i32) -> StackSlotData {
+        StackSlotData {
+            kind: StackSlotKind::IncomingArg,
+            size: size,
+            offset: offset,
+        }
     }
 }
 
 impl Display for StackSlotData {
     fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
-        write!(fmt, "stack_slot {}", self.size)
+        write!(fmt, "{} {}", self.kind, self.size)?;
+        if self.kind == StackSlotKind::OutgoingArg || self.kind == StackSlotKind::IncomingArg {
+            write!(fmt, ", offset {}", self.offset)?;
+        }
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use ir::Function;
-    use super::StackSlotData;
+    use super::{StackSlotData, StackSlotKind};
 
     #[test]
     fn stack_slot() {
         let mut func = Function::new();
 
-        let ss0 = func.stack_slots.push(StackSlotData::new(4));
-        let ss1 = func.stack_slots.push(StackSlotData::new(8));
+        let ss0 = func.stack_slots
+            .push(StackSlotData::new(StackSlotKind::Local, 4));
+        let ss1 = func.stack_slots
+            .push(StackSlotData::new(StackSlotKind::SpillSlot, 8));
         assert_eq!(ss0.to_string(), "ss0");
         assert_eq!(ss1.to_string(), "ss1");
 
         assert_eq!(func.stack_slots[ss0].size, 4);
         assert_eq!(func.stack_slots[ss1].size, 8);
+
+        assert_eq!(func.stack_slots[ss0].to_string(), "stack_slot 4");
+        assert_eq!(func.stack_slots[ss1].to_string(), "spill_slot 8");
+
+        let ss2 = func.stack_slots.push(StackSlotData::outgoing_arg(4, 8));
+        assert_eq!(func.stack_slots[ss2].to_string(), "outgoing_arg 4, offset 8");
+
+        let ss3 = func.stack_slots.push(StackSlotData::incoming_arg(8, 16));
+        assert_eq!(func.stack_slots[ss3].to_string(), "incoming_arg 8, offset 16");
+    }
+
+    #[test]
+    fn kind_names() {
+        for &kind in &[StackSlotKind::Local,
+                       StackSlotKind::SpillSlot,
+                       StackSlotKind::OutgoingArg,
+                       StackSlotKind::IncomingArg] {
+            assert_eq!(Ok(kind), kind.to_string().parse());
+        }
+        assert_eq!("stack".parse::<StackSlotKind>(), Err(()));
     }
 }
@@ -0,0 +1,49 @@
+//! Tables.
+//!
+//! Tables are declared in the preamble and assigned an `ir::entities::Table` reference. A table is
+//! an array of fixed-size elements in memory, like the function tables used by WebAssembly's
+//! `call_indirect`. The `table_addr` instruction computes the address of an element after checking
+//! its index against the bound of the table.
+
+use ir::GlobalVar;
+use std::fmt;
+
+/// Information about a table declaration.
+#[derive(Clone, Debug)]
+pub struct TableData {
+    /// Thread-local variable holding the address of the first element of the table.
+    pub base: GlobalVar,
+
+    /// Number of elements in the table. Indexes at or above the bound trap.
+    pub bound: u64,
+
+    /// Size of a table element in bytes.
+    pub element_size: u64,
+}
+
+impl fmt::Display for TableData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "table {}, bound {}, element_size {}",
+               self.base,
+               self.bound,
+               self.element_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TableData;
+    use entity_map::EntityRef;
+    use ir::GlobalVar;
+
+    #[test]
+    fn display() {
+        let table = TableData {
+            base: GlobalVar::new(1),
+            bound: 10,
+            element_size: 16,
+        };
+        assert_eq!(table.to_string(), "table gv1, bound 10, element_size 16");
+    }
+}
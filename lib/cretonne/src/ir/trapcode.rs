@@ -0,0 +1,85 @@
+//! Trap codes describing the reason for a trap.
+
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+/// A trap code describing the reason for a trap.
+///
+/// The binary emitter records the code offset and trap code of every machine instruction that can
+/// trap. A runtime that handles traps with signal handlers can use them to map a faulting program
+/// counter back to the reason for the trap.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum TrapCode {
+    /// The current stack space was exhausted.
+    StackOverflow,
+
+    /// A memory access was out of bounds, or the memory was not accessible.
+    HeapOutOfBounds,
+
+    /// An integer arithmetic operation caused an overflow.
+    IntegerOverflow,
+
+    /// An integer division by zero.
+    IntegerDivisionByZero,
+
+    /// Failed float-to-int conversion.
+    BadConversionToInteger,
+
+    /// A user-defined trap code.
+    User(u16),
+}
+
+impl Display for TrapCode {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        use self::TrapCode::*;
+        let identifier = match *self {
+            StackOverflow => "stk_ovf",
+            HeapOutOfBounds => "heap_oob",
+            IntegerOverflow => "int_ovf",
+            IntegerDivisionByZero => "int_divz",
+            BadConversionToInteger => "bad_toint",
+            User(x) => return write!(f, "user{}", x),
+        };
+        f.write_str(identifier)
+    }
+}
+
+impl FromStr for TrapCode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use self::TrapCode::*;
+        match s {
+            "stk_ovf" => Ok(StackOverflow),
+            "heap_oob" => Ok(HeapOutOfBounds),
+            "int_ovf" => Ok(IntegerOverflow),
+            "int_divz" => Ok(IntegerDivisionByZero),
+            "bad_toint" => Ok(BadConversionToInteger),
+            _ if s.starts_with("user") => s[4..].parse().map(User).map_err(|_| ()),
+            _ => Err(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static CODES: [TrapCode; 5] = [TrapCode::StackOverflow,
+                                   TrapCode::HeapOutOfBounds,
+                                   TrapCode::IntegerOverflow,
+                                   TrapCode::IntegerDivisionByZero,
+                                   TrapCode::BadConversionToInteger];
+
+    #[test]
+    fn display() {
+        for &code in &CODES {
+            assert_eq!(code.to_string().parse(), Ok(code));
+        }
+        assert_eq!("user7".parse(), Ok(TrapCode::User(7)));
+        assert_eq!(TrapCode::User(17).to_string(), "user17");
+        assert_eq!("bogus".parse::<TrapCode>(), Err(()));
+        assert_eq!("user".parse::<TrapCode>(), Err(()));
+        assert_eq!("user-1".parse::<TrapCode>(), Err(()));
+    }
+}
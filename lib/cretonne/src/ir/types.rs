@@ -197,6 +197,13 @@ impl Type {
         self.lane_bits() as u16 * self.lane_count()
     }
 
+    /// Get the number of bytes used to store this type in memory.
+    ///
+    /// Types that are not a whole number of bytes, like `b1`, are rounded up.
+    pub fn bytes(self) -> u32 {
+        (self.bits() as u32 + 7) / 8
+    }
+
     /// Get a SIMD vector type with `n` times more lanes than this one.
     ///
     /// If this is a scalar type, this produces a SIMD type with this as a lane type and `n` lanes.
@@ -346,6 +353,9 @@ mod tests {
         assert_eq!(big.lane_bits(), 64);
         assert_eq!(big.lane_count(), 256);
         assert_eq!(big.bits(), 64 * 256);
+        assert_eq!(big.bytes(), 8 * 256);
+        assert_eq!(B1.bytes(), 1);
+        assert_eq!(I16.bytes(), 2);
 
         assert_eq!(big.half_vector().unwrap().to_string(), "f64x128");
         assert_eq!(B1.by(2).unwrap().half_vector().unwrap().to_string(), "b1");
@@ -1,19 +1,25 @@
 //! Value locations.
 //!
-//! The register allocator assigns every SSA value to either a register or a stack slot. This
-//! assignment is represented by a `ValueLoc` object.
+//! The register allocator assigns every SSA value to either a register, a register pair, or a
+//! stack slot. This assignment is represented by a `ValueLoc` object.
 
 use isa::{RegInfo, RegUnit};
 use ir::StackSlot;
 use std::fmt;
 
 /// Value location.
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum ValueLoc {
     /// This value has not been assigned to a location yet.
     Unassigned,
     /// Value is assigned to a register.
     Reg(RegUnit),
+    /// Value is assigned to a pair of adjacent registers, starting at the given register unit.
+    ///
+    /// The first register holds the low half of the value, and the next register holds the high
+    /// half. This is used for values that are twice as wide as the registers in a pair register
+    /// class, like an `i64` value on a 32-bit target.
+    RegPair(RegUnit),
     /// Value is assigned to a stack slot.
     Stack(StackSlot),
 }
@@ -33,6 +39,33 @@ impl ValueLoc {
         }
     }
 
+    /// Get the low and high register units of this register pair location, or panic.
+    pub fn unwrap_reg_pair(self) -> (RegUnit, RegUnit) {
+        match self {
+            ValueLoc::RegPair(ru) => (ru, ru + 1),
+            _ => panic!("Expected register pair: {:?}", self),
+        }
+    }
+
+    /// Get the first register unit of a register or register pair location.
+    ///
+    /// Returns `None` for stack and unassigned locations.
+    pub fn regunit(self) -> Option<RegUnit> {
+        match self {
+            ValueLoc::Reg(ru) |
+            ValueLoc::RegPair(ru) => Some(ru),
+            _ => None,
+        }
+    }
+
+    /// Get the stack slot of this location, or panic.
+    pub fn unwrap_stack(self) -> StackSlot {
+        match self {
+            ValueLoc::Stack(ss) => ss,
+            _ => panic!("Expected stack slot: {:?}", self),
+        }
+    }
+
     /// Return an object that can display this value location, using the register info from the
     /// target ISA.
     pub fn display<'a, R: Into<Option<&'a RegInfo>>>(self, regs: R) -> DisplayValueLoc<'a> {
@@ -56,6 +89,17 @@ impl<'a> fmt::Display for DisplayValueLoc<'a> {
                     None => write!(f, "%{}", ru),
                 }
             }
+            ValueLoc::RegPair(ru) => {
+                match self.1 {
+                    Some(regs) => {
+                        write!(f,
+                               "{}:{}",
+                               regs.display_regunit(ru),
+                               regs.display_regunit(ru + 1))
+                    }
+                    None => write!(f, "%{}:%{}", ru, ru + 1),
+                }
+            }
             ValueLoc::Stack(ss) => write!(f, "{}", ss),
         }
     }
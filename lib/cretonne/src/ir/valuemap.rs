@@ -0,0 +1,76 @@
+//! Value remapping tables.
+//!
+//! Transformations that duplicate code, like inlining, loop unrolling, and tail duplication, copy
+//! a region of instructions and need to rewrite the arguments of the copies to use the copied
+//! values. A `ValueMap` records which value each original value was copied to, and
+//! `DataFlowGraph::clone_inst()` uses it to rewrite arguments and to record the new results.
+
+use entity_map::EntityMap;
+use ir::Value;
+use packed_option::PackedOption;
+
+/// A mapping from original values to their copies.
+///
+/// Values that haven't been mapped are left alone by `map()`, so values defined outside the
+/// duplicated region don't need to be entered in the table.
+#[derive(Clone, Debug)]
+pub struct ValueMap {
+    map: EntityMap<Value, PackedOption<Value>>,
+}
+
+impl ValueMap {
+    /// Create an empty value map.
+    pub fn new() -> ValueMap {
+        ValueMap { map: EntityMap::new() }
+    }
+
+    /// Remove all mappings, keeping the allocated memory.
+    pub fn clear(&mut self) {
+        self.map.clear();
+    }
+
+    /// Map `from` to `to`, replacing any existing mapping for `from`.
+    pub fn insert(&mut self, from: Value, to: Value) {
+        *self.map.ensure(from) = to.into();
+    }
+
+    /// Get the value that `from` is mapped to, if any.
+    pub fn get(&self, from: Value) -> Option<Value> {
+        self.map.get(from).and_then(|v| v.expand())
+    }
+
+    /// Get the value that `from` is mapped to, or `from` itself if it hasn't been mapped.
+    pub fn map(&self, from: Value) -> Value {
+        self.get(from).unwrap_or(from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ValueMap;
+    use entity_map::EntityRef;
+    use ir::Value;
+
+    #[test]
+    fn basic() {
+        let v0 = Value::new(0);
+        let v1 = Value::new(1);
+        let v7 = Value::new(7);
+
+        let mut map = ValueMap::new();
+        assert_eq!(map.get(v0), None);
+        assert_eq!(map.map(v0), v0);
+
+        map.insert(v7, v1);
+        assert_eq!(map.get(v7), Some(v1));
+        assert_eq!(map.get(v0), None);
+        assert_eq!(map.map(v7), v1);
+        assert_eq!(map.map(v1), v1);
+
+        map.insert(v7, v0);
+        assert_eq!(map.map(v7), v0);
+
+        map.clear();
+        assert_eq!(map.get(v7), None);
+    }
+}
@@ -0,0 +1,133 @@
+//! ARM32 ABI implementation.
+//!
+//! This module implements the AAPCS calling convention with the VFP variant for floating point
+//! arguments through the primary `legalize_signature()` entry point.
+//!
+//! Single precision arguments are not back-filled into the `%s` registers skipped when aligning a
+//! double precision argument, so some `f32` arguments end up on the stack when AAPCS would have
+//! put them in a register.
+
+use abi::{ArgAction, ValueConversion, ArgAssigner, legalize_args};
+use ir::{Signature, ArgumentType, ArgumentLoc, ArgumentExtension, ArgumentPurpose};
+use ir::types;
+use isa::RegUnit;
+use isa::arm32::registers::{GPR, S, D};
+
+/// The stack pointer `%r13` and the program counter `%r15` are never allocated.
+pub static RESERVED_REGS: [RegUnit; 2] = [77, 79];
+
+/// The link register `%r14` holds the return address.
+const LINK_REG: usize = 14;
+
+struct Args {
+    gpr_limit: u32,
+    gpr_used: u32,
+    /// Number of `%s` registers available and used. A `%d` register counts as two.
+    fpr_limit: u32,
+    fpr_used: u32,
+    offset: u32,
+}
+
+impl Args {
+    fn new(gpr_limit: u32, fpr_limit: u32) -> Args {
+        Args {
+            gpr_limit: gpr_limit,
+            gpr_used: 0,
+            fpr_limit: fpr_limit,
+            fpr_used: 0,
+            offset: 0,
+        }
+    }
+}
+
+impl ArgAssigner for Args {
+    fn assign(&mut self, arg: &ArgumentType) -> ArgAction {
+        fn align(value: u32, to: u32) -> u32 {
+            (value + to - 1) & !(to - 1)
+        }
+
+        let ty = arg.value_type;
+
+        // Check for a legal type.
+        // We don't support the NEON registers yet, so break all vectors down.
+        if !ty.is_scalar() {
+            return ValueConversion::VectorSplit.into();
+        }
+
+        // Large integers and booleans are broken down to fit in a register.
+        if !ty.is_float() && ty.bits() > 32 {
+            // Double-word integers start in an even-numbered register or an 8-byte aligned stack
+            // slot.
+            self.gpr_used = align(self.gpr_used, 2);
+            self.offset = align(self.offset, 8);
+            return ValueConversion::IntSplit.into();
+        }
+
+        // Small integers are extended to the size of a register.
+        if ty.is_int() && ty.bits() < 32 {
+            match arg.extension {
+                ArgumentExtension::None => {}
+                ArgumentExtension::Uext => return ValueConversion::Uext(types::I32).into(),
+                ArgumentExtension::Sext => return ValueConversion::Sext(types::I32).into(),
+            }
+        }
+
+        // Try to use a register. Integer and floating point registers are allocated
+        // independently.
+        if ty == types::F64 {
+            self.fpr_used = align(self.fpr_used, 2);
+            if self.fpr_used < self.fpr_limit {
+                let reg = D.unit(self.fpr_used as usize / 2);
+                self.fpr_used += 2;
+                return ArgumentLoc::Reg(reg).into();
+            }
+        } else if ty.is_float() {
+            if self.fpr_used < self.fpr_limit {
+                let reg = S.unit(self.fpr_used as usize);
+                self.fpr_used += 1;
+                return ArgumentLoc::Reg(reg).into();
+            }
+        } else if self.gpr_used < self.gpr_limit {
+            let reg = GPR.unit(self.gpr_used as usize);
+            self.gpr_used += 1;
+            return ArgumentLoc::Reg(reg).into();
+        }
+
+        // Assign a stack location. Stack arguments use 4-byte slots, and `f64` arguments are
+        // 8-byte aligned.
+        let size = if ty.bytes() > 4 { 8 } else { 4 };
+        self.offset = align(self.offset, size);
+        let loc = ArgumentLoc::Stack(self.offset);
+        self.offset += size;
+        loc.into()
+    }
+}
+
+/// Legalize `sig` for AAPCS.
+pub fn legalize_signature(sig: &mut Signature, current: bool) {
+    let mut rets = Args::new(2, 8);
+    legalize_args(&mut sig.return_types, &mut rets);
+
+    // Return values that don't fit in `%r0`-`%r1` or `%d0`-`%d3` are returned in memory. The stack
+    // locations assigned above are offsets into a return area provided by the caller, and a
+    // pointer to the return area is passed as a hidden first argument in `%r0`.
+    if rets.offset > 0 &&
+       !sig.argument_types
+            .iter()
+            .any(|arg| arg.purpose == ArgumentPurpose::StructReturn) {
+        let mut sret = ArgumentType::new(types::I32);
+        sret.purpose = ArgumentPurpose::StructReturn;
+        sig.argument_types.insert(0, sret);
+    }
+
+    let mut args = Args::new(4, 16);
+    legalize_args(&mut sig.argument_types, &mut args);
+
+    if current {
+        // Add the link register as an argument and return value. The `bx lr` return instruction
+        // reads the return address from it.
+        let link = ArgumentType::special_reg(types::I32, ArgumentPurpose::Link, GPR.unit(LINK_REG));
+        sig.argument_types.push(link);
+        sig.return_types.push(link);
+    }
+}
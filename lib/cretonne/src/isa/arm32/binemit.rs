@@ -1,6 +1,745 @@
 //! Emitting binary ARM32 machine code.
+//!
+//! The A32 instructions are emitted as a single little-endian 32-bit word. The 32-bit T32
+//! instructions are emitted as two little-endian halfwords, most significant halfword first.
 
-use binemit::{CodeSink, bad_encoding};
-use ir::{Function, Inst};
+use binemit::{CodeSink, CodeOffset, Reloc, bad_encoding};
+use ir::{Function, Inst, InstructionData};
+use ir::condcodes::IntCC;
+use isa::RegUnit;
+use predicates::is_signed_int;
+use regalloc::RegDiversions;
 
 include!(concat!(env!("OUT_DIR"), "/binemit-arm32.rs"));
+
+/// ARM32 relocation kinds.
+pub enum RelocKind {
+    /// An A32 `bl` call to a function.
+    Call,
+    /// A T32 `bl` call to a function.
+    ThumbCall,
+}
+
+pub static RELOC_NAMES: [&'static str; 2] = ["Call", "ThumbCall"];
+
+/// Emit `bytes` bytes of padding as `nop` instructions.
+///
+/// T32 code is padded with the 16-bit `nop` encoding, so it only needs to be halfword aligned.
+pub fn emit_padding<CS: CodeSink + ?Sized>(t32: bool, bytes: CodeOffset, sink: &mut CS) {
+    if t32 {
+        assert_eq!(bytes % 2, 0, "Can't pad {} bytes", bytes);
+        for _ in 0..bytes / 2 {
+            sink.put2(0xbf00);
+        }
+    } else {
+        assert_eq!(bytes % 4, 0, "Can't pad {} bytes", bytes);
+        for _ in 0..bytes / 4 {
+            sink.put4(0xe320f000);
+        }
+    }
+}
+
+impl Into<Reloc> for RelocKind {
+    fn into(self) -> Reloc {
+        Reloc(self as u16)
+    }
+}
+
+/// The condition field for instructions that are always executed.
+const AL: u32 = 0b1110;
+
+/// The program counter `%r15`. Used as the first operand of T32 `orr` to encode `mov`.
+const PC: RegUnit = 15;
+
+/// Get the condition code field corresponding to `cond`.
+fn icc2cond(cond: IntCC) -> u32 {
+    use ir::condcodes::IntCC::*;
+    match cond {
+        Equal => 0b0000,
+        NotEqual => 0b0001,
+        UnsignedGreaterThanOrEqual => 0b0010,
+        UnsignedLessThan => 0b0011,
+        UnsignedGreaterThan => 0b1000,
+        UnsignedLessThanOrEqual => 0b1001,
+        SignedGreaterThanOrEqual => 0b1010,
+        SignedLessThan => 0b1011,
+        SignedGreaterThan => 0b1100,
+        SignedLessThanOrEqual => 0b1101,
+    }
+}
+
+/// Emit a 32-bit T32 instruction as two halfwords.
+fn put_t32<CS: CodeSink + ?Sized>(i: u32, sink: &mut CS) {
+    sink.put2((i >> 16) as u16);
+    sink.put2(i as u16);
+}
+
+// A32 instructions.
+
+/// Data-processing (register) instructions with an unshifted operand.
+///
+///   31   27  24     20 19 15 11     3
+///   cond 000 opcode S  rn rd 00000000 rm
+///     28  25     21 20 16 12        4  0
+///
+/// Encoding bits: `opcode`.
+fn put_adp<CS: CodeSink + ?Sized>(bits: u16,
+                                  rn: RegUnit,
+                                  rm: RegUnit,
+                                  rd: RegUnit,
+                                  sink: &mut CS) {
+    let bits = bits as u32 & 0xf;
+    let rn = rn as u32 & 0xf;
+    let rm = rm as u32 & 0xf;
+    let rd = rd as u32 & 0xf;
+
+    let mut i = AL << 28;
+    i |= bits << 21;
+    i |= rn << 16;
+    i |= rd << 12;
+    i |= rm;
+
+    sink.put4(i);
+}
+
+fn recipe_arr<CS: CodeSink + ?Sized>(func: &Function,
+                                     inst: Inst,
+                                     divert: &mut RegDiversions,
+                                     sink: &mut CS) {
+    if let InstructionData::Binary { args, .. } = func.dfg[inst] {
+        put_adp(func.encodings[inst].bits(),
+                divert.reg(args[0], &func.locations),
+                divert.reg(args[1], &func.locations),
+                func.locations[func.dfg.first_result(inst)].unwrap_reg(),
+                sink);
+    } else {
+        panic!("Expected Binary format: {:?}", func.dfg[inst]);
+    }
+}
+
+/// Data-processing (immediate) instructions with an unrotated 8-bit immediate.
+///
+///   31   27  24     20 19 15 11     7
+///   cond 001 opcode S  rn rd rotate imm8
+///     28  25     21 20 16 12      8    0
+///
+/// Encoding bits: `opcode`.
+fn recipe_ari<CS: CodeSink + ?Sized>(func: &Function,
+                                     inst: Inst,
+                                     divert: &mut RegDiversions,
+                                     sink: &mut CS) {
+    if let InstructionData::BinaryImm { arg, imm, .. } = func.dfg[inst] {
+        let bits = func.encodings[inst].bits() as u32 & 0xf;
+        let rn = divert.reg(arg, &func.locations) as u32 & 0xf;
+        let rd = func.locations[func.dfg.first_result(inst)].unwrap_reg() as u32 & 0xf;
+        let imm: i64 = imm.into();
+
+        let mut i = AL << 28;
+        i |= 0b001 << 25;
+        i |= bits << 21;
+        i |= rn << 16;
+        i |= rd << 12;
+        i |= imm as u32 & 0xff;
+
+        sink.put4(i);
+    } else {
+        panic!("Expected BinaryImm format: {:?}", func.dfg[inst]);
+    }
+}
+
+/// Immediate shifts encoded as `mov rd, rm, <type> #imm5`.
+///
+///   31   27  24   20 19   15 11   6    4 3
+///   cond 000 1101 S  0000 rd imm5 type 0 rm
+///     28  25   21 20   16 12    7    5 4  0
+///
+/// Encoding bits: `type`.
+fn recipe_arsh<CS: CodeSink + ?Sized>(func: &Function,
+                                      inst: Inst,
+                                      divert: &mut RegDiversions,
+                                      sink: &mut CS) {
+    if let InstructionData::BinaryImm { arg, imm, .. } = func.dfg[inst] {
+        let imm: i64 = imm.into();
+        // A zero immediate encodes a shift by 32 for the right shifts, so use `lsl #0` instead.
+        let ty = if imm == 0 {
+            0
+        } else {
+            func.encodings[inst].bits() as u32 & 0x3
+        };
+        let rm = divert.reg(arg, &func.locations) as u32 & 0xf;
+        let rd = func.locations[func.dfg.first_result(inst)].unwrap_reg() as u32 & 0xf;
+
+        let mut i = AL << 28;
+        i |= 0b1101 << 21;
+        i |= rd << 12;
+        i |= (imm as u32 & 0x1f) << 7;
+        i |= ty << 5;
+        i |= rm;
+
+        sink.put4(i);
+    } else {
+        panic!("Expected BinaryImm format: {:?}", func.dfg[inst]);
+    }
+}
+
+/// Multiply instructions.
+///
+///   31   27      20 19 15   11 7    3
+///   cond 0000000 S  rd 0000 rm 1001 rn
+///     28       21 20 16   12  8    4  0
+fn recipe_amul<CS: CodeSink + ?Sized>(func: &Function,
+                                      inst: Inst,
+                                      divert: &mut RegDiversions,
+                                      sink: &mut CS) {
+    if let InstructionData::Binary { args, .. } = func.dfg[inst] {
+        let rn = divert.reg(args[0], &func.locations) as u32 & 0xf;
+        let rm = divert.reg(args[1], &func.locations) as u32 & 0xf;
+        let rd = func.locations[func.dfg.first_result(inst)].unwrap_reg() as u32 & 0xf;
+
+        let mut i = AL << 28;
+        i |= rd << 16;
+        i |= rm << 8;
+        i |= 0b1001 << 4;
+        i |= rn;
+
+        sink.put4(i);
+    } else {
+        panic!("Expected Binary format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_amov<CS: CodeSink + ?Sized>(func: &Function,
+                                      inst: Inst,
+                                      divert: &mut RegDiversions,
+                                      sink: &mut CS) {
+    if let InstructionData::Unary { arg, .. } = func.dfg[inst] {
+        put_adp(func.encodings[inst].bits(),
+                0,
+                divert.reg(arg, &func.locations),
+                func.locations[func.dfg.first_result(inst)].unwrap_reg(),
+                sink);
+    } else {
+        panic!("Expected Unary format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_armov<CS: CodeSink + ?Sized>(func: &Function,
+                                       inst: Inst,
+                                       divert: &mut RegDiversions,
+                                       sink: &mut CS) {
+    if let InstructionData::RegMove { arg, src, dst, .. } = func.dfg[inst] {
+        divert.regmove(arg, src, dst);
+        put_adp(func.encodings[inst].bits(), 0, src, dst, sink);
+    } else {
+        panic!("Expected RegMove format: {:?}", func.dfg[inst]);
+    }
+}
+
+/// Move wide instructions.
+///
+///   31   27       19   15 11
+///   cond 00110000 imm4 rd imm12
+///     28       20   16 12     0
+fn recipe_amovw<CS: CodeSink + ?Sized>(func: &Function,
+                                       inst: Inst,
+                                       _divert: &mut RegDiversions,
+                                       sink: &mut CS) {
+    if let InstructionData::UnaryImm { imm, .. } = func.dfg[inst] {
+        let imm: i64 = imm.into();
+        let imm = imm as u32;
+        let rd = func.locations[func.dfg.first_result(inst)].unwrap_reg() as u32 & 0xf;
+
+        let mut i = AL << 28;
+        i |= 0b00110000 << 20;
+        i |= (imm >> 12 & 0xf) << 16;
+        i |= rd << 12;
+        i |= imm & 0xfff;
+
+        sink.put4(i);
+    } else {
+        panic!("Expected UnaryImm format: {:?}", func.dfg[inst]);
+    }
+}
+
+/// Branch instructions.
+///
+///   31   27  24 23
+///   cond 101 L  imm24
+///     28  25 24     0
+///
+/// The displacement `disp` is relative to the branch instruction itself.
+fn put_ab<CS: CodeSink + ?Sized>(cond: u32, link: bool, disp: i64, sink: &mut CS) {
+    // The PC reads as the instruction address + 8.
+    let disp = disp - 8;
+    assert!(is_signed_int(disp, 26, 2), "B out of range {:#x}", disp);
+
+    let mut i = cond << 28;
+    i |= 0b101 << 25;
+    if link {
+        i |= 1 << 24;
+    }
+    i |= (disp >> 2) as u32 & 0xffffff;
+
+    sink.put4(i);
+}
+
+fn recipe_ab<CS: CodeSink + ?Sized>(func: &Function,
+                                    inst: Inst,
+                                    _divert: &mut RegDiversions,
+                                    sink: &mut CS) {
+    if let InstructionData::Jump { destination, .. } = func.dfg[inst] {
+        let dest = func.offsets[destination] as i64;
+        let disp = dest - sink.offset() as i64;
+        put_ab(AL, false, disp, sink);
+    } else {
+        panic!("Expected Jump format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_abl<CS: CodeSink + ?Sized>(func: &Function,
+                                     inst: Inst,
+                                     _divert: &mut RegDiversions,
+                                     sink: &mut CS) {
+    if let InstructionData::Call { func_ref, .. } = func.dfg[inst] {
+        // The PC reads as the instruction address + 8.
+        sink.reloc_func(RelocKind::Call.into(), func_ref, -8);
+        // The relocation provides the offset.
+        put_ab(AL, true, 8, sink);
+    } else {
+        panic!("Expected Call format: {:?}", func.dfg[inst]);
+    }
+}
+
+/// Compare instructions, `cmp rn, rm`.
+///
+///   31   27  24   20 19 15   11       3
+///   cond 000 1010 1  rn 0000 00000000 rm
+///     28  25   21 20 16   12        4  0
+fn put_acmp<CS: CodeSink + ?Sized>(rn: RegUnit, rm: RegUnit, sink: &mut CS) {
+    let rn = rn as u32 & 0xf;
+    let rm = rm as u32 & 0xf;
+
+    let mut i = AL << 28;
+    i |= 0b0001_0101 << 20;
+    i |= rn << 16;
+    i |= rm;
+
+    sink.put4(i);
+}
+
+/// Compare with zero, `cmp rn, #0`, followed by a conditional branch.
+///
+/// Encoding bits: `cond` for the branch.
+fn recipe_abrz<CS: CodeSink + ?Sized>(func: &Function,
+                                      inst: Inst,
+                                      divert: &mut RegDiversions,
+                                      sink: &mut CS) {
+    if let InstructionData::Branch {
+               destination,
+               ref args,
+               ..
+           } = func.dfg[inst] {
+        let args = &args.as_slice(&func.dfg.value_lists)[0..1];
+        let rn = divert.reg(args[0], &func.locations) as u32 & 0xf;
+
+        let mut i = AL << 28;
+        i |= 0b0011_0101 << 20;
+        i |= rn << 16;
+        sink.put4(i);
+
+        let dest = func.offsets[destination] as i64;
+        let disp = dest - sink.offset() as i64;
+        put_ab(func.encodings[inst].bits() as u32 & 0xf, false, disp, sink);
+    } else {
+        panic!("Expected Branch format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_abricmp<CS: CodeSink + ?Sized>(func: &Function,
+                                         inst: Inst,
+                                         divert: &mut RegDiversions,
+                                         sink: &mut CS) {
+    if let InstructionData::BranchIcmp {
+               cond,
+               destination,
+               ref args,
+               ..
+           } = func.dfg[inst] {
+        let args = &args.as_slice(&func.dfg.value_lists)[0..2];
+        put_acmp(divert.reg(args[0], &func.locations),
+                 divert.reg(args[1], &func.locations),
+                 sink);
+
+        let dest = func.offsets[destination] as i64;
+        let disp = dest - sink.offset() as i64;
+        put_ab(icc2cond(cond), false, disp, sink);
+    } else {
+        panic!("Expected BranchIcmp format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_admb<CS: CodeSink + ?Sized>(func: &Function,
+                                      inst: Inst,
+                                      _divert: &mut RegDiversions,
+                                      sink: &mut CS) {
+    // dmb <option>
+    sink.put4(0xf57ff050 | func.encodings[inst].bits() as u32);
+}
+
+fn recipe_abx<CS: CodeSink + ?Sized>(_func: &Function,
+                                     _inst: Inst,
+                                     _divert: &mut RegDiversions,
+                                     sink: &mut CS) {
+    // bx lr
+    // The return address is provided as a special-purpose link argument.
+    sink.put4(0xe12fff1e);
+}
+
+// T32 instructions.
+
+/// Data-processing (shifted register) instructions with a zero shift.
+///
+///   31      24     20 19   15 14   11 7    5    3
+///   1110101 opcode S  rn | 0  imm3 rd imm2 type rm
+///        25     21 20 16 | 15   12  8    6    4  0
+///
+/// Encoding bits: `opcode`.
+fn put_tdp<CS: CodeSink + ?Sized>(bits: u16,
+                                  rn: RegUnit,
+                                  rm: RegUnit,
+                                  rd: RegUnit,
+                                  sink: &mut CS) {
+    let bits = bits as u32 & 0xf;
+    let rn = rn as u32 & 0xf;
+    let rm = rm as u32 & 0xf;
+    let rd = rd as u32 & 0xf;
+
+    let mut i = 0b1110101 << 25;
+    i |= bits << 21;
+    i |= rn << 16;
+    i |= rd << 8;
+    i |= rm;
+
+    put_t32(i, sink);
+}
+
+fn recipe_trr<CS: CodeSink + ?Sized>(func: &Function,
+                                     inst: Inst,
+                                     divert: &mut RegDiversions,
+                                     sink: &mut CS) {
+    if let InstructionData::Binary { args, .. } = func.dfg[inst] {
+        put_tdp(func.encodings[inst].bits(),
+                divert.reg(args[0], &func.locations),
+                divert.reg(args[1], &func.locations),
+                func.locations[func.dfg.first_result(inst)].unwrap_reg(),
+                sink);
+    } else {
+        panic!("Expected Binary format: {:?}", func.dfg[inst]);
+    }
+}
+
+/// Data-processing (modified immediate) instructions with a plain 8-bit immediate.
+///
+///   31    26 25 24     20 19   15 14   11 7
+///   11110 i  0  opcode S  rn | 0  imm3 rd imm8
+///      27 26 25     21 20 16 | 15   12  8    0
+///
+/// Encoding bits: `opcode`.
+fn recipe_tri<CS: CodeSink + ?Sized>(func: &Function,
+                                     inst: Inst,
+                                     divert: &mut RegDiversions,
+                                     sink: &mut CS) {
+    if let InstructionData::BinaryImm { arg, imm, .. } = func.dfg[inst] {
+        let bits = func.encodings[inst].bits() as u32 & 0xf;
+        let rn = divert.reg(arg, &func.locations) as u32 & 0xf;
+        let rd = func.locations[func.dfg.first_result(inst)].unwrap_reg() as u32 & 0xf;
+        let imm: i64 = imm.into();
+
+        let mut i = 0b11110 << 27;
+        i |= bits << 21;
+        i |= rn << 16;
+        i |= rd << 8;
+        i |= imm as u32 & 0xff;
+
+        put_t32(i, sink);
+    } else {
+        panic!("Expected BinaryImm format: {:?}", func.dfg[inst]);
+    }
+}
+
+/// Immediate shifts encoded as `mov.w rd, rm, <type> #imm5`. The 5-bit shift amount is split into
+/// `imm3:imm2`.
+///
+/// Encoding bits: `type`.
+fn recipe_trsh<CS: CodeSink + ?Sized>(func: &Function,
+                                      inst: Inst,
+                                      divert: &mut RegDiversions,
+                                      sink: &mut CS) {
+    if let InstructionData::BinaryImm { arg, imm, .. } = func.dfg[inst] {
+        let imm: i64 = imm.into();
+        let imm = imm as u32 & 0x1f;
+        // A zero immediate encodes a shift by 32 for the right shifts, so use `lsl #0` instead.
+        let ty = if imm == 0 {
+            0
+        } else {
+            func.encodings[inst].bits() as u32 & 0x3
+        };
+        let rm = divert.reg(arg, &func.locations) as u32 & 0xf;
+        let rd = func.locations[func.dfg.first_result(inst)].unwrap_reg() as u32 & 0xf;
+
+        // `mov.w` is `orr.w` with `%r15` as the first operand.
+        let mut i = 0xea4f0000;
+        i |= (imm >> 2) << 12;
+        i |= rd << 8;
+        i |= (imm & 0x3) << 6;
+        i |= ty << 4;
+        i |= rm;
+
+        put_t32(i, sink);
+    } else {
+        panic!("Expected BinaryImm format: {:?}", func.dfg[inst]);
+    }
+}
+
+/// Multiply instructions.
+///
+///   31        22  20 19   15   11 7    3
+///   111110110 000 rn | 1111 rd 0000 rm
+///          23  20 16 | 15   12  8    4  0
+fn recipe_tmul<CS: CodeSink + ?Sized>(func: &Function,
+                                      inst: Inst,
+                                      divert: &mut RegDiversions,
+                                      sink: &mut CS) {
+    if let InstructionData::Binary { args, .. } = func.dfg[inst] {
+        let rn = divert.reg(args[0], &func.locations) as u32 & 0xf;
+        let rm = divert.reg(args[1], &func.locations) as u32 & 0xf;
+        let rd = func.locations[func.dfg.first_result(inst)].unwrap_reg() as u32 & 0xf;
+
+        let mut i = 0xfb00f000;
+        i |= rn << 16;
+        i |= rd << 8;
+        i |= rm;
+
+        put_t32(i, sink);
+    } else {
+        panic!("Expected Binary format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_tmov<CS: CodeSink + ?Sized>(func: &Function,
+                                      inst: Inst,
+                                      divert: &mut RegDiversions,
+                                      sink: &mut CS) {
+    if let InstructionData::Unary { arg, .. } = func.dfg[inst] {
+        put_tdp(func.encodings[inst].bits(),
+                PC,
+                divert.reg(arg, &func.locations),
+                func.locations[func.dfg.first_result(inst)].unwrap_reg(),
+                sink);
+    } else {
+        panic!("Expected Unary format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_trmov<CS: CodeSink + ?Sized>(func: &Function,
+                                       inst: Inst,
+                                       divert: &mut RegDiversions,
+                                       sink: &mut CS) {
+    if let InstructionData::RegMove { arg, src, dst, .. } = func.dfg[inst] {
+        divert.regmove(arg, src, dst);
+        put_tdp(func.encodings[inst].bits(), PC, src, dst, sink);
+    } else {
+        panic!("Expected RegMove format: {:?}", func.dfg[inst]);
+    }
+}
+
+/// Move wide instructions.
+///
+///   31    26 25       19     15 14   11 7
+///   11110 i  100100 imm4 | 0  imm3 rd imm8
+///      27 26     20   16 | 15   12  8    0
+fn recipe_tmovw<CS: CodeSink + ?Sized>(func: &Function,
+                                       inst: Inst,
+                                       _divert: &mut RegDiversions,
+                                       sink: &mut CS) {
+    if let InstructionData::UnaryImm { imm, .. } = func.dfg[inst] {
+        let imm: i64 = imm.into();
+        let imm = imm as u32;
+        let rd = func.locations[func.dfg.first_result(inst)].unwrap_reg() as u32 & 0xf;
+
+        let mut i = 0xf2400000;
+        i |= (imm >> 11 & 0x1) << 26;
+        i |= (imm >> 12 & 0xf) << 16;
+        i |= (imm >> 8 & 0x7) << 12;
+        i |= rd << 8;
+        i |= imm & 0xff;
+
+        put_t32(i, sink);
+    } else {
+        panic!("Expected UnaryImm format: {:?}", func.dfg[inst]);
+    }
+}
+
+/// Unconditional branch instructions, `b.w` and `bl`.
+///
+///   31    26 25      15 14 13 12 11 10
+///   11110 S  imm10 | 1  L  J1 1  J2 imm11
+///      27 26    16 | 15 14 13 12 11     0
+///
+/// The displacement `disp` is relative to the branch instruction itself. It is encoded as
+/// `S:I1:I2:imm10:imm11:0` where `J1 = !(I1 ^ S)` and `J2 = !(I2 ^ S)`.
+fn put_tb<CS: CodeSink + ?Sized>(link: bool, disp: i64, sink: &mut CS) {
+    // The PC reads as the instruction address + 4.
+    let disp = disp - 4;
+    assert!(is_signed_int(disp, 25, 1), "B.W out of range {:#x}", disp);
+
+    let disp = disp as u32;
+    let s = disp >> 24 & 1;
+    let j1 = !(disp >> 23 ^ s) & 1;
+    let j2 = !(disp >> 22 ^ s) & 1;
+
+    let mut i = 0b11110 << 27;
+    i |= s << 26;
+    i |= (disp >> 12 & 0x3ff) << 16;
+    let op = if link { 0b1101 } else { 0b1001 };
+    i |= op << 12;
+    i |= j1 << 13;
+    i |= j2 << 11;
+    i |= disp >> 1 & 0x7ff;
+
+    put_t32(i, sink);
+}
+
+fn recipe_tb<CS: CodeSink + ?Sized>(func: &Function,
+                                    inst: Inst,
+                                    _divert: &mut RegDiversions,
+                                    sink: &mut CS) {
+    if let InstructionData::Jump { destination, .. } = func.dfg[inst] {
+        let dest = func.offsets[destination] as i64;
+        let disp = dest - sink.offset() as i64;
+        put_tb(false, disp, sink);
+    } else {
+        panic!("Expected Jump format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_tbl<CS: CodeSink + ?Sized>(func: &Function,
+                                     inst: Inst,
+                                     _divert: &mut RegDiversions,
+                                     sink: &mut CS) {
+    if let InstructionData::Call { func_ref, .. } = func.dfg[inst] {
+        // The PC reads as the instruction address + 4.
+        sink.reloc_func(RelocKind::ThumbCall.into(), func_ref, -4);
+        // The relocation provides the offset.
+        put_tb(true, 4, sink);
+    } else {
+        panic!("Expected Call format: {:?}", func.dfg[inst]);
+    }
+}
+
+/// Conditional branch instructions, `b<cond>.w`.
+///
+///   31    26 25   21     15 14 13 12 11 10
+///   11110 S  cond imm6 | 1  0  J1 0  J2 imm11
+///      27 26   22   16 | 15 14 13 12 11     0
+///
+/// The displacement `disp` is relative to the branch instruction itself. It is encoded as
+/// `S:J2:J1:imm6:imm11:0`.
+fn put_tbcond<CS: CodeSink + ?Sized>(cond: u32, disp: i64, sink: &mut CS) {
+    // The PC reads as the instruction address + 4.
+    let disp = disp - 4;
+    assert!(is_signed_int(disp, 21, 1), "B<cond>.W out of range {:#x}", disp);
+
+    let disp = disp as u32;
+
+    let mut i = 0b11110 << 27;
+    i |= (disp >> 20 & 1) << 26;
+    i |= (cond & 0xf) << 22;
+    i |= (disp >> 12 & 0x3f) << 16;
+    i |= 0b10 << 14;
+    i |= (disp >> 18 & 1) << 13;
+    i |= (disp >> 19 & 1) << 11;
+    i |= disp >> 1 & 0x7ff;
+
+    put_t32(i, sink);
+}
+
+/// Compare instructions, `cmp.w rn, rm`.
+///
+///   31      24   20 19   15 14   11   7    5    3
+///   1110101 1101 1  rn | 0  imm3 1111 imm2 type rm
+///        25   21 20 16 | 15   12    8    6    4  0
+fn put_tcmp<CS: CodeSink + ?Sized>(rn: RegUnit, rm: RegUnit, sink: &mut CS) {
+    let rn = rn as u32 & 0xf;
+    let rm = rm as u32 & 0xf;
+
+    let mut i = 0xebb00f00;
+    i |= rn << 16;
+    i |= rm;
+
+    put_t32(i, sink);
+}
+
+/// Compare with zero, `cmp.w rn, #0`, followed by a conditional branch.
+///
+/// Encoding bits: `cond` for the branch.
+fn recipe_tbrz<CS: CodeSink + ?Sized>(func: &Function,
+                                      inst: Inst,
+                                      divert: &mut RegDiversions,
+                                      sink: &mut CS) {
+    if let InstructionData::Branch {
+               destination,
+               ref args,
+               ..
+           } = func.dfg[inst] {
+        let args = &args.as_slice(&func.dfg.value_lists)[0..1];
+        let rn = divert.reg(args[0], &func.locations) as u32 & 0xf;
+        put_t32(0xf1b00f00 | rn << 16, sink);
+
+        let dest = func.offsets[destination] as i64;
+        let disp = dest - sink.offset() as i64;
+        put_tbcond(func.encodings[inst].bits() as u32, disp, sink);
+    } else {
+        panic!("Expected Branch format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_tbricmp<CS: CodeSink + ?Sized>(func: &Function,
+                                         inst: Inst,
+                                         divert: &mut RegDiversions,
+                                         sink: &mut CS) {
+    if let InstructionData::BranchIcmp {
+               cond,
+               destination,
+               ref args,
+               ..
+           } = func.dfg[inst] {
+        let args = &args.as_slice(&func.dfg.value_lists)[0..2];
+        put_tcmp(divert.reg(args[0], &func.locations),
+                 divert.reg(args[1], &func.locations),
+                 sink);
+
+        let dest = func.offsets[destination] as i64;
+        let disp = dest - sink.offset() as i64;
+        put_tbcond(icc2cond(cond), disp, sink);
+    } else {
+        panic!("Expected BranchIcmp format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_tdmb<CS: CodeSink + ?Sized>(func: &Function,
+                                      inst: Inst,
+                                      _divert: &mut RegDiversions,
+                                      sink: &mut CS) {
+    // dmb <option>
+    put_t32(0xf3bf8f50 | func.encodings[inst].bits() as u32, sink);
+}
+
+fn recipe_tbx<CS: CodeSink + ?Sized>(_func: &Function,
+                                     _inst: Inst,
+                                     _divert: &mut RegDiversions,
+                                     sink: &mut CS) {
+    // bx lr
+    // The return address is provided as a special-purpose link argument.
+    sink.put2(0x4770);
+}
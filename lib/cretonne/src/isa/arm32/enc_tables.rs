@@ -1,10 +1,18 @@
 //! Encoding tables for ARM32 ISA.
 
-use ir::InstructionData;
 use ir::types;
+use ir::{Opcode, InstructionData, DataFlowGraph};
 use isa::EncInfo;
 use isa::constraints::*;
 use isa::enc_tables::{Level1Entry, Level2Entry};
 use isa::encoding::RecipeSizing;
+use predicates;
+use super::registers::*;
 
+// Include the generated encoding tables:
+// - `LEVEL1_A32`
+// - `LEVEL1_T32`
+// - `LEVEL2`
+// - `ENCLIST`
+// - `INFO`
 include!(concat!(env!("OUT_DIR"), "/encoding-arm32.rs"));
@@ -1,16 +1,22 @@
 //! ARM 32-bit Instruction Set Architecture.
+//!
+//! The backend doesn't have the stack pointer adjustments, spills, and fills needed to lay out a
+//! stack frame yet, so it uses the default `prologue_epilogue()` which fails with an
+//! `Unsupported` error. Functions can be legalized and encoded, but not fully compiled.
 
 pub mod settings;
+mod abi;
 mod binemit;
 mod enc_tables;
 mod registers;
 
-use binemit::CodeSink;
+use binemit::{CodeSink, CodeOffset, MemoryCodeSink, emit_function};
 use super::super::settings as shared_settings;
-use isa::enc_tables::{self as shared_enc_tables, lookup_enclist, general_encoding};
+use isa::enc_tables::{self as shared_enc_tables, lookup_enclist, general_encoding, Encodings};
 use isa::Builder as IsaBuilder;
-use isa::{TargetIsa, RegInfo, EncInfo, Encoding, Legalize};
+use isa::{TargetIsa, RegInfo, RegUnit, EncInfo, Encoding, Legalize};
 use ir;
+use regalloc::RegDiversions;
 
 #[allow(dead_code)]
 struct Isa {
@@ -23,6 +29,7 @@ struct Isa {
 pub fn isa_builder() -> IsaBuilder {
     IsaBuilder {
         setup: settings::builder(),
+        shared_defaults: Vec::new(),
         constructor: isa_constructor,
     }
 }
@@ -30,13 +37,14 @@ pub fn isa_builder() -> IsaBuilder {
 fn isa_constructor(shared_flags: shared_settings::Flags,
                    builder: &shared_settings::Builder)
                    -> Box<TargetIsa> {
-    let level1 = if shared_flags.is_compressed() {
+    let isa_flags = settings::Flags::new(&shared_flags, builder);
+    let level1 = if isa_flags.use_t32() {
         &enc_tables::LEVEL1_T32[..]
     } else {
         &enc_tables::LEVEL1_A32[..]
     };
     Box::new(Isa {
-                 isa_flags: settings::Flags::new(&shared_flags, builder),
+                 isa_flags: isa_flags,
                  shared_flags: shared_flags,
                  cpumode: level1,
              })
@@ -51,6 +59,10 @@ impl TargetIsa for Isa {
         &self.shared_flags
     }
 
+    fn isa_settings_bytes(&self) -> &[u8] {
+        self.isa_flags.settings_bytes()
+    }
+
     fn register_info(&self) -> RegInfo {
         registers::INFO.clone()
     }
@@ -59,8 +71,12 @@ impl TargetIsa for Isa {
         enc_tables::INFO.clone()
     }
 
+    fn reserved_regs(&self) -> &'static [RegUnit] {
+        &abi::RESERVED_REGS
+    }
+
     fn encode(&self,
-              _dfg: &ir::DataFlowGraph,
+              dfg: &ir::DataFlowGraph,
               inst: &ir::InstructionData,
               ctrl_typevar: ir::Type)
               -> Result<Encoding, Legalize> {
@@ -71,13 +87,137 @@ impl TargetIsa for Isa {
                 .and_then(|enclist_offset| {
                     general_encoding(enclist_offset,
                                      &enc_tables::ENCLISTS[..],
-                                     |instp| enc_tables::check_instp(inst, instp),
+                                     |instp| enc_tables::check_instp(inst, instp, dfg),
                                      |isap| self.isa_flags.numbered_predicate(isap as usize))
                             .ok_or(Legalize::Expand)
                 })
     }
 
-    fn emit_inst(&self, func: &ir::Function, inst: ir::Inst, sink: &mut CodeSink) {
-        binemit::emit_inst(func, inst, sink)
+    fn legal_encodings<'a>(&'a self,
+                           dfg: &'a ir::DataFlowGraph,
+                           inst: &'a ir::InstructionData,
+                           ctrl_typevar: ir::Type)
+                           -> Result<Box<Iterator<Item = Encoding> + 'a>, Legalize> {
+        lookup_enclist(ctrl_typevar, inst.opcode(), self.cpumode, &enc_tables::LEVEL2[..])
+            .map(|enclist_offset| {
+                let encs = Encodings::new(enclist_offset,
+                                          &enc_tables::ENCLISTS[..],
+                                          move |instp| enc_tables::check_instp(inst, instp, dfg),
+                                          move |isap| {
+                                              self.isa_flags.numbered_predicate(isap as usize)
+                                          });
+                Box::new(encs) as Box<Iterator<Item = Encoding>>
+            })
+    }
+
+    fn legalize_signature(&self, sig: &mut ir::Signature, current: bool) {
+        abi::legalize_signature(sig, current)
+    }
+
+    fn emit_inst(&self,
+                 func: &ir::Function,
+                 inst: ir::Inst,
+                 divert: &mut RegDiversions,
+                 sink: &mut CodeSink) {
+        binemit::emit_inst(func, inst, divert, sink)
+    }
+
+    fn uses_compressed_code(&self) -> bool {
+        self.isa_flags.use_t32()
+    }
+
+    fn emit_padding(&self, bytes: CodeOffset, sink: &mut CodeSink) {
+        binemit::emit_padding(self.isa_flags.use_t32(), bytes, sink)
+    }
+
+    fn emit_function(&self, func: &ir::Function, sink: &mut MemoryCodeSink) {
+        let t32 = self.isa_flags.use_t32();
+        emit_function(func,
+                      binemit::emit_inst,
+                      |bytes, sink| binemit::emit_padding(t32, bytes, sink),
+                      sink)
+    }
+
+    fn reloc_names(&self) -> &'static [&'static str] {
+        &binemit::RELOC_NAMES
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use settings::{self, Configurable};
+    use isa;
+    use ir::{DataFlowGraph, InstructionData, Opcode, Function, Cursor, InstBuilder};
+    use ir::{types, immediates};
+    use result::{CtonError, ErrorKind};
+    use Context;
+
+    fn encstr(isa: &isa::TargetIsa, enc: isa::Encoding) -> String {
+        isa.encoding_info().display(enc).to_string()
+    }
+
+    #[test]
+    fn test_cpumode() {
+        let dfg = DataFlowGraph::new();
+        let iconst = InstructionData::UnaryImm {
+            opcode: Opcode::Iconst,
+            imm: immediates::Imm64::new(0x1234),
+        };
+
+        // ARM code by default.
+        let shared_flags = settings::Flags::new(&settings::builder());
+        let isa = isa::lookup("arm32").unwrap().finish(shared_flags);
+        assert_eq!(encstr(&*isa, isa.encode(&dfg, &iconst, types::I32).unwrap()),
+                   "Amovw#00");
+        assert!(!isa.uses_compressed_code());
+
+        // Thumb-2 code for compressed instructions.
+        let mut shared_builder = settings::builder();
+        shared_builder.set_bool("is_compressed", true).unwrap();
+        let shared_flags = settings::Flags::new(&shared_builder);
+        let isa = isa::lookup("arm32").unwrap().finish(shared_flags);
+        assert_eq!(encstr(&*isa, isa.encode(&dfg, &iconst, types::I32).unwrap()),
+                   "Tmovw#00");
+        assert!(isa.uses_compressed_code());
+
+        // Thumb-2 code for CPUs without the ARM instruction set.
+        let shared_flags = settings::Flags::new(&settings::builder());
+        let mut isa_builder = isa::lookup("arm32").unwrap();
+        isa_builder.set_bool("has_a32", false).unwrap();
+        let isa = isa_builder.finish(shared_flags);
+        assert_eq!(encstr(&*isa, isa.encode(&dfg, &iconst, types::I32).unwrap()),
+                   "Tmovw#00");
+        assert!(isa.uses_compressed_code());
+
+        // Constants wider than 16 bits need more than one instruction.
+        let iconst = InstructionData::UnaryImm {
+            opcode: Opcode::Iconst,
+            imm: immediates::Imm64::new(0x1_0000),
+        };
+        assert_eq!(isa.encode(&dfg, &iconst, types::I32),
+                   Err(isa::Legalize::Expand));
+    }
+
+    #[test]
+    fn unsupported_prologue() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            dfg.ins(pos).return_(&[]);
+        }
+
+        let shared_flags = settings::Flags::new(&settings::builder());
+        let isa = isa::lookup("arm32").unwrap().finish(shared_flags);
+        let mut ctx = Context::new();
+        ctx.func = func;
+        match ctx.compile(&*isa) {
+            Err(CtonError { kind: ErrorKind::Unsupported, pass, .. }) => {
+                assert_eq!(pass, Some("prologue_epilogue"))
+            }
+            result => panic!("Unexpected result: {:?}", result),
+        }
     }
 }
@@ -7,3 +7,36 @@ use std::fmt;
 // `Flags` struct with an impl for all of the settings defined in
 // `lib/cretonne/meta/cretonne/settings.py`.
 include!(concat!(env!("OUT_DIR"), "/settings-arm32.rs"));
+
+#[cfg(test)]
+mod tests {
+    use super::{builder, Flags};
+    use settings::{self, Configurable};
+
+    #[test]
+    fn display_default() {
+        let shared = settings::Flags::new(&settings::builder());
+        let b = builder();
+        let f = Flags::new(&shared, &b);
+        assert_eq!(f.to_string(), "[arm32]\nhas_a32 = true\n");
+        // Predicates are not part of the Display output.
+        assert_eq!(f.use_t32(), false);
+    }
+
+    #[test]
+    fn predicates() {
+        // Compressed code uses the Thumb-2 instruction set.
+        let mut sb = settings::builder();
+        sb.set_bool("is_compressed", true).unwrap();
+        let shared = settings::Flags::new(&sb);
+        let f = Flags::new(&shared, &builder());
+        assert_eq!(f.use_t32(), true);
+
+        // So do CPUs without the ARM instruction set.
+        let shared = settings::Flags::new(&settings::builder());
+        let mut b = builder();
+        b.set_bool("has_a32", false).unwrap();
+        let f = Flags::new(&shared, &b);
+        assert_eq!(f.use_t32(), true);
+    }
+}
@@ -0,0 +1,323 @@
+//! ARM64 ABI implementation.
+//!
+//! This module implements the AAPCS64 calling convention used by System V targets through the
+//! primary `legalize_signature()` entry point, and the function prologue and epilogue through
+//! `prologue_epilogue()`.
+
+use abi::{ArgAction, ValueConversion, ArgAssigner, legalize_args};
+use ir::{Signature, ArgumentType, ArgumentLoc, ArgumentExtension, ArgumentPurpose};
+use ir::{Function, ValueLoc, Cursor, InstBuilder, StackSlotData, StackSlotKind,
+         FrameLayoutChange};
+use ir::types;
+use isa::{TargetIsa, RegUnit};
+use isa::frame::{used_callee_saved, save_link_reg, restore_link_reg};
+use isa::arm64::registers::{GPR, FPR};
+use result::{CtonError, CtonResult, ErrorKind};
+use stack_layout::{layout_stack, layout_incoming_args};
+
+/// The platform register `%x18` and the stack pointer / zero register `%x31` are never allocated.
+pub static RESERVED_REGS: [RegUnit; 2] = [18, 31];
+
+/// The indirect result location register `%x8` holds the `sret` pointer.
+const SRET_REG: usize = 8;
+
+/// The link register `%x30` holds the return address.
+const LINK_REG: usize = 30;
+
+struct Args {
+    gpr_used: u32,
+    fpr_used: u32,
+    offset: u32,
+}
+
+impl Args {
+    fn new() -> Args {
+        Args {
+            gpr_used: 0,
+            fpr_used: 0,
+            offset: 0,
+        }
+    }
+}
+
+impl ArgAssigner for Args {
+    fn assign(&mut self, arg: &ArgumentType) -> ArgAction {
+        fn align(value: u32, to: u32) -> u32 {
+            (value + to - 1) & !(to - 1)
+        }
+
+        let ty = arg.value_type;
+
+        // Check for a legal type.
+        // We don't support the SIMD registers yet, so break all vectors down.
+        if !ty.is_scalar() {
+            return ValueConversion::VectorSplit.into();
+        }
+
+        // Large integers and booleans are broken down to fit in a register.
+        if !ty.is_float() && ty.bits() > 64 {
+            // Quad-word integers start in an even-numbered register or a 16-byte aligned stack
+            // slot.
+            self.gpr_used = align(self.gpr_used, 2);
+            self.offset = align(self.offset, 16);
+            return ValueConversion::IntSplit.into();
+        }
+
+        // Small integers are extended to the size of a pointer register.
+        if ty.is_int() && ty.bits() < 64 {
+            match arg.extension {
+                ArgumentExtension::None => {}
+                ArgumentExtension::Uext => return ValueConversion::Uext(types::I64).into(),
+                ArgumentExtension::Sext => return ValueConversion::Sext(types::I64).into(),
+            }
+        }
+
+        // Try to use a register. Integer and floating point registers are allocated
+        // independently.
+        if ty.is_float() {
+            if self.fpr_used < 8 {
+                let reg = FPR.unit(self.fpr_used as usize);
+                self.fpr_used += 1;
+                return ArgumentLoc::Reg(reg).into();
+            }
+        } else if self.gpr_used < 8 {
+            let reg = GPR.unit(self.gpr_used as usize);
+            self.gpr_used += 1;
+            return ArgumentLoc::Reg(reg).into();
+        }
+
+        // Assign a stack location. Stack arguments use 8-byte slots.
+        let loc = ArgumentLoc::Stack(self.offset);
+        self.offset += 8;
+        loc.into()
+    }
+}
+
+/// Legalize `sig` for AAPCS64.
+pub fn legalize_signature(sig: &mut Signature, current: bool) {
+    let mut args = Args::new();
+    legalize_args(&mut sig.argument_types, &mut args);
+
+    let mut rets = Args::new();
+    legalize_args(&mut sig.return_types, &mut rets);
+
+    // Return values that don't fit in registers are returned in memory. The stack locations
+    // assigned above are offsets into a return area provided by the caller, and a pointer to the
+    // return area is passed in `%x8`, which is not one of the normal argument registers.
+    if rets.offset > 0 &&
+       !sig.argument_types
+            .iter()
+            .any(|arg| arg.purpose == ArgumentPurpose::StructReturn) {
+        let sret = ArgumentType::special_reg(types::I64,
+                                             ArgumentPurpose::StructReturn,
+                                             GPR.unit(SRET_REG));
+        sig.argument_types.push(sret);
+    }
+
+    if current {
+        // Add the link register as an argument and return value.
+        let link =
+            ArgumentType::special_reg(types::I64, ArgumentPurpose::Link, GPR.unit(LINK_REG));
+        sig.argument_types.push(link);
+        sig.return_types.push(link);
+    }
+}
+
+/// The stack pointer must be 16-byte aligned at all times.
+const STACK_ALIGNMENT: u32 = 16;
+
+/// Is `reg` one of the callee-saved registers?
+///
+/// These are the integer registers `%x19`-`%x29` and the floating point registers `%v8`-`%v15`.
+/// Only the low 64 bits of the floating point registers are preserved. The stack pointer is also
+/// callee-saved, but it is maintained by the prologue and epilogue directly.
+fn is_callee_saved(reg: RegUnit) -> bool {
+    (reg >= GPR.unit(19) && reg <= GPR.unit(29)) || (reg >= FPR.unit(8) && reg <= FPR.unit(15))
+}
+
+/// Get the registers that are clobbered by a call.
+///
+/// These are the allocatable registers that aren't callee-saved, including the link register.
+pub fn call_clobbers() -> Vec<RegUnit> {
+    (0..32)
+        .map(|i| GPR.unit(i))
+        .chain((0..32).map(|i| FPR.unit(i)))
+        .filter(|reg| !RESERVED_REGS.contains(reg) && !is_callee_saved(*reg))
+        .collect()
+}
+
+/// Get the DWARF register number of `reg`.
+///
+/// The `x` registers are numbered 0-31 and the `v` registers 64-95.
+pub fn map_dwarf_register(reg: RegUnit) -> Option<u16> {
+    if GPR.contains(reg) {
+        Some(reg - GPR.unit(0))
+    } else if FPR.contains(reg) {
+        Some(64 + reg - FPR.unit(0))
+    } else {
+        None
+    }
+}
+
+/// Insert the prologue and epilogue code for `func` after register allocation.
+///
+/// Any callee-saved registers used by the register allocator are saved in spill slots at the top
+/// of the entry block and restored before every return. They are represented in the signature as
+/// `csr` arguments and return values so the saved values are visibly passed through the function.
+///
+/// The calls in a non-leaf function overwrite the link register, so the return address is saved
+/// and restored the same way. A leaf function keeps it in the link register.
+/// Floating point registers are saved as `f64` values.
+///
+/// Finally, the stack frame is laid out, and the stack pointer is adjusted to make room for it.
+/// The frame layout changes are recorded in `func.frame_layout`.
+pub fn prologue_epilogue(func: &mut Function, isa: &TargetIsa) -> CtonResult {
+    let entry = func.layout
+        .entry_block()
+        .expect("Function has no entry block");
+
+    // Instructions inserted here that need encodings.
+    let mut new_insts = Vec::new();
+
+    // On entry, the CFA is the stack pointer and the return address is in the link register.
+    let sp = GPR.unit(31);
+    func.frame_layout.clear();
+    func.frame_layout
+        .initial
+        .push(FrameLayoutChange::CallFrameAddressAt { reg: sp, offset: 0 });
+
+    // Save the used callee-saved registers at the top of the entry block.
+    let csrs = used_callee_saved(func, is_callee_saved);
+    let mut saves = Vec::with_capacity(csrs.len());
+    // The link register is saved first, so the callee-saved register spills are inserted above it.
+    let link_save = save_link_reg(func, entry, types::I64)?;
+    {
+        let mut pos = Cursor::new(&mut func.layout);
+        pos.goto_top(entry);
+        pos.next_inst();
+
+        for &reg in &csrs {
+            let ty = if reg >= FPR.unit(0) {
+                types::F64
+            } else {
+                types::I64
+            };
+            let csr = ArgumentType::special_reg(ty, ArgumentPurpose::CalleeSaved, reg);
+            func.signature.argument_types.push(csr);
+            func.signature.return_types.push(csr);
+
+            let arg = func.dfg.append_ebb_arg(entry, ty);
+            *func.locations.ensure(arg) = ValueLoc::Reg(reg);
+
+            let ss = func.stack_slots
+                .push(StackSlotData::new(StackSlotKind::SpillSlot, ty.bytes()));
+            let saved = func.dfg.ins(&mut pos).spill(arg);
+            *func.locations.ensure(saved) = ValueLoc::Stack(ss);
+            saves.push((reg, saved));
+        }
+    }
+    new_insts.extend(saves
+                         .iter()
+                         .chain(&link_save)
+                         .map(|&(_, saved)| func.dfg.value_def(saved).unwrap_inst()));
+
+    // All the stack slots are known now.
+    let frame_size = layout_stack(&mut func.stack_slots, STACK_ALIGNMENT)?;
+
+    // The stack pointer adjustment must fit in a 12-bit unsigned immediate, which also keeps all
+    // the SP-relative offsets in range of the scaled load and store offsets.
+    if frame_size >= 4096 {
+        return Err(CtonError::new(ErrorKind::ImplLimitExceeded)
+                       .context(format!("stack frame of {} bytes exceeds 4 KB", frame_size)));
+    }
+
+    // The stack arguments are just above the frame.
+    layout_incoming_args(&mut func.stack_slots, frame_size)?;
+
+    // Allocate the stack frame before anything else.
+    if frame_size > 0 {
+        let mut pos = Cursor::new(&mut func.layout);
+        pos.goto_top(entry);
+        pos.next_inst();
+        let adjust = func.dfg.ins(&mut pos).adjust_sp_imm(-(frame_size as i64));
+        func.frame_layout
+            .push(adjust,
+                  FrameLayoutChange::CallFrameAddressAt {
+                      reg: sp,
+                      offset: frame_size as i32,
+                  });
+        new_insts.push(adjust);
+    }
+
+    // The save slots are addressed relative to the stack pointer after the frame is allocated.
+    for &(reg, saved) in saves.iter().chain(&link_save) {
+        let inst = func.dfg.value_def(saved).unwrap_inst();
+        let cfa_offset = match func.locations[saved] {
+            ValueLoc::Stack(ss) => func.stack_slots[ss].offset - frame_size as i32,
+            _ => panic!("Callee-saved register not spilled"),
+        };
+        let change = if link_save.map_or(false, |(_, link)| link == saved) {
+            FrameLayoutChange::ReturnAddressAt { cfa_offset: cfa_offset }
+        } else {
+            FrameLayoutChange::RegAt {
+                reg: reg,
+                cfa_offset: cfa_offset,
+            }
+        };
+        func.frame_layout.push(inst, change);
+    }
+
+    // Restore the callee-saved registers and the return address, and free the stack frame before
+    // every return.
+    let mut returns = Vec::new();
+    for ebb in func.layout.ebbs() {
+        if let Some(inst) = func.layout.last_inst(ebb) {
+            if func.dfg[inst].opcode().is_return() {
+                returns.push(inst);
+            }
+        }
+    }
+    for ret in returns {
+        let mut fills = Vec::new();
+        if let Some((reg, saved)) = link_save {
+            fills.push((reg, restore_link_reg(func, ret, reg, saved)?));
+        }
+
+        let mut pos = Cursor::new(&mut func.layout);
+        pos.goto_inst(ret);
+        let mut vlist = func.dfg[ret].take_value_list().unwrap();
+        for &(reg, saved) in &saves {
+            let restored = func.dfg.ins(&mut pos).fill(saved);
+            *func.locations.ensure(restored) = ValueLoc::Reg(reg);
+            vlist.push(restored, &mut func.dfg.value_lists);
+            fills.push((reg, func.dfg.value_def(restored).unwrap_inst()));
+        }
+        func.dfg[ret].put_value_list(vlist);
+
+        let mut epilogue = Vec::new();
+        for (reg, fill) in fills {
+            epilogue.push((fill, FrameLayoutChange::Restore { reg: reg }));
+            new_insts.push(fill);
+        }
+        if frame_size > 0 {
+            let adjust = func.dfg.ins(&mut pos).adjust_sp_imm(frame_size as i64);
+            epilogue.push((adjust, FrameLayoutChange::CallFrameAddressAt { reg: sp, offset: 0 }));
+            new_insts.push(adjust);
+        }
+        if let Some(&(first, _)) = epilogue.first() {
+            func.frame_layout
+                .push(first, FrameLayoutChange::RememberState);
+            func.frame_layout.changes.extend(epilogue);
+            func.frame_layout
+                .push(ret, FrameLayoutChange::RestoreState);
+        }
+    }
+
+    for inst in new_insts {
+        let enc = isa.encode(&func.dfg, &func.dfg[inst], func.dfg.ctrl_typevar(inst))
+            .expect("Can't encode prologue/epilogue instruction");
+        *func.encodings.ensure(inst) = enc;
+    }
+
+    Ok(())
+}
@@ -1,6 +1,611 @@
 //! Emitting binary ARM64 machine code.
 
-use binemit::{CodeSink, bad_encoding};
-use ir::{Function, Inst};
+use binemit::{CodeSink, CodeOffset, Reloc, bad_encoding};
+use ir::{Function, Inst, InstructionData, MemOrder, TrapCode};
+use ir::condcodes::IntCC;
+use isa::RegUnit;
+use predicates::{is_signed_int, is_unsigned_int};
+use regalloc::RegDiversions;
 
 include!(concat!(env!("OUT_DIR"), "/binemit-arm64.rs"));
+
+/// ARM64 relocation kinds.
+pub enum RelocKind {
+    /// A `bl` call to a function with a 26-bit word offset.
+    Call26,
+}
+
+pub static RELOC_NAMES: [&'static str; 1] = ["Call26"];
+
+/// Emit `bytes` bytes of padding as `nop` instructions.
+pub fn emit_padding<CS: CodeSink + ?Sized>(bytes: CodeOffset, sink: &mut CS) {
+    assert_eq!(bytes % 4, 0, "Can't pad {} bytes", bytes);
+    for _ in 0..bytes / 4 {
+        sink.put4(0xd503201f);
+    }
+}
+
+impl Into<Reloc> for RelocKind {
+    fn into(self) -> Reloc {
+        Reloc(self as u16)
+    }
+}
+
+/// The register number 31 encodes either the stack pointer or the zero register, depending on the
+/// instruction.
+const ZR: RegUnit = 31;
+const SP: RegUnit = 31;
+
+/// The link register `%x30`.
+const LR: RegUnit = 30;
+
+/// Get the A64 condition code corresponding to `cond`.
+fn icc2cond(cond: IntCC) -> u32 {
+    use ir::condcodes::IntCC::*;
+    match cond {
+        Equal => 0b0000,
+        NotEqual => 0b0001,
+        UnsignedGreaterThanOrEqual => 0b0010,
+        UnsignedLessThan => 0b0011,
+        UnsignedGreaterThan => 0b1000,
+        UnsignedLessThanOrEqual => 0b1001,
+        SignedGreaterThanOrEqual => 0b1010,
+        SignedLessThan => 0b1011,
+        SignedGreaterThan => 0b1100,
+        SignedLessThanOrEqual => 0b1101,
+    }
+}
+
+/// Three-register instructions.
+///
+///   31    20 15  9  4
+///   top11 rm op5 rn rd
+///      21 16  10  5  0
+///
+/// Encoding bits: `top11 | (op5 << 11)`.
+fn put_r<CS: CodeSink + ?Sized>(bits: u16,
+                                rn: RegUnit,
+                                rm: RegUnit,
+                                rd: RegUnit,
+                                sink: &mut CS) {
+    let bits = bits as u32;
+    let top11 = bits & 0x7ff;
+    let op5 = (bits >> 11) & 0x1f;
+    let rn = rn as u32 & 0x1f;
+    let rm = rm as u32 & 0x1f;
+    let rd = rd as u32 & 0x1f;
+
+    let mut i = top11 << 21;
+    i |= rm << 16;
+    i |= op5 << 10;
+    i |= rn << 5;
+    i |= rd;
+
+    sink.put4(i);
+}
+
+/// Emit a `cset rd, cond` of the 32-bit register `rd`, encoded as `csinc rd, zr, zr, !cond`.
+fn put_cset<CS: CodeSink + ?Sized>(cond: IntCC, rd: RegUnit, sink: &mut CS) {
+    // Inverting the low bit of an A64 condition code inverts the condition.
+    let cond = icc2cond(cond) ^ 1;
+    let rd = rd as u32 & 0x1f;
+
+    let mut i = 0x1a800400;
+    i |= (ZR as u32) << 16;
+    i |= cond << 12;
+    i |= (ZR as u32) << 5;
+    i |= rd;
+
+    sink.put4(i);
+}
+
+fn recipe_r<CS: CodeSink + ?Sized>(func: &Function,
+                                   inst: Inst,
+                                   divert: &mut RegDiversions,
+                                   sink: &mut CS) {
+    if let InstructionData::Binary { args, .. } = func.dfg[inst] {
+        put_r(func.encodings[inst].bits(),
+              divert.reg(args[0], &func.locations),
+              divert.reg(args[1], &func.locations),
+              func.locations[func.dfg.first_result(inst)].unwrap_reg(),
+              sink);
+    } else {
+        panic!("Expected Binary format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_fr<CS: CodeSink + ?Sized>(func: &Function,
+                                    inst: Inst,
+                                    divert: &mut RegDiversions,
+                                    sink: &mut CS) {
+    recipe_r(func, inst, divert, sink)
+}
+
+fn recipe_rcopy<CS: CodeSink + ?Sized>(func: &Function,
+                                       inst: Inst,
+                                       divert: &mut RegDiversions,
+                                       sink: &mut CS) {
+    if let InstructionData::Unary { arg, .. } = func.dfg[inst] {
+        put_r(func.encodings[inst].bits(),
+              ZR,
+              divert.reg(arg, &func.locations),
+              func.locations[func.dfg.first_result(inst)].unwrap_reg(),
+              sink);
+    } else {
+        panic!("Expected Unary format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_rrmov<CS: CodeSink + ?Sized>(func: &Function,
+                                       inst: Inst,
+                                       divert: &mut RegDiversions,
+                                       sink: &mut CS) {
+    if let InstructionData::RegMove { arg, src, dst, .. } = func.dfg[inst] {
+        divert.regmove(arg, src, dst);
+        put_r(func.encodings[inst].bits(), ZR, src, dst, sink);
+    } else {
+        panic!("Expected RegMove format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_ricmp<CS: CodeSink + ?Sized>(func: &Function,
+                                       inst: Inst,
+                                       divert: &mut RegDiversions,
+                                       sink: &mut CS) {
+    if let InstructionData::IntCompare { cond, args, .. } = func.dfg[inst] {
+        put_r(func.encodings[inst].bits(),
+              divert.reg(args[0], &func.locations),
+              divert.reg(args[1], &func.locations),
+              ZR,
+              sink);
+        put_cset(cond,
+                 func.locations[func.dfg.first_result(inst)].unwrap_reg(),
+                 sink);
+    } else {
+        panic!("Expected IntCompare format: {:?}", func.dfg[inst]);
+    }
+}
+
+/// Add/subtract immediate instructions.
+///
+///   31    21    9  4
+///   top10 imm12 rn rd
+///      22    10  5  0
+///
+/// Encoding bits: `top10`.
+///
+/// Immediates that are a multiple of 4096 are encoded with the 12-bit shift.
+fn put_i<CS: CodeSink + ?Sized>(bits: u16, rn: RegUnit, imm: i64, rd: RegUnit, sink: &mut CS) {
+    let bits = bits as u32;
+    let rn = rn as u32 & 0x1f;
+    let rd = rd as u32 & 0x1f;
+
+    let (sh, imm) = if imm != 0 && is_unsigned_int(imm, 24, 12) {
+        (1, imm >> 12)
+    } else {
+        (0, imm)
+    };
+    assert!(is_unsigned_int(imm, 12, 0), "I out of range {:#x}", imm);
+
+    let mut i = (bits | sh) << 22;
+    i |= (imm as u32) << 10;
+    i |= rn << 5;
+    i |= rd;
+
+    sink.put4(i);
+}
+
+fn recipe_i<CS: CodeSink + ?Sized>(func: &Function,
+                                   inst: Inst,
+                                   divert: &mut RegDiversions,
+                                   sink: &mut CS) {
+    if let InstructionData::BinaryImm { arg, imm, .. } = func.dfg[inst] {
+        put_i(func.encodings[inst].bits(),
+              divert.reg(arg, &func.locations),
+              imm.into(),
+              func.locations[func.dfg.first_result(inst)].unwrap_reg(),
+              sink);
+    } else {
+        panic!("Expected BinaryImm format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_iicmp<CS: CodeSink + ?Sized>(func: &Function,
+                                       inst: Inst,
+                                       divert: &mut RegDiversions,
+                                       sink: &mut CS) {
+    if let InstructionData::IntCompareImm { cond, arg, imm, .. } = func.dfg[inst] {
+        put_i(func.encodings[inst].bits(),
+              divert.reg(arg, &func.locations),
+              imm.into(),
+              ZR,
+              sink);
+        put_cset(cond,
+                 func.locations[func.dfg.first_result(inst)].unwrap_reg(),
+                 sink);
+    } else {
+        panic!("Expected IntCompareImm format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_iadjsp<CS: CodeSink + ?Sized>(func: &Function,
+                                        inst: Inst,
+                                        _divert: &mut RegDiversions,
+                                        sink: &mut CS) {
+    if let InstructionData::UnaryImm { imm, .. } = func.dfg[inst] {
+        let imm: i64 = imm.into();
+        // Negative adjustments are encoded as a `sub` by setting the `op` bit.
+        let (bits, imm) = if imm < 0 {
+            (func.encodings[inst].bits() | 0x100, -imm)
+        } else {
+            (func.encodings[inst].bits(), imm)
+        };
+        put_i(bits, SP, imm, SP, sink);
+    } else {
+        panic!("Expected UnaryImm format: {:?}", func.dfg[inst]);
+    }
+}
+
+/// Move wide instructions.
+///
+///   31    20    4
+///   top11 imm16 rd
+///      21     5  0
+///
+/// Encoding bits: `top11`, including the `hw` field in the low two bits.
+fn recipe_umovz<CS: CodeSink + ?Sized>(func: &Function,
+                                       inst: Inst,
+                                       _divert: &mut RegDiversions,
+                                       sink: &mut CS) {
+    if let InstructionData::UnaryImm { imm, .. } = func.dfg[inst] {
+        let bits = func.encodings[inst].bits() as u32;
+        let imm: i64 = imm.into();
+        let shift = 16 * (bits & 3);
+        let rd = func.locations[func.dfg.first_result(inst)].unwrap_reg() as u32 & 0x1f;
+
+        let mut i = bits << 21;
+        i |= ((imm >> shift) as u32 & 0xffff) << 5;
+        i |= rd;
+
+        sink.put4(i);
+    } else {
+        panic!("Expected UnaryImm format: {:?}", func.dfg[inst]);
+    }
+}
+
+/// Load/store register instructions with an unsigned offset.
+///
+///   31    21    9  4
+///   top10 imm12 rn rt
+///      22    10  5  0
+///
+/// Encoding bits: `top10`. The `size` field in the top two bits is also the scale of the offset.
+fn put_ldst<CS: CodeSink + ?Sized>(bits: u16,
+                                   rn: RegUnit,
+                                   offset: i64,
+                                   rt: RegUnit,
+                                   sink: &mut CS) {
+    let bits = bits as u32;
+    let scale = (bits >> 8) as u8;
+    let rn = rn as u32 & 0x1f;
+    let rt = rt as u32 & 0x1f;
+
+    assert!(is_unsigned_int(offset, 12 + scale, scale),
+            "LDST offset out of range {:#x}",
+            offset);
+
+    let mut i = bits << 22;
+    i |= ((offset >> scale) as u32) << 10;
+    i |= rn << 5;
+    i |= rt;
+
+    sink.put4(i);
+}
+
+fn recipe_ld<CS: CodeSink + ?Sized>(func: &Function,
+                                    inst: Inst,
+                                    divert: &mut RegDiversions,
+                                    sink: &mut CS) {
+    if let InstructionData::Load { flags, arg, offset, .. } = func.dfg[inst] {
+        if !flags.notrap() {
+            sink.trap(TrapCode::HeapOutOfBounds);
+        }
+        put_ldst(func.encodings[inst].bits(),
+                 divert.reg(arg, &func.locations),
+                 offset.into(),
+                 func.locations[func.dfg.first_result(inst)].unwrap_reg(),
+                 sink);
+    } else {
+        panic!("Expected Load format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_fld<CS: CodeSink + ?Sized>(func: &Function,
+                                     inst: Inst,
+                                     divert: &mut RegDiversions,
+                                     sink: &mut CS) {
+    recipe_ld(func, inst, divert, sink)
+}
+
+fn recipe_st<CS: CodeSink + ?Sized>(func: &Function,
+                                    inst: Inst,
+                                    divert: &mut RegDiversions,
+                                    sink: &mut CS) {
+    if let InstructionData::Store { flags, args, offset, .. } = func.dfg[inst] {
+        if !flags.notrap() {
+            sink.trap(TrapCode::HeapOutOfBounds);
+        }
+        put_ldst(func.encodings[inst].bits(),
+                 divert.reg(args[1], &func.locations),
+                 offset.into(),
+                 divert.reg(args[0], &func.locations),
+                 sink);
+    } else {
+        panic!("Expected Store format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_fst<CS: CodeSink + ?Sized>(func: &Function,
+                                     inst: Inst,
+                                     divert: &mut RegDiversions,
+                                     sink: &mut CS) {
+    recipe_st(func, inst, divert, sink)
+}
+
+fn recipe_spill<CS: CodeSink + ?Sized>(func: &Function,
+                                       inst: Inst,
+                                       divert: &mut RegDiversions,
+                                       sink: &mut CS) {
+    if let InstructionData::Unary { arg, .. } = func.dfg[inst] {
+        let ss = func.locations[func.dfg.first_result(inst)].unwrap_stack();
+        put_ldst(func.encodings[inst].bits(),
+                 SP,
+                 func.stack_slots[ss].offset as i64,
+                 divert.reg(arg, &func.locations),
+                 sink);
+    } else {
+        panic!("Expected Unary format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_fspill<CS: CodeSink + ?Sized>(func: &Function,
+                                        inst: Inst,
+                                        divert: &mut RegDiversions,
+                                        sink: &mut CS) {
+    recipe_spill(func, inst, divert, sink)
+}
+
+fn recipe_fill<CS: CodeSink + ?Sized>(func: &Function,
+                                      inst: Inst,
+                                      divert: &mut RegDiversions,
+                                      sink: &mut CS) {
+    if let InstructionData::Unary { arg, .. } = func.dfg[inst] {
+        let ss = divert.stack(arg, &func.locations);
+        put_ldst(func.encodings[inst].bits(),
+                 SP,
+                 func.stack_slots[ss].offset as i64,
+                 func.locations[func.dfg.first_result(inst)].unwrap_reg(),
+                 sink);
+    } else {
+        panic!("Expected Unary format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_ffill<CS: CodeSink + ?Sized>(func: &Function,
+                                       inst: Inst,
+                                       divert: &mut RegDiversions,
+                                       sink: &mut CS) {
+    recipe_fill(func, inst, divert, sink)
+}
+
+/// Floating-point data-processing (1 source) instructions.
+///
+///   31       23 21 20     14    9  4
+///   00011110 ty 1  opcode 10000 rn rd
+///         24 22 21     15    10  5  0
+///
+/// Encoding bits: `ty | (opcode << 2)`.
+fn put_fr1<CS: CodeSink + ?Sized>(bits: u16, rn: RegUnit, rd: RegUnit, sink: &mut CS) {
+    let bits = bits as u32;
+    let ty = bits & 0x3;
+    let opcode = (bits >> 2) & 0x3f;
+    let rn = rn as u32 & 0x1f;
+    let rd = rd as u32 & 0x1f;
+
+    let mut i = 0x1e204000;
+    i |= ty << 22;
+    i |= opcode << 15;
+    i |= rn << 5;
+    i |= rd;
+
+    sink.put4(i);
+}
+
+fn recipe_fru<CS: CodeSink + ?Sized>(func: &Function,
+                                     inst: Inst,
+                                     divert: &mut RegDiversions,
+                                     sink: &mut CS) {
+    if let InstructionData::Unary { arg, .. } = func.dfg[inst] {
+        put_fr1(func.encodings[inst].bits(),
+                divert.reg(arg, &func.locations),
+                func.locations[func.dfg.first_result(inst)].unwrap_reg(),
+                sink);
+    } else {
+        panic!("Expected Unary format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_frrmov<CS: CodeSink + ?Sized>(func: &Function,
+                                        inst: Inst,
+                                        divert: &mut RegDiversions,
+                                        sink: &mut CS) {
+    if let InstructionData::RegMove { arg, src, dst, .. } = func.dfg[inst] {
+        divert.regmove(arg, src, dst);
+        put_fr1(func.encodings[inst].bits(), src, dst, sink);
+    } else {
+        panic!("Expected RegMove format: {:?}", func.dfg[inst]);
+    }
+}
+
+/// Unconditional branch (immediate) instructions.
+///
+///   31   25
+///   top6 imm26
+///     26     0
+///
+/// Encoding bits: `top6`.
+fn put_b<CS: CodeSink + ?Sized>(bits: u16, disp: i64, sink: &mut CS) {
+    let bits = bits as u32;
+
+    assert!(is_signed_int(disp, 28, 2), "B out of range {:#x}", disp);
+
+    let mut i = bits << 26;
+    i |= (disp >> 2) as u32 & 0x3ffffff;
+
+    sink.put4(i);
+}
+
+fn recipe_b<CS: CodeSink + ?Sized>(func: &Function,
+                                   inst: Inst,
+                                   _divert: &mut RegDiversions,
+                                   sink: &mut CS) {
+    if let InstructionData::Jump { destination, .. } = func.dfg[inst] {
+        let dest = func.offsets[destination] as i64;
+        let disp = dest - sink.offset() as i64;
+        put_b(func.encodings[inst].bits(), disp, sink);
+    } else {
+        panic!("Expected Jump format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_bcall<CS: CodeSink + ?Sized>(func: &Function,
+                                       inst: Inst,
+                                       _divert: &mut RegDiversions,
+                                       sink: &mut CS) {
+    if let InstructionData::Call { func_ref, .. } = func.dfg[inst] {
+        sink.reloc_func(RelocKind::Call26.into(), func_ref, 0);
+        put_b(func.encodings[inst].bits(), 0, sink);
+    } else {
+        panic!("Expected Call format: {:?}", func.dfg[inst]);
+    }
+}
+
+/// Compare and branch instructions.
+///
+///   31   23    4
+///   top8 imm19 rt
+///     24     5  0
+///
+/// Encoding bits: `top8`.
+fn put_cb<CS: CodeSink + ?Sized>(bits: u16, disp: i64, rt: RegUnit, sink: &mut CS) {
+    let bits = bits as u32;
+    let rt = rt as u32 & 0x1f;
+
+    assert!(is_signed_int(disp, 21, 2), "CB out of range {:#x}", disp);
+
+    let mut i = bits << 24;
+    i |= ((disp >> 2) as u32 & 0x7ffff) << 5;
+    i |= rt;
+
+    sink.put4(i);
+}
+
+fn recipe_cbz<CS: CodeSink + ?Sized>(func: &Function,
+                                     inst: Inst,
+                                     divert: &mut RegDiversions,
+                                     sink: &mut CS) {
+    if let InstructionData::Branch {
+               destination,
+               ref args,
+               ..
+           } = func.dfg[inst] {
+        let dest = func.offsets[destination] as i64;
+        let disp = dest - sink.offset() as i64;
+        let args = &args.as_slice(&func.dfg.value_lists)[0..1];
+        put_cb(func.encodings[inst].bits(),
+               disp,
+               divert.reg(args[0], &func.locations),
+               sink);
+    } else {
+        panic!("Expected Branch format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_bicmp<CS: CodeSink + ?Sized>(func: &Function,
+                                       inst: Inst,
+                                       divert: &mut RegDiversions,
+                                       sink: &mut CS) {
+    if let InstructionData::BranchIcmp {
+               cond,
+               destination,
+               ref args,
+               ..
+           } = func.dfg[inst] {
+        let args = &args.as_slice(&func.dfg.value_lists)[0..2];
+        put_r(func.encodings[inst].bits(),
+              divert.reg(args[0], &func.locations),
+              divert.reg(args[1], &func.locations),
+              ZR,
+              sink);
+
+        // B.cond: 01010100 imm19 0 cond, relative to the branch itself.
+        let dest = func.offsets[destination] as i64;
+        let disp = dest - sink.offset() as i64;
+        assert!(is_signed_int(disp, 21, 2), "B.cond out of range {:#x}", disp);
+        let mut i = 0x54000000;
+        i |= ((disp >> 2) as u32 & 0x7ffff) << 5;
+        i |= icc2cond(cond);
+        sink.put4(i);
+    } else {
+        panic!("Expected BranchIcmp format: {:?}", func.dfg[inst]);
+    }
+}
+
+/// Barrier instructions.
+///
+///   31                   11  7   4
+///   11010101000000110011 CRm op2 11111
+///                     12   8   5     0
+///
+/// Encoding bits: `op2`.
+fn recipe_dmb<CS: CodeSink + ?Sized>(func: &Function,
+                                     inst: Inst,
+                                     _divert: &mut RegDiversions,
+                                     sink: &mut CS) {
+    if let InstructionData::Fence { order, .. } = func.dfg[inst] {
+        // An acquire fence only needs to order earlier loads, so it can use `dmb ishld`. Release
+        // fences also order earlier loads against later stores, so they need a full `dmb ish`.
+        let crm = if order == MemOrder::Acquire {
+            0b1001
+        } else {
+            0b1011
+        };
+        let bits = func.encodings[inst].bits() as u32;
+        let mut i = 0xd503301f;
+        i |= crm << 8;
+        i |= bits << 5;
+        sink.put4(i);
+    } else {
+        panic!("Expected Fence format: {:?}", func.dfg[inst]);
+    }
+}
+
+/// Unconditional branch (register) instructions.
+///
+///   31      24  20    15     9  4
+///   1101011 opc 11111 000000 rn 00000
+///        25  21    16     10  5     0
+///
+/// Encoding bits: `opc`.
+fn recipe_ret<CS: CodeSink + ?Sized>(func: &Function,
+                                     inst: Inst,
+                                     _divert: &mut RegDiversions,
+                                     sink: &mut CS) {
+    // Return instructions always branch to %x30.
+    // The return address is provided as a special-purpose link argument.
+    let bits = func.encodings[inst].bits() as u32;
+    let mut i = 0xd61f0000;
+    i |= bits << 21;
+    i |= (LR as u32) << 5;
+    sink.put4(i);
+}
@@ -1,16 +1,19 @@
 //! ARM 64-bit Instruction Set Architecture.
 
 pub mod settings;
+mod abi;
 mod binemit;
 mod enc_tables;
 mod registers;
 
-use binemit::CodeSink;
+use binemit::{CodeSink, CodeOffset, MemoryCodeSink, emit_function};
 use super::super::settings as shared_settings;
-use isa::enc_tables::{lookup_enclist, general_encoding};
+use isa::enc_tables::{lookup_enclist, general_encoding, Encodings};
 use isa::Builder as IsaBuilder;
-use isa::{TargetIsa, RegInfo, EncInfo, Encoding, Legalize};
+use isa::{TargetIsa, RegInfo, RegUnit, EncInfo, Encoding, Legalize};
 use ir;
+use regalloc::RegDiversions;
+use result::CtonResult;
 
 #[allow(dead_code)]
 struct Isa {
@@ -22,6 +25,7 @@ struct Isa {
 pub fn isa_builder() -> IsaBuilder {
     IsaBuilder {
         setup: settings::builder(),
+        shared_defaults: Vec::new(),
         constructor: isa_constructor,
     }
 }
@@ -44,6 +48,10 @@ impl TargetIsa for Isa {
         &self.shared_flags
     }
 
+    fn isa_settings_bytes(&self) -> &[u8] {
+        self.isa_flags.settings_bytes()
+    }
+
     fn register_info(&self) -> RegInfo {
         registers::INFO.clone()
     }
@@ -52,8 +60,16 @@ impl TargetIsa for Isa {
         enc_tables::INFO.clone()
     }
 
+    fn reserved_regs(&self) -> &'static [RegUnit] {
+        &abi::RESERVED_REGS
+    }
+
+    fn call_clobbers(&self, _sig: &ir::Signature) -> Vec<RegUnit> {
+        abi::call_clobbers()
+    }
+
     fn encode(&self,
-              _dfg: &ir::DataFlowGraph,
+              dfg: &ir::DataFlowGraph,
               inst: &ir::InstructionData,
               ctrl_typevar: ir::Type)
               -> Result<Encoding, Legalize> {
@@ -64,13 +80,106 @@ impl TargetIsa for Isa {
                 .and_then(|enclist_offset| {
                     general_encoding(enclist_offset,
                                      &enc_tables::ENCLISTS[..],
-                                     |instp| enc_tables::check_instp(inst, instp),
+                                     |instp| enc_tables::check_instp(inst, instp, dfg),
                                      |isap| self.isa_flags.numbered_predicate(isap as usize))
                             .ok_or(Legalize::Expand)
                 })
     }
 
-    fn emit_inst(&self, func: &ir::Function, inst: ir::Inst, sink: &mut CodeSink) {
-        binemit::emit_inst(func, inst, sink)
+    fn legal_encodings<'a>(&'a self,
+                           dfg: &'a ir::DataFlowGraph,
+                           inst: &'a ir::InstructionData,
+                           ctrl_typevar: ir::Type)
+                           -> Result<Box<Iterator<Item = Encoding> + 'a>, Legalize> {
+        let level1 = &enc_tables::LEVEL1_A64[..];
+        lookup_enclist(ctrl_typevar, inst.opcode(), level1, &enc_tables::LEVEL2[..])
+            .map(|enclist_offset| {
+                let encs = Encodings::new(enclist_offset,
+                                          &enc_tables::ENCLISTS[..],
+                                          move |instp| enc_tables::check_instp(inst, instp, dfg),
+                                          move |isap| {
+                                              self.isa_flags.numbered_predicate(isap as usize)
+                                          });
+                Box::new(encs) as Box<Iterator<Item = Encoding>>
+            })
+    }
+
+    fn legalize_signature(&self, sig: &mut ir::Signature, current: bool) {
+        abi::legalize_signature(sig, current)
+    }
+
+    fn prologue_epilogue(&self, func: &mut ir::Function) -> CtonResult {
+        abi::prologue_epilogue(func, self)
+    }
+
+    fn map_dwarf_register(&self, reg: RegUnit) -> Option<u16> {
+        abi::map_dwarf_register(reg)
+    }
+
+    fn dwarf_return_address_column(&self) -> Option<u16> {
+        Some(30)
+    }
+
+    fn emit_inst(&self,
+                 func: &ir::Function,
+                 inst: ir::Inst,
+                 divert: &mut RegDiversions,
+                 sink: &mut CodeSink) {
+        binemit::emit_inst(func, inst, divert, sink)
+    }
+
+    fn emit_padding(&self, bytes: CodeOffset, sink: &mut CodeSink) {
+        binemit::emit_padding(bytes, sink)
+    }
+
+    fn emit_function(&self, func: &ir::Function, sink: &mut MemoryCodeSink) {
+        emit_function(func, binemit::emit_inst, binemit::emit_padding, sink)
+    }
+
+    fn reloc_names(&self) -> &'static [&'static str] {
+        &binemit::RELOC_NAMES
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use settings;
+    use isa;
+    use ir::{DataFlowGraph, InstructionData, Opcode};
+    use ir::{types, immediates};
+
+    fn encstr(isa: &isa::TargetIsa, enc: isa::Encoding) -> String {
+        isa.encoding_info().display(enc).to_string()
+    }
+
+    #[test]
+    fn test_iconst() {
+        let shared_builder = settings::builder();
+        let shared_flags = settings::Flags::new(&shared_builder);
+        let isa = isa::lookup("arm64").unwrap().finish(shared_flags);
+
+        let dfg = DataFlowGraph::new();
+        let iconst = |imm| {
+            InstructionData::UnaryImm {
+                opcode: Opcode::Iconst,
+                imm: immediates::Imm64::new(imm),
+            }
+        };
+
+        // MOVZ with hw=0 is 0b1_10_100101_00.
+        assert_eq!(encstr(&*isa, isa.encode(&dfg, &iconst(0x1234), types::I64).unwrap()),
+                   "Umovz#694");
+        assert_eq!(encstr(&*isa, isa.encode(&dfg, &iconst(0x1234_0000), types::I64).unwrap()),
+                   "Umovz#695");
+        assert_eq!(encstr(&*isa, isa.encode(&dfg, &iconst(0x1234_0000_0000), types::I64).unwrap()),
+                   "Umovz#696");
+        assert_eq!(encstr(&*isa, isa.encode(&dfg, &iconst(0x1234_0000), types::I32).unwrap()),
+                   "Umovz#295");
+
+        // Constants with more than one non-zero chunk need more than one instruction.
+        assert_eq!(isa.encode(&dfg, &iconst(0x1_0001), types::I64),
+                   Err(isa::Legalize::Expand));
+        assert_eq!(isa.encode(&dfg, &iconst(0x1234_0000_0000), types::I32),
+                   Err(isa::Legalize::Expand));
     }
 }
@@ -11,6 +11,7 @@ use binemit::CodeOffset;
 use isa::{RegClass, RegUnit};
 
 /// Register constraint for a single value operand or instruction result.
+#[derive(Clone, Copy)]
 pub struct OperandConstraint {
     /// The kind of constraint.
     pub kind: ConstraintKind,
@@ -38,8 +39,10 @@ pub enum ConstraintKind {
     ///
     /// The associated number is the index of the input value operand this result is tied to.
     ///
-    /// The constraint's `regclass` field is the top-level register class containing the tied
-    /// operand's register class.
+    /// The constraint's `regclass` field is the register class of the tied operand.
+    ///
+    /// The register allocator inserts a copy of the tied operand before the instruction if the
+    /// operand value is still live after the instruction.
     Tied(u8),
 
     /// This operand must be a value in a stack slot.
@@ -66,6 +69,47 @@ pub struct RecipeConstraints {
     /// If the instruction produces a variable number of results, it's probably a call and the
     /// constraints must be derived from the calling convention ABI.
     pub outs: &'static [OperandConstraint],
+
+    /// Registers that are clobbered by the instruction without being operands or results.
+    ///
+    /// Values that are live across the instruction can't be assigned to these registers. The
+    /// registers clobbered by calls are described by the calling convention ABI instead. See
+    /// `TargetIsa::inst_clobbers()` for the complete set.
+    pub clobbers: &'static [RegUnit],
+}
+
+impl RecipeConstraints {
+    /// Get the fixed register required for value operand number `arg`, if any.
+    pub fn fixed_input(&self, arg: usize) -> Option<RegUnit> {
+        match self.ins.get(arg).map(|c| c.kind) {
+            Some(ConstraintKind::FixedReg(reg)) => Some(reg),
+            _ => None,
+        }
+    }
+
+    /// Get the fixed register required for result number `res`, if any.
+    ///
+    /// A result tied to a fixed register operand also uses that register.
+    pub fn fixed_output(&self, res: usize) -> Option<RegUnit> {
+        match self.outs.get(res).map(|c| c.kind) {
+            Some(ConstraintKind::FixedReg(reg)) => Some(reg),
+            Some(ConstraintKind::Tied(arg)) => self.fixed_input(arg as usize),
+            _ => None,
+        }
+    }
+
+    /// Get the number of the value operand that result number `res` is tied to, if any.
+    pub fn tied_input(&self, res: usize) -> Option<usize> {
+        match self.outs.get(res).map(|c| c.kind) {
+            Some(ConstraintKind::Tied(arg)) => Some(arg as usize),
+            _ => None,
+        }
+    }
+
+    /// Does the instruction clobber `reg`?
+    pub fn clobbers_reg(&self, reg: RegUnit) -> bool {
+        self.clobbers.contains(&reg)
+    }
 }
 
 /// Constraints on the range of a branch instruction.
@@ -104,6 +148,9 @@ impl BranchRange {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use ir::Signature;
+    use isa::{self, Encoding};
+    use settings;
 
     #[test]
     fn branch_range() {
@@ -123,4 +170,40 @@ mod tests {
         assert!(!t1.contains(1000, 746));
 
     }
+
+    #[test]
+    fn recipe_constraints() {
+        let isa_builder = isa::lookup("intel").unwrap();
+        let isa = isa_builder.finish(settings::Flags::new(&settings::builder()));
+        let names = isa.encoding_info().names;
+        let recipe = |name| {
+            let recipe = names.iter().position(|&n| n == name).unwrap();
+            isa.operand_constraints(Encoding::new(recipe as u16, 0)).unwrap()
+        };
+        let reginfo = isa.register_info();
+        let rax = reginfo.parse_regunit("rax").unwrap();
+        let rcx = reginfo.parse_regunit("rcx").unwrap();
+        let rbx = reginfo.parse_regunit("rbx").unwrap();
+
+        // Variable shifts take the count in %rcx, and the result is tied to the shifted value.
+        let shift = recipe("Op1rc");
+        assert_eq!(shift.fixed_input(0), None);
+        assert_eq!(shift.fixed_input(1), Some(rcx));
+        assert_eq!(shift.tied_input(0), Some(0));
+        assert_eq!(shift.fixed_output(0), None);
+        assert!(shift.clobbers.is_empty());
+
+        // The general-dynamic TLS sequence calls `__tls_get_addr`.
+        let tlsgd = recipe("RexOp1tlsgd");
+        assert!(tlsgd.clobbers_reg(rax));
+        assert!(!tlsgd.clobbers_reg(rbx));
+
+        assert!(isa.operand_constraints(Encoding::default()).is_none());
+
+        // Calls clobber the caller-saved registers of the calling convention.
+        let call_clobbers = isa.call_clobbers(&Signature::new());
+        assert!(call_clobbers.contains(&rax));
+        assert!(call_clobbers.contains(&rcx));
+        assert!(!call_clobbers.contains(&rbx));
+    }
 }
@@ -114,15 +114,76 @@ const CODE_ALWAYS: EncListEntry = PRED_MASK;
 /// The encoding list terminator.
 const CODE_FAIL: EncListEntry = 0xffff;
 
-/// Find the most general encoding of `inst`.
+/// An iterator over the legal encodings of an instruction.
 ///
-/// Given an encoding list offset as returned by `lookup_enclist` above, search the encoding list
-/// for the most general encoding that applies to `inst`. The encoding lists are laid out such that
-/// this is the last valid entry in the list.
+/// Given an encoding list offset as returned by `lookup_enclist` above, iterate over the entries
+/// in the encoding list that apply to the instruction. The encoding lists are laid out such that
+/// the most general encoding is the last valid entry in the list.
 ///
-/// This function takes two closures that are used to evaluate predicates:
+/// The iterator holds two closures that are used to evaluate predicates:
 /// - `instp` is passed an instruction predicate number to be evaluated on the current instruction.
 /// - `isap` is passed an ISA predicate number to evaluate.
+pub struct Encodings<'a, InstP, IsaP>
+    where InstP: Fn(EncListEntry) -> bool,
+          IsaP: Fn(EncListEntry) -> bool
+{
+    pos: usize,
+    enclist: &'a [EncListEntry],
+    instp: InstP,
+    isap: IsaP,
+}
+
+impl<'a, InstP, IsaP> Encodings<'a, InstP, IsaP>
+    where InstP: Fn(EncListEntry) -> bool,
+          IsaP: Fn(EncListEntry) -> bool
+{
+    /// Create a new iterator over the encoding list starting at `offset`.
+    pub fn new(offset: usize,
+               enclist: &'a [EncListEntry],
+               instp: InstP,
+               isap: IsaP)
+               -> Encodings<'a, InstP, IsaP> {
+        Encodings {
+            pos: offset,
+            enclist: enclist,
+            instp: instp,
+            isap: isap,
+        }
+    }
+}
+
+impl<'a, InstP, IsaP> Iterator for Encodings<'a, InstP, IsaP>
+    where InstP: Fn(EncListEntry) -> bool,
+          IsaP: Fn(EncListEntry) -> bool
+{
+    type Item = Encoding;
+
+    fn next(&mut self) -> Option<Encoding> {
+        while self.enclist[self.pos] != CODE_FAIL {
+            let pred = self.enclist[self.pos];
+            if pred <= CODE_ALWAYS {
+                // This is an instruction predicate followed by recipe and encbits entries.
+                let pos = self.pos;
+                self.pos += 3;
+                if pred == CODE_ALWAYS || (self.instp)(pred) {
+                    return Some(Encoding::new(self.enclist[pos + 1], self.enclist[pos + 2]));
+                }
+            } else {
+                // This is an ISA predicate entry.
+                self.pos += 1;
+                if !(self.isap)(pred & PRED_MASK) {
+                    // ISA predicate failed, skip the next N entries.
+                    self.pos += 3 * (pred >> PRED_BITS) as usize;
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Find the most general encoding of `inst`.
+///
+/// This is the last encoding produced by the `Encodings` iterator, see above.
 ///
 /// Returns the corresponding encoding, or `None` if no list entries are satisfied by `inst`.
 pub fn general_encoding<InstP, IsaP>(offset: usize,
@@ -133,24 +194,5 @@ pub fn general_encoding<InstP, IsaP>(offset: usize,
     where InstP: Fn(EncListEntry) -> bool,
           IsaP: Fn(EncListEntry) -> bool
 {
-    let mut found = None;
-    let mut pos = offset;
-    while enclist[pos] != CODE_FAIL {
-        let pred = enclist[pos];
-        if pred <= CODE_ALWAYS {
-            // This is an instruction predicate followed by recipe and encbits entries.
-            if pred == CODE_ALWAYS || instp(pred) {
-                found = Some(Encoding::new(enclist[pos + 1], enclist[pos + 2]))
-            }
-            pos += 3;
-        } else {
-            // This is an ISA predicate entry.
-            pos += 1;
-            if !isap(pred & PRED_MASK) {
-                // ISA predicate failed, skip the next N entries.
-                pos += 3 * (pred >> PRED_BITS) as usize;
-            }
-        }
-    }
-    found
+    Encodings::new(offset, enclist, instp, isap).last()
 }
@@ -119,6 +119,11 @@ impl EncInfo {
         }
     }
 
+    /// Get the name of the recipe used by `enc`, or `None` for illegal encodings.
+    pub fn recipe_name(&self, enc: Encoding) -> Option<&'static str> {
+        self.names.get(enc.recipe()).cloned()
+    }
+
     /// Get the exact size in bytes of instructions encoded with `enc`.
     ///
     /// Returns 0 for illegal encodings.
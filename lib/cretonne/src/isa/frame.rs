@@ -0,0 +1,178 @@
+//! Stack frame helpers shared by the ISA implementations of `prologue_epilogue()`.
+
+use ir::{Function, Ebb, Inst, Value, Type, InstructionData, ValueLoc, ArgumentLoc,
+         ArgumentPurpose, Cursor, InstBuilder, StackSlotData, StackSlotKind};
+use isa::RegUnit;
+use result::{CtonError, ErrorKind};
+
+/// Get the sorted list of callee-saved registers that have been assigned to values in `func`.
+///
+/// The registers satisfying `is_callee_saved` are collected from the locations of the EBB
+/// arguments and instruction results. A `regmove` can also move a value into a register that isn't
+/// assigned to any value, so the destinations of `regmove` instructions are included too.
+pub fn used_callee_saved<F>(func: &Function, is_callee_saved: F) -> Vec<RegUnit>
+    where F: Fn(RegUnit) -> bool
+{
+    let mut used = Vec::new();
+    for ebb in func.layout.ebbs() {
+        let results = func.layout
+            .ebb_insts(ebb)
+            .flat_map(|inst| func.dfg.inst_results(inst).iter());
+        let regs = func.dfg
+            .ebb_args(ebb)
+            .iter()
+            .chain(results)
+            .filter_map(|&value| match func.locations.get(value) {
+                            Some(&ValueLoc::Reg(reg)) => Some(reg),
+                            _ => None,
+                        });
+        let moves = func.layout
+            .ebb_insts(ebb)
+            .filter_map(|inst| match func.dfg[inst] {
+                            InstructionData::RegMove { dst, .. } => Some(dst),
+                            _ => None,
+                        });
+        for reg in regs.chain(moves) {
+            if is_callee_saved(reg) && !used.contains(&reg) {
+                used.push(reg);
+            }
+        }
+    }
+    used.sort();
+    used
+}
+
+/// Save the return address of a non-leaf function in a spill slot at the top of the entry block.
+///
+/// The calls in a non-leaf function overwrite the link register, so the `link` argument is spilled
+/// with a `ty` store. Return the link register and the spilled value, or `None` for a leaf function
+/// which keeps the return address in the link register.
+pub fn save_link_reg(func: &mut Function,
+                     entry: Ebb,
+                     ty: Type)
+                     -> Result<Option<(RegUnit, Value)>, CtonError> {
+    if func.is_leaf() {
+        return Ok(None);
+    }
+    let idx = match func.signature
+              .argument_types
+              .iter()
+              .position(|arg| arg.purpose == ArgumentPurpose::Link) {
+        Some(idx) => idx,
+        None => return Ok(None),
+    };
+    let link = func.dfg.ebb_args(entry)[idx];
+    let reg = match func.signature.argument_types[idx].location {
+        ArgumentLoc::Reg(reg) => reg,
+        _ => {
+            return Err(CtonError::new(ErrorKind::ImplLimitExceeded)
+                           .at(link)
+                           .context("link argument must be passed in a register"))
+        }
+    };
+
+    let ss = func.stack_slots
+        .push(StackSlotData::new(StackSlotKind::SpillSlot, ty.bytes()));
+    let mut pos = Cursor::new(&mut func.layout);
+    pos.goto_top(entry);
+    pos.next_inst();
+    let saved = func.dfg.ins(&mut pos).spill(link);
+    *func.locations.ensure(saved) = ValueLoc::Stack(ss);
+    Ok(Some((reg, saved)))
+}
+
+/// Restore the return address saved by `save_link_reg()` before the return instruction `ret`.
+///
+/// The filled value in the link register `reg` replaces the `link` return value. Return the `fill`
+/// instruction.
+pub fn restore_link_reg(func: &mut Function,
+                        ret: Inst,
+                        reg: RegUnit,
+                        saved: Value)
+                        -> Result<Inst, CtonError> {
+    let idx = match func.signature
+              .return_types
+              .iter()
+              .position(|rt| rt.purpose == ArgumentPurpose::Link) {
+        Some(idx) => idx,
+        None => {
+            return Err(CtonError::new(ErrorKind::Unsupported)
+                           .at(ret)
+                           .context("link argument without a link return value"))
+        }
+    };
+
+    let mut pos = Cursor::new(&mut func.layout);
+    pos.goto_inst(ret);
+    let restored = func.dfg.ins(&mut pos).fill(saved);
+    *func.locations.ensure(restored) = ValueLoc::Reg(reg);
+    func.dfg.inst_args_mut(ret)[idx] = restored;
+    Ok(func.dfg.value_def(restored).unwrap_inst())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{save_link_reg, restore_link_reg};
+    use ir::{Function, Cursor, InstBuilder, ArgumentType, ArgumentLoc, ArgumentPurpose,
+             ExtFuncData, FunctionName, Signature};
+    use ir::types::I64;
+    use result::{CtonError, ErrorKind};
+
+    // Make a non-leaf function with a `link` argument in `loc` and optionally a `link` return
+    // value.
+    fn non_leaf(loc: ArgumentLoc, link_ret: bool) -> Function {
+        let mut link = ArgumentType::new(I64);
+        link.purpose = ArgumentPurpose::Link;
+        link.location = loc;
+        let mut func = Function::new();
+        func.signature.argument_types.push(link);
+        if link_ret {
+            func.signature.return_types.push(link);
+        }
+        let sig = func.dfg.signatures.push(Signature::new());
+        let fn0 = func.dfg
+            .ext_funcs
+            .push(ExtFuncData {
+                      name: FunctionName::new("foo"),
+                      signature: sig,
+                      colocated: false,
+                  });
+
+        let ebb0 = func.dfg.make_ebb();
+        let v0 = func.dfg.append_ebb_arg(ebb0, I64);
+        {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            dfg.ins(pos).call(fn0, &[]);
+            if link_ret {
+                dfg.ins(pos).return_(&[v0]);
+            } else {
+                dfg.ins(pos).return_(&[]);
+            }
+        }
+        func
+    }
+
+    #[test]
+    fn link_errors() {
+        // The return address must be in a register.
+        let mut func = non_leaf(ArgumentLoc::Stack(0), true);
+        let ebb0 = func.layout.entry_block().unwrap();
+        match save_link_reg(&mut func, ebb0, I64) {
+            Err(CtonError { kind: ErrorKind::ImplLimitExceeded, .. }) => {}
+            result => panic!("Unexpected result: {:?}", result),
+        }
+
+        // The saved return address is passed back in the `link` return value.
+        let mut func = non_leaf(ArgumentLoc::Reg(30), false);
+        let ebb0 = func.layout.entry_block().unwrap();
+        let (reg, saved) = save_link_reg(&mut func, ebb0, I64).unwrap().unwrap();
+        assert_eq!(reg, 30);
+        let ret = func.layout.last_inst(ebb0).unwrap();
+        match restore_link_reg(&mut func, ret, reg, saved) {
+            Err(CtonError { kind: ErrorKind::Unsupported, .. }) => {}
+            result => panic!("Unexpected result: {:?}", result),
+        }
+    }
+}
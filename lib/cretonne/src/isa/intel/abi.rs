@@ -0,0 +1,496 @@
+//! Intel ABI implementation.
+//!
+//! This module implements the System V AMD64 calling convention, the Windows x64 calling
+//! convention, and the 32-bit System V i386 calling convention through the primary
+//! `legalize_signature()` entry point, and the function prologue and epilogue through
+//! `prologue_epilogue()`.
+
+use std::cmp;
+use abi::{ArgAction, ValueConversion, ArgAssigner, AggregateClass, legalize_args,
+          aggregate_scalars};
+use ir::{Signature, Type, ArgumentType, ArgumentLoc, ArgumentExtension, ArgumentPurpose,
+         CallConv};
+use ir::{Function, ValueLoc, Cursor, InstBuilder, StackSlotData, StackSlotKind,
+         FrameLayoutChange};
+use ir::types::F64;
+use isa::{TargetIsa, RegUnit};
+use isa::frame::used_callee_saved;
+use isa::intel::registers::{GPR, FPR};
+use result::{CtonError, CtonResult, ErrorKind};
+use settings as shared_settings;
+use stack_layout::{layout_stack, layout_incoming_args, frame_too_large};
+
+/// Integer argument registers in order: `%rdi`, `%rsi`, `%rdx`, `%rcx`, `%r8`, `%r9`.
+static ARG_GPRS: [usize; 6] = [7, 6, 2, 1, 8, 9];
+
+/// Integer return value registers in order: `%rax`, `%rdx`.
+static RET_GPRS: [usize; 2] = [0, 2];
+
+/// Windows x64 integer argument registers in order: `%rcx`, `%rdx`, `%r8`, `%r9`.
+static WIN_ARG_GPRS: [usize; 4] = [1, 2, 8, 9];
+
+/// Windows x64 integer return value register: `%rax`.
+static WIN_RET_GPRS: [usize; 1] = [0];
+
+/// The Windows x64 caller reserves a 32-byte "shadow space" for the callee below any stack
+/// arguments, where the callee can save its four register arguments.
+const WIN_SHADOW_SPACE: u32 = 32;
+
+/// The stack pointer `%rsp` is reserved for the stack frame.
+pub static RESERVED_REGS_64: [RegUnit; 1] = [4];
+
+/// In 32-bit mode, `%r8`-`%r15` and `%xmm8`-`%xmm15` don't exist since they need a REX prefix.
+pub static RESERVED_REGS_32: [RegUnit; 17] = [4, 8, 9, 10, 11, 12, 13, 14, 15, 24, 25, 26, 27,
+                                              28, 29, 30, 31];
+
+struct Args {
+    pointer_bits: u16,
+    pointer_bytes: u32,
+    pointer_type: Type,
+    gpr: &'static [usize],
+    gpr_used: usize,
+    fpr_limit: usize,
+    fpr_used: usize,
+    // Windows x64 assigns registers by argument position, so an integer argument also uses up an
+    // FPR and vice versa.
+    positional: bool,
+    offset: u32,
+}
+
+impl Args {
+    fn new(bits: u16, gpr: &'static [usize], fpr_limit: usize) -> Args {
+        Args {
+            pointer_bits: bits,
+            pointer_bytes: bits as u32 / 8,
+            pointer_type: Type::int(bits).unwrap(),
+            gpr: gpr,
+            gpr_used: 0,
+            fpr_limit: fpr_limit,
+            fpr_used: 0,
+            positional: false,
+            offset: 0,
+        }
+    }
+
+    /// Create an argument assigner for the Windows x64 convention, with the stack arguments
+    /// placed above the shadow space.
+    fn windows(gpr: &'static [usize], fpr_limit: usize, shadow_space: u32) -> Args {
+        let mut args = Args::new(64, gpr, fpr_limit);
+        args.positional = true;
+        args.offset = shadow_space;
+        args
+    }
+}
+
+impl ArgAssigner for Args {
+    fn assign(&mut self, arg: &ArgumentType) -> ArgAction {
+        let ty = arg.value_type;
+
+        // Check for a legal type.
+        // We don't support SIMD yet, so break all vectors down.
+        if !ty.is_scalar() {
+            return ValueConversion::VectorSplit.into();
+        }
+
+        // Large integers and booleans are broken down to fit in a register.
+        if !ty.is_float() && ty.bits() > self.pointer_bits {
+            return ValueConversion::IntSplit.into();
+        }
+
+        // Small integers are extended to the size of a pointer register.
+        if ty.is_int() && ty.bits() < self.pointer_bits {
+            match arg.extension {
+                ArgumentExtension::None => {}
+                ArgumentExtension::Uext => return ValueConversion::Uext(self.pointer_type).into(),
+                ArgumentExtension::Sext => return ValueConversion::Sext(self.pointer_type).into(),
+            }
+        }
+
+        // Try to use a register. Integer and floating point registers are allocated
+        // independently.
+        if ty.is_float() {
+            if self.fpr_used < self.fpr_limit {
+                let reg = FPR.unit(self.fpr_used);
+                self.fpr_used += 1;
+                if self.positional {
+                    self.gpr_used += 1;
+                }
+                return ArgumentLoc::Reg(reg).into();
+            }
+        } else if self.gpr_used < self.gpr.len() {
+            let reg = GPR.unit(self.gpr[self.gpr_used]);
+            self.gpr_used += 1;
+            if self.positional {
+                self.fpr_used += 1;
+            }
+            return ArgumentLoc::Reg(reg).into();
+        }
+
+        // Assign a stack location. Stack arguments use at least a pointer-sized slot.
+        let loc = ArgumentLoc::Stack(self.offset);
+        self.offset += cmp::max(ty.bytes(), self.pointer_bytes);
+        loc.into()
+    }
+}
+
+/// Legalize `sig` for the calling convention it specifies.
+///
+/// In 64-bit mode, this is either the System V AMD64 ABI or the Windows x64 ABI. The Windows ABI
+/// assigns the first four arguments to registers by position, returns a single value in `%rax` or
+/// `%xmm0`, and passes stack arguments above the shadow space.
+///
+/// In 32-bit mode, all calling conventions use the System V i386 ABI which passes all arguments on
+/// the stack. Floating point return values are returned in `%xmm0` instead of the x87 `%st0`
+/// register which we don't support.
+pub fn legalize_signature(sig: &mut Signature, flags: &shared_settings::Flags, _current: bool) {
+    let bits = if flags.is_64bit() { 64 } else { 32 };
+    let windows = flags.is_64bit() && sig.call_conv == CallConv::WindowsFastcall;
+
+    // Return values are assigned first, since they determine if a hidden `sret` argument is
+    // needed.
+    let mut rets = if windows {
+        Args::windows(&WIN_RET_GPRS, 1, 0)
+    } else {
+        Args::new(bits, &RET_GPRS, 2)
+    };
+    legalize_args(&mut sig.return_types, &mut rets);
+
+    // Return values that don't fit in registers are returned in memory. The stack locations
+    // assigned above are offsets into a return area provided by the caller, and a pointer to the
+    // return area is passed as the first argument.
+    if rets.offset > 0 &&
+       !sig.argument_types
+            .iter()
+            .any(|arg| arg.purpose == ArgumentPurpose::StructReturn) {
+        let mut sret = ArgumentType::new(rets.pointer_type);
+        sret.purpose = ArgumentPurpose::StructReturn;
+        sig.argument_types.insert(0, sret);
+    }
+
+    let mut args = if windows {
+        Args::windows(&WIN_ARG_GPRS, 4, WIN_SHADOW_SPACE)
+    } else if flags.is_64bit() {
+        Args::new(bits, &ARG_GPRS, 8)
+    } else {
+        Args::new(bits, &[], 0)
+    };
+    legalize_args(&mut sig.argument_types, &mut args);
+}
+
+/// Classify a by-value aggregate of `size` bytes.
+///
+/// The Windows x64 convention only passes aggregates of 1, 2, 4, or 8 bytes in a register. All
+/// other aggregates are passed in memory. The other conventions use the shared rules.
+pub fn classify_aggregate(size: u32,
+                          call_conv: CallConv,
+                          flags: &shared_settings::Flags)
+                          -> AggregateClass {
+    if !flags.is_64bit() {
+        ::abi::classify_aggregate(size, 4)
+    } else if call_conv == CallConv::WindowsFastcall {
+        match size {
+            1 | 2 | 4 | 8 => AggregateClass::Scalars(aggregate_scalars(size, 8)),
+            _ => AggregateClass::Memory,
+        }
+    } else {
+        ::abi::classify_aggregate(size, 8)
+    }
+}
+
+/// The stack pointer must be 16-byte aligned at call sites.
+const STACK_ALIGNMENT: u32 = 16;
+
+/// Is `reg` one of the callee-saved registers?
+///
+/// The System V AMD64 ABI saves `%rbx`, `%rbp`, and `%r12`-`%r15`. The Windows x64 ABI also saves
+/// `%rsi`, `%rdi`, and `%xmm6`-`%xmm15`. In 32-bit mode, `%ebx`, `%ebp`, `%esi`, and `%edi` are
+/// saved. The stack pointer is also callee-saved, but it is maintained by the prologue and
+/// epilogue directly.
+fn is_callee_saved(reg: RegUnit, is_64bit: bool, call_conv: CallConv) -> bool {
+    if !is_64bit {
+        reg == GPR.unit(3) || (reg >= GPR.unit(5) && reg <= GPR.unit(7))
+    } else if call_conv == CallConv::WindowsFastcall {
+        reg == GPR.unit(3) || (reg >= GPR.unit(5) && reg <= GPR.unit(7)) ||
+        (reg >= GPR.unit(12) && reg <= GPR.unit(15)) ||
+        (reg >= FPR.unit(6) && reg <= FPR.unit(15))
+    } else {
+        reg == GPR.unit(3) || reg == GPR.unit(5) || (reg >= GPR.unit(12) && reg <= GPR.unit(15))
+    }
+}
+
+/// Get the registers that are clobbered by a call to a function with the signature `sig`.
+///
+/// These are the registers that aren't callee-saved in the callee's calling convention, except
+/// for the `reserved` registers which are never allocated.
+pub fn call_clobbers(sig: &Signature, is_64bit: bool, reserved: &[RegUnit]) -> Vec<RegUnit> {
+    (0..16)
+        .map(|i| GPR.unit(i))
+        .chain((0..16).map(|i| FPR.unit(i)))
+        .filter(|reg| !reserved.contains(reg) && !is_callee_saved(*reg, is_64bit, sig.call_conv))
+        .collect()
+}
+
+/// Get the DWARF register number of `reg`.
+///
+/// The DWARF numbering of the general purpose registers differs from their encoding in
+/// instructions, and it is different in 32-bit and 64-bit mode.
+pub fn map_dwarf_register(reg: RegUnit, is_64bit: bool) -> Option<u16> {
+    const GPR_64: [u16; 16] = [0, 2, 1, 3, 7, 6, 4, 5, 8, 9, 10, 11, 12, 13, 14, 15];
+    if GPR.contains(reg) {
+        let idx = (reg - GPR.unit(0)) as usize;
+        if is_64bit {
+            Some(GPR_64[idx])
+        } else if idx < 8 {
+            Some(idx as u16)
+        } else {
+            None
+        }
+    } else if FPR.contains(reg) {
+        let idx = reg - FPR.unit(0);
+        if is_64bit {
+            Some(17 + idx)
+        } else if idx < 8 {
+            Some(21 + idx)
+        } else {
+            None
+        }
+    } else {
+        None
+    }
+}
+
+/// Does `func` call any functions with the Windows x64 calling convention?
+fn has_windows_calls(func: &Function) -> bool {
+    func.layout
+        .ebbs()
+        .flat_map(|ebb| func.layout.ebb_insts(ebb))
+        .filter_map(|inst| func.dfg.call_signature(inst))
+        .any(|sig| func.dfg.signatures[sig].call_conv == CallConv::WindowsFastcall)
+}
+
+/// Insert the prologue and epilogue code for `func` after register allocation.
+///
+/// Any callee-saved registers used by the register allocator are saved in spill slots at the top
+/// of the entry block and restored before every return. They are represented in the signature as
+/// `csr` arguments and return values so the saved values are visibly passed through the function.
+///
+/// The `call` instruction pushes the return address, so the stack pointer is one pointer size off
+/// its 16-byte alignment on entry. The stack frame allocated here restores the alignment when the
+/// function makes calls of its own. When calling Windows x64 functions, the bottom of the frame
+/// also holds the shadow space for the callee.
+///
+/// The Windows x64 ABI preserves the low 128 bits of `%xmm6`-`%xmm15`, but only the scalar part
+/// of those registers is saved since Cretonne doesn't support SIMD types yet.
+///
+/// If the signature has a `stack_limit` argument, the prologue traps when the allocated stack
+/// frame extends below the limit. Otherwise, frames larger than the probe size are allocated one
+/// page at a time with a stack probe for each page when the `enable_probestack` setting is on.
+///
+/// The frame layout changes are recorded in `func.frame_layout`. The CFA is always computed from
+/// `%rsp` since there is no frame pointer.
+pub fn prologue_epilogue(func: &mut Function, isa: &TargetIsa) -> CtonResult {
+    let bits = if isa.flags().is_64bit() { 64 } else { 32 };
+    let ptr = Type::int(bits).unwrap();
+    let entry = func.layout
+        .entry_block()
+        .expect("Function has no entry block");
+
+    // On entry, the return address pushed by the call is at the top of the stack.
+    let rsp = GPR.unit(4);
+    let ptr_bytes = ptr.bytes() as i32;
+    func.frame_layout.clear();
+    func.frame_layout
+        .initial
+        .push(FrameLayoutChange::CallFrameAddressAt {
+                  reg: rsp,
+                  offset: ptr_bytes,
+              });
+    func.frame_layout
+        .initial
+        .push(FrameLayoutChange::ReturnAddressAt { cfa_offset: -ptr_bytes });
+
+    // Instructions inserted here that need encodings.
+    let mut new_insts = Vec::new();
+
+    // Find the stack limit argument before any callee-saved register arguments are appended.
+    let stack_limit = func.signature
+        .argument_types
+        .iter()
+        .position(|arg| arg.purpose == ArgumentPurpose::StackLimit)
+        .map(|idx| (idx, func.dfg.ebb_args(entry)[idx]));
+
+    // Save the used callee-saved registers at the top of the entry block.
+    let is_64bit = isa.flags().is_64bit();
+    let call_conv = func.signature.call_conv;
+    let csrs = used_callee_saved(func, |reg| is_callee_saved(reg, is_64bit, call_conv));
+    let mut saves = Vec::with_capacity(csrs.len());
+    {
+        let mut pos = Cursor::new(&mut func.layout);
+        pos.goto_top(entry);
+        pos.next_inst();
+
+        for &reg in &csrs {
+            let ty = if FPR.contains(reg) { F64 } else { ptr };
+            let csr = ArgumentType::special_reg(ty, ArgumentPurpose::CalleeSaved, reg);
+            func.signature.argument_types.push(csr);
+            func.signature.return_types.push(csr);
+
+            let arg = func.dfg.append_ebb_arg(entry, ty);
+            *func.locations.ensure(arg) = ValueLoc::Reg(reg);
+
+            let ss = func.stack_slots
+                .push(StackSlotData::new(StackSlotKind::SpillSlot, ty.bytes()));
+            let saved = func.dfg.ins(&mut pos).spill(arg);
+            *func.locations.ensure(saved) = ValueLoc::Stack(ss);
+            saves.push((reg, saved));
+        }
+    }
+    new_insts.extend(saves.iter().map(|&(_, saved)| func.dfg.value_def(saved).unwrap_inst()));
+
+    // Reserve the shadow space at the bottom of the outgoing argument area.
+    if has_windows_calls(func) {
+        func.stack_slots
+            .push(StackSlotData::outgoing_arg(WIN_SHADOW_SPACE, 0));
+    }
+
+    // All the stack slots are known now.
+    let frame_size = layout_stack(&mut func.stack_slots, STACK_ALIGNMENT)?;
+
+    // Account for the pushed return address when the frame needs to be aligned. The return
+    // address is 4 bytes on 32-bit targets, so it takes 12 bytes of padding to realign the stack.
+    let adjustment = if frame_size > 0 || !func.is_leaf() {
+        frame_size
+            .checked_add(STACK_ALIGNMENT - ptr.bytes())
+            .ok_or_else(frame_too_large)?
+    } else {
+        0
+    };
+    if adjustment > i32::max_value() as u32 {
+        return Err(frame_too_large());
+    }
+
+    // The stack arguments are above the frame and the return address.
+    layout_incoming_args(&mut func.stack_slots, adjustment + ptr.bytes())?;
+
+    // Allocate the stack frame before anything else.
+    if adjustment > 0 {
+        let mut pos = Cursor::new(&mut func.layout);
+        pos.goto_top(entry);
+        pos.next_inst();
+
+        if let Some((idx, limit)) = stack_limit {
+            // The check comes before any other code in the entry block, so the limit is still in
+            // its argument register. Stack arguments would need a scratch register.
+            match func.signature.argument_types[idx].location {
+                ArgumentLoc::Reg(reg) => *func.locations.ensure(limit) = ValueLoc::Reg(reg),
+                _ => {
+                    return Err(CtonError::new(ErrorKind::ImplLimitExceeded)
+                                   .at(limit)
+                                   .context("stack limit must be passed in a register"))
+                }
+            }
+            let adjust = func.dfg.ins(&mut pos).adjust_sp_imm(-(adjustment as i64));
+            func.frame_layout
+                .push(adjust,
+                      FrameLayoutChange::CallFrameAddressAt {
+                          reg: rsp,
+                          offset: ptr_bytes + adjustment as i32,
+                      });
+            new_insts.push(adjust);
+            new_insts.push(func.dfg.ins(&mut pos).stack_check(limit));
+        } else {
+            // Allocate large frames one page at a time, probing each page.
+            let mut remaining = adjustment;
+            if isa.flags().enable_probestack() {
+                let probe_size = 1 << isa.flags().probestack_size_log2();
+                while remaining > probe_size {
+                    let adjust = func.dfg.ins(&mut pos).adjust_sp_imm(-(probe_size as i64));
+                    remaining -= probe_size;
+                    func.frame_layout
+                        .push(adjust,
+                              FrameLayoutChange::CallFrameAddressAt {
+                                  reg: rsp,
+                                  offset: ptr_bytes + (adjustment - remaining) as i32,
+                              });
+                    new_insts.push(adjust);
+                    new_insts.push(func.dfg.ins(&mut pos).stack_probe());
+                }
+            }
+            let adjust = func.dfg.ins(&mut pos).adjust_sp_imm(-(remaining as i64));
+            func.frame_layout
+                .push(adjust,
+                      FrameLayoutChange::CallFrameAddressAt {
+                          reg: rsp,
+                          offset: ptr_bytes + adjustment as i32,
+                      });
+            new_insts.push(adjust);
+        }
+    }
+
+    // The save slots are addressed relative to the stack pointer after the frame is allocated.
+    let cfa_offset = ptr_bytes + adjustment as i32;
+    for &(reg, saved) in &saves {
+        let inst = func.dfg.value_def(saved).unwrap_inst();
+        let offset = match func.locations[saved] {
+            ValueLoc::Stack(ss) => func.stack_slots[ss].offset,
+            _ => panic!("Callee-saved register not spilled"),
+        };
+        func.frame_layout
+            .push(inst,
+                  FrameLayoutChange::RegAt {
+                      reg: reg,
+                      cfa_offset: offset - cfa_offset,
+                  });
+    }
+
+    // Restore the callee-saved registers and free the stack frame before every return.
+    let mut returns = Vec::new();
+    for ebb in func.layout.ebbs() {
+        if let Some(inst) = func.layout.last_inst(ebb) {
+            if func.dfg[inst].opcode().is_return() {
+                returns.push(inst);
+            }
+        }
+    }
+    for ret in returns {
+        let mut pos = Cursor::new(&mut func.layout);
+        pos.goto_inst(ret);
+
+        let mut vlist = func.dfg[ret].take_value_list().unwrap();
+        let mut epilogue = Vec::new();
+        for &(reg, saved) in &saves {
+            let restored = func.dfg.ins(&mut pos).fill(saved);
+            *func.locations.ensure(restored) = ValueLoc::Reg(reg);
+            vlist.push(restored, &mut func.dfg.value_lists);
+            let fill = func.dfg.value_def(restored).unwrap_inst();
+            epilogue.push((fill, FrameLayoutChange::Restore { reg: reg }));
+            new_insts.push(fill);
+        }
+        func.dfg[ret].put_value_list(vlist);
+
+        if adjustment > 0 {
+            let adjust = func.dfg.ins(&mut pos).adjust_sp_imm(adjustment as i64);
+            epilogue.push((adjust,
+                           FrameLayoutChange::CallFrameAddressAt {
+                               reg: rsp,
+                               offset: ptr_bytes,
+                           }));
+            new_insts.push(adjust);
+        }
+        if let Some(&(first, _)) = epilogue.first() {
+            func.frame_layout
+                .push(first, FrameLayoutChange::RememberState);
+            func.frame_layout.changes.extend(epilogue);
+            func.frame_layout
+                .push(ret, FrameLayoutChange::RestoreState);
+        }
+    }
+
+    for inst in new_insts {
+        let enc = isa.encode(&func.dfg, &func.dfg[inst], func.dfg.ctrl_typevar(inst))
+            .expect("Can't encode prologue/epilogue instruction");
+        *func.encodings.ensure(inst) = enc;
+    }
+
+    Ok(())
+}
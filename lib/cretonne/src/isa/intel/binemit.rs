@@ -1,6 +1,1380 @@
 //! Emitting binary Intel machine code.
 
-use binemit::{CodeSink, bad_encoding};
-use ir::{Function, Inst};
+use binemit::{CodeSink, CodeOffset, Reloc, bad_encoding};
+use ir::{Function, FunctionName, Inst, InstructionData, Ebb, Opcode, TrapCode};
+use ir::condcodes::IntCC;
+use isa::RegUnit;
+use predicates::is_signed_int;
+use regalloc::RegDiversions;
 
 include!(concat!(env!("OUT_DIR"), "/binemit-intel.rs"));
+
+/// Intel relocation kinds.
+pub enum RelocKind {
+    /// A 4-byte relative function reference. The displacement is relative to the end of the
+    /// relocated 4 bytes, so these relocations have an addend of -4.
+    PCRel4,
+
+    /// A 4-byte relative reference to the PLT entry of a function, used for calls in
+    /// position-independent code. These relocations also have an addend of -4.
+    PLTRel4,
+
+    /// A 4-byte relative reference to the GOT entry holding the address of a function. These
+    /// relocations also have an addend of -4.
+    GOTPCRel4,
+
+    /// A 4-byte absolute function address.
+    Abs4,
+
+    /// An 8-byte absolute function address.
+    Abs8,
+
+    /// A 4-byte absolute address of the GOT entry holding the initial-exec TLS offset of a
+    /// variable.
+    TLSIE4,
+
+    /// A 4-byte relative reference to the GOT entry holding the initial-exec TLS offset of a
+    /// variable. These relocations have an addend of -4.
+    GOTTPOFF4,
+
+    /// A 4-byte relative reference to the GOT entry pair describing a general-dynamic TLS
+    /// variable. These relocations have an addend of -4.
+    TLSGD4,
+}
+
+pub static RELOC_NAMES: [&'static str; 8] = ["PCRel4",
+                                             "PLTRel4",
+                                             "GOTPCRel4",
+                                             "Abs4",
+                                             "Abs8",
+                                             "TLSIE4",
+                                             "GOTTPOFF4",
+                                             "TLSGD4"];
+
+/// The recommended multi-byte `nop` instructions, indexed by size.
+static NOPS: [&'static [u8]; 10] = [&[],
+                                    &[0x90],
+                                    &[0x66, 0x90],
+                                    &[0x0f, 0x1f, 0x00],
+                                    &[0x0f, 0x1f, 0x40, 0x00],
+                                    &[0x0f, 0x1f, 0x44, 0x00, 0x00],
+                                    &[0x66, 0x0f, 0x1f, 0x44, 0x00, 0x00],
+                                    &[0x0f, 0x1f, 0x80, 0x00, 0x00, 0x00, 0x00],
+                                    &[0x0f, 0x1f, 0x84, 0x00, 0x00, 0x00, 0x00, 0x00],
+                                    &[0x66, 0x0f, 0x1f, 0x84, 0x00, 0x00, 0x00, 0x00, 0x00]];
+
+/// Emit `bytes` bytes of padding as a sequence of the longest possible `nop` instructions.
+pub fn emit_padding<CS: CodeSink + ?Sized>(bytes: CodeOffset, sink: &mut CS) {
+    let mut left = bytes as usize;
+    while left > 0 {
+        let nop = NOPS[left.min(NOPS.len() - 1)];
+        for &byte in nop {
+            sink.put1(byte);
+        }
+        left -= nop.len();
+    }
+}
+
+impl Into<Reloc> for RelocKind {
+    fn into(self) -> Reloc {
+        Reloc(self as u16)
+    }
+}
+
+/// The stack pointer register `%rsp`. It can only be encoded as a base register with a SIB byte.
+const RSP: RegUnit = 4;
+
+/// The `%rax` and `%rdi` registers are used by the fixed general-dynamic TLS sequence.
+const RAX: RegUnit = 0;
+const RDI: RegUnit = 7;
+
+/// The `%rcx` and `%rdx` registers are used as temporaries by the atomic `cmpxchg` loops.
+const RCX: RegUnit = 1;
+const RDX: RegUnit = 2;
+
+/// The `lock` prefix making a read-modify-write memory instruction atomic.
+const LOCK: u8 = 0xf0;
+
+// Mandatory prefix bytes for the `pp` field in the encoding bits.
+const PREFIX: [u8; 3] = [0x66, 0xf3, 0xf2];
+
+/// Get a REX prefix with the `B` bit extending the ModR/M r/m field, the SIB base field, or the
+/// opcode register field.
+fn rex1(reg_b: RegUnit) -> u8 {
+    let b = ((reg_b >> 3) & 1) as u8;
+    0x40 | b
+}
+
+/// Get a REX prefix with the `B` bit extending the ModR/M r/m field or the SIB base field, and the
+/// `R` bit extending the ModR/M reg field.
+fn rex2(rm: RegUnit, reg: RegUnit) -> u8 {
+    let b = ((rm >> 3) & 1) as u8;
+    let r = ((reg >> 3) & 1) as u8;
+    0x40 | b | (r << 2)
+}
+
+/// Emit the prefixes and opcode bytes described by the encoding bits `bits`:
+///
+/// ```text
+///   <pp> REX 0F <mm> op
+/// ```
+///
+/// The `W` bit in the REX prefix is taken from `bits`. Pass `None` for `rex` to omit the REX
+/// prefix entirely.
+fn put_op<CS: CodeSink + ?Sized>(bits: u16, rex: Option<u8>, sink: &mut CS) {
+    let pp = (bits >> 8) & 3;
+    let mm = (bits >> 10) & 3;
+    let w = ((bits >> 15) & 1) as u8;
+
+    if pp != 0 {
+        sink.put1(PREFIX[(pp - 1) as usize]);
+    }
+    match rex {
+        Some(rex) => sink.put1(rex | (w << 3)),
+        None => debug_assert_eq!(w, 0, "REX.W bit requires a REX prefix"),
+    }
+    if mm != 0 {
+        sink.put1(0x0f);
+    }
+    match mm {
+        2 => sink.put1(0x38),
+        3 => sink.put1(0x3a),
+        _ => {}
+    }
+    sink.put1(bits as u8);
+}
+
+/// Get `prefix` if a REX prefix should be emitted, `None` otherwise.
+///
+/// Recipes without a REX prefix are constrained to the registers that can be encoded without one,
+/// so the extension bits in `prefix` must be clear in that case.
+fn rex_if(rex: bool, prefix: u8) -> Option<u8> {
+    if rex {
+        Some(prefix)
+    } else {
+        debug_assert_eq!(prefix, 0x40, "Register operands require a REX prefix");
+        None
+    }
+}
+
+/// Emit a ModR/M byte for reg-reg operands.
+fn modrm_rr<CS: CodeSink + ?Sized>(rm: RegUnit, reg: RegUnit, sink: &mut CS) {
+    let reg = reg as u8 & 7;
+    let rm = rm as u8 & 7;
+    let mut b = 0b11000000;
+    b |= reg << 3;
+    b |= rm;
+    sink.put1(b);
+}
+
+/// Emit a ModR/M byte where the reg bits are part of the opcode.
+fn modrm_r_bits<CS: CodeSink + ?Sized>(rm: RegUnit, bits: u16, sink: &mut CS) {
+    let reg = (bits >> 12) as u8 & 7;
+    let rm = rm as u8 & 7;
+    let mut b = 0b11000000;
+    b |= reg << 3;
+    b |= rm;
+    sink.put1(b);
+}
+
+/// Emit a memory operand addressing `base + disp` with `reg` in the ModR/M reg field.
+///
+/// The address is always encoded with a SIB byte and a 32-bit displacement. This works for any
+/// base register, including `%rsp`, `%rbp`, `%r12`, and `%r13` which need special treatment in the
+/// shorter forms. The REX prefix must have been computed with `rex2(base, reg)`.
+fn mem_disp32<CS: CodeSink + ?Sized>(base: RegUnit, reg: RegUnit, disp: i32, sink: &mut CS) {
+    // ModR/M: mod=10, r/m=100 for a SIB byte with disp32.
+    let reg = reg as u8 & 7;
+    sink.put1(0b10000100 | (reg << 3));
+    // SIB: scale=00, index=100 for no index.
+    let base = base as u8 & 7;
+    sink.put1(0b00100000 | base);
+    sink.put4(disp as u32);
+}
+
+/// Get a REX prefix with the `B` bit extending the SIB base field, the `X` bit extending the SIB
+/// index field, and the `R` bit extending the ModR/M reg field.
+fn rex3(base: RegUnit, index: RegUnit, reg: RegUnit) -> u8 {
+    let x = ((index >> 3) & 1) as u8;
+    rex2(base, reg) | (x << 1)
+}
+
+/// Emit a 32-bit displacement to `destination`, relative to the end of the displacement.
+fn disp4<CS: CodeSink + ?Sized>(destination: Ebb, func: &Function, sink: &mut CS) {
+    let delta = func.offsets[destination].wrapping_sub(sink.offset() + 4);
+    sink.put4(delta);
+}
+
+/// Emit an 8-bit displacement to `destination`, relative to the end of the displacement.
+fn disp1<CS: CodeSink + ?Sized>(destination: Ebb, func: &Function, sink: &mut CS) {
+    let delta = func.offsets[destination].wrapping_sub(sink.offset() + 1);
+    assert!(is_signed_int(delta as i32 as i64, 8, 0),
+            "Short branch to {} out of range",
+            destination);
+    sink.put1(delta as u8);
+}
+
+/// Get the low 4 bits of the `jcc` and `setcc` opcodes that test `cond` after a `cmp`.
+fn icc2opc(cond: IntCC) -> u8 {
+    use ir::condcodes::IntCC::*;
+    match cond {
+        Equal => 0x4,
+        NotEqual => 0x5,
+        SignedLessThan => 0xc,
+        SignedGreaterThanOrEqual => 0xd,
+        SignedGreaterThan => 0xf,
+        SignedLessThanOrEqual => 0xe,
+        UnsignedLessThan => 0x2,
+        UnsignedGreaterThanOrEqual => 0x3,
+        UnsignedGreaterThan => 0x7,
+        UnsignedLessThanOrEqual => 0x6,
+    }
+}
+
+/// Emit a `setcc` of the low byte of `reg`, followed by a `movzx` that clears the rest of the
+/// register.
+///
+/// Without a REX prefix, only the low byte of the ABCD registers can be addressed.
+fn put_setcc<CS: CodeSink + ?Sized>(cond: IntCC, reg: RegUnit, rex: bool, sink: &mut CS) {
+    // SETcc r/m8: REX 0F 90+cc /0. The REX prefix makes the low byte of %rsi and %rdi
+    // addressable instead of %dh and %bh.
+    debug_assert!(rex || reg < 4, "setcc needs a REX prefix for {}", reg);
+    put_op(0x0490 | icc2opc(cond) as u16, rex_if(rex, rex1(reg)), sink);
+    modrm_rr(reg, 0, sink);
+    // MOVZX r32, r/m8: REX 0F B6 /r.
+    put_op(0x04b6, rex_if(rex, rex2(reg, reg)), sink);
+    modrm_rr(reg, reg, sink);
+}
+
+/// Emit a `jcc` with a 32-bit displacement to `destination`.
+fn put_jcc<CS: CodeSink + ?Sized>(cond: IntCC, destination: Ebb, func: &Function, sink: &mut CS) {
+    // Jcc rel32: 0F 80+cc cd.
+    sink.put1(0x0f);
+    sink.put1(0x80 | icc2opc(cond));
+    disp4(destination, func, sink);
+}
+
+/// Emit a `jcc` with an 8-bit displacement to `destination`.
+fn put_jccb<CS: CodeSink + ?Sized>(cond: IntCC, destination: Ebb, func: &Function, sink: &mut CS) {
+    // Jcc rel8: 70+cc cb.
+    sink.put1(0x70 | icc2opc(cond));
+    disp1(destination, func, sink);
+}
+
+/// Define the plain and REX variants of a recipe.
+///
+/// Both recipes are emitted by the same `emit` function which takes a flag indicating if a REX
+/// prefix should be used.
+macro_rules! rex_recipes {
+    ($plain:ident, $rex:ident, $emit:ident) => {
+        fn $plain<CS: CodeSink + ?Sized>(func: &Function,
+                                         inst: Inst,
+                                         divert: &mut RegDiversions,
+                                         sink: &mut CS) {
+            $emit(func, inst, divert, sink, false)
+        }
+
+        fn $rex<CS: CodeSink + ?Sized>(func: &Function,
+                                       inst: Inst,
+                                       divert: &mut RegDiversions,
+                                       sink: &mut CS) {
+            $emit(func, inst, divert, sink, true)
+        }
+    }
+}
+
+rex_recipes!(recipe_op1rr, recipe_rexop1rr, emit_rr);
+rex_recipes!(recipe_op2rrx, recipe_rexop2rrx, emit_rrx);
+rex_recipes!(recipe_op1rc, recipe_rexop1rc, emit_rc);
+rex_recipes!(recipe_op1rib, recipe_rexop1rib, emit_rib);
+rex_recipes!(recipe_op1rid, recipe_rexop1rid, emit_rid);
+rex_recipes!(recipe_op1rishb, recipe_rexop1rishb, emit_rishb);
+rex_recipes!(recipe_op1umr, recipe_rexop1umr, emit_umr);
+rex_recipes!(recipe_op1rmov, recipe_rexop1rmov, emit_rmov);
+rex_recipes!(recipe_op1puid, recipe_rexop1puid, emit_puid);
+rex_recipes!(recipe_op1icscc, recipe_rexop1icscc, emit_icscc);
+rex_recipes!(recipe_op1icsccib, recipe_rexop1icsccib, emit_icsccib);
+rex_recipes!(recipe_op1icsccid, recipe_rexop1icsccid, emit_icsccid);
+rex_recipes!(recipe_op2tcmov, recipe_rexop2tcmov, emit_tcmov);
+rex_recipes!(recipe_op1tjccd, recipe_rexop1tjccd, emit_tjccd);
+rex_recipes!(recipe_op1icjccd, recipe_rexop1icjccd, emit_icjccd);
+rex_recipes!(recipe_op1tjccb, recipe_rexop1tjccb, emit_tjccb);
+rex_recipes!(recipe_op1icjccb, recipe_rexop1icjccb, emit_icjccb);
+rex_recipes!(recipe_op1spill, recipe_rexop1spill, emit_spill);
+rex_recipes!(recipe_op1fill, recipe_rexop1fill, emit_fill);
+rex_recipes!(recipe_op1adjustsp_ib, recipe_rexop1adjustsp_ib, emit_adjustsp_ib);
+rex_recipes!(recipe_op1adjustsp_id, recipe_rexop1adjustsp_id, emit_adjustsp_id);
+rex_recipes!(recipe_op1stkchk, recipe_rexop1stkchk, emit_stkchk);
+rex_recipes!(recipe_op1ld, recipe_rexop1ld, emit_ld);
+rex_recipes!(recipe_op2ld, recipe_rexop2ld, emit_ld);
+rex_recipes!(recipe_op1st, recipe_rexop1st, emit_st);
+rex_recipes!(recipe_mp1st, recipe_rexmp1st, emit_st);
+rex_recipes!(recipe_op1ald, recipe_rexop1ald, emit_ald);
+rex_recipes!(recipe_op1ast, recipe_rexop1ast, emit_amem);
+rex_recipes!(recipe_op1ast_fence, recipe_rexop1ast_fence, emit_ast_fence);
+rex_recipes!(recipe_op1axchg, recipe_rexop1axchg, emit_amem);
+rex_recipes!(recipe_op2axadd, recipe_rexop2axadd, emit_lock_amem);
+rex_recipes!(recipe_op2axsub, recipe_rexop2axsub, emit_axsub);
+rex_recipes!(recipe_op2acas, recipe_rexop2acas, emit_acas);
+rex_recipes!(recipe_op1armw, recipe_rexop1armw, emit_armw);
+rex_recipes!(recipe_mp2urm, recipe_rexmp2urm, emit_urm);
+rex_recipes!(recipe_mp2fa, recipe_rexmp2fa, emit_fa);
+rex_recipes!(recipe_mp2furm, recipe_rexmp2furm, emit_urm);
+rex_recipes!(recipe_mp2frurm, recipe_rexmp2frurm, emit_urm);
+rex_recipes!(recipe_mp2rfumr, recipe_rexmp2rfumr, emit_umr);
+rex_recipes!(recipe_op2furm, recipe_rexop2furm, emit_urm);
+rex_recipes!(recipe_op2frmov, recipe_rexop2frmov, emit_frmov);
+rex_recipes!(recipe_mp3furmi_rnd, recipe_rexmp3furmi_rnd, emit_furmi_rnd);
+rex_recipes!(recipe_mp2fspill, recipe_rexmp2fspill, emit_spill);
+rex_recipes!(recipe_mp2ffill, recipe_rexmp2ffill, emit_fill);
+
+// Byte stores without a REX prefix are constrained to the ABCD registers.
+fn recipe_op1st_abcd<CS: CodeSink + ?Sized>(func: &Function,
+                                            inst: Inst,
+                                            divert: &mut RegDiversions,
+                                            sink: &mut CS) {
+    emit_st(func, inst, divert, sink, false)
+}
+
+fn emit_rr<CS: CodeSink + ?Sized>(func: &Function,
+                                  inst: Inst,
+                                  divert: &mut RegDiversions,
+                                  sink: &mut CS,
+                                  rex: bool) {
+    if let InstructionData::Binary { args, .. } = func.dfg[inst] {
+        let in0 = divert.reg(args[0], &func.locations);
+        let in1 = divert.reg(args[1], &func.locations);
+        put_op(func.encodings[inst].bits(), rex_if(rex, rex2(in0, in1)), sink);
+        modrm_rr(in0, in1, sink);
+    } else {
+        panic!("Expected Binary format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn emit_rrx<CS: CodeSink + ?Sized>(func: &Function,
+                                   inst: Inst,
+                                   divert: &mut RegDiversions,
+                                   sink: &mut CS,
+                                   rex: bool) {
+    if let InstructionData::Binary { args, .. } = func.dfg[inst] {
+        let in0 = divert.reg(args[0], &func.locations);
+        let in1 = divert.reg(args[1], &func.locations);
+        put_op(func.encodings[inst].bits(), rex_if(rex, rex2(in1, in0)), sink);
+        modrm_rr(in1, in0, sink);
+    } else {
+        panic!("Expected Binary format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn emit_rc<CS: CodeSink + ?Sized>(func: &Function,
+                                  inst: Inst,
+                                  divert: &mut RegDiversions,
+                                  sink: &mut CS,
+                                  rex: bool) {
+    if let InstructionData::Binary { args, .. } = func.dfg[inst] {
+        // The shift amount is implicitly in %cl.
+        let in0 = divert.reg(args[0], &func.locations);
+        let bits = func.encodings[inst].bits();
+        put_op(bits, rex_if(rex, rex1(in0)), sink);
+        modrm_r_bits(in0, bits, sink);
+    } else {
+        panic!("Expected Binary format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn emit_rib<CS: CodeSink + ?Sized>(func: &Function,
+                                   inst: Inst,
+                                   divert: &mut RegDiversions,
+                                   sink: &mut CS,
+                                   rex: bool) {
+    if let InstructionData::BinaryImm { arg, imm, .. } = func.dfg[inst] {
+        let in0 = divert.reg(arg, &func.locations);
+        let bits = func.encodings[inst].bits();
+        put_op(bits, rex_if(rex, rex1(in0)), sink);
+        modrm_r_bits(in0, bits, sink);
+        let imm: i64 = imm.into();
+        sink.put1(imm as u8);
+    } else {
+        panic!("Expected BinaryImm format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn emit_rid<CS: CodeSink + ?Sized>(func: &Function,
+                                   inst: Inst,
+                                   divert: &mut RegDiversions,
+                                   sink: &mut CS,
+                                   rex: bool) {
+    if let InstructionData::BinaryImm { arg, imm, .. } = func.dfg[inst] {
+        let in0 = divert.reg(arg, &func.locations);
+        let bits = func.encodings[inst].bits();
+        put_op(bits, rex_if(rex, rex1(in0)), sink);
+        modrm_r_bits(in0, bits, sink);
+        let imm: i64 = imm.into();
+        sink.put4(imm as u32);
+    } else {
+        panic!("Expected BinaryImm format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn emit_rishb<CS: CodeSink + ?Sized>(func: &Function,
+                                     inst: Inst,
+                                     divert: &mut RegDiversions,
+                                     sink: &mut CS,
+                                     rex: bool) {
+    if let InstructionData::BinaryImm { arg, imm, .. } = func.dfg[inst] {
+        let in0 = divert.reg(arg, &func.locations);
+        let bits = func.encodings[inst].bits();
+        put_op(bits, rex_if(rex, rex1(in0)), sink);
+        modrm_r_bits(in0, bits, sink);
+        // The processor masks the shift amount to 5 or 6 bits.
+        let imm: i64 = imm.into();
+        sink.put1(imm as u8);
+    } else {
+        panic!("Expected BinaryImm format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn emit_umr<CS: CodeSink + ?Sized>(func: &Function,
+                                   inst: Inst,
+                                   divert: &mut RegDiversions,
+                                   sink: &mut CS,
+                                   rex: bool) {
+    if let InstructionData::Unary { arg, .. } = func.dfg[inst] {
+        let src = divert.reg(arg, &func.locations);
+        let dst = func.locations[func.dfg.first_result(inst)].unwrap_reg();
+        put_op(func.encodings[inst].bits(), rex_if(rex, rex2(dst, src)), sink);
+        modrm_rr(dst, src, sink);
+    } else {
+        panic!("Expected Unary format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn emit_rmov<CS: CodeSink + ?Sized>(func: &Function,
+                                    inst: Inst,
+                                    divert: &mut RegDiversions,
+                                    sink: &mut CS,
+                                    rex: bool) {
+    if let InstructionData::RegMove { arg, src, dst, .. } = func.dfg[inst] {
+        divert.regmove(arg, src, dst);
+        put_op(func.encodings[inst].bits(), rex_if(rex, rex2(dst, src)), sink);
+        modrm_rr(dst, src, sink);
+    } else {
+        panic!("Expected RegMove format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn emit_puid<CS: CodeSink + ?Sized>(func: &Function,
+                                    inst: Inst,
+                                    _divert: &mut RegDiversions,
+                                    sink: &mut CS,
+                                    rex: bool) {
+    if let InstructionData::UnaryImm { imm, .. } = func.dfg[inst] {
+        let dst = func.locations[func.dfg.first_result(inst)].unwrap_reg();
+        // The destination register is encoded in the low bits of the opcode.
+        let bits = func.encodings[inst].bits() | (dst & 7) as u16;
+        put_op(bits, rex_if(rex, rex1(dst)), sink);
+        let imm: i64 = imm.into();
+        sink.put4(imm as u32);
+    } else {
+        panic!("Expected UnaryImm format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_rexop1uid<CS: CodeSink + ?Sized>(func: &Function,
+                                           inst: Inst,
+                                           _divert: &mut RegDiversions,
+                                           sink: &mut CS) {
+    if let InstructionData::UnaryImm { imm, .. } = func.dfg[inst] {
+        let dst = func.locations[func.dfg.first_result(inst)].unwrap_reg();
+        let bits = func.encodings[inst].bits();
+        put_op(bits, Some(rex1(dst)), sink);
+        modrm_r_bits(dst, bits, sink);
+        let imm: i64 = imm.into();
+        sink.put4(imm as u32);
+    } else {
+        panic!("Expected UnaryImm format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_rexop1puiq<CS: CodeSink + ?Sized>(func: &Function,
+                                            inst: Inst,
+                                            _divert: &mut RegDiversions,
+                                            sink: &mut CS) {
+    if let InstructionData::UnaryImm { imm, .. } = func.dfg[inst] {
+        let dst = func.locations[func.dfg.first_result(inst)].unwrap_reg();
+        // The destination register is encoded in the low bits of the opcode.
+        let bits = func.encodings[inst].bits() | (dst & 7) as u16;
+        put_op(bits, Some(rex1(dst)), sink);
+        let imm: i64 = imm.into();
+        sink.put8(imm as u64);
+    } else {
+        panic!("Expected UnaryImm format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn emit_icscc<CS: CodeSink + ?Sized>(func: &Function,
+                                     inst: Inst,
+                                     divert: &mut RegDiversions,
+                                     sink: &mut CS,
+                                     rex: bool) {
+    if let InstructionData::IntCompare { cond, args, .. } = func.dfg[inst] {
+        // `cmp r/m, reg` computes `args[0] - args[1]`.
+        let in0 = divert.reg(args[0], &func.locations);
+        let in1 = divert.reg(args[1], &func.locations);
+        put_op(func.encodings[inst].bits(), rex_if(rex, rex2(in0, in1)), sink);
+        modrm_rr(in0, in1, sink);
+
+        let dst = func.locations[func.dfg.first_result(inst)].unwrap_reg();
+        put_setcc(cond, dst, rex, sink);
+    } else {
+        panic!("Expected IntCompare format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn emit_icsccib<CS: CodeSink + ?Sized>(func: &Function,
+                                       inst: Inst,
+                                       divert: &mut RegDiversions,
+                                       sink: &mut CS,
+                                       rex: bool) {
+    if let InstructionData::IntCompareImm { cond, arg, imm, .. } = func.dfg[inst] {
+        let in0 = divert.reg(arg, &func.locations);
+        let bits = func.encodings[inst].bits();
+        put_op(bits, rex_if(rex, rex1(in0)), sink);
+        modrm_r_bits(in0, bits, sink);
+        let imm: i64 = imm.into();
+        sink.put1(imm as u8);
+
+        let dst = func.locations[func.dfg.first_result(inst)].unwrap_reg();
+        put_setcc(cond, dst, rex, sink);
+    } else {
+        panic!("Expected IntCompareImm format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn emit_icsccid<CS: CodeSink + ?Sized>(func: &Function,
+                                       inst: Inst,
+                                       divert: &mut RegDiversions,
+                                       sink: &mut CS,
+                                       rex: bool) {
+    if let InstructionData::IntCompareImm { cond, arg, imm, .. } = func.dfg[inst] {
+        let in0 = divert.reg(arg, &func.locations);
+        let bits = func.encodings[inst].bits();
+        put_op(bits, rex_if(rex, rex1(in0)), sink);
+        modrm_r_bits(in0, bits, sink);
+        let imm: i64 = imm.into();
+        sink.put4(imm as u32);
+
+        let dst = func.locations[func.dfg.first_result(inst)].unwrap_reg();
+        put_setcc(cond, dst, rex, sink);
+    } else {
+        panic!("Expected IntCompareImm format: {:?}", func.dfg[inst]);
+    }
+}
+
+/// Emit a `test c, c` followed by a `cmovnz` of `x` into the result register for `select c, x, y`.
+fn emit_tcmov<CS: CodeSink + ?Sized>(func: &Function,
+                                     inst: Inst,
+                                     divert: &mut RegDiversions,
+                                     sink: &mut CS,
+                                     rex: bool) {
+    if let InstructionData::Ternary { args, .. } = func.dfg[inst] {
+        // TEST r/m, r: REX.W 85 /r. The operand size is determined by the condition type.
+        let cond = divert.reg(args[0], &func.locations);
+        let test_bits = if func.dfg.value_type(args[0]).bits() > 32 {
+            0x8085
+        } else {
+            0x0085
+        };
+        put_op(test_bits, rex_if(rex, rex2(cond, cond)), sink);
+        modrm_rr(cond, cond, sink);
+
+        // CMOVNZ r, r/m: REX.W 0F 45 /r. The result is tied to `y`, which is kept when the
+        // condition is zero.
+        let x = divert.reg(args[1], &func.locations);
+        let dst = divert.reg(args[2], &func.locations);
+        put_op(func.encodings[inst].bits(), rex_if(rex, rex2(x, dst)), sink);
+        modrm_rr(x, dst, sink);
+    } else {
+        panic!("Expected Ternary format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_op1jmpd<CS: CodeSink + ?Sized>(func: &Function,
+                                         inst: Inst,
+                                         _divert: &mut RegDiversions,
+                                         sink: &mut CS) {
+    if let InstructionData::Jump { destination, .. } = func.dfg[inst] {
+        put_op(func.encodings[inst].bits(), None, sink);
+        disp4(destination, func, sink);
+    } else {
+        panic!("Expected Jump format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_op1jmpb<CS: CodeSink + ?Sized>(func: &Function,
+                                         inst: Inst,
+                                         _divert: &mut RegDiversions,
+                                         sink: &mut CS) {
+    if let InstructionData::Jump { destination, .. } = func.dfg[inst] {
+        put_op(func.encodings[inst].bits(), None, sink);
+        disp1(destination, func, sink);
+    } else {
+        panic!("Expected Jump format: {:?}", func.dfg[inst]);
+    }
+}
+
+/// Emit a `test r, r` followed by the conditional branch emitted by `jcc`.
+fn emit_tjcc<CS: CodeSink + ?Sized>(func: &Function,
+                                    inst: Inst,
+                                    divert: &mut RegDiversions,
+                                    sink: &mut CS,
+                                    rex: bool,
+                                    jcc: fn(IntCC, Ebb, &Function, &mut CS)) {
+    if let InstructionData::Branch {
+               opcode,
+               destination,
+               ref args,
+           } = func.dfg[inst] {
+        // `test r, r` sets ZF if the argument is zero.
+        let reg = divert.reg(args.as_slice(&func.dfg.value_lists)[0], &func.locations);
+        put_op(func.encodings[inst].bits(), rex_if(rex, rex2(reg, reg)), sink);
+        modrm_rr(reg, reg, sink);
+
+        let cond = match opcode {
+            Opcode::Brz => IntCC::Equal,
+            Opcode::Brnz => IntCC::NotEqual,
+            _ => panic!("Unexpected branch opcode: {}", opcode),
+        };
+        jcc(cond, destination, func, sink);
+    } else {
+        panic!("Expected Branch format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn emit_tjccd<CS: CodeSink + ?Sized>(func: &Function,
+                                     inst: Inst,
+                                     divert: &mut RegDiversions,
+                                     sink: &mut CS,
+                                     rex: bool) {
+    emit_tjcc(func, inst, divert, sink, rex, put_jcc)
+}
+
+fn emit_tjccb<CS: CodeSink + ?Sized>(func: &Function,
+                                     inst: Inst,
+                                     divert: &mut RegDiversions,
+                                     sink: &mut CS,
+                                     rex: bool) {
+    emit_tjcc(func, inst, divert, sink, rex, put_jccb)
+}
+
+/// Emit a `cmp r, r` followed by the conditional branch emitted by `jcc`.
+fn emit_icjcc<CS: CodeSink + ?Sized>(func: &Function,
+                                     inst: Inst,
+                                     divert: &mut RegDiversions,
+                                     sink: &mut CS,
+                                     rex: bool,
+                                     jcc: fn(IntCC, Ebb, &Function, &mut CS)) {
+    if let InstructionData::BranchIcmp {
+               cond,
+               destination,
+               ref args,
+               ..
+           } = func.dfg[inst] {
+        let args = &args.as_slice(&func.dfg.value_lists)[0..2];
+        let in0 = divert.reg(args[0], &func.locations);
+        let in1 = divert.reg(args[1], &func.locations);
+        put_op(func.encodings[inst].bits(), rex_if(rex, rex2(in0, in1)), sink);
+        modrm_rr(in0, in1, sink);
+        jcc(cond, destination, func, sink);
+    } else {
+        panic!("Expected BranchIcmp format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn emit_icjccd<CS: CodeSink + ?Sized>(func: &Function,
+                                      inst: Inst,
+                                      divert: &mut RegDiversions,
+                                      sink: &mut CS,
+                                      rex: bool) {
+    emit_icjcc(func, inst, divert, sink, rex, put_jcc)
+}
+
+fn emit_icjccb<CS: CodeSink + ?Sized>(func: &Function,
+                                      inst: Inst,
+                                      divert: &mut RegDiversions,
+                                      sink: &mut CS,
+                                      rex: bool) {
+    emit_icjcc(func, inst, divert, sink, rex, put_jccb)
+}
+
+fn recipe_rexop1jt_base<CS: CodeSink + ?Sized>(func: &Function,
+                                               inst: Inst,
+                                               _divert: &mut RegDiversions,
+                                               sink: &mut CS) {
+    if let InstructionData::BranchTableBase { table, .. } = func.dfg[inst] {
+        let dst = func.locations[func.dfg.first_result(inst)].unwrap_reg();
+        put_op(func.encodings[inst].bits(), Some(rex2(0, dst)), sink);
+        // ModR/M: mod=00, r/m=101 for a RIP-relative address.
+        sink.put1(0b00000101 | ((dst as u8 & 7) << 3));
+        // The jump table follows the code, so the displacement is known. The relocation allows
+        // the read-only data to be moved elsewhere.
+        sink.reloc_jt(RelocKind::PCRel4.into(), table);
+        let delta = func.jt_offsets[table].wrapping_sub(sink.offset() + 4);
+        sink.put4(delta);
+    } else {
+        panic!("Expected BranchTableBase format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_rexop1jt_entry<CS: CodeSink + ?Sized>(func: &Function,
+                                                inst: Inst,
+                                                divert: &mut RegDiversions,
+                                                sink: &mut CS) {
+    if let InstructionData::BranchTableEntry { args, .. } = func.dfg[inst] {
+        let index = divert.reg(args[0], &func.locations);
+        let base = divert.reg(args[1], &func.locations);
+        let dst = func.locations[func.dfg.first_result(inst)].unwrap_reg();
+        debug_assert!(index != RSP, "%rsp can't be used as an index register");
+        put_op(func.encodings[inst].bits(), Some(rex3(base, index, dst)), sink);
+        // ModR/M: mod=01, r/m=100 for a SIB byte with disp8. This also works for a `%rbp` or
+        // `%r13` base.
+        sink.put1(0b01000100 | ((dst as u8 & 7) << 3));
+        // SIB: scale=10 for 4-byte entries.
+        sink.put1(0b10000000 | ((index as u8 & 7) << 3) | (base as u8 & 7));
+        sink.put1(0);
+    } else {
+        panic!("Expected BranchTableEntry format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_rexop1jt_br<CS: CodeSink + ?Sized>(func: &Function,
+                                             inst: Inst,
+                                             divert: &mut RegDiversions,
+                                             sink: &mut CS) {
+    if let InstructionData::BranchTable { arg, .. } = func.dfg[inst] {
+        let reg = divert.reg(arg, &func.locations);
+        let bits = func.encodings[inst].bits();
+        put_op(bits, Some(rex1(reg)), sink);
+        modrm_r_bits(reg, bits, sink);
+    } else {
+        panic!("Expected BranchTable format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_op1call_id<CS: CodeSink + ?Sized>(func: &Function,
+                                            inst: Inst,
+                                            _divert: &mut RegDiversions,
+                                            sink: &mut CS) {
+    if let InstructionData::Call { func_ref, .. } = func.dfg[inst] {
+        put_op(func.encodings[inst].bits(), None, sink);
+        sink.reloc_func(RelocKind::PCRel4.into(), func_ref, -4);
+        sink.put4(0);
+    } else {
+        panic!("Expected Call format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_op1call_plt_id<CS: CodeSink + ?Sized>(func: &Function,
+                                                inst: Inst,
+                                                _divert: &mut RegDiversions,
+                                                sink: &mut CS) {
+    if let InstructionData::Call { func_ref, .. } = func.dfg[inst] {
+        put_op(func.encodings[inst].bits(), None, sink);
+        sink.reloc_func(RelocKind::PLTRel4.into(), func_ref, -4);
+        sink.put4(0);
+    } else {
+        panic!("Expected Call format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_op1fnaddr4<CS: CodeSink + ?Sized>(func: &Function,
+                                            inst: Inst,
+                                            _divert: &mut RegDiversions,
+                                            sink: &mut CS) {
+    if let InstructionData::FuncAddr { func_ref, .. } = func.dfg[inst] {
+        let dst = func.locations[func.dfg.first_result(inst)].unwrap_reg();
+        // The destination register is encoded in the low bits of the opcode.
+        let bits = func.encodings[inst].bits() | (dst & 7) as u16;
+        put_op(bits, None, sink);
+        sink.reloc_func(RelocKind::Abs4.into(), func_ref, 0);
+        sink.put4(0);
+    } else {
+        panic!("Expected FuncAddr format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_rexop1fnaddr8<CS: CodeSink + ?Sized>(func: &Function,
+                                               inst: Inst,
+                                               _divert: &mut RegDiversions,
+                                               sink: &mut CS) {
+    if let InstructionData::FuncAddr { func_ref, .. } = func.dfg[inst] {
+        let dst = func.locations[func.dfg.first_result(inst)].unwrap_reg();
+        // The destination register is encoded in the low bits of the opcode.
+        let bits = func.encodings[inst].bits() | (dst & 7) as u16;
+        put_op(bits, Some(rex1(dst)), sink);
+        sink.reloc_func(RelocKind::Abs8.into(), func_ref, 0);
+        sink.put8(0);
+    } else {
+        panic!("Expected FuncAddr format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_rexop1pcrel_fnaddr<CS: CodeSink + ?Sized>(func: &Function,
+                                                    inst: Inst,
+                                                    _divert: &mut RegDiversions,
+                                                    sink: &mut CS) {
+    if let InstructionData::FuncAddr { func_ref, .. } = func.dfg[inst] {
+        let dst = func.locations[func.dfg.first_result(inst)].unwrap_reg();
+        put_op(func.encodings[inst].bits(), Some(rex2(0, dst)), sink);
+        // ModR/M: mod=00, r/m=101 for a RIP-relative address.
+        sink.put1(0b00000101 | ((dst as u8 & 7) << 3));
+        sink.reloc_func(RelocKind::PCRel4.into(), func_ref, -4);
+        sink.put4(0);
+    } else {
+        panic!("Expected FuncAddr format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_rexop1gotpcrel<CS: CodeSink + ?Sized>(func: &Function,
+                                                inst: Inst,
+                                                _divert: &mut RegDiversions,
+                                                sink: &mut CS) {
+    if let InstructionData::FuncAddr { func_ref, .. } = func.dfg[inst] {
+        let dst = func.locations[func.dfg.first_result(inst)].unwrap_reg();
+        put_op(func.encodings[inst].bits(), Some(rex2(0, dst)), sink);
+        // ModR/M: mod=00, r/m=101 for a RIP-relative address.
+        sink.put1(0b00000101 | ((dst as u8 & 7) << 3));
+        sink.reloc_func(RelocKind::GOTPCRel4.into(), func_ref, -4);
+        sink.put4(0);
+    } else {
+        panic!("Expected FuncAddr format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_op1tp_gs<CS: CodeSink + ?Sized>(func: &Function,
+                                          inst: Inst,
+                                          _divert: &mut RegDiversions,
+                                          sink: &mut CS) {
+    let dst = func.locations[func.dfg.first_result(inst)].unwrap_reg();
+    // %gs segment override prefix.
+    sink.put1(0x65);
+    put_op(func.encodings[inst].bits(), None, sink);
+    // ModR/M: mod=00, r/m=101 for an absolute disp32 address.
+    sink.put1(0b00000101 | ((dst as u8 & 7) << 3));
+    sink.put4(0);
+}
+
+fn recipe_rexop1tp_fs<CS: CodeSink + ?Sized>(func: &Function,
+                                             inst: Inst,
+                                             _divert: &mut RegDiversions,
+                                             sink: &mut CS) {
+    let dst = func.locations[func.dfg.first_result(inst)].unwrap_reg();
+    // %fs segment override prefix.
+    sink.put1(0x64);
+    put_op(func.encodings[inst].bits(), Some(rex2(0, dst)), sink);
+    // ModR/M: mod=00, r/m=100 for a SIB byte.
+    sink.put1(0b00000100 | ((dst as u8 & 7) << 3));
+    // SIB: no index, base=101 for an absolute disp32 address.
+    sink.put1(0x25);
+    sink.put4(0);
+}
+
+fn recipe_op1tlsie<CS: CodeSink + ?Sized>(func: &Function,
+                                          inst: Inst,
+                                          _divert: &mut RegDiversions,
+                                          sink: &mut CS) {
+    if let InstructionData::UnaryGlobalVar { global_var, .. } = func.dfg[inst] {
+        let dst = func.locations[func.dfg.first_result(inst)].unwrap_reg();
+        put_op(func.encodings[inst].bits(), None, sink);
+        // ModR/M: mod=00, r/m=101 for an absolute disp32 address.
+        sink.put1(0b00000101 | ((dst as u8 & 7) << 3));
+        sink.reloc_external(RelocKind::TLSIE4.into(), &func.global_vars[global_var].name, 0);
+        sink.put4(0);
+    } else {
+        panic!("Expected UnaryGlobalVar format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_rexop1gottpoff<CS: CodeSink + ?Sized>(func: &Function,
+                                                inst: Inst,
+                                                _divert: &mut RegDiversions,
+                                                sink: &mut CS) {
+    if let InstructionData::UnaryGlobalVar { global_var, .. } = func.dfg[inst] {
+        let dst = func.locations[func.dfg.first_result(inst)].unwrap_reg();
+        put_op(func.encodings[inst].bits(), Some(rex2(0, dst)), sink);
+        // ModR/M: mod=00, r/m=101 for a RIP-relative address.
+        sink.put1(0b00000101 | ((dst as u8 & 7) << 3));
+        sink.reloc_external(RelocKind::GOTTPOFF4.into(), &func.global_vars[global_var].name, -4);
+        sink.put4(0);
+    } else {
+        panic!("Expected UnaryGlobalVar format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_rexop1tlsgd<CS: CodeSink + ?Sized>(func: &Function,
+                                             inst: Inst,
+                                             _divert: &mut RegDiversions,
+                                             sink: &mut CS) {
+    if let InstructionData::UnaryGlobalVar { global_var, .. } = func.dfg[inst] {
+        let dst = func.locations[func.dfg.first_result(inst)].unwrap_reg();
+
+        // `lea x@tlsgd(%rip), %rdi` with a `data16` prefix.
+        put_op(func.encodings[inst].bits(), Some(rex2(0, RDI)), sink);
+        sink.put1(0b00000101 | ((RDI as u8 & 7) << 3));
+        sink.reloc_external(RelocKind::TLSGD4.into(), &func.global_vars[global_var].name, -4);
+        sink.put4(0);
+
+        // `call __tls_get_addr@plt` padded with `data16 data16 rex.W` to the 16-byte sequence
+        // that linkers know how to relax.
+        sink.put1(0x66);
+        sink.put1(0x66);
+        sink.put1(0x48);
+        sink.put1(0xe8);
+        sink.reloc_external(RelocKind::PLTRel4.into(),
+                            &FunctionName::new("__tls_get_addr"),
+                            -4);
+        sink.put4(0);
+
+        // `mov %rax, dst`.
+        sink.put1(rex2(dst, RAX) | 0x08);
+        sink.put1(0x89);
+        modrm_rr(dst, RAX, sink);
+    } else {
+        panic!("Expected UnaryGlobalVar format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_op1ret<CS: CodeSink + ?Sized>(func: &Function,
+                                        inst: Inst,
+                                        _divert: &mut RegDiversions,
+                                        sink: &mut CS) {
+    put_op(func.encodings[inst].bits(), None, sink);
+}
+
+fn emit_spill<CS: CodeSink + ?Sized>(func: &Function,
+                                     inst: Inst,
+                                     divert: &mut RegDiversions,
+                                     sink: &mut CS,
+                                     rex: bool) {
+    if let InstructionData::Unary { arg, .. } = func.dfg[inst] {
+        let src = divert.reg(arg, &func.locations);
+        let ss = func.locations[func.dfg.first_result(inst)].unwrap_stack();
+        put_op(func.encodings[inst].bits(), rex_if(rex, rex2(RSP, src)), sink);
+        mem_disp32(RSP, src, func.stack_slots[ss].offset, sink);
+    } else {
+        panic!("Expected Unary format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn emit_fill<CS: CodeSink + ?Sized>(func: &Function,
+                                    inst: Inst,
+                                    divert: &mut RegDiversions,
+                                    sink: &mut CS,
+                                    rex: bool) {
+    if let InstructionData::Unary { arg, .. } = func.dfg[inst] {
+        let ss = divert.stack(arg, &func.locations);
+        let dst = func.locations[func.dfg.first_result(inst)].unwrap_reg();
+        put_op(func.encodings[inst].bits(), rex_if(rex, rex2(RSP, dst)), sink);
+        mem_disp32(RSP, dst, func.stack_slots[ss].offset, sink);
+    } else {
+        panic!("Expected Unary format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn emit_adjustsp_ib<CS: CodeSink + ?Sized>(func: &Function,
+                                           inst: Inst,
+                                           _divert: &mut RegDiversions,
+                                           sink: &mut CS,
+                                           rex: bool) {
+    if let InstructionData::UnaryImm { imm, .. } = func.dfg[inst] {
+        let bits = func.encodings[inst].bits();
+        put_op(bits, rex_if(rex, rex1(RSP)), sink);
+        modrm_r_bits(RSP, bits, sink);
+        let imm: i64 = imm.into();
+        sink.put1(imm as u8);
+    } else {
+        panic!("Expected UnaryImm format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn emit_adjustsp_id<CS: CodeSink + ?Sized>(func: &Function,
+                                           inst: Inst,
+                                           _divert: &mut RegDiversions,
+                                           sink: &mut CS,
+                                           rex: bool) {
+    if let InstructionData::UnaryImm { imm, .. } = func.dfg[inst] {
+        let bits = func.encodings[inst].bits();
+        put_op(bits, rex_if(rex, rex1(RSP)), sink);
+        modrm_r_bits(RSP, bits, sink);
+        let imm: i64 = imm.into();
+        sink.put4(imm as u32);
+    } else {
+        panic!("Expected UnaryImm format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn emit_stkchk<CS: CodeSink + ?Sized>(func: &Function,
+                                      inst: Inst,
+                                      divert: &mut RegDiversions,
+                                      sink: &mut CS,
+                                      rex: bool) {
+    if let InstructionData::Unary { arg, .. } = func.dfg[inst] {
+        let limit = divert.reg(arg, &func.locations);
+        // CMP r/m, r: Compute `%rsp - limit`.
+        put_op(func.encodings[inst].bits(), rex_if(rex, rex2(RSP, limit)), sink);
+        modrm_rr(RSP, limit, sink);
+        // JAE rel8: Skip the trap when `%rsp >= limit`.
+        sink.put1(0x73);
+        sink.put1(2);
+        // UD2.
+        sink.trap(TrapCode::StackOverflow);
+        sink.put1(0x0f);
+        sink.put1(0x0b);
+    } else {
+        panic!("Expected Unary format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_op1stkprobe<CS: CodeSink + ?Sized>(func: &Function,
+                                             inst: Inst,
+                                             _divert: &mut RegDiversions,
+                                             sink: &mut CS) {
+    // TEST r/m32, r32 with `(%rsp)` as the memory operand. Only the memory read matters.
+    sink.trap(TrapCode::StackOverflow);
+    put_op(func.encodings[inst].bits(), None, sink);
+    // ModR/M: mod=00, reg=%esp, r/m=100 for a SIB byte.
+    sink.put1(0b00100100);
+    // SIB: scale=00, index=100 for no index, base=%rsp.
+    sink.put1(0b00100100);
+}
+
+fn emit_ld<CS: CodeSink + ?Sized>(func: &Function,
+                                  inst: Inst,
+                                  divert: &mut RegDiversions,
+                                  sink: &mut CS,
+                                  rex: bool) {
+    if let InstructionData::Load { flags, arg, offset, .. } = func.dfg[inst] {
+        let base = divert.reg(arg, &func.locations);
+        let dst = func.locations[func.dfg.first_result(inst)].unwrap_reg();
+        if !flags.notrap() {
+            sink.trap(TrapCode::HeapOutOfBounds);
+        }
+        put_op(func.encodings[inst].bits(), rex_if(rex, rex2(base, dst)), sink);
+        mem_disp32(base, dst, offset.into(), sink);
+    } else {
+        panic!("Expected Load format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn emit_st<CS: CodeSink + ?Sized>(func: &Function,
+                                  inst: Inst,
+                                  divert: &mut RegDiversions,
+                                  sink: &mut CS,
+                                  rex: bool) {
+    if let InstructionData::Store { flags, args, offset, .. } = func.dfg[inst] {
+        let data = divert.reg(args[0], &func.locations);
+        let base = divert.reg(args[1], &func.locations);
+        if !flags.notrap() {
+            sink.trap(TrapCode::HeapOutOfBounds);
+        }
+        put_op(func.encodings[inst].bits(), rex_if(rex, rex2(base, data)), sink);
+        mem_disp32(base, data, offset.into(), sink);
+    } else {
+        panic!("Expected Store format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn emit_ald<CS: CodeSink + ?Sized>(func: &Function,
+                                   inst: Inst,
+                                   divert: &mut RegDiversions,
+                                   sink: &mut CS,
+                                   rex: bool) {
+    if let InstructionData::AtomicLoad { arg, .. } = func.dfg[inst] {
+        let base = divert.reg(arg, &func.locations);
+        let dst = func.locations[func.dfg.first_result(inst)].unwrap_reg();
+        sink.trap(TrapCode::HeapOutOfBounds);
+        put_op(func.encodings[inst].bits(), rex_if(rex, rex2(base, dst)), sink);
+        mem_disp32(base, dst, 0, sink);
+    } else {
+        panic!("Expected AtomicLoad format: {:?}", func.dfg[inst]);
+    }
+}
+
+/// Emit an instruction with the `x` operand in the ModR/M reg field and `(p)` as the memory
+/// operand.
+fn emit_amem<CS: CodeSink + ?Sized>(func: &Function,
+                                    inst: Inst,
+                                    divert: &mut RegDiversions,
+                                    sink: &mut CS,
+                                    rex: bool) {
+    sink.trap(TrapCode::HeapOutOfBounds);
+    put_amem(func, inst, divert, sink, rex);
+}
+
+/// Emit the instruction for `emit_amem()` without the trap information.
+fn put_amem<CS: CodeSink + ?Sized>(func: &Function,
+                                   inst: Inst,
+                                   divert: &mut RegDiversions,
+                                   sink: &mut CS,
+                                   rex: bool) {
+    if let InstructionData::AtomicRmw { args, .. } = func.dfg[inst] {
+        let data = divert.reg(args[0], &func.locations);
+        let base = divert.reg(args[1], &func.locations);
+        put_op(func.encodings[inst].bits(), rex_if(rex, rex2(base, data)), sink);
+        mem_disp32(base, data, 0, sink);
+    } else {
+        panic!("Expected AtomicRmw format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn emit_lock_amem<CS: CodeSink + ?Sized>(func: &Function,
+                                         inst: Inst,
+                                         divert: &mut RegDiversions,
+                                         sink: &mut CS,
+                                         rex: bool) {
+    sink.trap(TrapCode::HeapOutOfBounds);
+    sink.put1(LOCK);
+    put_amem(func, inst, divert, sink, rex);
+}
+
+fn emit_ast_fence<CS: CodeSink + ?Sized>(func: &Function,
+                                         inst: Inst,
+                                         divert: &mut RegDiversions,
+                                         sink: &mut CS,
+                                         rex: bool) {
+    emit_amem(func, inst, divert, sink, rex);
+    // MFENCE: 0F AE F0.
+    sink.put1(0x0f);
+    sink.put1(0xae);
+    sink.put1(0xf0);
+}
+
+fn recipe_op2mfence<CS: CodeSink + ?Sized>(func: &Function,
+                                           inst: Inst,
+                                           _divert: &mut RegDiversions,
+                                           sink: &mut CS) {
+    // MFENCE: 0F AE /6 with a register form ModR/M byte.
+    put_op(func.encodings[inst].bits(), None, sink);
+    sink.put1(0xf0);
+}
+
+fn recipe_op0fence<CS: CodeSink + ?Sized>(_func: &Function,
+                                          _inst: Inst,
+                                          _divert: &mut RegDiversions,
+                                          _sink: &mut CS) {
+}
+
+fn emit_axsub<CS: CodeSink + ?Sized>(func: &Function,
+                                     inst: Inst,
+                                     divert: &mut RegDiversions,
+                                     sink: &mut CS,
+                                     rex: bool) {
+    if let InstructionData::AtomicRmw { args, .. } = func.dfg[inst] {
+        let data = divert.reg(args[0], &func.locations);
+        // NEG r/m: REX F7 /3 with the operand size of the encoding.
+        let neg_bits = 0x30f7 | (func.encodings[inst].bits() & 0x8000);
+        put_op(neg_bits, rex_if(rex, rex1(data)), sink);
+        modrm_r_bits(data, neg_bits, sink);
+        emit_lock_amem(func, inst, divert, sink, rex);
+    } else {
+        panic!("Expected AtomicRmw format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn emit_acas<CS: CodeSink + ?Sized>(func: &Function,
+                                    inst: Inst,
+                                    divert: &mut RegDiversions,
+                                    sink: &mut CS,
+                                    rex: bool) {
+    if let InstructionData::AtomicCas { args, .. } = func.dfg[inst] {
+        let expected = divert.reg(args[0], &func.locations);
+        let data = divert.reg(args[1], &func.locations);
+        let base = divert.reg(args[2], &func.locations);
+
+        // Registers after `expected` has been swapped with `%rax`.
+        let swapped = |reg| if reg == RAX {
+            expected
+        } else if reg == expected {
+            RAX
+        } else {
+            reg
+        };
+
+        // XCHG r/m, r: REX.W 87 /r. The full register is swapped in 64-bit mode.
+        let xchg_bits = if rex { 0x8087 } else { 0x87 };
+        put_op(xchg_bits, rex_if(rex, rex2(expected, RAX)), sink);
+        modrm_rr(expected, RAX, sink);
+
+        let (data, base) = (swapped(data), swapped(base));
+        sink.trap(TrapCode::HeapOutOfBounds);
+        sink.put1(LOCK);
+        put_op(func.encodings[inst].bits(), rex_if(rex, rex2(base, data)), sink);
+        mem_disp32(base, data, 0, sink);
+
+        put_op(xchg_bits, rex_if(rex, rex2(expected, RAX)), sink);
+        modrm_rr(expected, RAX, sink);
+    } else {
+        panic!("Expected AtomicCas format: {:?}", func.dfg[inst]);
+    }
+}
+
+/// Emit a `lock cmpxchg` loop applying the two-address ALU instruction in the encoding bits:
+///
+/// ```text
+///   push x; push p; push %rax; push %rcx; push %rdx
+///   mov  p_slot(%rsp), %rdx
+///   mov  (%rdx), %rax
+/// 1:
+///   mov  %rax, %rcx
+///   op   x_slot(%rsp), %rcx
+///   lock cmpxchg %rcx, (%rdx)
+///   jne  1b
+///   mov  %rax, x_slot(%rsp)
+///   pop  %rdx; pop %rcx; pop %rax; add $slot, %rsp; pop x
+/// ```
+///
+/// In 64-bit mode, all the `push` and `pop` instructions of operands get a REX prefix, so the
+/// sequence has a fixed size.
+fn emit_armw<CS: CodeSink + ?Sized>(func: &Function,
+                                    inst: Inst,
+                                    divert: &mut RegDiversions,
+                                    sink: &mut CS,
+                                    rex: bool) {
+    if let InstructionData::AtomicRmw { args, .. } = func.dfg[inst] {
+        let data = divert.reg(args[0], &func.locations);
+        let base = divert.reg(args[1], &func.locations);
+        let bits = func.encodings[inst].bits();
+        let w = bits & 0x8000;
+        // Size of a stack slot, and the offsets of the pushed `x` and `p`.
+        let slot: u8 = if rex { 8 } else { 4 };
+        let x_slot = 4 * slot;
+        let p_slot = 3 * slot;
+
+        // PUSH r: REX 50+rd.
+        for &reg in &[data, base] {
+            if let Some(prefix) = rex_if(rex, rex1(reg)) {
+                sink.put1(prefix);
+            }
+            sink.put1(0x50 | (reg as u8 & 7));
+        }
+        for &reg in &[RAX, RCX, RDX] {
+            sink.put1(0x50 | reg as u8);
+        }
+
+        // MOV r, r/m: REX.W 8B /r with an 8-bit displacement from `%rsp`.
+        put_op(if rex { 0x808b } else { 0x8b }, rex_if(rex, 0x40), sink);
+        sink.put1(0b01000100 | ((RDX as u8) << 3));
+        sink.put1(0x24);
+        sink.put1(p_slot);
+
+        sink.trap(TrapCode::HeapOutOfBounds);
+        put_op(w | 0x8b, rex_if(rex, 0x40), sink);
+        sink.put1((RAX as u8) << 3 | RDX as u8);
+
+        // The loop body.
+        let loop_start = sink.offset();
+        put_op(w | 0x89, rex_if(rex, 0x40), sink);
+        modrm_rr(RCX, RAX, sink);
+        put_op(bits, rex_if(rex, 0x40), sink);
+        sink.put1(0b01000100 | ((RCX as u8) << 3));
+        sink.put1(0x24);
+        sink.put1(x_slot);
+        sink.trap(TrapCode::HeapOutOfBounds);
+        sink.put1(LOCK);
+        put_op(w | 0x04b1, rex_if(rex, 0x40), sink);
+        sink.put1((RCX as u8) << 3 | RDX as u8);
+        // JNE rel8 back to the start of the loop.
+        sink.put1(0x70 | icc2opc(IntCC::NotEqual));
+        let delta = loop_start.wrapping_sub(sink.offset() + 1);
+        sink.put1(delta as u8);
+
+        // Store the old value in the `x` slot.
+        put_op(w | 0x89, rex_if(rex, 0x40), sink);
+        sink.put1(0b01000100 | ((RAX as u8) << 3));
+        sink.put1(0x24);
+        sink.put1(x_slot);
+
+        // POP r: REX 58+rd.
+        for &reg in &[RDX, RCX, RAX] {
+            sink.put1(0x58 | reg as u8);
+        }
+        // ADD r/m, imm8: REX.W 83 /0 ib dropping the `p` slot.
+        put_op(if rex { 0x8083 } else { 0x83 }, rex_if(rex, 0x40), sink);
+        modrm_rr(RSP, 0, sink);
+        sink.put1(slot);
+        if let Some(prefix) = rex_if(rex, rex1(data)) {
+            sink.put1(prefix);
+        }
+        sink.put1(0x58 | (data as u8 & 7));
+    } else {
+        panic!("Expected AtomicRmw format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn emit_urm<CS: CodeSink + ?Sized>(func: &Function,
+                                   inst: Inst,
+                                   divert: &mut RegDiversions,
+                                   sink: &mut CS,
+                                   rex: bool) {
+    if let InstructionData::Unary { arg, .. } = func.dfg[inst] {
+        let src = divert.reg(arg, &func.locations);
+        let dst = func.locations[func.dfg.first_result(inst)].unwrap_reg();
+        put_op(func.encodings[inst].bits(), rex_if(rex, rex2(src, dst)), sink);
+        modrm_rr(src, dst, sink);
+    } else {
+        panic!("Expected Unary format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn emit_fa<CS: CodeSink + ?Sized>(func: &Function,
+                                  inst: Inst,
+                                  divert: &mut RegDiversions,
+                                  sink: &mut CS,
+                                  rex: bool) {
+    if let InstructionData::Binary { args, .. } = func.dfg[inst] {
+        let in0 = divert.reg(args[0], &func.locations);
+        let in1 = divert.reg(args[1], &func.locations);
+        put_op(func.encodings[inst].bits(), rex_if(rex, rex2(in1, in0)), sink);
+        modrm_rr(in1, in0, sink);
+    } else {
+        panic!("Expected Binary format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn emit_frmov<CS: CodeSink + ?Sized>(func: &Function,
+                                     inst: Inst,
+                                     divert: &mut RegDiversions,
+                                     sink: &mut CS,
+                                     rex: bool) {
+    if let InstructionData::RegMove { arg, src, dst, .. } = func.dfg[inst] {
+        divert.regmove(arg, src, dst);
+        put_op(func.encodings[inst].bits(), rex_if(rex, rex2(src, dst)), sink);
+        modrm_rr(src, dst, sink);
+    } else {
+        panic!("Expected RegMove format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn emit_furmi_rnd<CS: CodeSink + ?Sized>(func: &Function,
+                                         inst: Inst,
+                                         divert: &mut RegDiversions,
+                                         sink: &mut CS,
+                                         rex: bool) {
+    if let InstructionData::Unary { opcode, arg } = func.dfg[inst] {
+        let src = divert.reg(arg, &func.locations);
+        let dst = func.locations[func.dfg.first_result(inst)].unwrap_reg();
+        put_op(func.encodings[inst].bits(), rex_if(rex, rex2(src, dst)), sink);
+        modrm_rr(src, dst, sink);
+        // The immediate selects the rounding mode.
+        sink.put1(match opcode {
+                      Opcode::Nearest => 0b00,
+                      Opcode::Floor => 0b01,
+                      Opcode::Ceil => 0b10,
+                      Opcode::Trunc => 0b11,
+                      _ => panic!("Unexpected rounding opcode: {}", opcode),
+                  });
+    } else {
+        panic!("Expected Unary format: {:?}", func.dfg[inst]);
+    }
+}
+
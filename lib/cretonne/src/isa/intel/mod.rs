@@ -1,16 +1,19 @@
 //! Intel Instruction Set Architectures.
 
 pub mod settings;
+mod abi;
 mod binemit;
 mod enc_tables;
 mod registers;
 
-use binemit::CodeSink;
+use binemit::{CodeSink, CodeOffset, MemoryCodeSink, emit_function};
 use super::super::settings as shared_settings;
-use isa::enc_tables::{self as shared_enc_tables, lookup_enclist, general_encoding};
+use isa::enc_tables::{self as shared_enc_tables, lookup_enclist, general_encoding, Encodings};
 use isa::Builder as IsaBuilder;
-use isa::{TargetIsa, RegInfo, EncInfo, Encoding, Legalize};
+use isa::{TargetIsa, RegInfo, RegUnit, EncInfo, Encoding, Legalize, AggregateClass};
 use ir;
+use regalloc::RegDiversions;
+use result::CtonResult;
 
 #[allow(dead_code)]
 struct Isa {
@@ -23,6 +26,7 @@ struct Isa {
 pub fn isa_builder() -> IsaBuilder {
     IsaBuilder {
         setup: settings::builder(),
+        shared_defaults: Vec::new(),
         constructor: isa_constructor,
     }
 }
@@ -51,6 +55,10 @@ impl TargetIsa for Isa {
         &self.shared_flags
     }
 
+    fn isa_settings_bytes(&self) -> &[u8] {
+        self.isa_flags.settings_bytes()
+    }
+
     fn register_info(&self) -> RegInfo {
         registers::INFO.clone()
     }
@@ -59,8 +67,20 @@ impl TargetIsa for Isa {
         enc_tables::INFO.clone()
     }
 
+    fn reserved_regs(&self) -> &'static [RegUnit] {
+        if self.shared_flags.is_64bit() {
+            &abi::RESERVED_REGS_64
+        } else {
+            &abi::RESERVED_REGS_32
+        }
+    }
+
+    fn call_clobbers(&self, sig: &ir::Signature) -> Vec<RegUnit> {
+        abi::call_clobbers(sig, self.shared_flags.is_64bit(), self.reserved_regs())
+    }
+
     fn encode(&self,
-              _dfg: &ir::DataFlowGraph,
+              dfg: &ir::DataFlowGraph,
               inst: &ir::InstructionData,
               ctrl_typevar: ir::Type)
               -> Result<Encoding, Legalize> {
@@ -71,13 +91,183 @@ impl TargetIsa for Isa {
                 .and_then(|enclist_offset| {
                     general_encoding(enclist_offset,
                                      &enc_tables::ENCLISTS[..],
-                                     |instp| enc_tables::check_instp(inst, instp),
+                                     |instp| enc_tables::check_instp(inst, instp, dfg),
                                      |isap| self.isa_flags.numbered_predicate(isap as usize))
                             .ok_or(Legalize::Expand)
                 })
     }
 
-    fn emit_inst(&self, func: &ir::Function, inst: ir::Inst, sink: &mut CodeSink) {
-        binemit::emit_inst(func, inst, sink)
+    fn legal_encodings<'a>(&'a self,
+                           dfg: &'a ir::DataFlowGraph,
+                           inst: &'a ir::InstructionData,
+                           ctrl_typevar: ir::Type)
+                           -> Result<Box<Iterator<Item = Encoding> + 'a>, Legalize> {
+        lookup_enclist(ctrl_typevar, inst.opcode(), self.cpumode, &enc_tables::LEVEL2[..])
+            .map(|enclist_offset| {
+                let encs = Encodings::new(enclist_offset,
+                                          &enc_tables::ENCLISTS[..],
+                                          move |instp| enc_tables::check_instp(inst, instp, dfg),
+                                          move |isap| {
+                                              self.isa_flags.numbered_predicate(isap as usize)
+                                          });
+                Box::new(encs) as Box<Iterator<Item = Encoding>>
+            })
+    }
+
+    fn legalize_signature(&self, sig: &mut ir::Signature, current: bool) {
+        abi::legalize_signature(sig, &self.shared_flags, current)
+    }
+
+    fn classify_aggregate(&self, size: u32, call_conv: ir::CallConv) -> AggregateClass {
+        abi::classify_aggregate(size, call_conv, &self.shared_flags)
+    }
+
+    fn prologue_epilogue(&self, func: &mut ir::Function) -> CtonResult {
+        abi::prologue_epilogue(func, self)
+    }
+
+    fn map_dwarf_register(&self, reg: RegUnit) -> Option<u16> {
+        abi::map_dwarf_register(reg, self.shared_flags.is_64bit())
+    }
+
+    fn dwarf_return_address_column(&self) -> Option<u16> {
+        Some(if self.shared_flags.is_64bit() { 16 } else { 8 })
+    }
+
+    fn emit_inst(&self,
+                 func: &ir::Function,
+                 inst: ir::Inst,
+                 divert: &mut RegDiversions,
+                 sink: &mut CodeSink) {
+        binemit::emit_inst(func, inst, divert, sink)
+    }
+
+    fn emit_padding(&self, bytes: CodeOffset, sink: &mut CodeSink) {
+        binemit::emit_padding(bytes, sink)
+    }
+
+    fn emit_function(&self, func: &ir::Function, sink: &mut MemoryCodeSink) {
+        emit_function(func, binemit::emit_inst, binemit::emit_padding, sink)
+    }
+
+    fn reloc_names(&self) -> &'static [&'static str] {
+        &binemit::RELOC_NAMES
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use settings::{self, Configurable};
+    use isa;
+    use ir::{DataFlowGraph, InstructionData, Opcode, CallConv};
+    use ir::{types, immediates};
+
+    fn encstr(isa: &isa::TargetIsa, enc: isa::Encoding) -> String {
+        isa.encoding_info().display(enc).to_string()
+    }
+
+    #[test]
+    fn test_64bitenc() {
+        let mut shared_builder = settings::builder();
+        shared_builder.set_bool("is_64bit", true).unwrap();
+        let shared_flags = settings::Flags::new(&shared_builder);
+        let isa = isa::lookup("intel").unwrap().finish(shared_flags);
+
+        let mut dfg = DataFlowGraph::new();
+        let ebb = dfg.make_ebb();
+        let arg64 = dfg.append_ebb_arg(ebb, types::I64);
+        let arg32 = dfg.append_ebb_arg(ebb, types::I32);
+
+        // Try to encode iadd_imm.i64 v1, -10000.
+        let inst64 = InstructionData::BinaryImm {
+            opcode: Opcode::IaddImm,
+            arg: arg64,
+            imm: immediates::Imm64::new(-10000),
+        };
+
+        // REX.W 81 /0 id
+        assert_eq!(encstr(&*isa, isa.encode(&dfg, &inst64, types::I64).unwrap()),
+                   "RexOp1rid#8081");
+
+        // Try to encode iadd_imm.i64 v1, 1 << 40.
+        let inst64_large = InstructionData::BinaryImm {
+            opcode: Opcode::IaddImm,
+            arg: arg64,
+            imm: immediates::Imm64::new(1 << 40),
+        };
+
+        // Immediate is out of range for a sign-extended 32-bit immediate.
+        assert_eq!(isa.encode(&dfg, &inst64_large, types::I64),
+                   Err(isa::Legalize::Expand));
+
+        // The source type of an int-to-float conversion is a secondary type variable, so it is
+        // checked by an instruction predicate.
+        let cvt64 = InstructionData::Unary {
+            opcode: Opcode::FcvtFromSint,
+            arg: arg64,
+        };
+        let cvt32 = InstructionData::Unary {
+            opcode: Opcode::FcvtFromSint,
+            arg: arg32,
+        };
+
+        // F2 REX.W 0F 2A /r
+        assert_eq!(encstr(&*isa, isa.encode(&dfg, &cvt64, types::F64).unwrap()),
+                   "RexMp2frurm#872a");
+        // F2 0F 2A /r
+        assert_eq!(encstr(&*isa, isa.encode(&dfg, &cvt32, types::F64).unwrap()),
+                   "RexMp2frurm#72a");
+    }
+    // Same as above, but for 32-bit mode.
+    #[test]
+    fn test_32bitenc() {
+        let mut shared_builder = settings::builder();
+        shared_builder.set_bool("is_64bit", false).unwrap();
+        let shared_flags = settings::Flags::new(&shared_builder);
+        let isa = isa::lookup("intel").unwrap().finish(shared_flags);
+
+        let mut dfg = DataFlowGraph::new();
+        let ebb = dfg.make_ebb();
+        let arg64 = dfg.append_ebb_arg(ebb, types::I64);
+        let arg32 = dfg.append_ebb_arg(ebb, types::I32);
+
+        // Try to encode iadd_imm.i64 v1, -10000.
+        let inst64 = InstructionData::BinaryImm {
+            opcode: Opcode::IaddImm,
+            arg: arg64,
+            imm: immediates::Imm64::new(-10000),
+        };
+
+        // There are no 64-bit registers in 32-bit mode.
+        assert_eq!(isa.encode(&dfg, &inst64, types::I64),
+                   Err(isa::Legalize::Narrow));
+
+        // Create an iadd_imm.i32 which is encodable without a REX prefix.
+        let inst32 = InstructionData::BinaryImm {
+            opcode: Opcode::IaddImm,
+            arg: arg32,
+            imm: immediates::Imm64::new(-10000),
+        };
+
+        // 81 /0 id
+        assert_eq!(encstr(&*isa, isa.encode(&dfg, &inst32, types::I32).unwrap()),
+                   "Op1rid#81");
+    }
+
+    #[test]
+    fn aggregates() {
+        let mut shared_builder = settings::builder();
+        shared_builder.set_bool("is_64bit", true).unwrap();
+        let shared_flags = settings::Flags::new(&shared_builder);
+        let isa = isa::lookup("intel").unwrap().finish(shared_flags);
+
+        assert_eq!(isa.classify_aggregate(12, CallConv::SystemV),
+                   isa::AggregateClass::Scalars(vec![types::I64, types::I32]));
+        assert_eq!(isa.classify_aggregate(8, CallConv::WindowsFastcall),
+                   isa::AggregateClass::Scalars(vec![types::I64]));
+        assert_eq!(isa.classify_aggregate(12, CallConv::WindowsFastcall),
+                   isa::AggregateClass::Memory);
+        assert_eq!(isa.classify_aggregate(3, CallConv::WindowsFastcall),
+                   isa::AggregateClass::Memory);
     }
 }
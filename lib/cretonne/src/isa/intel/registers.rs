@@ -58,5 +58,9 @@ mod tests {
         assert_eq!(FPR.intersect(FPR), Some(FPR.into()));
         assert_eq!(FPR.intersect(GPR), None);
         assert_eq!(FPR.intersect(ABCD), None);
+        assert_eq!(GPR8.intersect(GPR), Some(GPR8.into()));
+        assert_eq!(GPR8.intersect(ABCD), Some(ABCD.into()));
+        assert_eq!(FPR8.intersect(FPR), Some(FPR8.into()));
+        assert_eq!(FPR8.intersect(GPR8), None);
     }
 }
@@ -11,7 +11,8 @@
 //!
 //! - The name of the target ISA as a string. Cretonne is a cross-compiler, so the ISA to target
 //!   can be selected dynamically. Individual ISAs can be left out when Cretonne is compiled, so a
-//!   string is used to identify the proper sub-module.
+//!   string is used to identify the proper sub-module. The string can also be a target triple like
+//!   `x86_64-unknown-linux-gnu`.
 //! - Values for settings that apply to all ISAs. This is represented by a `settings::Flags`
 //!   instance.
 //! - Values for ISA-specific settings.
@@ -27,26 +28,51 @@
 //! let shared_flags = settings::Flags::new(&shared_builder);
 //!
 //! match isa::lookup("riscv") {
-//!     None => {
+//!     Err(_) => {
 //!         // The RISC-V target ISA is not available.
 //!     }
-//!     Some(mut isa_builder) => {
+//!     Ok(mut isa_builder) => {
 //!         isa_builder.set("supports_m", "on");
 //!         let isa = isa_builder.finish(shared_flags);
 //!     }
 //! }
 //! ```
 //!
+//! A target triple also implies values for some of the shared settings, like `is_64bit`. These are
+//! applied to the shared settings builder by `Builder::configure_shared()`:
+//!
+//! ```
+//! use cretonne::settings;
+//! use cretonne::isa;
+//!
+//! let mut shared_builder = settings::builder();
+//! if let Ok(isa_builder) = isa::lookup("x86_64-unknown-linux-gnu") {
+//!     isa_builder.configure_shared(&mut shared_builder);
+//!     let isa = isa_builder.finish(settings::Flags::new(&shared_builder));
+//!     assert_eq!(isa.name(), "intel");
+//!     assert!(isa.flags().is_64bit());
+//! }
+//! ```
+//!
+//! The `build()` function does all of the above for a target and a list of textual options, like
+//! the ones given on a command line.
+//!
 //! The configured target ISA trait object is a `Box<TargetIsa>` which can be used for multiple
-//! concurrent function compilations.
+//! concurrent function compilations. It is immutable, and `TargetIsa` requires `Send + Sync`, so
+//! it can be shared between threads in an `Arc`. See the `parallel` module.
 
+pub use abi::AggregateClass;
 pub use isa::constraints::{RecipeConstraints, OperandConstraint, ConstraintKind, BranchRange};
 pub use isa::encoding::{Encoding, EncInfo};
 pub use isa::registers::{RegInfo, RegUnit, RegClass, RegClassIndex};
 
-use binemit::CodeSink;
-use settings;
-use ir::{Function, Inst, InstructionData, DataFlowGraph, Signature, Type};
+use binemit::{CodeSink, CodeOffset, MemoryCodeSink};
+use settings::{self, Configurable, Error as SetError, OptionError};
+use abi;
+use ir::{Function, Inst, InstructionData, DataFlowGraph, Signature, Type, CallConv};
+use regalloc::RegDiversions;
+use result::{CtonError, CtonResult, ErrorKind};
+use std::fmt;
 
 pub mod riscv;
 pub mod intel;
@@ -56,44 +82,163 @@ pub mod registers;
 mod encoding;
 mod enc_tables;
 mod constraints;
+mod frame;
 
 /// Look for a supported ISA with the given `name`.
+///
+/// The `name` is either the name of an ISA like `"intel"` or a target triple like
+/// `"x86_64-unknown-linux-gnu"`.
 /// Return a builder that can create a corresponding `TargetIsa`.
-pub fn lookup(name: &str) -> Option<Builder> {
+pub fn lookup(name: &str) -> Result<Builder, LookupError> {
     match name {
         "riscv" => riscv_builder(),
         "intel" => intel_builder(),
         "arm32" => arm32_builder(),
         "arm64" => arm64_builder(),
-        _ => None,
+        _ => lookup_triple(name),
+    }
+}
+
+/// Look for a supported ISA matching the architecture component of a target `triple`.
+///
+/// The architecture determines the default values of the shared `is_64bit` and `is_compressed`
+/// settings as well as some ISA-specific settings.
+fn lookup_triple(triple: &str) -> Result<Builder, LookupError> {
+    let arch = triple.split('-').next().unwrap_or("");
+    let (builder, is_64bit) = match arch {
+        "x86_64" => (intel_builder(), true),
+        "i386" | "i486" | "i586" | "i686" => (intel_builder(), false),
+        "aarch64" => (arm64_builder(), true),
+        _ if arch.starts_with("riscv32") => (riscv_builder(), false),
+        _ if arch.starts_with("riscv64") => (riscv_builder(), true),
+        _ if arch.starts_with("arm") || arch.starts_with("thumb") => (arm32_builder(), false),
+        _ => return Err(LookupError::Unsupported),
+    };
+
+    builder.map(|mut b| {
+        b.shared_defaults.push(("is_64bit", is_64bit));
+        if arch.starts_with("riscv") {
+            // The remaining letters are the standard extensions, like `riscv64gc` or
+            // `riscv32imac`.
+            for ext in arch.get("riscv64".len()..).unwrap_or("").chars() {
+                let names: &[&str] = match ext {
+                    'g' => &["supports_m", "supports_a", "supports_f", "supports_d"],
+                    'm' => &["supports_m"],
+                    'a' => &["supports_a"],
+                    'f' => &["supports_f"],
+                    'd' => &["supports_d"],
+                    _ => &[],
+                };
+                for name in names {
+                    b.setup.set_bool(name, true).expect("Missing RISC-V setting");
+                }
+                if ext == 'c' {
+                    b.shared_defaults.push(("is_compressed", true));
+                }
+            }
+        } else if arch.starts_with("thumb") {
+            b.shared_defaults.push(("is_compressed", true));
+            // The microcontroller profiles like `thumbv7m` and `thumbv7em` don't support A32.
+            let profile = arch.get("thumbv".len()..)
+                .unwrap_or("")
+                .trim_left_matches(|c: char| c.is_digit(10));
+            if profile.contains('m') {
+                b.setup.set_bool("has_a32", false).expect("Missing has_a32 setting");
+            }
+        }
+        b
+    })
+}
+
+/// The reason an ISA lookup failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LookupError {
+    /// The name isn't a known ISA, or the target triple has an unsupported architecture.
+    Unsupported,
+}
+
+/// Build the ISA named by `target`, an ISA name or a target triple, configured by the
+/// comma-separated `options`.
+///
+/// Each option is a `name=value` setting or a boolean flag name. It is applied to the shared
+/// settings if possible, and otherwise to the ISA-specific settings.
+pub fn build(target: &str, options: &str) -> Result<Box<TargetIsa>, BuildError> {
+    let mut isa_builder = match lookup(target) {
+        Ok(b) => b,
+        Err(_) => return Err(BuildError::UnknownTarget(target.to_string())),
+    };
+    let mut flag_builder = settings::builder();
+    isa_builder.configure_shared(&mut flag_builder);
+
+    for opt in options.split(',').map(str::trim).filter(|o| !o.is_empty()) {
+        flag_builder
+            .enable(opt)
+            .or_else(|e| match e {
+                         SetError::BadName => isa_builder.enable(opt),
+                         _ => Err(e),
+                     })
+            .map_err(|e| BuildError::Option(OptionError::new(opt, e)))?;
+    }
+
+    Ok(isa_builder.finish(settings::Flags::new(&flag_builder)))
+}
+
+/// The reason `build()` failed.
+#[derive(Debug, PartialEq, Eq)]
+pub enum BuildError {
+    /// The target isn't a supported ISA name or target triple.
+    UnknownTarget(String),
+    /// An option couldn't be applied to either the shared or the ISA-specific settings.
+    Option(OptionError),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BuildError::UnknownTarget(ref target) => write!(f, "unknown target '{}'", target),
+            BuildError::Option(ref e) => e.fmt(f),
+        }
     }
 }
 
 // Make a builder for RISC-V.
-fn riscv_builder() -> Option<Builder> {
-    Some(riscv::isa_builder())
+fn riscv_builder() -> Result<Builder, LookupError> {
+    Ok(riscv::isa_builder())
 }
 
-fn intel_builder() -> Option<Builder> {
-    Some(intel::isa_builder())
+fn intel_builder() -> Result<Builder, LookupError> {
+    Ok(intel::isa_builder())
 }
 
-fn arm32_builder() -> Option<Builder> {
-    Some(arm32::isa_builder())
+fn arm32_builder() -> Result<Builder, LookupError> {
+    Ok(arm32::isa_builder())
 }
 
-fn arm64_builder() -> Option<Builder> {
-    Some(arm64::isa_builder())
+fn arm64_builder() -> Result<Builder, LookupError> {
+    Ok(arm64::isa_builder())
 }
 
 /// Builder for a `TargetIsa`.
 /// Modify the ISA-specific settings before creating the `TargetIsa` trait object with `finish`.
 pub struct Builder {
     setup: settings::Builder,
+    shared_defaults: Vec<(&'static str, bool)>,
     constructor: fn(settings::Flags, &settings::Builder) -> Box<TargetIsa>,
 }
 
 impl Builder {
+    /// Apply the shared settings implied by the target triple that was used to look up this
+    /// builder.
+    ///
+    /// This should be called on the shared settings builder before creating the `settings::Flags`
+    /// that are passed to `finish()`. Builders that were looked up by ISA name have no shared
+    /// defaults.
+    pub fn configure_shared(&self, shared: &mut settings::Builder) {
+        for &(name, value) in &self.shared_defaults {
+            shared.set_bool(name, value).expect("Missing shared setting");
+        }
+    }
+
     /// Combine the ISA-specific settings with the provided ISA-independent settings and allocate a
     /// fully configured `TargetIsa` trait object.
     pub fn finish(self, shared_flags: settings::Flags) -> Box<TargetIsa> {
@@ -101,7 +246,7 @@ impl Builder {
     }
 }
 
-impl settings::Configurable for Builder {
+impl Configurable for Builder {
     fn set(&mut self, name: &str, value: &str) -> settings::Result<()> {
         self.setup.set(name, value)
     }
@@ -111,6 +256,15 @@ impl settings::Configurable for Builder {
     }
 }
 
+/// The byte order of multi-byte values in memory.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Endianness {
+    /// The least significant byte is stored at the lowest address.
+    Little,
+    /// The most significant byte is stored at the lowest address.
+    Big,
+}
+
 /// After determining that an instruction doesn't have an encoding, how should we proceed to
 /// legalize it?
 ///
@@ -122,16 +276,55 @@ pub enum Legalize {
 
     /// Expanding in terms of other instructions using the same types.
     Expand,
+
+    /// Replace the instruction with a call to a runtime library routine.
+    Libcall,
 }
 
 /// Methods that are specialized to a target ISA.
-pub trait TargetIsa {
+///
+/// Implementations must be immutable after they are built so they can be shared by compilation
+/// threads.
+pub trait TargetIsa: Send + Sync {
     /// Get the name of this ISA.
     fn name(&self) -> &'static str;
 
     /// Get the ISA-independent flags that were used to make this trait object.
     fn flags(&self) -> &settings::Flags;
 
+    /// Get the byte order of this ISA.
+    ///
+    /// This determines which half of a wide integer is stored at the lower address when it is
+    /// split into two memory accesses, and the byte order of the instruction words and jump table
+    /// entries written to a `MemoryCodeSink`. All the current ISAs are little-endian.
+    fn endianness(&self) -> Endianness {
+        Endianness::Little
+    }
+
+    /// Get the values of the ISA-specific settings as bytes.
+    fn isa_settings_bytes(&self) -> &[u8];
+
+    /// Get a serialization of the complete settings state of this ISA.
+    ///
+    /// The key contains the ISA name followed by the shared and ISA-specific settings. Two
+    /// `TargetIsa` objects with the same key generate the same code, so it can be used to key a
+    /// cache of compiled code. The key doesn't identify the version of Cretonne.
+    fn settings_key(&self) -> Vec<u8> {
+        let mut key = Vec::new();
+        key.extend_from_slice(self.name().as_bytes());
+        key.push(0);
+        key.extend_from_slice(self.flags().settings_bytes());
+        key.extend_from_slice(self.isa_settings_bytes());
+        key
+    }
+
+    /// Get a 64-bit hash of `settings_key()`.
+    ///
+    /// The hash function is fixed, so the hash is the same across runs and host platforms.
+    fn settings_hash(&self) -> u64 {
+        fnv1a(&self.settings_key())
+    }
+
     /// Get a data structure describing the registers in this ISA.
     fn register_info(&self) -> RegInfo;
 
@@ -147,9 +340,74 @@ pub trait TargetIsa {
               ctrl_typevar: Type)
               -> Result<Encoding, Legalize>;
 
+    /// Get all the legal encodings of an instruction.
+    ///
+    /// Return an iterator over the encodings of `inst` that satisfy the instruction and ISA
+    /// predicates. The most general encoding comes last, and that is the one returned by
+    /// `encode()`. The encodings of an instruction can have different sizes and branch ranges, so
+    /// this is used by branch relaxation to find a branch encoding with a longer range.
+    fn legal_encodings<'a>(&'a self,
+                           dfg: &'a DataFlowGraph,
+                           inst: &'a InstructionData,
+                           ctrl_typevar: Type)
+                           -> Result<Box<Iterator<Item = Encoding> + 'a>, Legalize>;
+
     /// Get a data structure describing the instruction encodings in this ISA.
     fn encoding_info(&self) -> EncInfo;
 
+    /// Get the register constraints of the encoding `enc`.
+    ///
+    /// This is the authoritative description of the register classes, fixed registers, tied
+    /// results, and clobbered registers of the encoding recipes. Returns `None` for an illegal
+    /// encoding.
+    fn operand_constraints(&self, enc: Encoding) -> Option<&'static RecipeConstraints> {
+        self.encoding_info().constraints.get(enc.recipe())
+    }
+
+    /// Get the pinned register, if any.
+    ///
+    /// When the `enable_pinned_reg` setting is enabled, the ISA reserves a register that is never
+    /// used by the register allocator. The embedder can keep a global value like a heap base or VM
+    /// context pointer in it, and the `get_pinned_reg` instruction reads it.
+    fn pinned_reg(&self) -> Option<RegUnit> {
+        None
+    }
+
+    /// Get the registers that are reserved by the ISA.
+    ///
+    /// Reserved registers have a fixed role like the stack pointer, and they are never used by the
+    /// register allocator.
+    fn reserved_regs(&self) -> &'static [RegUnit] {
+        &[]
+    }
+
+    /// Get the registers that are clobbered by a call to a function with the signature `sig`.
+    ///
+    /// These are the allocatable registers that the callee's calling convention doesn't preserve.
+    fn call_clobbers(&self, _sig: &Signature) -> Vec<RegUnit> {
+        Vec::new()
+    }
+
+    /// Get the registers clobbered by `inst` with the encoding `enc`.
+    ///
+    /// This combines the registers clobbered by the encoding recipe with the `call_clobbers()` of
+    /// the callee for call instructions. Values that are live across `inst` can't be in any of
+    /// these registers.
+    fn inst_clobbers(&self, dfg: &DataFlowGraph, inst: Inst, enc: Encoding) -> Vec<RegUnit> {
+        let mut clobbers = match self.operand_constraints(enc) {
+            Some(constraints) => constraints.clobbers.to_vec(),
+            None => Vec::new(),
+        };
+        if let Some(sig) = dfg.call_signature(inst) {
+            for reg in self.call_clobbers(&dfg.signatures[sig]) {
+                if !clobbers.contains(&reg) {
+                    clobbers.push(reg);
+                }
+            }
+        }
+        clobbers
+    }
+
     /// Legalize a function signature.
     ///
     /// This is used to legalize both the signature of the function being compiled and any called
@@ -183,21 +441,205 @@ pub trait TargetIsa {
     /// Arguments and return values for the caller's frame pointer and other callee-saved registers
     /// should not be added by this function. These arguments are not added until after register
     /// allocation.
-    fn legalize_signature(&self, _sig: &mut Signature, _current: bool) {
-        unimplemented!()
+    fn legalize_signature(&self, sig: &mut Signature, current: bool);
+
+    /// Classify a by-value aggregate of `size` bytes passed to or returned from a function with
+    /// the calling convention `call_conv`.
+    ///
+    /// The default implementation passes aggregates no larger than two pointers as integer
+    /// values, and larger aggregates in memory.
+    fn classify_aggregate(&self, size: u32, _call_conv: CallConv) -> AggregateClass {
+        let reg_bytes = if self.flags().is_64bit() { 8 } else { 4 };
+        abi::classify_aggregate(size, reg_bytes)
+    }
+
+    /// Insert the function prologue and epilogue after register allocation.
+    ///
+    /// This determines which callee-saved registers were used by the register allocator, saves and
+    /// restores them according to the calling convention, and lays out the stack frame. The
+    /// `CalleeSaved` arguments and return values representing the saved registers are added to the
+    /// function signature here.
+    ///
+    /// The default implementation returns an `Unsupported` error for the ISAs that can't generate
+    /// prologues and epilogues yet.
+    fn prologue_epilogue(&self, _func: &mut Function) -> CtonResult {
+        Err(CtonError::new(ErrorKind::Unsupported)
+                .context(format!("{} can't generate prologues and epilogues", self.name())))
+    }
+
+    /// Map a register unit to its DWARF register number.
+    ///
+    /// This is used to generate the unwind information for the frame layout changes recorded by
+    /// `prologue_epilogue()`. Returns `None` for registers that don't have a DWARF number or when
+    /// the ISA doesn't support unwind information.
+    fn map_dwarf_register(&self, _reg: RegUnit) -> Option<u16> {
+        None
+    }
+
+    /// Get the DWARF register number of the return address column.
+    ///
+    /// On ISAs with a link register, this is the DWARF number of the link register.
+    fn dwarf_return_address_column(&self) -> Option<u16> {
+        None
     }
 
     /// Emit binary machine code for a single instruction into the `sink` trait object.
     ///
+    /// The register diversions in `divert` are used to find the current location of the
+    /// instruction operands, and they are updated if `inst` is a `regmove`. They must be cleared
+    /// at the start of each EBB.
+    ///
     /// Note that this will call `put*` methods on the trait object via its vtable which is not the
     /// fastest way of emitting code.
-    fn emit_inst(&self, func: &Function, inst: Inst, sink: &mut CodeSink);
+    fn emit_inst(&self,
+                 func: &Function,
+                 inst: Inst,
+                 divert: &mut RegDiversions,
+                 sink: &mut CodeSink);
+
+    /// Does the emitted code use the compressed instruction encodings?
+    ///
+    /// This follows the shared `is_compressed` setting by default, but an ISA can also be forced
+    /// into a compressed mode by its own settings, like ARM32 without the A32 instruction set. A
+    /// disassembler needs this to pick the right mode.
+    fn uses_compressed_code(&self) -> bool {
+        self.flags().is_compressed()
+    }
+
+    /// Emit `bytes` bytes of padding into the `sink` trait object.
+    ///
+    /// The padding before an aligned loop header can be executed by falling through to the loop
+    /// header, so it consists of no-op instructions.
+    fn emit_padding(&self, bytes: CodeOffset, sink: &mut CodeSink);
+
+    /// Emit a whole function into memory.
+    ///
+    /// This is more performant than calling `emit_inst` for each instruction because the
+    /// instruction emitter can be specialized for the `MemoryCodeSink` type. The function must have
+    /// been prepared by `binemit::relax_branches()`.
+    fn emit_function(&self, func: &Function, sink: &mut MemoryCodeSink);
 
     /// Get a static array of names associated with relocations in this ISA.
     ///
     /// This array can be indexed by the contents of `binemit::Reloc` objects passed to a
     /// `CodeSink`.
-    fn reloc_names(&self) -> &'static [&'static str] {
-        unimplemented!()
+    fn reloc_names(&self) -> &'static [&'static str];
+}
+
+/// The 64-bit FNV-1a hash function.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .fold(0xcbf29ce484222325,
+              |h, &b| (h ^ b as u64).wrapping_mul(0x100000001b3))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{build, lookup, LookupError, TargetIsa};
+    use settings::{self, Configurable};
+
+    fn triple_isa(triple: &str) -> Box<TargetIsa> {
+        let isa_builder = lookup(triple).unwrap();
+        let mut shared_builder = settings::builder();
+        isa_builder.configure_shared(&mut shared_builder);
+        isa_builder.finish(settings::Flags::new(&shared_builder))
+    }
+
+    #[test]
+    fn triples() {
+        let isa = triple_isa("x86_64-unknown-linux-gnu");
+        assert_eq!(isa.name(), "intel");
+        assert!(isa.flags().is_64bit());
+
+        let isa = triple_isa("i686-pc-windows-msvc");
+        assert_eq!(isa.name(), "intel");
+        assert!(!isa.flags().is_64bit());
+
+        let isa = triple_isa("aarch64-apple-darwin");
+        assert_eq!(isa.name(), "arm64");
+        assert!(isa.flags().is_64bit());
+
+        let isa = triple_isa("riscv64gc-unknown-linux-gnu");
+        assert_eq!(isa.name(), "riscv");
+        assert!(isa.flags().is_64bit());
+        assert!(isa.flags().is_compressed());
+
+        let isa = triple_isa("riscv32imac-unknown-none-elf");
+        assert_eq!(isa.name(), "riscv");
+        assert!(!isa.flags().is_64bit());
+
+        let isa = triple_isa("thumbv7em-none-eabihf");
+        assert_eq!(isa.name(), "arm32");
+        assert!(isa.flags().is_compressed());
+
+        let isa = triple_isa("armv7-unknown-linux-gnueabihf");
+        assert_eq!(isa.name(), "arm32");
+        assert!(!isa.flags().is_compressed());
+
+        // Short architecture names must not be sliced past their end.
+        let isa = triple_isa("thumb");
+        assert_eq!(isa.name(), "arm32");
+        assert!(isa.flags().is_compressed());
+
+        assert_eq!(lookup("sparc-sun-solaris").err(), Some(LookupError::Unsupported));
+        assert_eq!(lookup("").err(), Some(LookupError::Unsupported));
+    }
+
+    #[test]
+    fn build_options() {
+        let isa = build("riscv32imac-unknown-none-elf", "opt_level=best, supports_d").unwrap();
+        assert_eq!(isa.name(), "riscv");
+        assert!(!isa.flags().is_64bit());
+        assert_eq!(isa.flags().opt_level(), settings::OptLevel::Best);
+
+        assert_eq!(build("sparc", "").err().unwrap().to_string(),
+                   "unknown target 'sparc'");
+        assert_eq!(build("intel", "is_64bit,frobnicate").err().unwrap().to_string(),
+                   "unknown setting 'frobnicate'");
+        assert_eq!(build("intel", "opt_level=fastest,is_64bit=maybe")
+                       .err()
+                       .unwrap()
+                       .to_string(),
+                   "invalid setting value: 'is_64bit=maybe'");
+
+        // ISA-specific settings are found after the shared settings reject the name.
+        assert_eq!(build("riscv", "supports_m=maybe").err().unwrap().to_string(),
+                   "invalid setting value: 'supports_m=maybe'");
+    }
+
+    #[test]
+    fn isa_names() {
+        // ISA names don't imply any shared settings.
+        let isa_builder = lookup("intel").unwrap();
+        let mut shared_builder = settings::builder();
+        isa_builder.configure_shared(&mut shared_builder);
+        let isa = isa_builder.finish(settings::Flags::new(&shared_builder));
+        assert!(!isa.flags().is_64bit());
+    }
+
+    #[test]
+    fn settings_hash() {
+        let a = triple_isa("x86_64-unknown-linux-gnu");
+        let b = triple_isa("x86_64-unknown-linux-gnu");
+        assert_eq!(a.settings_key(), b.settings_key());
+        assert_eq!(a.settings_hash(), b.settings_hash());
+        assert!(a.settings_key().starts_with(b"intel\0"));
+
+        // A different shared setting.
+        let isa_builder = lookup("x86_64-unknown-linux-gnu").unwrap();
+        let mut shared_builder = settings::builder();
+        isa_builder.configure_shared(&mut shared_builder);
+        shared_builder.set("opt_level", "best").unwrap();
+        let c = isa_builder.finish(settings::Flags::new(&shared_builder));
+        assert!(a.settings_hash() != c.settings_hash());
+
+        // A different ISA-specific setting.
+        let d = triple_isa("riscv64imac-unknown-none-elf");
+        let e = triple_isa("riscv64gc-unknown-none-elf");
+        assert!(d.settings_key() != e.settings_key());
+
+        assert_eq!(super::fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(super::fnv1a(b"a"), 0xaf63dc4c8601ec8c);
     }
 }
@@ -1,6 +1,7 @@
 //! Data structures describing the registers in an ISA.
 
 use entity_map::EntityRef;
+use ir::ValueLoc;
 use std::fmt;
 
 /// Register units are the smallest units of register allocation.
@@ -108,6 +109,12 @@ pub struct RegClassData {
     /// How many register units to allocate per register.
     pub width: u8,
 
+    /// Does this class allocate register pairs?
+    ///
+    /// A register pair holds a wide value in two adjacent registers. The first register holds the
+    /// low half of the value. Register pairs are always two units wide.
+    pub pairs: bool,
+
     /// The first register unit in this class.
     pub first: RegUnit,
 
@@ -146,6 +153,25 @@ impl RegClassData {
         self.subclasses & (1 << other.into().0) != 0
     }
 
+    /// Does this register class contain the register starting at `regunit`?
+    ///
+    /// For classes with `width > 1`, only the first register unit of each register is considered
+    /// a member.
+    pub fn contains(&self, regunit: RegUnit) -> bool {
+        self.mask
+            .get((regunit / 32) as usize)
+            .map_or(false, |&word| word & (1 << (regunit % 32)) != 0)
+    }
+
+    /// Get the value location for the register starting at `regunit` in this class.
+    pub fn location(&self, regunit: RegUnit) -> ValueLoc {
+        if self.pairs {
+            ValueLoc::RegPair(regunit)
+        } else {
+            ValueLoc::Reg(regunit)
+        }
+    }
+
     /// Get a specific register unit in this class.
     pub fn unit(&self, offset: usize) -> RegUnit {
         let uoffset = offset * self.width as usize;
@@ -217,6 +243,23 @@ impl RegInfo {
     pub fn rc(&self, idx: RegClassIndex) -> RegClass {
         &self.classes[idx.index()]
     }
+
+    /// Get the top-level register class containing `rc`.
+    pub fn toprc(&self, rc: RegClass) -> RegClass {
+        // Register classes are ordered topologically, so the first match is a top-level class.
+        self.classes
+            .iter()
+            .find(|top| top.has_subclass(rc))
+            .expect("Register class is not a subclass of itself")
+    }
+
+    /// Get the top-level register class containing the single register `regunit`.
+    pub fn toprc_containing(&self, regunit: RegUnit) -> Option<RegClass> {
+        // Register classes are ordered topologically, so the first match is a top-level class.
+        self.classes
+            .iter()
+            .find(|rc| rc.width == 1 && rc.contains(regunit))
+    }
 }
 
 /// Temporary object that holds enough information to print a register unit.
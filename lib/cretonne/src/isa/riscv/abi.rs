@@ -1,29 +1,40 @@
 //! RISC-V ABI implementation.
 //!
 //! This module implements the RISC-V calling convention through the primary `legalize_signature()`
-//! entry point.
+//! entry point, and the function prologue and epilogue through `prologue_epilogue()`.
 //!
-//! This doesn't support the soft-float ABI at the moment.
+//! With the `soft_float` setting, floating point arguments are passed in integer registers like
+//! integers of the same size. Soft-float `f64` arguments on RV32 are bit-cast to `i64` and split
+//! into a pair of registers like an `i64`.
 
 use abi::{ArgAction, ValueConversion, ArgAssigner, legalize_args};
 use ir::{Signature, Type, ArgumentType, ArgumentLoc, ArgumentExtension, ArgumentPurpose};
+use ir::{Function, ValueLoc, Cursor, InstBuilder, StackSlotData, StackSlotKind,
+         FrameLayoutChange};
+use isa::{TargetIsa, RegUnit};
+use isa::frame::{used_callee_saved, save_link_reg, restore_link_reg};
 use isa::riscv::registers::{GPR, FPR};
+use isa::riscv::settings as isa_settings;
+use result::{CtonError, CtonResult, ErrorKind};
 use settings as shared_settings;
+use stack_layout::{layout_stack, layout_incoming_args};
 
 struct Args {
     pointer_bits: u16,
     pointer_bytes: u32,
     pointer_type: Type,
+    hard_float: bool,
     regs: u32,
     offset: u32,
 }
 
 impl Args {
-    fn new(bits: u16) -> Args {
+    fn new(bits: u16, hard_float: bool) -> Args {
         Args {
             pointer_bits: bits,
             pointer_bytes: bits as u32 / 8,
             pointer_type: Type::int(bits).unwrap(),
+            hard_float: hard_float,
             regs: 0,
             offset: 0,
         }
@@ -52,6 +63,11 @@ impl ArgAssigner for Args {
             return ValueConversion::IntSplit.into();
         }
 
+        // Soft-float values too large for a register are passed like integers of the same size.
+        if ty.is_float() && !self.hard_float && ty.bits() > self.pointer_bits {
+            return ValueConversion::IntBits.into();
+        }
+
         // Small integers are extended to the size of a pointer register.
         if ty.is_int() && ty.bits() < self.pointer_bits {
             match arg.extension {
@@ -63,7 +79,7 @@ impl ArgAssigner for Args {
 
         if self.regs < 8 {
             // Assign to a register.
-            let reg = if ty.is_float() {
+            let reg = if ty.is_float() && self.hard_float {
                 FPR.unit(10 + self.regs as usize)
             } else {
                 GPR.unit(10 + self.regs as usize)
@@ -80,15 +96,34 @@ impl ArgAssigner for Args {
 }
 
 /// Legalize `sig` for RISC-V.
-pub fn legalize_signature(sig: &mut Signature, flags: &shared_settings::Flags, current: bool) {
+pub fn legalize_signature(sig: &mut Signature,
+                          flags: &shared_settings::Flags,
+                          isa_flags: &isa_settings::Flags,
+                          current: bool) {
     let bits = if flags.is_64bit() { 64 } else { 32 };
+    let hard_float = !isa_flags.soft_float();
 
-    let mut args = Args::new(bits);
+    let mut args = Args::new(bits, hard_float);
     legalize_args(&mut sig.argument_types, &mut args);
 
-    let mut rets = Args::new(bits);
+    let mut rets = Args::new(bits, hard_float);
     legalize_args(&mut sig.return_types, &mut rets);
 
+    // Return values that don't fit in registers are returned in memory. The stack locations
+    // assigned above are offsets into a return area provided by the caller, and a pointer to the
+    // return area is passed in an `sret` argument.
+    if rets.offset > 0 &&
+       !sig.argument_types
+            .iter()
+            .any(|arg| arg.purpose == ArgumentPurpose::StructReturn) {
+        let mut sret = ArgumentType::new(args.pointer_type);
+        sret.purpose = ArgumentPurpose::StructReturn;
+        if let ArgAction::Assign(loc) = args.assign(&sret) {
+            sret.location = loc;
+        }
+        sig.argument_types.push(sret);
+    }
+
     if current {
         let ptr = Type::int(bits).unwrap();
 
@@ -102,3 +137,213 @@ pub fn legalize_signature(sig: &mut Signature, flags: &shared_settings::Flags, c
         sig.return_types.push(link);
     }
 }
+
+/// The register reserved by the `enable_pinned_reg` setting.
+///
+/// This is `s11` which is callee-saved, so the pinned value is preserved across calls to functions
+/// that don't know about it.
+pub const PINNED_REG: RegUnit = 27;
+
+/// The thread pointer register `tp`, a.k.a. `%x4`.
+pub const TP_REG: RegUnit = 4;
+
+/// The zero register, the stack pointer, the global pointer, and the thread pointer are never
+/// allocated.
+pub static RESERVED_REGS: [RegUnit; 4] = [0, 2, 3, TP_REG];
+
+/// The RISC-V stack pointer must be 16-byte aligned at all times.
+const STACK_ALIGNMENT: u32 = 16;
+
+/// Is `reg` one of the callee-saved integer registers `s0`-`s11`?
+///
+/// These are `%x8`, `%x9`, and `%x18`-`%x27`. The stack pointer `%x2` is also callee-saved, but it
+/// is maintained by the prologue and epilogue directly.
+fn is_callee_saved(reg: RegUnit) -> bool {
+    (reg >= GPR.unit(8) && reg <= GPR.unit(9)) || (reg >= GPR.unit(18) && reg <= GPR.unit(27))
+}
+
+/// Get the registers that are clobbered by a call.
+///
+/// These are the allocatable integer registers that aren't callee-saved, including the return
+/// address `%x1`, and the floating point registers other than `%f8`, `%f9`, and `%f18`-`%f27`.
+pub fn call_clobbers() -> Vec<RegUnit> {
+    let gprs = (0..32)
+        .map(|i| GPR.unit(i))
+        .filter(|reg| !RESERVED_REGS.contains(reg) && !is_callee_saved(*reg));
+    let fprs = (0..32)
+        .filter(|&i| i < 8 || (i > 9 && i < 18) || i > 27)
+        .map(|i| FPR.unit(i));
+    gprs.chain(fprs).collect()
+}
+
+/// Get the DWARF register number of `reg`.
+///
+/// The `x` registers are numbered 0-31 and the `f` registers 32-63.
+pub fn map_dwarf_register(reg: RegUnit) -> Option<u16> {
+    if GPR.contains(reg) {
+        Some(reg - GPR.unit(0))
+    } else if FPR.contains(reg) {
+        Some(32 + reg - FPR.unit(0))
+    } else {
+        None
+    }
+}
+
+/// Insert the prologue and epilogue code for `func` after register allocation.
+///
+/// Any callee-saved registers used by the register allocator are saved in spill slots at the top
+/// of the entry block and restored before every return. They are represented in the signature as
+/// `csr` arguments and return values so the saved values are visibly passed through the function.
+///
+/// The calls in a non-leaf function overwrite the link register, so the return address is saved
+/// and restored the same way. A leaf function keeps it in the link register.
+///
+/// Finally, the stack frame is laid out, and the stack pointer is adjusted to make room for it.
+/// The frame layout changes are recorded in `func.frame_layout`.
+pub fn prologue_epilogue(func: &mut Function, isa: &TargetIsa) -> CtonResult {
+    let bits = if isa.flags().is_64bit() { 64 } else { 32 };
+    let ptr = Type::int(bits).unwrap();
+    let entry = func.layout
+        .entry_block()
+        .expect("Function has no entry block");
+
+    // Instructions inserted here that need encodings.
+    let mut new_insts = Vec::new();
+
+    // On entry, the CFA is the stack pointer and the return address is in the link register.
+    let sp = GPR.unit(2);
+    func.frame_layout.clear();
+    func.frame_layout
+        .initial
+        .push(FrameLayoutChange::CallFrameAddressAt { reg: sp, offset: 0 });
+
+    // Save the used callee-saved registers at the top of the entry block.
+    let csrs = used_callee_saved(func, is_callee_saved);
+    let mut saves = Vec::with_capacity(csrs.len());
+    // The link register is saved first, so the callee-saved register spills are inserted above it.
+    let link_save = save_link_reg(func, entry, ptr)?;
+    {
+        let mut pos = Cursor::new(&mut func.layout);
+        pos.goto_top(entry);
+        pos.next_inst();
+
+        for &reg in &csrs {
+            let csr = ArgumentType::special_reg(ptr, ArgumentPurpose::CalleeSaved, reg);
+            func.signature.argument_types.push(csr);
+            func.signature.return_types.push(csr);
+
+            let arg = func.dfg.append_ebb_arg(entry, ptr);
+            *func.locations.ensure(arg) = ValueLoc::Reg(reg);
+
+            let ss = func.stack_slots
+                .push(StackSlotData::new(StackSlotKind::SpillSlot, ptr.bytes()));
+            let saved = func.dfg.ins(&mut pos).spill(arg);
+            *func.locations.ensure(saved) = ValueLoc::Stack(ss);
+            saves.push((reg, saved));
+        }
+    }
+    new_insts.extend(saves
+                         .iter()
+                         .chain(&link_save)
+                         .map(|&(_, saved)| func.dfg.value_def(saved).unwrap_inst()));
+
+    // All the stack slots are known now.
+    let frame_size = layout_stack(&mut func.stack_slots, STACK_ALIGNMENT)?;
+
+    // The stack pointer adjustment and all SP-relative offsets must fit in a 12-bit signed
+    // immediate.
+    if frame_size >= 2048 {
+        return Err(CtonError::new(ErrorKind::ImplLimitExceeded)
+                       .context(format!("stack frame of {} bytes exceeds 2 KB", frame_size)));
+    }
+
+    // The stack arguments are just above the frame.
+    layout_incoming_args(&mut func.stack_slots, frame_size)?;
+
+    // Allocate the stack frame before anything else.
+    if frame_size > 0 {
+        let mut pos = Cursor::new(&mut func.layout);
+        pos.goto_top(entry);
+        pos.next_inst();
+        let adjust = func.dfg.ins(&mut pos).adjust_sp_imm(-(frame_size as i64));
+        func.frame_layout
+            .push(adjust,
+                  FrameLayoutChange::CallFrameAddressAt {
+                      reg: sp,
+                      offset: frame_size as i32,
+                  });
+        new_insts.push(adjust);
+    }
+
+    // The save slots are addressed relative to the stack pointer after the frame is allocated.
+    for &(reg, saved) in saves.iter().chain(&link_save) {
+        let inst = func.dfg.value_def(saved).unwrap_inst();
+        let cfa_offset = match func.locations[saved] {
+            ValueLoc::Stack(ss) => func.stack_slots[ss].offset - frame_size as i32,
+            _ => panic!("Callee-saved register not spilled"),
+        };
+        let change = if link_save.map_or(false, |(_, link)| link == saved) {
+            FrameLayoutChange::ReturnAddressAt { cfa_offset: cfa_offset }
+        } else {
+            FrameLayoutChange::RegAt {
+                reg: reg,
+                cfa_offset: cfa_offset,
+            }
+        };
+        func.frame_layout.push(inst, change);
+    }
+
+    // Restore the callee-saved registers and the return address, and free the stack frame before
+    // every return.
+    let mut returns = Vec::new();
+    for ebb in func.layout.ebbs() {
+        if let Some(inst) = func.layout.last_inst(ebb) {
+            if func.dfg[inst].opcode().is_return() {
+                returns.push(inst);
+            }
+        }
+    }
+    for ret in returns {
+        let mut fills = Vec::new();
+        if let Some((reg, saved)) = link_save {
+            fills.push((reg, restore_link_reg(func, ret, reg, saved)?));
+        }
+
+        let mut pos = Cursor::new(&mut func.layout);
+        pos.goto_inst(ret);
+        let mut vlist = func.dfg[ret].take_value_list().unwrap();
+        for &(reg, saved) in &saves {
+            let restored = func.dfg.ins(&mut pos).fill(saved);
+            *func.locations.ensure(restored) = ValueLoc::Reg(reg);
+            vlist.push(restored, &mut func.dfg.value_lists);
+            fills.push((reg, func.dfg.value_def(restored).unwrap_inst()));
+        }
+        func.dfg[ret].put_value_list(vlist);
+
+        let mut epilogue = Vec::new();
+        for (reg, fill) in fills {
+            epilogue.push((fill, FrameLayoutChange::Restore { reg: reg }));
+            new_insts.push(fill);
+        }
+        if frame_size > 0 {
+            let adjust = func.dfg.ins(&mut pos).adjust_sp_imm(frame_size as i64);
+            epilogue.push((adjust, FrameLayoutChange::CallFrameAddressAt { reg: sp, offset: 0 }));
+            new_insts.push(adjust);
+        }
+        if let Some(&(first, _)) = epilogue.first() {
+            func.frame_layout
+                .push(first, FrameLayoutChange::RememberState);
+            func.frame_layout.changes.extend(epilogue);
+            func.frame_layout
+                .push(ret, FrameLayoutChange::RestoreState);
+        }
+    }
+
+    for inst in new_insts {
+        let enc = isa.encode(&func.dfg, &func.dfg[inst], func.dfg.ctrl_typevar(inst))
+            .expect("Can't encode prologue/epilogue instruction");
+        *func.encodings.ensure(inst) = enc;
+    }
+
+    Ok(())
+}
@@ -1,9 +1,11 @@
 //! Emitting binary RISC-V machine code.
 
-use binemit::{CodeSink, Reloc, bad_encoding};
-use ir::{Function, Inst, InstructionData};
+use binemit::{CodeSink, CodeOffset, Reloc, bad_encoding};
+use ir::{Function, FunctionName, Inst, InstructionData, MemOrder, TrapCode};
 use isa::RegUnit;
+use isa::riscv::abi;
 use predicates::is_signed_int;
+use regalloc::RegDiversions;
 
 include!(concat!(env!("OUT_DIR"), "/binemit-riscv.rs"));
 
@@ -11,9 +13,49 @@ include!(concat!(env!("OUT_DIR"), "/binemit-riscv.rs"));
 pub enum RelocKind {
     /// A jal call to a function.
     Call,
+
+    /// An `auipc`+`jalr` call pair to the PLT entry of a function.
+    CallPLT,
+
+    /// The high 20 bits of an absolute function address in a `lui` instruction.
+    Hi20,
+
+    /// The low 12 bits of an absolute function address in an I-type instruction.
+    Lo12I,
+
+    /// The high 20 bits of the PC-relative address of the GOT entry holding a function address,
+    /// in an `auipc` instruction.
+    GotPCRelHi20,
+
+    /// The low 12 bits of the PC-relative address whose high bits were computed by the `auipc`
+    /// instruction immediately before this I-type instruction.
+    PCRelLo12I,
+
+    /// The high 20 bits of the PC-relative address of the GOT entry holding the initial-exec TLS
+    /// offset of a variable, in an `auipc` instruction.
+    TlsIEPCRelHi20,
+
+    /// The high 20 bits of the PC-relative address of the GOT entry pair describing a
+    /// general-dynamic TLS variable, in an `auipc` instruction.
+    TlsGDPCRelHi20,
 }
 
-pub static RELOC_NAMES: [&'static str; 1] = ["Call"];
+pub static RELOC_NAMES: [&'static str; 8] = ["Call",
+                                             "CallPLT",
+                                             "Hi20",
+                                             "Lo12I",
+                                             "GotPCRelHi20",
+                                             "PCRelLo12I",
+                                             "TlsIEPCRelHi20",
+                                             "TlsGDPCRelHi20"];
+
+/// Emit `bytes` bytes of padding as `nop` instructions, i.e. `addi x0, x0, 0`.
+pub fn emit_padding<CS: CodeSink + ?Sized>(bytes: CodeOffset, sink: &mut CS) {
+    assert_eq!(bytes % 4, 0, "Can't pad {} bytes", bytes);
+    for _ in 0..bytes / 4 {
+        sink.put4(0x00000013);
+    }
+}
 
 impl Into<Reloc> for RelocKind {
     fn into(self) -> Reloc {
@@ -87,11 +129,14 @@ fn put_rshamt<CS: CodeSink + ?Sized>(bits: u16,
     sink.put4(i);
 }
 
-fn recipe_r<CS: CodeSink + ?Sized>(func: &Function, inst: Inst, sink: &mut CS) {
+fn recipe_r<CS: CodeSink + ?Sized>(func: &Function,
+                                   inst: Inst,
+                                   divert: &mut RegDiversions,
+                                   sink: &mut CS) {
     if let InstructionData::Binary { args, .. } = func.dfg[inst] {
         put_r(func.encodings[inst].bits(),
-              func.locations[args[0]].unwrap_reg(),
-              func.locations[args[1]].unwrap_reg(),
+              divert.reg(args[0], &func.locations),
+              divert.reg(args[1], &func.locations),
               func.locations[func.dfg.first_result(inst)].unwrap_reg(),
               sink);
     } else {
@@ -99,11 +144,14 @@ fn recipe_r<CS: CodeSink + ?Sized>(func: &Function, inst: Inst, sink: &mut CS) {
     }
 }
 
-fn recipe_ricmp<CS: CodeSink + ?Sized>(func: &Function, inst: Inst, sink: &mut CS) {
+fn recipe_ricmp<CS: CodeSink + ?Sized>(func: &Function,
+                                       inst: Inst,
+                                       divert: &mut RegDiversions,
+                                       sink: &mut CS) {
     if let InstructionData::IntCompare { args, .. } = func.dfg[inst] {
         put_r(func.encodings[inst].bits(),
-              func.locations[args[0]].unwrap_reg(),
-              func.locations[args[1]].unwrap_reg(),
+              divert.reg(args[0], &func.locations),
+              divert.reg(args[1], &func.locations),
               func.locations[func.dfg.first_result(inst)].unwrap_reg(),
               sink);
     } else {
@@ -111,10 +159,13 @@ fn recipe_ricmp<CS: CodeSink + ?Sized>(func: &Function, inst: Inst, sink: &mut C
     }
 }
 
-fn recipe_rshamt<CS: CodeSink + ?Sized>(func: &Function, inst: Inst, sink: &mut CS) {
+fn recipe_rshamt<CS: CodeSink + ?Sized>(func: &Function,
+                                        inst: Inst,
+                                        divert: &mut RegDiversions,
+                                        sink: &mut CS) {
     if let InstructionData::BinaryImm { arg, imm, .. } = func.dfg[inst] {
         put_rshamt(func.encodings[inst].bits(),
-                   func.locations[arg].unwrap_reg(),
+                   divert.reg(arg, &func.locations),
                    imm.into(),
                    func.locations[func.dfg.first_result(inst)].unwrap_reg(),
                    sink);
@@ -148,10 +199,13 @@ fn put_i<CS: CodeSink + ?Sized>(bits: u16, rs1: RegUnit, imm: i64, rd: RegUnit,
     sink.put4(i);
 }
 
-fn recipe_i<CS: CodeSink + ?Sized>(func: &Function, inst: Inst, sink: &mut CS) {
+fn recipe_i<CS: CodeSink + ?Sized>(func: &Function,
+                                   inst: Inst,
+                                   divert: &mut RegDiversions,
+                                   sink: &mut CS) {
     if let InstructionData::BinaryImm { arg, imm, .. } = func.dfg[inst] {
         put_i(func.encodings[inst].bits(),
-              func.locations[arg].unwrap_reg(),
+              divert.reg(arg, &func.locations),
               imm.into(),
               func.locations[func.dfg.first_result(inst)].unwrap_reg(),
               sink);
@@ -160,10 +214,13 @@ fn recipe_i<CS: CodeSink + ?Sized>(func: &Function, inst: Inst, sink: &mut CS) {
     }
 }
 
-fn recipe_iicmp<CS: CodeSink + ?Sized>(func: &Function, inst: Inst, sink: &mut CS) {
+fn recipe_iicmp<CS: CodeSink + ?Sized>(func: &Function,
+                                       inst: Inst,
+                                       divert: &mut RegDiversions,
+                                       sink: &mut CS) {
     if let InstructionData::IntCompareImm { arg, imm, .. } = func.dfg[inst] {
         put_i(func.encodings[inst].bits(),
-              func.locations[arg].unwrap_reg(),
+              divert.reg(arg, &func.locations),
               imm.into(),
               func.locations[func.dfg.first_result(inst)].unwrap_reg(),
               sink);
@@ -172,7 +229,79 @@ fn recipe_iicmp<CS: CodeSink + ?Sized>(func: &Function, inst: Inst, sink: &mut C
     }
 }
 
-fn recipe_iret<CS: CodeSink + ?Sized>(func: &Function, inst: Inst, sink: &mut CS) {
+fn recipe_icopy<CS: CodeSink + ?Sized>(func: &Function,
+                                       inst: Inst,
+                                       divert: &mut RegDiversions,
+                                       sink: &mut CS) {
+    if let InstructionData::Unary { arg, .. } = func.dfg[inst] {
+        put_i(func.encodings[inst].bits(),
+              divert.reg(arg, &func.locations),
+              0,
+              func.locations[func.dfg.first_result(inst)].unwrap_reg(),
+              sink);
+    } else {
+        panic!("Expected Unary format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_irmov<CS: CodeSink + ?Sized>(func: &Function,
+                                       inst: Inst,
+                                       divert: &mut RegDiversions,
+                                       sink: &mut CS) {
+    if let InstructionData::RegMove { arg, src, dst, .. } = func.dfg[inst] {
+        divert.regmove(arg, src, dst);
+        put_i(func.encodings[inst].bits(), src, 0, dst, sink);
+    } else {
+        panic!("Expected RegMove format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_iadjsp<CS: CodeSink + ?Sized>(func: &Function,
+                                        inst: Inst,
+                                        _divert: &mut RegDiversions,
+                                        sink: &mut CS) {
+    if let InstructionData::UnaryImm { imm, .. } = func.dfg[inst] {
+        put_i(func.encodings[inst].bits(),
+              2, // rs1 = %x2 (sp)
+              imm.into(),
+              2, // rd = %x2 (sp)
+              sink);
+    } else {
+        panic!("Expected UnaryImm format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_gpfi<CS: CodeSink + ?Sized>(func: &Function,
+                                      inst: Inst,
+                                      divert: &mut RegDiversions,
+                                      sink: &mut CS) {
+    if let InstructionData::Unary { arg, .. } = func.dfg[inst] {
+        let ss = divert.stack(arg, &func.locations);
+        put_i(func.encodings[inst].bits(),
+              2, // rs1 = %x2 (sp)
+              func.stack_slots[ss].offset as i64,
+              func.locations[func.dfg.first_result(inst)].unwrap_reg(),
+              sink);
+    } else {
+        panic!("Expected Unary format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_ipinned<CS: CodeSink + ?Sized>(func: &Function,
+                                         inst: Inst,
+                                         _divert: &mut RegDiversions,
+                                         sink: &mut CS) {
+    put_i(func.encodings[inst].bits(),
+          abi::PINNED_REG,
+          0,
+          func.locations[func.dfg.first_result(inst)].unwrap_reg(),
+          sink);
+}
+
+fn recipe_iret<CS: CodeSink + ?Sized>(func: &Function,
+                                      inst: Inst,
+                                      _divert: &mut RegDiversions,
+                                      sink: &mut CS) {
     // Return instructions are always a jalr to %x1.
     // The return address is provided as a special-purpose link argument.
     put_i(func.encodings[inst].bits(),
@@ -203,7 +332,10 @@ fn put_u<CS: CodeSink + ?Sized>(bits: u16, imm: i64, rd: RegUnit, sink: &mut CS)
     sink.put4(i);
 }
 
-fn recipe_u<CS: CodeSink + ?Sized>(func: &Function, inst: Inst, sink: &mut CS) {
+fn recipe_u<CS: CodeSink + ?Sized>(func: &Function,
+                                   inst: Inst,
+                                   _divert: &mut RegDiversions,
+                                   sink: &mut CS) {
     if let InstructionData::UnaryImm { imm, .. } = func.dfg[inst] {
         put_u(func.encodings[inst].bits(),
               imm.into(),
@@ -214,6 +346,51 @@ fn recipe_u<CS: CodeSink + ?Sized>(func: &Function, inst: Inst, sink: &mut CS) {
     }
 }
 
+/// S-type store instructions.
+///
+///   31  24  19  14     11  6
+///   imm rs2 rs1 funct3 imm opcode
+///    25  20  15     12   7      0
+///
+/// Encoding bits: `opcode[6:2] | (funct3 << 5)`
+fn put_s<CS: CodeSink + ?Sized>(bits: u16, imm: i64, rs1: RegUnit, rs2: RegUnit, sink: &mut CS) {
+    let bits = bits as u32;
+    let opcode5 = bits & 0x1f;
+    let funct3 = (bits >> 5) & 0x7;
+    let rs1 = rs1 as u32 & 0x1f;
+    let rs2 = rs2 as u32 & 0x1f;
+
+    assert!(is_signed_int(imm, 12, 0), "S out of range {:#x}", imm);
+    let imm = imm as u32;
+
+    // 0-6: opcode
+    let mut i = 0x3;
+    i |= opcode5 << 2;
+    i |= (imm & 0x1f) << 7;
+    i |= funct3 << 12;
+    i |= rs1 << 15;
+    i |= rs2 << 20;
+    i |= ((imm >> 5) & 0x7f) << 25;
+
+    sink.put4(i);
+}
+
+fn recipe_gpsp<CS: CodeSink + ?Sized>(func: &Function,
+                                      inst: Inst,
+                                      divert: &mut RegDiversions,
+                                      sink: &mut CS) {
+    if let InstructionData::Unary { arg, .. } = func.dfg[inst] {
+        let ss = func.locations[func.dfg.first_result(inst)].unwrap_stack();
+        put_s(func.encodings[inst].bits(),
+              func.stack_slots[ss].offset as i64,
+              2, // rs1 = %x2 (sp)
+              divert.reg(arg, &func.locations),
+              sink);
+    } else {
+        panic!("Expected Unary format: {:?}", func.dfg[inst]);
+    }
+}
+
 /// SB-type branch instructions.
 ///
 ///   31  24  19  14     11  6
@@ -247,7 +424,10 @@ fn put_sb<CS: CodeSink + ?Sized>(bits: u16, imm: i64, rs1: RegUnit, rs2: RegUnit
     sink.put4(i);
 }
 
-fn recipe_sb<CS: CodeSink + ?Sized>(func: &Function, inst: Inst, sink: &mut CS) {
+fn recipe_sb<CS: CodeSink + ?Sized>(func: &Function,
+                                    inst: Inst,
+                                    divert: &mut RegDiversions,
+                                    sink: &mut CS) {
     if let InstructionData::BranchIcmp {
                destination,
                ref args,
@@ -258,15 +438,18 @@ fn recipe_sb<CS: CodeSink + ?Sized>(func: &Function, inst: Inst, sink: &mut CS)
         let args = &args.as_slice(&func.dfg.value_lists)[0..2];
         put_sb(func.encodings[inst].bits(),
                disp,
-               func.locations[args[0]].unwrap_reg(),
-               func.locations[args[1]].unwrap_reg(),
+               divert.reg(args[0], &func.locations),
+               divert.reg(args[1], &func.locations),
                sink);
     } else {
         panic!("Expected BranchIcmp format: {:?}", func.dfg[inst]);
     }
 }
 
-fn recipe_sbzero<CS: CodeSink + ?Sized>(func: &Function, inst: Inst, sink: &mut CS) {
+fn recipe_sbzero<CS: CodeSink + ?Sized>(func: &Function,
+                                        inst: Inst,
+                                        divert: &mut RegDiversions,
+                                        sink: &mut CS) {
     if let InstructionData::Branch {
                destination,
                ref args,
@@ -277,7 +460,7 @@ fn recipe_sbzero<CS: CodeSink + ?Sized>(func: &Function, inst: Inst, sink: &mut
         let args = &args.as_slice(&func.dfg.value_lists)[0..1];
         put_sb(func.encodings[inst].bits(),
                disp,
-               func.locations[args[0]].unwrap_reg(),
+               divert.reg(args[0], &func.locations),
                0,
                sink);
     } else {
@@ -314,7 +497,10 @@ fn put_uj<CS: CodeSink + ?Sized>(bits: u16, imm: i64, rd: RegUnit, sink: &mut CS
     sink.put4(i);
 }
 
-fn recipe_uj<CS: CodeSink + ?Sized>(func: &Function, inst: Inst, sink: &mut CS) {
+fn recipe_uj<CS: CodeSink + ?Sized>(func: &Function,
+                                    inst: Inst,
+                                    _divert: &mut RegDiversions,
+                                    sink: &mut CS) {
     if let InstructionData::Jump { destination, .. } = func.dfg[inst] {
         let dest = func.offsets[destination] as i64;
         let disp = dest - sink.offset() as i64;
@@ -324,9 +510,290 @@ fn recipe_uj<CS: CodeSink + ?Sized>(func: &Function, inst: Inst, sink: &mut CS)
     }
 }
 
-fn recipe_ujcall<CS: CodeSink + ?Sized>(func: &Function, inst: Inst, sink: &mut CS) {
+// Encoding bits for the U-type `auipc` and `lui` instructions which start some of the
+// two-instruction recipes below.
+const AUIPC_BITS: u16 = 0b00101;
+const LUI_BITS: u16 = 0b01101;
+
+// Encoding bits for the I-type `addi` and `jalr` instructions in the general-dynamic TLS sequence.
+const ADDI_BITS: u16 = 0b00100;
+const JALR_BITS: u16 = 0b11001;
+
+/// The `a0` register holds the argument and return value of `__tls_get_addr`.
+const A0: RegUnit = 10;
+
+fn recipe_icallplt<CS: CodeSink + ?Sized>(func: &Function,
+                                          inst: Inst,
+                                          _divert: &mut RegDiversions,
+                                          sink: &mut CS) {
+    if let InstructionData::Call { func_ref, .. } = func.dfg[inst] {
+        sink.reloc_func(RelocKind::CallPLT.into(), func_ref, 0);
+        // rd=%x1 is the standard link register.
+        put_u(AUIPC_BITS, 0, 1, sink);
+        put_i(func.encodings[inst].bits(), 1, 0, 1, sink);
+    } else {
+        panic!("Expected Call format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_ifnaddr<CS: CodeSink + ?Sized>(func: &Function,
+                                         inst: Inst,
+                                         _divert: &mut RegDiversions,
+                                         sink: &mut CS) {
+    if let InstructionData::FuncAddr { func_ref, .. } = func.dfg[inst] {
+        let rd = func.locations[func.dfg.first_result(inst)].unwrap_reg();
+        sink.reloc_func(RelocKind::Hi20.into(), func_ref, 0);
+        put_u(LUI_BITS, 0, rd, sink);
+        sink.reloc_func(RelocKind::Lo12I.into(), func_ref, 0);
+        put_i(func.encodings[inst].bits(), rd, 0, rd, sink);
+    } else {
+        panic!("Expected FuncAddr format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_igotpcrel<CS: CodeSink + ?Sized>(func: &Function,
+                                           inst: Inst,
+                                           _divert: &mut RegDiversions,
+                                           sink: &mut CS) {
+    if let InstructionData::FuncAddr { func_ref, .. } = func.dfg[inst] {
+        let rd = func.locations[func.dfg.first_result(inst)].unwrap_reg();
+        sink.reloc_func(RelocKind::GotPCRelHi20.into(), func_ref, 0);
+        put_u(AUIPC_BITS, 0, rd, sink);
+        sink.reloc_func(RelocKind::PCRelLo12I.into(), func_ref, 0);
+        put_i(func.encodings[inst].bits(), rd, 0, rd, sink);
+    } else {
+        panic!("Expected FuncAddr format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_itp<CS: CodeSink + ?Sized>(func: &Function,
+                                     inst: Inst,
+                                     _divert: &mut RegDiversions,
+                                     sink: &mut CS) {
+    put_i(func.encodings[inst].bits(),
+          abi::TP_REG,
+          0,
+          func.locations[func.dfg.first_result(inst)].unwrap_reg(),
+          sink);
+}
+
+fn recipe_itlsie<CS: CodeSink + ?Sized>(func: &Function,
+                                        inst: Inst,
+                                        _divert: &mut RegDiversions,
+                                        sink: &mut CS) {
+    if let InstructionData::UnaryGlobalVar { global_var, .. } = func.dfg[inst] {
+        let rd = func.locations[func.dfg.first_result(inst)].unwrap_reg();
+        let name = &func.global_vars[global_var].name;
+        sink.reloc_external(RelocKind::TlsIEPCRelHi20.into(), name, 0);
+        put_u(AUIPC_BITS, 0, rd, sink);
+        sink.reloc_external(RelocKind::PCRelLo12I.into(), name, 0);
+        put_i(func.encodings[inst].bits(), rd, 0, rd, sink);
+    } else {
+        panic!("Expected UnaryGlobalVar format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_itlsgd<CS: CodeSink + ?Sized>(func: &Function,
+                                        inst: Inst,
+                                        _divert: &mut RegDiversions,
+                                        sink: &mut CS) {
+    if let InstructionData::UnaryGlobalVar { global_var, .. } = func.dfg[inst] {
+        let rd = func.locations[func.dfg.first_result(inst)].unwrap_reg();
+        let name = &func.global_vars[global_var].name;
+        sink.reloc_external(RelocKind::TlsGDPCRelHi20.into(), name, 0);
+        put_u(AUIPC_BITS, 0, A0, sink);
+        sink.reloc_external(RelocKind::PCRelLo12I.into(), name, 0);
+        put_i(ADDI_BITS, A0, 0, A0, sink);
+        sink.reloc_external(RelocKind::CallPLT.into(), &FunctionName::new("__tls_get_addr"), 0);
+        // rd=%x1 is the standard link register.
+        put_u(AUIPC_BITS, 0, 1, sink);
+        put_i(JALR_BITS, 1, 0, 1, sink);
+        put_i(func.encodings[inst].bits(), A0, 0, rd, sink);
+    } else {
+        panic!("Expected UnaryGlobalVar format: {:?}", func.dfg[inst]);
+    }
+}
+
+/// Get the encoding bits of an atomic memory operation with the `aq` and `rl` bits set in funct7.
+fn amo_bits(bits: u16, aq: bool, rl: bool) -> u16 {
+    bits | ((aq as u16) << 9) | ((rl as u16) << 8)
+}
+
+// Encoding bits for the instructions in the atomic sequences below, without funct3 which gives
+// the operand width.
+const LR_BITS: u16 = 0b01011 | (0b00010 << 10);
+const SC_BITS: u16 = 0b01011 | (0b00011 << 10);
+const SUB_BITS: u16 = 0b01100 | (0b0100000 << 8);
+const BNE_BITS: u16 = 0b11000 | (0b001 << 5);
+const JAL_BITS: u16 = 0b11011;
+
+/// Registers that can be used as a scratch register in the compare-and-swap loop.
+const CAS_SCRATCH: [RegUnit; 5] = [5, 6, 7, 28, 29];
+
+fn recipe_rlr<CS: CodeSink + ?Sized>(func: &Function,
+                                     inst: Inst,
+                                     divert: &mut RegDiversions,
+                                     sink: &mut CS) {
+    if let InstructionData::AtomicLoad { order, arg, .. } = func.dfg[inst] {
+        // A sequentially consistent load is `lr.aqrl`.
+        sink.trap(TrapCode::HeapOutOfBounds);
+        put_r(amo_bits(func.encodings[inst].bits(),
+                       order.is_acquire(),
+                       order == MemOrder::SeqCst),
+              divert.reg(arg, &func.locations),
+              0,
+              func.locations[func.dfg.first_result(inst)].unwrap_reg(),
+              sink);
+    } else {
+        panic!("Expected AtomicLoad format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_ramost<CS: CodeSink + ?Sized>(func: &Function,
+                                        inst: Inst,
+                                        divert: &mut RegDiversions,
+                                        sink: &mut CS) {
+    if let InstructionData::AtomicRmw { order, args, .. } = func.dfg[inst] {
+        // A sequentially consistent store is `amoswap.aqrl`.
+        sink.trap(TrapCode::HeapOutOfBounds);
+        put_r(amo_bits(func.encodings[inst].bits(),
+                       order == MemOrder::SeqCst,
+                       order.is_release()),
+              divert.reg(args[1], &func.locations),
+              divert.reg(args[0], &func.locations),
+              0,
+              sink);
+    } else {
+        panic!("Expected AtomicRmw format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_ramo<CS: CodeSink + ?Sized>(func: &Function,
+                                      inst: Inst,
+                                      divert: &mut RegDiversions,
+                                      sink: &mut CS) {
+    if let InstructionData::AtomicRmw { order, args, .. } = func.dfg[inst] {
+        sink.trap(TrapCode::HeapOutOfBounds);
+        put_r(amo_bits(func.encodings[inst].bits(),
+                       order.is_acquire(),
+                       order.is_release()),
+              divert.reg(args[1], &func.locations),
+              divert.reg(args[0], &func.locations),
+              func.locations[func.dfg.first_result(inst)].unwrap_reg(),
+              sink);
+    } else {
+        panic!("Expected AtomicRmw format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_ramosub<CS: CodeSink + ?Sized>(func: &Function,
+                                         inst: Inst,
+                                         divert: &mut RegDiversions,
+                                         sink: &mut CS) {
+    if let InstructionData::AtomicRmw { order, args, .. } = func.dfg[inst] {
+        let x = divert.reg(args[0], &func.locations);
+        let p = divert.reg(args[1], &func.locations);
+        put_r(SUB_BITS, 0, x, x, sink);
+        sink.trap(TrapCode::HeapOutOfBounds);
+        put_r(amo_bits(func.encodings[inst].bits(),
+                       order.is_acquire(),
+                       order.is_release()),
+              p,
+              x,
+              x,
+              sink);
+    } else {
+        panic!("Expected AtomicRmw format: {:?}", func.dfg[inst]);
+    }
+}
+
+/// Emit a compare-and-swap loop:
+///
+/// ```text
+///     addi sp, sp, -16
+///     sw   t, 0(sp)
+/// 1:  lr.w t, (p)
+///     bne  t, e, 2f
+///     sc.w t, x, (p)
+///     bnez t, 1b
+///     j    3f
+/// 2:  mv   e, t
+/// 3:  lw   t, 0(sp)
+///     addi sp, sp, 16
+/// ```
+///
+/// The scratch register `t` is picked from `CAS_SCRATCH` so it doesn't interfere with the operands.
+/// The 64-bit loop uses `lr.d`, `sc.d`, `sd`, and `ld`.
+fn recipe_rcas<CS: CodeSink + ?Sized>(func: &Function,
+                                      inst: Inst,
+                                      divert: &mut RegDiversions,
+                                      sink: &mut CS) {
+    if let InstructionData::AtomicCas { order, args, .. } = func.dfg[inst] {
+        let e = divert.reg(args[0], &func.locations);
+        let x = divert.reg(args[1], &func.locations);
+        let p = divert.reg(args[2], &func.locations);
+        let t = *CAS_SCRATCH
+                     .iter()
+                     .find(|&&r| r != e && r != x && r != p)
+                     .expect("No scratch register for CAS loop");
+        // The funct3 bits select the operand width of all the loads and stores.
+        let funct3 = func.encodings[inst].bits() & (0b111 << 5);
+
+        put_i(ADDI_BITS, 2, -16, 2, sink);
+        put_s(funct3 | 0b01000, 0, 2, t, sink);
+        sink.trap(TrapCode::HeapOutOfBounds);
+        put_r(amo_bits(funct3 | LR_BITS,
+                       order.is_acquire(),
+                       order == MemOrder::SeqCst),
+              p,
+              0,
+              t,
+              sink);
+        put_sb(BNE_BITS, 16, t, e, sink);
+        put_r(amo_bits(funct3 | SC_BITS, false, order.is_release()),
+              p,
+              x,
+              t,
+              sink);
+        put_sb(BNE_BITS, -12, t, 0, sink);
+        put_uj(JAL_BITS, 8, 0, sink);
+        put_i(ADDI_BITS, t, 0, e, sink);
+        put_i(funct3, 2, 0, t, sink);
+        put_i(ADDI_BITS, 2, 16, 2, sink);
+    } else {
+        panic!("Expected AtomicCas format: {:?}", func.dfg[inst]);
+    }
+}
+
+/// Fence instructions.
+///
+///   31 27   23   19  14     11 6
+///   fm pred succ rs1 funct3 rd opcode
+///   28   24   20  15     12  7      0
+///
+/// The `pred` and `succ` sets use the bits I, O, R, W from high to low.
+fn recipe_ifence<CS: CodeSink + ?Sized>(func: &Function,
+                                        inst: Inst,
+                                        _divert: &mut RegDiversions,
+                                        sink: &mut CS) {
+    if let InstructionData::Fence { order, .. } = func.dfg[inst] {
+        const R: i64 = 0b0010;
+        const W: i64 = 0b0001;
+        // An acquire fence is `fence r, rw`, and a release fence is `fence rw, w`.
+        let pred = if order.is_release() { R | W } else { R };
+        let succ = if order.is_acquire() { R | W } else { W };
+        put_i(func.encodings[inst].bits(), 0, (pred << 4) | succ, 0, sink);
+    } else {
+        panic!("Expected Fence format: {:?}", func.dfg[inst]);
+    }
+}
+
+fn recipe_ujcall<CS: CodeSink + ?Sized>(func: &Function,
+                                        inst: Inst,
+                                        _divert: &mut RegDiversions,
+                                        sink: &mut CS) {
     if let InstructionData::Call { func_ref, .. } = func.dfg[inst] {
-        sink.reloc_func(RelocKind::Call.into(), func_ref);
+        sink.reloc_func(RelocKind::Call.into(), func_ref, 0);
         // rd=%x1 is the standard link register.
         put_uj(func.encodings[inst].bits(), 0, 1, sink);
     } else {
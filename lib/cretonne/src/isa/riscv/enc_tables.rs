@@ -2,7 +2,7 @@
 
 use ir::condcodes::IntCC;
 use ir::types;
-use ir::{Opcode, InstructionData};
+use ir::{Opcode, InstructionData, DataFlowGraph};
 use isa::EncInfo;
 use isa::constraints::*;
 use isa::enc_tables::{Level1Entry, Level2Entry};
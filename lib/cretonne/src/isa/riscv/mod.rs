@@ -7,11 +7,13 @@ mod enc_tables;
 mod registers;
 
 use super::super::settings as shared_settings;
-use binemit::CodeSink;
-use isa::enc_tables::{self as shared_enc_tables, lookup_enclist, general_encoding};
+use binemit::{CodeSink, CodeOffset, MemoryCodeSink, emit_function};
+use isa::enc_tables::{self as shared_enc_tables, lookup_enclist, general_encoding, Encodings};
 use isa::Builder as IsaBuilder;
-use isa::{TargetIsa, RegInfo, EncInfo, Encoding, Legalize};
+use isa::{TargetIsa, RegInfo, RegUnit, EncInfo, Encoding, Legalize};
 use ir::{Function, Inst, InstructionData, DataFlowGraph, Signature, Type};
+use regalloc::RegDiversions;
+use result::CtonResult;
 
 #[allow(dead_code)]
 struct Isa {
@@ -24,6 +26,7 @@ struct Isa {
 pub fn isa_builder() -> IsaBuilder {
     IsaBuilder {
         setup: settings::builder(),
+        shared_defaults: Vec::new(),
         constructor: isa_constructor,
     }
 }
@@ -52,6 +55,10 @@ impl TargetIsa for Isa {
         &self.shared_flags
     }
 
+    fn isa_settings_bytes(&self) -> &[u8] {
+        self.isa_flags.settings_bytes()
+    }
+
     fn register_info(&self) -> RegInfo {
         registers::INFO.clone()
     }
@@ -60,8 +67,24 @@ impl TargetIsa for Isa {
         enc_tables::INFO.clone()
     }
 
+    fn reserved_regs(&self) -> &'static [RegUnit] {
+        &abi::RESERVED_REGS
+    }
+
+    fn pinned_reg(&self) -> Option<RegUnit> {
+        if self.shared_flags.enable_pinned_reg() {
+            Some(abi::PINNED_REG)
+        } else {
+            None
+        }
+    }
+
+    fn call_clobbers(&self, _sig: &Signature) -> Vec<RegUnit> {
+        abi::call_clobbers()
+    }
+
     fn encode(&self,
-              _dfg: &DataFlowGraph,
+              dfg: &DataFlowGraph,
               inst: &InstructionData,
               ctrl_typevar: Type)
               -> Result<Encoding, Legalize> {
@@ -72,19 +95,65 @@ impl TargetIsa for Isa {
                 .and_then(|enclist_offset| {
                     general_encoding(enclist_offset,
                                      &enc_tables::ENCLISTS[..],
-                                     |instp| enc_tables::check_instp(inst, instp),
+                                     |instp| enc_tables::check_instp(inst, instp, dfg),
                                      |isap| self.isa_flags.numbered_predicate(isap as usize))
                             .ok_or(Legalize::Expand)
                 })
+                .map_err(|action| if self.isa_flags.soft_float() &&
+                                     uses_float(dfg, inst, ctrl_typevar) {
+                             Legalize::Libcall
+                         } else {
+                             action
+                         })
+    }
+
+    fn legal_encodings<'a>(&'a self,
+                           dfg: &'a DataFlowGraph,
+                           inst: &'a InstructionData,
+                           ctrl_typevar: Type)
+                           -> Result<Box<Iterator<Item = Encoding> + 'a>, Legalize> {
+        lookup_enclist(ctrl_typevar, inst.opcode(), self.cpumode, &enc_tables::LEVEL2[..])
+            .map(|enclist_offset| {
+                let encs = Encodings::new(enclist_offset,
+                                          &enc_tables::ENCLISTS[..],
+                                          move |instp| enc_tables::check_instp(inst, instp, dfg),
+                                          move |isap| {
+                                              self.isa_flags.numbered_predicate(isap as usize)
+                                          });
+                Box::new(encs) as Box<Iterator<Item = Encoding>>
+            })
     }
 
     fn legalize_signature(&self, sig: &mut Signature, current: bool) {
-        // We can pass in `self.isa_flags` too, if we need it.
-        abi::legalize_signature(sig, &self.shared_flags, current)
+        abi::legalize_signature(sig, &self.shared_flags, &self.isa_flags, current)
+    }
+
+    fn prologue_epilogue(&self, func: &mut Function) -> CtonResult {
+        abi::prologue_epilogue(func, self)
+    }
+
+    fn map_dwarf_register(&self, reg: RegUnit) -> Option<u16> {
+        abi::map_dwarf_register(reg)
     }
 
-    fn emit_inst(&self, func: &Function, inst: Inst, sink: &mut CodeSink) {
-        binemit::emit_inst(func, inst, sink)
+    fn dwarf_return_address_column(&self) -> Option<u16> {
+        Some(1)
+    }
+
+    fn emit_inst(&self,
+                 func: &Function,
+                 inst: Inst,
+                 divert: &mut RegDiversions,
+                 sink: &mut CodeSink) {
+        binemit::emit_inst(func, inst, divert, sink)
+    }
+
+    fn emit_padding(&self, bytes: CodeOffset, sink: &mut CodeSink) {
+        binemit::emit_padding(bytes, sink)
+    }
+
+    fn emit_function(&self, func: &Function, sink: &mut MemoryCodeSink) {
+        emit_function(func, binemit::emit_inst, binemit::emit_padding, sink)
     }
 
     fn reloc_names(&self) -> &'static [&'static str] {
@@ -92,6 +161,16 @@ impl TargetIsa for Isa {
     }
 }
 
+/// Does `inst` have any floating point operands or a floating point controlling type?
+///
+/// With the `soft_float` setting, these instructions are legalized as library calls.
+fn uses_float(dfg: &DataFlowGraph, inst: &InstructionData, ctrl_typevar: Type) -> bool {
+    ctrl_typevar.is_float() ||
+    inst.arguments(&dfg.value_lists)
+        .iter()
+        .any(|&arg| dfg.value_type(arg).is_float())
+}
+
 #[cfg(test)]
 mod tests {
     use settings::{self, Configurable};
@@ -230,4 +309,27 @@ mod tests {
         assert_eq!(encstr(&*isa, isa.encode(&dfg, &mul32, types::I32).unwrap()),
                    "R#10c");
     }
+
+    #[test]
+    fn test_pinned_reg() {
+        let get_pinned = InstructionData::Nullary { opcode: Opcode::GetPinnedReg };
+        let dfg = DataFlowGraph::new();
+
+        // Without the setting, there is no pinned register and nothing to read.
+        let isa = isa::lookup("riscv")
+            .unwrap()
+            .finish(settings::Flags::new(&settings::builder()));
+        assert_eq!(isa.pinned_reg(), None);
+        assert_eq!(isa.encode(&dfg, &get_pinned, types::I32),
+                   Err(isa::Legalize::Expand));
+
+        let mut shared_builder = settings::builder();
+        shared_builder.set_bool("enable_pinned_reg", true).unwrap();
+        let isa = isa::lookup("riscv")
+            .unwrap()
+            .finish(settings::Flags::new(&shared_builder));
+        assert_eq!(isa.pinned_reg(), Some(27));
+        assert_eq!(encstr(&*isa, isa.encode(&dfg, &get_pinned, types::I32).unwrap()),
+                   "Ipinned#04");
+    }
 }
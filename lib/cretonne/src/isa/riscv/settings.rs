@@ -24,7 +24,8 @@ mod tests {
                     supports_a = false\n\
                     supports_f = false\n\
                     supports_d = false\n\
-                    enable_m = true\n");
+                    enable_m = true\n\
+                    soft_float = false\n");
         // Predicates are not part of the Display output.
         assert_eq!(f.full_float(), false);
     }
@@ -16,11 +16,33 @@
 //!
 //! Between the two phases, preamble signatures and call/return arguments don't match. This
 //! intermediate state doesn't type check.
+//!
+//! # Return values in memory
+//!
+//! When a signature has more return values than the ABI can return in registers, the ISA can
+//! assign stack locations to the excess return values and add an `sret` argument pointing to a
+//! return area in the caller's frame. The stack locations of the return values are then byte
+//! offsets into the return area:
+//!
+//! - Call sites allocate a stack slot for the return area, pass its address as the `sret`
+//!   argument, and load the returned values from the stack slot after the call.
+//! - Return instructions store the values through the `sret` pointer. The values are still passed
+//!   to the return instruction so it matches the signature, but the caller only sees the stored
+//!   copies.
+//!
+//! # Outgoing stack arguments
+//!
+//! Call arguments that the ABI passes on the stack are written to `outgoing_arg` stack slots at
+//! the bottom of the caller's stack frame. The call site gets a `spill` instruction for each stack
+//! argument, and the spilled value is assigned to an outgoing argument slot at the offset given by
+//! the signature.
 
 use abi::{legalize_abi_value, ValueConversion};
+use entity_map::EntityMap;
 use flowgraph::ControlFlowGraph;
 use ir::{Function, Cursor, DataFlowGraph, Inst, InstBuilder, Ebb, Type, Value, Signature, SigRef,
-         ArgumentType, ArgumentPurpose};
+         ArgumentType, ArgumentPurpose, ArgumentLoc, StackSlot, StackSlotData, StackSlotKind,
+         MemFlags, ValueLoc};
 use ir::instructions::CallInfo;
 use isa::TargetIsa;
 use legalizer::split::{isplit, vsplit};
@@ -52,6 +74,7 @@ pub fn legalize_signatures(func: &mut Function, isa: &TargetIsa) {
 fn legalize_entry_arguments(func: &mut Function, entry: Ebb) {
     let mut has_sret = false;
     let mut has_link = false;
+    let mut has_stack_limit = false;
 
     // Insert position for argument conversion code.
     // We want to insert instructions before the first instruction in the entry block.
@@ -77,11 +100,16 @@ fn legalize_entry_arguments(func: &mut Function, entry: Ebb) {
             // Just use the original EBB argument value. This is the most common case.
             func.dfg.attach_ebb_arg(entry, arg);
             match abi_types[abi_arg].purpose {
-                ArgumentPurpose::Normal => {}
+                ArgumentPurpose::Normal |
+                ArgumentPurpose::VMContext => {}
                 ArgumentPurpose::StructReturn => {
                     assert!(!has_sret, "Multiple sret arguments found");
                     has_sret = true;
                 }
+                ArgumentPurpose::StackLimit => {
+                    assert!(!has_stack_limit, "Multiple stack_limit arguments found");
+                    has_stack_limit = true;
+                }
                 _ => panic!("Unexpected special-purpose arg {}", abi_types[abi_arg]),
             }
             abi_arg += 1;
@@ -130,6 +158,12 @@ fn legalize_entry_arguments(func: &mut Function, entry: Ebb) {
                 assert!(!has_sret, "Multiple sret arguments found");
                 has_sret = true;
             }
+            // The stack limit and the VM context are provided by the caller, so they must be in
+            // the original signature.
+            ArgumentPurpose::StackLimit |
+            ArgumentPurpose::VMContext => {
+                panic!("Special arg {} was not in the original signature", arg);
+            }
         }
         // Just create entry block values to match here. We will use them in `handle_return_abi()`
         // below.
@@ -223,7 +257,7 @@ fn convert_from_abi<GetArg>(dfg: &mut DataFlowGraph,
     // Reconstruct how `ty` was legalized into the `arg_type` argument.
     let conversion = legalize_abi_value(ty, &arg_type);
 
-    dbg!("convert_from_abi({}): {:?}", ty, conversion);
+    debug!("convert_from_abi({}): {:?}", ty, conversion);
 
     // The conversion describes value to ABI argument. We implement the reverse conversion here.
     match conversion {
@@ -232,11 +266,11 @@ fn convert_from_abi<GetArg>(dfg: &mut DataFlowGraph,
             let abi_ty = ty.half_width().expect("Invalid type for conversion");
             let lo = convert_from_abi(dfg, pos, abi_ty, None, get_arg);
             let hi = convert_from_abi(dfg, pos, abi_ty, None, get_arg);
-            dbg!("intsplit {}: {}, {}: {}",
-                 lo,
-                 dfg.value_type(lo),
-                 hi,
-                 dfg.value_type(hi));
+            debug!("intsplit {}: {}, {}: {}",
+                    lo,
+                    dfg.value_type(lo),
+                    hi,
+                    dfg.value_type(hi));
             dfg.ins(pos).with_results([into_result]).iconcat(lo, hi)
         }
         // Construct a `ty` by concatenating two halves of a vector.
@@ -372,11 +406,14 @@ fn check_return_signature(dfg: &DataFlowGraph, inst: Inst, sig: &Signature) -> b
 /// - `get_abi_type` is a closure that can provide the desired `ArgumentType` for a given ABI
 ///   argument number in `0..abi_args`.
 ///
+/// Returns the number of ABI arguments that were computed from the original arguments. Any
+/// remaining ABI arguments are special-purpose arguments that the caller must provide.
 fn legalize_inst_arguments<ArgType>(dfg: &mut DataFlowGraph,
                                     cfg: &ControlFlowGraph,
                                     pos: &mut Cursor,
                                     abi_args: usize,
                                     mut get_abi_type: ArgType)
+                                    -> usize
     where ArgType: FnMut(&DataFlowGraph, usize) -> ArgumentType
 {
     let inst = pos.current_inst()
@@ -442,6 +479,20 @@ fn legalize_inst_arguments<ArgType>(dfg: &mut DataFlowGraph,
 
     // Put the modified value list back.
     dfg[inst].put_value_list(vlist);
+
+    abi_arg
+}
+
+/// Get the size of the return area needed for the return values in `sig` that are returned in
+/// memory, or `None` if all return values are returned in registers.
+fn return_area_size(sig: &Signature) -> Option<u32> {
+    sig.return_types
+        .iter()
+        .filter_map(|rt| match rt.location {
+                        ArgumentLoc::Stack(offset) => Some(offset + rt.value_type.bytes()),
+                        _ => None,
+                    })
+        .max()
 }
 
 /// Insert ABI conversion code before and after the call instruction at `pos`.
@@ -454,28 +505,57 @@ fn legalize_inst_arguments<ArgType>(dfg: &mut DataFlowGraph,
 /// original return values. The call's result values will be adapted to match the new signature.
 ///
 /// Returns `true` if any instructions were inserted.
-pub fn handle_call_abi(dfg: &mut DataFlowGraph, cfg: &ControlFlowGraph, pos: &mut Cursor) -> bool {
+pub fn handle_call_abi(dfg: &mut DataFlowGraph,
+                       cfg: &ControlFlowGraph,
+                       pos: &mut Cursor,
+                       stack_slots: &mut EntityMap<StackSlot, StackSlotData>,
+                       locations: &mut EntityMap<Value, ValueLoc>)
+                       -> bool {
     let mut inst = pos.current_inst()
         .expect("Cursor must point to a call instruction");
 
     // Start by checking if the argument types already match the signature.
+    // Once they do, the stack arguments can be spilled to their outgoing argument slots.
     let sig_ref = match check_call_signature(dfg, inst) {
-        Ok(_) => return false,
+        Ok(_) => return spill_call_arguments(dfg, pos, stack_slots, locations),
         Err(s) => s,
     };
 
     // OK, we need to fix the call arguments to match the ABI signature.
     let abi_args = dfg.signatures[sig_ref].argument_types.len();
-    legalize_inst_arguments(dfg,
-                            cfg,
-                            pos,
-                            abi_args,
-                            |dfg, abi_arg| dfg.signatures[sig_ref].argument_types[abi_arg]);
+    let have_args =
+        legalize_inst_arguments(dfg,
+                                cfg,
+                                pos,
+                                abi_args,
+                                |dfg, abi_arg| dfg.signatures[sig_ref].argument_types[abi_arg]);
+
+    // Provide a return area for values returned in memory if the legalized signature added an
+    // `sret` argument.
+    let mut return_area = None;
+    for abi_arg in have_args..abi_args {
+        let arg = dfg.signatures[sig_ref].argument_types[abi_arg];
+        assert_eq!(arg.purpose,
+                   ArgumentPurpose::StructReturn,
+                   "Can't provide special-purpose argument {}",
+                   arg);
+        let size = return_area_size(&dfg.signatures[sig_ref])
+            .expect("sret argument added without any return values in memory");
+        let ss = stack_slots.push(StackSlotData::new(StackSlotKind::Local, size));
+        pos.goto_inst(inst);
+        let addr = dfg.ins(pos).stack_addr(arg.value_type, ss, 0);
+        dfg.inst_variable_args_mut(inst)[abi_arg] = addr;
+        return_area = Some(ss);
+    }
 
     if !dfg.signatures[sig_ref].return_types.is_empty() {
+        pos.goto_inst(inst);
         inst = legalize_inst_results(dfg,
                                      pos,
                                      |dfg, abi_res| dfg.signatures[sig_ref].return_types[abi_res]);
+        if let Some(ss) = return_area {
+            load_memory_results(dfg, pos, inst, sig_ref, ss);
+        }
     }
 
     debug_assert!(check_call_signature(dfg, inst).is_ok(),
@@ -488,6 +568,119 @@ pub fn handle_call_abi(dfg: &mut DataFlowGraph, cfg: &ControlFlowGraph, pos: &mu
     true
 }
 
+/// Get an outgoing argument stack slot of `size` bytes at `offset`, reusing an existing one if
+/// possible.
+fn get_outgoing_arg(stack_slots: &mut EntityMap<StackSlot, StackSlotData>,
+                    size: u32,
+                    offset: i32)
+                    -> StackSlot {
+    let existing = stack_slots
+        .keys()
+        .find(|&ss| {
+                  let slot = &stack_slots[ss];
+                  slot.kind == StackSlotKind::OutgoingArg && slot.offset == offset &&
+                  slot.size == size
+              });
+    match existing {
+        Some(ss) => ss,
+        None => stack_slots.push(StackSlotData::outgoing_arg(size, offset)),
+    }
+}
+
+/// Spill the stack arguments of the call instruction at `pos` into outgoing argument slots.
+///
+/// The call arguments must already match the signature. Each argument that the signature passes
+/// on the stack is replaced with the result of a `spill` instruction inserted before the call,
+/// and the spilled value is assigned to the outgoing argument slot at the right offset.
+///
+/// Returns `true` if any `spill` instructions were inserted.
+fn spill_call_arguments(dfg: &mut DataFlowGraph,
+                        pos: &mut Cursor,
+                        stack_slots: &mut EntityMap<StackSlot, StackSlotData>,
+                        locations: &mut EntityMap<Value, ValueLoc>)
+                        -> bool {
+    let inst = pos.current_inst()
+        .expect("Cursor must point to a call instruction");
+    let sig_ref = dfg.call_signature(inst)
+        .expect("Call instruction expected");
+
+    let mut changed = false;
+    for abi_arg in 0..dfg.signatures[sig_ref].argument_types.len() {
+        let offset = match dfg.signatures[sig_ref].argument_types[abi_arg].location {
+            ArgumentLoc::Stack(offset) => offset as i32,
+            _ => continue,
+        };
+        let arg = dfg.inst_variable_args(inst)[abi_arg];
+        let size = dfg.value_type(arg).bytes();
+
+        // Skip arguments that were already spilled to the right slot.
+        if let Some(&ValueLoc::Stack(ss)) = locations.get(arg) {
+            let slot = &stack_slots[ss];
+            if slot.kind == StackSlotKind::OutgoingArg && slot.offset == offset {
+                continue;
+            }
+        }
+
+        let ss = get_outgoing_arg(stack_slots, size, offset);
+        let spilled = dfg.ins(pos).spill(arg);
+        *locations.ensure(spilled) = ValueLoc::Stack(ss);
+        dfg.inst_variable_args_mut(inst)[abi_arg] = spilled;
+        changed = true;
+    }
+    changed
+}
+
+/// Load the results of the call `inst` that were returned in memory from the return area `ss`.
+///
+/// The call still produces result values for the memory return values, but they are replaced with
+/// aliases of values loaded from the return area right after the call.
+fn load_memory_results(dfg: &mut DataFlowGraph,
+                       pos: &mut Cursor,
+                       inst: Inst,
+                       sig_ref: SigRef,
+                       ss: StackSlot) {
+    pos.goto_inst(inst);
+    pos.next_inst();
+
+    let results = dfg.detach_results(inst);
+    for i in 0..results.len(&dfg.value_lists) {
+        let res = results.get(i, &dfg.value_lists).unwrap();
+        let ty = dfg.value_type(res);
+        if let ArgumentLoc::Stack(offset) = dfg.signatures[sig_ref].return_types[i].location {
+            dfg.append_result(inst, ty);
+            let loaded = dfg.ins(pos).stack_load(ty, ss, offset as i32);
+            dfg.change_to_alias(res, loaded);
+        } else {
+            dfg.attach_result(inst, res);
+        }
+    }
+}
+
+/// Store the arguments of the return instruction `inst` that are returned in memory through the
+/// `sret` pointer.
+fn store_memory_returns(dfg: &mut DataFlowGraph, pos: &mut Cursor, inst: Inst, sig: &Signature) {
+    let mut sret = None;
+    for (i, rt) in sig.return_types.iter().enumerate() {
+        if let ArgumentLoc::Stack(offset) = rt.location {
+            let ptr = match sret {
+                Some(ptr) => ptr,
+                None => {
+                    let idx = sig.argument_types
+                        .iter()
+                        .rposition(|t| t.purpose == ArgumentPurpose::StructReturn)
+                        .expect("Return values in memory need an sret argument");
+                    let ptr = dfg.ebb_args(pos.layout.entry_block().unwrap())[idx];
+                    sret = Some(ptr);
+                    ptr
+                }
+            };
+            let value = dfg.inst_variable_args(inst)[i];
+            dfg.ins(pos)
+                .store(MemFlags::new(), value, ptr, offset as i32);
+        }
+    }
+}
+
 /// Insert ABI conversion code before and after the return instruction at `pos`.
 ///
 /// Return `true` if any instructions were inserted.
@@ -527,9 +720,9 @@ pub fn handle_return_abi(dfg: &mut DataFlowGraph,
     // legalized signature. These values should simply be propagated from the entry block
     // arguments.
     if special_args > 0 {
-        dbg!("Adding {} special-purpose arguments to {}",
-             special_args,
-             dfg.display_inst(inst));
+        debug!("Adding {} special-purpose arguments to {}",
+                special_args,
+                dfg.display_inst(inst));
         let mut vlist = dfg[inst].take_value_list().unwrap();
         for arg in &sig.return_types[abi_args..] {
             match arg.purpose {
@@ -554,6 +747,9 @@ pub fn handle_return_abi(dfg: &mut DataFlowGraph,
         dfg[inst].put_value_list(vlist);
     }
 
+    // Store any return values that are returned in memory.
+    store_memory_returns(dfg, pos, inst, sig);
+
     debug_assert!(check_return_signature(dfg, inst, sig),
                   "Signature still wrong: {} / signature {}",
                   dfg.display_inst(inst),
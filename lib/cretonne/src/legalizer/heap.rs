@@ -0,0 +1,120 @@
+//! Heap address legalization.
+//!
+//! The `heap_addr` instruction checks that an access is within the bounds of the heap and computes
+//! its absolute address. A runtime with its own heap representation can register a legalization
+//! hook for `heap_addr`. Otherwise, when the `enable_pinned_reg` setting is on, the heap base is
+//! read from the pinned register, and the bounds check depends on the `heap_style` setting:
+//!
+//! - A `dynamic` heap has its size stored in the word below the heap base:
+//!
+//!   ```cton
+//!       v1 = heap_addr.i64 v0+8
+//!   ```
+//!
+//!   becomes:
+//!
+//!   ```cton
+//!       v2 = get_pinned_reg.i64
+//!       v3 = load.i64 notrap aligned v2-8
+//!       v4 = iadd_imm v0, 8
+//!       v5 = icmp ugt v4, v3
+//!       trapnz v5
+//!       v1 = iadd v2, v0
+//!   ```
+//!
+//! - A `static` heap is followed by a guard region, so an access that can only reach the heap or
+//!   the guard region doesn't need a check. It faults instead, and the runtime finds the trap code
+//!   of the faulting load or store in the trap table. The heap index of a WebAssembly memory
+//!   access is a zero-extended 32-bit value, so with the default 4 GB heap and 2 GB guard region,
+//!   the address computation is all that is left:
+//!
+//!   ```cton
+//!       v2 = get_pinned_reg.i64
+//!       v1 = iadd v2, v0
+//!   ```
+//!
+//!   Other accesses are compared to the end of the guard region, which is a constant.
+
+use ir::{Cursor, DataFlowGraph, InstBuilder, InstructionData, MemFlags, Value, ValueDef, Opcode};
+use ir::condcodes::IntCC;
+use isa::TargetIsa;
+use settings::HeapStyle;
+
+/// Expand the `heap_addr` instruction at `pos` according to the `heap_style` setting.
+pub fn expand_heap_addr(dfg: &mut DataFlowGraph, pos: &mut Cursor, isa: &TargetIsa) {
+    let inst = pos.current_inst().expect("need instruction");
+    let (index, size): (Value, u32) = match dfg[inst] {
+        InstructionData::HeapLoad { arg, offset, .. } => (arg, offset.into()),
+        _ => panic!("Expected heap_addr: {}", dfg.display_inst(inst)),
+    };
+    let ty = dfg.ctrl_typevar(inst);
+    let max_index = max_value(dfg, index);
+
+    let base = dfg.ins(pos).get_pinned_reg(ty);
+    match isa.flags().heap_style() {
+        HeapStyle::Dynamic => {
+            let mut flags = MemFlags::new();
+            flags.set_notrap();
+            flags.set_aligned();
+            let bound = dfg.ins(pos).load(ty, flags, base, -(ty.bytes() as i32));
+            let end = dfg.ins(pos).iadd_imm(index, size as i64);
+            let oob = dfg.ins(pos)
+                .icmp(IntCC::UnsignedGreaterThan, end, bound);
+            dfg.ins(pos).trapnz(oob);
+            // The end of the access can wrap around when the index is a full-width value.
+            if max_index.checked_add(size as u64).map_or(true, |e| e > ty_max(ty.bits())) {
+                let wrapped = dfg.ins(pos).icmp(IntCC::UnsignedLessThan, end, index);
+                dfg.ins(pos).trapnz(wrapped);
+            }
+        }
+        HeapStyle::Static => {
+            let flags = isa.flags();
+            let bound = 1u64
+                .checked_shl(flags.static_heap_bound_log2() as u32)
+                .unwrap_or(u64::max_value());
+            let guard = match flags.static_heap_guard_log2() {
+                0 => 0,
+                log2 => 1u64.checked_shl(log2 as u32).unwrap_or(u64::max_value()),
+            };
+            // Accesses ending within the guard region fault without a check.
+            let limit = bound.saturating_add(guard);
+            if max_index.saturating_add(size as u64) > limit {
+                let oob = match limit.checked_sub(size as u64) {
+                    Some(last) => {
+                        dfg.ins(pos)
+                            .icmp_imm(IntCC::UnsignedGreaterThan, index, last as i64)
+                    }
+                    // The access is larger than the heap and its guard region.
+                    None => {
+                        dfg.ins(pos)
+                            .icmp_imm(IntCC::UnsignedGreaterThanOrEqual, index, 0)
+                    }
+                };
+                dfg.ins(pos).trapnz(oob);
+            }
+        }
+    }
+    dfg.replace(inst).iadd(base, index);
+}
+
+/// Get the largest value `value` can have.
+///
+/// A value that was zero-extended from a narrower type can't exceed the narrower type's range.
+fn max_value(dfg: &DataFlowGraph, value: Value) -> u64 {
+    if let ValueDef::Res(def, _) = dfg.value_def(value) {
+        if dfg[def].opcode() == Opcode::Uextend {
+            let arg = dfg.inst_args(def)[0];
+            return ty_max(dfg.value_type(arg).bits());
+        }
+    }
+    ty_max(dfg.value_type(value).bits())
+}
+
+/// Get the largest unsigned value of an integer type with `bits` bits.
+fn ty_max(bits: u16) -> u64 {
+    if bits >= 64 {
+        u64::max_value()
+    } else {
+        (1 << bits) - 1
+    }
+}
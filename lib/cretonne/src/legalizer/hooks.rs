@@ -0,0 +1,127 @@
+//! Custom legalization hooks.
+//!
+//! An embedder can lower some instructions in its own runtime-specific way. For example, a
+//! WebAssembly runtime decides how `heap_addr` checks the bounds of its linear memory. Instead of
+//! forking the legalizer, the embedder registers a hook for the opcode, and the legalizer calls it
+//! before trying the ISA's own legalization actions.
+
+use flowgraph::ControlFlowGraph;
+use ir::{DataFlowGraph, Cursor, Opcode};
+use isa::TargetIsa;
+
+/// A custom legalization callback.
+///
+/// The callback is called with the cursor pointing at an instruction with the opcode it was
+/// registered for. It should return `true` after replacing the instruction, and the legalizer
+/// then goes back to legalize the replacement instructions. Returning `false` leaves the
+/// instruction to the next hook or the built-in legalization.
+///
+/// A hook that returns `true` without replacing the instruction would be called again forever.
+pub type LegalizeHook = Send + Sync + Fn(&mut DataFlowGraph,
+                                        &mut ControlFlowGraph,
+                                        &mut Cursor,
+                                        &TargetIsa) -> bool;
+
+/// The legalization hooks registered by an embedder.
+#[derive(Default)]
+pub struct LegalizeHooks {
+    hooks: Vec<(Opcode, Box<LegalizeHook>)>,
+}
+
+impl LegalizeHooks {
+    /// Create an empty set of hooks.
+    pub fn new() -> LegalizeHooks {
+        LegalizeHooks::default()
+    }
+
+    /// Register `hook` for instructions with `opcode`.
+    ///
+    /// Multiple hooks can be registered for the same opcode. They are tried in the order they
+    /// were registered.
+    pub fn add<F>(&mut self, opcode: Opcode, hook: F)
+        where F: Fn(&mut DataFlowGraph, &mut ControlFlowGraph, &mut Cursor, &TargetIsa) -> bool,
+              F: Send + Sync + 'static
+    {
+        self.hooks.push((opcode, Box::new(hook)));
+    }
+
+    /// Remove all the registered hooks.
+    pub fn clear(&mut self) {
+        self.hooks.clear();
+    }
+
+    /// Is there no hook registered at all?
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    /// Is there a hook registered for `opcode`?
+    pub fn handles(&self, opcode: Opcode) -> bool {
+        self.hooks.iter().any(|&(op, _)| op == opcode)
+    }
+
+    /// Run the hooks registered for `opcode` on the instruction at `pos`.
+    ///
+    /// Returns `true` if a hook replaced the instruction.
+    pub fn legalize(&self,
+                    opcode: Opcode,
+                    dfg: &mut DataFlowGraph,
+                    cfg: &mut ControlFlowGraph,
+                    pos: &mut Cursor,
+                    isa: &TargetIsa)
+                    -> bool {
+        self.hooks
+            .iter()
+            .filter(|&&(op, _)| op == opcode)
+            .any(|&(_, ref hook)| hook(dfg, cfg, pos, isa))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use Context;
+    use ir::{Function, Signature, ArgumentType, InstBuilder, Cursor, Opcode, ValueDef};
+    use ir::types::I32;
+    use isa;
+    use settings;
+
+    #[test]
+    fn heap_addr_hook() {
+        let isa_builder = isa::lookup("riscv").unwrap();
+        let isa = isa_builder.finish(settings::Flags::new(&settings::builder()));
+
+        let mut ctx = Context::new();
+        let mut sig = Signature::new();
+        sig.argument_types.push(ArgumentType::new(I32));
+        sig.return_types.push(ArgumentType::new(I32));
+        ctx.func = Function::with_name_signature(Default::default(), sig);
+        let ebb0 = ctx.func.dfg.make_ebb();
+        let p = ctx.func.dfg.append_ebb_arg(ebb0, I32);
+        ctx.func.layout.append_ebb(ebb0);
+        let addr;
+        {
+            let dfg = &mut ctx.func.dfg;
+            let pos = &mut Cursor::new(&mut ctx.func.layout);
+            pos.goto_bottom(ebb0);
+            addr = dfg.ins(pos).heap_addr(p, 16);
+            dfg.ins(pos).return_(&[addr]);
+        }
+
+        ctx.legalize_hooks
+            .add(Opcode::HeapAddr, |dfg, _cfg, pos, _isa| {
+                let inst = pos.current_inst().unwrap();
+                let p = dfg.inst_args(inst)[0];
+                dfg.replace(inst).iadd_imm(p, 0x100);
+                true
+            });
+        ctx.flowgraph();
+        ctx.legalize(&*isa).unwrap();
+
+        let inst = match ctx.func.dfg.value_def(addr) {
+            ValueDef::Res(inst, _) => inst,
+            _ => panic!("{} isn't an instruction result", addr),
+        };
+        assert_eq!(ctx.func.dfg[inst].opcode(), Opcode::IaddImm);
+        assert!(ctx.func.encodings[inst].is_legal());
+    }
+}
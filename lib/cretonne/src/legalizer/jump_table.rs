@@ -0,0 +1,208 @@
+//! Jump table legalization.
+//!
+//! The `br_table` instruction doesn't have any encodings. When the ISA can encode the
+//! `jump_table_base` instruction, it is expanded into a bounds check and a table lookup:
+//!
+//! ```cton
+//!     br_table v1, jt0
+//!     v9 = iadd v7, v8
+//! ```
+//!
+//! becomes:
+//!
+//! ```cton
+//!     v2 = uextend.i64 v1
+//!     v3 = icmp_imm uge v2, 4
+//!     brnz v3, ebb5
+//!     v4 = jump_table_base.i64 jt0
+//!     v5 = jump_table_entry v2, v4, 4, jt0
+//!     v6 = iadd v4, v5
+//!     indirect_jump_table_br v6, jt0
+//! ebb5:
+//!     v9 = iadd v7, v8
+//! ```
+//!
+//! The instructions following the `br_table` are moved to a new EBB which also becomes the
+//! destination of any missing entries in the jump table.
+//!
+//! Other ISAs get a chain of `icmp_imm` and `brnz` instructions comparing the index to each entry.
+
+use diagnostics::Diagnostics;
+use flowgraph::ControlFlowGraph;
+use ir::{self, Function, Cursor, Inst, InstBuilder, InstructionData, JumpTable,
+         JumpTableData, Opcode, Value, types};
+use ir::condcodes::IntCC;
+use isa::TargetIsa;
+use super::LegalizeTrace;
+
+/// Expand all the `br_table` instructions in `func`.
+///
+/// This can split EBBs, so the control flow graph is recomputed if anything changed. The
+/// expansions are recorded in `trace` when it is given.
+pub fn expand_br_tables(func: &mut Function,
+                        cfg: &mut ControlFlowGraph,
+                        isa: &TargetIsa,
+                        mut trace: Option<&mut LegalizeTrace>) {
+    let mut br_tables = Vec::new();
+    for ebb in func.layout.ebbs() {
+        for inst in func.layout.ebb_insts(ebb) {
+            if func.dfg[inst].opcode() == Opcode::BrTable {
+                br_tables.push(inst);
+            }
+        }
+    }
+    if br_tables.is_empty() {
+        return;
+    }
+
+    let addr_ty = if isa.flags().is_64bit() {
+        types::I64
+    } else {
+        types::I32
+    };
+    for inst in br_tables {
+        let (arg, table) = match func.dfg[inst] {
+            InstructionData::BranchTable { arg, table, .. } => (arg, table),
+            _ => panic!("Expected br_table: {}", func.dfg.display_inst(inst)),
+        };
+        let text = trace
+            .as_ref()
+            .map(|_| func.dfg.display_inst(inst).to_string());
+        let base = InstructionData::BranchTableBase {
+            opcode: Opcode::JumpTableBase,
+            table: table,
+        };
+        if isa.encode(&func.dfg, &base, addr_ty).is_ok() &&
+           func.dfg.value_type(arg).bits() <= addr_ty.bits() {
+            expand_table_lookup(func, inst, arg, table, addr_ty);
+            if let Some(ref mut trace) = trace {
+                trace.push(inst, "br_table_lookup", None, text.unwrap_or_default());
+            }
+        } else {
+            expand_compare_chain(func, inst, arg, table);
+            if let Some(ref mut trace) = trace {
+                trace.push(inst, "br_table_chain", None, text.unwrap_or_default());
+            }
+        }
+    }
+    cfg.compute(func);
+}
+
+/// Warn about large jump tables where most of the entries are holes.
+///
+/// The table lookup expansion fills the holes, so a sparse table wastes a lot of space. A chain
+/// of comparisons or a binary search would probably be a better choice for such a switch.
+pub fn check_sparse_tables(func: &Function, diagnostics: &mut Diagnostics) {
+    // Don't warn about small tables, they are cheap even when they are sparse.
+    const MIN_SIZE: usize = 16;
+
+    for jt in func.jump_tables.keys() {
+        let data = &func.jump_tables[jt];
+        let size = data.len();
+        if size < MIN_SIZE {
+            continue;
+        }
+        let holes = size - data.entries().count();
+        if holes * 10 >= size * 9 {
+            diagnostics.warn("legalize",
+                             jt,
+                             format!("jump table is {}% holes", holes * 100 / size));
+        }
+    }
+}
+
+/// Expand the `br_table` instruction `inst` into a jump table lookup.
+fn expand_table_lookup(func: &mut Function,
+                       inst: Inst,
+                       arg: Value,
+                       table: JumpTable,
+                       addr_ty: ir::Type) {
+    // The missing entries branch to the instructions following `inst`.
+    let fallthrough = func.dfg.make_ebb();
+    let next = {
+        let mut pos = Cursor::new(&mut func.layout);
+        pos.goto_inst(inst);
+        pos.next_inst().expect("br_table can't terminate an EBB")
+    };
+    func.layout.split_ebb(fallthrough, next);
+    let table = fill_holes(func, inst, table, fallthrough);
+    let size = func.jump_tables[table].len();
+
+    let mut pos = Cursor::new(&mut func.layout);
+    pos.goto_inst(inst);
+    let dfg = &mut func.dfg;
+    let index = if dfg.value_type(arg) == addr_ty {
+        arg
+    } else {
+        dfg.ins(&mut pos).uextend(addr_ty, arg)
+    };
+    let oob = dfg.ins(&mut pos)
+        .icmp_imm(IntCC::UnsignedGreaterThanOrEqual, index, size as i64);
+    dfg.ins(&mut pos).brnz(oob, fallthrough, &[]);
+    let base = dfg.ins(&mut pos).jump_table_base(addr_ty, table);
+    let entry = dfg.ins(&mut pos).jump_table_entry(index, base, 4, table);
+    let addr = dfg.ins(&mut pos).iadd(base, entry);
+    dfg.replace(inst).indirect_jump_table_br(addr, table);
+}
+
+/// Get a version of `table` where the missing entries are replaced by `fallthrough`.
+///
+/// The table is modified in place unless it is shared with other instructions than `inst`.
+fn fill_holes(func: &mut Function,
+              inst: Inst,
+              table: JumpTable,
+              fallthrough: ir::Ebb)
+              -> JumpTable {
+    if !func.jump_tables[table].has_holes() {
+        return table;
+    }
+
+    let shared = is_shared(func, inst, table);
+    let mut data = func.jump_tables[table].clone();
+    for idx in 0..data.len() {
+        if data.get_entry(idx).is_none() {
+            data.set_entry(idx, fallthrough);
+        }
+    }
+    if shared {
+        func.jump_tables.push(data)
+    } else {
+        func.jump_tables[table] = data;
+        table
+    }
+}
+
+/// Expand the `br_table` instruction `inst` into a chain of comparisons and conditional branches.
+fn expand_compare_chain(func: &mut Function, inst: Inst, arg: Value, table: JumpTable) {
+    let entries: Vec<_> = func.jump_tables[table].entries().collect();
+    let mut pos = Cursor::new(&mut func.layout);
+    pos.goto_inst(inst);
+    for (idx, dest) in entries {
+        let eq = func.dfg
+            .ins(&mut pos)
+            .icmp_imm(IntCC::Equal, arg, idx as i64);
+        func.dfg.ins(&mut pos).brnz(eq, dest, &[]);
+    }
+    pos.remove_inst();
+
+    // Don't emit an unused table.
+    if !is_shared(func, inst, table) {
+        func.jump_tables[table] = JumpTableData::new();
+    }
+}
+
+/// Is `table` used by any other instructions than `inst`?
+fn is_shared(func: &Function, inst: Inst, table: JumpTable) -> bool {
+    func.layout
+        .ebbs()
+        .flat_map(|ebb| func.layout.ebb_insts(ebb))
+        .any(|i| {
+                 i != inst &&
+                 match func.dfg[i] {
+                     InstructionData::BranchTable { table: t, .. } |
+                     InstructionData::BranchTableBase { table: t, .. } |
+                     InstructionData::BranchTableEntry { table: t, .. } => t == table,
+                     _ => false,
+                 }
+             })
+}
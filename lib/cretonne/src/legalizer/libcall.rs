@@ -0,0 +1,195 @@
+//! Legalization of instructions as runtime library calls.
+//!
+//! Some instructions can't be encoded on the target ISA, and are too complicated to expand into
+//! other instructions. Floating point arithmetic on a CPU without an FPU is the typical example.
+//! The ISA requests this legalization by returning `Legalize::Libcall` from its `encode()` method.
+//!
+//! The instruction is replaced by a call to a routine in the runtime library. The routines follow
+//! the naming in libgcc and compiler-rt, falling back to the C math library for the operations
+//! that the compiler support libraries don't provide:
+//!
+//! ```cton
+//!     v3 = fadd.f32 v1, v2
+//! ```
+//!
+//! becomes:
+//!
+//! ```cton
+//!     fn0 = function __addsf3(f32, f32) -> f32
+//!     ...
+//!     v3 = call fn0(v1, v2)
+//! ```
+//!
+//! Floating point comparisons call a routine returning an `i32` which is then compared to zero.
+//! The `one` and `ueq` conditions would need two calls, so they are not supported.
+//!
+//! The conversions from floating point to integers call routines that don't trap when the result
+//! is out of range.
+//!
+//! The narrowing of wide integer instructions also uses this module for the multiplication,
+//! division, and variable shift routines. The division routines don't trap on a zero divisor.
+
+use ir::{DataFlowGraph, Cursor, InstBuilder, InstructionData, Opcode, Type, Value};
+use ir::{Signature, ArgumentType, ExtFuncData, FunctionName, FuncRef};
+use ir::condcodes::{FloatCC, IntCC};
+use ir::types::I32;
+use isa::TargetIsa;
+
+/// Replace the instruction at `pos` with a call to a runtime library routine.
+///
+/// Returns `false` if there is no library routine for the instruction.
+pub fn expand_as_libcall(dfg: &mut DataFlowGraph, pos: &mut Cursor, isa: &TargetIsa) -> bool {
+    let inst = pos.current_inst().expect("need instruction");
+    let opcode = dfg[inst].opcode();
+    let args = dfg.inst_args(inst).to_vec();
+    let arg_type = dfg.value_type(args[0]);
+    let result_type = dfg.ctrl_typevar(inst);
+
+    if let InstructionData::FloatCompare { cond, .. } = dfg[inst] {
+        let (name, cc) = match fcmp_libcall(cond) {
+            Some(x) => x,
+            None => return false,
+        };
+        let name = format!("__{}{}2", name, float_suffix(arg_type));
+        let fref = import_libcall(dfg, isa, &name, &args, I32);
+        let call = dfg.ins(pos).call(fref, &args);
+        let value = dfg.first_result(call);
+        dfg.replace(inst).icmp_imm(cc, value, 0);
+        return true;
+    }
+
+    let name = match libcall_name(opcode, arg_type, result_type) {
+        Some(name) => name,
+        None => return false,
+    };
+    let fref = import_libcall(dfg, isa, &name, &args, result_type);
+    dfg.replace(inst).call(fref, &args);
+    true
+}
+
+/// Get the name of the library routine implementing `opcode` with an `arg_type` argument and a
+/// `result_type` result.
+fn libcall_name(opcode: Opcode, arg_type: Type, result_type: Type) -> Option<String> {
+    let name = match opcode {
+        Opcode::Fadd => format!("__add{}3", float_suffix(result_type)),
+        Opcode::Fsub => format!("__sub{}3", float_suffix(result_type)),
+        Opcode::Fmul => format!("__mul{}3", float_suffix(result_type)),
+        Opcode::Fdiv => format!("__div{}3", float_suffix(result_type)),
+        Opcode::Fpromote | Opcode::Fdemote => {
+            let op = if opcode == Opcode::Fpromote {
+                "extend"
+            } else {
+                "trunc"
+            };
+            format!("__{}{}{}2", op, float_suffix(arg_type), float_suffix(result_type))
+        }
+        Opcode::FcvtToSint => format!("__fix{}{}", float_suffix(arg_type), int_suffix(result_type)),
+        Opcode::FcvtToUint => {
+            format!("__fixuns{}{}", float_suffix(arg_type), int_suffix(result_type))
+        }
+        Opcode::FcvtFromSint => {
+            format!("__float{}{}", int_suffix(arg_type), float_suffix(result_type))
+        }
+        Opcode::FcvtFromUint => {
+            format!("__floatun{}{}", int_suffix(arg_type), float_suffix(result_type))
+        }
+        Opcode::Imul => format!("__mul{}3", int_suffix(result_type)),
+        Opcode::Udiv => format!("__udiv{}3", int_suffix(result_type)),
+        Opcode::Sdiv => format!("__div{}3", int_suffix(result_type)),
+        Opcode::Urem => format!("__umod{}3", int_suffix(result_type)),
+        Opcode::Srem => format!("__mod{}3", int_suffix(result_type)),
+        Opcode::Ishl => format!("__ashl{}3", int_suffix(result_type)),
+        Opcode::Ushr => format!("__lshr{}3", int_suffix(result_type)),
+        Opcode::Sshr => format!("__ashr{}3", int_suffix(result_type)),
+        Opcode::Sqrt => libm_name("sqrt", result_type),
+        Opcode::Fma => libm_name("fma", result_type),
+        Opcode::Ceil => libm_name("ceil", result_type),
+        Opcode::Floor => libm_name("floor", result_type),
+        Opcode::Trunc => libm_name("trunc", result_type),
+        Opcode::Nearest => libm_name("nearbyint", result_type),
+        Opcode::Fminnum => libm_name("fmin", result_type),
+        Opcode::Fmaxnum => libm_name("fmax", result_type),
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// Get the name of the comparison routine and the integer condition code to apply to its result
+/// for `cond`.
+///
+/// The comparison routines return a value that compares to zero like the operands compare to each
+/// other. The ordered routines return a value that makes the comparison false when an operand is
+/// NaN, so the inverted condition gives the unordered variant.
+fn fcmp_libcall(cond: FloatCC) -> Option<(&'static str, IntCC)> {
+    use ir::condcodes::FloatCC::*;
+    Some(match cond {
+             Ordered => ("unord", IntCC::Equal),
+             Unordered => ("unord", IntCC::NotEqual),
+             Equal => ("eq", IntCC::Equal),
+             NotEqual => ("ne", IntCC::NotEqual),
+             LessThan => ("lt", IntCC::SignedLessThan),
+             LessThanOrEqual => ("le", IntCC::SignedLessThanOrEqual),
+             GreaterThan => ("gt", IntCC::SignedGreaterThan),
+             GreaterThanOrEqual => ("ge", IntCC::SignedGreaterThanOrEqual),
+             UnorderedOrLessThan => ("ge", IntCC::SignedLessThan),
+             UnorderedOrLessThanOrEqual => ("gt", IntCC::SignedLessThanOrEqual),
+             UnorderedOrGreaterThan => ("le", IntCC::SignedGreaterThan),
+             UnorderedOrGreaterThanOrEqual => ("lt", IntCC::SignedGreaterThanOrEqual),
+             OrderedNotEqual | UnorderedOrEqual => return None,
+         })
+}
+
+/// The mode suffix used by the compiler support libraries for a floating point type.
+fn float_suffix(ty: Type) -> &'static str {
+    match ty.bits() {
+        32 => "sf",
+        64 => "df",
+        _ => panic!("Unsupported float type {}", ty),
+    }
+}
+
+/// The mode suffix used by the compiler support libraries for an integer type.
+fn int_suffix(ty: Type) -> &'static str {
+    match ty.bits() {
+        32 => "si",
+        64 => "di",
+        _ => panic!("Unsupported integer type {}", ty),
+    }
+}
+
+/// The name of a C math library function for `ty`.
+fn libm_name(base: &str, ty: Type) -> String {
+    match ty.bits() {
+        32 => format!("{}f", base),
+        _ => base.to_string(),
+    }
+}
+
+/// Get a reference to the library routine `name`, importing it with a signature matching `args`
+/// and `result_type` if it hasn't been imported already.
+fn import_libcall(dfg: &mut DataFlowGraph,
+                  isa: &TargetIsa,
+                  name: &str,
+                  args: &[Value],
+                  result_type: Type)
+                  -> FuncRef {
+    let name = FunctionName::new(name);
+    if let Some(fref) = dfg.ext_funcs.keys().find(|&f| dfg.ext_funcs[f].name == name) {
+        return fref;
+    }
+
+    let mut sig = Signature::new();
+    sig.argument_types = args.iter()
+        .map(|&arg| ArgumentType::new(dfg.value_type(arg)))
+        .collect();
+    sig.return_types.push(ArgumentType::new(result_type));
+    // The signatures were legalized before the legalizer started, so legalize this one now.
+    isa.legalize_signature(&mut sig, false);
+    let signature = dfg.signatures.push(sig);
+
+    dfg.ext_funcs.push(ExtFuncData {
+                           name: name,
+                           signature: signature,
+                           colocated: false,
+                       })
+}
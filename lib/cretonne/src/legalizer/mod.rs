@@ -12,22 +12,56 @@
 //!
 //! The legalizer does not deal with register allocation constraints. These constraints are derived
 //! from the encoding recipes, and solved later by the register allocator.
+//!
+//! The instructions inserted by the legalizer inherit the source location of the instruction they
+//! were expanded from.
 
+use diagnostics::Diagnostics;
+use entity_map::EntityRef;
 use flowgraph::ControlFlowGraph;
-use ir::{Function, Cursor, DataFlowGraph, InstructionData, Opcode, InstBuilder};
+use ir::{Function, Cursor, DataFlowGraph, InstructionData, Opcode, InstBuilder, SourceLoc};
 use ir::condcodes::IntCC;
 use isa::{TargetIsa, Legalize};
 
+pub use self::hooks::{LegalizeHook, LegalizeHooks};
+pub use self::query::{lowering, Lowering};
+pub use self::trace::{LegalizeStep, LegalizeTrace};
+
 mod boundary;
+mod heap;
+mod hooks;
+mod jump_table;
+mod libcall;
+mod narrow_int;
+mod query;
+mod select;
 mod split;
+mod table;
+mod tls;
+mod trace;
 
 /// Legalize `func` for `isa`.
 ///
 /// - Transform any instructions that don't have a legal representation in `isa`.
 /// - Fill out `func.encodings`.
 ///
-pub fn legalize_function(func: &mut Function, cfg: &mut ControlFlowGraph, isa: &TargetIsa) {
+/// The embedder's `hooks` are given the first chance at transforming the instructions they were
+/// registered for. Sparse jump tables and instructions replaced by library calls are reported as
+/// warnings in `diagnostics`. When a `trace` is given, the action that transformed each
+/// instruction is recorded in it.
+pub fn legalize_function(func: &mut Function,
+                         cfg: &mut ControlFlowGraph,
+                         isa: &TargetIsa,
+                         hooks: &LegalizeHooks,
+                         diagnostics: &mut Diagnostics,
+                         mut trace: Option<&mut LegalizeTrace>) {
+    // Instructions numbered from here on are inserted by the legalizer.
+    let first_new_inst = func.dfg.num_insts();
+
     boundary::legalize_signatures(func, isa);
+    jump_table::check_sparse_tables(func, diagnostics);
+    jump_table::expand_br_tables(func, cfg, isa, trace.as_mut().map(|t| &mut **t));
+    select::expand_selects(func, cfg, isa, hooks, trace.as_mut().map(|t| &mut **t));
 
     func.encodings.resize(func.dfg.num_insts());
 
@@ -46,8 +80,23 @@ pub fn legalize_function(func: &mut Function, cfg: &mut ControlFlowGraph, isa: &
         while let Some(inst) = pos.next_inst() {
             let opcode = func.dfg[inst].opcode();
 
+            // Record a transformation of `inst` in the trace, if we have one.
+            let dfg = &func.dfg;
+            let text = trace.as_ref().map(|_| dfg.display_inst(inst).to_string());
+            let mut record = |action: &'static str, rule: Option<&'static str>| {
+                if let Some(ref mut trace) = trace {
+                    trace.push(inst, action, rule, text.clone().unwrap_or_default());
+                }
+            };
+
             // Check for ABI boundaries that need to be converted to the legalized signature.
-            if opcode.is_call() && boundary::handle_call_abi(&mut func.dfg, cfg, &mut pos) {
+            if opcode.is_call() &&
+               boundary::handle_call_abi(&mut func.dfg,
+                                         cfg,
+                                         &mut pos,
+                                         &mut func.stack_slots,
+                                         &mut func.locations) {
+                record("call_abi", None);
                 // Go back and legalize the inserted argument conversion instructions.
                 pos.set_position(prev_pos);
                 continue;
@@ -55,6 +104,7 @@ pub fn legalize_function(func: &mut Function, cfg: &mut ControlFlowGraph, isa: &
 
             if opcode.is_return() &&
                boundary::handle_return_abi(&mut func.dfg, cfg, &mut pos, &func.signature) {
+                record("return_abi", None);
                 // Go back and legalize the inserted return value conversion instructions.
                 pos.set_position(prev_pos);
                 continue;
@@ -64,6 +114,36 @@ pub fn legalize_function(func: &mut Function, cfg: &mut ControlFlowGraph, isa: &
                 split::simplify_branch_arguments(&mut func.dfg, inst);
             }
 
+            if hooks.legalize(opcode, &mut func.dfg, cfg, &mut pos, isa) {
+                record("hook", None);
+                pos.set_position(prev_pos);
+                continue;
+            }
+
+            // The built-in heap bounds checks depend on ISA settings.
+            if opcode == Opcode::HeapAddr && isa.flags().enable_pinned_reg() {
+                heap::expand_heap_addr(&mut func.dfg, &mut pos, isa);
+                record("heap_addr", None);
+                pos.set_position(prev_pos);
+                continue;
+            }
+
+            // The table layout is declared in the preamble.
+            if opcode == Opcode::TableAddr {
+                table::expand_table_addr(&mut func.dfg, &mut pos, &func.tables);
+                record("table_addr", None);
+                pos.set_position(prev_pos);
+                continue;
+            }
+
+            // The thread-local storage access sequence depends on ISA settings.
+            if opcode == Opcode::TlsValue {
+                tls::expand_tls_value(&mut func.dfg, &mut pos, isa);
+                record("tls", None);
+                pos.set_position(prev_pos);
+                continue;
+            }
+
             match isa.encode(&func.dfg, &func.dfg[inst], func.dfg.ctrl_typevar(inst)) {
                 Ok(encoding) => *func.encodings.ensure(inst) = encoding,
                 Err(action) => {
@@ -78,18 +158,49 @@ pub fn legalize_function(func: &mut Function, cfg: &mut ControlFlowGraph, isa: &
                     //    typically means expressing `i8` and `i16` arithmetic in terms if `i32`
                     //    operations on RISC targets. (It may or may not be beneficial to promote
                     //    small vector types versus splitting them.)
-                    // 4. TODO: Convert to library calls. For example, floating point operations on
-                    //    an ISA with no IEEE 754 support.
-                    let changed = match action {
-                        Legalize::Expand => expand(&mut func.dfg, cfg, &mut pos),
-                        Legalize::Narrow => narrow(&mut func.dfg, cfg, &mut pos),
+                    // 4. Legalize::Libcall: Convert to library calls. For example, floating point
+                    //    operations on an ISA with no IEEE 754 support.
+                    let fired = match action {
+                        Legalize::Expand => {
+                            if let Some(rule) = expand(&mut func.dfg, cfg, &mut pos) {
+                                Some(("expand", Some(rule)))
+                            } else if narrow_int::narrow_args(&mut func.dfg, cfg, &mut pos, isa) {
+                                Some(("narrow_args", None))
+                            } else {
+                                None
+                            }
+                        }
+                        Legalize::Narrow => {
+                            if let Some(rule) = narrow(&mut func.dfg, cfg, &mut pos) {
+                                Some(("narrow", Some(rule)))
+                            } else if narrow_int::narrow(&mut func.dfg, cfg, &mut pos, isa) {
+                                Some(("narrow_int", None))
+                            } else {
+                                None
+                            }
+                        }
+                        Legalize::Libcall => {
+                            let ctrl_type = func.dfg.ctrl_typevar(inst);
+                            if libcall::expand_as_libcall(&mut func.dfg, &mut pos, isa) {
+                                diagnostics.warn("legalize",
+                                                 inst,
+                                                 format!("{}.{} has no encoding, replaced by \
+                                                          a library call",
+                                                         opcode,
+                                                         ctrl_type));
+                                Some(("libcall", None))
+                            } else {
+                                None
+                            }
+                        }
                     };
                     // If the current instruction was replaced, we need to double back and revisit
                     // the expanded sequence. This is both to assign encodings and possible to
                     // expand further.
                     // There's a risk of infinite looping here if the legalization patterns are
                     // unsound. Should we attempt to detect that?
-                    if changed {
+                    if let Some((action, rule)) = fired {
+                        record(action, rule);
                         pos.set_position(prev_pos);
                     }
                 }
@@ -99,6 +210,28 @@ pub fn legalize_function(func: &mut Function, cfg: &mut ControlFlowGraph, isa: &
             prev_pos = pos.position();
         }
     }
+
+    if !func.srclocs.is_empty() {
+        propagate_srclocs(func, first_new_inst);
+    }
+}
+
+/// Give the instructions inserted by the legalizer a source location.
+///
+/// The expansions insert new instructions in front of the instruction being legalized, so an
+/// inserted instruction gets the source location of the next instruction in its EBB.
+fn propagate_srclocs(func: &mut Function, first_new_inst: usize) {
+    for ebb in func.layout.ebbs() {
+        let mut srcloc = SourceLoc::default();
+        for inst in func.layout.ebb_insts(ebb).rev() {
+            let loc = func.srclocs.get(inst).cloned().unwrap_or_default();
+            if !loc.is_default() {
+                srcloc = loc;
+            } else if inst.index() >= first_new_inst && !srcloc.is_default() {
+                *func.srclocs.ensure(inst) = srcloc;
+            }
+        }
+    }
 }
 
 // Include legalization patterns that were generated by `gen_legalizer.py` from the `XForms` in
@@ -0,0 +1,345 @@
+//! Narrowing of wide integer instructions.
+//!
+//! Most instructions operating on integers that are too wide for the target ISA are narrowed by
+//! the XForms in `meta/base/legalize.py`, which split the operands into low and high halves with
+//! `isplit` and join the results with `iconcat`. This module handles the instructions whose
+//! narrowed form depends on the value of an immediate or a condition code, which the XForms can't
+//! express.
+//!
+//! Integer comparisons compare the high halves and use an unsigned comparison of the low halves
+//! when the high halves are equal:
+//!
+//! ```cton
+//!     v1 = icmp slt v2, v3
+//! ```
+//!
+//! becomes:
+//!
+//! ```cton
+//!     v20, v21 = isplit v2
+//!     v30, v31 = isplit v3
+//!     v10 = icmp slt v21, v31
+//!     v11 = icmp eq v21, v31
+//!     v12 = icmp ult v20, v30
+//!     v13 = band v11, v12
+//!     v1 = bor v10, v13
+//! ```
+//!
+//! Branches on wide values branch on a narrow value computed the same way.
+//!
+//! Wide loads and stores are split into two accesses of the halves. The low half is at the lower
+//! address on little-endian targets and at the higher address on big-endian targets.
+//!
+//! Multiplication, division, and shifts by a variable amount are too complicated to expand
+//! inline, so they call the routines in the compiler support library instead.
+
+use flowgraph::ControlFlowGraph;
+use ir::{DataFlowGraph, Cursor, InstBuilder, InstructionData, Opcode, Type, Value};
+use ir::condcodes::IntCC;
+use isa::{TargetIsa, Endianness};
+use legalizer::{libcall, split};
+
+/// Narrow the wide integer instruction at `pos`.
+///
+/// Returns `false` if the instruction isn't handled here.
+pub fn narrow(dfg: &mut DataFlowGraph,
+              cfg: &mut ControlFlowGraph,
+              pos: &mut Cursor,
+              isa: &TargetIsa)
+              -> bool {
+    let inst = pos.current_inst().expect("need instruction");
+    let ty = dfg.ctrl_typevar(inst);
+    let half = match ty.half_width() {
+        Some(half) if ty.is_int() => half,
+        _ => return false,
+    };
+
+    match dfg[inst] {
+        InstructionData::UnaryImm {
+            opcode: Opcode::Iconst,
+            imm,
+        } => {
+            let (lo, hi) = iconst_halves(dfg, pos, half, imm.into());
+            dfg.replace(inst).iconcat(lo, hi);
+        }
+        InstructionData::Unary { opcode, arg } if opcode == Opcode::Uextend ||
+                                                   opcode == Opcode::Sextend => {
+            let lo = if dfg.value_type(arg) == half {
+                arg
+            } else if opcode == Opcode::Uextend {
+                dfg.ins(pos).uextend(half, arg)
+            } else {
+                dfg.ins(pos).sextend(half, arg)
+            };
+            let hi = if opcode == Opcode::Uextend {
+                dfg.ins(pos).iconst(half, 0)
+            } else {
+                dfg.ins(pos).sshr_imm(lo, half.bits() as i64 - 1)
+            };
+            dfg.replace(inst).iconcat(lo, hi);
+        }
+        InstructionData::BinaryImm { opcode, arg, imm } if opcode == Opcode::IshlImm ||
+                                                            opcode == Opcode::UshrImm ||
+                                                            opcode == Opcode::SshrImm => {
+            let amount: i64 = imm.into();
+            let (lo, hi) = narrow_shift_imm(dfg, cfg, pos, opcode, arg, half, amount);
+            dfg.replace(inst).iconcat(lo, hi);
+        }
+        InstructionData::Load {
+            opcode: Opcode::Load,
+            flags,
+            arg,
+            offset,
+        } => {
+            let (lo_offset, hi_offset) =
+                match half_offsets(isa.endianness(), offset.into(), half) {
+                    Some(offsets) => offsets,
+                    None => return false,
+                };
+            let lo = dfg.ins(pos).load(half, flags, arg, lo_offset);
+            let hi = dfg.ins(pos).load(half, flags, arg, hi_offset);
+            dfg.replace(inst).iconcat(lo, hi);
+        }
+        InstructionData::Store {
+            opcode: Opcode::Store,
+            flags,
+            args,
+            offset,
+        } => {
+            let (lo_offset, hi_offset) =
+                match half_offsets(isa.endianness(), offset.into(), half) {
+                    Some(offsets) => offsets,
+                    None => return false,
+                };
+            let (lo, hi) = split::isplit(dfg, cfg, pos, args[0]);
+            dfg.ins(pos).store(flags, lo, args[1], lo_offset);
+            dfg.replace(inst).store(flags, hi, args[1], hi_offset);
+        }
+        InstructionData::IntCompare { cond, args, .. } => {
+            let x = split::isplit(dfg, cfg, pos, args[0]);
+            let y = split::isplit(dfg, cfg, pos, args[1]);
+            let value = narrow_icmp(dfg, pos, cond, x, y);
+            replace_with_value(dfg, pos, value);
+        }
+        InstructionData::IntCompareImm { cond, arg, imm, .. } => {
+            let x = split::isplit(dfg, cfg, pos, arg);
+            let y = iconst_halves(dfg, pos, half, imm.into());
+            let value = narrow_icmp(dfg, pos, cond, x, y);
+            replace_with_value(dfg, pos, value);
+        }
+        InstructionData::Branch { .. } => {
+            let arg = dfg.inst_args(inst)[0];
+            let (lo, hi) = split::isplit(dfg, cfg, pos, arg);
+            let value = dfg.ins(pos).bor(lo, hi);
+            dfg.inst_args_mut(inst)[0] = value;
+        }
+        InstructionData::BranchIcmp { cond, destination, .. } => {
+            let (x, y) = (dfg.inst_args(inst)[0], dfg.inst_args(inst)[1]);
+            let x = split::isplit(dfg, cfg, pos, x);
+            let y = split::isplit(dfg, cfg, pos, y);
+            let value = narrow_icmp(dfg, pos, cond, x, y);
+            let ebb_args = dfg.inst_variable_args(inst).to_vec();
+            dfg.replace(inst).brnz(value, destination, &ebb_args);
+        }
+        InstructionData::Binary { opcode, args } => {
+            match opcode {
+                Opcode::Ishl | Opcode::Ushr | Opcode::Sshr => {
+                    // The library routines take the shift amount as a narrow integer.
+                    if dfg.value_type(args[1]) == ty {
+                        let (lo, _) = split::isplit(dfg, cfg, pos, args[1]);
+                        dfg.inst_args_mut(inst)[1] = lo;
+                    }
+                }
+                Opcode::Imul | Opcode::Udiv | Opcode::Sdiv | Opcode::Urem | Opcode::Srem => {}
+                _ => return false,
+            }
+            return libcall::expand_as_libcall(dfg, pos, isa);
+        }
+        _ => return false,
+    }
+    true
+}
+
+/// Narrow the wide arguments of the instruction at `pos` whose controlling type is legal.
+///
+/// This handles `ireduce` from an integer type wider than the ISA's registers, which is legalized
+/// by expansion since its controlling type variable is the narrow result type.
+///
+/// Returns `false` if the instruction isn't handled here.
+pub fn narrow_args(dfg: &mut DataFlowGraph,
+                   cfg: &mut ControlFlowGraph,
+                   pos: &mut Cursor,
+                   isa: &TargetIsa)
+                   -> bool {
+    let inst = pos.current_inst().expect("need instruction");
+    let arg = match dfg[inst] {
+        InstructionData::Unary {
+            opcode: Opcode::Ireduce,
+            arg,
+        } => arg,
+        _ => return false,
+    };
+    let reg_bits = if isa.flags().is_64bit() { 64 } else { 32 };
+    let arg_type = dfg.value_type(arg);
+    let half = match arg_type.half_width() {
+        Some(half) if arg_type.bits() > reg_bits => half,
+        _ => return false,
+    };
+    let ty = dfg.ctrl_typevar(inst);
+
+    let (lo, _) = split::isplit(dfg, cfg, pos, arg);
+    if half == ty {
+        replace_with_value(dfg, pos, lo);
+    } else {
+        dfg.replace(inst).ireduce(ty, lo);
+    }
+    true
+}
+
+/// Get the offsets of the low and high halves of a wide integer of `half` sized halves stored at
+/// `offset`, or `None` if the offset of the upper half overflows.
+fn half_offsets(endianness: Endianness, offset: i32, half: Type) -> Option<(i32, i32)> {
+    let upper = match offset.checked_add(half.bytes() as i32) {
+        Some(upper) => upper,
+        None => return None,
+    };
+    Some(match endianness {
+             Endianness::Little => (offset, upper),
+             Endianness::Big => (upper, offset),
+         })
+}
+
+/// Remove the single-result instruction at `pos`, turning its result into an alias of `value`.
+fn replace_with_value(dfg: &mut DataFlowGraph, pos: &mut Cursor, value: Value) {
+    let inst = pos.remove_inst();
+    let result = dfg.first_result(inst);
+    dfg.clear_results(inst);
+    dfg.change_to_alias(result, value);
+}
+
+/// Insert instructions materializing the low and high halves of the wide constant `imm`.
+fn iconst_halves(dfg: &mut DataFlowGraph,
+                 pos: &mut Cursor,
+                 half: Type,
+                 imm: i64)
+                 -> (Value, Value) {
+    let bits = half.bits() as i64;
+    let lo = dfg.ins(pos).iconst(half, (imm << (64 - bits)) >> (64 - bits));
+    let hi = dfg.ins(pos).iconst(half, imm >> bits);
+    (lo, hi)
+}
+
+/// Insert instructions computing `icmp cond, x, y` from the low and high halves of the wide
+/// values `x` and `y`.
+fn narrow_icmp(dfg: &mut DataFlowGraph,
+               pos: &mut Cursor,
+               cond: IntCC,
+               (xl, xh): (Value, Value),
+               (yl, yh): (Value, Value))
+               -> Value {
+    match cond {
+        IntCC::Equal => {
+            let lo = dfg.ins(pos).icmp(cond, xl, yl);
+            let hi = dfg.ins(pos).icmp(cond, xh, yh);
+            dfg.ins(pos).band(lo, hi)
+        }
+        IntCC::NotEqual => {
+            let lo = dfg.ins(pos).icmp(cond, xl, yl);
+            let hi = dfg.ins(pos).icmp(cond, xh, yh);
+            dfg.ins(pos).bor(lo, hi)
+        }
+        _ => {
+            let (hi_cond, lo_cond) = split_cond(cond);
+            let hi = dfg.ins(pos).icmp(hi_cond, xh, yh);
+            let hi_eq = dfg.ins(pos).icmp(IntCC::Equal, xh, yh);
+            let lo = dfg.ins(pos).icmp(lo_cond, xl, yl);
+            let lo = dfg.ins(pos).band(hi_eq, lo);
+            dfg.ins(pos).bor(hi, lo)
+        }
+    }
+}
+
+/// Get the condition codes to apply to the high and low halves for the ordered comparison `cond`.
+///
+/// The high halves are compared strictly with the signedness of `cond`, and the low halves are
+/// always compared as unsigned numbers.
+fn split_cond(cond: IntCC) -> (IntCC, IntCC) {
+    use ir::condcodes::IntCC::*;
+    match cond {
+        SignedLessThan => (SignedLessThan, UnsignedLessThan),
+        SignedLessThanOrEqual => (SignedLessThan, UnsignedLessThanOrEqual),
+        SignedGreaterThan => (SignedGreaterThan, UnsignedGreaterThan),
+        SignedGreaterThanOrEqual => (SignedGreaterThan, UnsignedGreaterThanOrEqual),
+        UnsignedLessThan => (UnsignedLessThan, UnsignedLessThan),
+        UnsignedLessThanOrEqual => (UnsignedLessThan, UnsignedLessThanOrEqual),
+        UnsignedGreaterThan => (UnsignedGreaterThan, UnsignedGreaterThan),
+        UnsignedGreaterThanOrEqual => (UnsignedGreaterThan, UnsignedGreaterThanOrEqual),
+        Equal | NotEqual => panic!("{} is not an ordered condition", cond),
+    }
+}
+
+/// Insert instructions computing the low and high halves of the wide shift `opcode` of `x` by
+/// the constant `amount`.
+fn narrow_shift_imm(dfg: &mut DataFlowGraph,
+                    cfg: &mut ControlFlowGraph,
+                    pos: &mut Cursor,
+                    opcode: Opcode,
+                    x: Value,
+                    half: Type,
+                    amount: i64)
+                    -> (Value, Value) {
+    let bits = half.bits() as i64;
+    let amount = amount & (2 * bits - 1);
+    let (xl, xh) = split::isplit(dfg, cfg, pos, x);
+
+    if amount == 0 {
+        return (xl, xh);
+    }
+
+    match opcode {
+        Opcode::IshlImm if amount < bits => {
+            let lo = dfg.ins(pos).ishl_imm(xl, amount);
+            let hi = dfg.ins(pos).ishl_imm(xh, amount);
+            let carry = dfg.ins(pos).ushr_imm(xl, bits - amount);
+            (lo, dfg.ins(pos).bor(hi, carry))
+        }
+        Opcode::IshlImm => {
+            let lo = dfg.ins(pos).iconst(half, 0);
+            (lo, dfg.ins(pos).ishl_imm(xl, amount - bits))
+        }
+        _ if amount < bits => {
+            let lo = dfg.ins(pos).ushr_imm(xl, amount);
+            let carry = dfg.ins(pos).ishl_imm(xh, bits - amount);
+            let lo = dfg.ins(pos).bor(lo, carry);
+            let hi = if opcode == Opcode::UshrImm {
+                dfg.ins(pos).ushr_imm(xh, amount)
+            } else {
+                dfg.ins(pos).sshr_imm(xh, amount)
+            };
+            (lo, hi)
+        }
+        Opcode::UshrImm => {
+            let lo = dfg.ins(pos).ushr_imm(xh, amount - bits);
+            (lo, dfg.ins(pos).iconst(half, 0))
+        }
+        _ => {
+            let lo = dfg.ins(pos).sshr_imm(xh, amount - bits);
+            (lo, dfg.ins(pos).sshr_imm(xh, bits - 1))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::half_offsets;
+    use ir::types::I32;
+    use isa::Endianness;
+
+    #[test]
+    fn offsets() {
+        assert_eq!(half_offsets(Endianness::Little, 8, I32), Some((8, 12)));
+        assert_eq!(half_offsets(Endianness::Big, 8, I32), Some((12, 8)));
+        assert_eq!(half_offsets(Endianness::Big, -4, I32), Some((0, -4)));
+        assert_eq!(half_offsets(Endianness::Little, i32::max_value() - 2, I32), None);
+    }
+}
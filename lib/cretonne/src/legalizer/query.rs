@@ -0,0 +1,113 @@
+//! Querying the legalization of an instruction.
+//!
+//! A frontend can often generate the same operation in several ways, and the best choice depends
+//! on what the target ISA can encode. For example, a division by a constant may be better
+//! expressed as a multiplication when the ISA has no divide instruction. The `lowering()` function
+//! tells how the legalizer would handle an instruction without changing anything.
+
+use ir::{DataFlowGraph, InstructionData, Opcode, Type};
+use isa::{Encoding, Legalize, TargetIsa};
+use super::LegalizeHooks;
+
+/// How the legalizer handles an instruction.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Lowering {
+    /// The instruction is legal, and it will get this encoding.
+    Native(Encoding),
+
+    /// The embedder has registered a legalization hook for the opcode.
+    ///
+    /// A hook can decline to transform an instruction, so it isn't known what happens to it
+    /// without running the hook.
+    Hook,
+
+    /// The instruction is illegal, and it will be transformed by this legalization action.
+    ///
+    /// The instructions replacing it may need further legalization.
+    Legalize(Legalize),
+}
+
+impl Lowering {
+    /// Will the instruction be encoded as is?
+    pub fn is_native(&self) -> bool {
+        match *self {
+            Lowering::Native(_) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Determine how the legalizer would handle the instruction `data` with the controlling type
+/// variable `ctrl_typevar`.
+///
+/// The values used by `data` must be defined in `dfg`, but the instruction doesn't have to be
+/// inserted in a function. The `hooks` are the ones that will be passed to `legalize_function()`.
+pub fn lowering(dfg: &DataFlowGraph,
+                data: &InstructionData,
+                ctrl_typevar: Type,
+                isa: &TargetIsa,
+                hooks: &LegalizeHooks)
+                -> Lowering {
+    let opcode = data.opcode();
+    if hooks.handles(opcode) {
+        return Lowering::Hook;
+    }
+
+    // The legalizer has dedicated expansions for these.
+    match opcode {
+        Opcode::TlsValue | Opcode::BrTable => return Lowering::Legalize(Legalize::Expand),
+        Opcode::HeapAddr if isa.flags().enable_pinned_reg() => {
+            return Lowering::Legalize(Legalize::Expand)
+        }
+        _ => {}
+    }
+
+    match isa.encode(dfg, data, ctrl_typevar) {
+        Ok(encoding) => Lowering::Native(encoding),
+        Err(action) => Lowering::Legalize(action),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lowering, Lowering};
+    use ir::{Function, InstructionData, Opcode};
+    use ir::types::{I32, I64, F32};
+    use isa::{self, Legalize};
+    use legalizer::LegalizeHooks;
+    use settings::{self, Configurable};
+
+    #[test]
+    fn riscv() {
+        let shared_flags = settings::Flags::new(&settings::builder());
+        let mut isa_builder = isa::lookup("riscv").unwrap();
+        isa_builder.set_bool("soft_float", true).unwrap();
+        let isa = isa_builder.finish(shared_flags);
+        let mut hooks = LegalizeHooks::new();
+
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let a = func.dfg.append_ebb_arg(ebb0, I32);
+        let b = func.dfg.append_ebb_arg(ebb0, I64);
+        let c = func.dfg.append_ebb_arg(ebb0, F32);
+        let binary = |opcode, x| {
+            InstructionData::Binary {
+                opcode: opcode,
+                args: [x, x],
+            }
+        };
+
+        let iadd32 = binary(Opcode::Iadd, a);
+        let query = |data: &InstructionData, ty, hooks: &LegalizeHooks| {
+            lowering(&func.dfg, data, ty, &*isa, hooks)
+        };
+        assert!(query(&iadd32, I32, &hooks).is_native());
+        assert_eq!(query(&binary(Opcode::Iadd, b), I64, &hooks),
+                   Lowering::Legalize(Legalize::Narrow));
+        assert_eq!(query(&binary(Opcode::Fadd, c), F32, &hooks),
+                   Lowering::Legalize(Legalize::Libcall));
+
+        hooks.add(Opcode::Iadd, |_, _, _, _| false);
+        assert_eq!(query(&iadd32, I32, &hooks), Lowering::Hook);
+    }
+}
@@ -0,0 +1,100 @@
+//! Select legalization.
+//!
+//! ISAs with conditional moves can encode the `select` instruction directly. Other selects are
+//! expanded into a conditional branch that passes the selected value as an EBB argument:
+//!
+//! ```cton
+//!     v3 = select v0, v1, v2
+//!     v4 = iadd v3, v3
+//! ```
+//!
+//! becomes:
+//!
+//! ```cton
+//!     brnz v0, ebb1(v1)
+//!     jump ebb1(v2)
+//! ebb1(v3: i32):
+//!     v4 = iadd v3, v3
+//! ```
+//!
+//! A select that is narrowed into smaller selects is only expanded if the smaller selects can't
+//! be encoded.
+
+use flowgraph::ControlFlowGraph;
+use ir::{Function, Cursor, DataFlowGraph, Inst, InstBuilder, InstructionData, Opcode};
+use isa::{TargetIsa, Legalize};
+use super::{LegalizeHooks, LegalizeTrace};
+
+/// Expand all the `select` instructions in `func` that can't be encoded by `isa`.
+///
+/// This splits EBBs, so the control flow graph is recomputed if anything changed. Selects handled
+/// by a legalization hook are left alone. The expansions are recorded in `trace` when it is given.
+pub fn expand_selects(func: &mut Function,
+                      cfg: &mut ControlFlowGraph,
+                      isa: &TargetIsa,
+                      hooks: &LegalizeHooks,
+                      mut trace: Option<&mut LegalizeTrace>) {
+    if hooks.handles(Opcode::Select) {
+        return;
+    }
+    let mut selects = Vec::new();
+    for ebb in func.layout.ebbs() {
+        for inst in func.layout.ebb_insts(ebb) {
+            if func.dfg[inst].opcode() == Opcode::Select && !has_encoding(&func.dfg, inst, isa) {
+                selects.push(inst);
+            }
+        }
+    }
+    if selects.is_empty() {
+        return;
+    }
+
+    for inst in selects {
+        let text = trace
+            .as_ref()
+            .map(|_| func.dfg.display_inst(inst).to_string());
+        expand_select(func, inst);
+        if let Some(ref mut trace) = trace {
+            trace.push(inst, "select_branch", None, text.unwrap_or_default());
+        }
+    }
+    cfg.compute(func);
+}
+
+/// Can the `select` instruction `inst` be encoded, possibly after narrowing it?
+fn has_encoding(dfg: &DataFlowGraph, inst: Inst, isa: &TargetIsa) -> bool {
+    let mut ty = dfg.ctrl_typevar(inst);
+    loop {
+        match isa.encode(dfg, &dfg[inst], ty) {
+            Ok(_) => return true,
+            Err(Legalize::Narrow) => {
+                match ty.half_width() {
+                    Some(half) => ty = half,
+                    None => return false,
+                }
+            }
+            Err(_) => return false,
+        }
+    }
+}
+
+/// Expand the `select` instruction `inst` into a conditional branch.
+fn expand_select(func: &mut Function, inst: Inst) {
+    let (cond, x, y) = match func.dfg[inst] {
+        InstructionData::Ternary { args, .. } => (args[0], args[1], args[2]),
+        _ => panic!("Expected select: {}", func.dfg.display_inst(inst)),
+    };
+
+    // The result becomes the argument of a new EBB containing the instructions after `inst`.
+    let result = func.dfg.first_result(inst);
+    let ebb = func.dfg.make_ebb();
+    func.dfg.clear_results(inst);
+    func.dfg.attach_ebb_arg(ebb, result);
+
+    let mut pos = Cursor::new(&mut func.layout);
+    pos.goto_inst(inst);
+    pos.next_inst().expect("select can't terminate an EBB");
+    func.dfg.ins(&mut pos).jump(ebb, &[y]);
+    pos.insert_ebb(ebb);
+    func.dfg.replace(inst).brnz(cond, ebb, &[x]);
+}
@@ -0,0 +1,84 @@
+//! Table address legalization.
+//!
+//! The `table_addr` instruction checks an index against the bound of a table and computes the
+//! address of the element. The table base address is loaded from the table's thread-local
+//! variable:
+//!
+//! ```cton
+//!     table0 = table gv0, bound 10, element_size 16
+//!
+//!     v1 = table_addr.i64 table0, v0+8
+//! ```
+//!
+//! becomes:
+//!
+//! ```cton
+//!     v2 = icmp_imm uge v0, 10
+//!     trapnz v2
+//!     v3 = uextend.i64 v0
+//!     v4 = tls_value.i64 gv0
+//!     v5 = load.i64 notrap aligned v4
+//!     v6 = ishl_imm v3, 4
+//!     v7 = iadd v5, v6
+//!     v1 = iadd_imm v7, 8
+//! ```
+
+use entity_map::EntityMap;
+use ir::{Cursor, DataFlowGraph, InstBuilder, InstructionData, MemFlags, Table, TableData, Value};
+use ir::condcodes::IntCC;
+
+/// Expand the `table_addr` instruction at `pos` using the declarations in `tables`.
+pub fn expand_table_addr(dfg: &mut DataFlowGraph,
+                         pos: &mut Cursor,
+                         tables: &EntityMap<Table, TableData>) {
+    let inst = pos.current_inst().expect("need instruction");
+    let (table, index, offset): (Table, Value, i32) = match dfg[inst] {
+        InstructionData::TableAddr {
+            table,
+            arg,
+            offset,
+            ..
+        } => (table, arg, offset.into()),
+        _ => panic!("Expected table_addr: {}", dfg.display_inst(inst)),
+    };
+    let ty = dfg.ctrl_typevar(inst);
+    let index_ty = dfg.value_type(index);
+    let data = &tables[table];
+
+    // Every index is in bounds when the bound doesn't fit in the index type.
+    if index_ty.bits() == 64 || data.bound < 1 << index_ty.bits() {
+        let oob = dfg.ins(pos)
+            .icmp_imm(IntCC::UnsignedGreaterThanOrEqual, index, data.bound as i64);
+        dfg.ins(pos).trapnz(oob);
+    }
+
+    let index = if index_ty.bits() < ty.bits() {
+        dfg.ins(pos).uextend(ty, index)
+    } else if index_ty.bits() > ty.bits() {
+        dfg.ins(pos).ireduce(ty, index)
+    } else {
+        index
+    };
+
+    let mut flags = MemFlags::new();
+    flags.set_notrap();
+    flags.set_aligned();
+    let base_addr = dfg.ins(pos).tls_value(ty, data.base);
+    let base = dfg.ins(pos).load(ty, flags, base_addr, 0);
+
+    let scaled = if data.element_size == 1 {
+        index
+    } else if data.element_size.is_power_of_two() {
+        dfg.ins(pos)
+            .ishl_imm(index, data.element_size.trailing_zeros() as i64)
+    } else {
+        dfg.ins(pos).imul_imm(index, data.element_size as i64)
+    };
+
+    if offset == 0 {
+        dfg.replace(inst).iadd(base, scaled);
+    } else {
+        let elem = dfg.ins(pos).iadd(base, scaled);
+        dfg.replace(inst).iadd_imm(elem, offset as i64);
+    }
+}
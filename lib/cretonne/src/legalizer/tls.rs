@@ -0,0 +1,46 @@
+//! Thread-local storage legalization.
+//!
+//! The `tls_value` instruction computes the address of a thread-local variable. The code sequence
+//! needed depends on the `tls_model` setting, so it can't be expressed as an `XForm`:
+//!
+//! - `initial_exec` adds the thread pointer to an offset loaded from the GOT:
+//!
+//!   ```cton
+//!       v1 = tls_value.i64 gv0
+//!   ```
+//!
+//!   becomes:
+//!
+//!   ```cton
+//!       v2 = thread_pointer.i64
+//!       v3 = tls_ie_offset.i64 gv0
+//!       v1 = iadd v2, v3
+//!   ```
+//!
+//! - `general_dynamic` calls `__tls_get_addr` which is hidden inside the `tls_gd_addr`
+//!   instruction.
+
+use ir::{Cursor, DataFlowGraph, InstBuilder, InstructionData};
+use isa::TargetIsa;
+use settings::TlsModel;
+
+/// Expand the `tls_value` instruction at `pos` according to the `tls_model` setting.
+pub fn expand_tls_value(dfg: &mut DataFlowGraph, pos: &mut Cursor, isa: &TargetIsa) {
+    let inst = pos.current_inst().expect("need instruction");
+    let gv = match dfg[inst] {
+        InstructionData::UnaryGlobalVar { global_var, .. } => global_var,
+        _ => panic!("Expected tls_value: {}", dfg.display_inst(inst)),
+    };
+    let ty = dfg.ctrl_typevar(inst);
+
+    match isa.flags().tls_model() {
+        TlsModel::InitialExec => {
+            let tp = dfg.ins(pos).thread_pointer(ty);
+            let offset = dfg.ins(pos).tls_ie_offset(ty, gv);
+            dfg.replace(inst).iadd(tp, offset);
+        }
+        TlsModel::GeneralDynamic => {
+            dfg.replace(inst).tls_gd_addr(ty, gv);
+        }
+    }
+}
@@ -0,0 +1,81 @@
+//! Tracing the legalization actions.
+//!
+//! When the tables describing the encodings and legalization patterns for an ISA don't produce the
+//! expected code, it helps to know which action transformed each instruction. The legalizer can
+//! record a `LegalizeStep` for every instruction it transforms.
+
+use ir::Inst;
+use std::fmt;
+use std::slice;
+
+/// A single instruction transformed by the legalizer.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct LegalizeStep {
+    /// The instruction that was transformed.
+    ///
+    /// The instruction may have been removed or replaced by the transformation.
+    pub inst: Inst,
+    /// The legalization action that transformed the instruction, like `"expand"` or `"libcall"`.
+    pub action: &'static str,
+    /// The name of the transformation from the ISA's legalization patterns that was applied, like
+    /// `"narrow.iadd"`. Actions that aren't described by a pattern don't have one.
+    pub rule: Option<&'static str>,
+    /// The instruction as it was written before the transformation.
+    pub text: String,
+}
+
+impl fmt::Display for LegalizeStep {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.action, self.inst)?;
+        if let Some(rule) = self.rule {
+            write!(f, " by {}", rule)?;
+        }
+        write!(f, ": {}", self.text)
+    }
+}
+
+/// The legalization actions applied to a function, in the order they were applied.
+///
+/// An instruction is transformed repeatedly when the replacement instructions need further
+/// legalization, and each of those steps is recorded.
+#[derive(Clone, Debug, Default)]
+pub struct LegalizeTrace {
+    steps: Vec<LegalizeStep>,
+}
+
+impl LegalizeTrace {
+    /// Create an empty trace.
+    pub fn new() -> LegalizeTrace {
+        LegalizeTrace::default()
+    }
+
+    /// Forget all the recorded steps.
+    pub fn clear(&mut self) {
+        self.steps.clear();
+    }
+
+    /// Record that `action` transformed `inst`, which was written as `text` before, by applying
+    /// the transformation named `rule`.
+    pub fn push(&mut self,
+                inst: Inst,
+                action: &'static str,
+                rule: Option<&'static str>,
+                text: String) {
+        self.steps.push(LegalizeStep {
+                            inst: inst,
+                            action: action,
+                            rule: rule,
+                            text: text,
+                        });
+    }
+
+    /// Get the recorded steps.
+    pub fn steps(&self) -> slice::Iter<LegalizeStep> {
+        self.steps.iter()
+    }
+
+    /// Is the trace empty?
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+}
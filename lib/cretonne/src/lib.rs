@@ -2,37 +2,55 @@
 
 #![deny(missing_docs)]
 
-pub use context::Context;
-pub use legalizer::legalize_function;
+pub use context::{Context, CompiledFunction, PassState};
+pub use legalizer::{legalize_function, lowering, LegalizeHook, LegalizeHooks, LegalizeStep,
+                    LegalizeTrace, Lowering};
 pub use verifier::verify_function;
-pub use write::write_function;
+pub use write::{write_function, write_function_with_cfg, write_version, IL_VERSION};
 
 /// Version number of the cretonne crate.
 pub const VERSION: &'static str = env!("CARGO_PKG_VERSION");
 
 #[macro_use]
-pub mod dbg;
+pub mod log;
 
 pub mod binemit;
+pub mod branches;
+pub mod diagnostics;
 pub mod dominator_tree;
 pub mod entity_list;
 pub mod entity_map;
 pub mod flowgraph;
 pub mod ir;
 pub mod isa;
+pub mod mem_usage;
+pub mod native;
+pub mod opcode_stats;
+pub mod parallel;
+pub mod pipeline;
+pub mod print_errors;
+pub mod randfunc;
 pub mod regalloc;
 pub mod result;
+pub mod serialize;
 pub mod settings;
 pub mod sparse_map;
+pub mod timing;
 pub mod verifier;
 
 mod abi;
 mod constant_hash;
+mod compact;
 mod context;
+mod dce;
+mod ebb_layout;
+mod heap_checks;
 mod iterators;
 mod legalizer;
 mod packed_option;
 mod partition_slice;
 mod predicates;
+mod preopt;
 mod ref_slice;
+mod stack_layout;
 mod write;
@@ -0,0 +1,320 @@
+//! Leveled logging.
+//!
+//! This module defines the `error!`, `warn!`, `info!`, `debug!`, and `trace!` macros which work
+//! like `println!` except they write to the Cretonne log file if logging is enabled for the
+//! calling module at that level.
+//!
+//! Logging is configured by the `CRETONNE_LOG` environment variable which holds a comma-separated
+//! list of directives:
+//!
+//! - A level name like `info` sets the level for all modules.
+//! - `module=level` sets the level for a module and its sub-modules. The module path is given
+//!   without the crate name, so `regalloc=trace` enables tracing for all of the register
+//!   allocator, and `regalloc::coloring=off` disables logging from the coloring pass.
+//! - A module name by itself enables all logging for that module.
+//!
+//! The most specific directive applies, so `CRETONNE_LOG=debug,legalizer=off` logs everything
+//! except the legalizer at the debug level.
+//!
+//! The output will appear in files named `cretonne.log.*`, where the suffix is named after the
+//! thread doing the logging.
+//!
+//! When logging is disabled, the macros only cost a relaxed atomic load. The `debug!` and
+//! `trace!` macros compile to nothing when debug assertions are disabled.
+
+use std::ascii::AsciiExt;
+use std::cell::RefCell;
+use std::env;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Write, BufWriter};
+use std::str::FromStr;
+use std::sync::{Once, ONCE_INIT};
+use std::sync::atomic::{AtomicUsize, ATOMIC_USIZE_INIT, Ordering};
+use std::thread;
+
+/// Log levels, from the most to the least important.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Level {
+    /// Something went wrong.
+    Error = 1,
+    /// Something looks suspicious.
+    Warn,
+    /// High-level progress information.
+    Info,
+    /// Decisions made by the compiler passes.
+    Debug,
+    /// Very detailed tracing.
+    Trace,
+}
+
+const LEVEL_NAMES: [&'static str; 6] = ["off", "error", "warn", "info", "debug", "trace"];
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(LEVEL_NAMES[*self as usize])
+    }
+}
+
+/// The most verbose level enabled for a module, or 0 when logging is off.
+type LevelFilter = usize;
+
+/// Parse a level name into a level filter.
+fn parse_level(name: &str) -> Option<LevelFilter> {
+    LEVEL_NAMES.iter().position(|&n| n == name)
+}
+
+/// A parsed `CRETONNE_LOG` specification.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Filter {
+    default: LevelFilter,
+    // Module paths without the crate name, and their levels.
+    modules: Vec<(String, LevelFilter)>,
+}
+
+impl Filter {
+    /// Create a filter that disables all logging.
+    pub fn off() -> Filter {
+        Filter {
+            default: 0,
+            modules: Vec::new(),
+        }
+    }
+
+    /// Should a message at `level` from the module `module_path` be logged?
+    ///
+    /// The module path is the full path as returned by `module_path!()`, including the crate
+    /// name.
+    pub fn allows(&self, level: Level, module_path: &str) -> bool {
+        let path = match module_path.find("::") {
+            Some(pos) => &module_path[pos + 2..],
+            None => "",
+        };
+        let mut best: Option<(&str, LevelFilter)> = None;
+        for &(ref module, filter) in &self.modules {
+            let matches = path.starts_with(module.as_str()) &&
+                          (path.len() == module.len() || path[module.len()..].starts_with("::"));
+            if matches && best.map_or(true, |(m, _)| module.len() > m.len()) {
+                best = Some((module, filter));
+            }
+        }
+        level as LevelFilter <= best.map_or(self.default, |(_, f)| f)
+    }
+
+    /// Get the most verbose level enabled for any module.
+    fn max_level(&self) -> LevelFilter {
+        self.modules
+            .iter()
+            .map(|&(_, f)| f)
+            .fold(self.default, ::std::cmp::max)
+    }
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Filter, String> {
+        let mut filter = Filter::off();
+        for directive in s.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            match directive.find('=') {
+                Some(pos) => {
+                    let level = &directive[pos + 1..];
+                    let level = parse_level(level)
+                        .ok_or_else(|| format!("unknown log level '{}'", level))?;
+                    filter
+                        .modules
+                        .push((directive[..pos].to_string(), level));
+                }
+                None => {
+                    match parse_level(directive) {
+                        Some(level) => filter.default = level,
+                        None => {
+                            filter
+                                .modules
+                                .push((directive.to_string(), Level::Trace as LevelFilter))
+                        }
+                    }
+                }
+            }
+        }
+        Ok(filter)
+    }
+}
+
+// The initialization state is 0 before the filter has been read from the environment, and
+// `max_level + 1` after.
+static STATE: AtomicUsize = ATOMIC_USIZE_INIT;
+static INIT: Once = ONCE_INIT;
+// Pointer to the leaked `Filter`, once initialized.
+static FILTER: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Is logging enabled for messages at `level` from `module_path`?
+///
+/// This inline function turns into a constant `false` for the `Debug` and `Trace` levels when
+/// debug assertions are disabled.
+#[inline]
+pub fn enabled(level: Level, module_path: &str) -> bool {
+    if level >= Level::Debug && !cfg!(debug_assertions) {
+        return false;
+    }
+    let max_level = match STATE.load(Ordering::Relaxed) {
+        0 => initialize(),
+        s => s - 1,
+    };
+    level as LevelFilter <= max_level && filter().allows(level, module_path)
+}
+
+/// Read the filter from the `CRETONNE_LOG` environment variable and return its maximum level.
+#[cold]
+fn initialize() -> LevelFilter {
+    INIT.call_once(|| {
+        let filter = match env::var("CRETONNE_LOG") {
+            Ok(spec) => {
+                spec.parse().unwrap_or_else(|e| {
+                    writeln!(io::stderr(), "Ignoring CRETONNE_LOG: {}", e).ok();
+                    Filter::off()
+                })
+            }
+            Err(_) => Filter::off(),
+        };
+        let max_level = filter.max_level();
+        FILTER.store(Box::into_raw(Box::new(filter)) as usize, Ordering::SeqCst);
+        STATE.store(max_level + 1, Ordering::SeqCst);
+    });
+    STATE.load(Ordering::SeqCst) - 1
+}
+
+/// Get the filter after it has been initialized.
+fn filter() -> &'static Filter {
+    // The filter is never freed, and it is stored before `STATE` is made non-zero.
+    unsafe { &*(FILTER.load(Ordering::SeqCst) as *const Filter) }
+}
+
+thread_local! {
+    static WRITER : RefCell<BufWriter<File>> = RefCell::new(open_file());
+}
+
+/// Execute a closure with mutable access to the log file writer for the current thread.
+///
+/// This can be used to write large amounts of text to the log after checking `enabled()`.
+pub fn with_writer<F, R>(f: F) -> R
+    where F: FnOnce(&mut Write) -> R
+{
+    WRITER.with(|rc| f(&mut *rc.borrow_mut()))
+}
+
+/// Open the log file for the current thread.
+fn open_file() -> BufWriter<File> {
+    let file = match thread::current().name() {
+            None => File::create("cretonne.log"),
+            Some(name) => {
+                let mut path = "cretonne.log.".to_owned();
+                for ch in name.chars() {
+                    if ch.is_ascii() && ch.is_alphanumeric() {
+                        path.push(ch);
+                    }
+                }
+                File::create(path)
+            }
+        }
+        .expect("Can't open log file");
+    BufWriter::new(file)
+}
+
+/// Write a message from `module_path` to the log file.
+///
+/// This is for use by the logging macros which have already checked that the message is enabled.
+pub fn write(level: Level, module_path: &str, args: fmt::Arguments) {
+    // Drop the error result. What are you going to do, log the error?
+    with_writer(|w| writeln!(w, "{} {}: {}", level, module_path, args).ok());
+}
+
+/// Write a line to the log file if logging is enabled for `level` in the current module.
+///
+/// The remaining arguments are the same as for `println!`.
+#[macro_export]
+macro_rules! log_at {
+    ($lvl:expr, $($arg:tt)+) => {
+        if $crate::log::enabled($lvl, module_path!()) {
+            $crate::log::write($lvl, module_path!(), format_args!($($arg)+))
+        }
+    }
+}
+
+/// Log an error.
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)+) => {
+        if $crate::log::enabled($crate::log::Level::Error, module_path!()) {
+            $crate::log::write($crate::log::Level::Error, module_path!(), format_args!($($arg)+))
+        }
+    }
+}
+
+/// Log a warning.
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)+) => {
+        if $crate::log::enabled($crate::log::Level::Warn, module_path!()) {
+            $crate::log::write($crate::log::Level::Warn, module_path!(), format_args!($($arg)+))
+        }
+    }
+}
+
+/// Log an informational message.
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)+) => {
+        if $crate::log::enabled($crate::log::Level::Info, module_path!()) {
+            $crate::log::write($crate::log::Level::Info, module_path!(), format_args!($($arg)+))
+        }
+    }
+}
+
+/// Log a debug message.
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)+) => {
+        if $crate::log::enabled($crate::log::Level::Debug, module_path!()) {
+            $crate::log::write($crate::log::Level::Debug, module_path!(), format_args!($($arg)+))
+        }
+    }
+}
+
+/// Log a trace message.
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)+) => {
+        if $crate::log::enabled($crate::log::Level::Trace, module_path!()) {
+            $crate::log::write($crate::log::Level::Trace, module_path!(), format_args!($($arg)+))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Filter, Level};
+
+    #[test]
+    fn parse() {
+        assert_eq!("".parse(), Ok(Filter::off()));
+        assert_eq!("info,regalloc=trace, legalizer".parse::<Filter>().unwrap().max_level(),
+                   Level::Trace as usize);
+        assert_eq!("regalloc=loud".parse::<Filter>(),
+                   Err("unknown log level 'loud'".to_string()));
+    }
+
+    #[test]
+    fn allows() {
+        let f: Filter = "info,regalloc=trace,regalloc::coloring=off,binemit".parse().unwrap();
+        assert!(f.allows(Level::Info, "cretonne::legalizer"));
+        assert!(!f.allows(Level::Debug, "cretonne::legalizer"));
+        assert!(f.allows(Level::Trace, "cretonne::regalloc::coalescing"));
+        assert!(!f.allows(Level::Error, "cretonne::regalloc::coloring"));
+        assert!(f.allows(Level::Trace, "cretonne::binemit"));
+        assert!(f.allows(Level::Trace, "cretonne::binemit::relaxation"));
+        // Module names must match whole path components.
+        assert!(!f.allows(Level::Debug, "cretonne::binemitter"));
+        assert!(f.allows(Level::Info, "cretonne"));
+    }
+}
@@ -0,0 +1,83 @@
+//! Memory usage statistics.
+//!
+//! `Function::mem_usage()` and `Context::mem_usage()` report the heap memory allocated by a
+//! function and by the compiler's side tables, broken down by component. The numbers count the
+//! allocated capacity rather than the used length since that is what a reused `Context` holds on
+//! to.
+//!
+//! Only the main tables are counted. Small allocations owned by individual entities, like the
+//! predecessor lists in the control flow graph or the arguments of a signature, are not included.
+//!
+//! The `Display` implementation prints a report with one line per component.
+
+use std::fmt;
+
+/// Bytes of memory allocated by each component of a data structure.
+#[derive(Clone, Debug)]
+pub struct MemUsage {
+    // Components in the order they were first added.
+    components: Vec<(&'static str, usize)>,
+}
+
+impl MemUsage {
+    /// Create an empty report.
+    pub fn new() -> MemUsage {
+        MemUsage { components: Vec::new() }
+    }
+
+    /// Add `bytes` to the component `name`.
+    pub fn add(&mut self, name: &'static str, bytes: usize) {
+        if let Some(c) = self.components.iter_mut().find(|c| c.0 == name) {
+            c.1 += bytes;
+            return;
+        }
+        self.components.push((name, bytes));
+    }
+
+    /// Get the components and their sizes in bytes.
+    pub fn components(&self) -> &[(&'static str, usize)] {
+        &self.components
+    }
+
+    /// Get the number of bytes used by the component `name`.
+    pub fn get(&self, name: &str) -> Option<usize> {
+        self.components
+            .iter()
+            .find(|c| c.0 == name)
+            .map(|c| c.1)
+    }
+
+    /// Get the total number of bytes used by all components.
+    pub fn total(&self) -> usize {
+        self.components.iter().map(|c| c.1).sum()
+    }
+}
+
+impl fmt::Display for MemUsage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "      Bytes  Component")?;
+        for &(name, bytes) in &self.components {
+            writeln!(f, "{:>11}  {}", bytes, name)?;
+        }
+        writeln!(f, "{:>11}  total", self.total())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MemUsage;
+
+    #[test]
+    fn report() {
+        let mut usage = MemUsage::new();
+        usage.add("dfg.insts", 1024);
+        usage.add("layout", 64);
+        usage.add("dfg.insts", 512);
+        assert_eq!(usage.get("dfg.insts"), Some(1536));
+        assert_eq!(usage.get("cfg"), None);
+        assert_eq!(usage.total(), 1600);
+        assert_eq!(usage.to_string(),
+                   "      Bytes  Component\n       1536  dfg.insts\n         64  layout\n       \
+                    1600  total\n");
+    }
+}
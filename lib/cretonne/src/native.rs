@@ -0,0 +1,159 @@
+//! Host CPU feature detection.
+//!
+//! Cretonne is a cross-compiler, so the target ISA and its settings are normally configured
+//! explicitly. A JIT compiler generates code for the machine it is running on, and it can use the
+//! `builders()` function in this module to get settings builders that are pre-populated with the
+//! features detected on the host CPU:
+//!
+//! ```
+//! use cretonne::{native, settings};
+//!
+//! if let Ok((shared_builder, isa_builder)) = native::builders() {
+//!     let isa = isa_builder.finish(settings::Flags::new(&shared_builder));
+//! }
+//! ```
+//!
+//! The returned builders can still be modified before the `TargetIsa` is created.
+
+use isa;
+use settings::{self, Configurable};
+
+/// Get shared and ISA-specific settings builders that are configured for the host CPU.
+///
+/// Return an error if Cretonne doesn't support the host architecture.
+pub fn builders() -> Result<(settings::Builder, isa::Builder), &'static str> {
+    let mut isa_builder = match isa::lookup(host_arch()) {
+        Ok(b) => b,
+        Err(_) => return Err("Cretonne doesn't support the host architecture"),
+    };
+    let mut shared_builder = settings::builder();
+    isa_builder.configure_shared(&mut shared_builder);
+    detect_features(&mut isa_builder);
+    Ok((shared_builder, isa_builder))
+}
+
+/// Get the architecture component of the host target triple.
+fn host_arch() -> &'static str {
+    if cfg!(target_arch = "x86_64") {
+        "x86_64"
+    } else if cfg!(target_arch = "x86") {
+        "i686"
+    } else if cfg!(target_arch = "aarch64") {
+        "aarch64"
+    } else if cfg!(target_arch = "arm") {
+        "arm"
+    } else if cfg!(target_arch = "riscv64") {
+        "riscv64"
+    } else if cfg!(target_arch = "riscv32") {
+        "riscv32"
+    } else {
+        "unknown"
+    }
+}
+
+/// Set the Intel `has_*` settings from the CPUID bits they are named after.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[allow(unused_unsafe)]
+fn detect_features(isa_builder: &mut isa::Builder) {
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::{__cpuid, __cpuid_count};
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::{__cpuid, __cpuid_count};
+
+    let mut set = |name: &str, value: bool| {
+        isa_builder
+            .set_bool(name, value)
+            .expect("Missing Intel setting")
+    };
+
+    // The CPUID instruction is available on all CPUs that Cretonne supports.
+    let max_leaf = unsafe { __cpuid(0) }.eax;
+    let max_ext_leaf = unsafe { __cpuid(0x8000_0000) }.eax;
+
+    let leaf1 = unsafe { __cpuid(1) };
+    set("has_sse2", leaf1.edx & (1 << 26) != 0);
+    set("has_sse3", leaf1.ecx & (1 << 0) != 0);
+    set("has_ssse3", leaf1.ecx & (1 << 9) != 0);
+    set("has_sse41", leaf1.ecx & (1 << 19) != 0);
+    set("has_sse42", leaf1.ecx & (1 << 20) != 0);
+    set("has_popcnt", leaf1.ecx & (1 << 23) != 0);
+
+    if max_leaf >= 7 {
+        let leaf7 = unsafe { __cpuid_count(7, 0) };
+        set("has_bmi1", leaf7.ebx & (1 << 3) != 0);
+    }
+
+    if max_ext_leaf >= 0x8000_0001 {
+        let ext_leaf1 = unsafe { __cpuid(0x8000_0001) };
+        set("has_lzcnt", ext_leaf1.ecx & (1 << 5) != 0);
+    }
+}
+
+/// Set the RISC-V `supports_*` settings from the Linux `AT_HWCAP` auxiliary vector entry, which
+/// has a bit for each single-letter extension.
+#[cfg(all(any(target_arch = "riscv32", target_arch = "riscv64"), target_os = "linux"))]
+fn detect_features(isa_builder: &mut isa::Builder) {
+    if let Some(hwcap) = linux_hwcap() {
+        for &(ext, name) in &[('m', "supports_m"),
+                              ('a', "supports_a"),
+                              ('f', "supports_f"),
+                              ('d', "supports_d")] {
+            let bit = ext as usize - 'a' as usize;
+            isa_builder
+                .set_bool(name, hwcap & (1 << bit) != 0)
+                .expect("Missing RISC-V setting");
+        }
+    }
+}
+
+/// The ISA settings for other hosts have no features that can be detected.
+#[cfg(not(any(target_arch = "x86",
+              target_arch = "x86_64",
+              all(any(target_arch = "riscv32", target_arch = "riscv64"), target_os = "linux"))))]
+fn detect_features(_isa_builder: &mut isa::Builder) {}
+
+/// Read the `AT_HWCAP` entry from the auxiliary vector of the current process.
+#[cfg(all(any(target_arch = "riscv32", target_arch = "riscv64"), target_os = "linux"))]
+fn linux_hwcap() -> Option<usize> {
+    use std::fs::File;
+    use std::io::Read;
+    use std::mem::size_of;
+
+    const AT_HWCAP: usize = 16;
+
+    let mut auxv = Vec::new();
+    File::open("/proc/self/auxv")
+        .and_then(|mut f| f.read_to_end(&mut auxv))
+        .ok()?;
+
+    // The auxiliary vector is a list of native-endian `(type, value)` word pairs.
+    let words: Vec<usize> = auxv.chunks(size_of::<usize>())
+        .map(|c| if cfg!(target_endian = "little") {
+                 c.iter().rev().fold(0, |w, &b| w << 8 | b as usize)
+             } else {
+                 c.iter().fold(0, |w, &b| w << 8 | b as usize)
+             })
+        .collect();
+    words
+        .chunks(2)
+        .find(|pair| pair[0] == AT_HWCAP && pair.len() == 2)
+        .map(|pair| pair[1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::builders;
+    use settings;
+
+    #[test]
+    fn host_isa() {
+        // Not all hosts are supported, but if the host is supported, we should get a working ISA.
+        if let Ok((shared_builder, isa_builder)) = builders() {
+            let isa = isa_builder.finish(settings::Flags::new(&shared_builder));
+            if cfg!(target_arch = "x86_64") {
+                assert_eq!(isa.name(), "intel");
+                assert!(isa.flags().is_64bit());
+            }
+        }
+    }
+}
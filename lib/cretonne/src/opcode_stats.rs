@@ -0,0 +1,173 @@
+//! Instruction statistics.
+//!
+//! Counting the instructions in the compiled functions by opcode makes changes to the code
+//! generator quantifiable: A patch that makes the register allocator insert 12% more `spill` and
+//! `copy` instructions shows up in the counts recorded after the `regalloc` pass.
+//!
+//! When a `Context` has `OpcodeStats`, `run_pipeline()` counts the instructions in the function
+//! before the first pass and after each pass. The counts are accumulated over all the functions
+//! compiled, and the `Display` implementation prints a table with a column per pass.
+
+use ir::{Function, Opcode};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Number of instructions in the layout for each opcode.
+#[derive(Clone, Debug, Default)]
+pub struct OpcodeCounts {
+    counts: HashMap<Opcode, u64>,
+}
+
+impl OpcodeCounts {
+    /// Create an empty set of counts.
+    pub fn new() -> OpcodeCounts {
+        OpcodeCounts::default()
+    }
+
+    /// Count the instructions in the layout of `func`.
+    pub fn with_function(func: &Function) -> OpcodeCounts {
+        let mut counts = OpcodeCounts::new();
+        counts.add_function(func);
+        counts
+    }
+
+    /// Add the instructions in the layout of `func` to the counts.
+    pub fn add_function(&mut self, func: &Function) {
+        for ebb in func.layout.ebbs() {
+            for inst in func.layout.ebb_insts(ebb) {
+                *self.counts.entry(func.dfg[inst].opcode()).or_insert(0) += 1;
+            }
+        }
+    }
+
+    /// Get the number of instructions with `opcode`.
+    pub fn get(&self, opcode: Opcode) -> u64 {
+        self.counts.get(&opcode).cloned().unwrap_or(0)
+    }
+
+    /// Get the total number of instructions.
+    pub fn total(&self) -> u64 {
+        self.counts.values().sum()
+    }
+
+    /// Get the opcodes that have been counted, sorted by name.
+    pub fn opcodes(&self) -> Vec<Opcode> {
+        let mut opcodes: Vec<Opcode> = self.counts.keys().cloned().collect();
+        opcodes.sort_by_key(|op| op.to_string());
+        opcodes
+    }
+}
+
+/// Instruction counts before the first pass and after each pass.
+#[derive(Clone, Debug, Default)]
+pub struct OpcodeStats {
+    // Counts by pass name in the order the passes were first run. The counts before the first pass
+    // are named "input".
+    stages: Vec<(&'static str, OpcodeCounts)>,
+}
+
+impl OpcodeStats {
+    /// Create an empty set of statistics.
+    pub fn new() -> OpcodeStats {
+        OpcodeStats::default()
+    }
+
+    /// Forget all the accumulated counts.
+    pub fn clear(&mut self) {
+        self.stages.clear();
+    }
+
+    /// Add the instructions in `func` to the counts after `pass`.
+    pub fn record(&mut self, pass: &'static str, func: &Function) {
+        match self.stages.iter().position(|&(name, _)| name == pass) {
+            Some(idx) => self.stages[idx].1.add_function(func),
+            None => self.stages.push((pass, OpcodeCounts::with_function(func))),
+        }
+    }
+
+    /// Get the counts after `pass`, or `"input"` for the counts before the first pass.
+    pub fn get(&self, pass: &str) -> Option<&OpcodeCounts> {
+        self.stages
+            .iter()
+            .find(|&&(name, _)| name == pass)
+            .map(|&(_, ref counts)| counts)
+    }
+
+    /// Get the names of the recorded passes in the order they were first run.
+    pub fn passes(&self) -> Vec<&'static str> {
+        self.stages.iter().map(|&(name, _)| name).collect()
+    }
+}
+
+impl fmt::Display for OpcodeStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut opcodes: Vec<Opcode> = self.stages
+            .iter()
+            .flat_map(|&(_, ref counts)| counts.opcodes())
+            .collect();
+        opcodes.sort_by_key(|op| op.to_string());
+        opcodes.dedup();
+
+        write!(f, "{:<20}", "Opcode")?;
+        for &(name, _) in &self.stages {
+            write!(f, " {:>17}", name)?;
+        }
+        writeln!(f, "")?;
+        for opcode in opcodes {
+            write!(f, "{:<20}", opcode.to_string())?;
+            for &(_, ref counts) in &self.stages {
+                write!(f, " {:>17}", counts.get(opcode))?;
+            }
+            writeln!(f, "")?;
+        }
+        write!(f, "{:<20}", "total")?;
+        for &(_, ref counts) in &self.stages {
+            write!(f, " {:>17}", counts.total())?;
+        }
+        writeln!(f, "")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OpcodeStats;
+    use ir::{Function, Cursor, InstBuilder, Opcode};
+    use ir::types::I32;
+
+    #[test]
+    fn record() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let arg = func.dfg.append_ebb_arg(ebb0, I32);
+        {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            let v = dfg.ins(pos).iadd_imm(arg, 1);
+            let v = dfg.ins(pos).iadd_imm(v, 2);
+            dfg.ins(pos).return_(&[v]);
+        }
+
+        let mut stats = OpcodeStats::new();
+        stats.record("input", &func);
+        stats.record("dce", &func);
+        stats.record("input", &func);
+        assert_eq!(stats.passes(), ["input", "dce"]);
+        let input = stats.get("input").unwrap();
+        assert_eq!(input.get(Opcode::IaddImm), 4);
+        assert_eq!(input.get(Opcode::Return), 2);
+        assert_eq!(input.get(Opcode::Iadd), 0);
+        assert_eq!(input.total(), 6);
+        assert_eq!(stats.get("dce").unwrap().total(), 3);
+        assert!(stats.get("regalloc").is_none());
+
+        assert_eq!(stats.to_string(),
+                   "Opcode                           input               dce\n\
+                    iadd_imm                             4                 2\n\
+                    return                               2                 1\n\
+                    total                                6                 3\n");
+
+        stats.clear();
+        assert!(stats.passes().is_empty());
+    }
+}
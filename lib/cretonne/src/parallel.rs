@@ -0,0 +1,172 @@
+//! Parallel compilation.
+//!
+//! A `TargetIsa` is immutable after it has been built, and it is `Send + Sync` so it can be shared
+//! by many threads. Everything that changes during a compilation lives in a `Context`, so multiple
+//! functions can be compiled concurrently as long as each thread has its own `Context`.
+//!
+//! The `compile_all()` function does exactly that for a batch of functions. It starts a number of
+//! worker threads, each with one `Context` which is cleared and reused for all the functions
+//! compiled by that thread:
+//!
+//! ```
+//! use cretonne::ir::{Function, Cursor, InstBuilder, Signature, ArgumentType};
+//! use cretonne::ir::types::I32;
+//! use cretonne::{isa, settings};
+//! use cretonne::parallel::compile_all;
+//! use std::sync::Arc;
+//!
+//! // Build a function returning its argument plus `n`.
+//! fn add_n(n: i64) -> Function {
+//!     let mut sig = Signature::new();
+//!     sig.argument_types.push(ArgumentType::new(I32));
+//!     sig.return_types.push(ArgumentType::new(I32));
+//!     let mut func = Function::with_name_signature(Default::default(), sig);
+//!     let ebb = func.dfg.make_ebb();
+//!     let arg = func.dfg.append_ebb_arg(ebb, I32);
+//!     {
+//!         let dfg = &mut func.dfg;
+//!         let pos = &mut Cursor::new(&mut func.layout);
+//!         pos.insert_ebb(ebb);
+//!         let sum = dfg.ins(pos).iadd_imm(arg, n);
+//!         dfg.ins(pos).return_(&[sum]);
+//!     }
+//!     func
+//! }
+//!
+//! if let Ok(isa_builder) = isa::lookup("riscv") {
+//!     let isa = Arc::from(isa_builder.finish(settings::Flags::new(&settings::builder())));
+//!     let funcs = (0..100).map(add_n).collect();
+//!     let compiled = compile_all(isa, funcs, 4);
+//!     assert_eq!(compiled.len(), 100);
+//!     assert!(compiled.iter().all(|c| c.is_ok()));
+//! }
+//! ```
+
+use context::Context;
+use ir::Function;
+use isa::TargetIsa;
+use result::CtonError;
+use std::panic;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc;
+use std::thread;
+
+pub use context::CompiledFunction;
+
+/// Compile and emit `funcs` for `isa` using `threads` worker threads.
+///
+/// The results are returned in the same order as `funcs`. The functions are handed out to the
+/// workers one at a time, so a few large functions don't hold up the rest of the batch.
+///
+/// If the compiler panics on a function, the panic is propagated to the caller after all the
+/// workers have stopped.
+pub fn compile_all(isa: Arc<TargetIsa>,
+                   funcs: Vec<Function>,
+                   threads: usize)
+                   -> Vec<Result<CompiledFunction, CtonError>> {
+    let num_funcs = funcs.len();
+    let queue = Arc::new(Mutex::new(funcs.into_iter().enumerate()));
+    let (sender, receiver) = mpsc::channel();
+
+    let workers: Vec<_> = (0..threads.max(1).min(num_funcs))
+        .map(|_| {
+            let isa = isa.clone();
+            let queue = queue.clone();
+            let sender = sender.clone();
+            thread::spawn(move || {
+                let mut ctx = Context::new();
+                loop {
+                    // Don't hold the lock while compiling.
+                    let next = queue.lock().unwrap().next();
+                    let (idx, func) = match next {
+                        Some(work) => work,
+                        None => break,
+                    };
+                    ctx.clear();
+                    ctx.func = func;
+                    sender.send((idx, ctx.compile_and_emit(&*isa))).unwrap();
+                }
+            })
+        })
+        .collect();
+    drop(sender);
+
+    let mut results: Vec<Option<Result<CompiledFunction, CtonError>>> =
+        (0..num_funcs).map(|_| None).collect();
+    for (idx, result) in receiver {
+        results[idx] = Some(result);
+    }
+    for worker in workers {
+        if let Err(payload) = worker.join() {
+            panic::resume_unwind(payload);
+        }
+    }
+    results
+        .into_iter()
+        .map(|r| r.expect("missing compilation result"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::compile_all;
+    use context::Context;
+    use ir::{Function, Cursor, InstBuilder};
+    use isa::{self, TargetIsa};
+    use settings::{self, Configurable};
+    use std::sync::Arc;
+
+    fn assert_send<T: Send>() {}
+    fn assert_sync<T: Sync>() {}
+
+    #[test]
+    fn send_sync() {
+        // Shared between compilation threads.
+        assert_send::<Box<TargetIsa>>();
+        assert_sync::<Box<TargetIsa>>();
+        assert_send::<settings::Flags>();
+        assert_sync::<settings::Flags>();
+
+        // Moved to a compilation thread.
+        assert_send::<Context>();
+        assert_send::<Function>();
+        assert_send::<isa::Builder>();
+    }
+
+    #[test]
+    fn errors() {
+        let mut flag_builder = settings::builder();
+        flag_builder.enable("enable_verifier").unwrap();
+        let isa_builder = isa::lookup("riscv").unwrap();
+        let isa: Arc<TargetIsa> = isa_builder
+            .finish(settings::Flags::new(&flag_builder))
+            .into();
+
+        // Every other function is missing its terminator.
+        let funcs = (0..10)
+            .map(|i| {
+                     let mut func = Function::new();
+                     let ebb = func.dfg.make_ebb();
+                     let dfg = &mut func.dfg;
+                     let pos = &mut Cursor::new(&mut func.layout);
+                     pos.insert_ebb(ebb);
+                     if i % 2 == 0 {
+                         dfg.ins(pos).return_(&[]);
+                     }
+                     func
+                 })
+            .collect();
+
+        let results = compile_all(isa.clone(), funcs, 3);
+        assert_eq!(results.len(), 10);
+        for (i, result) in results.iter().enumerate() {
+            match *result {
+                Ok(ref compiled) => assert!(i % 2 == 0 && !compiled.code.is_empty()),
+                Err(ref e) if e.is_verifier() => assert!(i % 2 == 1),
+                Err(ref e) => panic!("unexpected error: {}", e),
+            }
+        }
+
+        assert!(compile_all(isa, Vec::new(), 4).is_empty());
+    }
+}
@@ -0,0 +1,205 @@
+//! Compilation pipelines.
+//!
+//! A pipeline is the sequence of passes that `Context::run_pipeline()` runs on a function to
+//! prepare it for binary emission. The default pipeline is chosen by the `opt_level` setting, but
+//! it can be replaced by parsing a comma-separated list of pass names:
+//!
+//! ```
+//! use cretonne::pipeline::{Pipeline, Pass};
+//!
+//! let pipeline: Pipeline = "dce,legalize,regalloc".parse().unwrap();
+//! assert_eq!(pipeline.passes(), &[Pass::Dce, Pass::Legalize, Pass::Regalloc]);
+//! assert_eq!(pipeline.to_string(), "dce,legalize,regalloc");
+//! ```
+//!
+//! The `opt_level` setting also selects the `Heuristics` used by the passes, trading compile time
+//! for code quality.
+
+use settings::OptLevel;
+use std::fmt;
+use std::str::FromStr;
+
+/// A compiler pass that can appear in a pipeline.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Pass {
+    /// Fold integer conversion chains and merge extensions into loads.
+    Preopt,
+    /// Dead code elimination.
+    Dce,
+    /// Hoist loop invariant heap bounds checks and remove redundant ones.
+    HeapChecks,
+    /// Legalize instructions for the target ISA.
+    Legalize,
+    /// Move cold EBBs to the end of the layout.
+    EbbLayout,
+    /// Register allocation.
+    Regalloc,
+    /// Insert the prologue and epilogue after register allocation.
+    PrologueEpilogue,
+    /// Renumber the entities in the function densely.
+    Compact,
+}
+
+const ALL_PASSES: [Pass; 8] = [Pass::Preopt,
+                               Pass::Dce,
+                               Pass::HeapChecks,
+                               Pass::Legalize,
+                               Pass::EbbLayout,
+                               Pass::Regalloc,
+                               Pass::PrologueEpilogue,
+                               Pass::Compact];
+
+impl Pass {
+    /// Get the name of this pass as it appears in a pipeline description.
+    pub fn name(self) -> &'static str {
+        match self {
+            Pass::Preopt => "preopt",
+            Pass::Dce => "dce",
+            Pass::HeapChecks => "heap_checks",
+            Pass::Legalize => "legalize",
+            Pass::EbbLayout => "ebb_layout",
+            Pass::Regalloc => "regalloc",
+            Pass::PrologueEpilogue => "prologue_epilogue",
+            Pass::Compact => "compact",
+        }
+    }
+}
+
+impl fmt::Display for Pass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Pass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Pass, String> {
+        ALL_PASSES
+            .iter()
+            .cloned()
+            .find(|pass| pass.name() == s)
+            .ok_or_else(|| format!("unknown pass '{}'", s))
+    }
+}
+
+/// A sequence of passes to run.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Pipeline {
+    passes: Vec<Pass>,
+}
+
+impl Pipeline {
+    /// Create a pipeline running `passes` in order.
+    pub fn new(passes: Vec<Pass>) -> Pipeline {
+        Pipeline { passes: passes }
+    }
+
+    /// Get the default pipeline for `opt_level`.
+    pub fn for_opt_level(opt_level: OptLevel) -> Pipeline {
+        let passes = match opt_level {
+            OptLevel::Fastest => vec![Pass::Legalize, Pass::Regalloc, Pass::PrologueEpilogue],
+            OptLevel::Default | OptLevel::Best => {
+                vec![Pass::Preopt,
+                     Pass::Dce,
+                     Pass::HeapChecks,
+                     Pass::Legalize,
+                     Pass::EbbLayout,
+                     Pass::Regalloc,
+                     Pass::PrologueEpilogue]
+            }
+        };
+        Pipeline::new(passes)
+    }
+
+    /// Get the passes in this pipeline.
+    pub fn passes(&self) -> &[Pass] {
+        &self.passes
+    }
+}
+
+impl fmt::Display for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, pass) in self.passes.iter().enumerate() {
+            if i != 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{}", pass)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Pipeline {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Pipeline, String> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Ok(Pipeline::new(Vec::new()));
+        }
+        s.split(',')
+            .map(|name| name.trim().parse())
+            .collect::<Result<_, _>>()
+            .map(Pipeline::new)
+    }
+}
+
+/// Tuning parameters for the passes that don't affect correctness.
+///
+/// Cretonne compiles one function at a time without an inliner, so there is no inlining budget.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Heuristics {
+    /// The number of existing spill slots to search for one that can be shared by a newly spilled
+    /// value before giving up and creating a new spill slot.
+    ///
+    /// Each candidate slot is checked for interference with all the values already assigned to
+    /// it, so a larger search produces smaller stack frames at a higher compile time cost.
+    pub spill_slot_search: usize,
+}
+
+impl Heuristics {
+    /// Get the heuristics for `opt_level`.
+    pub fn for_opt_level(opt_level: OptLevel) -> Heuristics {
+        match opt_level {
+            OptLevel::Fastest => Heuristics { spill_slot_search: 0 },
+            OptLevel::Default => Heuristics { spill_slot_search: 16 },
+            OptLevel::Best => Heuristics { spill_slot_search: usize::max_value() },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Pipeline, Pass, Heuristics};
+    use settings::OptLevel;
+
+    #[test]
+    fn parse() {
+        let p: Pipeline = " legalize, regalloc ,prologue_epilogue".parse().unwrap();
+        assert_eq!(p.passes(),
+                   &[Pass::Legalize, Pass::Regalloc, Pass::PrologueEpilogue]);
+        assert_eq!("".parse::<Pipeline>(), Ok(Pipeline::new(Vec::new())));
+        assert_eq!("legalize,licm".parse::<Pipeline>(),
+                   Err("unknown pass 'licm'".to_string()));
+        assert_eq!("legalize,,regalloc".parse::<Pipeline>(),
+                   Err("unknown pass ''".to_string()));
+    }
+
+    #[test]
+    fn defaults() {
+        assert_eq!(Pipeline::for_opt_level(OptLevel::Fastest).to_string(),
+                   "legalize,regalloc,prologue_epilogue");
+        assert_eq!(Pipeline::for_opt_level(OptLevel::Default).to_string(),
+                   "preopt,dce,heap_checks,legalize,ebb_layout,regalloc,prologue_epilogue");
+    }
+
+    #[test]
+    fn heuristics() {
+        let fastest = Heuristics::for_opt_level(OptLevel::Fastest);
+        let default = Heuristics::for_opt_level(OptLevel::Default);
+        let best = Heuristics::for_opt_level(OptLevel::Best);
+        assert!(fastest.spill_slot_search < default.spill_slot_search);
+        assert!(default.spill_slot_search < best.spill_slot_search);
+    }
+}
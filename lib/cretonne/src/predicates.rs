@@ -9,6 +9,8 @@
 //! Some of these predicates may be unused in certain ISA configurations, so we suppress the
 //! dead_code warning.
 
+use ir::{DataFlowGraph, FuncRef};
+
 /// Check that `x` is the same as `y`.
 #[allow(dead_code)]
 pub fn is_equal<T: Eq + Copy>(x: T, y: T) -> bool {
@@ -31,6 +33,12 @@ pub fn is_unsigned_int<T: Into<i64>>(x: T, wd: u8, sc: u8) -> bool {
     u == (u & m)
 }
 
+/// Check that `func_ref` refers to a function that is colocated with the caller.
+#[allow(dead_code)]
+pub fn is_colocated_func(func_ref: FuncRef, dfg: &DataFlowGraph) -> bool {
+    dfg.ext_funcs[func_ref].colocated
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
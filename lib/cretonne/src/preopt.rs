@@ -0,0 +1,278 @@
+//! Simplifications before legalization.
+//!
+//! The WebAssembly translator produces many back-to-back integer conversions, like an
+//! `i64.extend_u/i32` of an `i32.load8_u`. This pass folds chains of `uextend`, `sextend`, and
+//! `ireduce` instructions into a single conversion, and merges an extension of a load into an
+//! extending load:
+//!
+//! ```cton
+//!     v1 = uload8.i32 v0
+//!     v2 = uextend.i64 v1
+//!     v3 = sextend.i64 v4
+//!     v5 = ireduce.i32 v3
+//! ```
+//!
+//! becomes:
+//!
+//! ```cton
+//!     v2 = uload8.i64 v0
+//!     v3 = sextend.i64 v4
+//!     v5 -> v4
+//! ```
+//!
+//! The conversions that are bypassed by a fold are left for the `dce` pass to remove. Loads are
+//! only merged when the target ISA can encode the extending load.
+
+use entity_map::EntityMap;
+use ir::{Function, Cursor, DataFlowGraph, Inst, InstructionData, Opcode, Value, ValueDef};
+use ir::types::{I8, I16, I32, I64};
+use isa::TargetIsa;
+
+/// Fold the integer conversion chains in `func` and merge extensions into loads supported by
+/// `isa`.
+pub fn do_preopt(func: &mut Function, isa: &TargetIsa) {
+    // Count the uses of every value in the layout. A load can only be merged into its extension
+    // when the extension is the only use.
+    let mut uses = EntityMap::<Value, u32>::new();
+    let insts: Vec<Inst> = func.layout
+        .ebbs()
+        .flat_map(|ebb| func.layout.ebb_insts(ebb))
+        .collect();
+    for &inst in &insts {
+        for &arg in func.dfg.inst_args(inst) {
+            *uses.ensure(func.dfg.resolve_aliases(arg)) += 1;
+        }
+    }
+
+    for inst in insts {
+        match func.dfg[inst].opcode() {
+            Opcode::Uextend | Opcode::Sextend => {
+                while fold_extend(func, &mut uses, inst) {}
+                merge_load(func, isa, &uses, inst);
+            }
+            Opcode::Ireduce => while fold_reduce(func, &mut uses, inst) {},
+            _ => {}
+        }
+    }
+
+    resolve_aliases(func);
+}
+
+/// Replace the uses of value aliases created by the folds with the original values, since the
+/// later passes don't resolve aliases.
+fn resolve_aliases(func: &mut Function) {
+    let mut pos = Cursor::new(&mut func.layout);
+    while pos.next_ebb().is_some() {
+        while let Some(inst) = pos.next_inst() {
+            for i in 0..func.dfg.inst_args(inst).len() {
+                let arg = func.dfg.resolve_aliases(func.dfg.inst_args(inst)[i]);
+                func.dfg.inst_args_mut(inst)[i] = arg;
+            }
+        }
+    }
+}
+
+/// Get the opcode and the argument of the instruction defining `value` if it is an integer
+/// conversion.
+fn def_conversion(dfg: &DataFlowGraph, value: Value) -> Option<(Opcode, Value)> {
+    if let ValueDef::Res(inst, _) = dfg.value_def(value) {
+        if let InstructionData::Unary { opcode, arg } = dfg[inst] {
+            match opcode {
+                Opcode::Uextend | Opcode::Sextend | Opcode::Ireduce => {
+                    return Some((opcode, dfg.resolve_aliases(arg)))
+                }
+                _ => {}
+            }
+        }
+    }
+    None
+}
+
+/// Get the resolved argument of the conversion `inst`.
+fn conversion_arg(dfg: &DataFlowGraph, inst: Inst) -> Value {
+    dfg.resolve_aliases(dfg.inst_args(inst)[0])
+}
+
+/// Change the conversion `inst` into `opcode` applied to `arg`.
+fn rewrite(func: &mut Function,
+           uses: &mut EntityMap<Value, u32>,
+           inst: Inst,
+           new_opcode: Opcode,
+           new_arg: Value) {
+    let old_arg = conversion_arg(&func.dfg, inst);
+    uses[old_arg] -= 1;
+    *uses.ensure(new_arg) += 1;
+    match func.dfg[inst] {
+        InstructionData::Unary {
+            ref mut opcode,
+            ref mut arg,
+        } => {
+            *opcode = new_opcode;
+            *arg = new_arg;
+        }
+        _ => panic!("Expected a conversion: {}", func.dfg.display_inst(inst)),
+    }
+}
+
+/// Fold the extension `inst` of another extension. Returns true if anything changed.
+fn fold_extend(func: &mut Function, uses: &mut EntityMap<Value, u32>, inst: Inst) -> bool {
+    let opcode = func.dfg[inst].opcode();
+    match def_conversion(&func.dfg, conversion_arg(&func.dfg, inst)) {
+        // A zero-extended value has a clear sign bit, so a sign extension of it is also a zero
+        // extension.
+        Some((Opcode::Uextend, x)) => {
+            rewrite(func, uses, inst, Opcode::Uextend, x);
+            true
+        }
+        Some((Opcode::Sextend, x)) if opcode == Opcode::Sextend => {
+            rewrite(func, uses, inst, Opcode::Sextend, x);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Fold the reduction `inst` of an extension or another reduction. Returns true if `inst` was
+/// changed and can be folded again.
+fn fold_reduce(func: &mut Function, uses: &mut EntityMap<Value, u32>, inst: Inst) -> bool {
+    let ty = func.dfg.ctrl_typevar(inst);
+    match def_conversion(&func.dfg, conversion_arg(&func.dfg, inst)) {
+        Some((Opcode::Ireduce, x)) => {
+            rewrite(func, uses, inst, Opcode::Ireduce, x);
+            true
+        }
+        Some((ext, x)) => {
+            let x_bits = func.dfg.value_type(x).bits();
+            if x_bits == ty.bits() {
+                // The reduction undoes the extension.
+                let old_arg = conversion_arg(&func.dfg, inst);
+                uses[old_arg] -= 1;
+                let result = func.dfg.first_result(inst);
+                let result_uses = uses.get(result).cloned().unwrap_or(0);
+                *uses.ensure(x) += result_uses;
+                func.layout.remove_inst(inst);
+                func.dfg.clear_results(inst);
+                func.dfg.change_to_alias(result, x);
+                false
+            } else if x_bits < ty.bits() {
+                rewrite(func, uses, inst, ext, x);
+                false
+            } else {
+                rewrite(func, uses, inst, Opcode::Ireduce, x);
+                true
+            }
+        }
+        None => false,
+    }
+}
+
+/// Merge the extension `inst` of a load into an extending load if the extension is the only use
+/// of the loaded value and `isa` can encode the extending load.
+///
+/// The load stays where it is, so it isn't reordered with respect to other memory accesses.
+fn merge_load(func: &mut Function, isa: &TargetIsa, uses: &EntityMap<Value, u32>, inst: Inst) {
+    let ext = func.dfg[inst].opcode();
+    let ty = func.dfg.ctrl_typevar(inst);
+    let arg = conversion_arg(&func.dfg, inst);
+    if uses.get(arg).cloned() != Some(1) {
+        return;
+    }
+    let load = match func.dfg.value_def(arg) {
+        ValueDef::Res(load, _) => load,
+        ValueDef::Arg(..) => return,
+    };
+    let load_opcode = match func.dfg[load] {
+        InstructionData::Load { opcode, .. } => opcode,
+        _ => return,
+    };
+
+    let new_opcode = match (load_opcode, func.dfg.value_type(arg)) {
+        (Opcode::Load, I8) if ext == Opcode::Uextend => Opcode::Uload8,
+        (Opcode::Load, I8) => Opcode::Sload8,
+        (Opcode::Load, I16) if ext == Opcode::Uextend => Opcode::Uload16,
+        (Opcode::Load, I16) => Opcode::Sload16,
+        (Opcode::Load, I32) if ty == I64 && ext == Opcode::Uextend => Opcode::Uload32,
+        (Opcode::Load, I32) if ty == I64 => Opcode::Sload32,
+        // Like above, a sign extension of a zero-extended value is a zero extension.
+        (Opcode::Uload8, _) | (Opcode::Uload16, _) => load_opcode,
+        (Opcode::Sload8, _) | (Opcode::Sload16, _) if ext == Opcode::Sextend => load_opcode,
+        _ => return,
+    };
+
+    // Don't create loads that the legalizer would have to expand again, like an `sload32.i64` on
+    // 32-bit Intel.
+    let mut data = func.dfg[load].clone();
+    if let InstructionData::Load { ref mut opcode, .. } = data {
+        *opcode = new_opcode;
+    }
+    if isa.encode(&func.dfg, &data, ty).is_err() {
+        return;
+    }
+
+    // The load takes over the result of the extension.
+    let result = func.dfg.first_result(inst);
+    func.layout.remove_inst(inst);
+    func.dfg.clear_results(inst);
+    func.dfg.clear_results(load);
+    func.dfg.attach_result(load, result);
+    func.dfg[load] = data;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::do_preopt;
+    use ir::{Function, Cursor, InstBuilder, MemFlags, Opcode, ValueDef};
+    use ir::types::{I8, I32, I64};
+    use isa;
+    use settings::{self, Configurable};
+
+    #[test]
+    fn fold() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let p = func.dfg.append_ebb_arg(ebb0, I64);
+        let x = func.dfg.append_ebb_arg(ebb0, I32);
+        let (v0, v1, v2, v3, v4, v5, v6) = {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            let v0 = dfg.ins(pos).uload8(I32, MemFlags::new(), p, 0);
+            let v1 = dfg.ins(pos).uextend(I64, v0);
+            let v2 = dfg.ins(pos).sextend(I64, x);
+            let v3 = dfg.ins(pos).ireduce(I32, v2);
+            let v4 = dfg.ins(pos).ireduce(I8, v2);
+            let v5 = dfg.ins(pos).load(I32, MemFlags::new(), p, 4);
+            let v6 = dfg.ins(pos).sextend(I64, v5);
+            dfg.ins(pos).store(MemFlags::new(), v5, p, 8);
+            dfg.ins(pos).return_(&[v1, v3, v4, v6]);
+            (v0, v1, v2, v3, v4, v5, v6)
+        };
+
+        let mut flag_builder = settings::builder();
+        flag_builder.enable("is_64bit").unwrap();
+        let isa = isa::lookup("intel")
+            .unwrap()
+            .finish(settings::Flags::new(&flag_builder));
+        do_preopt(&mut func, &*isa);
+        let insts: Vec<_> = func.layout.ebb_insts(ebb0).collect();
+        assert_eq!(insts.len(), 7);
+
+        // The extension was merged into the load.
+        assert_eq!(func.dfg.value_def(v1), ValueDef::Res(insts[0], 0));
+        assert_eq!(func.dfg[insts[0]].opcode(), Opcode::Uload8);
+        assert!(!func.dfg.value_is_attached(v0));
+
+        // The reductions skip the extension.
+        assert_eq!(func.dfg.resolve_aliases(v3), x);
+        assert_eq!(func.dfg.value_def(v4), ValueDef::Res(insts[2], 0));
+        assert_eq!(func.dfg[insts[2]].opcode(), Opcode::Ireduce);
+        assert_eq!(func.dfg.inst_args(insts[2]), &[x]);
+        assert_eq!(func.dfg.inst_args(insts[6])[1], x);
+        assert_eq!(func.dfg.value_def(v2), ValueDef::Res(insts[1], 0));
+
+        // The loaded value has another use, so it can't be merged.
+        assert_eq!(func.dfg.value_def(v5), ValueDef::Res(insts[3], 0));
+        assert_eq!(func.dfg[insts[3]].opcode(), Opcode::Load);
+        assert_eq!(func.dfg.value_def(v6), ValueDef::Res(insts[4], 0));
+    }
+}
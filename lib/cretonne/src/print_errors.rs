@@ -0,0 +1,145 @@
+//! Pretty-printing of compilation errors.
+//!
+//! A verifier error only names the offending entity, which isn't very helpful without the IL it
+//! refers to. The functions in this module render an error together with the surrounding IL, so
+//! every embedder can report useful diagnostics.
+
+use ir::{Function, Ebb};
+use ir::entities::AnyEntity;
+use isa::TargetIsa;
+use result::{CtonError, ErrorKind};
+use verifier;
+use write::{write_function, write_ebb_header, write_instruction};
+use std::fmt::{self, Write};
+
+/// Pretty-print a verifier error.
+///
+/// When the error refers to an instruction or an EBB in the layout, the message is followed by
+/// the surrounding EBB with the error repeated in a comment below the offending line. Otherwise
+/// the message is followed by the whole function. Use `isa` to print ISA-dependent annotations.
+pub fn pretty_verifier_error(func: &Function,
+                             isa: Option<&TargetIsa>,
+                             err: verifier::Error)
+                             -> String {
+    let mut msg = err.to_string();
+    msg.push_str("\n\n");
+    let ebb = match err.location {
+        AnyEntity::Inst(inst) => func.layout.inst_ebb(inst),
+        AnyEntity::Ebb(ebb) if func.layout.is_ebb_inserted(ebb) => Some(ebb),
+        _ => None,
+    };
+    match ebb {
+        Some(ebb) => write_ebb_context(&mut msg, func, isa, ebb, &err).unwrap(),
+        None => {
+            // An instruction that isn't in the layout can still be printed on its own.
+            if let AnyEntity::Inst(inst) = err.location {
+                write!(msg, "{}: {}\n\n", inst, func.dfg.display_inst(inst)).unwrap();
+            }
+            write_function(&mut msg, func, isa).unwrap();
+        }
+    }
+    msg
+}
+
+/// Pretty-print a Cretonne error.
+///
+/// Verifier errors are printed with `pretty_verifier_error()`, prefixed by the pass that produced
+/// the invalid function. Other errors are printed as their message.
+pub fn pretty_error(func: &Function, isa: Option<&TargetIsa>, err: CtonError) -> String {
+    if !err.is_verifier() {
+        return err.to_string();
+    }
+    let mut msg = String::new();
+    if let Some(pass) = err.pass {
+        write!(msg, "{}: ", pass).unwrap();
+    }
+    for ctx in err.context.iter().rev() {
+        write!(msg, "{}: ", ctx).unwrap();
+    }
+    if let ErrorKind::Verifier(e) = err.kind {
+        msg.push_str(&pretty_verifier_error(func, isa, e));
+    }
+    msg
+}
+
+// Write `ebb` with the `err` comment below the line it refers to.
+fn write_ebb_context(w: &mut Write,
+                     func: &Function,
+                     isa: Option<&TargetIsa>,
+                     ebb: Ebb,
+                     err: &verifier::Error)
+                     -> fmt::Result {
+    write_ebb_header(w, func, ebb)?;
+    if err.location == AnyEntity::Ebb(ebb) {
+        write_error(w, err)?;
+    }
+    for inst in func.layout.ebb_insts(ebb) {
+        write_instruction(w, func, isa, inst)?;
+        if err.location == AnyEntity::Inst(inst) {
+            write_error(w, err)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_error(w: &mut Write, err: &verifier::Error) -> fmt::Result {
+    writeln!(w, "    ; error: {}", err.message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pretty_verifier_error, pretty_error};
+    use ir::{Function, Cursor, InstBuilder, Ebb, ArgumentType};
+    use ir::entities::AnyEntity;
+    use ir::types::{I32, I64};
+    use result::{CtonError, ErrorKind};
+    use verifier::{self, verify_function};
+
+    #[test]
+    fn inst_context() {
+        let mut func = Function::new();
+        func.signature.argument_types.push(ArgumentType::new(I32));
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        let arg = func.dfg.append_ebb_arg(ebb0, I32);
+        {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            dfg.ins(pos).jump(ebb1, &[]);
+            pos.insert_ebb(ebb1);
+            let wide = dfg.ins(pos).iconst(I64, 1);
+            dfg.ins(pos).iadd(arg, wide);
+            dfg.ins(pos).return_(&[]);
+        }
+
+        let err = verify_function(&func).unwrap_err();
+        let msg = pretty_verifier_error(&func, None, err);
+        assert_eq!(msg,
+                   "inst2: arg 1 (v1) has type i64, expected i32\n\
+                    \n\
+                    ebb1:\n    \
+                        v1 = iconst.i64 1\n    \
+                        v2 = iadd.i32 v0, v1\n    \
+                        ; error: arg 1 (v1) has type i64, expected i32\n    \
+                        return\n");
+    }
+
+    #[test]
+    fn function_context() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        func.layout.append_ebb(ebb0);
+
+        // An EBB that isn't in the layout is printed with the whole function.
+        let err = verifier::Error {
+            location: AnyEntity::Ebb(Ebb::with_number(3).unwrap()),
+            message: "bad".to_string(),
+        };
+        assert_eq!(pretty_error(&func, None, CtonError::from(err).in_pass("dce")),
+                   "dce: ebb3: bad\n\nfunction \"\"() {\nebb0:\n}\n");
+        let err = CtonError::new(ErrorKind::CodeTooLarge).in_pass("relax_branches");
+        assert_eq!(pretty_error(&func, None, err),
+                   "relax_branches: Code for function is too large");
+    }
+}
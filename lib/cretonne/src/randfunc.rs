@@ -0,0 +1,436 @@
+//! Random function generator.
+//!
+//! This module generates random functions that pass the verifier. It is used for property-based
+//! testing of compiler passes, like "DCE preserves the results computed by the interpreter", and
+//! by fuzzers that want to exercise the compiler without spending time on unparsable input.
+//!
+//! The same seed and configuration always produce the same function, so a failing test case can
+//! be reproduced from its seed.
+//!
+//! ```
+//! use cretonne::randfunc::{random_function, Config};
+//! use cretonne::verify_function;
+//!
+//! let func = random_function(17, &Config::default());
+//! assert!(verify_function(&func).is_ok());
+//! ```
+//!
+//! The generated functions only use integer arithmetic on `i32` and `i64` values, comparisons,
+//! and control flow. None of the instructions can trap, and without loops, the functions always
+//! return.
+//!
+//! The instructions are limited to the ones that the legalizer can handle on both 32-bit and
+//! 64-bit targets, so the functions can be compiled for any Intel target:
+//!
+//! - Immediates fit in the type of the instruction, and immediate operands of bitwise
+//!   instructions fit in 32 bits since they can't be expanded when they are wider.
+//! - Multiplications, rotates, and shifts by a variable amount only use `i32` values since their
+//!   `i64` versions need library calls or aren't supported at all on 32-bit targets.
+//! - Every EBB argument is used, and the uses are never dead. The arguments of each EBB are
+//!   folded into the first argument passed to the next EBB, or into the return value.
+
+use ir::{Function, FunctionName, Signature, ArgumentType, Cursor, InstBuilder, Ebb, Value, Type,
+         DataFlowGraph};
+use ir::condcodes::IntCC;
+use ir::types::{I32, I64};
+
+/// Limits on the size and shape of the generated functions.
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Maximum number of EBBs in a function.
+    pub max_ebbs: usize,
+    /// Maximum number of instructions in an EBB, not counting the branches at the end.
+    pub max_insts: usize,
+    /// Maximum number of function arguments.
+    pub max_args: usize,
+    /// Maximum number of arguments to each EBB after the entry block. When it isn't 0, every EBB
+    /// after the entry block has at least one argument so the arguments of the previous EBB can be
+    /// passed along.
+    pub max_ebb_args: usize,
+    /// Allow branches to earlier EBBs. The resulting loops may never terminate.
+    pub loops: bool,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            max_ebbs: 4,
+            max_insts: 12,
+            max_args: 3,
+            max_ebb_args: 3,
+            loops: false,
+        }
+    }
+}
+
+/// A small xorshift* pseudo-random number generator.
+///
+/// It doesn't need any dependencies, and it produces the same numbers on all platforms.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        // The state must never be 0. Mix the seed so small seeds don't start out similar.
+        Rng(seed.wrapping_mul(0x9e3779b97f4a7c15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545f4914f6cdd1d)
+    }
+
+    /// Get a random number in the range `0..n`.
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    /// Get a random number in the range `0..n+1`.
+    fn upto(&mut self, n: usize) -> usize {
+        self.below(n + 1)
+    }
+
+    fn choose<T: Copy>(&mut self, items: &[T]) -> T {
+        items[self.below(items.len())]
+    }
+}
+
+const TYPES: [Type; 2] = [I32, I64];
+
+const CONDS: [IntCC; 10] = [IntCC::Equal,
+                            IntCC::NotEqual,
+                            IntCC::SignedLessThan,
+                            IntCC::SignedGreaterThanOrEqual,
+                            IntCC::SignedGreaterThan,
+                            IntCC::SignedLessThanOrEqual,
+                            IntCC::UnsignedLessThan,
+                            IntCC::UnsignedGreaterThanOrEqual,
+                            IntCC::UnsignedGreaterThan,
+                            IntCC::UnsignedLessThanOrEqual];
+
+/// Generate a random function from `seed`.
+///
+/// The function is named after the seed, and it returns a single integer.
+pub fn random_function(seed: u64, config: &Config) -> Function {
+    let mut rng = Rng::new(seed);
+
+    let mut sig = Signature::new();
+    for _ in 0..rng.upto(config.max_args) {
+        sig.argument_types
+            .push(ArgumentType::new(rng.choose(&TYPES)));
+    }
+    let ret_type = rng.choose(&TYPES);
+    sig.return_types.push(ArgumentType::new(ret_type));
+
+    let mut func = Function::with_name_signature(FunctionName::new(format!("rand{}", seed)), sig);
+
+    // Create all the EBBs up front so branches can refer to any of them.
+    let num_ebbs = 1 + rng.below(config.max_ebbs.max(1));
+    let ebbs: Vec<Ebb> = (0..num_ebbs).map(|_| func.dfg.make_ebb()).collect();
+    for (i, &ebb) in ebbs.iter().enumerate() {
+        func.layout.append_ebb(ebb);
+        if i == 0 {
+            for idx in 0..func.signature.argument_types.len() {
+                let ty = func.signature.argument_types[idx].value_type;
+                func.dfg.append_ebb_arg(ebb, ty);
+            }
+        } else if config.max_ebb_args > 0 {
+            for _ in 0..1 + rng.below(config.max_ebb_args) {
+                func.dfg.append_ebb_arg(ebb, rng.choose(&TYPES));
+            }
+        }
+    }
+
+    // The entry EBB dominates all the others since they are all reachable, so its arguments can
+    // be used everywhere. Other values are only used in the EBB that defines them.
+    let entry_args = func.dfg.ebb_args(ebbs[0]).to_vec();
+
+    // Entry arguments that couldn't be passed to the next EBB because it has no arguments. They
+    // are folded into the return value instead.
+    let mut deferred = Vec::new();
+
+    for (i, &ebb) in ebbs.iter().enumerate() {
+        let dfg = &mut func.dfg;
+        let pos = &mut Cursor::new(&mut func.layout);
+        pos.goto_bottom(ebb);
+
+        let mut gen = EbbGen {
+            rng: &mut rng,
+            avail: entry_args.clone(),
+        };
+        let keep = if i == 0 {
+            entry_args.clone()
+        } else {
+            dfg.ebb_args(ebb).to_vec()
+        };
+        if i != 0 {
+            gen.avail.extend_from_slice(&keep);
+        }
+
+        for _ in 0..gen.rng.upto(config.max_insts) {
+            gen.inst(dfg, pos);
+        }
+
+        // The last EBB returns. The others fall through to the next EBB, and they may branch to
+        // another EBB first. Every EBB is reachable since every EBB falls through to the next.
+        if i + 1 == ebbs.len() {
+            let rval = gen.value(dfg, pos, ret_type);
+            let rval = gen.fold(dfg, pos, rval, &keep);
+            let rval = gen.fold(dfg, pos, rval, &deferred);
+            dfg.ins(pos).return_(&[rval]);
+            continue;
+        }
+
+        if gen.rng.below(2) == 0 {
+            let dest = if config.loops {
+                gen.rng.choose(&ebbs[1..])
+            } else {
+                gen.rng.choose(&ebbs[i + 1..])
+            };
+            let cond = gen.condition(dfg, pos);
+            let args = gen.ebb_args(dfg, pos, dest);
+            dfg.ins(pos).brnz(cond, dest, &args);
+        }
+        let next = ebbs[i + 1];
+        let mut args = gen.ebb_args(dfg, pos, next);
+        match args.first_mut() {
+            Some(first) => *first = gen.fold(dfg, pos, *first, &keep),
+            None => deferred.extend(keep),
+        }
+        dfg.ins(pos).jump(next, &args);
+    }
+
+    func
+}
+
+/// State for generating the instructions in a single EBB.
+struct EbbGen<'a> {
+    rng: &'a mut Rng,
+    /// Integer values that can be used at the current position.
+    avail: Vec<Value>,
+}
+
+impl<'a> EbbGen<'a> {
+    /// Get a value of type `ty`, either an existing value or a new constant.
+    fn value(&mut self, dfg: &mut DataFlowGraph, pos: &mut Cursor, ty: Type) -> Value {
+        let candidates: Vec<Value> = self.avail
+            .iter()
+            .cloned()
+            .filter(|&v| dfg.value_type(v) == ty)
+            .collect();
+        if candidates.is_empty() || self.rng.below(8) == 0 {
+            let imm = self.immediate(ty);
+            let v = dfg.ins(pos).iconst(ty, imm);
+            self.avail.push(v);
+            v
+        } else {
+            self.rng.choose(&candidates)
+        }
+    }
+
+    /// Get a random immediate for type `ty`, biased towards small numbers and edge cases.
+    ///
+    /// Immediates for `i32` are sign-extended from 32 bits.
+    fn immediate(&mut self, ty: Type) -> i64 {
+        let imm = match self.rng.below(4) {
+            0 => self.rng.choose(&[0, 1, -1, i64::min_value(), i64::max_value()]),
+            1 => self.rng.next() as i64,
+            _ => self.rng.below(64) as i64 - 16,
+        };
+        if ty == I32 { imm as i32 as i64 } else { imm }
+    }
+
+    /// Combine the `values` into `acc` so they are all used, and return the new value of `acc`.
+    fn fold(&mut self,
+            dfg: &mut DataFlowGraph,
+            pos: &mut Cursor,
+            mut acc: Value,
+            values: &[Value])
+            -> Value {
+        let ty = dfg.value_type(acc);
+        for &value in values {
+            let value = match (dfg.value_type(value), ty) {
+                (I32, I64) => dfg.ins(pos).uextend(I64, value),
+                (I64, I32) => dfg.ins(pos).ireduce(I32, value),
+                _ => value,
+            };
+            acc = if self.rng.below(2) == 0 {
+                dfg.ins(pos).iadd(acc, value)
+            } else {
+                dfg.ins(pos).bxor(acc, value)
+            };
+        }
+        acc
+    }
+
+    /// Get a `b1` condition value.
+    fn condition(&mut self, dfg: &mut DataFlowGraph, pos: &mut Cursor) -> Value {
+        let ty = self.rng.choose(&TYPES);
+        let x = self.value(dfg, pos, ty);
+        let y = self.value(dfg, pos, ty);
+        let cc = self.rng.choose(&CONDS);
+        dfg.ins(pos).icmp(cc, x, y)
+    }
+
+    /// Get arguments for a branch to `dest`.
+    fn ebb_args(&mut self, dfg: &mut DataFlowGraph, pos: &mut Cursor, dest: Ebb) -> Vec<Value> {
+        let types: Vec<Type> = dfg.ebb_args(dest)
+            .iter()
+            .map(|&v| dfg.value_type(v))
+            .collect();
+        types.into_iter().map(|ty| self.value(dfg, pos, ty)).collect()
+    }
+
+    /// Insert a random instruction.
+    fn inst(&mut self, dfg: &mut DataFlowGraph, pos: &mut Cursor) {
+        let ty = self.rng.choose(&TYPES);
+        let result = match self.rng.below(6) {
+            0 => {
+                let imm = self.immediate(ty);
+                dfg.ins(pos).iconst(ty, imm)
+            }
+            1 | 2 => {
+                // Some of the binary operations are only supported for `i32`.
+                let op = self.rng.below(11);
+                let ty = if op < 5 { ty } else { I32 };
+                let x = self.value(dfg, pos, ty);
+                let y = self.value(dfg, pos, ty);
+                let ins = dfg.ins(pos);
+                match op {
+                    0 => ins.iadd(x, y),
+                    1 => ins.isub(x, y),
+                    2 => ins.band(x, y),
+                    3 => ins.bor(x, y),
+                    4 => ins.bxor(x, y),
+                    5 => ins.imul(x, y),
+                    6 => ins.ishl(x, y),
+                    7 => ins.ushr(x, y),
+                    8 => ins.sshr(x, y),
+                    9 => ins.rotl(x, y),
+                    _ => ins.rotr(x, y),
+                }
+            }
+            3 => {
+                let x = self.value(dfg, pos, ty);
+                let imm = self.immediate(ty);
+                let narrow_imm = self.immediate(I32);
+                let shift = self.rng.below(ty.lane_bits() as usize) as i64;
+                let ins = dfg.ins(pos);
+                match self.rng.below(7) {
+                    0 => ins.iadd_imm(x, imm),
+                    1 => ins.band_imm(x, narrow_imm),
+                    2 => ins.bor_imm(x, narrow_imm),
+                    3 => ins.bxor_imm(x, narrow_imm),
+                    4 => ins.ishl_imm(x, shift),
+                    5 => ins.ushr_imm(x, shift),
+                    _ => ins.sshr_imm(x, shift),
+                }
+            }
+            4 => {
+                // Conversions between the two integer types.
+                if self.rng.below(2) == 0 {
+                    let x = self.value(dfg, pos, I32);
+                    dfg.ins(pos).uextend(I64, x)
+                } else {
+                    let x = self.value(dfg, pos, I64);
+                    dfg.ins(pos).ireduce(I32, x)
+                }
+            }
+            _ => {
+                let c = self.condition(dfg, pos);
+                let x = self.value(dfg, pos, ty);
+                let y = self.value(dfg, pos, ty);
+                dfg.ins(pos).select(c, x, y)
+            }
+        };
+        self.avail.push(result);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{random_function, Config};
+    use dce::do_dce;
+    use ir::{InstructionData, Opcode};
+    use ir::types::{I32, I64};
+    use verifier::verify_function;
+
+    #[test]
+    fn verifies() {
+        let mut config = Config::default();
+        for seed in 0..200 {
+            config.loops = seed % 2 == 1;
+            let mut func = random_function(seed, &config);
+            if let Err(e) = verify_function(&func) {
+                panic!("seed {}: {}\n{}", seed, e, func.display(None));
+            }
+            do_dce(&mut func);
+            if let Err(e) = verify_function(&func) {
+                panic!("seed {} after DCE: {}\n{}", seed, e, func.display(None));
+            }
+        }
+    }
+
+    #[test]
+    fn ebb_args_used() {
+        let config = Config::default();
+        for seed in 0..200 {
+            let mut func = random_function(seed, &config);
+            do_dce(&mut func);
+            for ebb in func.layout.ebbs() {
+                for &arg in func.dfg.ebb_args(ebb) {
+                    let used = func.layout
+                        .ebbs()
+                        .flat_map(|ebb| func.layout.ebb_insts(ebb))
+                        .any(|inst| func.dfg.inst_args(inst).contains(&arg));
+                    assert!(used, "seed {}: {} is unused", seed, arg);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn supported_insts() {
+        let config = Config::default();
+        for seed in 0..200 {
+            let func = random_function(seed, &config);
+            for ebb in func.layout.ebbs() {
+                for inst in func.layout.ebb_insts(ebb) {
+                    let ty = func.dfg.ctrl_typevar(inst);
+                    let text = func.dfg.display_inst(inst).to_string();
+                    match func.dfg[inst] {
+                        InstructionData::UnaryImm { imm, .. } if ty == I32 => {
+                            let imm: i64 = imm.into();
+                            assert_eq!(imm, imm as i32 as i64, "seed {}: {}", seed, text);
+                        }
+                        InstructionData::BinaryImm { opcode, imm, .. } => {
+                            let imm: i64 = imm.into();
+                            if opcode != Opcode::IaddImm || ty == I32 {
+                                assert_eq!(imm, imm as i32 as i64, "seed {}: {}", seed, text);
+                            }
+                        }
+                        InstructionData::Binary { opcode, .. } if ty == I64 => {
+                            match opcode {
+                                Opcode::Iadd | Opcode::Isub | Opcode::Band | Opcode::Bor |
+                                Opcode::Bxor => {}
+                                _ => panic!("seed {}: {}", seed, text),
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn deterministic() {
+        let config = Config::default();
+        let a = random_function(5, &config).display(None).to_string();
+        let b = random_function(5, &config).display(None).to_string();
+        let c = random_function(6, &config).display(None).to_string();
+        assert_eq!(a, b);
+        assert!(a != c);
+    }
+}
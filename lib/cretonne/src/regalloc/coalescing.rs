@@ -0,0 +1,215 @@
+//! Copy coalescing.
+//!
+//! The coalescing pass runs after liveness analysis and before coloring. It identifies pairs of
+//! copy-related SSA values whose live ranges don't interfere and merges them into virtual
+//! registers. The coloring pass then uses the virtual registers as hints, trying to assign the same
+//! register to all the values in a virtual register.
+//!
+//! Two values are copy-related when:
+//!
+//! - One is the argument and the other is the result of a `copy` instruction, or
+//! - One is passed as an EBB argument by a branch and the other is the corresponding EBB argument
+//!   value in the destination EBB.
+//!
+//! When the hints are satisfied, the copies become identity copies where the source and
+//! destination are the same register. Those copies are removed after coloring by
+//! `remove_identity_copies()`.
+//!
+//! # Interference
+//!
+//! Two SSA values interfere if one of them is live after the definition of the other. Since all
+//! the values in a virtual register must be assigned the same location, two virtual registers can
+//! only be merged if none of their members interfere.
+
+use ir::{Function, Value, Inst, Opcode, ValueLoc};
+use ir::instructions::BranchInfo;
+use isa::{TargetIsa, RegInfo};
+use regalloc::RegDiversions;
+use regalloc::affinity::Affinity;
+use regalloc::liveness::Liveness;
+use regalloc::virtregs::VirtRegs;
+use std::mem;
+
+/// Data structures for the coalescing pass.
+///
+/// These are scratch space data structures that can be reused between invocations.
+pub struct Coalescing {
+    /// Pairs of copy-related values to be considered for merging.
+    candidates: Vec<(Value, Value)>,
+
+    /// Identity copy instructions to be removed.
+    copies: Vec<Inst>,
+}
+
+impl Coalescing {
+    /// Allocate scratch space data structures for the coalescing pass.
+    pub fn new() -> Coalescing {
+        Coalescing {
+            candidates: Vec::new(),
+            copies: Vec::new(),
+        }
+    }
+
+    /// Clear all data structures in this coalescing pass.
+    pub fn clear(&mut self) {
+        self.candidates.clear();
+        self.copies.clear();
+    }
+
+    /// Get the number of bytes of heap memory allocated by this pass.
+    pub fn mem_usage(&self) -> usize {
+        self.candidates.capacity() * mem::size_of::<(Value, Value)>() +
+        self.copies.capacity() * mem::size_of::<Inst>()
+    }
+
+    /// Build virtual registers for `func` by merging copy-related values.
+    ///
+    /// The live ranges in `liveness` must be up to date. Values without a register affinity may
+    /// have their affinity updated to match the virtual register they join.
+    pub fn run(&mut self,
+               isa: &TargetIsa,
+               func: &Function,
+               liveness: &mut Liveness,
+               virtregs: &mut VirtRegs) {
+        virtregs.clear();
+        self.find_candidates(func);
+
+        let reginfo = isa.register_info();
+        for &(a, b) in &self.candidates {
+            if virtregs.same_vreg(a, b) {
+                continue;
+            }
+
+            // Values without live ranges aren't used anywhere, so there is nothing to gain.
+            let affinity = match (liveness.get(a), liveness.get(b)) {
+                (Some(lr_a), Some(lr_b)) => {
+                    match merge_affinities(lr_a.affinity, lr_b.affinity, &reginfo) {
+                        Some(affinity) => affinity,
+                        None => continue,
+                    }
+                }
+                _ => continue,
+            };
+
+            if vregs_interfere(a, b, func, liveness, virtregs) {
+                continue;
+            }
+
+            debug!("Coalescing {} and {}", a, b);
+            virtregs.union(a, b);
+
+            // Values without a preference join the preference of the virtual register.
+            for v in virtregs.values(a) {
+                let lr = liveness.get_mut(v).expect("Virtual register value has no live range");
+                if let Affinity::Any = lr.affinity {
+                    lr.affinity = affinity;
+                }
+            }
+        }
+    }
+
+    /// Collect all the pairs of copy-related values in `func`.
+    fn find_candidates(&mut self, func: &Function) {
+        self.candidates.clear();
+        for ebb in func.layout.ebbs() {
+            for inst in func.layout.ebb_insts(ebb) {
+                if func.dfg[inst].opcode() == Opcode::Copy {
+                    let arg = func.dfg.inst_args(inst)[0];
+                    let res = func.dfg.first_result(inst);
+                    self.candidates.push((arg, res));
+                } else if let BranchInfo::SingleDest(dest, args) =
+                    func.dfg[inst].analyze_branch(&func.dfg.value_lists) {
+                    for (&arg, &ebb_arg) in args.iter().zip(func.dfg.ebb_args(dest)) {
+                        self.candidates.push((arg, ebb_arg));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Remove `copy` instructions whose argument and result were assigned the same register.
+    ///
+    /// The result of a removed copy is turned into an alias of the argument, and all uses are
+    /// rewritten to use the argument directly.
+    ///
+    /// This changes the instructions in the function, so any liveness analysis is invalidated.
+    pub fn remove_identity_copies(&mut self, func: &mut Function) {
+        self.copies.clear();
+        let mut divert = RegDiversions::new();
+        for ebb in func.layout.ebbs() {
+            divert.clear();
+            for inst in func.layout.ebb_insts(ebb) {
+                divert.apply(&func.dfg[inst]);
+                if func.dfg[inst].opcode() != Opcode::Copy {
+                    continue;
+                }
+                let arg = func.dfg.inst_args(inst)[0];
+                let res = func.dfg.first_result(inst);
+                // An argument that was moved by a `regmove` isn't in its assigned register.
+                if divert.get(arg, &func.locations) != func.locations[arg] {
+                    continue;
+                }
+                if let (ValueLoc::Reg(src), ValueLoc::Reg(dst)) =
+                    (func.locations[arg], func.locations[res]) {
+                    if src == dst {
+                        self.copies.push(inst);
+                    }
+                }
+            }
+        }
+
+        if self.copies.is_empty() {
+            return;
+        }
+
+        for &inst in &self.copies {
+            let arg = func.dfg.inst_args(inst)[0];
+            let res = func.dfg.first_result(inst);
+            debug!("Removing identity copy {}", func.dfg.display_inst(inst));
+            func.dfg.clear_results(inst);
+            func.dfg.change_to_alias(res, arg);
+            func.layout.remove_inst(inst);
+        }
+
+        // Rewrite all uses of the removed copy results.
+        for ebb in func.layout.ebbs() {
+            for inst in func.layout.ebb_insts(ebb) {
+                for i in 0..func.dfg.inst_args(inst).len() {
+                    let arg = func.dfg.resolve_aliases(func.dfg.inst_args(inst)[i]);
+                    func.dfg.inst_args_mut(inst)[i] = arg;
+                }
+            }
+        }
+    }
+}
+
+/// Merge the affinities of two values that are going to share a location.
+///
+/// Returns `None` if the affinities are incompatible.
+fn merge_affinities(a: Affinity, b: Affinity, reginfo: &RegInfo) -> Option<Affinity> {
+    match (a, b) {
+        (Affinity::Any, x) |
+        (x, Affinity::Any) => Some(x),
+        (Affinity::Stack, Affinity::Stack) => Some(Affinity::Stack),
+        (Affinity::Reg(rc_a), Affinity::Reg(rc_b)) => {
+            reginfo.rc(rc_a).intersect(reginfo.rc(rc_b)).map(Affinity::Reg)
+        }
+        _ => None,
+    }
+}
+
+/// Check if any members of the virtual registers containing `a` and `b` interfere.
+fn vregs_interfere(a: Value,
+                   b: Value,
+                   func: &Function,
+                   liveness: &Liveness,
+                   virtregs: &VirtRegs)
+                   -> bool {
+    virtregs
+        .values(a)
+        .any(|x| {
+                 virtregs
+                     .values(b)
+                     .any(|y| liveness.interferes(x, y, &func.layout))
+             })
+}
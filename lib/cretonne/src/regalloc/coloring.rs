@@ -12,10 +12,14 @@
 //!    register assignments and provides exact constraints.
 //!
 //! 2. Instructions with tied operands must be in a coloring-friendly state. Specifically, the
-//!    values used by the tied operands must be killed by the instruction. This can be achieved by
-//!    inserting a `copy` to a new value immediately before the two-address instruction.
+//!    values used by the tied operands must be killed by the instruction. The `tied_operands`
+//!    pass achieves this by inserting a `copy` to a new value immediately before the two-address
+//!    instruction.
 //!
-//! 3. The register pressure must be lowered sufficiently by inserting spill code. Register
+//! 3. Values used by fixed register operands must be defined by a `copy` in the sequence of copies
+//!    immediately before the instruction. The `tied_operands` pass inserts those copies too.
+//!
+//! 4. The register pressure must be lowered sufficiently by inserting spill code. Register
 //!    operands are allowed to read spilled values, but each such instance must be counted as using
 //!    a register.
 //!
@@ -34,16 +38,59 @@
 //! There are many valid topological orders of the EBBs, and the specific order can affect which
 //! coloring hints are satisfied and which are broken.
 //!
+//! # Stack values
+//!
+//! Values with a stack affinity are assigned a spill slot instead of a register. Spill slots are
+//! shared between values whose live ranges don't overlap, see the `spill_slots` module.
+//!
+//! # Coloring hints
+//!
+//! The coalescing pass groups copy-related values into virtual registers. When a value is colored,
+//! we first try to reuse a register that has already been assigned to another value in the same
+//! virtual register. When that works, the copy between the two values becomes an identity copy
+//! that can be removed.
+//!
+//! # Fixed register operands
+//!
+//! The copy feeding a fixed register operand is colored with the fixed register. If another value
+//! is already in that register, it is evicted with a `regmove` before the copy, and moved back
+//! after the instruction using the fixed register. The ABI registers of call arguments and return
+//! values are handled the same way, see `tied_operands::fixed_arg_reg()`.
+//!
+//! # Clobbered registers
+//!
+//! Values that are live across an instruction are evicted the same way from the registers it
+//! clobbers, like the caller-saved registers clobbered by calls and by the `__tls_get_addr` call
+//! in the general-dynamic TLS sequence. See `TargetIsa::inst_clobbers()`.
+//!
+//! The arguments to the entry block stay in their ABI registers. This includes the link register
+//! argument, so it is evicted from the link register like any other value.
+//!
+//! # Branch arguments
+//!
+//! The coloring hints from the coalescing pass can't always be satisfied, so the arguments passed
+//! by a branch are moved into the registers of the destination EBB arguments with `regmove`
+//! instructions before the branch. When the destination EBB hasn't been visited yet, its arguments
+//! are simply assigned the registers of the branch arguments. The `tied_operands` pass makes sure
+//! that every branch argument is a distinct value killed by the branch, so the moved values never
+//! need to be moved back.
 
 use entity_map::EntityMap;
 use dominator_tree::DominatorTree;
-use ir::{Ebb, Inst, Value, Function, Cursor, ValueLoc, DataFlowGraph};
-use isa::{TargetIsa, RegInfo, Encoding, EncInfo, ConstraintKind};
+use ir::{Ebb, Inst, InstBuilder, Opcode, Value, Function, Cursor, ValueLoc, DataFlowGraph,
+         Layout};
+use ir::{ArgumentLoc, Signature, StackSlot, StackSlotData, StackSlotKind};
+use ir::instructions::BranchInfo;
+use isa::{TargetIsa, RegInfo, RegClass, RegUnit, Encoding, EncInfo, ConstraintKind};
 use regalloc::affinity::Affinity;
 use regalloc::allocatable_set::AllocatableSet;
 use regalloc::live_value_tracker::{LiveValue, LiveValueTracker};
 use regalloc::liveness::Liveness;
+use regalloc::spill_slots::SpillSlots;
+use regalloc::tied_operands::fixed_arg_reg;
+use regalloc::virtregs::VirtRegs;
 use sparse_map::SparseSet;
+use std::mem;
 
 
 /// Data structures for the coloring pass.
@@ -66,6 +113,8 @@ pub struct Coloring {
 /// Immutable context information and mutable references that don't need to be borrowed across
 /// method calls should go in this struct.
 struct Context<'a> {
+    isa: &'a TargetIsa,
+
     // Cached ISA information.
     // We save it here to avoid frequent virtual function calls on the `TargetIsa` trait object.
     reginfo: RegInfo,
@@ -74,10 +123,24 @@ struct Context<'a> {
     // References to contextual data structures we need.
     domtree: &'a DominatorTree,
     liveness: &'a mut Liveness,
+    virtregs: &'a VirtRegs,
+    spill_slots: &'a mut SpillSlots,
 
     // Pristine set of registers that the allocator can use.
     // This set remains immutable, we make clones.
     usable_regs: AllocatableSet,
+
+    // Values that were evicted from a fixed register and must be moved back after the instruction
+    // using the fixed register.
+    evicted: Vec<Eviction>,
+}
+
+/// A value temporarily moved out of a register needed by a fixed register operand.
+struct Eviction {
+    value: Value,
+    regclass: RegClass,
+    from: RegUnit,
+    to: RegUnit,
 }
 
 impl Coloring {
@@ -89,28 +152,81 @@ impl Coloring {
         }
     }
 
+    /// Clear all data structures in this coloring pass.
+    pub fn clear(&mut self) {
+        self.visited.clear();
+        self.stack.clear();
+    }
+
+    /// Get the number of bytes of heap memory allocated by this pass.
+    pub fn mem_usage(&self) -> usize {
+        self.visited.mem_usage() + self.stack.capacity() * mem::size_of::<Ebb>()
+    }
+
     /// Run the coloring algorithm over `func`.
     pub fn run(&mut self,
                isa: &TargetIsa,
                func: &mut Function,
                domtree: &DominatorTree,
                liveness: &mut Liveness,
+               virtregs: &VirtRegs,
+               spill_slots: &mut SpillSlots,
                tracker: &mut LiveValueTracker) {
+        let reginfo = isa.register_info();
+        let usable_regs = usable_regs(isa, &reginfo);
         let mut ctx = Context {
-            reginfo: isa.register_info(),
+            isa: isa,
+            reginfo: reginfo,
             encinfo: isa.encoding_info(),
             domtree: domtree,
             liveness: liveness,
-            // TODO: Ask the target ISA about reserved registers etc.
-            usable_regs: AllocatableSet::new(),
+            virtregs: virtregs,
+            spill_slots: spill_slots,
+            usable_regs: usable_regs,
+            evicted: Vec::new(),
         };
         ctx.run(self, func, tracker)
     }
 }
 
+/// Has `value` been assigned to an outgoing argument stack slot?
+fn is_outgoing_arg(value: Value,
+                   locations: &EntityMap<Value, ValueLoc>,
+                   stack_slots: &EntityMap<StackSlot, StackSlotData>)
+                   -> bool {
+    match locations.get(value) {
+        Some(&ValueLoc::Stack(ss)) => stack_slots[ss].kind == StackSlotKind::OutgoingArg,
+        _ => false,
+    }
+}
+
+/// Get the set of registers that the register allocator can use for `isa`.
+///
+/// This excludes the ISA's reserved registers and the pinned register, if the ISA has one.
+pub fn usable_regs(isa: &TargetIsa, reginfo: &RegInfo) -> AllocatableSet {
+    let mut regs = AllocatableSet::new();
+    for &reg in isa.reserved_regs().iter().chain(isa.pinned_reg().iter()) {
+        let rc = reginfo
+            .toprc_containing(reg)
+            .expect("Reserved register is not in any register class");
+        regs.take(rc, reg);
+    }
+    regs
+}
+
 impl<'a> Context<'a> {
     /// Run the coloring algorithm.
     fn run(&mut self, data: &mut Coloring, func: &mut Function, tracker: &mut LiveValueTracker) {
+        // Branches assign registers to the arguments of EBBs that haven't been visited, so start
+        // out without any.
+        for ebb in func.layout.ebbs().skip(1) {
+            for &arg in func.dfg.ebb_args(ebb) {
+                if func.locations.is_valid(arg) {
+                    func.locations[arg] = ValueLoc::Unassigned;
+                }
+            }
+        }
+
         // Just visit blocks in layout order, letting `process_ebb` enforce a topological ordering.
         // TODO: Once we have a loop tree, we could visit hot blocks first.
         let mut next = func.layout.entry_block();
@@ -159,11 +275,14 @@ impl<'a> Context<'a> {
             assert!(encoding.is_legal(), "Illegal: {}", func.dfg[inst].opcode());
             self.visit_inst(inst,
                             encoding,
+                            &func.signature,
                             &mut pos,
                             &mut func.dfg,
                             tracker,
                             &mut regs,
-                            &mut func.locations);
+                            &mut func.encodings,
+                            &mut func.locations,
+                            &mut func.stack_slots);
             tracker.drop_dead(inst);
         }
 
@@ -172,7 +291,7 @@ impl<'a> Context<'a> {
     /// Visit the `ebb` header.
     ///
     /// Initialize the set of live registers and color the arguments to `ebb`.
-    fn visit_ebb_header(&self,
+    fn visit_ebb_header(&mut self,
                         ebb: Ebb,
                         func: &mut Function,
                         tracker: &mut LiveValueTracker)
@@ -184,9 +303,12 @@ impl<'a> Context<'a> {
         // The live-ins have already been assigned a register. Reconstruct the allocatable set.
         let mut regs = self.livein_regs(liveins, func);
 
-        // TODO: Arguments to the entry block are pre-colored by the ABI. We should probably call
-        // a whole other function for that case.
-        self.color_args(args, &mut regs, &mut func.locations);
+        // Arguments to the entry block are pre-colored by the ABI.
+        if func.layout.entry_block() == Some(ebb) {
+            self.color_entry_args(ebb, args, &mut regs, func);
+        } else {
+            self.color_args(args, &mut regs, func);
+        }
 
         regs
     }
@@ -204,10 +326,10 @@ impl<'a> Context<'a> {
                 .get(value)
                 .expect("No live range for live-in")
                 .affinity;
-            if let Affinity::Reg(rc_index) = affinity {
-                let regclass = self.reginfo.rc(rc_index);
+            if let Some(regclass) = self.live_regclass(value, affinity, &func.locations) {
                 match func.locations[value] {
-                    ValueLoc::Reg(regunit) => regs.take(regclass, regunit),
+                    ValueLoc::Reg(regunit) |
+                    ValueLoc::RegPair(regunit) => regs.take(regclass, regunit),
                     ValueLoc::Unassigned => panic!("Live-in {} wasn't assigned", value),
                     ValueLoc::Stack(ss) => {
                         panic!("Live-in {} is in {}, should be register", value, ss)
@@ -219,44 +341,396 @@ impl<'a> Context<'a> {
         regs
     }
 
+    /// Color the live arguments to the entry block.
+    ///
+    /// Arguments passed in registers stay in their ABI registers, including special arguments like
+    /// the link register that don't have a register affinity. The remaining arguments are colored
+    /// like the arguments to any other EBB.
+    fn color_entry_args(&mut self,
+                        ebb: Ebb,
+                        args: &[LiveValue],
+                        regs: &mut AllocatableSet,
+                        func: &mut Function) {
+        // Take the ABI registers first so the other arguments can't be colored into them.
+        let mut others = Vec::new();
+        for (i, lv) in args.iter().enumerate() {
+            let abi_index = func.dfg
+                .ebb_args(ebb)
+                .iter()
+                .position(|&arg| arg == lv.value)
+                .expect("Not an entry block argument");
+            let reg = match func.signature.argument_types[abi_index].location {
+                ArgumentLoc::Reg(reg) => reg,
+                _ => {
+                    others.push(i);
+                    continue;
+                }
+            };
+            let regclass = match lv.affinity {
+                Affinity::Reg(rc_index) if self.reginfo.rc(rc_index).contains(reg) => {
+                    self.reginfo.rc(rc_index)
+                }
+                _ => {
+                    self.reginfo
+                        .toprc_containing(reg)
+                        .expect("ABI register is not in any register class")
+                }
+            };
+            regs.take(regclass, reg);
+            *func.locations.ensure(lv.value) = ValueLoc::Reg(reg);
+        }
+        for i in others {
+            self.color_arg(&args[i], regs, func);
+        }
+    }
+
     /// Color the live arguments to the current block.
     ///
     /// It is assumed that any live-in register values have already been taken out of the register
     /// set.
-    fn color_args(&self,
-                  args: &[LiveValue],
-                  regs: &mut AllocatableSet,
-                  locations: &mut EntityMap<Value, ValueLoc>) {
+    fn color_args(&mut self, args: &[LiveValue], regs: &mut AllocatableSet, func: &mut Function) {
         for lv in args {
-            // Only look at the register arguments.
-            if let Affinity::Reg(rc_index) = lv.affinity {
+            self.color_arg(lv, regs, func);
+        }
+    }
+
+    /// Color a single live block argument.
+    fn color_arg(&mut self, lv: &LiveValue, regs: &mut AllocatableSet, func: &mut Function) {
+        match lv.affinity {
+            Affinity::Reg(rc_index) => {
                 let regclass = self.reginfo.rc(rc_index);
+                // An argument that was assigned a register by a branch visited earlier keeps it.
                 // TODO: Fall back to a top-level super-class. Sub-classes are only hints.
-                let regunit = regs.iter(regclass)
-                    .next()
+                let regunit = match func.locations.get(lv.value) {
+                        Some(&ValueLoc::Reg(reg)) => Some(reg),
+                        _ => None,
+                    }
+                    .or_else(|| self.vreg_hint(lv.value, regclass, regs, &func.locations))
+                    .or_else(|| regs.iter(regclass).next())
                     .expect("Out of registers for arguments");
                 regs.take(regclass, regunit);
-                *locations.ensure(lv.value) = ValueLoc::Reg(regunit);
+                *func.locations.ensure(lv.value) = regclass.location(regunit);
+            }
+            Affinity::Stack => {
+                let ss = self.spill_slot(lv.value,
+                                         &func.dfg,
+                                         &mut func.stack_slots,
+                                         &func.layout);
+                *func.locations.ensure(lv.value) = ValueLoc::Stack(ss);
+            }
+            Affinity::Any => {}
+        }
+    }
+
+    /// Assign a spill slot to `value`, sharing an existing slot if possible.
+    fn spill_slot(&mut self,
+                  value: Value,
+                  dfg: &DataFlowGraph,
+                  stack_slots: &mut EntityMap<StackSlot, StackSlotData>,
+                  layout: &Layout)
+                  -> StackSlot {
+        let size = dfg.value_type(value).bytes();
+        self.spill_slots
+            .assign(value, size, stack_slots, self.liveness, layout)
+    }
+
+    /// Find a register for `value` that is already used by another value in the same virtual
+    /// register.
+    ///
+    /// Only registers in `rc` that are still available in `regs` are considered.
+    fn vreg_hint(&self,
+                 value: Value,
+                 rc: RegClass,
+                 regs: &AllocatableSet,
+                 locations: &EntityMap<Value, ValueLoc>)
+                 -> Option<RegUnit> {
+        self.virtregs
+            .values(value)
+            .filter_map(|v| locations.get(v).and_then(|loc| loc.regunit()))
+            .find(|&regunit| rc.contains(regunit) && regs.is_avail(rc, regunit))
+    }
+
+    /// Get the register currently holding `value`, taking evictions into account.
+    fn current_reg(&self,
+                   value: Value,
+                   locations: &EntityMap<Value, ValueLoc>)
+                   -> Option<RegUnit> {
+        match self.evicted.iter().find(|ev| ev.value == value) {
+            Some(ev) => Some(ev.to),
+            None => locations[value].regunit(),
+        }
+    }
+
+    /// Get the fixed register that the `copy` instruction `inst` should write.
+    ///
+    /// This is the fixed register of the operand using the copied value in the first instruction
+    /// following the sequence of copies that `inst` belongs to.
+    fn fixed_copy_reg(&self,
+                      inst: Inst,
+                      sig: &Signature,
+                      pos: &mut Cursor,
+                      dfg: &DataFlowGraph,
+                      encodings: &EntityMap<Inst, Encoding>)
+                      -> Option<RegUnit> {
+        let value = dfg.first_result(inst);
+        let here = pos.position();
+        pos.goto_inst(inst);
+        let mut user = pos.next_inst();
+        while let Some(next) = user {
+            if dfg[next].opcode() != Opcode::Copy {
+                break;
+            }
+            user = pos.next_inst();
+        }
+        pos.set_position(here);
+        let user = match user {
+            Some(user) => user,
+            None => return None,
+        };
+        let constraints = match self.encinfo.operand_constraints(encodings[user]) {
+            Some(constraints) => constraints,
+            None => return None,
+        };
+        dfg.inst_args(user)
+            .iter()
+            .position(|&arg| arg == value)
+            .and_then(|arg_index| fixed_arg_reg(dfg, sig, user, constraints, arg_index))
+    }
+
+    /// Get the register class of a live value that is in a register.
+    ///
+    /// Values without a register affinity, like the link register argument, can still be pinned
+    /// to their ABI register. They use the top-level register class containing it.
+    fn live_regclass(&self,
+                     value: Value,
+                     affinity: Affinity,
+                     locations: &EntityMap<Value, ValueLoc>)
+                     -> Option<RegClass> {
+        match affinity {
+            Affinity::Reg(rc_index) => Some(self.reginfo.rc(rc_index)),
+            Affinity::Stack => None,
+            Affinity::Any => {
+                match locations.get(value) {
+                    Some(&ValueLoc::Reg(reg)) => self.reginfo.toprc_containing(reg),
+                    _ => None,
+                }
             }
         }
     }
 
+    /// Find the live value that is currently in `reg`.
+    fn reg_occupant(&self,
+                    reg: RegUnit,
+                    tracker: &LiveValueTracker,
+                    locations: &EntityMap<Value, ValueLoc>)
+                    -> Option<(Value, RegClass)> {
+        tracker
+            .live()
+            .iter()
+            .filter_map(|lv| {
+                            self.live_regclass(lv.value, lv.affinity, locations)
+                                .map(|rc| (lv.value, rc))
+                        })
+            .find(|&(value, _)| self.current_reg(value, locations) == Some(reg))
+    }
+
+    /// Make `reg` available by evicting `occupant` from it.
+    ///
+    /// The evicted value is moved to another register in `regclass` that isn't in `avoid` with a
+    /// `regmove` before the instruction at `pos`. Returns false if there is no free register for
+    /// the evicted value.
+    fn evict(&mut self,
+             occupant: Value,
+             regclass: RegClass,
+             reg: RegUnit,
+             avoid: &[RegUnit],
+             pos: &mut Cursor,
+             dfg: &mut DataFlowGraph,
+             regs: &mut AllocatableSet,
+             encodings: &mut EntityMap<Inst, Encoding>)
+             -> bool {
+        let to = match regs.iter(regclass).find(|r| !avoid.contains(r)) {
+            Some(to) => to,
+            None => return false,
+        };
+        regs.take(regclass, to);
+
+        let regmove = dfg.ins(pos).regmove(occupant, reg, to);
+        encode_regmove(self.isa, regmove, occupant, dfg, encodings);
+        debug!("Evicted {} from {}", occupant, reg);
+
+        // A value that is evicted again still returns to its original register.
+        if let Some(ev) = self.evicted.iter_mut().find(|ev| ev.value == occupant) {
+            ev.to = to;
+            return true;
+        }
+        self.evicted
+            .push(Eviction {
+                      value: occupant,
+                      regclass: regclass,
+                      from: reg,
+                      to: to,
+                  });
+        true
+    }
+
+    /// Move the live value `value` to `to` with a `regmove` before the instruction at `pos`.
+    ///
+    /// The move is recorded as an eviction, so the value is moved back after the instruction if it
+    /// is still live.
+    fn move_value(&mut self,
+                  value: Value,
+                  regclass: RegClass,
+                  to: RegUnit,
+                  pos: &mut Cursor,
+                  dfg: &mut DataFlowGraph,
+                  regs: &mut AllocatableSet,
+                  encodings: &mut EntityMap<Inst, Encoding>,
+                  locations: &EntityMap<Value, ValueLoc>) {
+        let from = self.current_reg(value, locations)
+            .expect("Moved value is not in a register");
+        if regs.is_avail(regclass, to) {
+            regs.take(regclass, to);
+        }
+        regs.free(regclass, from);
+
+        let regmove = dfg.ins(pos).regmove(value, from, to);
+        encode_regmove(self.isa, regmove, value, dfg, encodings);
+
+        if let Some(ev) = self.evicted.iter_mut().find(|ev| ev.value == value) {
+            ev.to = to;
+            return;
+        }
+        self.evicted
+            .push(Eviction {
+                      value: value,
+                      regclass: regclass,
+                      from: from,
+                      to: to,
+                  });
+    }
+
+    /// Move the arguments passed by the branch `inst` into the registers of the destination EBB
+    /// arguments.
+    ///
+    /// The arguments of a destination EBB that hasn't been visited yet are assigned the registers
+    /// of the branch arguments when possible. Other values in the destination registers are
+    /// evicted. Returns true if any values were moved.
+    fn shuffle_branch_args(&mut self,
+                           inst: Inst,
+                           pos: &mut Cursor,
+                           dfg: &mut DataFlowGraph,
+                           tracker: &LiveValueTracker,
+                           regs: &mut AllocatableSet,
+                           encodings: &mut EntityMap<Inst, Encoding>,
+                           locations: &mut EntityMap<Value, ValueLoc>)
+                           -> bool {
+        let (dest, args) = match dfg[inst].analyze_branch(&dfg.value_lists) {
+            BranchInfo::SingleDest(dest, args) => (dest, args.to_vec()),
+            _ => return false,
+        };
+
+        // Pick the register of each EBB argument, and collect the moves as `(value, rc, to)`.
+        let mut moves: Vec<(Value, RegClass, RegUnit)> = Vec::new();
+        for (&arg, &ebb_arg) in args.iter().zip(dfg.ebb_args(dest)) {
+            let regclass = match self.liveness.get(ebb_arg).map(|lr| lr.affinity) {
+                Some(Affinity::Reg(rc_index)) => self.reginfo.rc(rc_index),
+                _ => continue,
+            };
+            let from = match self.current_reg(arg, locations) {
+                Some(reg) => reg,
+                None => continue,
+            };
+            let to = match locations.get(ebb_arg) {
+                Some(&ValueLoc::Reg(reg)) => reg,
+                _ => {
+                    let to = if regclass.contains(from) {
+                        from
+                    } else {
+                        regs.iter(regclass)
+                            .find(|&r| moves.iter().all(|&(_, _, to)| to != r))
+                            .expect("Out of registers for EBB arguments")
+                    };
+                    *locations.ensure(ebb_arg) = regclass.location(to);
+                    to
+                }
+            };
+            if from != to {
+                moves.push((arg, regclass, to));
+            }
+        }
+        if moves.is_empty() {
+            return false;
+        }
+        let targets: Vec<RegUnit> = moves.iter().map(|&(_, _, to)| to).collect();
+
+        // Evict the values that aren't branch arguments from the destination registers.
+        for &to in &targets {
+            if let Some((value, rc)) = self.reg_occupant(to, tracker, locations) {
+                if args.contains(&value) {
+                    continue;
+                }
+                if !self.evict(value, rc, to, &targets, pos, dfg, regs, encodings) {
+                    panic!("Out of registers for evicting {} from a branch argument", value);
+                }
+            }
+        }
+
+        // Move the arguments whose destination register is free, and break any cycles by moving
+        // an argument to a scratch register.
+        while !moves.is_empty() {
+            let ready = moves
+                .iter()
+                .position(|&(_, _, to)| {
+                              moves
+                                  .iter()
+                                  .all(|&(v, _, _)| self.current_reg(v, locations) != Some(to))
+                          });
+            match ready {
+                Some(i) => {
+                    let (value, regclass, to) = moves.swap_remove(i);
+                    self.move_value(value, regclass, to, pos, dfg, regs, encodings, locations);
+                }
+                None => {
+                    let (value, regclass, _) = moves[0];
+                    let scratch = regs.iter(regclass)
+                        .find(|r| !targets.contains(r))
+                        .expect("No scratch register for branch arguments");
+                    self.move_value(value, regclass, scratch, pos, dfg, regs, encodings, locations);
+                }
+            }
+        }
+        true
+    }
+
     /// Color the values defined by `inst` and insert any necessary shuffle code to satisfy
     /// instruction constraints.
     ///
     /// Update `regs` to reflect the allocated registers after `inst`, including removing any dead
     /// or killed values from the set.
-    fn visit_inst(&self,
+    fn visit_inst(&mut self,
                   inst: Inst,
                   encoding: Encoding,
-                  _pos: &mut Cursor,
+                  sig: &Signature,
+                  pos: &mut Cursor,
                   dfg: &mut DataFlowGraph,
                   tracker: &mut LiveValueTracker,
                   regs: &mut AllocatableSet,
-                  locations: &mut EntityMap<Value, ValueLoc>) {
-        // First update the live value tracker with this instruction.
-        // Get lists of values that are killed and defined by `inst`.
-        let (kills, defs) = tracker.process_inst(inst, dfg, self.liveness);
+                  encodings: &mut EntityMap<Inst, Encoding>,
+                  locations: &mut EntityMap<Value, ValueLoc>,
+                  stack_slots: &mut EntityMap<StackSlot, StackSlotData>) {
+        // A copy feeding a fixed register operand should go in that register. Find the value
+        // currently in the register before the tracker forgets the values killed here.
+        let fixed = if dfg[inst].opcode() == Opcode::Copy {
+            self.fixed_copy_reg(inst, sig, pos, dfg, encodings)
+                .map(|reg| (reg, self.reg_occupant(reg, tracker, locations)))
+        } else {
+            None
+        };
+
+        // Move the branch arguments into the registers of the destination EBB arguments.
+        let shuffled =
+            self.shuffle_branch_args(inst, pos, dfg, tracker, regs, encodings, locations);
 
         // Get the operand constraints for `inst` that we are trying to satisfy.
         let constraints = self.encinfo
@@ -264,16 +738,67 @@ impl<'a> Context<'a> {
             .expect("Missing instruction encoding")
             .clone();
 
+        // Move the values that are live across `inst` out of the registers it clobbers.
+        let clobbers = self.isa.inst_clobbers(dfg, inst, encoding);
+        for lv in tracker.live() {
+            if lv.endpoint == inst {
+                continue;
+            }
+            if let Some(regclass) = self.live_regclass(lv.value, lv.affinity, locations) {
+                match self.current_reg(lv.value, locations) {
+                    Some(reg) if clobbers.contains(&reg) => {
+                        // A value that was already evicted to `reg` doesn't need it afterwards.
+                        let again = self.evicted.iter().any(|ev| ev.value == lv.value);
+                        if self.evict(lv.value,
+                                      regclass,
+                                      reg,
+                                      &clobbers,
+                                      pos,
+                                      dfg,
+                                      regs,
+                                      encodings) && again {
+                            regs.free(regclass, reg);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        // First update the live value tracker with this instruction.
+        // Get lists of values that are killed and defined by `inst`.
+        let (kills, defs) = tracker.process_inst(inst, dfg, self.liveness);
+
         // Get rid of the killed values.
         for lv in kills {
-            if let Affinity::Reg(rc_index) = lv.affinity {
-                let regclass = self.reginfo.rc(rc_index);
-                if let ValueLoc::Reg(regunit) = locations[lv.value] {
+            if let Some(regclass) = self.live_regclass(lv.value, lv.affinity, locations) {
+                if let Some(regunit) = self.current_reg(lv.value, locations) {
                     regs.free(regclass, regunit);
                 }
             }
         }
 
+        // Evicted values that are still live return to their registers after an instruction with
+        // fixed register operands or branch arguments. The values moved into those registers were
+        // killed above, but the registers of values evicted by a clobber were never released.
+        let uses_fixed = shuffled ||
+                         (0..dfg.inst_args(inst).len()).any(|arg_index| {
+                                                                fixed_arg_reg(dfg,
+                                                                              sig,
+                                                                              inst,
+                                                                              &constraints,
+                                                                              arg_index)
+                                                                        .is_some()
+                                                            });
+        if uses_fixed {
+            for ev in &self.evicted {
+                if kills.iter().all(|lv| lv.value != ev.value) &&
+                   regs.is_avail(ev.regclass, ev.from) {
+                    regs.take(ev.regclass, ev.from);
+                }
+            }
+        }
+
         // Process the defined values with fixed constraints.
         // TODO: Handle constraints on call return values.
         assert_eq!(defs.len(),
@@ -296,24 +821,45 @@ impl<'a> Context<'a> {
                                     lv.value,
                                     pref_rc.name,
                                     opcst.regclass.name);
+                            // A copy feeding a fixed register operand goes in that register.
+                            // The register of an evicted value is handed over to the copy.
+                            if let Some((reg, occupant)) = fixed {
+                                let taken = if !opcst.regclass.contains(reg) {
+                                    false
+                                } else if regs.is_avail(opcst.regclass, reg) {
+                                    regs.take(opcst.regclass, reg);
+                                    true
+                                } else {
+                                    occupant.map_or(false, |(value, rc)| {
+                                        self.evict(value, rc, reg, &[], pos, dfg, regs, encodings)
+                                    })
+                                };
+                                if taken {
+                                    *locations.ensure(lv.value) = opcst.regclass.location(reg);
+                                    continue;
+                                }
+                            }
                             // Try to grab a register from the preferred class, but fall back to
                             // the actual constraint if we have to.
-                            let regunit = regs.iter(pref_rc)
-                                .next()
+                            let regunit = self.vreg_hint(lv.value, pref_rc, regs, locations)
+                                .or_else(|| regs.iter(pref_rc).next())
                                 .or_else(|| regs.iter(opcst.regclass).next())
                                 .expect("Ran out of registers");
                             regs.take(opcst.regclass, regunit);
-                            *locations.ensure(lv.value) = ValueLoc::Reg(regunit);
+                            *locations.ensure(lv.value) = opcst.regclass.location(regunit);
                         }
                         ConstraintKind::Tied(arg_index) => {
                             // This def must use the same register as a fixed instruction argument.
                             let arg = dfg.inst_args(inst)[arg_index as usize];
-                            let loc = locations[arg];
+                            let loc = match self.current_reg(arg, locations) {
+                                Some(regunit) => opcst.regclass.location(regunit),
+                                None => locations[arg],
+                            };
                             *locations.ensure(lv.value) = loc;
                             // Mark the reused register. It's not really clear if we support tied
                             // stack operands. We could do that for some Intel read-modify-write
                             // encodings.
-                            if let ValueLoc::Reg(regunit) = loc {
+                            if let Some(regunit) = loc.regunit() {
                                 // This is going to assert out unless the incoming value at
                                 // `arg_index` was killed. Tied operands must be fixed to
                                 // ensure that before running the coloring pass.
@@ -326,7 +872,13 @@ impl<'a> Context<'a> {
                         }
                     }
                 }
-                Affinity::Stack => unimplemented!(),
+                Affinity::Stack => {
+                    // Outgoing call arguments were assigned their stack slots by the legalizer.
+                    if !is_outgoing_arg(lv.value, locations, stack_slots) {
+                        let ss = self.spill_slot(lv.value, dfg, stack_slots, pos.layout);
+                        *locations.ensure(lv.value) = ValueLoc::Stack(ss);
+                    }
+                }
                 Affinity::Any => unimplemented!(),
             }
         }
@@ -336,11 +888,39 @@ impl<'a> Context<'a> {
             if lv.endpoint == inst {
                 if let Affinity::Reg(rc_index) = lv.affinity {
                     let regclass = self.reginfo.rc(rc_index);
-                    if let ValueLoc::Reg(regunit) = locations[lv.value] {
+                    if let Some(regunit) = locations[lv.value].regunit() {
                         regs.free(regclass, regunit);
                     }
                 }
             }
         }
+
+        // Move the evicted values that are still live back.
+        self.evicted
+            .retain(|ev| kills.iter().all(|lv| lv.value != ev.value));
+        if (uses_fixed || !clobbers.is_empty()) && !self.evicted.is_empty() {
+            assert!(!dfg[inst].opcode().is_terminator(),
+                    "Can't restore evicted values after {}",
+                    dfg[inst].opcode());
+            pos.next_inst();
+            for ev in self.evicted.drain(..) {
+                regs.free(ev.regclass, ev.to);
+                let regmove = dfg.ins(pos).regmove(ev.value, ev.to, ev.from);
+                encode_regmove(self.isa, regmove, ev.value, dfg, encodings);
+            }
+            // Leave the cursor at the last `regmove` so it isn't visited.
+            pos.prev_inst();
+        }
     }
 }
+
+/// Assign an encoding to the `regmove` instruction `inst` moving `value`.
+fn encode_regmove(isa: &TargetIsa,
+                  inst: Inst,
+                  value: Value,
+                  dfg: &DataFlowGraph,
+                  encodings: &mut EntityMap<Inst, Encoding>) {
+    let enc = isa.encode(dfg, &dfg[inst], dfg.value_type(value))
+        .expect("Can't encode regmove");
+    *encodings.ensure(inst) = enc;
+}
@@ -5,20 +5,35 @@
 //! avoids allocating data structures independently for each function begin compiled.
 
 use dominator_tree::DominatorTree;
+use entity_map::EntityMap;
 use flowgraph::ControlFlowGraph;
-use ir::Function;
+use ir::{Function, Ebb};
 use isa::TargetIsa;
-use regalloc::coloring::Coloring;
+use mem_usage::MemUsage;
+use pipeline::Heuristics;
+use regalloc::coalescing::Coalescing;
+use regalloc::coloring::{Coloring, usable_regs};
 use regalloc::live_value_tracker::LiveValueTracker;
 use regalloc::liveness::Liveness;
+use regalloc::pressure::{Pressure, max_pressure};
+use regalloc::spill_all::SpillAll;
+use regalloc::spill_slots::SpillSlots;
+use regalloc::tied_operands::fix_tied_operands;
+use regalloc::virtregs::VirtRegs;
 use result::CtonResult;
-use verifier::{verify_context, verify_liveness};
+use settings::Regalloc;
+use verifier::{verify_context, verify_liveness, verify_locations};
 
 /// Persistent memory allocations for register allocation.
 pub struct Context {
     liveness: Liveness,
+    virtregs: VirtRegs,
     tracker: LiveValueTracker,
+    coalescing: Coalescing,
     coloring: Coloring,
+    spill_slots: SpillSlots,
+    spill_all: SpillAll,
+    pressure: EntityMap<Ebb, Pressure>,
 }
 
 impl Context {
@@ -29,11 +44,65 @@ impl Context {
     pub fn new() -> Context {
         Context {
             liveness: Liveness::new(),
+            virtregs: VirtRegs::new(),
             tracker: LiveValueTracker::new(),
+            coalescing: Coalescing::new(),
             coloring: Coloring::new(),
+            spill_slots: SpillSlots::new(),
+            spill_all: SpillAll::new(),
+            pressure: EntityMap::new(),
         }
     }
 
+    /// Clear all data structures in this register allocation context.
+    ///
+    /// The allocated memory is kept for the next function. The register allocator clears the data
+    /// structures it needs when it runs, so calling this between functions is not required, but
+    /// it leaves the context in the same state as `new()`.
+    pub fn clear(&mut self) {
+        self.liveness.clear();
+        self.virtregs.clear();
+        self.tracker.clear();
+        self.coalescing.clear();
+        self.coloring.clear();
+        self.spill_slots.clear(usize::max_value());
+        self.spill_all.clear();
+        self.pressure.clear();
+    }
+
+    /// Add the heap memory allocated by the register allocator's data structures to `usage`.
+    pub fn mem_usage(&self, usage: &mut MemUsage) {
+        usage.add("regalloc.liveness", self.liveness.mem_usage());
+        usage.add("regalloc.virtregs", self.virtregs.mem_usage());
+        usage.add("regalloc.tracker", self.tracker.mem_usage());
+        usage.add("regalloc.other",
+                  self.coalescing.mem_usage() + self.coloring.mem_usage() +
+                  self.spill_slots.mem_usage() + self.spill_all.mem_usage() +
+                  self.pressure.mem_usage());
+    }
+
+    /// Compute the maximum register pressure in each EBB of `func` before register allocation.
+    ///
+    /// This runs the liveness analysis, but it doesn't modify `func`. The returned map is indexed
+    /// by EBB, and unreachable EBBs are not included.
+    pub fn pressure(&mut self,
+                    isa: &TargetIsa,
+                    func: &Function,
+                    cfg: &ControlFlowGraph,
+                    domtree: &DominatorTree)
+                    -> &EntityMap<Ebb, Pressure> {
+        let reginfo = isa.register_info();
+        self.liveness.compute(isa, func, cfg);
+        max_pressure(&reginfo,
+                     &usable_regs(isa, &reginfo),
+                     func,
+                     domtree,
+                     &self.liveness,
+                     &mut self.tracker,
+                     &mut self.pressure);
+        &self.pressure
+    }
+
     /// Allocate registers in `func`.
     ///
     /// After register allocation, all values in `func` have been assigned to a register or stack
@@ -44,10 +113,26 @@ impl Context {
                cfg: &ControlFlowGraph,
                domtree: &DominatorTree)
                -> CtonResult {
-        // `Liveness` and `Coloring` are self-clearing.
+        if isa.flags().regalloc() == Regalloc::SpillAll {
+            // The baseline allocator doesn't need any analysis.
+            self.spill_all.run(isa, func);
+            if isa.flags().enable_verifier() {
+                verify_context(func, cfg, domtree)?;
+                // Compute the live ranges so the clobbered registers are verified too.
+                self.liveness.compute(isa, func, cfg);
+                verify_locations(isa, func, Some(&self.liveness))?;
+            }
+            return Ok(());
+        }
+
+        let heuristics = Heuristics::for_opt_level(isa.flags().opt_level());
+
+        // `Liveness` is self-clearing, but the set of EBBs visited by `Coloring` is not.
         // Tracker state (dominator live sets) is actually reused between the spilling and coloring
         // phases.
         self.tracker.clear();
+        self.coloring.clear();
+        self.spill_slots.clear(heuristics.spill_slot_search);
 
         // First pass: Liveness analysis.
         self.liveness.compute(isa, func, cfg);
@@ -56,15 +141,38 @@ impl Context {
             verify_liveness(isa, func, cfg, &self.liveness)?;
         }
 
+        // Copy tied operands that aren't killed by their instruction.
+        fix_tied_operands(isa, func, &mut self.liveness);
+
+        // Build virtual registers from copy-related values that don't interfere.
+        // This runs at every optimization level since the coloring hints from the virtual
+        // registers avoid most of the moves of branch arguments into the EBB argument registers.
+        self.coalescing
+            .run(isa, func, &mut self.liveness, &mut self.virtregs);
+
         // TODO: Second pass: Spilling.
 
         // Third pass: Reload and coloring.
         self.coloring
-            .run(isa, func, domtree, &mut self.liveness, &mut self.tracker);
+            .run(isa,
+                 func,
+                 domtree,
+                 &mut self.liveness,
+                 &self.virtregs,
+                 &mut self.spill_slots,
+                 &mut self.tracker);
 
         if isa.flags().enable_verifier() {
             verify_context(func, cfg, domtree)?;
             verify_liveness(isa, func, cfg, &self.liveness)?;
+            verify_locations(isa, func, Some(&self.liveness))?;
+        }
+
+        // Copies between coalesced values are now redundant. This invalidates the live ranges.
+        self.coalescing.remove_identity_copies(func);
+
+        if isa.flags().enable_verifier() {
+            verify_context(func, cfg, domtree)?;
         }
         Ok(())
     }
@@ -0,0 +1,173 @@
+//! Register diversions.
+//!
+//! Normally, a value is assigned to a single register or stack location by the register allocator.
+//! Sometimes, it is necessary to move register values to a different register in order to satisfy
+//! instruction constraints.
+//!
+//! These register diversions are local to an EBB. No values can be diverted when entering a new
+//! EBB.
+
+use entity_map::EntityMap;
+use ir::{Value, ValueLoc, StackSlot, InstructionData, Opcode};
+use isa::RegUnit;
+
+/// A diversion of a value from its original location to a new register or stack location.
+///
+/// In IL, a diversion is represented by a `regmove` instruction, possibly a chain of them for the
+/// same value.
+///
+/// When tracking diversions, the `from` field is the original assigned value location, and `to` is
+/// the current one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Diversion {
+    /// The value that is diverted.
+    pub value: Value,
+    /// The original value location.
+    pub from: ValueLoc,
+    /// The current value location.
+    pub to: ValueLoc,
+}
+
+impl Diversion {
+    /// Make a new diversion.
+    pub fn new(value: Value, from: ValueLoc, to: ValueLoc) -> Diversion {
+        Diversion {
+            value: value,
+            from: from,
+            to: to,
+        }
+    }
+}
+
+/// Keep track of diversions in an EBB.
+pub struct RegDiversions {
+    current: Vec<Diversion>,
+}
+
+impl RegDiversions {
+    /// Create a new empty diversion tracker.
+    pub fn new() -> RegDiversions {
+        RegDiversions { current: Vec::new() }
+    }
+
+    /// Clear the tracker, preparing for a new EBB.
+    pub fn clear(&mut self) {
+        self.current.clear()
+    }
+
+    /// Are there any diversions?
+    pub fn is_empty(&self) -> bool {
+        self.current.is_empty()
+    }
+
+    /// Get the current diversion of `value`, if any.
+    pub fn diversion(&self, value: Value) -> Option<&Diversion> {
+        self.current.iter().find(|d| d.value == value)
+    }
+
+    /// Get all current diversions.
+    pub fn all(&self) -> &[Diversion] {
+        self.current.as_slice()
+    }
+
+    /// Get the current location for `value`. Fall back to the assignment map for non-diverted
+    /// values.
+    pub fn get(&self, value: Value, locations: &EntityMap<Value, ValueLoc>) -> ValueLoc {
+        match self.diversion(value) {
+            Some(d) => d.to,
+            None => locations[value],
+        }
+    }
+
+    /// Get the current register location for `value`, or panic if `value` isn't in a register.
+    pub fn reg(&self, value: Value, locations: &EntityMap<Value, ValueLoc>) -> RegUnit {
+        self.get(value, locations).unwrap_reg()
+    }
+
+    /// Get the current stack location for `value`, or panic if `value` isn't in a stack slot.
+    pub fn stack(&self, value: Value, locations: &EntityMap<Value, ValueLoc>) -> StackSlot {
+        self.get(value, locations).unwrap_stack()
+    }
+
+    /// Record a diversion of `value` from `from` to `to`.
+    ///
+    /// The `from` location must match an existing `to` location, if any.
+    pub fn divert(&mut self, value: Value, from: ValueLoc, to: ValueLoc) {
+        if let Some(i) = self.current.iter().position(|d| d.value == value) {
+            debug_assert_eq!(self.current[i].to, from, "Bad regmove chain for {}", value);
+            if self.current[i].from != to {
+                self.current[i].to = to;
+            } else {
+                // The value has been moved back to its original location.
+                self.current.swap_remove(i);
+            }
+        } else {
+            self.current.push(Diversion::new(value, from, to));
+        }
+    }
+
+    /// Record a register -> register move.
+    pub fn regmove(&mut self, value: Value, from: RegUnit, to: RegUnit) {
+        self.divert(value, ValueLoc::Reg(from), ValueLoc::Reg(to));
+    }
+
+    /// Apply the effect of `inst` to the diversions.
+    ///
+    /// If `inst` is a `regmove` instruction, update the diversions to match.
+    pub fn apply(&mut self, inst: &InstructionData) {
+        if let InstructionData::RegMove {
+                   opcode: Opcode::Regmove,
+                   arg,
+                   src,
+                   dst,
+               } = *inst {
+            self.regmove(arg, src, dst);
+        }
+    }
+
+    /// Drop any recorded diversion of `value`.
+    ///
+    /// Returns the `to` location of the removed diversion.
+    pub fn remove(&mut self, value: Value) -> Option<ValueLoc> {
+        self.current
+            .iter()
+            .position(|d| d.value == value)
+            .map(|i| self.current.swap_remove(i).to)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use entity_map::EntityRef;
+    use ir::Value;
+
+    #[test]
+    fn inserts() {
+        let mut divs = RegDiversions::new();
+        let v1 = Value::new(1);
+        let v2 = Value::new(2);
+
+        divs.regmove(v1, 10, 12);
+        assert_eq!(divs.diversion(v1),
+                   Some(&Diversion {
+                             value: v1,
+                             from: ValueLoc::Reg(10),
+                             to: ValueLoc::Reg(12),
+                         }));
+        assert_eq!(divs.diversion(v2), None);
+
+        divs.regmove(v1, 12, 11);
+        assert_eq!(divs.diversion(v1).unwrap().to, ValueLoc::Reg(11));
+        assert_eq!(divs.all().len(), 1);
+
+        // Moving back to the original register removes the diversion.
+        divs.regmove(v1, 11, 10);
+        assert_eq!(divs.diversion(v1), None);
+        assert!(divs.is_empty());
+
+        divs.regmove(v2, 3, 4);
+        assert_eq!(divs.remove(v2), Some(ValueLoc::Reg(4)));
+        assert!(divs.is_empty());
+    }
+}
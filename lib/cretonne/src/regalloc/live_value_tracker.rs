@@ -13,6 +13,7 @@ use regalloc::affinity::Affinity;
 use regalloc::liveness::Liveness;
 
 use std::collections::HashMap;
+use std::mem;
 
 type ValueList = EntityList<Value>;
 
@@ -126,6 +127,13 @@ impl LiveValueTracker {
         self.idom_pool.clear();
     }
 
+    /// Get the number of bytes of heap memory allocated by this tracker.
+    pub fn mem_usage(&self) -> usize {
+        self.live.values.capacity() * mem::size_of::<LiveValue>() +
+        self.idom_sets.capacity() * mem::size_of::<(Inst, ValueList)>() +
+        self.idom_pool.mem_usage()
+    }
+
     /// Get the set of currently live values.
     ///
     /// Between calls to `process_inst()` and `drop_dead()`, this includes both values killed and
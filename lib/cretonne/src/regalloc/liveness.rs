@@ -26,6 +26,16 @@
 //! number of live values at every program point and insert spill code until the number of
 //! registers needed is small enough.
 //!
+//! The liveness analysis is also available to passes outside the register allocator, like the
+//! liveness verifier. They can compute it for a function with `Liveness::compute` and ask:
+//!
+//! - What is the live range of a value? See `Liveness::get` and the `LiveRange` queries.
+//! - Is a value live at an instruction that uses it? See `Liveness::reaches_use`.
+//! - Which values are live-in to an EBB? See `Liveness::liveins`.
+//!
+//! The analysis is only valid for the function it was computed for. Removing instructions or
+//! changing the EBB layout invalidates it.
+//!
 //!
 //! # Alternative algorithms
 //!
@@ -177,11 +187,13 @@
 
 use flowgraph::ControlFlowGraph;
 use ir::dfg::ValueDef;
-use ir::{Function, Value, Inst, Ebb};
+use ir::{Function, Value, Inst, Ebb, Layout, ProgramPoint, ExpandedProgramPoint};
 use isa::{TargetIsa, EncInfo};
 use regalloc::affinity::Affinity;
 use regalloc::liverange::LiveRange;
 use sparse_map::SparseMap;
+use std::mem;
+use std::slice;
 
 /// A set of live ranges, indexed by value number.
 type LiveRangeSet = SparseMap<Value, LiveRange>;
@@ -260,6 +272,16 @@ fn extend_to_use(lr: &mut LiveRange,
     }
 }
 
+/// Get the EBB containing the definition of the live range `lr`.
+fn def_ebb(lr: &LiveRange, layout: &Layout) -> Ebb {
+    match lr.def().into() {
+        ExpandedProgramPoint::Ebb(ebb) => ebb,
+        ExpandedProgramPoint::Inst(inst) => {
+            layout.inst_ebb(inst).expect("Value defined by instruction not in layout")
+        }
+    }
+}
+
 /// Liveness analysis for a function.
 ///
 /// Compute a live range for every SSA value used in the function.
@@ -285,11 +307,86 @@ impl Liveness {
         }
     }
 
+    /// Forget all the live ranges, keeping the allocated memory.
+    pub fn clear(&mut self) {
+        self.ranges.clear();
+        self.worklist.clear();
+    }
+
+    /// Get the number of bytes of heap memory allocated by this liveness analysis, not counting the
+    /// live-in intervals of each live range.
+    pub fn mem_usage(&self) -> usize {
+        self.ranges.mem_usage() + self.worklist.capacity() * mem::size_of::<Ebb>()
+    }
+
     /// Get the live range for `value`, if it exists.
     pub fn get(&self, value: Value) -> Option<&LiveRange> {
         self.ranges.get(value)
     }
 
+    /// Get a mutable reference to the live range for `value`, if it exists.
+    pub fn get_mut(&mut self, value: Value) -> Option<&mut LiveRange> {
+        self.ranges.get_mut(value)
+    }
+
+    /// Create a new live range for `value` which is defined at `def` without any uses.
+    ///
+    /// The new live range will be dead. Use `extend_locally()` to add uses.
+    pub fn create_dead<PP>(&mut self, value: Value, def: PP, affinity: Affinity)
+        where PP: Into<ProgramPoint>
+    {
+        let old = self.ranges
+            .insert(LiveRange::new(value, def.into(), affinity));
+        assert!(old.is_none(), "{} already has a live range", value);
+    }
+
+    /// Extend the live range of `value` to reach `user` in `ebb`.
+    ///
+    /// The live range of `value` must already be live in `ebb`, either because it is defined
+    /// there or because it is live-in, so no other EBBs are affected.
+    pub fn extend_locally(&mut self, value: Value, ebb: Ebb, user: Inst, layout: &Layout) {
+        let lr = self.ranges
+            .get_mut(value)
+            .expect("Value has no live range");
+        let livein = lr.extend_in_ebb(ebb, user, layout);
+        assert!(!livein, "{} should already be live in {}", value, ebb);
+    }
+
+    /// Get an iterator over all the computed live ranges, in no particular order.
+    pub fn iter(&self) -> slice::Iter<LiveRange> {
+        self.ranges.values()
+    }
+
+    /// Check if `value` is live at `user`, an instruction in `ebb` that uses it.
+    ///
+    /// Returns false if `value` has no live range.
+    pub fn reaches_use(&self, value: Value, user: Inst, ebb: Ebb, layout: &Layout) -> bool {
+        self.get(value)
+            .map_or(false, |lr| lr.reaches_use(user, ebb, layout))
+    }
+
+    /// Get an iterator over the values that are live-in to `ebb`.
+    ///
+    /// The EBB arguments of `ebb` are not included since they are defined at the EBB header.
+    pub fn liveins<'a>(&'a self, ebb: Ebb, layout: &'a Layout) -> LiveIns<'a> {
+        LiveIns {
+            ranges: self.ranges.values(),
+            ebb: ebb,
+            layout: layout,
+        }
+    }
+
+    /// Check if the live ranges of `a` and `b` interfere.
+    ///
+    /// Two SSA values interfere if one of them is live after the definition of the other. Both
+    /// values must have a live range.
+    pub fn interferes(&self, a: Value, b: Value, layout: &Layout) -> bool {
+        let lr_a = self.get(a).expect("Value has no live range");
+        let lr_b = self.get(b).expect("Value has no live range");
+        lr_a.overlaps_def(lr_b.def().into(), def_ebb(lr_b, layout), layout) ||
+        lr_b.overlaps_def(lr_a.def().into(), def_ebb(lr_a, layout), layout)
+    }
+
     /// Compute the live ranges of all SSA values used in `func`.
     /// This clears out any existing analysis stored in this data structure.
     pub fn compute(&mut self, isa: &TargetIsa, func: &Function, cfg: &ControlFlowGraph) {
@@ -340,3 +437,79 @@ impl Liveness {
         }
     }
 }
+
+/// Iterator over the values that are live-in to an EBB.
+///
+/// This is returned by `Liveness::liveins`.
+pub struct LiveIns<'a> {
+    ranges: slice::Iter<'a, LiveRange>,
+    ebb: Ebb,
+    layout: &'a Layout,
+}
+
+impl<'a> Iterator for LiveIns<'a> {
+    type Item = Value;
+
+    fn next(&mut self) -> Option<Value> {
+        let ebb = self.ebb;
+        let layout = self.layout;
+        self.ranges
+            .find(|lr| lr.is_livein(ebb, layout))
+            .map(|lr| lr.value())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use flowgraph::ControlFlowGraph;
+    use ir::{Function, InstBuilder, Cursor, types};
+    use isa;
+    use settings;
+    use super::Liveness;
+
+    #[test]
+    fn liveins() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let v0 = func.dfg.append_ebb_arg(ebb0, types::I32);
+        let v1 = func.dfg.append_ebb_arg(ebb0, types::I32);
+        let ebb1 = func.dfg.make_ebb();
+        let v2 = func.dfg.append_ebb_arg(ebb1, types::I32);
+
+        let (add, ret);
+        {
+            let dfg = &mut func.dfg;
+            let cur = &mut Cursor::new(&mut func.layout);
+
+            cur.insert_ebb(ebb0);
+            dfg.ins(cur).jump(ebb1, &[v1]);
+
+            cur.insert_ebb(ebb1);
+            let v3 = dfg.ins(cur).iadd(v0, v2);
+            add = cur.prev_inst().unwrap();
+            cur.next_inst();
+            ret = dfg.ins(cur).return_(&[v3]);
+        }
+
+        // The instructions don't have encodings, so no affinities are assigned.
+        let num_insts = func.dfg.num_insts();
+        func.encodings.resize(num_insts);
+
+        let isa = isa::lookup("riscv")
+            .unwrap()
+            .finish(settings::Flags::new(&settings::builder()));
+        let cfg = ControlFlowGraph::with_function(&func);
+        let mut liveness = Liveness::new();
+        liveness.compute(&*isa, &func, &cfg);
+
+        assert_eq!(liveness.iter().count(), 4);
+        assert_eq!(liveness.liveins(ebb0, &func.layout).count(), 0);
+        assert_eq!(liveness.liveins(ebb1, &func.layout).collect::<Vec<_>>(),
+                   [v0]);
+
+        assert!(liveness.reaches_use(v0, add, ebb1, &func.layout));
+        assert!(liveness.reaches_use(v2, add, ebb1, &func.layout));
+        assert!(!liveness.reaches_use(v2, ret, ebb1, &func.layout));
+        assert!(!liveness.reaches_use(v1, add, ebb1, &func.layout));
+    }
+}
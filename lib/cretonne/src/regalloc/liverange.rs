@@ -108,7 +108,7 @@
 //!
 
 use std::cmp::Ordering;
-use ir::{Inst, Ebb, Value, ProgramPoint, ProgramOrder};
+use ir::{Inst, Ebb, Value, ProgramPoint, ExpandedProgramPoint, ProgramOrder};
 use regalloc::affinity::Affinity;
 use sparse_map::SparseMapValue;
 
@@ -373,6 +373,61 @@ impl LiveRange {
     pub fn liveins(&self) -> &[Interval] {
         &self.liveins
     }
+
+    /// Get the value described by this live range.
+    pub fn value(&self) -> Value {
+        self.value
+    }
+
+    /// Is this live range live-in to `ebb`?
+    pub fn is_livein<PO: ProgramOrder>(&self, ebb: Ebb, order: &PO) -> bool {
+        self.livein_local_end(ebb, order).is_some()
+    }
+
+    /// Check if this live range reaches a use at `user` in `ebb`.
+    ///
+    /// The value must be live at `user`, but not defined by it.
+    pub fn reaches_use<PO: ProgramOrder>(&self, user: Inst, ebb: Ebb, order: &PO) -> bool {
+        // Check if `user` is in the def range, not including the def itself.
+        if order.cmp(self.def_begin, user) == Ordering::Less &&
+           order.cmp(user, self.def_end) != Ordering::Greater {
+            return true;
+        }
+
+        // Otherwise see if `user` is in one of the live-in ranges.
+        match self.livein_local_end(ebb, order) {
+            Some(end) => order.cmp(user, end) != Ordering::Greater,
+            None => false,
+        }
+    }
+
+    /// Check if this live range overlaps a definition in `ebb`.
+    ///
+    /// The definition point `def` must belong to `ebb`. It can be an EBB header for an EBB
+    /// argument definition. This live range overlaps the definition if the value is still live
+    /// *after* `def`. A live range that ends at the instruction defining another value does not
+    /// overlap it, which is exactly the situation for a `copy` instruction that kills its
+    /// argument.
+    ///
+    /// Since two SSA values can only interfere if one of them is live at the definition of the
+    /// other, this is sufficient to detect interference between live ranges.
+    pub fn overlaps_def<PO: ProgramOrder>(&self,
+                                          def: ExpandedProgramPoint,
+                                          ebb: Ebb,
+                                          order: &PO)
+                                          -> bool {
+        // Check for an overlap with the def interval.
+        if order.cmp(def, self.def_begin) != Ordering::Less &&
+           order.cmp(def, self.def_end) == Ordering::Less {
+            return true;
+        }
+
+        // Check for an overlap with a live-in interval.
+        match self.livein_local_end(ebb, order) {
+            Some(end) => order.cmp(def, end) == Ordering::Less,
+            None => false,
+        }
+    }
 }
 
 /// Allow a `LiveRange` to be stored in a `SparseMap` indexed by values.
@@ -623,5 +678,36 @@ mod tests {
         assert_eq!(lr.liveins[0].end, i41);
     }
 
+    #[test]
+    fn overlaps_def() {
+        let v0 = Value::new(0);
+        let e10 = Ebb::new(10);
+        let i11 = Inst::new(11);
+        let i12 = Inst::new(12);
+        let i13 = Inst::new(13);
+        let e20 = Ebb::new(20);
+        let i21 = Inst::new(21);
+        let i22 = Inst::new(22);
+        let i23 = Inst::new(23);
+        let mut lr = LiveRange::new(v0, i11.into(), Default::default());
+        lr.extend_in_ebb(e10, i12, PO);
+        lr.extend_in_ebb(e20, i22, PO);
+        PO.validate(&lr);
+
+        // The def itself and program points before it don't overlap.
+        assert!(!lr.overlaps_def(e10.into(), e10, PO));
+        assert!(lr.overlaps_def(i11.into(), e10, PO));
+
+        // A def at the last use doesn't overlap. This is the `copy` case.
+        assert!(!lr.overlaps_def(i12.into(), e10, PO));
+        assert!(!lr.overlaps_def(i13.into(), e10, PO));
+
+        // Live-in to `e20` up to `i22`.
+        assert!(lr.overlaps_def(e20.into(), e20, PO));
+        assert!(lr.overlaps_def(i21.into(), e20, PO));
+        assert!(!lr.overlaps_def(i22.into(), e20, PO));
+        assert!(!lr.overlaps_def(i23.into(), e20, PO));
+    }
+
     // TODO: Add more tests that exercise the binary search algorithm.
 }
@@ -7,8 +7,16 @@ pub mod liveness;
 pub mod allocatable_set;
 pub mod live_value_tracker;
 pub mod coloring;
+pub mod virtregs;
+pub mod coalescing;
+pub mod spill_slots;
+pub mod affinity;
+pub mod tied_operands;
+pub mod spill_all;
+pub mod pressure;
+pub mod diversion;
 
-mod affinity;
 mod context;
 
 pub use self::context::Context;
+pub use self::diversion::RegDiversions;
@@ -0,0 +1,241 @@
+//! Register pressure tracking.
+//!
+//! The register pressure is the number of register units occupied by live values at a program
+//! point. It is tracked separately for each top-level register class since those are disjoint.
+//! When the pressure in a register class exceeds the number of usable registers, some values
+//! must be spilled.
+//!
+//! The `Pressure` struct counts register units as values are taken and freed, and it can be
+//! used incrementally by passes that track live values, like the spiller.
+//!
+//! The `max_pressure()` function computes the maximum register pressure in each EBB of a function
+//! from the liveness analysis. This is useful for heuristics that need a summary of the whole
+//! function, and for diagnostics.
+
+use dominator_tree::DominatorTree;
+use entity_map::EntityMap;
+use ir::{Function, Ebb};
+use isa::{RegInfo, RegClass};
+use regalloc::affinity::Affinity;
+use regalloc::allocatable_set::AllocatableSet;
+use regalloc::live_value_tracker::{LiveValue, LiveValueTracker};
+use regalloc::liveness::Liveness;
+use std::fmt;
+
+/// The maximum number of register classes in an ISA. This matches the width of the `subclasses`
+/// bit mask in `RegClassData`.
+const MAX_RCS: usize = 32;
+
+/// Register pressure in the top-level register classes.
+#[derive(Clone, Default)]
+pub struct Pressure {
+    /// Number of register units in use, indexed by top-level register class.
+    units: [u32; MAX_RCS],
+
+    /// Number of usable register units, indexed by top-level register class.
+    limits: [u32; MAX_RCS],
+
+    /// The top-level register class containing each register class.
+    toprc: [u8; MAX_RCS],
+}
+
+impl Pressure {
+    /// Create a new pressure tracker with no registers in use.
+    ///
+    /// The limits are computed from the registers in `usable`.
+    pub fn new(reginfo: &RegInfo, usable: &AllocatableSet) -> Pressure {
+        let mut p = Pressure::default();
+        for rc in reginfo.classes {
+            let top = reginfo.toprc(rc);
+            p.toprc[rc.index as usize] = top.index;
+            if top.index == rc.index {
+                p.limits[rc.index as usize] = usable.iter(rc).count() as u32 * rc.width as u32;
+            }
+        }
+        p
+    }
+
+    /// Get the number of register units in use in the top-level register class containing `rc`.
+    pub fn units(&self, rc: RegClass) -> u32 {
+        self.units[self.toprc[rc.index as usize] as usize]
+    }
+
+    /// Get the number of usable register units in the top-level register class containing `rc`.
+    pub fn limit(&self, rc: RegClass) -> u32 {
+        self.limits[self.toprc[rc.index as usize] as usize]
+    }
+
+    /// Check if a register from `rc` is available without exceeding the limit.
+    pub fn check_avail(&self, rc: RegClass) -> bool {
+        self.units(rc) + rc.width as u32 <= self.limit(rc)
+    }
+
+    /// Does the pressure exceed the limit in any register class?
+    pub fn is_over(&self) -> bool {
+        self.units
+            .iter()
+            .zip(self.limits.iter())
+            .any(|(&units, &limit)| units > limit)
+    }
+
+    /// Take a register from `rc`.
+    ///
+    /// The pressure can exceed the limit, use `check_avail()` first if that matters.
+    pub fn take(&mut self, rc: RegClass) {
+        let top = self.toprc[rc.index as usize] as usize;
+        self.units[top] += rc.width as u32;
+    }
+
+    /// Free a register in `rc` that was previously taken.
+    pub fn free(&mut self, rc: RegClass) {
+        let top = self.toprc[rc.index as usize] as usize;
+        assert!(self.units[top] >= rc.width as u32, "Freeing unused register");
+        self.units[top] -= rc.width as u32;
+    }
+
+    /// Reset the pressure to zero in all register classes.
+    pub fn reset(&mut self) {
+        self.units = [0; MAX_RCS];
+    }
+
+    /// Raise the pressure in each register class to at least the pressure in `other`.
+    pub fn merge_max(&mut self, other: &Pressure) {
+        for (units, &other_units) in self.units.iter_mut().zip(other.units.iter()) {
+            *units = (*units).max(other_units);
+        }
+    }
+
+    /// Make a temporary object that displays the pressure in each top-level register class.
+    pub fn display<'a>(&'a self, reginfo: &'a RegInfo) -> DisplayPressure<'a> {
+        DisplayPressure {
+            pressure: self,
+            reginfo: reginfo,
+        }
+    }
+}
+
+/// Temporary object that holds enough information to print register pressure.
+pub struct DisplayPressure<'a> {
+    pressure: &'a Pressure,
+    reginfo: &'a RegInfo,
+}
+
+impl<'a> fmt::Display for DisplayPressure<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut sep = "";
+        for rc in self.reginfo.classes {
+            if self.pressure.toprc[rc.index as usize] == rc.index {
+                write!(f,
+                       "{}{}={}/{}",
+                       sep,
+                       rc.name,
+                       self.pressure.units(rc),
+                       self.pressure.limit(rc))?;
+                sep = ", ";
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Compute the maximum register pressure in each EBB of `func`.
+///
+/// The live ranges in `liveness` must be up to date. Only values with a register affinity count
+/// towards the pressure. Unreachable EBBs are not included in `result`.
+pub fn max_pressure(reginfo: &RegInfo,
+                    usable: &AllocatableSet,
+                    func: &Function,
+                    domtree: &DominatorTree,
+                    liveness: &Liveness,
+                    tracker: &mut LiveValueTracker,
+                    result: &mut EntityMap<Ebb, Pressure>) {
+    result.clear();
+    tracker.clear();
+    let mut pressure = Pressure::new(reginfo, usable);
+
+    // The live value tracker needs to visit the dominators first.
+    let mut ebbs: Vec<Ebb> = func.layout
+        .ebbs()
+        .filter(|&ebb| domtree.is_reachable(ebb))
+        .collect();
+    ebbs.sort_by(|&a, &b| domtree.rpo_cmp(a, b));
+
+    for ebb in ebbs {
+        tracker.ebb_top(ebb, &func.dfg, liveness, &func.layout, domtree);
+        let mut max = live_pressure(tracker.live(), reginfo, &mut pressure).clone();
+
+        for inst in func.layout.ebb_insts(ebb) {
+            // The operands of `inst` are read before its results are written, so the pressure is
+            // measured between instructions.
+            tracker.process_inst(inst, &func.dfg, liveness);
+            tracker.drop_dead(inst);
+            max.merge_max(live_pressure(tracker.live(), reginfo, &mut pressure));
+        }
+
+        *result.ensure(ebb) = max;
+    }
+}
+
+/// Compute the register pressure of the `live` values in `pressure`.
+fn live_pressure<'a>(live: &[LiveValue],
+                     reginfo: &RegInfo,
+                     pressure: &'a mut Pressure)
+                     -> &'a Pressure {
+    pressure.reset();
+    for lv in live {
+        if let Affinity::Reg(rci) = lv.affinity {
+            pressure.take(reginfo.rc(rci));
+        }
+    }
+    pressure
+}
+
+#[cfg(test)]
+mod tests {
+    use isa;
+    use regalloc::allocatable_set::AllocatableSet;
+    use settings;
+    use super::Pressure;
+
+    #[test]
+    fn gpr_pressure() {
+        let isa = isa::lookup("riscv")
+            .unwrap()
+            .finish(settings::Flags::new(&settings::builder()));
+        let reginfo = isa.register_info();
+        let gpr = reginfo
+            .classes
+            .iter()
+            .find(|rc| rc.name == "GPR")
+            .unwrap();
+
+        let mut usable = AllocatableSet::new();
+        usable.take(gpr, gpr.unit(0));
+        let mut p = Pressure::new(&reginfo, &usable);
+        assert_eq!(p.limit(gpr), 31);
+        assert_eq!(p.units(gpr), 0);
+        assert!(!p.is_over());
+
+        for _ in 0..31 {
+            assert!(p.check_avail(gpr));
+            p.take(gpr);
+        }
+        assert!(!p.check_avail(gpr));
+        assert!(!p.is_over());
+        assert_eq!(p.units(gpr), 31);
+
+        p.take(gpr);
+        assert!(p.is_over());
+        p.free(gpr);
+        assert!(!p.is_over());
+
+        let mut max = Pressure::new(&reginfo, &usable);
+        max.take(gpr);
+        max.merge_max(&p);
+        assert_eq!(max.units(gpr), 31);
+        assert_eq!(max.display(&reginfo).to_string(), "GPR=31/31, FPR=0/32");
+
+        p.reset();
+        assert_eq!(p.units(gpr), 0);
+    }
+}
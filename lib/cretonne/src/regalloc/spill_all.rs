@@ -0,0 +1,421 @@
+//! Spill-everything register allocator.
+//!
+//! This is a minimal register allocator intended for baseline compilation where compile time
+//! matters more than the quality of the generated code. It is selected with the `regalloc =
+//! "spill_all"` setting.
+//!
+//! Every SSA value is assigned its own stack slot. Register operands are loaded into scratch
+//! registers with `fill` instructions immediately before each instruction, and register results
+//! are written back to their stack slots with `spill` instructions immediately after it:
+//!
+//! ```cton
+//!     v3 = iadd v1, v2
+//! ```
+//!
+//! Becomes:
+//!
+//! ```cton
+//!     v4 = fill v1
+//!     v5 = fill v2
+//!     v6 = iadd v4, v5
+//!     v3 = spill v6
+//! ```
+//!
+//! Since no values are live in registers between instructions, there is no need for liveness
+//! analysis or interference checks, and calls can't clobber anything.
+//!
+//! ABI boundaries are handled the same way:
+//!
+//! - Entry block arguments arriving in registers are spilled at the top of the entry block.
+//!   Arguments arriving on the stack stay in their incoming argument slots.
+//! - Call arguments and return values are filled into their ABI registers before the call or
+//!   return, and call results are spilled after the call.
+//! - Arguments to branches are copied through a scratch register into the stack slots of the
+//!   destination EBB arguments. When a destination slot is also read by another argument, like
+//!   in `jump ebb1(v2, v1)` where `v1` and `v2` are the arguments to `ebb1`, the argument is first
+//!   copied to a temporary slot so the destination slots are written after all of them are
+//!   read.
+
+use entity_map::EntityMap;
+use ir::{Function, Cursor, DataFlowGraph, Ebb, Inst, InstBuilder, Value, ValueLoc, ArgumentType,
+         ArgumentLoc, StackSlot, StackSlotData, StackSlotKind};
+use ir::instructions::BranchInfo;
+use isa::{TargetIsa, RegInfo, EncInfo, RegUnit, RegClass, ConstraintKind, Encoding};
+use regalloc::allocatable_set::AllocatableSet;
+use regalloc::coloring::usable_regs;
+use std::mem;
+
+/// Data structures for the spill-everything allocator.
+///
+/// These are scratch space data structures that can be reused between invocations.
+pub struct SpillAll {
+    /// Scratch registers holding the fixed value operands of the current instruction.
+    arg_regs: Vec<Option<RegUnit>>,
+
+    /// Results of the current instruction that must be spilled, as `(value, register copy)`.
+    spills: Vec<(Value, Value)>,
+
+    /// Branch arguments that must be copied, as `(argument index, destination slot)`.
+    moves: Vec<(usize, StackSlot)>,
+}
+
+/// Context for spilling a single function.
+struct Context<'a> {
+    isa: &'a TargetIsa,
+    reginfo: RegInfo,
+    encinfo: EncInfo,
+
+    // Pristine set of registers that the allocator can use.
+    usable_regs: AllocatableSet,
+
+    // The parts of the function we're working on.
+    dfg: &'a mut DataFlowGraph,
+    encodings: &'a mut EntityMap<Inst, Encoding>,
+    locations: &'a mut EntityMap<Value, ValueLoc>,
+    stack_slots: &'a mut EntityMap<StackSlot, StackSlotData>,
+}
+
+impl SpillAll {
+    /// Allocate scratch space data structures for the spill-everything allocator.
+    pub fn new() -> SpillAll {
+        SpillAll {
+            arg_regs: Vec::new(),
+            spills: Vec::new(),
+            moves: Vec::new(),
+        }
+    }
+
+    /// Clear all data structures in this pass.
+    pub fn clear(&mut self) {
+        self.arg_regs.clear();
+        self.spills.clear();
+        self.moves.clear();
+    }
+
+    /// Get the number of bytes of heap memory allocated by this pass.
+    pub fn mem_usage(&self) -> usize {
+        self.arg_regs.capacity() * mem::size_of::<Option<RegUnit>>() +
+        self.spills.capacity() * mem::size_of::<(Value, Value)>() +
+        self.moves.capacity() * mem::size_of::<(usize, StackSlot)>()
+    }
+
+    /// Assign a stack slot to every value in `func`, inserting `fill` and `spill` instructions
+    /// around every instruction that needs register operands.
+    pub fn run(&mut self, isa: &TargetIsa, func: &mut Function) {
+        let reginfo = isa.register_info();
+        let mut ctx = Context {
+            isa: isa,
+            usable_regs: usable_regs(isa, &reginfo),
+            reginfo: reginfo,
+            encinfo: isa.encoding_info(),
+            dfg: &mut func.dfg,
+            encodings: &mut func.encodings,
+            locations: &mut func.locations,
+            stack_slots: &mut func.stack_slots,
+        };
+        let mut pos = Cursor::new(&mut func.layout);
+
+        // Assign stack slots to the EBB arguments first, so branches know where to put them.
+        let entry = pos.layout.entry_block();
+        while let Some(ebb) = pos.next_ebb() {
+            if Some(ebb) != entry {
+                for i in 0..ctx.dfg.num_ebb_args(ebb) {
+                    let arg = ctx.dfg.ebb_args(ebb)[i];
+                    ctx.stack_loc(arg);
+                }
+            }
+        }
+
+        // The entry block arguments passed on the stack are used where they are.
+        if let Some(ebb) = entry {
+            ctx.incoming_stack_args(ebb, &func.signature.argument_types);
+        }
+
+        while let Some(_) = pos.next_ebb() {
+            while let Some(inst) = pos.next_inst() {
+                ctx.visit_inst(inst, self, &func.signature.return_types, &mut pos);
+            }
+        }
+
+        // Spill the incoming arguments last, so the inserted spills aren't visited above.
+        if let Some(ebb) = entry {
+            ctx.spill_entry_args(ebb, &func.signature.argument_types, &mut pos);
+        }
+    }
+}
+
+impl<'a> Context<'a> {
+    /// Get the stack slot holding `value`, assigning a new spill slot if necessary.
+    ///
+    /// Values that were already assigned a stack slot keep it. This includes outgoing call
+    /// arguments whose slots were assigned by the legalizer.
+    fn stack_loc(&mut self, value: Value) -> StackSlot {
+        if let Some(&ValueLoc::Stack(ss)) = self.locations.get(value) {
+            return ss;
+        }
+        let size = self.dfg.value_type(value).bytes();
+        let ss = self.stack_slots
+            .push(StackSlotData::new(StackSlotKind::SpillSlot, size));
+        *self.locations.ensure(value) = ValueLoc::Stack(ss);
+        ss
+    }
+
+    /// Assign an encoding to the newly inserted instruction defining `value`.
+    fn encode_def(&mut self, value: Value) -> Inst {
+        let inst = self.dfg.value_def(value).unwrap_inst();
+        let ctrl_type = self.dfg.ctrl_typevar(inst);
+        let enc = self.isa
+            .encode(self.dfg, &self.dfg[inst], ctrl_type)
+            .unwrap_or_else(|_| panic!("Can't encode {}", self.dfg.display_inst(inst)));
+        *self.encodings.ensure(inst) = enc;
+        inst
+    }
+
+    /// Take a register for the result of the instruction defining `value`.
+    ///
+    /// Use `fixed` if given, otherwise pick a register from the register class required by the
+    /// encoding.
+    fn take_reg(&mut self,
+                value: Value,
+                fixed: Option<RegUnit>,
+                regs: &mut AllocatableSet)
+                -> RegUnit {
+        let inst = self.dfg.value_def(value).unwrap_inst();
+        let rc = self.encinfo
+            .operand_constraints(self.encodings[inst])
+            .expect("Missing encoding")
+            .outs[0]
+            .regclass;
+        let reg = match fixed {
+            Some(reg) => reg,
+            None => regs.iter(rc).next().expect("Out of scratch registers"),
+        };
+        self.take(reg, rc, regs);
+        *self.locations.ensure(value) = rc.location(reg);
+        reg
+    }
+
+    /// Take `reg` out of `regs`, using `rc` unless `reg` doesn't belong to it.
+    fn take(&self, reg: RegUnit, rc: RegClass, regs: &mut AllocatableSet) {
+        let rc = if rc.contains(reg) {
+            rc
+        } else {
+            self.reginfo
+                .toprc_containing(reg)
+                .expect("Register is not in any register class")
+        };
+        assert!(regs.is_avail(rc, reg), "Scratch register conflict");
+        regs.take(rc, reg);
+    }
+
+    /// Insert a `fill` of `value` into a scratch register before the cursor position.
+    fn fill(&mut self,
+            value: Value,
+            fixed: Option<RegUnit>,
+            regs: &mut AllocatableSet,
+            pos: &mut Cursor)
+            -> (Value, RegUnit) {
+        self.stack_loc(value);
+        let filled = self.dfg.ins(pos).fill(value);
+        self.encode_def(filled);
+        let reg = self.take_reg(filled, fixed, regs);
+        (filled, reg)
+    }
+
+    /// Copy `value` through a scratch register to a new value before the cursor position.
+    ///
+    /// The new value is not assigned a stack slot.
+    fn copy_slot(&mut self, value: Value, pos: &mut Cursor) -> Value {
+        let mut regs = self.usable_regs.clone();
+        let (filled, _) = self.fill(value, None, &mut regs, pos);
+        let copy = self.dfg.ins(pos).spill(filled);
+        self.encode_def(copy);
+        copy
+    }
+
+    /// Assign incoming argument slots to the entry block arguments passed on the stack.
+    fn incoming_stack_args(&mut self, ebb: Ebb, abi_args: &[ArgumentType]) {
+        for i in 0..self.dfg.num_ebb_args(ebb) {
+            if let ArgumentLoc::Stack(offset) = abi_args[i].location {
+                let arg = self.dfg.ebb_args(ebb)[i];
+                let size = self.dfg.value_type(arg).bytes();
+                let ss = self.stack_slots
+                    .push(StackSlotData::incoming_arg(size, offset as i32));
+                *self.locations.ensure(arg) = ValueLoc::Stack(ss);
+            }
+        }
+    }
+
+    /// Spill the entry block arguments that arrive in registers.
+    fn spill_entry_args(&mut self,
+                        ebb: Ebb,
+                        abi_args: &[ArgumentType],
+                        pos: &mut Cursor) {
+        pos.goto_top(ebb);
+        pos.next_inst();
+        for i in 0..self.dfg.num_ebb_args(ebb) {
+            let arg = self.dfg.ebb_args(ebb)[i];
+            match abi_args[i].location {
+                ArgumentLoc::Reg(reg) => {
+                    let ty = self.dfg.value_type(arg);
+                    let incoming = self.dfg.replace_ebb_arg(arg, ty);
+                    *self.locations.ensure(incoming) = ValueLoc::Reg(reg);
+                    self.dfg.ins(pos).with_result(arg).spill(incoming);
+                    self.encode_def(arg);
+                    self.stack_loc(arg);
+                }
+                ArgumentLoc::Stack(_) => {}
+                ArgumentLoc::Unassigned => panic!("Entry argument {} has no ABI location", arg),
+            }
+        }
+    }
+
+    /// Insert fills and spills around `inst`.
+    ///
+    /// Leave the cursor at the last instruction inserted after `inst`, or at `inst` itself.
+    fn visit_inst(&mut self,
+                  inst: Inst,
+                  data: &mut SpillAll,
+                  abi_rets: &[ArgumentType],
+                  pos: &mut Cursor) {
+        let constraints = self.encinfo
+            .operand_constraints(self.encodings[inst])
+            .expect("Missing instruction encoding")
+            .clone();
+
+        // Copy branch arguments to the stack slots of the destination EBB arguments. This is
+        // done first, so the scratch registers can be reused for the fixed operands.
+        if let BranchInfo::SingleDest(dest, _) =
+            self.dfg[inst].analyze_branch(&self.dfg.value_lists) {
+            data.moves.clear();
+            for i in 0..self.dfg.num_ebb_args(dest) {
+                let arg = self.dfg.inst_variable_args(inst)[i];
+                let dest_ss = self.stack_loc(self.dfg.ebb_args(dest)[i]);
+                if self.stack_loc(arg) != dest_ss {
+                    data.moves.push((i, dest_ss));
+                }
+            }
+
+            // Arguments in a slot that is written by another move are copied to a temporary slot
+            // first.
+            for &(i, _) in &data.moves {
+                let arg = self.dfg.inst_variable_args(inst)[i];
+                let src_ss = self.stack_loc(arg);
+                if data.moves.iter().any(|&(_, dest_ss)| dest_ss == src_ss) {
+                    let temp = self.copy_slot(arg, pos);
+                    self.stack_loc(temp);
+                    self.dfg.inst_variable_args_mut(inst)[i] = temp;
+                }
+            }
+
+            for &(i, dest_ss) in &data.moves {
+                let arg = self.dfg.inst_variable_args(inst)[i];
+                let moved = self.copy_slot(arg, pos);
+                *self.locations.ensure(moved) = ValueLoc::Stack(dest_ss);
+                self.dfg.inst_variable_args_mut(inst)[i] = moved;
+            }
+        }
+
+        let mut regs = self.usable_regs.clone();
+
+        // Fill the ABI register arguments to calls and returns.
+        let abi_args = match self.dfg.call_signature(inst) {
+            Some(sig) => Some(self.dfg.signatures[sig].argument_types.clone()),
+            None if self.dfg[inst].opcode().is_return() => Some(abi_rets.to_vec()),
+            None => None,
+        };
+        if let Some(abi_args) = abi_args {
+            for (i, abi_arg) in abi_args.iter().enumerate() {
+                if let ArgumentLoc::Reg(reg) = abi_arg.location {
+                    let arg = self.dfg.inst_variable_args(inst)[i];
+                    let (filled, _) = self.fill(arg, Some(reg), &mut regs, pos);
+                    self.dfg.inst_variable_args_mut(inst)[i] = filled;
+                }
+            }
+        }
+
+        // Fill the fixed register operands.
+        data.arg_regs.clear();
+        for (i, opcst) in constraints.ins.iter().enumerate() {
+            let arg = self.dfg.inst_args(inst)[i];
+            let fixed = match opcst.kind {
+                ConstraintKind::Reg => None,
+                ConstraintKind::FixedReg(reg) => Some(reg),
+                ConstraintKind::Stack => {
+                    self.stack_loc(arg);
+                    data.arg_regs.push(None);
+                    continue;
+                }
+                ConstraintKind::Tied(_) => panic!("Value operands can't be tied"),
+            };
+            let (filled, reg) = self.fill(arg, fixed, &mut regs, pos);
+            self.dfg.inst_args_mut(inst)[i] = filled;
+            data.arg_regs.push(Some(reg));
+        }
+
+        if !self.dfg.has_results(inst) {
+            return;
+        }
+
+        // The operands are read before the results are written, so the results can reuse the
+        // scratch registers.
+        let mut regs = self.usable_regs.clone();
+        let abi_results = self.dfg
+            .call_signature(inst)
+            .map(|sig| self.dfg.signatures[sig].return_types.clone());
+        data.spills.clear();
+        let results = self.dfg.detach_results(inst);
+        for i in 0..results.len(&self.dfg.value_lists) {
+            let res = results.get(i, &self.dfg.value_lists).unwrap();
+            let (rc, fixed) = match constraints.outs.get(i) {
+                Some(opcst) => {
+                    match opcst.kind {
+                        ConstraintKind::Reg => (opcst.regclass, None),
+                        ConstraintKind::FixedReg(reg) => (opcst.regclass, Some(reg)),
+                        ConstraintKind::Tied(n) => (opcst.regclass, data.arg_regs[n as usize]),
+                        ConstraintKind::Stack => {
+                            self.dfg.attach_result(inst, res);
+                            self.stack_loc(res);
+                            continue;
+                        }
+                    }
+                }
+                None => {
+                    let abi_results = abi_results
+                        .as_ref()
+                        .expect("Can't handle variable results");
+                    match abi_results[i].location {
+                        ArgumentLoc::Reg(reg) => {
+                            let rc = self.reginfo
+                                .toprc_containing(reg)
+                                .expect("Register is not in any register class");
+                            (rc, Some(reg))
+                        }
+                        _ => panic!("Call result {} must be returned in a register", res),
+                    }
+                }
+            };
+
+            let ty = self.dfg.value_type(res);
+            let reg = match fixed {
+                Some(reg) => reg,
+                None => regs.iter(rc).next().expect("Out of scratch registers"),
+            };
+            self.take(reg, rc, &mut regs);
+            let in_reg = self.dfg.append_result(inst, ty);
+            *self.locations.ensure(in_reg) = rc.location(reg);
+            data.spills.push((res, in_reg));
+        }
+
+        // Spill the register results after `inst`.
+        if !data.spills.is_empty() {
+            pos.next_inst();
+            for &(res, in_reg) in &data.spills {
+                self.dfg.ins(pos).with_result(res).spill(in_reg);
+                self.encode_def(res);
+                self.stack_loc(res);
+            }
+            pos.prev_inst();
+        }
+    }
+}
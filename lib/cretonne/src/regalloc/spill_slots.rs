@@ -0,0 +1,152 @@
+//! Spill slot allocation.
+//!
+//! Values that are assigned to the stack by the register allocator need a spill slot. Giving every
+//! spilled value its own slot would waste a lot of stack space, so spill slots are shared between
+//! values whose live ranges don't overlap.
+//!
+//! Since all the values sharing a spill slot must not interfere with each other, a new value can
+//! use an existing spill slot of the right size if it doesn't interfere with any of the values
+//! already assigned to the slot. Otherwise, a new spill slot is created.
+//!
+//! Checking a slot for interference costs time proportional to the number of values assigned to
+//! it, so the number of slots searched is limited by the `spill_slot_search` heuristic.
+
+use entity_map::EntityMap;
+use ir::{Layout, StackSlot, StackSlotData, StackSlotKind, Value};
+use regalloc::liveness::Liveness;
+use std::mem;
+
+/// A spill slot and the values that have been assigned to it.
+struct SlotUsers {
+    slot: StackSlot,
+    size: u32,
+    values: Vec<Value>,
+}
+
+/// Spill slot allocator.
+///
+/// This keeps track of the spill slots created for a function and the values that live in them.
+pub struct SpillSlots {
+    slots: Vec<SlotUsers>,
+    // Maximum number of existing slots to consider for sharing.
+    search_limit: usize,
+}
+
+impl SpillSlots {
+    /// Create a new empty spill slot allocator.
+    pub fn new() -> SpillSlots {
+        SpillSlots {
+            slots: Vec::new(),
+            search_limit: usize::max_value(),
+        }
+    }
+
+    /// Forget about all the spill slots, preparing for a new function.
+    ///
+    /// At most `search_limit` existing spill slots will be considered for sharing when assigning
+    /// a slot to a value.
+    pub fn clear(&mut self, search_limit: usize) {
+        self.slots.clear();
+        self.search_limit = search_limit;
+    }
+
+    /// Get the number of bytes of heap memory allocated by this table, not counting the
+    /// lists of values assigned to each slot.
+    pub fn mem_usage(&self) -> usize {
+        self.slots.capacity() * mem::size_of::<SlotUsers>()
+    }
+
+    /// Assign a spill slot of `size` bytes to `value`.
+    ///
+    /// Reuse one of the first `search_limit` spill slots if `value` doesn't interfere with any of
+    /// the values already assigned to it. Otherwise create a new spill slot in `stack_slots`.
+    pub fn assign(&mut self,
+                  value: Value,
+                  size: u32,
+                  stack_slots: &mut EntityMap<StackSlot, StackSlotData>,
+                  liveness: &Liveness,
+                  layout: &Layout)
+                  -> StackSlot {
+        for users in self.slots.iter_mut().take(self.search_limit) {
+            if users.size == size &&
+               !users
+                    .values
+                    .iter()
+                    .any(|&v| liveness.interferes(v, value, layout)) {
+                users.values.push(value);
+                return users.slot;
+            }
+        }
+
+        let slot = stack_slots.push(StackSlotData::new(StackSlotKind::SpillSlot, size));
+        self.slots
+            .push(SlotUsers {
+                      slot: slot,
+                      size: size,
+                      values: vec![value],
+                  });
+        slot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use entity_map::EntityMap;
+    use flowgraph::ControlFlowGraph;
+    use ir::{Function, InstBuilder, Cursor, types};
+    use isa;
+    use regalloc::liveness::Liveness;
+    use settings;
+    use super::SpillSlots;
+
+    #[test]
+    fn assign() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let v0 = func.dfg.append_ebb_arg(ebb0, types::I32);
+        let v1 = func.dfg.append_ebb_arg(ebb0, types::I32);
+        let (v2, v3);
+        {
+            let dfg = &mut func.dfg;
+            let cur = &mut Cursor::new(&mut func.layout);
+
+            cur.insert_ebb(ebb0);
+            v2 = dfg.ins(cur).iadd_imm(v0, 1);
+            v3 = dfg.ins(cur).iadd(v2, v1);
+            dfg.ins(cur).return_(&[v3]);
+        }
+        let num_insts = func.dfg.num_insts();
+        func.encodings.resize(num_insts);
+
+        let isa = isa::lookup("riscv")
+            .unwrap()
+            .finish(settings::Flags::new(&settings::builder()));
+        let cfg = ControlFlowGraph::with_function(&func);
+        let mut liveness = Liveness::new();
+        liveness.compute(&*isa, &func, &cfg);
+
+        let mut stack_slots = EntityMap::new();
+        let mut slots = SpillSlots::new();
+        let layout = &func.layout;
+        let ss0 = slots.assign(v0, 4, &mut stack_slots, &liveness, layout);
+        let ss1 = slots.assign(v1, 4, &mut stack_slots, &liveness, layout);
+        assert!(ss1 != ss0);
+
+        // `v3` is defined after `v0` dies, so they can share a slot.
+        assert_eq!(slots.assign(v3, 4, &mut stack_slots, &liveness, layout),
+                   ss0);
+
+        // Slots of a different size are never shared.
+        let ss2 = slots.assign(v2, 8, &mut stack_slots, &liveness, layout);
+        assert!(ss2 != ss0 && ss2 != ss1);
+        assert_eq!(stack_slots.len(), 3);
+        assert_eq!(stack_slots[ss2].size, 8);
+
+        // Without any slots to search, every value gets a new slot.
+        slots.clear(0);
+        let ss3 = slots.assign(v0, 4, &mut stack_slots, &liveness, layout);
+        let ss4 = slots.assign(v3, 4, &mut stack_slots, &liveness, layout);
+        assert!(ss3 != ss4);
+        assert_eq!(stack_slots.len(), 5);
+    }
+}
@@ -0,0 +1,258 @@
+//! Tied and fixed operand fixup.
+//!
+//! Some encoding recipes have results that are *tied* to a value operand. The result must be
+//! written to the same register as the tied operand, which means that the tied operand's value is
+//! clobbered by the instruction. This is common for Intel's two-address instructions.
+//!
+//! The coloring pass requires the values used by tied operands to be killed by the instruction,
+//! see the preconditions in the `coloring` module. This pass runs after liveness analysis and
+//! inserts a `copy` of any tied operand value that is still live after the instruction:
+//!
+//! ```cton
+//!     v2 = iadd v0, v1        ; v0 is used later.
+//! ```
+//!
+//! Becomes:
+//!
+//! ```cton
+//!     v3 = copy v0
+//!     v2 = iadd v3, v1
+//! ```
+//!
+//! Copies are only inserted when necessary. If the tied operand value is killed by the
+//! instruction, the result can simply reuse its register.
+//!
+//! Some encoding recipes also require a value operand to be in a *fixed* register, like the shift
+//! amount in `%rcx` for Intel's shift and rotate instructions. The same value can't be in two
+//! registers at once, so `rotr v0, v0` could never satisfy both its tied and fixed operands. This
+//! pass always copies the value used by a fixed register operand immediately before the
+//! instruction, and the coloring pass places the copy in the fixed register:
+//!
+//! ```cton
+//!     v1 = rotr v0, v0
+//! ```
+//!
+//! Becomes:
+//!
+//! ```cton
+//!     v2 = copy v0
+//!     v1 = rotr v0, v2
+//! ```
+//!
+//! The arguments to calls and the return values that the calling convention passes in registers
+//! are fixed register operands too, and they are copied the same way. See `fixed_arg_reg()`.
+//!
+//! The fixed register copies are inserted before the tied operand copies, so the tied operand
+//! copy is never the value evicted from the fixed register.
+//!
+//! Finally, the coloring pass moves the arguments passed by a branch into the registers of the
+//! destination EBB arguments. This only works when every argument is a distinct value killed by
+//! the branch, so this pass copies the arguments that are passed twice or that are still live
+//! after the branch:
+//!
+//! ```cton
+//!     brnz v1, ebb1(v0, v0)
+//! ```
+//!
+//! Becomes:
+//!
+//! ```cton
+//!     v2 = copy v0
+//!     brnz v1, ebb1(v2, v0)
+//! ```
+//!
+//! The live ranges of the copied values are added to the liveness analysis, so it remains valid.
+
+use entity_map::EntityMap;
+use ir::{Function, Cursor, DataFlowGraph, Inst, InstBuilder, Value, Signature, ArgumentLoc,
+         ArgumentPurpose};
+use ir::instructions::BranchInfo;
+use isa::{TargetIsa, ConstraintKind, Encoding, RecipeConstraints, RegUnit};
+use regalloc::affinity::Affinity;
+use regalloc::liveness::Liveness;
+
+/// Insert copies of tied operand values in `func` that are live after the instruction using
+/// them, copies of all fixed register operand values, and copies of branch arguments that aren't
+/// distinct values killed by the branch.
+///
+/// The live ranges in `liveness` must be up to date, and they are updated to include the new
+/// values.
+pub fn fix_tied_operands(isa: &TargetIsa, func: &mut Function, liveness: &mut Liveness) {
+    let encinfo = isa.encoding_info();
+    let reginfo = isa.register_info();
+    let mut pos = Cursor::new(&mut func.layout);
+    while let Some(ebb) = pos.next_ebb() {
+        while let Some(inst) = pos.next_inst() {
+            let constraints = match encinfo.operand_constraints(func.encodings[inst]) {
+                Some(c) => c,
+                None => continue,
+            };
+
+            for arg_index in 0..func.dfg.inst_args(inst).len() {
+                let reg = match fixed_arg_reg(&func.dfg,
+                                              &func.signature,
+                                              inst,
+                                              constraints,
+                                              arg_index) {
+                    Some(reg) => reg,
+                    None => continue,
+                };
+                let affinity = match constraints.ins.get(arg_index) {
+                    Some(opcst) => Affinity::new(opcst),
+                    None => {
+                        let rc = reginfo
+                            .toprc_containing(reg)
+                            .expect("ABI register is not in any register class");
+                        Affinity::Reg(rc.into())
+                    }
+                };
+
+                let arg = func.dfg.inst_args(inst)[arg_index];
+                let copy = insert_copy(isa,
+                                       &mut func.dfg,
+                                       &mut func.encodings,
+                                       &mut pos,
+                                       inst,
+                                       arg_index);
+                debug!("Copied fixed operand {} to {} for {}", arg, copy, inst);
+
+                // The live range of the original value still reaches `inst`, which is
+                // conservative.
+                let copy_inst = func.dfg.value_def(copy).unwrap_inst();
+                liveness.create_dead(copy, copy_inst, affinity);
+                liveness.extend_locally(copy, ebb, inst, pos.layout);
+            }
+
+            for opcst in constraints.outs {
+                let arg_index = match opcst.kind {
+                    ConstraintKind::Tied(n) => n as usize,
+                    _ => continue,
+                };
+
+                // If the tied value is killed here, the result can reuse its register.
+                let arg = func.dfg.inst_args(inst)[arg_index];
+                if !liveness
+                        .get(arg)
+                        .expect("Tied operand has no live range")
+                        .overlaps_def(inst.into(), ebb, pos.layout) {
+                    continue;
+                }
+
+                let copy = insert_copy(isa,
+                                       &mut func.dfg,
+                                       &mut func.encodings,
+                                       &mut pos,
+                                       inst,
+                                       arg_index);
+                debug!("Copied tied operand {} to {} for {}", arg, copy, inst);
+
+                // The original value is live after `inst`, so its live range already covers the
+                // copy. The copy lives from its definition to `inst`.
+                liveness.create_dead(copy,
+                                     func.dfg.value_def(copy).unwrap_inst(),
+                                     Affinity::new(&constraints.ins[arg_index]));
+                liveness.extend_locally(copy, ebb, inst, pos.layout);
+            }
+
+            let num_fixed = func.dfg.inst_fixed_args(inst).len();
+            let (dest, num_ebb_args) = match func.dfg[inst]
+                      .analyze_branch(&func.dfg.value_lists) {
+                BranchInfo::SingleDest(dest, args) => (dest, args.len()),
+                _ => continue,
+            };
+            for arg_index in num_fixed..num_fixed + num_ebb_args {
+                let arg = func.dfg.inst_args(inst)[arg_index];
+                let unique = func.dfg.inst_args(inst)[num_fixed..]
+                    .iter()
+                    .filter(|&&a| a == arg)
+                    .count() == 1;
+                // The live range of a value that is live-in to `dest` may end at the branch.
+                let killed = match liveness.get(arg) {
+                    Some(lr) => {
+                        !lr.overlaps_def(inst.into(), ebb, pos.layout) &&
+                        !lr.is_livein(dest, pos.layout)
+                    }
+                    None => continue,
+                };
+                if unique && killed {
+                    continue;
+                }
+
+                let copy = insert_copy(isa,
+                                       &mut func.dfg,
+                                       &mut func.encodings,
+                                       &mut pos,
+                                       inst,
+                                       arg_index);
+                debug!("Copied branch argument {} to {} for {}", arg, copy, inst);
+
+                let copy_inst = func.dfg.value_def(copy).unwrap_inst();
+                let affinity = encinfo
+                    .operand_constraints(func.encodings[copy_inst])
+                    .map_or(Affinity::Any, |c| Affinity::new(&c.outs[0]));
+                liveness.create_dead(copy, copy_inst, affinity);
+                liveness.extend_locally(copy, ebb, inst, pos.layout);
+            }
+        }
+    }
+}
+
+/// Get the fixed register required for argument `arg_index` of `inst`, if any.
+///
+/// This is either a fixed register operand of the encoding recipe, or an ABI register of a call
+/// argument or a return value. The return values are described by `sig`, the signature of the
+/// function containing `inst`.
+///
+/// The link register return value is not included. It is pre-colored at the entry block, and it
+/// stays in the link register when it isn't evicted.
+pub fn fixed_arg_reg(dfg: &DataFlowGraph,
+                     sig: &Signature,
+                     inst: Inst,
+                     constraints: &RecipeConstraints,
+                     arg_index: usize)
+                     -> Option<RegUnit> {
+    if let Some(reg) = constraints.fixed_input(arg_index) {
+        return Some(reg);
+    }
+
+    // Variable arguments follow the fixed arguments.
+    let var_index = match arg_index.checked_sub(dfg.inst_fixed_args(inst).len()) {
+        Some(var_index) => var_index,
+        None => return None,
+    };
+    let abi_arg = if let Some(sigref) = dfg.call_signature(inst) {
+        dfg.signatures[sigref].argument_types.get(var_index)
+    } else if dfg[inst].opcode().is_return() {
+        match sig.return_types.get(var_index) {
+            Some(arg) if arg.purpose == ArgumentPurpose::Link => None,
+            arg => arg,
+        }
+    } else {
+        None
+    };
+    match abi_arg.map(|arg| arg.location) {
+        Some(ArgumentLoc::Reg(reg)) => Some(reg),
+        _ => None,
+    }
+}
+
+/// Insert a copy of argument `arg_index` before `inst` and make it the argument instead.
+///
+/// Returns the new value.
+fn insert_copy(isa: &TargetIsa,
+               dfg: &mut DataFlowGraph,
+               encodings: &mut EntityMap<Inst, Encoding>,
+               pos: &mut Cursor,
+               inst: Inst,
+               arg_index: usize)
+               -> Value {
+    let arg = dfg.inst_args(inst)[arg_index];
+    let copy = dfg.ins(pos).copy(arg);
+    let copy_inst = dfg.value_def(copy).unwrap_inst();
+    let ctrl_type = dfg.value_type(copy);
+    let enc = isa.encode(dfg, &dfg[copy_inst], ctrl_type)
+        .expect("Can't encode operand copy");
+    *encodings.ensure(copy_inst) = enc;
+    dfg.inst_args_mut(inst)[arg_index] = copy;
+    copy
+}
@@ -0,0 +1,173 @@
+//! Virtual registers.
+//!
+//! A virtual register is a set of related SSA values whose live ranges don't interfere. If all the
+//! values in a virtual register are assigned to the same location, fewer copies will result in the
+//! output.
+//!
+//! A virtual register is typically built by merging together SSA values that are "phi-related" -
+//! that is, one value is passed as an EBB argument to a branch and the other is the EBB argument
+//! value itself - or by merging the argument and result of a `copy` instruction.
+//!
+//! Every SSA value belongs to exactly one virtual register. Values that haven't been merged with
+//! anything else form a singleton virtual register of their own. The members of a virtual
+//! register are kept in a circular linked list, and each value points to a *leader* value that
+//! identifies the virtual register.
+
+use entity_map::EntityMap;
+use ir::Value;
+use packed_option::PackedOption;
+
+/// Collection of virtual registers.
+pub struct VirtRegs {
+    /// The leader of the virtual register containing each value.
+    /// Values without a leader are singletons, and they lead their own virtual register.
+    leaders: EntityMap<Value, PackedOption<Value>>,
+
+    /// The next value in the circular list of virtual register members.
+    /// A missing link means that the value links to itself.
+    links: EntityMap<Value, PackedOption<Value>>,
+}
+
+impl VirtRegs {
+    /// Create a new virtual register collection.
+    pub fn new() -> VirtRegs {
+        VirtRegs {
+            leaders: EntityMap::new(),
+            links: EntityMap::new(),
+        }
+    }
+
+    /// Clear all virtual registers, turning every value into a singleton.
+    pub fn clear(&mut self) {
+        self.leaders.clear();
+        self.links.clear();
+    }
+
+    /// Get the number of bytes of heap memory allocated by the virtual register tables.
+    pub fn mem_usage(&self) -> usize {
+        self.leaders.mem_usage() + self.links.mem_usage()
+    }
+
+    /// Get the value that identifies the virtual register containing `value`.
+    pub fn leader(&self, value: Value) -> Value {
+        self.leaders
+            .get(value)
+            .and_then(|l| l.expand())
+            .unwrap_or(value)
+    }
+
+    /// Are `a` and `b` members of the same virtual register?
+    pub fn same_vreg(&self, a: Value, b: Value) -> bool {
+        self.leader(a) == self.leader(b)
+    }
+
+    /// Get the next member of the virtual register containing `value`.
+    fn next(&self, value: Value) -> Value {
+        self.links
+            .get(value)
+            .and_then(|l| l.expand())
+            .unwrap_or(value)
+    }
+
+    /// Get an iterator over all the values in the virtual register containing `value`.
+    pub fn values(&self, value: Value) -> Values {
+        Values {
+            vregs: self,
+            first: value,
+            next: Some(value),
+        }
+    }
+
+    /// Merge the virtual registers containing `a` and `b`.
+    ///
+    /// The caller is responsible for checking that the values in the two virtual registers don't
+    /// interfere.
+    pub fn union(&mut self, a: Value, b: Value) {
+        let lead_a = self.leader(a);
+        let lead_b = self.leader(b);
+        if lead_a == lead_b {
+            return;
+        }
+
+        // Make `lead_a` the leader of all the values currently in `b`'s virtual register.
+        let mut v = lead_b;
+        loop {
+            *self.leaders.ensure(v) = lead_a.into();
+            v = self.next(v);
+            if v == lead_b {
+                break;
+            }
+        }
+
+        // Splice the two circular lists together.
+        let next_a = self.next(lead_a);
+        let next_b = self.next(lead_b);
+        *self.links.ensure(lead_a) = next_b.into();
+        *self.links.ensure(lead_b) = next_a.into();
+    }
+}
+
+/// Iterator over the values in a virtual register.
+pub struct Values<'a> {
+    vregs: &'a VirtRegs,
+    first: Value,
+    next: Option<Value>,
+}
+
+impl<'a> Iterator for Values<'a> {
+    type Item = Value;
+
+    fn next(&mut self) -> Option<Value> {
+        self.next.map(|v| {
+                          let n = self.vregs.next(v);
+                          self.next = if n == self.first { None } else { Some(n) };
+                          v
+                      })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::VirtRegs;
+    use entity_map::EntityRef;
+    use ir::Value;
+
+    #[test]
+    fn singletons() {
+        let v0 = Value::new(0);
+        let v1 = Value::new(1);
+        let vregs = VirtRegs::new();
+        assert_eq!(vregs.leader(v0), v0);
+        assert!(!vregs.same_vreg(v0, v1));
+        assert_eq!(vregs.values(v1).collect::<Vec<_>>(), [v1]);
+    }
+
+    #[test]
+    fn union() {
+        let v0 = Value::new(0);
+        let v1 = Value::new(1);
+        let v2 = Value::new(2);
+        let v3 = Value::new(3);
+        let mut vregs = VirtRegs::new();
+
+        vregs.union(v0, v1);
+        assert!(vregs.same_vreg(v0, v1));
+        assert!(!vregs.same_vreg(v0, v2));
+
+        vregs.union(v2, v3);
+        vregs.union(v3, v1);
+        assert!(vregs.same_vreg(v0, v3));
+        assert_eq!(vregs.leader(v1), vregs.leader(v2));
+
+        let mut members = vregs.values(v2).collect::<Vec<_>>();
+        members.sort_by_key(|v| v.index());
+        assert_eq!(members, [v0, v1, v2, v3]);
+
+        // Merging members of the same virtual register is a no-op.
+        vregs.union(v0, v3);
+        assert_eq!(vregs.values(v0).count(), 4);
+
+        vregs.clear();
+        assert!(!vregs.same_vreg(v0, v1));
+    }
+}
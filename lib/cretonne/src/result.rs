@@ -1,15 +1,14 @@
 //! Result and error types representing the outcome of compiling a function.
 
+use ir::entities::AnyEntity;
 use verifier;
 use std::error::Error as StdError;
 use std::fmt;
 use std::result;
 
-/// A compilation error.
-///
-/// When Cretonne fails to compile a function, it will return one of these error codes.
+/// The kind of a compilation error.
 #[derive(Debug)]
-pub enum CtonError {
+pub enum ErrorKind {
     /// An IL verifier error.
     ///
     /// This always represents a bug, either in the code that generated IL for Cretonne, or a bug
@@ -29,40 +28,195 @@ pub enum CtonError {
     /// Different target ISAs may impose a limit on the size of a compiled function. If that limit
     /// is exceeded, compilation fails.
     CodeTooLarge,
+
+    /// The target ISA doesn't support something the function needs.
+    ///
+    /// Some of the ISAs are incomplete. For example, ARM32 can't generate function prologues and
+    /// epilogues yet.
+    Unsupported,
+
+    /// A pass was run before a pass it depends on.
+    ///
+    /// This represents a bug in the embedder which must run the passes in the right order, for
+    /// example by using `Context::compile()`.
+    MissingPass {
+        /// The pass that was run.
+        pass: &'static str,
+        /// The pass that should have been run first.
+        requires: &'static str,
+    },
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ErrorKind::Verifier(ref e) => write!(f, "Verifier error: {}", e),
+            ErrorKind::MissingPass { pass, requires } => {
+                write!(f, "Can't run {} before {}", pass, requires)
+            }
+            ErrorKind::ImplLimitExceeded => f.write_str("Implementation limit exceeded"),
+            ErrorKind::CodeTooLarge => f.write_str("Code for function is too large"),
+            ErrorKind::Unsupported => f.write_str("Not supported by the target ISA"),
+        }
+    }
+}
+
+/// A compilation error.
+///
+/// When Cretonne fails to compile a function, it returns one of these. Besides the kind of error,
+/// it records the pass that failed and the entity that caused the failure when they are known,
+/// along with a chain of messages describing what was being done when the error occurred.
+#[derive(Debug)]
+pub struct CtonError {
+    /// The kind of error.
+    pub kind: ErrorKind,
+    /// The name of the pass that failed, like `"regalloc"`.
+    ///
+    /// For verifier errors, this is the pass that produced the invalid function.
+    pub pass: Option<&'static str>,
+    /// The entity causing the error.
+    pub entity: Option<AnyEntity>,
+    /// Messages describing the error, innermost first.
+    ///
+    /// Each caller that handles the error on its way out can add a message with `context()`.
+    pub context: Vec<String>,
 }
 
 /// A Cretonne compilation result.
 pub type CtonResult = result::Result<(), CtonError>;
 
+impl CtonError {
+    /// Create an error of the given kind with no additional information.
+    pub fn new(kind: ErrorKind) -> CtonError {
+        let (pass, entity) = match kind {
+            ErrorKind::Verifier(ref e) => (None, Some(e.location)),
+            ErrorKind::MissingPass { pass, .. } => (Some(pass), None),
+            _ => (None, None),
+        };
+        CtonError {
+            kind: kind,
+            pass: pass,
+            entity: entity,
+            context: Vec::new(),
+        }
+    }
+
+    /// Record that the error occurred in `pass`, unless a pass has been recorded already.
+    pub fn in_pass(mut self, pass: &'static str) -> CtonError {
+        if self.pass.is_none() {
+            self.pass = Some(pass);
+        }
+        self
+    }
+
+    /// Record the entity that caused the error.
+    pub fn at<E: Into<AnyEntity>>(mut self, entity: E) -> CtonError {
+        self.entity = Some(entity.into());
+        self
+    }
+
+    /// Add a message describing the context of the error.
+    pub fn context<S: Into<String>>(mut self, msg: S) -> CtonError {
+        self.context.push(msg.into());
+        self
+    }
+
+    /// Is this a verifier error?
+    pub fn is_verifier(&self) -> bool {
+        match self.kind {
+            ErrorKind::Verifier(_) => true,
+            _ => false,
+        }
+    }
+}
+
+/// The error is displayed as the pass followed by the context messages, outermost first, the
+/// kind of error, and the entity:
+///
+/// ```text
+/// prologue_epilogue: compiling foo: stack frame exceeds 2 GB: Implementation limit exceeded (ss3)
+/// ```
 impl fmt::Display for CtonError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            CtonError::Verifier(ref e) => write!(f, "Verifier error: {}", e),
-            CtonError::ImplLimitExceeded |
-            CtonError::CodeTooLarge => f.write_str(self.description()),
+        // A missing pass error already names the pass.
+        match (self.pass, &self.kind) {
+            (_, &ErrorKind::MissingPass { .. }) |
+            (None, _) => {}
+            (Some(pass), _) => write!(f, "{}: ", pass)?,
+        }
+        for msg in self.context.iter().rev() {
+            write!(f, "{}: ", msg)?;
+        }
+        write!(f, "{}", self.kind)?;
+        // Verifier errors already include their location.
+        match (self.entity, &self.kind) {
+            (_, &ErrorKind::Verifier(_)) |
+            (None, _) => Ok(()),
+            (Some(entity), _) => write!(f, " ({})", entity),
         }
     }
 }
 
 impl StdError for CtonError {
     fn description(&self) -> &str {
-        match *self {
-            CtonError::Verifier(ref e) => &e.message,
-            CtonError::ImplLimitExceeded => "Implementation limit exceeded",
-            CtonError::CodeTooLarge => "Code for function is too large",
+        match self.kind {
+            ErrorKind::Verifier(ref e) => &e.message,
+            ErrorKind::ImplLimitExceeded => "Implementation limit exceeded",
+            ErrorKind::CodeTooLarge => "Code for function is too large",
+            ErrorKind::Unsupported => "Not supported by the target ISA",
+            ErrorKind::MissingPass { .. } => "Pass run before its prerequisites",
         }
     }
     fn cause(&self) -> Option<&StdError> {
-        match *self {
-            CtonError::Verifier(ref e) => Some(e),
-            CtonError::ImplLimitExceeded |
-            CtonError::CodeTooLarge => None,
+        match self.kind {
+            ErrorKind::Verifier(ref e) => Some(e),
+            ErrorKind::ImplLimitExceeded |
+            ErrorKind::CodeTooLarge |
+            ErrorKind::Unsupported |
+            ErrorKind::MissingPass { .. } => None,
         }
     }
 }
 
+impl From<ErrorKind> for CtonError {
+    fn from(kind: ErrorKind) -> CtonError {
+        CtonError::new(kind)
+    }
+}
+
 impl From<verifier::Error> for CtonError {
     fn from(e: verifier::Error) -> CtonError {
-        CtonError::Verifier(e)
+        CtonError::new(ErrorKind::Verifier(e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CtonError, ErrorKind};
+    use entity_map::EntityRef;
+    use ir::{Inst, StackSlot};
+    use verifier;
+
+    #[test]
+    fn display() {
+        let err = CtonError::new(ErrorKind::ImplLimitExceeded)
+            .at(StackSlot::new(3))
+            .context("stack frame exceeds 2 GB")
+            .in_pass("prologue_epilogue")
+            .in_pass("compile")
+            .context("compiling foo");
+        assert_eq!(err.pass, Some("prologue_epilogue"));
+        assert_eq!(err.to_string(),
+                   "prologue_epilogue: compiling foo: stack frame exceeds 2 GB: \
+                    Implementation limit exceeded (ss3)");
+
+        let err = CtonError::from(verifier::Error {
+                                      location: Inst::new(2).into(),
+                                      message: "bad".to_string(),
+                                  })
+                .in_pass("legalize");
+        assert!(err.is_verifier());
+        assert_eq!(err.entity, Some(Inst::new(2).into()));
+        assert_eq!(err.to_string(), "legalize: Verifier error: inst2: bad");
     }
 }
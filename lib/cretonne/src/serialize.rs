@@ -0,0 +1,1018 @@
+//! Binary serialization of functions.
+//!
+//! Parsing and printing the textual IL is relatively slow. This module provides a compact binary
+//! encoding of a `Function` which is cheap to produce and to decode, so functions can be cached on
+//! disk or shipped between build machines.
+//!
+//! The encoded function is canonicalized like the `compact` pass does: EBBs, instructions, and
+//! values are renumbered densely in layout order, value aliases are resolved, and entities that
+//! are not in the layout are dropped. Decoding recreates the entities in the same order, so a
+//! round trip produces the compacted function. The stack slots, jump tables, global variables,
+//! signatures, and external functions keep their numbers.
+//!
+//! The encodings, value locations, source locations, and cold EBBs are preserved. The code offsets
+//! and the frame layout are not; they can be recomputed by running `Context::relax_branches()`
+//! and `Context::prologue_epilogue()` again.
+//!
+//! # Format
+//!
+//! The stream begins with the magic bytes `CTON` followed by `FORMAT_VERSION`. Integers are
+//! LEB128 varints, using a zigzag encoding for signed numbers. Opcodes, condition codes, and other
+//! enumerations that the IL text format spells out are encoded by name, so adding an instruction
+//! doesn't invalidate existing encodings. Any other change to the format must bump
+//! `FORMAT_VERSION`.
+//!
+//! The decoded function is not verified. Run the verifier on functions decoded from an untrusted
+//! source.
+
+use compact::do_compact;
+use entity_map::EntityRef;
+use ir::{Function, FunctionName, Signature, CallConv, ArgumentType, ArgumentExtension,
+         ArgumentPurpose, ArgumentLoc, ExtFuncData, StackSlotData, StackSlotKind, JumpTableData,
+         GlobalVarData, TableData, ValueLoc, InstructionData, Opcode, ValueList, ValueListPool,
+         Type, MemFlags, MemOrder, SourceLoc, Ebb, Value, StackSlot, JumpTable, GlobalVar, Table,
+         FuncRef, SigRef};
+use ir::condcodes::{IntCC, FloatCC};
+use ir::immediates::{Imm64, Ieee32, Ieee64, Offset32, Uoffset32};
+use ir::instructions::InstructionFormat;
+use ir::types;
+use isa::Encoding;
+use std::error::Error as StdError;
+use std::fmt::{self, Display, Formatter};
+use std::result;
+use std::str::FromStr;
+
+/// Magic bytes at the start of an encoded function.
+pub const MAGIC: &'static [u8; 4] = b"CTON";
+
+/// Version of the binary format written by `serialize_function()`.
+///
+/// The decoder rejects any other version.
+pub const FORMAT_VERSION: u32 = 2;
+
+/// An error decoding a function.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Error {
+    /// Byte offset of the error in the input.
+    pub offset: usize,
+    /// Error message.
+    pub message: String,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "offset {}: {}", self.offset, self.message)
+    }
+}
+
+impl StdError for Error {
+    fn description(&self) -> &str {
+        &self.message
+    }
+}
+
+/// Decoding result.
+pub type Result<T> = result::Result<T, Error>;
+
+/// Encode `func` in the binary format.
+pub fn serialize_function(func: &Function) -> Vec<u8> {
+    let mut func = func.clone();
+    do_compact(&mut func);
+
+    let mut w = Writer { bytes: Vec::new() };
+    w.bytes.extend_from_slice(MAGIC);
+    w.uvar(FORMAT_VERSION as u64);
+
+    // Table of the opcodes used in the function. Instructions refer to it by index.
+    let mut opcodes = Vec::new();
+    let mut opcode_index = Vec::new();
+    for ebb in func.layout.ebbs() {
+        for inst in func.layout.ebb_insts(ebb) {
+            let opcode = func.dfg[inst].opcode();
+            let i = opcode as usize;
+            if i >= opcode_index.len() {
+                opcode_index.resize(i + 1, None);
+            }
+            if opcode_index[i].is_none() {
+                opcode_index[i] = Some(opcodes.len());
+                opcodes.push(opcode);
+            }
+        }
+    }
+    w.uvar(opcodes.len() as u64);
+    for opcode in &opcodes {
+        w.str(&opcode.to_string());
+    }
+
+    // Preamble.
+    func.name.encode(&mut w);
+    func.signature.encode(&mut w);
+    w.uvar(func.dfg.signatures.len() as u64);
+    for sig in func.dfg.signatures.keys() {
+        func.dfg.signatures[sig].encode(&mut w);
+    }
+    w.uvar(func.dfg.ext_funcs.len() as u64);
+    for fref in func.dfg.ext_funcs.keys() {
+        let ext = &func.dfg.ext_funcs[fref];
+        ext.name.encode(&mut w);
+        ext.signature.encode(&mut w);
+        ext.colocated.encode(&mut w);
+    }
+    w.uvar(func.stack_slots.len() as u64);
+    for ss in func.stack_slots.keys() {
+        let data = &func.stack_slots[ss];
+        data.kind.encode(&mut w);
+        data.size.encode(&mut w);
+        data.offset.encode(&mut w);
+    }
+    w.uvar(func.global_vars.len() as u64);
+    for gv in func.global_vars.keys() {
+        let data = &func.global_vars[gv];
+        data.name.encode(&mut w);
+        data.tls.encode(&mut w);
+    }
+    w.uvar(func.tables.len() as u64);
+    for table in func.tables.keys() {
+        let data = &func.tables[table];
+        data.base.encode(&mut w);
+        data.bound.encode(&mut w);
+        data.element_size.encode(&mut w);
+    }
+    w.uvar(func.jump_tables.len() as u64);
+    for jt in func.jump_tables.keys() {
+        let data = &func.jump_tables[jt];
+        w.uvar(data.len() as u64);
+        for idx in 0..data.len() {
+            // Holes are encoded as 0.
+            w.uvar(data.get_entry(idx).map_or(0, |ebb| ebb.index() as u64 + 1));
+        }
+    }
+
+    // Function body. The compacted entities are numbered in the order they are written.
+    w.uvar(func.layout.ebbs().count() as u64);
+    for ebb in func.layout.ebbs() {
+        func.is_cold(ebb).encode(&mut w);
+        let args = func.dfg.ebb_args(ebb);
+        w.uvar(args.len() as u64);
+        for &arg in args {
+            func.dfg.value_type(arg).encode(&mut w);
+            func.locations.get(arg).cloned().unwrap_or_default().encode(&mut w);
+        }
+        w.uvar(func.layout.ebb_insts(ebb).count() as u64);
+        for inst in func.layout.ebb_insts(ebb) {
+            let data = &func.dfg[inst];
+            let opcode_idx = opcode_index[data.opcode() as usize].expect("opcode table");
+            w.uvar(opcode_idx as u64);
+            encode_inst_data(data, &func.dfg.value_lists, &mut w);
+
+            let results = func.dfg.inst_results(inst);
+            w.uvar(results.len() as u64);
+            for &res in results {
+                func.dfg.value_type(res).encode(&mut w);
+                func.locations.get(res).cloned().unwrap_or_default().encode(&mut w);
+            }
+
+            let enc = func.encodings.get(inst).cloned();
+            let srcloc = func.srclocs.get(inst).cloned();
+            w.byte(enc.map_or(0, |_| 1) | srcloc.map_or(0, |_| 2));
+            if let Some(enc) = enc {
+                (enc.recipe() as u16).encode(&mut w);
+                enc.bits().encode(&mut w);
+            }
+            if let Some(srcloc) = srcloc {
+                srcloc.bits().encode(&mut w);
+            }
+        }
+    }
+
+    w.bytes
+}
+
+/// Decode a function encoded by `serialize_function()`.
+pub fn deserialize_function(bytes: &[u8]) -> Result<Function> {
+    let mut r = Reader {
+        bytes: bytes,
+        pos: 0,
+    };
+    if !bytes.starts_with(MAGIC) {
+        return r.err("not an encoded Cretonne function");
+    }
+    r.pos = MAGIC.len();
+    let version = r.uvar()?;
+    if version != FORMAT_VERSION as u64 {
+        return r.err(format!("unsupported format version {}, expected {}",
+                             version,
+                             FORMAT_VERSION));
+    }
+
+    let mut opcodes = Vec::new();
+    for _ in 0..r.count()? {
+        let name = r.str()?;
+        match name.parse::<Opcode>() {
+            Ok(opcode) => opcodes.push(opcode),
+            Err(_) => return r.err(format!("unknown opcode '{}'", name)),
+        }
+    }
+
+    let name = FunctionName::decode(&mut r)?;
+    let sig = Signature::decode(&mut r)?;
+    let mut func = Function::with_name_signature(name, sig);
+    for _ in 0..r.count()? {
+        let sig = Signature::decode(&mut r)?;
+        func.dfg.signatures.push(sig);
+    }
+    for _ in 0..r.count()? {
+        let ext = ExtFuncData {
+            name: Decode::decode(&mut r)?,
+            signature: Decode::decode(&mut r)?,
+            colocated: Decode::decode(&mut r)?,
+        };
+        func.dfg.ext_funcs.push(ext);
+    }
+    for _ in 0..r.count()? {
+        let data = StackSlotData {
+            kind: Decode::decode(&mut r)?,
+            size: Decode::decode(&mut r)?,
+            offset: Decode::decode(&mut r)?,
+        };
+        func.stack_slots.push(data);
+    }
+    for _ in 0..r.count()? {
+        let data = GlobalVarData {
+            name: Decode::decode(&mut r)?,
+            tls: Decode::decode(&mut r)?,
+        };
+        func.global_vars.push(data);
+    }
+    for _ in 0..r.count()? {
+        let data = TableData {
+            base: Decode::decode(&mut r)?,
+            bound: Decode::decode(&mut r)?,
+            element_size: Decode::decode(&mut r)?,
+        };
+        func.tables.push(data);
+    }
+    for _ in 0..r.count()? {
+        let mut data = JumpTableData::new();
+        let len = r.count()?;
+        for idx in 0..len {
+            match r.uvar()? {
+                0 => {}
+                n => {
+                    let ebb = r.entity_index(n - 1, "Ebb")?;
+                    data.set_entry(idx, Ebb::new(ebb));
+                }
+            }
+        }
+        // Make room for trailing holes.
+        if len > data.len() {
+            data.set_entry(len - 1, Ebb::new(0));
+            data.clear_entry(len - 1);
+        }
+        func.jump_tables.push(data);
+    }
+
+    // Create the EBBs first, so branches can refer to them.
+    let num_ebbs = r.count()?;
+    let ebbs: Vec<Ebb> = (0..num_ebbs).map(|_| func.dfg.make_ebb()).collect();
+    for &ebb in &ebbs {
+        func.layout.append_ebb(ebb);
+        if bool::decode(&mut r)? {
+            func.set_cold(ebb);
+        }
+        for _ in 0..r.count()? {
+            let ty = Type::decode(&mut r)?;
+            let arg = func.dfg.append_ebb_arg(ebb, ty);
+            let loc = ValueLoc::decode(&mut r)?;
+            if loc != ValueLoc::default() {
+                *func.locations.ensure(arg) = loc;
+            }
+        }
+        for _ in 0..r.count()? {
+            let opcode = match opcodes.get(r.uvar()? as usize) {
+                Some(&opcode) => opcode,
+                None => return r.err("opcode index out of range"),
+            };
+            let data = decode_inst_data(opcode, &mut func.dfg.value_lists, &mut r)?;
+            let inst = func.dfg.make_inst(data);
+            func.layout.append_inst(inst, ebb);
+            for _ in 0..r.count()? {
+                let ty = Type::decode(&mut r)?;
+                let res = func.dfg.append_result(inst, ty);
+                let loc = ValueLoc::decode(&mut r)?;
+                if loc != ValueLoc::default() {
+                    *func.locations.ensure(res) = loc;
+                }
+            }
+
+            let flags = r.byte()?;
+            if flags & !3 != 0 {
+                return r.err(format!("invalid instruction flags {:#x}", flags));
+            }
+            if flags & 1 != 0 {
+                let recipe = u16::decode(&mut r)?;
+                let bits = u16::decode(&mut r)?;
+                *func.encodings.ensure(inst) = Encoding::new(recipe, bits);
+            }
+            if flags & 2 != 0 {
+                *func.srclocs.ensure(inst) = SourceLoc::new(u32::decode(&mut r)?);
+            }
+        }
+    }
+
+    if r.pos != bytes.len() {
+        return r.err("trailing bytes after function");
+    }
+    Ok(func)
+}
+
+/// Output stream.
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn byte(&mut self, b: u8) {
+        self.bytes.push(b);
+    }
+
+    /// Write an unsigned LEB128 varint.
+    fn uvar(&mut self, mut x: u64) {
+        while x >= 0x80 {
+            self.byte(x as u8 | 0x80);
+            x >>= 7;
+        }
+        self.byte(x as u8);
+    }
+
+    /// Write a zigzag-encoded signed varint.
+    fn svar(&mut self, x: i64) {
+        self.uvar(((x << 1) ^ (x >> 63)) as u64);
+    }
+
+    fn str(&mut self, s: &str) {
+        self.uvar(s.len() as u64);
+        self.bytes.extend_from_slice(s.as_bytes());
+    }
+
+    fn value_list(&mut self, values: &[Value]) {
+        self.uvar(values.len() as u64);
+        for v in values {
+            v.encode(self);
+        }
+    }
+}
+
+/// Input stream.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn err<T, S: Into<String>>(&self, message: S) -> Result<T> {
+        Err(Error {
+                offset: self.pos,
+                message: message.into(),
+            })
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        match self.bytes.get(self.pos) {
+            Some(&b) => {
+                self.pos += 1;
+                Ok(b)
+            }
+            None => self.err("unexpected end of input"),
+        }
+    }
+
+    fn uvar(&mut self) -> Result<u64> {
+        let mut x = 0;
+        let mut shift = 0;
+        loop {
+            let b = self.byte()?;
+            if shift > 63 || (shift == 63 && b > 1) {
+                return self.err("varint overflow");
+            }
+            x |= ((b & 0x7f) as u64) << shift;
+            if b & 0x80 == 0 {
+                return Ok(x);
+            }
+            shift += 7;
+        }
+    }
+
+    fn svar(&mut self) -> Result<i64> {
+        let x = self.uvar()?;
+        Ok((x >> 1) as i64 ^ -((x & 1) as i64))
+    }
+
+    /// Read the number of elements in a sequence.
+    ///
+    /// Every element takes at least one byte, so a count that exceeds the remaining input is
+    /// rejected before anything is allocated for it.
+    fn count(&mut self) -> Result<usize> {
+        let n = self.uvar()?;
+        if n > (self.bytes.len() - self.pos) as u64 {
+            return self.err(format!("count {} exceeds the remaining input", n));
+        }
+        Ok(n as usize)
+    }
+
+    /// Check that a decoded entity index fits in the 32-bit index space of an entity reference.
+    fn entity_index(&self, x: u64, what: &str) -> Result<usize> {
+        if x >= u32::max_value() as u64 {
+            return self.err(format!("{} index {} out of range", what, x));
+        }
+        Ok(x as usize)
+    }
+
+    fn str(&mut self) -> Result<&'a str> {
+        let len = self.count()?;
+        let bytes = &self.bytes[self.pos..self.pos + len];
+        match ::std::str::from_utf8(bytes) {
+            Ok(s) => {
+                self.pos += len;
+                Ok(s)
+            }
+            Err(_) => self.err("invalid UTF-8 string"),
+        }
+    }
+
+    fn value_list(&mut self, pool: &mut ValueListPool) -> Result<ValueList> {
+        let mut list = ValueList::new();
+        for _ in 0..self.count()? {
+            let v = Value::decode(self)?;
+            list.push(v, pool);
+        }
+        Ok(list)
+    }
+
+    /// Read a string and parse it as a `T`.
+    fn parse<T: FromStr>(&mut self, what: &str) -> Result<T> {
+        let s = self.str()?;
+        match s.parse() {
+            Ok(x) => Ok(x),
+            Err(_) => self.err(format!("invalid {} '{}'", what, s)),
+        }
+    }
+}
+
+/// A type that can be written to the binary format.
+trait Encode {
+    fn encode(&self, w: &mut Writer);
+}
+
+/// A type that can be read from the binary format.
+trait Decode: Sized {
+    fn decode(r: &mut Reader) -> Result<Self>;
+}
+
+impl Encode for bool {
+    fn encode(&self, w: &mut Writer) {
+        w.byte(*self as u8);
+    }
+}
+
+impl Decode for bool {
+    fn decode(r: &mut Reader) -> Result<bool> {
+        match r.byte()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            b => r.err(format!("invalid bool {}", b)),
+        }
+    }
+}
+
+// Unsigned integers are varints.
+macro_rules! unsigned_impl {
+    ($ty:ident) => {
+        impl Encode for $ty {
+            fn encode(&self, w: &mut Writer) {
+                w.uvar(*self as u64);
+            }
+        }
+
+        impl Decode for $ty {
+            fn decode(r: &mut Reader) -> Result<$ty> {
+                let x = r.uvar()?;
+                if x > $ty::max_value() as u64 {
+                    return r.err(format!("{} out of range for {}", x, stringify!($ty)));
+                }
+                Ok(x as $ty)
+            }
+        }
+    }
+}
+
+unsigned_impl!(u8);
+unsigned_impl!(u16);
+unsigned_impl!(u32);
+unsigned_impl!(u64);
+
+impl Encode for i32 {
+    fn encode(&self, w: &mut Writer) {
+        w.svar(*self as i64);
+    }
+}
+
+impl Decode for i32 {
+    fn decode(r: &mut Reader) -> Result<i32> {
+        let x = r.svar()?;
+        if x < i32::min_value() as i64 || x > i32::max_value() as i64 {
+            return r.err(format!("{} out of range for i32", x));
+        }
+        Ok(x as i32)
+    }
+}
+
+impl Encode for Imm64 {
+    fn encode(&self, w: &mut Writer) {
+        w.svar((*self).into());
+    }
+}
+
+impl Decode for Imm64 {
+    fn decode(r: &mut Reader) -> Result<Imm64> {
+        r.svar().map(Imm64::new)
+    }
+}
+
+impl Encode for Offset32 {
+    fn encode(&self, w: &mut Writer) {
+        let x: i32 = (*self).into();
+        x.encode(w);
+    }
+}
+
+impl Decode for Offset32 {
+    fn decode(r: &mut Reader) -> Result<Offset32> {
+        i32::decode(r).map(Offset32::new)
+    }
+}
+
+impl Encode for Uoffset32 {
+    fn encode(&self, w: &mut Writer) {
+        let x: u32 = (*self).into();
+        x.encode(w);
+    }
+}
+
+impl Decode for Uoffset32 {
+    fn decode(r: &mut Reader) -> Result<Uoffset32> {
+        u32::decode(r).map(Uoffset32::new)
+    }
+}
+
+impl Encode for Ieee32 {
+    fn encode(&self, w: &mut Writer) {
+        w.bytes.extend_from_slice(&[self.bits() as u8,
+                                    (self.bits() >> 8) as u8,
+                                    (self.bits() >> 16) as u8,
+                                    (self.bits() >> 24) as u8]);
+    }
+}
+
+impl Decode for Ieee32 {
+    fn decode(r: &mut Reader) -> Result<Ieee32> {
+        let mut bits = 0;
+        for i in 0..4 {
+            bits |= (r.byte()? as u32) << (8 * i);
+        }
+        Ok(Ieee32::from_bits(bits))
+    }
+}
+
+impl Encode for Ieee64 {
+    fn encode(&self, w: &mut Writer) {
+        for i in 0..8 {
+            w.byte((self.bits() >> (8 * i)) as u8);
+        }
+    }
+}
+
+impl Decode for Ieee64 {
+    fn decode(r: &mut Reader) -> Result<Ieee64> {
+        let mut bits = 0;
+        for i in 0..8 {
+            bits |= (r.byte()? as u64) << (8 * i);
+        }
+        Ok(Ieee64::from_bits(bits))
+    }
+}
+
+impl Encode for FunctionName {
+    fn encode(&self, w: &mut Writer) {
+        w.str(self.as_str());
+    }
+}
+
+impl Decode for FunctionName {
+    fn decode(r: &mut Reader) -> Result<FunctionName> {
+        r.str().map(FunctionName::new)
+    }
+}
+
+// Entity references are encoded as their index.
+macro_rules! entity_impl {
+    ($entity:ident) => {
+        impl Encode for $entity {
+            fn encode(&self, w: &mut Writer) {
+                w.uvar(self.index() as u64);
+            }
+        }
+
+        impl Decode for $entity {
+            fn decode(r: &mut Reader) -> Result<$entity> {
+                let x = r.uvar()?;
+                r.entity_index(x, stringify!($entity)).map($entity::new)
+            }
+        }
+    }
+}
+
+entity_impl!(Ebb);
+entity_impl!(Value);
+entity_impl!(StackSlot);
+entity_impl!(JumpTable);
+entity_impl!(GlobalVar);
+entity_impl!(Table);
+entity_impl!(FuncRef);
+entity_impl!(SigRef);
+
+// Enumerations with a text format spelling are encoded by name.
+macro_rules! name_impl {
+    ($ty:ident, $what:expr) => {
+        impl Encode for $ty {
+            fn encode(&self, w: &mut Writer) {
+                w.str(&self.to_string());
+            }
+        }
+
+        impl Decode for $ty {
+            fn decode(r: &mut Reader) -> Result<$ty> {
+                r.parse($what)
+            }
+        }
+    }
+}
+
+name_impl!(IntCC, "integer condition code");
+name_impl!(FloatCC, "float condition code");
+name_impl!(MemOrder, "memory ordering");
+name_impl!(CallConv, "calling convention");
+name_impl!(ArgumentPurpose, "argument purpose");
+name_impl!(StackSlotKind, "stack slot kind");
+
+impl Encode for MemFlags {
+    fn encode(&self, w: &mut Writer) {
+        w.byte(self.notrap() as u8 | (self.aligned() as u8) << 1);
+    }
+}
+
+impl Decode for MemFlags {
+    fn decode(r: &mut Reader) -> Result<MemFlags> {
+        let bits = r.byte()?;
+        if bits & !3 != 0 {
+            return r.err(format!("invalid memory flags {:#x}", bits));
+        }
+        let mut flags = MemFlags::new();
+        if bits & 1 != 0 {
+            flags.set_notrap();
+        }
+        if bits & 2 != 0 {
+            flags.set_aligned();
+        }
+        Ok(flags)
+    }
+}
+
+/// Lane types in the order of their type codes.
+const LANE_TYPES: [Type; 12] = [types::VOID,
+                                types::B1,
+                                types::B8,
+                                types::B16,
+                                types::B32,
+                                types::B64,
+                                types::I8,
+                                types::I16,
+                                types::I32,
+                                types::I64,
+                                types::F32,
+                                types::F64];
+
+impl Encode for Type {
+    fn encode(&self, w: &mut Writer) {
+        let lane = self.lane_type();
+        let code = LANE_TYPES
+            .iter()
+            .position(|&t| t == lane)
+            .expect("unknown lane type");
+        w.byte(code as u8 | self.log2_lane_count() << 4);
+    }
+}
+
+impl Decode for Type {
+    fn decode(r: &mut Reader) -> Result<Type> {
+        let b = r.byte()?;
+        let lane = LANE_TYPES.get((b & 0xf) as usize).cloned();
+        match lane.and_then(|t| t.by(1 << (b >> 4))) {
+            Some(ty) => Ok(ty),
+            None => r.err(format!("invalid type code {:#x}", b)),
+        }
+    }
+}
+
+impl Encode for ValueLoc {
+    fn encode(&self, w: &mut Writer) {
+        match *self {
+            ValueLoc::Unassigned => w.byte(0),
+            ValueLoc::Reg(ru) => {
+                w.byte(1);
+                ru.encode(w);
+            }
+            ValueLoc::RegPair(ru) => {
+                w.byte(2);
+                ru.encode(w);
+            }
+            ValueLoc::Stack(ss) => {
+                w.byte(3);
+                ss.encode(w);
+            }
+        }
+    }
+}
+
+impl Decode for ValueLoc {
+    fn decode(r: &mut Reader) -> Result<ValueLoc> {
+        match r.byte()? {
+            0 => Ok(ValueLoc::Unassigned),
+            1 => Decode::decode(r).map(ValueLoc::Reg),
+            2 => Decode::decode(r).map(ValueLoc::RegPair),
+            3 => Decode::decode(r).map(ValueLoc::Stack),
+            b => r.err(format!("invalid value location kind {}", b)),
+        }
+    }
+}
+
+impl Encode for ArgumentType {
+    fn encode(&self, w: &mut Writer) {
+        self.value_type.encode(w);
+        self.purpose.encode(w);
+        w.byte(match self.extension {
+                   ArgumentExtension::None => 0,
+                   ArgumentExtension::Uext => 1,
+                   ArgumentExtension::Sext => 2,
+               });
+        match self.location {
+            ArgumentLoc::Unassigned => w.byte(0),
+            ArgumentLoc::Reg(ru) => {
+                w.byte(1);
+                ru.encode(w);
+            }
+            ArgumentLoc::Stack(offset) => {
+                w.byte(2);
+                offset.encode(w);
+            }
+        }
+    }
+}
+
+impl Decode for ArgumentType {
+    fn decode(r: &mut Reader) -> Result<ArgumentType> {
+        let mut arg = ArgumentType::new(Type::decode(r)?);
+        arg.purpose = ArgumentPurpose::decode(r)?;
+        arg.extension = match r.byte()? {
+            0 => ArgumentExtension::None,
+            1 => ArgumentExtension::Uext,
+            2 => ArgumentExtension::Sext,
+            b => return r.err(format!("invalid argument extension {}", b)),
+        };
+        arg.location = match r.byte()? {
+            0 => ArgumentLoc::Unassigned,
+            1 => ArgumentLoc::Reg(Decode::decode(r)?),
+            2 => ArgumentLoc::Stack(Decode::decode(r)?),
+            b => return r.err(format!("invalid argument location kind {}", b)),
+        };
+        Ok(arg)
+    }
+}
+
+impl Encode for Signature {
+    fn encode(&self, w: &mut Writer) {
+        for list in &[&self.argument_types, &self.return_types] {
+            w.uvar(list.len() as u64);
+            for arg in list.iter() {
+                arg.encode(w);
+            }
+        }
+        match self.argument_bytes {
+            None => w.byte(0),
+            Some(bytes) => {
+                w.byte(1);
+                bytes.encode(w);
+            }
+        }
+        self.call_conv.encode(w);
+    }
+}
+
+impl Decode for Signature {
+    fn decode(r: &mut Reader) -> Result<Signature> {
+        let mut sig = Signature::new();
+        for _ in 0..r.count()? {
+            let arg = ArgumentType::decode(r)?;
+            sig.argument_types.push(arg);
+        }
+        for _ in 0..r.count()? {
+            let arg = ArgumentType::decode(r)?;
+            sig.return_types.push(arg);
+        }
+        sig.argument_bytes = match r.byte()? {
+            0 => None,
+            1 => Some(Decode::decode(r)?),
+            b => return r.err(format!("invalid argument bytes flag {}", b)),
+        };
+        sig.call_conv = CallConv::decode(r)?;
+        Ok(sig)
+    }
+}
+
+// Include code generated by `lib/cretonne/meta/gen_instr.py`. This file contains the
+// `encode_inst_data()` and `decode_inst_data()` functions which handle the operands of each
+// instruction format.
+include!(concat!(env!("OUT_DIR"), "/inst_serialize.rs"));
+
+#[cfg(test)]
+mod tests {
+    use super::{serialize_function, deserialize_function, FORMAT_VERSION};
+    use Context;
+    use compact::do_compact;
+    use entity_map::EntityRef;
+    use ir::{Function, FunctionName, Signature, ArgumentType, ExtFuncData, StackSlotData,
+             StackSlotKind, JumpTableData, GlobalVarData, Cursor, InstBuilder, MemFlags,
+             SourceLoc, ValueLoc, Ebb, StackSlot};
+    use ir::condcodes::IntCC;
+    use ir::immediates::Ieee32;
+    use ir::types::{I32, I64, F32, I32X4};
+    use isa;
+    use settings::{self, Configurable};
+
+    fn sample() -> Function {
+        let mut sig = Signature::new();
+        sig.argument_types.push(ArgumentType::new(I64));
+        sig.return_types.push(ArgumentType::new(I32));
+        let mut func = Function::with_name_signature(FunctionName::new("sample"), sig.clone());
+        let sigref = func.dfg.signatures.push(sig);
+        let callee = func.dfg
+            .ext_funcs
+            .push(ExtFuncData {
+                      name: FunctionName::new("callee"),
+                      signature: sigref,
+                      colocated: true,
+                  });
+        let ss = func.stack_slots
+            .push(StackSlotData::new(StackSlotKind::Local, 16));
+        let gv = func.global_vars
+            .push(GlobalVarData::new(FunctionName::new("tls"), true));
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        let ebb2 = func.dfg.make_ebb();
+        let mut jt = JumpTableData::new();
+        jt.set_entry(0, ebb1);
+        jt.set_entry(2, ebb2);
+        let jt = func.jump_tables.push(jt);
+        let arg = func.dfg.append_ebb_arg(ebb0, I64);
+        let ebb2_arg = func.dfg.append_ebb_arg(ebb2, I32);
+        {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            let mut flags = MemFlags::new();
+            flags.set_aligned();
+            let x = dfg.ins(pos).load(I32, flags, arg, -8);
+            dfg.ins(pos).stack_store(x, ss, 4);
+            dfg.ins(pos).f32const(Ieee32::new(-1.5));
+            dfg.ins(pos).tls_value(I64, gv);
+            let call = dfg.ins(pos).call(callee, &[arg]);
+            let r = dfg.inst_results(call)[0];
+            let c = dfg.ins(pos).icmp(IntCC::UnsignedLessThan, x, r);
+            dfg.ins(pos).brnz(c, ebb2, &[r]);
+            dfg.ins(pos).br_table(x, jt);
+            dfg.ins(pos).jump(ebb1, &[]);
+            pos.insert_ebb(ebb1);
+            let v = dfg.ins(pos).iconst(I32X4.lane_type(), -1000);
+            dfg.ins(pos).return_(&[v]);
+            pos.insert_ebb(ebb2);
+            dfg.ins(pos).return_(&[ebb2_arg]);
+        }
+        func.set_cold(ebb2);
+        let first = func.layout.ebb_insts(ebb0).next().unwrap();
+        *func.srclocs.ensure(first) = SourceLoc::new(42);
+        *func.locations.ensure(ebb2_arg) = ValueLoc::Stack(ss);
+        func
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut func = sample();
+        let bytes = serialize_function(&func);
+        let copy = deserialize_function(&bytes).unwrap();
+        assert_eq!(serialize_function(&copy), bytes);
+
+        // The decoded function is the compacted original.
+        do_compact(&mut func);
+        assert_eq!(copy.to_string(), func.to_string());
+        let ebb2 = Ebb::new(2);
+        assert_eq!(copy.locations[copy.dfg.ebb_args(ebb2)[0]],
+                   ValueLoc::Stack(StackSlot::new(0)));
+        let first = copy.layout.ebb_insts(Ebb::new(0)).next().unwrap();
+        assert_eq!(copy.srclocs[first], SourceLoc::new(42));
+        assert!(copy.is_cold(ebb2));
+
+        // The float and vector types survive the trip through the type codes.
+        let mut func = Function::new();
+        let ebb = func.dfg.make_ebb();
+        func.dfg.append_ebb_arg(ebb, F32);
+        func.dfg.append_ebb_arg(ebb, I32X4);
+        func.layout.append_ebb(ebb);
+        let copy = deserialize_function(&serialize_function(&func)).unwrap();
+        assert_eq!(copy.to_string(), "function \"\"() {\nebb0(v0: f32, v1: i32x4):\n}\n");
+    }
+
+    #[test]
+    fn compiled() {
+        let mut shared_builder = settings::builder();
+        shared_builder.set_bool("is_64bit", true).unwrap();
+        let shared_flags = settings::Flags::new(&shared_builder);
+        let isa = isa::lookup("intel").unwrap().finish(shared_flags);
+
+        let mut ctx = Context::new();
+        ctx.func.signature.argument_types.push(ArgumentType::new(I32));
+        ctx.func.signature.return_types.push(ArgumentType::new(I32));
+        let ebb0 = ctx.func.dfg.make_ebb();
+        let arg = ctx.func.dfg.append_ebb_arg(ebb0, I32);
+        {
+            let dfg = &mut ctx.func.dfg;
+            let pos = &mut Cursor::new(&mut ctx.func.layout);
+            pos.insert_ebb(ebb0);
+            let v = dfg.ins(pos).iadd(arg, arg);
+            dfg.ins(pos).return_(&[v]);
+        }
+        ctx.compile(&*isa).unwrap();
+
+        let copy = deserialize_function(&serialize_function(&ctx.func)).unwrap();
+        assert_eq!(copy.display(&*isa).to_string(),
+                   ctx.func.display(&*isa).to_string());
+    }
+
+    #[test]
+    fn errors() {
+        let bytes = serialize_function(&sample());
+        let err = deserialize_function(b"CTNO").unwrap_err();
+        assert_eq!(err.to_string(), "offset 0: not an encoded Cretonne function");
+
+        let mut bad_version = bytes.clone();
+        bad_version[4] = FORMAT_VERSION as u8 + 1;
+        let err = deserialize_function(&bad_version).unwrap_err();
+        assert_eq!(err.offset, 5);
+        assert_eq!(err.message,
+                   format!("unsupported format version {}, expected {}",
+                           FORMAT_VERSION + 1,
+                           FORMAT_VERSION));
+
+        // Every truncation of a valid encoding is an error, not a panic.
+        for len in 0..bytes.len() {
+            assert!(deserialize_function(&bytes[..len]).is_err());
+        }
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(deserialize_function(&trailing).unwrap_err().message,
+                   "trailing bytes after function");
+    }
+
+    #[test]
+    fn corrupt_jump_table() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        func.layout.append_ebb(ebb0);
+        let mut jt = JumpTableData::new();
+        jt.set_entry(0, ebb0);
+        let jt = func.jump_tables.push(jt);
+        let bytes = serialize_function(&func);
+
+        // The only byte that changes when the entry becomes a hole is the entry itself.
+        func.jump_tables[jt].clear_entry(0);
+        let hole = serialize_function(&func);
+        let pos = (0..bytes.len()).find(|&i| bytes[i] != hole[i]).unwrap();
+
+        // Splice in an entry that is out of range for an `Ebb`.
+        let mut corrupt = bytes[..pos].to_vec();
+        corrupt.extend_from_slice(&[0x81, 0x80, 0x80, 0x80, 0x10]);
+        corrupt.extend_from_slice(&bytes[pos + 1..]);
+        assert_eq!(deserialize_function(&corrupt).unwrap_err().message,
+                   "Ebb index 4294967296 out of range");
+    }
+
+}
@@ -19,6 +19,21 @@
 //! let f = settings::Flags::new(&b);
 //! assert_eq!(f.opt_level(), settings::OptLevel::Fastest);
 //! ```
+//!
+//! Settings can also be given as a comma-separated list like on a command line:
+//!
+//! ```
+//! use cretonne::settings::{self, Configurable};
+//!
+//! let mut b = settings::builder();
+//! b.enable_all("enable_verifier,opt_level=best,is_64bit").unwrap();
+//! assert_eq!(b.enable_all("opt_level=slowest").unwrap_err().to_string(),
+//!            "invalid setting value: 'opt_level=slowest'");
+//!
+//! let f = settings::Flags::new(&b);
+//! assert_eq!(f.opt_level(), settings::OptLevel::Best);
+//! assert!(f.enable_verifier());
+//! ```
 
 use std::fmt;
 use std::result;
@@ -39,9 +54,31 @@ pub trait Configurable {
     ///
     /// If the identified setting isn't a boolean, a `BadType` error is returned.
     fn set_bool(&mut self, name: &str, value: bool) -> Result<()>;
+
+    /// Apply a single option of the form `name=value`, or a bare `name` which enables a boolean
+    /// setting.
+    fn enable(&mut self, option: &str) -> Result<()> {
+        match option.find('=') {
+            Some(pos) => self.set(&option[..pos], &option[pos + 1..]),
+            None => self.set_bool(option, true),
+        }
+    }
+
+    /// Apply a comma-separated list of options like `"enable_verifier,opt_level=best"`.
+    ///
+    /// Each option is applied with `enable()`. Empty options are ignored. Stop at the first option
+    /// that can't be applied and return an error identifying it.
+    fn enable_all(&mut self, options: &str) -> result::Result<(), OptionError> {
+        for option in options.split(',').map(str::trim).filter(|o| !o.is_empty()) {
+            self.enable(option)
+                .map_err(|error| OptionError::new(option, error))?;
+        }
+        Ok(())
+    }
 }
 
 /// Collect settings values based on a template.
+#[derive(Clone)]
 pub struct Builder {
     template: &'static detail::Template,
     bytes: Vec<u8>,
@@ -149,6 +186,35 @@ pub enum Error {
 /// A result returned when changing a setting.
 pub type Result<T> = result::Result<T, Error>;
 
+/// An error produced when applying a textual option, along with the offending option.
+#[derive(Debug, PartialEq, Eq)]
+pub struct OptionError {
+    /// The option as it was written, like `opt_level=best`.
+    pub option: String,
+    /// The reason the option couldn't be applied.
+    pub error: Error,
+}
+
+impl OptionError {
+    /// Create an error for `option`.
+    pub fn new(option: &str, error: Error) -> OptionError {
+        OptionError {
+            option: option.to_string(),
+            error: error,
+        }
+    }
+}
+
+impl fmt::Display for OptionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.error {
+            Error::BadName => write!(f, "unknown setting '{}'", self.option),
+            Error::BadType => write!(f, "invalid setting type: '{}'", self.option),
+            Error::BadValue => write!(f, "invalid setting value: '{}'", self.option),
+        }
+    }
+}
+
 /// Implementation details for generated code.
 ///
 /// This module holds definitions that need to be public so the can be instantiated by generated
@@ -260,7 +326,7 @@ include!(concat!(env!("OUT_DIR"), "/settings.rs"));
 
 #[cfg(test)]
 mod tests {
-    use super::{builder, Flags};
+    use super::{builder, Flags, OptionError};
     use super::Error::*;
     use super::Configurable;
 
@@ -271,12 +337,24 @@ mod tests {
         assert_eq!(f.to_string(),
                    "[shared]\n\
                     opt_level = \"default\"\n\
+                    regalloc = \"coloring\"\n\
                     enable_verifier = false\n\
                     is_64bit = false\n\
                     is_compressed = false\n\
+                    is_pic = false\n\
+                    tls_model = \"initial_exec\"\n\
                     enable_float = true\n\
                     enable_simd = true\n\
-                    enable_atomics = true\n");
+                    enable_atomics = true\n\
+                    enable_pinned_reg = false\n\
+                    heap_style = \"dynamic\"\n\
+                    static_heap_bound_log2 = 32\n\
+                    static_heap_guard_log2 = 31\n\
+                    enable_probestack = true\n\
+                    probestack_size_log2 = 12\n\
+                    function_alignment_log2 = 4\n\
+                    align_loops = false\n\
+                    loop_alignment_log2 = 4\n");
         assert_eq!(f.opt_level(), super::OptLevel::Default);
         assert_eq!(f.enable_simd(), true);
     }
@@ -306,4 +384,26 @@ mod tests {
         assert_eq!(f.enable_simd(), false);
         assert_eq!(f.opt_level(), super::OptLevel::Best);
     }
+
+    #[test]
+    fn enable_all() {
+        let mut b = builder();
+        assert_eq!(b.enable("opt_level=fastest"), Ok(()));
+        assert_eq!(b.enable_all(" enable_verifier, probestack_size_log2=16,,"),
+                   Ok(()));
+        assert_eq!(b.enable_all("is_pic,opt_level,is_64bit"),
+                   Err(OptionError::new("opt_level", BadType)));
+        assert_eq!(b.enable_all("is_64bit=maybe").unwrap_err().to_string(),
+                   "invalid setting value: 'is_64bit=maybe'");
+        assert_eq!(b.enable_all("is_pie").unwrap_err().to_string(),
+                   "unknown setting 'is_pie'");
+
+        let f = Flags::new(&b);
+        assert_eq!(f.opt_level(), super::OptLevel::Fastest);
+        assert!(f.enable_verifier());
+        assert_eq!(f.probestack_size_log2(), 16);
+        // Options before the failing one were applied.
+        assert!(f.is_pic());
+        assert!(!f.is_64bit());
+    }
 }
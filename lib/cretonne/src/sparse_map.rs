@@ -86,6 +86,13 @@ impl<K, V> SparseMap<K, V>
         self.dense.clear();
     }
 
+    /// Get the number of bytes of heap memory allocated by this map.
+    ///
+    /// This does not include any memory owned by the values themselves.
+    pub fn mem_usage(&self) -> usize {
+        self.sparse.mem_usage() + self.dense.capacity() * mem::size_of::<V>()
+    }
+
     /// Returns a reference to the value corresponding to the key.
     pub fn get(&self, key: K) -> Option<&V> {
         if let Some(idx) = self.sparse.get(key).cloned() {
@@ -0,0 +1,180 @@
+//! Computing stack layout.
+//!
+//! After register allocation, all the stack slots in a function are known, and they can be
+//! assigned offsets in the stack frame. The frame is addressed relative to the stack pointer after
+//! the function prologue has adjusted it, so all slot offsets are non-negative.
+//!
+//! The bottom of the frame is reserved for outgoing call arguments. Their offsets are fixed by the
+//! calling convention, and the reserved area is large enough for the call with the most stack
+//! arguments.
+//!
+//! Incoming stack arguments are above the frame, beyond anything the prologue pushes. They are
+//! placed by `layout_incoming_args()` once the prologue knows its size.
+
+use entity_map::EntityMap;
+use ir::{StackSlot, StackSlotData, StackSlotKind};
+use result::{CtonError, ErrorKind};
+
+/// Compute the stack frame layout.
+///
+/// Assign an offset to every stack slot in `stack_slots` except outgoing and incoming arguments,
+/// which get their offsets from the calling convention. Each slot is aligned to the smallest power
+/// of two that holds it, but not more than `alignment` bytes, which must be a power of two.
+///
+/// On success, return the total size of the stack frame which is a multiple of `alignment`.
+pub fn layout_stack(stack_slots: &mut EntityMap<StackSlot, StackSlotData>,
+                    alignment: u32)
+                    -> Result<u32, CtonError> {
+    assert!(alignment.is_power_of_two(), "Bad stack alignment {}", alignment);
+
+    // Stack frames larger than 2 GB are not supported.
+    let max_size = i32::max_value() as u32;
+
+    // Reserve the outgoing argument area.
+    let mut offset = 0u32;
+    for ss in stack_slots.keys() {
+        let slot = &stack_slots[ss];
+        if slot.kind == StackSlotKind::OutgoingArg {
+            assert!(slot.offset >= 0, "Negative outgoing argument offset");
+            let end = (slot.offset as u32)
+                .checked_add(slot.size)
+                .ok_or_else(|| frame_too_large().at(ss))?;
+            offset = offset.max(end);
+        }
+    }
+
+    for ss in stack_slots.keys() {
+        let slot = &mut stack_slots[ss];
+        if slot.kind == StackSlotKind::OutgoingArg || slot.kind == StackSlotKind::IncomingArg {
+            continue;
+        }
+        let align = slot.size.next_power_of_two().min(alignment);
+        offset = align_to(offset, align)?;
+        slot.offset = offset as i32;
+        offset = offset
+            .checked_add(slot.size)
+            .ok_or_else(|| frame_too_large().at(ss))?;
+    }
+
+    let size = align_to(offset, alignment)?;
+    if size > max_size {
+        Err(frame_too_large())
+    } else {
+        Ok(size)
+    }
+}
+
+/// Make the offsets of the incoming argument slots relative to the stack pointer after the
+/// prologue.
+///
+/// The stack pointer after the prologue is `call_offset` bytes below the stack pointer at the
+/// call, which includes the stack frame and anything pushed by the call and the prologue.
+pub fn layout_incoming_args(stack_slots: &mut EntityMap<StackSlot, StackSlotData>,
+                            call_offset: u32)
+                            -> Result<(), CtonError> {
+    for ss in stack_slots.keys() {
+        let slot = &mut stack_slots[ss];
+        if slot.kind == StackSlotKind::IncomingArg {
+            slot.offset = slot.offset
+                .checked_add(call_offset as i32)
+                .ok_or_else(|| frame_too_large().at(ss))?;
+        }
+    }
+    Ok(())
+}
+
+/// Get the size of the stack frame laid out by `layout_stack()`.
+///
+/// This is the end of the highest stack slot, before rounding up to the stack alignment.
+/// Incoming arguments are not part of the frame.
+pub fn frame_size(stack_slots: &EntityMap<StackSlot, StackSlotData>) -> u32 {
+    stack_slots
+        .keys()
+        .filter(|&ss| stack_slots[ss].kind != StackSlotKind::IncomingArg)
+        .map(|ss| {
+                 let slot = &stack_slots[ss];
+                 (slot.offset as u32).saturating_add(slot.size)
+             })
+        .max()
+        .unwrap_or(0)
+}
+
+/// Round `offset` up to a multiple of `align`, a power of two.
+fn align_to(offset: u32, align: u32) -> Result<u32, CtonError> {
+    offset
+        .checked_add(align - 1)
+        .map(|x| x & !(align - 1))
+        .ok_or_else(frame_too_large)
+}
+
+/// The error returned when the stack frame is larger than 2 GB.
+pub fn frame_too_large() -> CtonError {
+    CtonError::new(ErrorKind::ImplLimitExceeded).context("stack frame exceeds 2 GB")
+}
+
+#[cfg(test)]
+mod tests {
+    use ir::{Function, StackSlotData, StackSlotKind};
+    use super::{layout_stack, layout_incoming_args, frame_size};
+
+    #[test]
+    fn layout() {
+        let mut func = Function::new();
+        assert_eq!(layout_stack(&mut func.stack_slots, 16).unwrap(), 0);
+        assert_eq!(frame_size(&func.stack_slots), 0);
+
+        let ss0 = func.stack_slots
+            .push(StackSlotData::new(StackSlotKind::Local, 1));
+        let ss1 = func.stack_slots
+            .push(StackSlotData::new(StackSlotKind::SpillSlot, 8));
+        let ss2 = func.stack_slots
+            .push(StackSlotData::new(StackSlotKind::SpillSlot, 4));
+        let ss3 = func.stack_slots
+            .push(StackSlotData::new(StackSlotKind::Local, 24));
+
+        assert_eq!(layout_stack(&mut func.stack_slots, 16).unwrap(), 64);
+        assert_eq!(func.stack_slots[ss0].offset, 0);
+        assert_eq!(func.stack_slots[ss1].offset, 8);
+        assert_eq!(func.stack_slots[ss2].offset, 16);
+        assert_eq!(func.stack_slots[ss3].offset, 32);
+        assert_eq!(frame_size(&func.stack_slots), 56);
+
+        // With a smaller alignment, the 24-byte slot only needs 4-byte alignment.
+        assert_eq!(layout_stack(&mut func.stack_slots, 4).unwrap(), 40);
+        assert_eq!(func.stack_slots[ss1].offset, 4);
+        assert_eq!(func.stack_slots[ss2].offset, 12);
+        assert_eq!(func.stack_slots[ss3].offset, 16);
+    }
+
+    #[test]
+    fn outgoing_args() {
+        let mut func = Function::new();
+        let ss0 = func.stack_slots
+            .push(StackSlotData::new(StackSlotKind::SpillSlot, 4));
+        let ss1 = func.stack_slots.push(StackSlotData::outgoing_arg(4, 4));
+        let ss2 = func.stack_slots.push(StackSlotData::outgoing_arg(8, 0));
+
+        // The spill slot goes above the 8-byte outgoing argument area.
+        assert_eq!(layout_stack(&mut func.stack_slots, 16).unwrap(), 16);
+        assert_eq!(func.stack_slots[ss0].offset, 8);
+        assert_eq!(func.stack_slots[ss1].offset, 4);
+        assert_eq!(func.stack_slots[ss2].offset, 0);
+    }
+
+    #[test]
+    fn incoming_args() {
+        let mut func = Function::new();
+        let ss0 = func.stack_slots
+            .push(StackSlotData::new(StackSlotKind::SpillSlot, 8));
+        let ss1 = func.stack_slots.push(StackSlotData::incoming_arg(8, 0));
+
+        // Incoming arguments are not part of the frame.
+        assert_eq!(layout_stack(&mut func.stack_slots, 16).unwrap(), 16);
+        assert_eq!(frame_size(&func.stack_slots), 8);
+        assert_eq!(func.stack_slots[ss0].offset, 0);
+        assert_eq!(func.stack_slots[ss1].offset, 0);
+
+        layout_incoming_args(&mut func.stack_slots, 24).unwrap();
+        assert_eq!(func.stack_slots[ss1].offset, 24);
+    }
+}
@@ -0,0 +1,114 @@
+//! Pass timing.
+//!
+//! The compilation passes run by a `Context` are timed, and the times are accumulated in the
+//! context's `PassTimes` until it is cleared. This makes it possible to attribute compile time
+//! regressions to a specific pass, even when the time is spread over many small functions.
+//!
+//! The `Display` implementation prints a report with one line per pass.
+
+use std::fmt;
+use std::time::Duration;
+
+/// The accumulated time spent in a single pass.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PassTime {
+    /// The name of the pass.
+    pub name: &'static str,
+    /// Total time spent in the pass.
+    pub total: Duration,
+    /// Number of times the pass was run.
+    pub runs: u32,
+}
+
+/// Accumulated times for all the passes that have been run.
+#[derive(Clone, Debug)]
+pub struct PassTimes {
+    // Passes in the order they were first run.
+    passes: Vec<PassTime>,
+}
+
+impl PassTimes {
+    /// Create an empty set of pass times.
+    pub fn new() -> PassTimes {
+        PassTimes { passes: Vec::new() }
+    }
+
+    /// Forget all the accumulated times.
+    pub fn clear(&mut self) {
+        self.passes.clear();
+    }
+
+    /// Add a run of the pass `name` which took `time`.
+    pub fn add(&mut self, name: &'static str, time: Duration) {
+        if let Some(pass) = self.passes.iter_mut().find(|p| p.name == name) {
+            pass.total += time;
+            pass.runs += 1;
+            return;
+        }
+        self.passes.push(PassTime {
+                             name: name,
+                             total: time,
+                             runs: 1,
+                         });
+    }
+
+    /// Get the accumulated pass times in the order the passes were first run.
+    pub fn passes(&self) -> &[PassTime] {
+        &self.passes
+    }
+
+    /// Get the total time spent in all passes.
+    pub fn total(&self) -> Duration {
+        self.passes
+            .iter()
+            .fold(Duration::new(0, 0), |sum, p| sum + p.total)
+    }
+}
+
+/// Format a duration in milliseconds.
+fn millis(d: Duration) -> f64 {
+    d.as_secs() as f64 * 1e3 + d.subsec_nanos() as f64 * 1e-6
+}
+
+impl fmt::Display for PassTimes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "  Time (ms)   Runs  Pass")?;
+        for pass in &self.passes {
+            writeln!(f,
+                     "{:>11.3} {:>6}  {}",
+                     millis(pass.total),
+                     pass.runs,
+                     pass.name)?;
+        }
+        writeln!(f, "{:>11.3}         total", millis(self.total()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PassTimes;
+    use std::time::Duration;
+
+    #[test]
+    fn accumulate() {
+        let mut times = PassTimes::new();
+        times.add("legalize", Duration::from_millis(2));
+        times.add("regalloc", Duration::from_millis(5));
+        times.add("legalize", Duration::new(1, 500000));
+        times.add("dce", Duration::from_millis(0));
+
+        let passes = times.passes();
+        assert_eq!(passes.len(), 3);
+        assert_eq!(passes[0].name, "legalize");
+        assert_eq!(passes[0].runs, 2);
+        assert_eq!(passes[0].total, Duration::new(1, 2500000));
+        assert_eq!(passes[2].name, "dce");
+        assert_eq!(times.total(), Duration::new(1, 7500000));
+
+        let report = times.to_string();
+        assert_eq!(report.lines().nth(1), Some("   1002.500      2  legalize"));
+
+        times.clear();
+        assert!(times.passes().is_empty());
+    }
+}
@@ -0,0 +1,64 @@
+//! Feature verifier.
+//!
+//! The `enable_float`, `enable_simd`, and `enable_atomics` settings control which IL features a
+//! function is allowed to use. An embedder can turn them off to reject functions that use
+//! features it doesn't support, independently of what the target ISA is able to encode.
+
+use ir::{Function, Type, InstructionData};
+use settings::Flags;
+use verifier::Result;
+
+/// Verify that `func` only uses the IL features enabled in `flags`.
+///
+/// - Float types, including vectors of floats, require `enable_float`.
+/// - Vector types require `enable_simd`.
+/// - Atomic memory operations require `enable_atomics`. Fences are always allowed.
+pub fn verify_features(func: &Function, flags: &Flags) -> Result {
+    if flags.enable_float() && flags.enable_simd() && flags.enable_atomics() {
+        return Ok(());
+    }
+
+    for ebb in func.layout.ebbs() {
+        for &arg in func.dfg.ebb_args(ebb) {
+            let ty = func.dfg.value_type(arg);
+            if let Some(setting) = disabled_by(ty, flags) {
+                return err!(ebb, "argument {} has type {} which needs {}", arg, ty, setting);
+            }
+        }
+
+        for inst in func.layout.ebb_insts(ebb) {
+            if !flags.enable_atomics() {
+                match func.dfg[inst] {
+                    InstructionData::AtomicLoad { opcode, .. } |
+                    InstructionData::AtomicRmw { opcode, .. } |
+                    InstructionData::AtomicCas { opcode, .. } => {
+                        return err!(inst, "{} needs enable_atomics", opcode);
+                    }
+                    _ => {}
+                }
+            }
+
+            for &value in func.dfg
+                    .inst_results(inst)
+                    .iter()
+                    .chain(func.dfg.inst_args(inst)) {
+                let ty = func.dfg.value_type(value);
+                if let Some(setting) = disabled_by(ty, flags) {
+                    return err!(inst, "{} has type {} which needs {}", value, ty, setting);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Get the name of the disabled setting that is needed to use values of type `ty`.
+fn disabled_by(ty: Type, flags: &Flags) -> Option<&'static str> {
+    if !ty.is_scalar() && !flags.enable_simd() {
+        Some("enable_simd")
+    } else if ty.lane_type().is_float() && !flags.enable_float() {
+        Some("enable_float")
+    } else {
+        None
+    }
+}
@@ -1,12 +1,11 @@
 //! Liveness verifier.
 
 use flowgraph::ControlFlowGraph;
-use ir::{Function, Inst, Value, ProgramOrder, ProgramPoint, ExpandedProgramPoint};
+use ir::{Function, Inst, Value, ProgramPoint, ExpandedProgramPoint};
 use ir::entities::AnyEntity;
 use isa::TargetIsa;
 use regalloc::liveness::Liveness;
 use regalloc::liverange::LiveRange;
-use std::cmp::Ordering;
 use verifier::Result;
 
 /// Verify liveness information for `func`.
@@ -88,18 +87,7 @@ impl<'a> LivenessVerifier<'a> {
     /// Is `lr` live at the use `inst`?
     fn live_at_use(&self, lr: &LiveRange, inst: Inst) -> bool {
         let l = &self.func.layout;
-
-        // Check if `inst` is in the def range, not including the def itself.
-        if l.cmp(lr.def(), inst) == Ordering::Less &&
-           l.cmp(inst, lr.def_local_end()) != Ordering::Greater {
-            return true;
-        }
-
-        // Otherwise see if `inst` is in one of the live-in ranges.
-        match lr.livein_local_end(l.inst_ebb(inst).unwrap(), l) {
-            Some(end) => l.cmp(inst, end) != Ordering::Greater,
-            None => false,
-        }
+        lr.reaches_use(inst, l.inst_ebb(inst).unwrap(), l)
     }
 
     /// Check the integrity of the live range `lr`.
@@ -0,0 +1,190 @@
+//! Value location verifier.
+
+use ir::{Function, Ebb, Inst, Value, ValueLoc};
+use ir::instructions::BranchInfo;
+use isa::{TargetIsa, Encoding, OperandConstraint, ConstraintKind};
+use regalloc::RegDiversions;
+use regalloc::liveness::Liveness;
+use verifier::Result;
+
+/// Verify that the value locations in `func` satisfy the register constraints of the instruction
+/// encodings.
+///
+/// This is meant to run after register allocation when all values have been assigned a location.
+/// The constraints come from `isa.operand_constraints()`.
+///
+/// - Value operands and results must be in a register of the right class, in the required fixed
+///   register, or in a stack slot.
+/// - Tied results must be in the same register as their value operand.
+/// - When `liveness` is provided, values that are live across an instruction must not be in a
+///   register clobbered by the instruction. This includes the registers clobbered by calls as
+///   described by `isa.inst_clobbers()`.
+/// - Arguments passed by a branch must be in the locations of the destination EBB arguments.
+///   When `liveness` is provided, values that are live-in to the destination must not be diverted
+///   at the branch.
+///
+/// Register diversions made by `regmove` instructions are tracked within each EBB.
+pub fn verify_locations(isa: &TargetIsa, func: &Function, liveness: Option<&Liveness>) -> Result {
+    let verifier = LocationVerifier {
+        isa: isa,
+        func: func,
+        liveness: liveness,
+    };
+    verifier.check_insts()
+}
+
+struct LocationVerifier<'a> {
+    isa: &'a TargetIsa,
+    func: &'a Function,
+    liveness: Option<&'a Liveness>,
+}
+
+impl<'a> LocationVerifier<'a> {
+    /// Check the constraints on all encoded instructions.
+    fn check_insts(&self) -> Result {
+        let mut divert = RegDiversions::new();
+
+        for ebb in self.func.layout.ebbs() {
+            divert.clear();
+            let mut insts = self.func.layout.ebb_insts(ebb).peekable();
+            while let Some(inst) = insts.next() {
+                if let BranchInfo::SingleDest(dest, args) =
+                    self.func.dfg[inst].analyze_branch(&self.func.dfg.value_lists) {
+                    self.check_branch_args(inst, dest, args, &divert)?;
+                }
+
+                let enc = self.func.encodings[inst];
+                let constraints = match self.isa.operand_constraints(enc) {
+                    Some(constraints) => constraints,
+                    None => continue,
+                };
+
+                let args = self.func.dfg.inst_fixed_args(inst);
+                for (&arg, cst) in args.iter().zip(constraints.ins) {
+                    let loc = divert.get(arg, &self.func.locations);
+                    self.check_constraint(inst, arg, loc, cst)?;
+                }
+
+                divert.apply(&self.func.dfg[inst]);
+
+                let results = self.func.dfg.inst_results(inst);
+                for (num, (&res, cst)) in results.iter().zip(constraints.outs).enumerate() {
+                    let loc = self.func.locations[res];
+                    if let Some(tied) = constraints.tied_input(num) {
+                        let tied_loc = divert.get(args[tied], &self.func.locations);
+                        if loc != tied_loc {
+                            return err!(inst,
+                                        "{} is not in the same location as its tied operand {}",
+                                        res,
+                                        args[tied]);
+                        }
+                    }
+                    self.check_constraint(inst, res, loc, cst)?;
+                }
+
+                self.check_clobbers(inst, insts.peek().cloned(), enc, &divert)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that `value` at location `loc` satisfies the operand constraint `cst`.
+    fn check_constraint(&self,
+                        inst: Inst,
+                        value: Value,
+                        loc: ValueLoc,
+                        cst: &OperandConstraint)
+                        -> Result {
+        match (cst.kind, loc) {
+            (ConstraintKind::Stack, ValueLoc::Stack(_)) => Ok(()),
+            (ConstraintKind::Stack, _) => err!(inst, "{} must be in a stack slot", value),
+            (ConstraintKind::FixedReg(reg), ValueLoc::Reg(unit)) if reg == unit => Ok(()),
+            (ConstraintKind::FixedReg(reg), _) => {
+                err!(inst,
+                     "{} must be in the fixed register {}",
+                     value,
+                     self.isa.register_info().display_regunit(reg))
+            }
+            (_, ValueLoc::Reg(unit)) if cst.regclass.contains(unit) => Ok(()),
+            (_, _) => err!(inst, "{} must be in a {} register", value, cst.regclass.name),
+        }
+    }
+
+    /// Check that the arguments `args` passed to `dest` by the branch `inst` are in the locations
+    /// of the EBB arguments, and that no value live-in to `dest` is diverted.
+    fn check_branch_args(&self,
+                         inst: Inst,
+                         dest: Ebb,
+                         args: &[Value],
+                         divert: &RegDiversions)
+                         -> Result {
+        let reginfo = self.isa.register_info();
+        for (&arg, &ebb_arg) in args.iter().zip(self.func.dfg.ebb_args(dest)) {
+            let loc = divert.get(arg, &self.func.locations);
+            let ebb_loc = self.func.locations[ebb_arg];
+            if loc != ebb_loc && loc != ValueLoc::Unassigned && ebb_loc != ValueLoc::Unassigned {
+                return err!(inst,
+                            "{} is in {}, but the EBB argument {} is in {}",
+                            arg,
+                            loc.display(&reginfo),
+                            ebb_arg,
+                            ebb_loc.display(&reginfo));
+            }
+        }
+
+        if let Some(liveness) = self.liveness {
+            for d in divert.all() {
+                let livein = liveness
+                    .get(d.value)
+                    .map_or(false, |lr| lr.is_livein(dest, &self.func.layout));
+                if livein {
+                    return err!(inst, "{} is diverted when branching to {}", d.value, dest);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that no value live across `inst` is in a register clobbered by `inst`.
+    ///
+    /// The instruction following `inst` in its EBB is `next`.
+    fn check_clobbers(&self,
+                      inst: Inst,
+                      next: Option<Inst>,
+                      enc: Encoding,
+                      divert: &RegDiversions)
+                      -> Result {
+        let liveness = match self.liveness {
+            Some(liveness) => liveness,
+            None => return Ok(()),
+        };
+        let clobbers = self.isa.inst_clobbers(&self.func.dfg, inst, enc);
+        if clobbers.is_empty() {
+            return Ok(());
+        }
+        let layout = &self.func.layout;
+        let ebb = layout.inst_ebb(inst).unwrap();
+        let next = match next {
+            Some(next) => next,
+            None => return Ok(()),
+        };
+
+        for lr in liveness.iter() {
+            let value = lr.value();
+            if self.func.locations.get(value).is_none() {
+                continue;
+            }
+            let reg = match divert.get(value, &self.func.locations) {
+                ValueLoc::Reg(reg) if clobbers.contains(&reg) => reg,
+                _ => continue,
+            };
+            if lr.reaches_use(inst, ebb, layout) && lr.reaches_use(next, ebb, layout) {
+                return err!(inst,
+                            "{} is live across the instruction in clobbered register {}",
+                            value,
+                            self.isa.register_info().display_regunit(reg));
+            }
+        }
+        Ok(())
+    }
+}
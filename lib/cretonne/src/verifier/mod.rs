@@ -14,6 +14,8 @@
 //!    - The instruction format must match the opcode.
 //!    - All result values must be created for multi-valued instructions.
 //!    - All referenced entities must exist. (Values, EBBs, stack slots, ...)
+//!    - Atomic loads can't have release semantics, atomic stores can't have acquire semantics,
+//!      and fences can't be relaxed.
 //!
 //!   SSA form
 //!
@@ -40,6 +42,16 @@
 //!    - All return instructions must have return value operands matching the current
 //!      function signature.
 //!
+//!   Features enabled by the settings (`verify_features()`)
+//!
+//!    - Float types, vector types, and atomic instructions can only be used when the
+//!      `enable_float`, `enable_simd`, and `enable_atomics` settings are on respectively.
+//!
+//!   Value locations, after register allocation (`verify_locations()`)
+//!
+//!    - Operands and results must satisfy the register constraints of their encoding.
+//!    - Values live across an instruction can't be in a register clobbered by it.
+//!
 //! TODO:
 //!   Ad hoc checking
 //!
@@ -56,14 +68,16 @@ use dominator_tree::DominatorTree;
 use flowgraph::ControlFlowGraph;
 use ir::entities::AnyEntity;
 use ir::instructions::{InstructionFormat, BranchInfo, ResolvedConstraint, CallInfo};
-use ir::{types, Function, ValueDef, Ebb, Inst, SigRef, FuncRef, ValueList, JumpTable, StackSlot,
-         Value, Type};
+use ir::{types, Function, ValueDef, Ebb, Inst, SigRef, FuncRef, ValueList, JumpTable, GlobalVar,
+         Table, StackSlot, Value, Type, InstructionData, Opcode, MemOrder};
 use std::error as std_error;
 use std::fmt::{self, Display, Formatter};
 use std::result;
 use std::collections::BTreeSet;
 
+pub use self::features::verify_features;
 pub use self::liveness::verify_liveness;
+pub use self::locations::verify_locations;
 
 // Create an `Err` variant of `Result<X>` from a location and `format!` arguments.
 macro_rules! err {
@@ -82,7 +96,9 @@ macro_rules! err {
     };
 }
 
+mod features;
 mod liveness;
+mod locations;
 
 /// A verifier error.
 #[derive(Debug, PartialEq, Eq)]
@@ -186,6 +202,16 @@ impl<'a> Verifier<'a> {
             return err!(inst, "instruction opcode doesn't match instruction format");
         }
 
+        // The fixed value operands are stored at the front of the value list.
+        let format = inst_data.opcode().format();
+        let num_args = dfg.inst_args(inst).len();
+        if format.has_value_list() && num_args < format.num_value_operands() {
+            return err!(inst,
+                        "expected at least {} value operands, found {}",
+                        format.num_value_operands(),
+                        num_args);
+        }
+
         let fixed_results = inst_data.opcode().constraints().fixed_results();
         // var_results is 0 if we aren't a call instruction
         let var_results = dfg.call_signature(inst)
@@ -202,7 +228,8 @@ impl<'a> Verifier<'a> {
                         got_results);
         }
 
-        self.verify_entity_references(inst)
+        self.verify_entity_references(inst)?;
+        self.verify_memory_order(inst)
     }
 
     fn verify_entity_references(&self, inst: Inst) -> Result {
@@ -244,7 +271,9 @@ impl<'a> Verifier<'a> {
                 self.verify_ebb(inst, destination)?;
                 self.verify_value_list(inst, args)?;
             }
-            &BranchTable { table, .. } => {
+            &BranchTable { table, .. } |
+            &BranchTableBase { table, .. } |
+            &BranchTableEntry { table, .. } => {
                 self.verify_jump_table(inst, table)?;
             }
             &Call { func_ref, ref args, .. } => {
@@ -255,6 +284,20 @@ impl<'a> Verifier<'a> {
                 self.verify_sig_ref(inst, sig_ref)?;
                 self.verify_value_list(inst, args)?;
             }
+            &FuncAddr { func_ref, .. } => {
+                self.verify_func_ref(inst, func_ref)?;
+            }
+            &UnaryGlobalVar { global_var, .. } => {
+                self.verify_global_var(inst, global_var)?;
+                // All the instructions that reference global variables access thread-local
+                // storage.
+                if !self.func.global_vars[global_var].tls {
+                    return err!(inst, "{} is not a thread-local variable", global_var);
+                }
+            }
+            &TableAddr { table, .. } => {
+                self.verify_table(inst, table)?;
+            }
             &StackLoad { stack_slot, .. } |
             &StackStore { stack_slot, .. } => {
                 self.verify_stack_slot(inst, stack_slot)?;
@@ -277,12 +320,46 @@ impl<'a> Verifier<'a> {
             &HeapLoad { .. } |
             &HeapStore { .. } |
             &Load { .. } |
-            &Store { .. } => {}
+            &Store { .. } |
+            &AtomicLoad { .. } |
+            &AtomicRmw { .. } |
+            &AtomicCas { .. } |
+            &Fence { .. } |
+            &RegMove { .. } => {}
         }
 
         Ok(())
     }
 
+    /// Check that the memory ordering of an atomic load, store, or fence makes sense. A load can't
+    /// have release semantics, a store can't have acquire semantics, and a fence must order
+    /// something.
+    fn verify_memory_order(&self, inst: Inst) -> Result {
+        match self.func.dfg[inst] {
+            InstructionData::AtomicLoad { order, .. } => {
+                if order.is_release() && order != MemOrder::SeqCst {
+                    return err!(inst, "atomic load can't have {} ordering", order);
+                }
+            }
+            InstructionData::AtomicRmw {
+                opcode: Opcode::AtomicStore,
+                order,
+                ..
+            } => {
+                if order.is_acquire() && order != MemOrder::SeqCst {
+                    return err!(inst, "atomic store can't have {} ordering", order);
+                }
+            }
+            InstructionData::Fence { order, .. } => {
+                if order == MemOrder::Relaxed {
+                    return err!(inst, "fence can't have {} ordering", order);
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
     fn verify_ebb(&self, inst: Inst, e: Ebb) -> Result {
         if !self.func.dfg.ebb_is_valid(e) {
             err!(inst, "invalid ebb reference {}", e)
@@ -307,6 +384,27 @@ impl<'a> Verifier<'a> {
         }
     }
 
+    fn verify_global_var(&self, inst: Inst, gv: GlobalVar) -> Result {
+        if !self.func.global_vars.is_valid(gv) {
+            err!(inst, "invalid global variable {}", gv)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn verify_table(&self, inst: Inst, table: Table) -> Result {
+        if !self.func.tables.is_valid(table) {
+            return err!(inst, "invalid table {}", table);
+        }
+        // The table base is a thread-local variable like the other global variable references.
+        let base = self.func.tables[table].base;
+        self.verify_global_var(inst, base)?;
+        if !self.func.global_vars[base].tls {
+            return err!(inst, "the base of {}, {}, is not a thread-local variable", table, base);
+        }
+        Ok(())
+    }
+
     fn verify_stack_slot(&self, inst: Inst, ss: StackSlot) -> Result {
         if !self.func.stack_slots.is_valid(ss) {
             err!(inst, "invalid stack slot {}", ss)
@@ -446,10 +544,28 @@ impl<'a> Verifier<'a> {
         self.typecheck_fixed_args(inst, ctrl_type)?;
         self.typecheck_variable_args(inst)?;
         self.typecheck_return(inst)?;
+        self.typecheck_bitcast(inst, ctrl_type)?;
 
         Ok(())
     }
 
+    /// Check that a `bitcast` doesn't change the number of bits in the value.
+    fn typecheck_bitcast(&self, inst: Inst, ctrl_type: Type) -> Result {
+        if let InstructionData::Unary {
+                   opcode: Opcode::Bitcast,
+                   arg,
+               } = self.func.dfg[inst] {
+            let arg_type = self.func.dfg.value_type(arg);
+            if arg_type.bits() != ctrl_type.bits() {
+                return err!(inst,
+                            "bitcast from {} to {} changes the number of bits",
+                            arg_type,
+                            ctrl_type);
+            }
+        }
+        Ok(())
+    }
+
     fn typecheck_results(&self, inst: Inst, ctrl_type: Type) -> Result {
         let mut i = 0;
         for &result in self.func.dfg.inst_results(inst) {
@@ -673,8 +789,9 @@ impl<'a> Verifier<'a> {
 #[cfg(test)]
 mod tests {
     use super::{Verifier, Error};
-    use ir::Function;
-    use ir::instructions::{InstructionData, Opcode};
+    use entity_map::EntityRef;
+    use ir::{Function, SigRef};
+    use ir::instructions::{InstructionData, Opcode, ValueList};
 
     macro_rules! assert_err_with_msg {
         ($e:expr, $msg:expr) => (
@@ -708,4 +825,27 @@ mod tests {
         let verifier = Verifier::new(&func);
         assert_err_with_msg!(verifier.run(), "instruction format");
     }
+
+    #[test]
+    fn short_value_list() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        func.layout.append_ebb(ebb0);
+        // A `call_indirect` with an empty value list is missing its callee.
+        let call = func.dfg
+            .make_inst(InstructionData::IndirectCall {
+                           opcode: Opcode::CallIndirect,
+                           sig_ref: SigRef::new(0),
+                           args: ValueList::new(),
+                       });
+        func.layout.append_inst(call, ebb0);
+        let ret = func.dfg
+            .make_inst(InstructionData::MultiAry {
+                           opcode: Opcode::Return,
+                           args: ValueList::new(),
+                       });
+        func.layout.append_inst(ret, ebb0);
+        let verifier = Verifier::new(&func);
+        assert_err_with_msg!(verifier.run(), "expected at least 1 value operands, found 0");
+    }
 }
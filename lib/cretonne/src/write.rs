@@ -4,14 +4,48 @@
 //! equivalent textual representation. This textual representation can be read back by the
 //! `cretonne-reader` crate.
 
+use flowgraph::ControlFlowGraph;
 use ir::{Function, DataFlowGraph, Ebb, Inst, Value, ValueDef, Type};
 use isa::{TargetIsa, RegInfo};
 use std::fmt::{self, Result, Error, Write};
 use std::result;
 
+/// Version of the textual IL format written by this module.
+///
+/// The version is bumped when a change to the format means that existing IL can no longer be
+/// parsed, or would be parsed with a different meaning. Additions that existing IL doesn't use,
+/// like new instructions or new optional annotations, don't change the version.
+pub const IL_VERSION: u32 = 1;
+
+/// Write a `version` line identifying the format of the IL that follows.
+///
+/// Files containing IL can begin with this line so a parser that doesn't support the format can
+/// reject them with a clear error.
+pub fn write_version(w: &mut Write) -> Result {
+    writeln!(w, "version {}", IL_VERSION)
+}
+
 /// Write `func` to `w` as equivalent text.
 /// Use `isa` to emit ISA-dependent annotations.
 pub fn write_function(w: &mut Write, func: &Function, isa: Option<&TargetIsa>) -> Result {
+    write_function_impl(w, func, isa, None)
+}
+
+/// Write `func` to `w` like `write_function`, with a comment after each EBB header listing the
+/// predecessors and successors of the EBB in `cfg`.
+pub fn write_function_with_cfg(w: &mut Write,
+                               func: &Function,
+                               isa: Option<&TargetIsa>,
+                               cfg: &ControlFlowGraph)
+                               -> Result {
+    write_function_impl(w, func, isa, Some(cfg))
+}
+
+fn write_function_impl(w: &mut Write,
+                       func: &Function,
+                       isa: Option<&TargetIsa>,
+                       cfg: Option<&ControlFlowGraph>)
+                       -> Result {
     let regs = isa.map(TargetIsa::register_info);
     let regs = regs.as_ref();
 
@@ -22,7 +56,7 @@ pub fn write_function(w: &mut Write, func: &Function, isa: Option<&TargetIsa>) -
         if any {
             writeln!(w, "")?;
         }
-        write_ebb(w, func, isa, ebb)?;
+        write_ebb(w, func, isa, cfg, ebb)?;
         any = true;
     }
     writeln!(w, "}}")
@@ -69,6 +103,16 @@ fn write_preamble(w: &mut Write,
         writeln!(w, "    {} = {}", jt, func.jump_tables[jt])?;
     }
 
+    for gv in func.global_vars.keys() {
+        any = true;
+        writeln!(w, "    {} = {}", gv, func.global_vars[gv])?;
+    }
+
+    for table in func.tables.keys() {
+        any = true;
+        writeln!(w, "    {} = {}", table, func.tables[table])?;
+    }
+
     Ok(any)
 }
 
@@ -88,6 +132,7 @@ pub fn write_ebb_header(w: &mut Write, func: &Function, ebb: Ebb) -> Result {
     //    ebb1:
     //    ebb1(v1: i32):
     //    ebb10(v4: f64, v5: b1):
+    //    ebb2 cold:
     //
 
     // If we're writing encoding annotations, shift by 20.
@@ -95,24 +140,56 @@ pub fn write_ebb_header(w: &mut Write, func: &Function, ebb: Ebb) -> Result {
         write!(w, "                    ")?;
     }
 
+    write!(w, "{}", ebb)?;
     let mut args = func.dfg.ebb_args(ebb).iter().cloned();
-    match args.next() {
-        None => return writeln!(w, "{}:", ebb),
-        Some(arg) => {
-            write!(w, "{}(", ebb)?;
+    if let Some(arg) = args.next() {
+        write!(w, "(")?;
+        write_arg(w, func, arg)?;
+        // Remaining arguments.
+        for arg in args {
+            write!(w, ", ")?;
             write_arg(w, func, arg)?;
         }
+        write!(w, ")")?;
     }
-    // Remaining arguments.
-    for arg in args {
-        write!(w, ", ")?;
-        write_arg(w, func, arg)?;
+    if func.is_cold(ebb) {
+        write!(w, " cold")?;
     }
-    writeln!(w, "):")
+    writeln!(w, ":")
 }
 
-pub fn write_ebb(w: &mut Write, func: &Function, isa: Option<&TargetIsa>, ebb: Ebb) -> Result {
+/// Write a comment listing the predecessors and successors of `ebb`, indented like its
+/// instructions.
+fn write_ebb_cfg(w: &mut Write, func: &Function, cfg: &ControlFlowGraph, ebb: Ebb) -> Result {
+    write!(w, "{1:0$}; preds:", instruction_indent(func), "")?;
+    let preds = cfg.get_predecessors(ebb);
+    if preds.is_empty() {
+        write!(w, " none")?;
+    }
+    for (i, &(pred, inst)) in preds.iter().enumerate() {
+        write!(w, "{} {}:{}", if i == 0 { "" } else { "," }, pred, inst)?;
+    }
+    write!(w, "; succs:")?;
+    let succs = cfg.get_successors(ebb);
+    if succs.is_empty() {
+        write!(w, " none")?;
+    }
+    for (i, succ) in succs.iter().enumerate() {
+        write!(w, "{} {}", if i == 0 { "" } else { "," }, succ)?;
+    }
+    writeln!(w, "")
+}
+
+pub fn write_ebb(w: &mut Write,
+                 func: &Function,
+                 isa: Option<&TargetIsa>,
+                 cfg: Option<&ControlFlowGraph>,
+                 ebb: Ebb)
+                 -> Result {
     write_ebb_header(w, func, ebb)?;
+    if let Some(cfg) = cfg {
+        write_ebb_cfg(w, func, cfg, ebb)?;
+    }
     for inst in func.layout.ebb_insts(ebb) {
         write_instruction(w, func, isa, inst)?;
     }
@@ -169,20 +246,39 @@ fn write_value_aliases(w: &mut Write, func: &Function, inst: Inst, indent: usize
     Ok(())
 }
 
-fn write_instruction(w: &mut Write,
-                     func: &Function,
-                     isa: Option<&TargetIsa>,
-                     inst: Inst)
-                     -> Result {
-    // Indent all instructions to col 24 if any encodings are present.
-    let indent = if func.encodings.is_empty() { 4 } else { 24 };
+/// Get the column that instructions are indented to.
+fn instruction_indent(func: &Function) -> usize {
+    // Indent all instructions to col 24 if any encodings are present. Source locations take up
+    // another 6 columns.
+    let mut indent = if func.encodings.is_empty() { 4 } else { 24 };
+    if !func.srclocs.is_empty() {
+        indent += 6;
+    }
+    indent
+}
+
+pub fn write_instruction(w: &mut Write,
+                         func: &Function,
+                         isa: Option<&TargetIsa>,
+                         inst: Inst)
+                         -> Result {
+    let indent = instruction_indent(func);
 
     // Value aliases come out on lines before the instruction using them.
     write_value_aliases(w, func, inst, indent)?;
 
+    // Prefix containing the source location and the encoding info.
+    let mut s = String::with_capacity(16);
+
+    // Write out the source location.
+    if let Some(&srcloc) = func.srclocs.get(inst) {
+        if !srcloc.is_default() {
+            write!(s, "{} ", srcloc)?;
+        }
+    }
+
     // Write out encoding info.
     if let Some(enc) = func.encodings.get(inst).cloned() {
-        let mut s = String::with_capacity(16);
         if let Some(isa) = isa {
             write!(s, "[{}", isa.encoding_info().display(enc))?;
             // Write value locations, if we have them.
@@ -202,11 +298,14 @@ fn write_instruction(w: &mut Write,
         } else {
             write!(s, "[{}]", enc)?;
         }
-        // Align instruction following ISA annotation to col 24.
-        write!(w, "{:23} ", s)?;
-    } else {
+    }
+
+    if s.is_empty() {
         // No annotations, simply indent.
         write!(w, "{1:0$}", indent, "")?;
+    } else {
+        // Align instruction following the annotations to the indentation column.
+        write!(w, "{1:0$} ", indent - 1, s)?;
     }
 
     // Write out the result values, if any.
@@ -231,12 +330,18 @@ fn write_instruction(w: &mut Write,
         None => write!(w, "{}", opcode)?,
     }
 
-    write_operands(w, &func.dfg, inst)?;
+    write_operands(w, &func.dfg, isa, inst)?;
     writeln!(w, "")
 }
 
 /// Write the operands of `inst` to `w` with a prepended space.
-pub fn write_operands(w: &mut Write, dfg: &DataFlowGraph, inst: Inst) -> Result {
+///
+/// Use `isa` to print register names. Without an ISA, register units are printed as numbers.
+pub fn write_operands(w: &mut Write,
+                      dfg: &DataFlowGraph,
+                      isa: Option<&TargetIsa>,
+                      inst: Inst)
+                      -> Result {
     let pool = &dfg.value_lists;
     use ir::instructions::InstructionData::*;
     match dfg[inst] {
@@ -245,6 +350,7 @@ pub fn write_operands(w: &mut Write, dfg: &DataFlowGraph, inst: Inst) -> Result
         UnaryImm { imm, .. } => write!(w, " {}", imm),
         UnaryIeee32 { imm, .. } => write!(w, " {}", imm),
         UnaryIeee64 { imm, .. } => write!(w, " {}", imm),
+        UnaryGlobalVar { global_var, .. } => write!(w, " {}", global_var),
         Binary { args, .. } => write!(w, " {}, {}", args[0], args[1]),
         BinaryImm { arg, imm, .. } => write!(w, " {}, {}", arg, imm),
         Ternary { args, .. } => write!(w, " {}, {}, {}", args[0], args[1], args[2]),
@@ -300,6 +406,13 @@ pub fn write_operands(w: &mut Write, dfg: &DataFlowGraph, inst: Inst) -> Result
             Ok(())
         }
         BranchTable { arg, table, .. } => write!(w, " {}, {}", arg, table),
+        BranchTableBase { table, .. } => write!(w, " {}", table),
+        BranchTableEntry {
+            args,
+            imm,
+            table,
+            ..
+        } => write!(w, " {}, {}, {}, {}", args[0], args[1], imm, table),
         Call { func_ref, ref args, .. } => {
             write!(w, " {}({})", func_ref, DisplayValues(args.as_slice(pool)))
         }
@@ -311,6 +424,7 @@ pub fn write_operands(w: &mut Write, dfg: &DataFlowGraph, inst: Inst) -> Result
                    args[0],
                    DisplayValues(&args[1..]))
         }
+        FuncAddr { func_ref, .. } => write!(w, " {}", func_ref),
         StackLoad { stack_slot, offset, .. } => write!(w, " {}{}", stack_slot, offset),
         StackStore {
             arg,
@@ -320,6 +434,12 @@ pub fn write_operands(w: &mut Write, dfg: &DataFlowGraph, inst: Inst) -> Result
         } => write!(w, " {}, {}{}", arg, stack_slot, offset),
         HeapLoad { arg, offset, .. } => write!(w, " {}{}", arg, offset),
         HeapStore { args, offset, .. } => write!(w, " {}, {}{}", args[0], args[1], offset),
+        TableAddr {
+            table,
+            arg,
+            offset,
+            ..
+        } => write!(w, " {}, {}{}", table, arg, offset),
         Load { flags, arg, offset, .. } => write!(w, "{} {}{}", flags, arg, offset),
         Store {
             flags,
@@ -327,6 +447,24 @@ pub fn write_operands(w: &mut Write, dfg: &DataFlowGraph, inst: Inst) -> Result
             offset,
             ..
         } => write!(w, "{} {}, {}{}", flags, args[0], args[1], offset),
+        AtomicLoad { order, arg, .. } => write!(w, " {} {}", order, arg),
+        AtomicRmw { order, args, .. } => write!(w, " {} {}, {}", order, args[0], args[1]),
+        AtomicCas { order, args, .. } => {
+            write!(w, " {} {}, {}, {}", order, args[0], args[1], args[2])
+        }
+        Fence { order, .. } => write!(w, " {}", order),
+        RegMove { arg, src, dst, .. } => {
+            if let Some(isa) = isa {
+                let regs = isa.register_info();
+                write!(w,
+                       " {}, {} -> {}",
+                       arg,
+                       regs.display_regunit(src),
+                       regs.display_regunit(dst))
+            } else {
+                write!(w, " {}, %{} -> %{}", arg, src, dst)
+            }
+        }
     }
 }
 
@@ -348,7 +486,7 @@ impl<'a> fmt::Display for DisplayValues<'a> {
 
 #[cfg(test)]
 mod tests {
-    use ir::{Function, FunctionName, StackSlotData};
+    use ir::{Function, FunctionName, StackSlotData, StackSlotKind};
     use ir::types;
 
     #[test]
@@ -359,7 +497,8 @@ mod tests {
         f.name = FunctionName::new("foo".to_string());
         assert_eq!(f.to_string(), "function foo() {\n}\n");
 
-        f.stack_slots.push(StackSlotData::new(4));
+        f.stack_slots
+            .push(StackSlotData::new(StackSlotKind::Local, 4));
         assert_eq!(f.to_string(),
                    "function foo() {\n    ss0 = stack_slot 4\n}\n");
 
@@ -375,5 +514,10 @@ mod tests {
         f.dfg.append_ebb_arg(ebb, types::F32.by(4).unwrap());
         assert_eq!(f.to_string(),
                    "function foo() {\n    ss0 = stack_slot 4\n\nebb0(v0: i8, v1: f32x4):\n}\n");
+
+        f.set_cold(ebb);
+        assert_eq!(f.to_string(),
+                   "function foo() {\n    ss0 = stack_slot 4\n\n\
+                    ebb0(v0: i8, v1: f32x4) cold:\n}\n");
     }
 }
@@ -0,0 +1,338 @@
+//! Lowering of by-value aggregates.
+//!
+//! Cretonne IL has no aggregate types, but C-like languages pass structs and arrays by value. The
+//! helpers in this module lower by-value aggregates to memory and scalar values following the
+//! classification from `TargetIsa::classify_aggregate()`:
+//!
+//! - Small aggregates are passed and returned as a sequence of integer values loaded from the
+//!   aggregate's memory.
+//! - Other aggregate arguments are copied to a stack slot in the caller, and a pointer to the copy
+//!   is passed.
+//! - Other aggregate return values are written to a return area provided by the caller. Its
+//!   address is passed as an `sret` argument.
+//!
+//! Aggregates are always manipulated through pointers. The frontend allocates explicit stack slots
+//! for them with `FunctionBuilder::create_aggregate_slot()`.
+
+use cretonne::ir::{Signature, ArgumentType, ArgumentPurpose, Type, Value, StackSlot, SigRef,
+                   StackSlotData, StackSlotKind, InstBuilder, MemFlags};
+use cretonne::ir::types;
+use cretonne::isa::{TargetIsa, AggregateClass};
+use frontend::FunctionBuilder;
+
+/// Append the arguments for passing an aggregate of `size` bytes by value to `sig`.
+pub fn append_aggregate_arg(sig: &mut Signature, size: u32, isa: &TargetIsa) {
+    match isa.classify_aggregate(size, sig.call_conv) {
+        AggregateClass::Scalars(tys) => {
+            sig.argument_types
+                .extend(tys.into_iter().map(ArgumentType::new))
+        }
+        AggregateClass::Memory => sig.argument_types.push(ArgumentType::new(pointer_type(isa))),
+    }
+}
+
+/// Make `sig` return an aggregate of `size` bytes by value.
+///
+/// An aggregate returned in memory adds an `sret` pointer as the first argument, and the
+/// signature has no return values. The ISA's signature legalizer assigns the `sret` pointer to
+/// the register required by its ABI.
+pub fn set_aggregate_return(sig: &mut Signature, size: u32, isa: &TargetIsa) {
+    assert!(sig.return_types.is_empty(),
+            "An aggregate must be the only return value");
+    match isa.classify_aggregate(size, sig.call_conv) {
+        AggregateClass::Scalars(tys) => {
+            sig.return_types
+                .extend(tys.into_iter().map(ArgumentType::new))
+        }
+        AggregateClass::Memory => {
+            let mut sret = ArgumentType::new(pointer_type(isa));
+            sret.purpose = ArgumentPurpose::StructReturn;
+            sig.argument_types.insert(0, sret);
+        }
+    }
+}
+
+impl<'a> FunctionBuilder<'a> {
+    /// Create a stack slot that can hold an aggregate of `size` bytes.
+    pub fn create_aggregate_slot(&mut self, size: u32) -> StackSlot {
+        self.create_stack_slot(StackSlotData::new(StackSlotKind::Local, size))
+    }
+
+    /// Copy the aggregate of `size` bytes at address `src` to address `dest`.
+    ///
+    /// The copy is made with integer loads and stores no wider than a pointer. The two aggregates
+    /// must not overlap.
+    pub fn copy_aggregate(&mut self, dest: Value, src: Value, size: u32) {
+        let pointer_type = self.func.dfg.value_type(src);
+        for (ty, offset) in chunks(size, pointer_type) {
+            let val = self.ins()
+                .load(ty, MemFlags::new(), src, offset as i32);
+            self.ins()
+                .store(MemFlags::new(), val, dest, offset as i32);
+        }
+    }
+
+    /// Get the call arguments for passing the aggregate of `size` bytes at address `src` by
+    /// value to a function with the signature `sig`.
+    ///
+    /// The arguments match the ones added by `append_aggregate_arg()`.
+    pub fn aggregate_call_args(&mut self,
+                               isa: &TargetIsa,
+                               sig: SigRef,
+                               src: Value,
+                               size: u32)
+                               -> Vec<Value> {
+        let call_conv = self.func.dfg.signatures[sig].call_conv;
+        match isa.classify_aggregate(size, call_conv) {
+            AggregateClass::Scalars(tys) => self.load_aggregate(src, &tys),
+            AggregateClass::Memory => {
+                // The callee may modify its argument, so it gets a copy.
+                let pointer_type = pointer_type(isa);
+                let slot = self.create_aggregate_slot(size);
+                let copy = self.ins().stack_addr(pointer_type, slot, 0);
+                self.copy_aggregate(copy, src, size);
+                vec![copy]
+            }
+        }
+    }
+
+    /// Get the address of an aggregate of `size` bytes passed by value in the function
+    /// arguments `args`.
+    ///
+    /// The `args` are the entry EBB arguments corresponding to the arguments added by
+    /// `append_aggregate_arg()`. An aggregate passed as scalar values is stored to a new stack
+    /// slot.
+    pub fn aggregate_from_args(&mut self, isa: &TargetIsa, args: &[Value], size: u32) -> Value {
+        let call_conv = self.func.signature.call_conv;
+        match isa.classify_aggregate(size, call_conv) {
+            AggregateClass::Scalars(tys) => {
+                let pointer_type = pointer_type(isa);
+                let slot = self.create_aggregate_slot(size);
+                let addr = self.ins().stack_addr(pointer_type, slot, 0);
+                self.store_aggregate(addr, &tys, args);
+                addr
+            }
+            AggregateClass::Memory => {
+                assert_eq!(args.len(), 1, "An aggregate in memory is passed as one pointer");
+                args[0]
+            }
+        }
+    }
+
+    /// Get the values to return for returning the aggregate of `size` bytes at address `src` by
+    /// value from the current function.
+    ///
+    /// The function signature must have been set up with `set_aggregate_return()`. An aggregate
+    /// returned in memory is copied to the `sret` return area, and there are no values to return.
+    pub fn aggregate_return_values(&mut self,
+                                   isa: &TargetIsa,
+                                   src: Value,
+                                   size: u32)
+                                   -> Vec<Value> {
+        let call_conv = self.func.signature.call_conv;
+        match isa.classify_aggregate(size, call_conv) {
+            AggregateClass::Scalars(tys) => self.load_aggregate(src, &tys),
+            AggregateClass::Memory => {
+                let idx = self.func
+                    .signature
+                    .argument_types
+                    .iter()
+                    .position(|arg| arg.purpose == ArgumentPurpose::StructReturn)
+                    .expect("The signature has no sret argument");
+                let entry = self.func
+                    .layout
+                    .entry_block()
+                    .expect("The entry EBB must have been built");
+                let sret = self.ebb_args(entry)[idx];
+                self.copy_aggregate(sret, src, size);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Store the results of a call to a function with the signature `sig` returning an aggregate
+    /// of `size` bytes to the aggregate at address `dest`.
+    ///
+    /// When the aggregate is returned in memory, the call must be passed `dest` as its first
+    /// `sret` argument, and there are no `results` to store.
+    pub fn store_aggregate_results(&mut self,
+                                   isa: &TargetIsa,
+                                   sig: SigRef,
+                                   dest: Value,
+                                   results: &[Value],
+                                   size: u32) {
+        let call_conv = self.func.dfg.signatures[sig].call_conv;
+        match isa.classify_aggregate(size, call_conv) {
+            AggregateClass::Scalars(tys) => self.store_aggregate(dest, &tys, results),
+            AggregateClass::Memory => assert!(results.is_empty()),
+        }
+    }
+
+    // Load the aggregate at `src` as the scalar values `tys` of `AggregateClass::Scalars`.
+    fn load_aggregate(&mut self, src: Value, tys: &[Type]) -> Vec<Value> {
+        scalar_offsets(tys)
+            .into_iter()
+            .map(|(ty, offset)| {
+                     self.ins()
+                         .load(ty, MemFlags::new(), src, offset as i32)
+                 })
+            .collect()
+    }
+
+    // Store the scalar values of `AggregateClass::Scalars` with the types `tys` to the aggregate
+    // at `dest`.
+    fn store_aggregate(&mut self, dest: Value, tys: &[Type], values: &[Value]) {
+        assert_eq!(tys.len(), values.len(), "Wrong number of aggregate values");
+        for ((_, offset), &val) in scalar_offsets(tys).into_iter().zip(values) {
+            self.ins()
+                .store(MemFlags::new(), val, dest, offset as i32);
+        }
+    }
+}
+
+// Get the type of a pointer on `isa`.
+fn pointer_type(isa: &TargetIsa) -> Type {
+    if isa.flags().is_64bit() {
+        types::I64
+    } else {
+        types::I32
+    }
+}
+
+// Get `(type, offset)` pairs for the scalar values `tys` stored consecutively in an aggregate.
+fn scalar_offsets(tys: &[Type]) -> Vec<(Type, u32)> {
+    let mut offset = 0;
+    tys.iter()
+        .map(|&ty| {
+                 let cur = offset;
+                 offset += ty.bytes();
+                 (ty, cur)
+             })
+        .collect()
+}
+
+// Divide an aggregate of `size` bytes into integer pieces no wider than a pointer. Return an
+// iterator of `(type, offset)` pairs.
+fn chunks(size: u32, pointer_type: Type) -> Chunks {
+    Chunks {
+        offset: 0,
+        size: size,
+        max_bytes: pointer_type.bytes(),
+    }
+}
+
+struct Chunks {
+    offset: u32,
+    size: u32,
+    max_bytes: u32,
+}
+
+impl Iterator for Chunks {
+    type Item = (Type, u32);
+
+    fn next(&mut self) -> Option<(Type, u32)> {
+        let left = self.size - self.offset;
+        let ty = if left >= 8 && self.max_bytes >= 8 {
+            types::I64
+        } else if left >= 4 && self.max_bytes >= 4 {
+            types::I32
+        } else if left >= 2 {
+            types::I16
+        } else if left >= 1 {
+            types::I8
+        } else {
+            return None;
+        };
+        let offset = self.offset;
+        self.offset += ty.bytes();
+        Some((ty, offset))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cretonne::ir::{Function, FunctionName, ExtFuncData, InstBuilder, CallConv};
+    use cretonne::ir::types::I64;
+    use cretonne::isa;
+    use cretonne::settings::{self, Configurable};
+    use cretonne::verify_function;
+    use frontend::ILBuilder;
+
+    fn intel_64() -> Box<TargetIsa> {
+        let mut flag_builder = settings::builder();
+        flag_builder.enable("is_64bit").unwrap();
+        isa::lookup("intel")
+            .unwrap()
+            .finish(settings::Flags::new(&flag_builder))
+    }
+
+    #[test]
+    fn signatures() {
+        let isa = intel_64();
+        let mut sig = Signature::new();
+        append_aggregate_arg(&mut sig, 12, &*isa);
+        append_aggregate_arg(&mut sig, 40, &*isa);
+        set_aggregate_return(&mut sig, 24, &*isa);
+        assert_eq!(sig.to_string(), "(i64 sret, i64, i32, i64)");
+
+        let mut sig = Signature::new();
+        set_aggregate_return(&mut sig, 3, &*isa);
+        assert_eq!(sig.to_string(), "() -> i16, i8");
+
+        // The Windows x64 convention passes a 12-byte aggregate in memory.
+        let mut sig = Signature::new();
+        sig.call_conv = CallConv::WindowsFastcall;
+        append_aggregate_arg(&mut sig, 12, &*isa);
+        append_aggregate_arg(&mut sig, 8, &*isa);
+        assert_eq!(sig.to_string(), "(i64, i64) windows_fastcall");
+    }
+
+    // Build a function taking a small and a large aggregate, and returning the large one after
+    // passing both to `callee` with the same signature.
+    #[test]
+    fn pass_and_return() {
+        let isa = intel_64();
+        let mut sig = Signature::new();
+        append_aggregate_arg(&mut sig, 12, &*isa);
+        append_aggregate_arg(&mut sig, 40, &*isa);
+        set_aggregate_return(&mut sig, 24, &*isa);
+
+        let mut func = Function::with_name_signature(FunctionName::new("agg"), sig.clone());
+        let mut il_builder = ILBuilder::new();
+        {
+            let mut builder = FunctionBuilder::new(&mut func, &mut il_builder);
+            let entry = builder.create_ebb();
+            builder.switch_to_block(entry);
+            builder.seal_block(entry);
+            builder.append_ebb_args_for_function_args(entry);
+            let args = builder.ebb_args(entry).to_vec();
+            let small = builder.aggregate_from_args(&*isa, &args[1..3], 12);
+            let large = builder.aggregate_from_args(&*isa, &args[3..4], 40);
+
+            let sigref = builder.import_signature(sig);
+            let callee = builder.import_function(ExtFuncData {
+                                                     name: FunctionName::new("callee"),
+                                                     signature: sigref,
+                                                     colocated: false,
+                                                 });
+            let result_slot = builder.create_aggregate_slot(24);
+            let result = builder.ins().stack_addr(I64, result_slot, 0);
+            let mut call_args = vec![result];
+            call_args.extend(builder.aggregate_call_args(&*isa, sigref, small, 12));
+            call_args.extend(builder.aggregate_call_args(&*isa, sigref, large, 40));
+            let call = builder.ins().call(callee, &call_args);
+            let results = builder.func.dfg.inst_results(call).to_vec();
+            builder.store_aggregate_results(&*isa, sigref, result, &results, 24);
+
+            let rets = builder.aggregate_return_values(&*isa, result, 24);
+            builder.ins().return_(&rets);
+            builder.finalize();
+        }
+        if let Err(err) = verify_function(&func) {
+            panic!("{}\n{}", err, func.display(None));
+        }
+        // Stack slots for the small argument, the result, and the large argument copy.
+        assert_eq!(func.stack_slots.len(), 3);
+    }
+}
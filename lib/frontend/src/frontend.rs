@@ -0,0 +1,456 @@
+//! A frontend for building Cretonne IL from other languages.
+
+use cretonne::entity_map::EntityMap;
+use cretonne::ir::{Ebb, Type, Value, Inst, Function, DataFlowGraph, InstructionData,
+                   InstBuilderBase, JumpTable, JumpTableData, StackSlot, StackSlotData, SigRef,
+                   Signature, FuncRef, ExtFuncData, SourceLoc};
+use ssa::{SSABuilder, Block};
+use variable::Variable;
+
+/// Reusable data structures for building functions with a `FunctionBuilder`.
+///
+/// The `ILBuilder` holds the side tables used to construct SSA form. Like the `Context` used for
+/// compiling functions, it should be reused for translating many functions, so the memory
+/// allocated for one function can be reused for the next.
+pub struct ILBuilder {
+    ssa: SSABuilder,
+    ebbs: EntityMap<Ebb, EbbData>,
+    types: EntityMap<Variable, Type>,
+}
+
+impl ILBuilder {
+    /// Create a new, empty `ILBuilder`.
+    pub fn new() -> ILBuilder {
+        ILBuilder {
+            ssa: SSABuilder::new(),
+            ebbs: EntityMap::new(),
+            types: EntityMap::new(),
+        }
+    }
+
+    /// Clear all the data structures while keeping their allocated memory.
+    pub fn clear(&mut self) {
+        self.ssa.clear();
+        self.ebbs.clear();
+        self.types.clear();
+    }
+
+    /// Are all the data structures empty?
+    pub fn is_empty(&self) -> bool {
+        self.ssa.is_empty() && self.ebbs.is_empty() && self.types.is_empty()
+    }
+}
+
+/// Builder for a Cretonne function using mutable variables.
+///
+/// The builder appends instructions to the end of the current EBB. The frontend defines and uses
+/// variables with `def_var()` and `use_var()`, and the builder translates them into SSA values,
+/// inserting EBB arguments where needed.
+///
+/// An EBB must be *sealed* with `seal_block()` when all the branches to it have been built. The
+/// builder can only resolve the variables used in an EBB completely when its predecessors are
+/// known, so seal the EBBs as early as possible. All EBBs must be sealed and filled when the
+/// function is finalized.
+pub struct FunctionBuilder<'a> {
+    /// The function being built.
+    pub func: &'a mut Function,
+
+    il_builder: &'a mut ILBuilder,
+    position: Position,
+    srcloc: SourceLoc,
+}
+
+#[derive(Clone, Default)]
+struct EbbData {
+    // Has the EBB been inserted in the layout? This happens when its first instruction is built.
+    inserted: bool,
+    // Does the EBB end with a terminator instruction?
+    filled: bool,
+    // The number of EBB arguments added by the frontend. The following arguments were added by
+    // the SSA builder.
+    user_args: usize,
+}
+
+// The current insertion point: the last basic block of an EBB.
+struct Position {
+    ebb: Option<Ebb>,
+    block: Option<Block>,
+}
+
+/// Instruction builder that appends instructions to the current EBB of a `FunctionBuilder`.
+///
+/// Branches are declared to the SSA builder as predecessors of their destinations.
+pub struct FuncInstBuilder<'short, 'a: 'short> {
+    builder: &'short mut FunctionBuilder<'a>,
+    ebb: Ebb,
+}
+
+impl<'short, 'a> InstBuilderBase<'short> for FuncInstBuilder<'short, 'a> {
+    fn data_flow_graph(&self) -> &DataFlowGraph {
+        &self.builder.func.dfg
+    }
+
+    fn data_flow_graph_mut(&mut self) -> &mut DataFlowGraph {
+        &mut self.builder.func.dfg
+    }
+
+    fn build(self, data: InstructionData, ctrl_typevar: Type) -> (Inst, &'short mut DataFlowGraph) {
+        let builder = self.builder;
+        let ebb = self.ebb;
+        builder.ensure_inserted(ebb);
+
+        let opcode = data.opcode();
+        let dest = data.branch_destination();
+        let table = match data {
+            InstructionData::BranchTable { table, .. } => Some(table),
+            _ => None,
+        };
+        let inst = builder.func.dfg.make_inst(data);
+        builder.func.dfg.make_inst_results(inst, ctrl_typevar);
+        builder.func.layout.append_inst(inst, ebb);
+        if !builder.srcloc.is_default() {
+            *builder.func.srclocs.ensure(inst) = builder.srcloc;
+        }
+
+        if opcode.is_branch() {
+            let block = builder.position.block.unwrap();
+            if let Some(dest) = dest {
+                builder.il_builder.ssa.declare_ebb_predecessor(dest, block, inst);
+            }
+            if let Some(table) = table {
+                let mut dests: Vec<Ebb> = builder.func.jump_tables[table]
+                    .entries()
+                    .map(|(_, dest)| dest)
+                    .collect();
+                dests.sort();
+                dests.dedup();
+                for dest in dests {
+                    builder.il_builder.ssa.declare_ebb_predecessor(dest, block, inst);
+                }
+            }
+        }
+
+        if opcode.is_terminator() {
+            builder.il_builder.ebbs[ebb].filled = true;
+        } else if opcode.is_branch() {
+            // A conditional branch ends the current basic block.
+            let block = builder.position.block.unwrap();
+            builder.position.block = Some(builder.il_builder.ssa.declare_ebb_body_block(block));
+        }
+
+        (inst, &mut builder.func.dfg)
+    }
+}
+
+impl<'a> FunctionBuilder<'a> {
+    /// Create a new builder for `func`, which should be empty.
+    ///
+    /// The data structures in `il_builder` are cleared, so it can be reused from the previous
+    /// function.
+    pub fn new(func: &'a mut Function, il_builder: &'a mut ILBuilder) -> FunctionBuilder<'a> {
+        il_builder.clear();
+        FunctionBuilder {
+            func: func,
+            il_builder: il_builder,
+            position: Position {
+                ebb: None,
+                block: None,
+            },
+            srcloc: SourceLoc::default(),
+        }
+    }
+
+    /// Set the source location given to the instructions built from now on.
+    pub fn set_srcloc(&mut self, srcloc: SourceLoc) {
+        self.srcloc = srcloc;
+    }
+
+    /// Create a new EBB.
+    ///
+    /// The EBB is inserted in the layout when its first instruction is built.
+    pub fn create_ebb(&mut self) -> Ebb {
+        let ebb = self.func.dfg.make_ebb();
+        self.il_builder.ssa.declare_ebb_header_block(ebb);
+        self.il_builder.ebbs.ensure(ebb);
+        ebb
+    }
+
+    /// Mark `ebb` as cold, like a trap or slow path that is expected to execute rarely.
+    ///
+    /// Cold EBBs are moved out of the way of the hot code.
+    pub fn set_cold_ebb(&mut self, ebb: Ebb) {
+        self.func.set_cold(ebb);
+    }
+
+    /// Switch to appending instructions to `ebb`.
+    ///
+    /// The current EBB must be filled with a terminator instruction or still be empty.
+    pub fn switch_to_block(&mut self, ebb: Ebb) {
+        if let Some(current) = self.position.ebb {
+            assert!(self.is_filled() || !self.il_builder.ebbs[current].inserted,
+                    "{} must be filled before switching to {}",
+                    current,
+                    ebb);
+        }
+        assert!(!self.il_builder.ebbs[ebb].filled, "{} is already filled", ebb);
+        self.position.ebb = Some(ebb);
+        self.position.block = Some(self.il_builder.ssa.header_block(ebb));
+    }
+
+    /// Get the EBB instructions are currently appended to.
+    pub fn current_ebb(&self) -> Option<Ebb> {
+        self.position.ebb
+    }
+
+    /// Has the current EBB been terminated?
+    pub fn is_filled(&self) -> bool {
+        self.position
+            .ebb
+            .map_or(false, |ebb| self.il_builder.ebbs[ebb].filled)
+    }
+
+    /// Declare that all the branches to `ebb` have been built.
+    pub fn seal_block(&mut self, ebb: Ebb) {
+        self.il_builder.ssa.seal_ebb_header_block(ebb, self.func);
+        self.handle_split_ebbs();
+    }
+
+    /// Seal all the EBBs that haven't been sealed yet.
+    ///
+    /// This is convenient for a frontend that doesn't know when the EBBs are complete, but it is
+    /// more efficient to seal the EBBs as early as possible.
+    pub fn seal_all_blocks(&mut self) {
+        self.il_builder.ssa.seal_all_ebb_header_blocks(self.func);
+        self.handle_split_ebbs();
+    }
+
+    /// Declare the variable `var` with type `ty`.
+    pub fn declare_var(&mut self, var: Variable, ty: Type) {
+        *self.il_builder.types.ensure(var) = ty;
+    }
+
+    /// Get the value of `var` at the current position.
+    ///
+    /// A variable that hasn't been defined on all paths to the current position is
+    /// zero-initialized at the top of the entry EBB.
+    pub fn use_var(&mut self, var: Variable) -> Value {
+        let ty = *self.il_builder
+                      .types
+                      .get(var)
+                      .expect("the variable must be declared before it is used");
+        let ebb = self.position.ebb.expect("no current EBB");
+        self.ensure_inserted(ebb);
+        let block = self.position.block.unwrap();
+        let val = self.il_builder.ssa.use_var(self.func, var, ty, block);
+        self.handle_split_ebbs();
+        val
+    }
+
+    /// Set the value of `var` at the current position to `val`.
+    pub fn def_var(&mut self, var: Variable, val: Value) {
+        assert_eq!(Some(&self.func.dfg.value_type(val)),
+                   self.il_builder.types.get(var),
+                   "the value type must match the declared type of {}",
+                   var);
+        let block = self.position.block.expect("no current EBB");
+        self.il_builder.ssa.def_var(var, val, block)
+    }
+
+    /// Append an argument with type `ty` to `ebb`.
+    ///
+    /// The frontend's EBB arguments must be added before any variables are used in the EBB.
+    pub fn append_ebb_arg(&mut self, ebb: Ebb, ty: Type) -> Value {
+        assert_eq!(self.func.dfg.num_ebb_args(ebb),
+                   self.il_builder.ebbs[ebb].user_args,
+                   "{} arguments must be added before variables are used in it",
+                   ebb);
+        self.il_builder.ebbs[ebb].user_args += 1;
+        self.func.dfg.append_ebb_arg(ebb, ty)
+    }
+
+    /// Append arguments matching the function signature to the entry `ebb`.
+    pub fn append_ebb_args_for_function_args(&mut self, ebb: Ebb) {
+        let types: Vec<Type> = self.func
+            .signature
+            .argument_types
+            .iter()
+            .map(|arg| arg.value_type)
+            .collect();
+        for ty in types {
+            self.append_ebb_arg(ebb, ty);
+        }
+    }
+
+    /// Get the arguments added to `ebb` by the frontend.
+    pub fn ebb_args(&self, ebb: Ebb) -> &[Value] {
+        &self.func.dfg.ebb_args(ebb)[0..self.il_builder.ebbs[ebb].user_args]
+    }
+
+    /// Create a stack slot in the function.
+    pub fn create_stack_slot(&mut self, data: StackSlotData) -> StackSlot {
+        self.func.stack_slots.push(data)
+    }
+
+    /// Create a jump table in the function, to be used by `br_table` instructions.
+    pub fn create_jump_table(&mut self, data: JumpTableData) -> JumpTable {
+        self.func.jump_tables.push(data)
+    }
+
+    /// Add a signature that can be referenced by indirect calls.
+    pub fn import_signature(&mut self, signature: Signature) -> SigRef {
+        self.func.dfg.signatures.push(signature)
+    }
+
+    /// Declare an external function that can be called directly.
+    pub fn import_function(&mut self, data: ExtFuncData) -> FuncRef {
+        self.func.dfg.ext_funcs.push(data)
+    }
+
+    /// Get an instruction builder that appends to the current EBB.
+    ///
+    /// Panics if there is no current EBB or it has been filled already.
+    pub fn ins<'short>(&'short mut self) -> FuncInstBuilder<'short, 'a> {
+        let ebb = self.position
+            .ebb
+            .expect("switch_to_block() must be called before building instructions");
+        assert!(!self.il_builder.ebbs[ebb].filled,
+                "{} is filled, there can't be any more instructions",
+                ebb);
+        FuncInstBuilder {
+            builder: self,
+            ebb: ebb,
+        }
+    }
+
+    /// Finish building the function.
+    ///
+    /// Panics unless all the EBBs in the layout have been sealed and filled.
+    pub fn finalize(self) {
+        for ebb in self.func.layout.ebbs() {
+            assert!(self.il_builder.ssa.is_sealed(ebb), "{} is not sealed", ebb);
+            assert!(self.il_builder.ebbs[ebb].filled, "{} is not filled", ebb);
+        }
+    }
+
+    // Insert `ebb` in the layout if it isn't already.
+    fn ensure_inserted(&mut self, ebb: Ebb) {
+        if !self.il_builder.ebbs[ebb].inserted {
+            self.func.layout.append_ebb(ebb);
+            self.il_builder.ebbs[ebb].inserted = true;
+        }
+    }
+
+    // Record the EBBs the SSA builder created to split `br_table` edges. They are complete.
+    fn handle_split_ebbs(&mut self) {
+        for ebb in self.il_builder.ssa.take_split_ebbs() {
+            let data = self.il_builder.ebbs.ensure(ebb);
+            data.inserted = true;
+            data.filled = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cretonne::entity_map::EntityRef;
+    use cretonne::ir::{Function, FunctionName, Signature, ArgumentType, InstBuilder, types};
+    use cretonne::ir::condcodes::IntCC;
+    use cretonne::verify_function;
+
+    // Build a function computing the sum 1 + 2 + ... + n.
+    fn sum(il_builder: &mut ILBuilder, seal_early: bool) -> Function {
+        let mut sig = Signature::new();
+        sig.argument_types.push(ArgumentType::new(types::I32));
+        sig.return_types.push(ArgumentType::new(types::I32));
+        let mut func = Function::with_name_signature(FunctionName::new("sum"), sig);
+        {
+            let mut builder = FunctionBuilder::new(&mut func, il_builder);
+            let n = Variable::new(0);
+            let i = Variable::new(1);
+            let sum = Variable::new(2);
+            builder.declare_var(n, types::I32);
+            builder.declare_var(i, types::I32);
+            builder.declare_var(sum, types::I32);
+
+            let entry = builder.create_ebb();
+            let header = builder.create_ebb();
+            let exit = builder.create_ebb();
+
+            builder.switch_to_block(entry);
+            builder.append_ebb_args_for_function_args(entry);
+            let arg = builder.ebb_args(entry)[0];
+            builder.def_var(n, arg);
+            let one = builder.ins().iconst(types::I32, 1);
+            builder.def_var(i, one);
+            let zero = builder.ins().iconst(types::I32, 0);
+            builder.def_var(sum, zero);
+            builder.ins().jump(header, &[]);
+            if seal_early {
+                builder.seal_block(entry);
+            }
+
+            // Loop header: `while i <= n { sum += i; i += 1 }`.
+            builder.switch_to_block(header);
+            let iv = builder.use_var(i);
+            let nv = builder.use_var(n);
+            let done = builder.ins().icmp(IntCC::SignedGreaterThan, iv, nv);
+            builder.ins().brnz(done, exit, &[]);
+            let sv = builder.use_var(sum);
+            let iv = builder.use_var(i);
+            let sv = builder.ins().iadd(sv, iv);
+            builder.def_var(sum, sv);
+            let iv = builder.ins().iadd_imm(iv, 1);
+            builder.def_var(i, iv);
+            builder.ins().jump(header, &[]);
+            if seal_early {
+                builder.seal_block(header);
+                builder.seal_block(exit);
+            }
+
+            builder.switch_to_block(exit);
+            let sv = builder.use_var(sum);
+            builder.ins().return_(&[sv]);
+
+            builder.seal_all_blocks();
+            builder.finalize();
+        }
+        func
+    }
+
+    #[test]
+    fn loop_sum() {
+        // Reuse the same `ILBuilder` for both functions.
+        let mut il_builder = ILBuilder::new();
+        for &seal_early in &[false, true] {
+            let func = sum(&mut il_builder, seal_early);
+            if let Err(err) = verify_function(&func) {
+                panic!("{}\n{}", err, func.display(None));
+            }
+
+            // The loop header gets arguments for `i` and `sum`, but not for `n`.
+            let header = func.layout.ebbs().nth(1).unwrap();
+            assert_eq!(func.dfg.num_ebb_args(header), 2);
+        }
+    }
+
+    #[test]
+    fn srclocs() {
+        let mut func = Function::new();
+        let mut il_builder = ILBuilder::new();
+        {
+            let mut builder = FunctionBuilder::new(&mut func, &mut il_builder);
+            let ebb = builder.create_ebb();
+            builder.switch_to_block(ebb);
+            builder.seal_block(ebb);
+            builder.set_srcloc(SourceLoc::new(3));
+            builder.ins().return_(&[]);
+            builder.finalize();
+        }
+        let inst = func.layout
+            .ebb_insts(func.layout.entry_block().unwrap())
+            .next()
+            .unwrap();
+        assert_eq!(func.srclocs[inst], SourceLoc::new(3));
+    }
+}
@@ -0,0 +1,71 @@
+//! Cretonne IL builder library.
+//!
+//! Provides a straightforward way to create a Cretonne IL function and fill it with instructions
+//! translated from another language. The `FunctionBuilder` works with mutable *variables* instead
+//! of SSA values, and takes care of constructing the SSA form as the function is built, using the
+//! algorithm described in "Simple and Efficient Construction of Static Single Assignment Form" by
+//! Braun et al.
+//!
+//! The `ILBuilder` owns the data structures used to build a function, and it should be reused
+//! for translating many functions to avoid repeated memory allocation.
+//!
+//! # Example
+//!
+//! ```
+//! extern crate cretonne;
+//! extern crate cton_frontend;
+//!
+//! use cretonne::entity_map::EntityRef;
+//! use cretonne::ir::{Function, FunctionName, Signature, ArgumentType, InstBuilder, types};
+//! use cretonne::verify_function;
+//! use cton_frontend::{ILBuilder, FunctionBuilder, Variable};
+//!
+//! fn main() {
+//!     let mut sig = Signature::new();
+//!     sig.argument_types.push(ArgumentType::new(types::I32));
+//!     sig.return_types.push(ArgumentType::new(types::I32));
+//!     let mut func = Function::with_name_signature(FunctionName::new("sample"), sig);
+//!     let mut il_builder = ILBuilder::new();
+//!     {
+//!         let mut builder = FunctionBuilder::new(&mut func, &mut il_builder);
+//!         let x = Variable::new(0);
+//!         builder.declare_var(x, types::I32);
+//!
+//!         let entry = builder.create_ebb();
+//!         let block1 = builder.create_ebb();
+//!         builder.switch_to_block(entry);
+//!         builder.append_ebb_args_for_function_args(entry);
+//!         let arg = builder.ebb_args(entry)[0];
+//!         builder.def_var(x, arg);
+//!         builder.ins().brz(arg, block1, &[]);
+//!         let tmp = builder.use_var(x);
+//!         let tmp = builder.ins().iadd_imm(tmp, 1);
+//!         builder.def_var(x, tmp);
+//!         builder.ins().jump(block1, &[]);
+//!         builder.seal_block(entry);
+//!
+//!         // `x` is passed as an EBB argument since `block1` has two predecessors.
+//!         builder.switch_to_block(block1);
+//!         builder.seal_block(block1);
+//!         let result = builder.use_var(x);
+//!         builder.ins().return_(&[result]);
+//!         builder.finalize();
+//!     }
+//!     verify_function(&func).unwrap();
+//! }
+//! ```
+
+#![deny(missing_docs)]
+
+extern crate cretonne;
+
+pub use aggregate::{append_aggregate_arg, set_aggregate_return};
+pub use frontend::{ILBuilder, FunctionBuilder, FuncInstBuilder};
+pub use switch::Switch;
+pub use variable::Variable;
+
+mod aggregate;
+mod frontend;
+mod ssa;
+mod switch;
+mod variable;
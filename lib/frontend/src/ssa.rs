@@ -0,0 +1,563 @@
+//! SSA construction for functions with an incomplete control flow graph.
+//!
+//! The algorithm is from Braun M., Buchwald S., Hack S., Leißa R., Mallon C., Zwinkau A. (2013)
+//! "Simple and Efficient Construction of Static Single Assignment Form". In: Compiler
+//! Construction. CC 2013. Lecture Notes in Computer Science, vol 7791.
+//!
+//! Variables are defined and used in basic blocks while the function is being built. The use of a
+//! variable is resolved by searching backwards through the predecessors of the basic block for a
+//! definition. EBB arguments are inserted at the EBB headers where definitions from different
+//! predecessors meet.
+//!
+//! The predecessors of an EBB are not known until all the branches to it have been built, so an
+//! EBB header starts out *unsealed*. Variables used in an unsealed EBB get a placeholder EBB
+//! argument, and the corresponding branch arguments are added when the EBB is sealed.
+//!
+//! A `br_table` instruction can't pass arguments to its destinations. When an EBB argument is
+//! needed on an edge from a `br_table`, the edge is split with a new EBB that jumps to the
+//! destination.
+//!
+//! The resulting SSA form is not necessarily minimal. An EBB argument is removed when all its
+//! predecessors pass the same value, but the EBB arguments depending on the removed argument are
+//! not revisited.
+
+use cretonne::entity_map::{EntityMap, EntityRef, PrimaryEntityData};
+use cretonne::ir::{Ebb, Function, Inst, InstBuilder, InstructionData, Cursor, DataFlowGraph, Type,
+                   Value, types};
+use cretonne::ir::condcodes::IntCC;
+use cretonne::ir::immediates::{Ieee32, Ieee64};
+use std::collections::HashMap;
+use std::u32;
+use variable::Variable;
+
+/// A basic block in the function being built.
+///
+/// An EBB is divided into basic blocks by its conditional branches. The first basic block of an
+/// EBB is the *header block* whose predecessors are the branches to the EBB. Each of the following
+/// basic blocks has the basic block before it as its single predecessor.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub struct Block(u32);
+
+impl EntityRef for Block {
+    fn new(index: usize) -> Block {
+        assert!(index < (u32::MAX as usize));
+        Block(index as u32)
+    }
+
+    fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+// Data for a basic block.
+enum BlockData {
+    // The first basic block in an EBB.
+    EbbHeader(EbbHeaderBlockData),
+    // A basic block following a conditional branch.
+    EbbBody { predecessor: Block },
+}
+
+impl PrimaryEntityData for BlockData {}
+
+struct EbbHeaderBlockData {
+    // The branches to this EBB and the basic blocks containing them.
+    predecessors: Vec<(Block, Inst)>,
+    // Have all the predecessors been declared?
+    sealed: bool,
+    // The EBB this block is the header of.
+    ebb: Ebb,
+    // Placeholder EBB arguments for the variables used before the EBB was sealed.
+    undef_variables: Vec<(Variable, Value)>,
+}
+
+/// SSA builder.
+///
+/// The `SSABuilder` keeps track of the variable definitions in each basic block, and translates
+/// variable uses into SSA values.
+pub struct SSABuilder {
+    // The value of each variable in the basic blocks where it has been defined or resolved.
+    variables: HashMap<(Variable, Block), Value>,
+
+    // All the basic blocks.
+    blocks: EntityMap<Block, BlockData>,
+
+    // The header block of each EBB.
+    ebb_headers: EntityMap<Ebb, Option<Block>>,
+
+    // EBBs created to split `br_table` edges since the last call to `take_split_ebbs()`.
+    split_ebbs: Vec<Ebb>,
+}
+
+impl SSABuilder {
+    /// Create a new, empty SSA builder.
+    pub fn new() -> SSABuilder {
+        SSABuilder {
+            variables: HashMap::new(),
+            blocks: EntityMap::new(),
+            ebb_headers: EntityMap::new(),
+            split_ebbs: Vec::new(),
+        }
+    }
+
+    /// Clear all the data structures while keeping their allocated memory.
+    pub fn clear(&mut self) {
+        self.variables.clear();
+        self.blocks.clear();
+        self.ebb_headers.clear();
+        self.split_ebbs.clear();
+    }
+
+    /// Are all the data structures empty?
+    pub fn is_empty(&self) -> bool {
+        self.variables.is_empty() && self.blocks.is_empty() && self.ebb_headers.is_empty() &&
+        self.split_ebbs.is_empty()
+    }
+
+    /// Declare a new EBB and return its header block.
+    ///
+    /// The EBB starts out unsealed with no predecessors.
+    pub fn declare_ebb_header_block(&mut self, ebb: Ebb) -> Block {
+        let block = self.blocks
+            .push(BlockData::EbbHeader(EbbHeaderBlockData {
+                                           predecessors: Vec::new(),
+                                           sealed: false,
+                                           ebb: ebb,
+                                           undef_variables: Vec::new(),
+                                       }));
+        *self.ebb_headers.ensure(ebb) = Some(block);
+        block
+    }
+
+    /// Declare a new basic block following a conditional branch at the end of `predecessor`.
+    pub fn declare_ebb_body_block(&mut self, predecessor: Block) -> Block {
+        self.blocks
+            .push(BlockData::EbbBody { predecessor: predecessor })
+    }
+
+    /// Get the header block of `ebb`.
+    pub fn header_block(&self, ebb: Ebb) -> Block {
+        self.ebb_headers[ebb].expect("EBB not declared to the SSA builder")
+    }
+
+    /// Declare the branch `inst` at the end of `block` as a predecessor of `ebb`.
+    ///
+    /// The EBB must not be sealed yet.
+    pub fn declare_ebb_predecessor(&mut self, ebb: Ebb, block: Block, inst: Inst) {
+        let header = self.header_block(ebb);
+        match self.blocks[header] {
+            BlockData::EbbHeader(ref mut data) => {
+                assert!(!data.sealed,
+                        "{} is sealed, it can't get more predecessors",
+                        ebb);
+                data.predecessors.push((block, inst));
+            }
+            BlockData::EbbBody { .. } => panic!("{} has no header block", ebb),
+        }
+    }
+
+    /// Get the predecessors of `ebb` that have been declared so far.
+    pub fn predecessors(&self, ebb: Ebb) -> &[(Block, Inst)] {
+        match self.blocks[self.header_block(ebb)] {
+            BlockData::EbbHeader(ref data) => &data.predecessors,
+            BlockData::EbbBody { .. } => panic!("{} has no header block", ebb),
+        }
+    }
+
+    /// Have all the predecessors of `ebb` been declared?
+    pub fn is_sealed(&self, ebb: Ebb) -> bool {
+        match self.blocks[self.header_block(ebb)] {
+            BlockData::EbbHeader(ref data) => data.sealed,
+            BlockData::EbbBody { .. } => panic!("{} has no header block", ebb),
+        }
+    }
+
+    /// Get the EBBs created to split `br_table` edges since the last call.
+    ///
+    /// These EBBs have been appended to the layout and filled with a `jump` instruction. They are
+    /// sealed, and must not get any more instructions.
+    pub fn take_split_ebbs(&mut self) -> Vec<Ebb> {
+        self.split_ebbs.split_off(0)
+    }
+
+    /// Record that `var` has the value `val` at the end of `block`.
+    pub fn def_var(&mut self, var: Variable, val: Value, block: Block) {
+        self.variables.insert((var, block), val);
+    }
+
+    /// Get the value of `var` with type `ty` at the current end of `block`.
+    ///
+    /// This may insert EBB arguments and append branch arguments to the predecessors in `func`. A
+    /// variable that is used before being defined in the entry block is zero-initialized.
+    pub fn use_var(&mut self, func: &mut Function, var: Variable, ty: Type, block: Block) -> Value {
+        if let Some(&val) = self.variables.get(&(var, block)) {
+            return func.dfg.resolve_aliases(val);
+        }
+
+        let (ebb, sealed, predecessors) = match self.blocks[block] {
+            BlockData::EbbBody { predecessor } => {
+                let val = self.use_var(func, var, ty, predecessor);
+                self.def_var(var, val, block);
+                return val;
+            }
+            BlockData::EbbHeader(ref data) => {
+                (data.ebb, data.sealed, data.predecessors.len())
+            }
+        };
+
+        let val = if !sealed {
+            // The predecessors aren't known yet. Insert a placeholder argument which is completed
+            // when the EBB is sealed.
+            let val = func.dfg.append_ebb_arg(ebb, ty);
+            if let BlockData::EbbHeader(ref mut data) = self.blocks[block] {
+                data.undef_variables.push((var, val));
+            }
+            val
+        } else if predecessors == 0 {
+            // The variable is used in the entry block before being defined.
+            emit_zero(func, ebb, ty)
+        } else if predecessors == 1 {
+            let pred = self.predecessors(ebb)[0].0;
+            self.use_var(func, var, ty, pred)
+        } else {
+            // Define the variable before looking at the predecessors to break cycles.
+            let val = func.dfg.append_ebb_arg(ebb, ty);
+            self.def_var(var, val, block);
+            self.complete_ebb_arg(func, var, ty, block, val)
+        };
+        self.def_var(var, val, block);
+        val
+    }
+
+    /// Seal `ebb` after all its predecessors have been declared.
+    ///
+    /// The placeholder arguments for the variables used in the EBB get their branch arguments.
+    pub fn seal_ebb_header_block(&mut self, ebb: Ebb, func: &mut Function) {
+        let block = self.header_block(ebb);
+        let (undef_variables, num_preds) = match self.blocks[block] {
+            BlockData::EbbHeader(ref mut data) => {
+                assert!(!data.sealed, "{} is already sealed", ebb);
+                data.sealed = true;
+                (data.undef_variables.split_off(0), data.predecessors.len())
+            }
+            BlockData::EbbBody { .. } => panic!("{} has no header block", ebb),
+        };
+
+        for (var, val) in undef_variables {
+            let ty = func.dfg.value_type(val);
+            if num_preds == 0 {
+                // The variable is used in the entry block before being defined.
+                let zero = emit_zero(func, ebb, ty);
+                func.dfg.remove_ebb_arg(val);
+                func.dfg.change_to_alias(val, zero);
+            } else {
+                self.complete_ebb_arg(func, var, ty, block, val);
+            }
+        }
+    }
+
+    /// Seal all the EBBs that have been declared and are not sealed yet.
+    pub fn seal_all_ebb_header_blocks(&mut self, func: &mut Function) {
+        let ebbs: Vec<Ebb> = self.ebb_headers
+            .keys()
+            .filter(|&ebb| self.ebb_headers[ebb].is_some() && !self.is_sealed(ebb))
+            .collect();
+        for ebb in ebbs {
+            self.seal_ebb_header_block(ebb, func);
+        }
+    }
+
+    /// Find the values of `var` passed by the predecessors of the header `block` to the EBB
+    /// argument `val`.
+    ///
+    /// If all the predecessors pass the same value, the EBB argument is removed and turned into
+    /// an alias of that value. Otherwise, the values are appended to the predecessor branches.
+    ///
+    /// Return the value of `var` at the start of the EBB.
+    fn complete_ebb_arg(&mut self,
+                        func: &mut Function,
+                        var: Variable,
+                        ty: Type,
+                        block: Block,
+                        val: Value)
+                        -> Value {
+        let predecessors = match self.blocks[block] {
+            BlockData::EbbHeader(ref data) => data.predecessors.clone(),
+            BlockData::EbbBody { .. } => panic!("{} is not an EBB header block", block.0),
+        };
+
+        let pred_vals: Vec<Value> = predecessors
+            .iter()
+            .map(|&(pred, _)| self.use_var(func, var, ty, pred))
+            .collect();
+
+        // The argument is trivial if all the predecessors pass the same value, not counting the
+        // argument itself passed around a loop.
+        let mut same = None;
+        for &pred_val in &pred_vals {
+            let pred_val = func.dfg.resolve_aliases(pred_val);
+            if pred_val == val || Some(pred_val) == same {
+                continue;
+            }
+            if same.is_some() {
+                same = None;
+                break;
+            }
+            same = Some(pred_val);
+        }
+
+        if let Some(same) = same {
+            func.dfg.remove_ebb_arg(val);
+            func.dfg.change_to_alias(val, same);
+            same
+        } else {
+            for (idx, (&(_, inst), &pred_val)) in predecessors.iter().zip(&pred_vals).enumerate() {
+                let inst = if func.dfg[inst].branch_destination().is_some() {
+                    inst
+                } else {
+                    self.split_branch_table_edge(func, block, idx)
+                };
+                func.dfg.append_inst_arg(inst, pred_val);
+            }
+            val
+        }
+    }
+
+    /// Split the edge from the `br_table` instruction that is predecessor number `idx` of the
+    /// header `block`.
+    ///
+    /// A new EBB is inserted between the `br_table` and its destination, and it becomes the
+    /// predecessor instead. Return the new `jump` instruction which can take EBB arguments.
+    fn split_branch_table_edge(&mut self, func: &mut Function, block: Block, idx: usize) -> Inst {
+        let (ebb, pred_block, branch) = match self.blocks[block] {
+            BlockData::EbbHeader(ref data) => {
+                let (pred_block, branch) = data.predecessors[idx];
+                (data.ebb, pred_block, branch)
+            }
+            BlockData::EbbBody { .. } => panic!("{} is not an EBB header block", block.0),
+        };
+        let table = match func.dfg[branch] {
+            InstructionData::BranchTable { table, .. } => table,
+            _ => panic!("{} can't pass arguments to its destination",
+                        func.dfg.display_inst(branch)),
+        };
+
+        let middle = func.dfg.make_ebb();
+        func.layout.append_ebb(middle);
+        let middle_block = self.declare_ebb_header_block(middle);
+        self.declare_ebb_predecessor(middle, pred_block, branch);
+        if let BlockData::EbbHeader(ref mut data) = self.blocks[middle_block] {
+            data.sealed = true;
+        }
+        self.split_ebbs.push(middle);
+
+        let entries: Vec<usize> = func.jump_tables[table]
+            .entries()
+            .filter(|&(_, dest)| dest == ebb)
+            .map(|(idx, _)| idx)
+            .collect();
+        for idx in entries {
+            func.jump_tables[table].set_entry(idx, middle);
+        }
+
+        let jump = {
+            let mut pos = Cursor::new(&mut func.layout);
+            pos.goto_bottom(middle);
+            func.dfg.ins(&mut pos).jump(ebb, &[])
+        };
+        if let BlockData::EbbHeader(ref mut data) = self.blocks[block] {
+            data.predecessors[idx] = (middle_block, jump);
+        }
+        jump
+    }
+}
+
+/// Insert a zero value of type `ty` at the top of `ebb`.
+fn emit_zero(func: &mut Function, ebb: Ebb, ty: Type) -> Value {
+    let mut pos = Cursor::new(&mut func.layout);
+    pos.goto_top(ebb);
+    pos.next_inst();
+    let dfg = &mut func.dfg;
+    if !ty.is_scalar() {
+        let lane = zero_scalar(dfg, &mut pos, ty.lane_type());
+        dfg.ins(&mut pos).splat(ty, lane)
+    } else {
+        zero_scalar(dfg, &mut pos, ty)
+    }
+}
+
+fn zero_scalar(dfg: &mut DataFlowGraph, pos: &mut Cursor, ty: Type) -> Value {
+    if ty.is_int() {
+        dfg.ins(pos).iconst(ty, 0)
+    } else if ty == types::F32 {
+        dfg.ins(pos).f32const(Ieee32::new(0.0))
+    } else if ty == types::F64 {
+        dfg.ins(pos).f64const(Ieee64::new(0.0))
+    } else if ty.is_bool() {
+        let zero = dfg.ins(pos).iconst(types::I32, 0);
+        let b1 = dfg.ins(pos).icmp_imm(IntCC::NotEqual, zero, 0);
+        if ty == types::B1 {
+            b1
+        } else {
+            dfg.ins(pos).bextend(ty, b1)
+        }
+    } else {
+        panic!("Can't create a zero value of type {}", ty)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cretonne::entity_map::EntityRef;
+    use cretonne::ir::{Function, DataFlowGraph, InstBuilder, Cursor, Ebb, Inst, Value, types};
+    use cretonne::verify_function;
+    use variable::Variable;
+
+    // Get a cursor for appending instructions to `ebb`.
+    fn cursor<'f>(func: &'f mut Function, ebb: Ebb) -> (&'f mut DataFlowGraph, Cursor<'f>) {
+        let mut pos = Cursor::new(&mut func.layout);
+        pos.goto_bottom(ebb);
+        (&mut func.dfg, pos)
+    }
+
+    fn iconst(func: &mut Function, ebb: Ebb, imm: i64) -> Value {
+        let (dfg, mut pos) = cursor(func, ebb);
+        dfg.ins(&mut pos).iconst(types::I32, imm)
+    }
+
+    fn jump(func: &mut Function, ebb: Ebb, dest: Ebb) -> Inst {
+        let (dfg, mut pos) = cursor(func, ebb);
+        dfg.ins(&mut pos).jump(dest, &[])
+    }
+
+    fn brnz(func: &mut Function, ebb: Ebb, cond: Value, dest: Ebb) -> Inst {
+        let (dfg, mut pos) = cursor(func, ebb);
+        dfg.ins(&mut pos).brnz(cond, dest, &[])
+    }
+
+    fn ret(func: &mut Function, ebb: Ebb) {
+        let (dfg, mut pos) = cursor(func, ebb);
+        dfg.ins(&mut pos).return_(&[]);
+    }
+
+    #[test]
+    fn straight_line() {
+        let mut func = Function::new();
+        let mut ssa = SSABuilder::new();
+        let x = Variable::new(0);
+        let y = Variable::new(1);
+
+        let ebb0 = func.dfg.make_ebb();
+        func.layout.append_ebb(ebb0);
+        let block0 = ssa.declare_ebb_header_block(ebb0);
+        ssa.seal_ebb_header_block(ebb0, &mut func);
+
+        let x1 = iconst(&mut func, ebb0, 1);
+        ssa.def_var(x, x1, block0);
+        assert_eq!(ssa.use_var(&mut func, x, types::I32, block0), x1);
+
+        // A use before definition in the entry block is zero-initialized.
+        let y0 = ssa.use_var(&mut func, y, types::I32, block0);
+        assert_eq!(func.dfg.display_inst(func.layout.ebb_insts(ebb0).next().unwrap())
+                       .to_string(),
+                   format!("{} = iconst.i32 0", y0));
+        assert_eq!(func.dfg.num_ebb_args(ebb0), 0);
+    }
+
+    #[test]
+    fn diamond() {
+        // ebb0: x = 1; brnz ebb2
+        //       x = 2; jump ebb1   (body block of ebb0)
+        // ebb2: jump ebb1
+        // ebb1: use x
+        let mut func = Function::new();
+        let mut ssa = SSABuilder::new();
+        let x = Variable::new(0);
+
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        let ebb2 = func.dfg.make_ebb();
+        func.layout.append_ebb(ebb0);
+        func.layout.append_ebb(ebb2);
+        func.layout.append_ebb(ebb1);
+        let block0 = ssa.declare_ebb_header_block(ebb0);
+        let block1 = ssa.declare_ebb_header_block(ebb1);
+        let block2 = ssa.declare_ebb_header_block(ebb2);
+        ssa.seal_ebb_header_block(ebb0, &mut func);
+
+        let x1 = iconst(&mut func, ebb0, 1);
+        ssa.def_var(x, x1, block0);
+        let br = brnz(&mut func, ebb0, x1, ebb2);
+        ssa.declare_ebb_predecessor(ebb2, block0, br);
+        let body = ssa.declare_ebb_body_block(block0);
+        let x2 = iconst(&mut func, ebb0, 2);
+        ssa.def_var(x, x2, body);
+        let jmp0 = jump(&mut func, ebb0, ebb1);
+        ssa.declare_ebb_predecessor(ebb1, body, jmp0);
+
+        ssa.seal_ebb_header_block(ebb2, &mut func);
+        let jmp2 = jump(&mut func, ebb2, ebb1);
+        ssa.declare_ebb_predecessor(ebb1, block2, jmp2);
+        ssa.seal_ebb_header_block(ebb1, &mut func);
+
+        let x3 = ssa.use_var(&mut func, x, types::I32, block1);
+        ret(&mut func, ebb1);
+        assert_eq!(func.dfg.ebb_args(ebb1), &[x3]);
+        assert_eq!(func.dfg.inst_variable_args(jmp0), &[x2]);
+        assert_eq!(func.dfg.inst_variable_args(jmp2), &[x1]);
+        assert_eq!(func.dfg.num_ebb_args(ebb2), 0);
+        assert!(verify_function(&func).is_ok());
+    }
+
+    #[test]
+    fn unsealed_loop() {
+        // ebb0: x = 1; jump ebb1
+        // ebb1: brnz x, ebb2; return
+        // ebb2: jump ebb1
+        let mut func = Function::new();
+        let mut ssa = SSABuilder::new();
+        let x = Variable::new(0);
+        let y = Variable::new(1);
+
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        let ebb2 = func.dfg.make_ebb();
+        func.layout.append_ebb(ebb0);
+        func.layout.append_ebb(ebb1);
+        func.layout.append_ebb(ebb2);
+        let block0 = ssa.declare_ebb_header_block(ebb0);
+        let block1 = ssa.declare_ebb_header_block(ebb1);
+        let block2 = ssa.declare_ebb_header_block(ebb2);
+        ssa.seal_ebb_header_block(ebb0, &mut func);
+
+        let x1 = iconst(&mut func, ebb0, 1);
+        ssa.def_var(x, x1, block0);
+        let y1 = iconst(&mut func, ebb0, 2);
+        ssa.def_var(y, y1, block0);
+        let jmp0 = jump(&mut func, ebb0, ebb1);
+        ssa.declare_ebb_predecessor(ebb1, block0, jmp0);
+
+        // The loop header isn't sealed until the back edge has been built.
+        let x2 = ssa.use_var(&mut func, x, types::I32, block1);
+        let y2 = ssa.use_var(&mut func, y, types::I32, block1);
+        assert_eq!(func.dfg.ebb_args(ebb1), &[x2, y2]);
+        let br = brnz(&mut func, ebb1, x2, ebb2);
+        ssa.declare_ebb_predecessor(ebb2, block1, br);
+        ssa.seal_ebb_header_block(ebb2, &mut func);
+        ret(&mut func, ebb1);
+
+        // The loop body changes `x` but not `y`.
+        let x3 = iconst(&mut func, ebb2, 3);
+        ssa.def_var(x, x3, block2);
+        let jmp2 = jump(&mut func, ebb2, ebb1);
+        ssa.declare_ebb_predecessor(ebb1, block2, jmp2);
+        ssa.seal_ebb_header_block(ebb1, &mut func);
+
+        // The `y` argument was trivial.
+        assert_eq!(func.dfg.ebb_args(ebb1), &[x2]);
+        assert_eq!(func.dfg.resolve_aliases(y2), y1);
+        assert_eq!(func.dfg.inst_variable_args(jmp0), &[x1]);
+        assert_eq!(func.dfg.inst_variable_args(jmp2), &[x3]);
+        assert!(verify_function(&func).is_ok());
+    }
+}
@@ -0,0 +1,302 @@
+//! Lowering of switch statements.
+//!
+//! A switch with dense case values is best implemented with a `br_table` instruction, but the
+//! case values of a source language switch statement can be sparse and span the whole 64-bit
+//! range. The `Switch` helper divides the cases into runs of consecutive values. Long runs get a
+//! jump table, and the rest are found with a binary search tree of comparisons.
+
+use cretonne::ir::{Ebb, Value, InstBuilder, JumpTableData};
+use cretonne::ir::condcodes::IntCC;
+use frontend::FunctionBuilder;
+use std::collections::BTreeMap;
+
+// Runs of consecutive case values shorter than this are compared one value at a time.
+const MIN_JUMP_TABLE_SIZE: usize = 4;
+
+// The maximum number of case ranges tested in sequence at a leaf of the search tree.
+const MAX_LINEAR_RANGES: usize = 3;
+
+/// A switch statement mapping integer values to EBBs.
+///
+/// Add the cases with `set_entry()`, and then call `emit()` to terminate the current EBB with
+/// branches to the case EBBs.
+///
+/// # Example
+///
+/// ```
+/// # extern crate cretonne;
+/// # extern crate cton_frontend;
+/// # use cretonne::ir::{Function, InstBuilder, types};
+/// # use cton_frontend::{ILBuilder, FunctionBuilder, Switch};
+/// # fn main() {
+/// # let mut func = Function::new();
+/// # let mut il_builder = ILBuilder::new();
+/// # let mut builder = FunctionBuilder::new(&mut func, &mut il_builder);
+/// # let entry = builder.create_ebb();
+/// # builder.switch_to_block(entry);
+/// # let val = builder.ins().iconst(types::I64, 7);
+/// let small = builder.create_ebb();
+/// let huge = builder.create_ebb();
+/// let otherwise = builder.create_ebb();
+///
+/// let mut switch = Switch::new();
+/// for case in 0..8 {
+///     switch.set_entry(case, small);
+/// }
+/// switch.set_entry(1 << 40, huge);
+/// switch.emit(&mut builder, val, otherwise);
+/// # }
+/// ```
+#[derive(Debug, Default)]
+pub struct Switch {
+    cases: BTreeMap<u64, Ebb>,
+}
+
+// A run of consecutive case values, starting at `first`.
+#[derive(Debug, PartialEq, Eq)]
+struct CaseRange {
+    first: u64,
+    ebbs: Vec<Ebb>,
+}
+
+impl Switch {
+    /// Create a new, empty switch.
+    pub fn new() -> Switch {
+        Switch { cases: BTreeMap::new() }
+    }
+
+    /// Branch to `ebb` when the switch value is `index`.
+    ///
+    /// Panics if there already is a case for `index`.
+    pub fn set_entry(&mut self, index: u64, ebb: Ebb) {
+        let prev = self.cases.insert(index, ebb);
+        assert!(prev.is_none(), "switch case {} is already defined", index);
+    }
+
+    /// Terminate the current EBB of `builder` with branches to the case EBBs based on `val`,
+    /// falling back to `otherwise` for values without a case.
+    ///
+    /// The case values are compared with `val` as unsigned integers, so they must fit in the type
+    /// of `val`. The EBBs created for the search tree and jump tables are sealed. The caller is
+    /// responsible for sealing the case EBBs and `otherwise` when all their predecessors have been
+    /// built.
+    pub fn emit(self, builder: &mut FunctionBuilder, val: Value, otherwise: Ebb) {
+        let bits = builder.func.dfg.value_type(val).bits();
+        if let Some((&last, _)) = self.cases.iter().next_back() {
+            assert!(bits >= 64 || last >> bits == 0,
+                    "switch case {} doesn't fit in {} bits",
+                    last,
+                    bits);
+        }
+
+        let ranges = self.case_ranges();
+        let mut jump_tables = Vec::new();
+        let mut stack: Vec<(Option<Ebb>, &[CaseRange])> = vec![(None, &ranges)];
+        while let Some((ebb, ranges)) = stack.pop() {
+            if let Some(ebb) = ebb {
+                builder.switch_to_block(ebb);
+                builder.seal_block(ebb);
+            }
+
+            if ranges.len() <= MAX_LINEAR_RANGES {
+                // Test the ranges from the top. Each range is known to contain the lowest value
+                // that gets past the tests of the ranges above it.
+                for range in ranges.iter().rev() {
+                    if range.ebbs.len() == 1 {
+                        let cond = builder.ins().icmp_imm(IntCC::Equal, val, range.first as i64);
+                        builder.ins().brnz(cond, range.ebbs[0], &[]);
+                    } else {
+                        let jt_ebb = builder.create_ebb();
+                        let cond = builder
+                            .ins()
+                            .icmp_imm(IntCC::UnsignedGreaterThanOrEqual, val, range.first as i64);
+                        builder.ins().brnz(cond, jt_ebb, &[]);
+                        jump_tables.push((jt_ebb, range));
+                    }
+                }
+                builder.ins().jump(otherwise, &[]);
+            } else {
+                let (left, right) = ranges.split_at(ranges.len() / 2);
+                let left_ebb = builder.create_ebb();
+                let right_ebb = builder.create_ebb();
+                let cond = builder
+                    .ins()
+                    .icmp_imm(IntCC::UnsignedGreaterThanOrEqual, val, right[0].first as i64);
+                builder.ins().brnz(cond, right_ebb, &[]);
+                builder.ins().jump(left_ebb, &[]);
+                stack.push((Some(left_ebb), left));
+                stack.push((Some(right_ebb), right));
+            }
+        }
+
+        // Values past the end of a jump table fall through to `otherwise`.
+        for (jt_ebb, range) in jump_tables {
+            builder.switch_to_block(jt_ebb);
+            builder.seal_block(jt_ebb);
+            let index = if range.first == 0 {
+                val
+            } else {
+                builder
+                    .ins()
+                    .iadd_imm(val, (range.first as i64).wrapping_neg())
+            };
+            let mut data = JumpTableData::new();
+            for (idx, &ebb) in range.ebbs.iter().enumerate() {
+                data.set_entry(idx, ebb);
+            }
+            let jt = builder.create_jump_table(data);
+            builder.ins().br_table(index, jt);
+            builder.ins().jump(otherwise, &[]);
+        }
+    }
+
+    // Divide the cases into ranges of consecutive values, in increasing order. Runs that are too
+    // short for a jump table are split into single-value ranges.
+    fn case_ranges(&self) -> Vec<CaseRange> {
+        let mut runs: Vec<CaseRange> = Vec::new();
+        for (&index, &ebb) in &self.cases {
+            if let Some(run) = runs.last_mut() {
+                if index - run.first == run.ebbs.len() as u64 {
+                    run.ebbs.push(ebb);
+                    continue;
+                }
+            }
+            runs.push(CaseRange {
+                          first: index,
+                          ebbs: vec![ebb],
+                      });
+        }
+
+        let mut ranges = Vec::with_capacity(runs.len());
+        for run in runs {
+            if run.ebbs.len() >= MIN_JUMP_TABLE_SIZE {
+                ranges.push(run);
+            } else {
+                for (idx, ebb) in run.ebbs.into_iter().enumerate() {
+                    ranges.push(CaseRange {
+                                    first: run.first + idx as u64,
+                                    ebbs: vec![ebb],
+                                });
+                }
+            }
+        }
+        ranges
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cretonne::entity_map::EntityRef;
+    use cretonne::ir::{Function, FunctionName, Signature, ArgumentType, types};
+    use cretonne::verify_function;
+    use frontend::ILBuilder;
+    use variable::Variable;
+
+    #[test]
+    fn ranges() {
+        let ebbs: Vec<Ebb> = (0..4).map(Ebb::new).collect();
+        let mut switch = Switch::new();
+        for i in 0..5 {
+            switch.set_entry(i, ebbs[0]);
+        }
+        switch.set_entry(7, ebbs[1]);
+        switch.set_entry(8, ebbs[2]);
+        switch.set_entry(!0, ebbs[3]);
+        assert_eq!(switch.case_ranges(),
+                   [CaseRange {
+                        first: 0,
+                        ebbs: vec![ebbs[0]; 5],
+                    },
+                    CaseRange {
+                        first: 7,
+                        ebbs: vec![ebbs[1]],
+                    },
+                    CaseRange {
+                        first: 8,
+                        ebbs: vec![ebbs[2]],
+                    },
+                    CaseRange {
+                        first: !0,
+                        ebbs: vec![ebbs[3]],
+                    }]);
+    }
+
+    // Build a function returning a constant per case of a switch on its argument. When
+    // `loop_back` is set, the first case EBB is also a loop header to pass arguments to.
+    fn switch_func(cases: &[u64], loop_back: bool) -> Function {
+        let mut sig = Signature::new();
+        sig.argument_types.push(ArgumentType::new(types::I64));
+        sig.return_types.push(ArgumentType::new(types::I64));
+        let mut func = Function::with_name_signature(FunctionName::new("switch"), sig);
+        let mut il_builder = ILBuilder::new();
+        {
+            let mut builder = FunctionBuilder::new(&mut func, &mut il_builder);
+            let x = Variable::new(0);
+            builder.declare_var(x, types::I64);
+
+            let entry = builder.create_ebb();
+            let otherwise = builder.create_ebb();
+            let case_ebbs: Vec<Ebb> = cases.iter().map(|_| builder.create_ebb()).collect();
+
+            builder.switch_to_block(entry);
+            builder.append_ebb_args_for_function_args(entry);
+            let arg = builder.ebb_args(entry)[0];
+            builder.def_var(x, arg);
+            let mut switch = Switch::new();
+            for (&case, &ebb) in cases.iter().zip(&case_ebbs) {
+                switch.set_entry(case, ebb);
+            }
+            switch.emit(&mut builder, arg, otherwise);
+            builder.seal_block(entry);
+
+            builder.switch_to_block(otherwise);
+            builder.seal_block(otherwise);
+            if loop_back {
+                let v = builder.ins().iconst(types::I64, 1);
+                builder.def_var(x, v);
+                builder.ins().jump(case_ebbs[0], &[]);
+            } else {
+                let v = builder.ins().iconst(types::I64, -1);
+                builder.ins().return_(&[v]);
+            }
+
+            for &ebb in &case_ebbs {
+                builder.switch_to_block(ebb);
+                builder.seal_block(ebb);
+                let v = builder.use_var(x);
+                builder.ins().return_(&[v]);
+            }
+            builder.finalize();
+        }
+        func
+    }
+
+    #[test]
+    fn sparse() {
+        let cases = [0, 1, 2, 3, 4, 10, 11, 100, 1000, 1 << 40, !0];
+        let func = switch_func(&cases, false);
+        if let Err(err) = verify_function(&func) {
+            panic!("{}\n{}", err, func.display(None));
+        }
+        assert_eq!(func.jump_tables.len(), 1);
+    }
+
+    #[test]
+    fn split_jump_table_edge() {
+        let cases = [0, 1, 2, 3, 4];
+        let func = switch_func(&cases, true);
+        if let Err(err) = verify_function(&func) {
+            panic!("{}\n{}", err, func.display(None));
+        }
+
+        // The first case EBB needs an argument for `x`, so its jump table entry is redirected.
+        let jt = func.jump_tables.keys().next().unwrap();
+        let middle = func.jump_tables[jt].get_entry(0).unwrap();
+        let dest = func.dfg[func.layout.last_inst(middle).unwrap()]
+            .branch_destination()
+            .unwrap();
+        assert_eq!(func.dfg.num_ebb_args(dest), 1);
+        assert!(func.jump_tables[jt].get_entry(1) != Some(middle));
+    }
+}
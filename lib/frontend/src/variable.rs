@@ -0,0 +1,29 @@
+//! Frontend variables.
+
+use cretonne::entity_map::EntityRef;
+use std::fmt;
+use std::u32;
+
+/// A mutable variable in the function being built.
+///
+/// Variables are numbered by the frontend, typically after the local variables of the source
+/// language. They are translated into SSA values by the `FunctionBuilder`.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub struct Variable(u32);
+
+impl EntityRef for Variable {
+    fn new(index: usize) -> Variable {
+        assert!(index < (u32::MAX as usize));
+        Variable(index as u32)
+    }
+
+    fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl fmt::Display for Variable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "var{}", self.0)
+    }
+}
@@ -0,0 +1,1016 @@
+//! The Cretonne IL interpreter.
+//!
+//! The interpreter executes one instruction at a time, keeping the values of a function call in a
+//! `Frame`. All memory accesses go to a single sandboxed buffer: The heap occupies the addresses
+//! from 0 to the heap size, and the stack slots of active calls are allocated above it. Any access
+//! outside the buffer traps with `heap_oob` instead of touching host memory.
+
+use cretonne::entity_map::EntityMap;
+use cretonne::ir::{Function, Ebb, Inst, Value, Type, StackSlot, InstructionData, Opcode, TrapCode,
+                   types};
+use cretonne::ir::condcodes::{IntCC, FloatCC};
+use cretonne::ir::instructions::BranchInfo;
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::result;
+use value::DataValue;
+
+/// The default size of the heap in bytes.
+const DEFAULT_HEAP_SIZE: usize = 64 * 1024;
+
+/// The maximum number of bytes allocated to stack slots by all active calls.
+const MAX_STACK_SIZE: usize = 1024 * 1024;
+
+/// The maximum depth of nested calls. Each call also uses the host stack.
+const MAX_CALL_DEPTH: usize = 100;
+
+/// The reasons the interpreter can fail to return from a function.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The function trapped.
+    Trap(TrapCode),
+
+    /// The function uses an instruction the interpreter doesn't support.
+    Unsupported(String),
+
+    /// A called function was not added to the interpreter.
+    UnknownFunction(String),
+
+    /// A function was called with arguments that don't match its signature.
+    BadArguments(String),
+
+    /// The interpreter ran out of fuel before the function returned.
+    OutOfFuel,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            Error::Trap(code) => write!(f, "trap {}", code),
+            Error::Unsupported(ref msg) => write!(f, "unsupported: {}", msg),
+            Error::UnknownFunction(ref name) => write!(f, "unknown function {}", name),
+            Error::BadArguments(ref msg) => write!(f, "bad arguments: {}", msg),
+            Error::OutOfFuel => write!(f, "out of fuel"),
+        }
+    }
+}
+
+/// Result of interpreting a function.
+pub type Result<T> = result::Result<T, Error>;
+
+/// What to do after executing an instruction.
+enum Control {
+    /// Continue with the next instruction.
+    Continue,
+    /// Jump to an EBB with the given arguments.
+    Jump(Ebb, Vec<DataValue>),
+    /// Return from the function with the given values.
+    Return(Vec<DataValue>),
+}
+
+/// The state of a single function call.
+struct Frame {
+    values: EntityMap<Value, Option<DataValue>>,
+    slots: EntityMap<StackSlot, u64>,
+}
+
+impl Frame {
+    fn get(&self, value: Value) -> DataValue {
+        self.values
+            .get(value)
+            .and_then(|v| *v)
+            .unwrap_or_else(|| panic!("{} used before it was defined", value))
+    }
+
+    fn set(&mut self, value: Value, data: DataValue) {
+        *self.values.ensure(value) = Some(data);
+    }
+
+    fn set_all(&mut self, values: &[Value], data: &[DataValue]) {
+        assert_eq!(values.len(), data.len());
+        for (&value, &data) in values.iter().zip(data) {
+            self.set(value, data);
+        }
+    }
+}
+
+/// An interpreter for Cretonne IL functions.
+///
+/// Functions that can be called from the interpreted code must be added with `add_function()`.
+/// They are looked up by name. The heap contents persist between calls, so they can be inspected
+/// after a function returns.
+pub struct Interpreter<'f> {
+    functions: HashMap<String, &'f Function>,
+    memory: Vec<u8>,
+    heap_size: usize,
+    fuel: Option<u64>,
+    depth: usize,
+}
+
+impl<'f> Interpreter<'f> {
+    /// Create an interpreter with the default heap size.
+    pub fn new() -> Interpreter<'f> {
+        Interpreter::with_heap_size(DEFAULT_HEAP_SIZE)
+    }
+
+    /// Create an interpreter with a zero-initialized heap of `heap_size` bytes.
+    pub fn with_heap_size(heap_size: usize) -> Interpreter<'f> {
+        Interpreter {
+            functions: HashMap::new(),
+            memory: vec![0; heap_size],
+            heap_size: heap_size,
+            fuel: None,
+            depth: 0,
+        }
+    }
+
+    /// Make `func` available to `call` instructions and `call_by_name()`.
+    pub fn add_function(&mut self, func: &'f Function) {
+        self.functions.insert(func.name.as_str().to_string(), func);
+    }
+
+    /// Limit the number of instructions executed, or remove the limit with `None`.
+    ///
+    /// Each executed instruction consumes one unit of fuel, and the interpreter stops with
+    /// `Error::OutOfFuel` when there is none left. This guarantees termination when running
+    /// randomly generated code.
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.fuel = fuel;
+    }
+
+    /// Get the remaining fuel.
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    /// Get the contents of the heap.
+    pub fn heap(&self) -> &[u8] {
+        &self.memory[0..self.heap_size]
+    }
+
+    /// Get the contents of the heap for modification.
+    pub fn heap_mut(&mut self) -> &mut [u8] {
+        &mut self.memory[0..self.heap_size]
+    }
+
+    /// Call the function named `name` which was added with `add_function()`.
+    pub fn call_by_name(&mut self, name: &str, args: &[DataValue]) -> Result<Vec<DataValue>> {
+        let func = match self.functions.get(name) {
+            Some(&func) => func,
+            None => return Err(Error::UnknownFunction(name.to_string())),
+        };
+        self.call(func, args)
+    }
+
+    /// Call `func` with `args` and return its return values.
+    pub fn call(&mut self, func: &Function, args: &[DataValue]) -> Result<Vec<DataValue>> {
+        let sig = &func.signature;
+        if args.len() != sig.argument_types.len() ||
+           args.iter()
+               .zip(&sig.argument_types)
+               .any(|(&arg, at)| !has_type(arg, at.value_type)) {
+            return Err(Error::BadArguments(format!("{} expects {}", func.name, sig)));
+        }
+        if self.depth >= MAX_CALL_DEPTH {
+            return Err(Error::Trap(TrapCode::StackOverflow));
+        }
+
+        // Allocate the stack slots above any active calls.
+        let stack_base = self.memory.len();
+        let mut frame = Frame {
+            values: EntityMap::new(),
+            slots: EntityMap::new(),
+        };
+        for ss in func.stack_slots.keys() {
+            let addr = (self.memory.len() + 7) & !7;
+            *frame.slots.ensure(ss) = addr as u64;
+            self.memory.resize(addr + func.stack_slots[ss].size as usize, 0);
+        }
+        if self.memory.len() > self.heap_size + MAX_STACK_SIZE {
+            self.memory.truncate(stack_base);
+            return Err(Error::Trap(TrapCode::StackOverflow));
+        }
+
+        self.depth += 1;
+        let result = self.run(func, &mut frame, args);
+        self.depth -= 1;
+        self.memory.truncate(stack_base);
+        result
+    }
+
+    // Execute the body of `func`.
+    fn run(&mut self,
+           func: &Function,
+           frame: &mut Frame,
+           args: &[DataValue])
+           -> Result<Vec<DataValue>> {
+        let mut ebb = match func.layout.entry_block() {
+            Some(ebb) => ebb,
+            None => return Err(Error::Unsupported(format!("{} has no body", func.name))),
+        };
+        frame.set_all(func.dfg.ebb_args(ebb), args);
+
+        loop {
+            let mut target = None;
+            for inst in func.layout.ebb_insts(ebb) {
+                if let Some(ref mut fuel) = self.fuel {
+                    if *fuel == 0 {
+                        return Err(Error::OutOfFuel);
+                    }
+                    *fuel -= 1;
+                }
+                match self.step(func, frame, inst)? {
+                    Control::Continue => {}
+                    Control::Jump(dest, args) => {
+                        target = Some((dest, args));
+                        break;
+                    }
+                    Control::Return(values) => return Ok(values),
+                }
+            }
+            match target {
+                Some((dest, args)) => {
+                    frame.set_all(func.dfg.ebb_args(dest), &args);
+                    ebb = dest;
+                }
+                None => return Err(Error::Unsupported(format!("{} has no terminator", ebb))),
+            }
+        }
+    }
+
+    // Execute a single instruction.
+    fn step(&mut self, func: &Function, frame: &mut Frame, inst: Inst) -> Result<Control> {
+        let dfg = &func.dfg;
+        let data = &dfg[inst];
+        let opcode = data.opcode();
+        let args: Vec<DataValue> = dfg.inst_args(inst)
+            .iter()
+            .map(|&v| frame.get(v))
+            .collect();
+        let results = dfg.inst_results(inst);
+        let rty = results
+            .first()
+            .map(|&v| dfg.value_type(v))
+            .unwrap_or(types::VOID);
+
+        // Branches and jumps.
+        match data.analyze_branch(&dfg.value_lists) {
+            BranchInfo::SingleDest(dest, ebb_args) => {
+                let taken = match *data {
+                    InstructionData::Jump { .. } => true,
+                    InstructionData::Branch { .. } => args[0].as_bool() == (opcode == Opcode::Brnz),
+                    InstructionData::BranchIcmp { cond, .. } => icmp(cond, args[0], args[1]),
+                    _ => return Err(Error::Unsupported(opcode.to_string())),
+                };
+                if !taken {
+                    return Ok(Control::Continue);
+                }
+                let ebb_args = ebb_args.iter().map(|&v| frame.get(v)).collect();
+                return Ok(Control::Jump(dest, ebb_args));
+            }
+            BranchInfo::Table(jt) => {
+                let jt_data = &func.jump_tables[jt];
+                let idx = args[0].as_u64();
+                return Ok(match jt_data.get_entry(idx as usize) {
+                              Some(dest) if idx < jt_data.len() as u64 => {
+                                  Control::Jump(dest, Vec::new())
+                              }
+                              _ => Control::Continue,
+                          });
+            }
+            BranchInfo::NotABranch => {}
+        }
+
+        let values = match *data {
+            InstructionData::UnaryImm { imm, .. } => vec![DataValue::int(rty, imm.into())],
+            InstructionData::UnaryIeee32 { imm, .. } => {
+                vec![DataValue::F32(f32::from_bits(imm.bits()))]
+            }
+            InstructionData::UnaryIeee64 { imm, .. } => {
+                vec![DataValue::F64(f64::from_bits(imm.bits()))]
+            }
+            InstructionData::BinaryImm { imm, .. } => {
+                let ty = dfg.value_type(dfg.inst_args(inst)[0]);
+                let imm = DataValue::int(ty, imm.into());
+                let value = match opcode {
+                    Opcode::IrsubImm => binary(Opcode::Isub, imm, args[0])?,
+                    _ => binary(imm_opcode(opcode), args[0], imm)?,
+                };
+                vec![value]
+            }
+            InstructionData::IntCompare { cond, .. } => {
+                vec![DataValue::Bool(icmp(cond, args[0], args[1]))]
+            }
+            InstructionData::IntCompareImm { cond, imm, .. } => {
+                let imm = DataValue::int(dfg.value_type(dfg.inst_args(inst)[0]), imm.into());
+                vec![DataValue::Bool(icmp(cond, args[0], imm))]
+            }
+            InstructionData::FloatCompare { cond, .. } => {
+                vec![DataValue::Bool(fcmp(cond, args[0], args[1]))]
+            }
+            InstructionData::Call { func_ref, .. } => {
+                let name = dfg.ext_funcs[func_ref].name.as_str();
+                let callee = match self.functions.get(name) {
+                    Some(&callee) => callee,
+                    None => return Err(Error::UnknownFunction(name.to_string())),
+                };
+                self.call(callee, &args)?
+            }
+            InstructionData::Load { offset, .. } => {
+                let offset: i64 = offset.into();
+                let addr = args[0].as_u64().wrapping_add(offset as u64);
+                vec![self.load(opcode, rty, addr, self.memory.len())?]
+            }
+            InstructionData::Store { offset, .. } => {
+                let offset: i64 = offset.into();
+                let addr = args[1].as_u64().wrapping_add(offset as u64);
+                let limit = self.memory.len();
+                self.store(opcode, args[0], addr, limit)?;
+                Vec::new()
+            }
+            InstructionData::StackLoad { stack_slot, offset, .. } => {
+                let offset: i64 = offset.into();
+                let addr = frame.slots[stack_slot].wrapping_add(offset as u64);
+                match opcode {
+                    Opcode::StackAddr => vec![DataValue::int(rty, addr as i64)],
+                    _ => vec![self.load(Opcode::Load, rty, addr, self.memory.len())?],
+                }
+            }
+            InstructionData::StackStore { stack_slot, offset, .. } => {
+                let offset: i64 = offset.into();
+                let addr = frame.slots[stack_slot].wrapping_add(offset as u64);
+                let limit = self.memory.len();
+                self.store(Opcode::Store, args[0], addr, limit)?;
+                Vec::new()
+            }
+            InstructionData::HeapLoad { offset, .. } => {
+                let offset: i64 = offset.into();
+                let addr = args[0].as_u64().wrapping_add(offset as u64);
+                match opcode {
+                    Opcode::HeapAddr => {
+                        // The offset is the size of the access to bounds check.
+                        if addr > self.heap_size as u64 {
+                            return Err(Error::Trap(TrapCode::HeapOutOfBounds));
+                        }
+                        vec![DataValue::int(rty, args[0].as_i64())]
+                    }
+                    _ => vec![self.load(Opcode::Load, rty, addr, self.heap_size)?],
+                }
+            }
+            InstructionData::HeapStore { offset, .. } => {
+                let offset: i64 = offset.into();
+                let addr = args[1].as_u64().wrapping_add(offset as u64);
+                let limit = self.heap_size;
+                self.store(Opcode::Store, args[0], addr, limit)?;
+                Vec::new()
+            }
+            InstructionData::Nullary { .. } |
+            InstructionData::Unary { .. } |
+            InstructionData::Binary { .. } |
+            InstructionData::Ternary { .. } |
+            InstructionData::MultiAry { .. } |
+            InstructionData::RegMove { .. } |
+            InstructionData::Fence { .. } => {
+                match opcode {
+                    Opcode::Return => return Ok(Control::Return(args)),
+                    Opcode::Trap => return Err(Error::Trap(TrapCode::User(0))),
+                    Opcode::Trapz | Opcode::Trapnz => {
+                        if args[0].as_bool() == (opcode == Opcode::Trapnz) {
+                            return Err(Error::Trap(TrapCode::User(0)));
+                        }
+                        Vec::new()
+                    }
+                    Opcode::Regmove | Opcode::Fence => Vec::new(),
+                    Opcode::Select => vec![if args[0].as_bool() { args[1] } else { args[2] }],
+                    Opcode::Fma => {
+                        vec![match (args[0], args[1], args[2]) {
+                                 (DataValue::F32(a), DataValue::F32(b), DataValue::F32(c)) => {
+                                     DataValue::F32(a.mul_add(b, c))
+                                 }
+                                 (a, b, c) => {
+                                     DataValue::F64(a.as_f64().mul_add(b.as_f64(), c.as_f64()))
+                                 }
+                             }]
+                    }
+                    Opcode::IaddCin => vec![add_with_carry(args[0], args[1], args[2].as_bool()).0],
+                    Opcode::IsubBin => vec![sub_with_borrow(args[0], args[1], args[2].as_bool()).0],
+                    Opcode::IaddCout | Opcode::IaddCarry => {
+                        let cin = opcode == Opcode::IaddCarry && args[2].as_bool();
+                        let (a, c) = add_with_carry(args[0], args[1], cin);
+                        vec![a, DataValue::Bool(c)]
+                    }
+                    Opcode::IsubBout | Opcode::IsubBorrow => {
+                        let bin = opcode == Opcode::IsubBorrow && args[2].as_bool();
+                        let (a, b) = sub_with_borrow(args[0], args[1], bin);
+                        vec![a, DataValue::Bool(b)]
+                    }
+                    Opcode::Isplit => {
+                        let half = dfg.value_type(results[0]);
+                        let x = args[0].as_u64();
+                        vec![DataValue::int(half, x as i64),
+                             DataValue::int(half, (x >> half.bits()) as i64)]
+                    }
+                    Opcode::Iconcat => {
+                        let half = rty.half_width().expect("iconcat result type");
+                        let x = args[0].as_u64() | (args[1].as_u64() << half.bits());
+                        vec![DataValue::int(rty, x as i64)]
+                    }
+                    _ if args.len() == 1 => vec![unary(opcode, rty, args[0])?],
+                    _ if args.len() == 2 => vec![binary(opcode, args[0], args[1])?],
+                    _ => return Err(Error::Unsupported(opcode.to_string())),
+                }
+            }
+            _ => return Err(Error::Unsupported(opcode.to_string())),
+        };
+
+        frame.set_all(results, &values);
+        Ok(Control::Continue)
+    }
+
+    // Load a value for one of the load opcodes from `addr`, trapping unless the whole access is
+    // below `limit`.
+    fn load(&self, opcode: Opcode, ty: Type, addr: u64, limit: usize) -> Result<DataValue> {
+        let (size, signed) = match opcode {
+            Opcode::Uload8 => (1, false),
+            Opcode::Sload8 => (1, true),
+            Opcode::Uload16 => (2, false),
+            Opcode::Sload16 => (2, true),
+            Opcode::Uload32 => (4, false),
+            Opcode::Sload32 => (4, true),
+            _ => return Ok(DataValue::from_bits(ty, self.read(addr, ty.bytes(), limit)?)),
+        };
+        let bits = self.read(addr, size, limit)?;
+        let shift = 64 - 8 * size;
+        let x = if signed {
+            ((bits << shift) as i64) >> shift
+        } else {
+            bits as i64
+        };
+        Ok(DataValue::int(ty, x))
+    }
+
+    // Store `value` for one of the store opcodes at `addr`.
+    fn store(&mut self, opcode: Opcode, value: DataValue, addr: u64, limit: usize) -> Result<()> {
+        let size = match opcode {
+            Opcode::Istore8 => 1,
+            Opcode::Istore16 => 2,
+            Opcode::Istore32 => 4,
+            _ => {
+                match value {
+                    DataValue::Int(ty, _) => ty.bytes(),
+                    DataValue::F32(_) => 4,
+                    DataValue::Bool(_) => 1,
+                    DataValue::F64(_) => 8,
+                }
+            }
+        };
+        let start = check_bounds(addr, size, limit)?;
+        let bits = value.to_bits();
+        for i in 0..size as usize {
+            self.memory[start + i] = (bits >> (8 * i)) as u8;
+        }
+        Ok(())
+    }
+
+    // Read `size` bytes in little-endian order.
+    fn read(&self, addr: u64, size: u32, limit: usize) -> Result<u64> {
+        let start = check_bounds(addr, size, limit)?;
+        Ok(self.memory[start..start + size as usize]
+               .iter()
+               .rev()
+               .fold(0, |bits, &b| (bits << 8) | b as u64))
+    }
+}
+
+// Check that the `size` bytes at `addr` are below `limit`, and return the address as an index.
+fn check_bounds(addr: u64, size: u32, limit: usize) -> Result<usize> {
+    match addr.checked_add(size as u64) {
+        Some(end) if end <= limit as u64 => Ok(addr as usize),
+        _ => Err(Error::Trap(TrapCode::HeapOutOfBounds)),
+    }
+}
+
+// Does `value` have type `ty`?
+fn has_type(value: DataValue, ty: Type) -> bool {
+    match value {
+        DataValue::Bool(_) => ty.is_bool(),
+        DataValue::Int(vty, _) => vty == ty,
+        DataValue::F32(_) => ty == types::F32,
+        DataValue::F64(_) => ty == types::F64,
+    }
+}
+
+// Get the opcode corresponding to a `BinaryImm` opcode.
+fn imm_opcode(opcode: Opcode) -> Opcode {
+    match opcode {
+        Opcode::IaddImm => Opcode::Iadd,
+        Opcode::ImulImm => Opcode::Imul,
+        Opcode::UdivImm => Opcode::Udiv,
+        Opcode::SdivImm => Opcode::Sdiv,
+        Opcode::UremImm => Opcode::Urem,
+        Opcode::SremImm => Opcode::Srem,
+        Opcode::BandImm => Opcode::Band,
+        Opcode::BorImm => Opcode::Bor,
+        Opcode::BxorImm => Opcode::Bxor,
+        Opcode::RotlImm => Opcode::Rotl,
+        Opcode::RotrImm => Opcode::Rotr,
+        Opcode::IshlImm => Opcode::Ishl,
+        Opcode::UshrImm => Opcode::Ushr,
+        Opcode::SshrImm => Opcode::Sshr,
+        _ => opcode,
+    }
+}
+
+// Apply `$op` to the operands of two floats of the same type.
+macro_rules! float_binop {
+    ($x:expr, $y:expr, |$a:ident, $b:ident| $op:expr) => {
+        match ($x, $y) {
+            (DataValue::F32($a), DataValue::F32($b)) => DataValue::F32($op),
+            (DataValue::F64($a), DataValue::F64($b)) => DataValue::F64($op),
+            (x, y) => panic!("bad float operands {:?}, {:?}", x, y),
+        }
+    }
+}
+
+// Apply `$op` to a float.
+macro_rules! float_unop {
+    ($x:expr, |$a:ident| $op:expr) => {
+        match $x {
+            DataValue::F32($a) => DataValue::F32($op),
+            DataValue::F64($a) => DataValue::F64($op),
+            x => panic!("bad float operand {:?}", x),
+        }
+    }
+}
+
+// Evaluate an instruction with two operands and a single result.
+fn binary(opcode: Opcode, x: DataValue, y: DataValue) -> Result<DataValue> {
+    if let (DataValue::Bool(a), DataValue::Bool(b)) = (x, y) {
+        return match opcode {
+                   Opcode::Band => Ok(DataValue::Bool(a & b)),
+                   Opcode::Bor => Ok(DataValue::Bool(a | b)),
+                   Opcode::Bxor => Ok(DataValue::Bool(a ^ b)),
+                   _ => Err(Error::Unsupported(format!("{} on booleans", opcode))),
+               };
+    }
+
+    let ty = match x {
+        DataValue::Int(ty, _) => ty,
+        _ => {
+            return Ok(match opcode {
+                          Opcode::Fadd => float_binop!(x, y, |a, b| a + b),
+                          Opcode::Fsub => float_binop!(x, y, |a, b| a - b),
+                          Opcode::Fmul => float_binop!(x, y, |a, b| a * b),
+                          Opcode::Fdiv => float_binop!(x, y, |a, b| a / b),
+                          Opcode::Fcopysign => {
+                              float_binop!(x, y, |a, b| if b.is_sign_negative() {
+                                  -a.abs()
+                              } else {
+                                  a.abs()
+                              })
+                          }
+                          Opcode::Fmin => {
+                              float_binop!(x, y, |a, b| if a.is_nan() || b.is_nan() {
+                                  a + b
+                              } else if a == b {
+                                  if a.is_sign_negative() { a } else { b }
+                              } else {
+                                  a.min(b)
+                              })
+                          }
+                          Opcode::Fminnum => {
+                              float_binop!(x, y, |a, b| if a == b {
+                                  if a.is_sign_negative() { a } else { b }
+                              } else {
+                                  a.min(b)
+                              })
+                          }
+                          Opcode::Fmax => {
+                              float_binop!(x, y, |a, b| if a.is_nan() || b.is_nan() {
+                                  a + b
+                              } else if a == b {
+                                  if a.is_sign_positive() { a } else { b }
+                              } else {
+                                  a.max(b)
+                              })
+                          }
+                          Opcode::Fmaxnum => {
+                              float_binop!(x, y, |a, b| if a == b {
+                                  if a.is_sign_positive() { a } else { b }
+                              } else {
+                                  a.max(b)
+                              })
+                          }
+                          _ => return Err(Error::Unsupported(opcode.to_string())),
+                      })
+        }
+    };
+
+    let bits = ty.bits() as u64;
+    let (a, b) = (x.as_i64(), y.as_i64());
+    let (ua, ub) = (x.as_u64(), y.as_u64());
+    let value = match opcode {
+        Opcode::Iadd => a.wrapping_add(b),
+        Opcode::Isub => a.wrapping_sub(b),
+        Opcode::Imul => a.wrapping_mul(b),
+        Opcode::Udiv | Opcode::Urem | Opcode::Sdiv | Opcode::Srem if b == 0 => {
+            return Err(Error::Trap(TrapCode::IntegerDivisionByZero))
+        }
+        Opcode::Udiv => (ua / ub) as i64,
+        Opcode::Urem => (ua % ub) as i64,
+        Opcode::Sdiv => {
+            // The only overflow is `MIN / -1` since the operands are sign-extended.
+            if b == -1 && a == DataValue::int(ty, 1 << (bits - 1)).as_i64() {
+                return Err(Error::Trap(TrapCode::IntegerOverflow));
+            }
+            a / b
+        }
+        Opcode::Srem => a.wrapping_rem(b),
+        Opcode::Band => a & b,
+        Opcode::Bor => a | b,
+        Opcode::Bxor => a ^ b,
+        Opcode::Ishl => a.wrapping_shl((ub % bits) as u32),
+        Opcode::Ushr => (ua >> (ub % bits)) as i64,
+        Opcode::Sshr => a >> (ub % bits),
+        Opcode::Rotl | Opcode::Rotr => {
+            let mut amt = ub % bits;
+            if opcode == Opcode::Rotr && amt != 0 {
+                amt = bits - amt;
+            }
+            if amt == 0 {
+                a
+            } else {
+                ((ua << amt) | (ua >> (bits - amt))) as i64
+            }
+        }
+        _ => return Err(Error::Unsupported(opcode.to_string())),
+    };
+    Ok(DataValue::int(ty, value))
+}
+
+// Evaluate an instruction with one operand and a single result of type `rty`.
+fn unary(opcode: Opcode, rty: Type, x: DataValue) -> Result<DataValue> {
+    Ok(match opcode {
+           Opcode::Copy | Opcode::Spill | Opcode::Fill => x,
+           Opcode::Bnot => {
+               match x {
+                   DataValue::Bool(b) => DataValue::Bool(!b),
+                   _ => DataValue::int(rty, !x.as_i64()),
+               }
+           }
+           Opcode::Clz | Opcode::Cls | Opcode::Ctz | Opcode::Popcnt => {
+               // The result type may be narrower than the operand.
+               let bits = match x {
+                   DataValue::Int(ty, _) => ty.bits() as u32,
+                   _ => panic!("bad integer operand {:?}", x),
+               };
+               let v = x.as_u64();
+               let count = match opcode {
+                   Opcode::Clz if v == 0 => bits,
+                   Opcode::Clz => v.leading_zeros() - (64 - bits),
+                   Opcode::Ctz if v == 0 => bits,
+                   Opcode::Ctz => v.trailing_zeros(),
+                   Opcode::Cls => {
+                       let s = x.as_i64();
+                       let y = if s < 0 { !s } else { s };
+                       y.leading_zeros() - (64 - bits) - 1
+                   }
+                   _ => v.count_ones(),
+               };
+               DataValue::int(rty, count as i64)
+           }
+           Opcode::Sqrt => float_unop!(x, |a| a.sqrt()),
+           Opcode::Fneg => float_unop!(x, |a| -a),
+           Opcode::Fabs => float_unop!(x, |a| a.abs()),
+           Opcode::Ceil => float_unop!(x, |a| a.ceil()),
+           Opcode::Floor => float_unop!(x, |a| a.floor()),
+           Opcode::Trunc => float_unop!(x, |a| a.trunc()),
+           Opcode::Nearest => {
+               // Round to nearest, ties to even.
+               float_unop!(x, |a| if (a - a.trunc()).abs() == 0.5 {
+                   2.0 * (a / 2.0).round()
+               } else {
+                   a.round()
+               })
+           }
+           Opcode::Bitcast => DataValue::from_bits(rty, x.to_bits()),
+           Opcode::Breduce | Opcode::Bextend => DataValue::Bool(x.as_bool()),
+           Opcode::Bint => DataValue::int(rty, x.as_bool() as i64),
+           Opcode::Bmask => DataValue::int(rty, -(x.as_bool() as i64)),
+           Opcode::Ireduce | Opcode::Sextend => DataValue::int(rty, x.as_i64()),
+           Opcode::Uextend => DataValue::int(rty, x.as_u64() as i64),
+           Opcode::Fpromote => DataValue::F64(x.as_f32() as f64),
+           Opcode::Fdemote => DataValue::F32(x.as_f64() as f32),
+           Opcode::FcvtToSint | Opcode::FcvtToUint => {
+               let f = match x {
+                   DataValue::F32(f) => f as f64,
+                   _ => x.as_f64(),
+               };
+               if f.is_nan() {
+                   return Err(Error::Trap(TrapCode::BadConversionToInteger));
+               }
+               let t = f.trunc();
+               let bits = rty.bits() as i32;
+               let (min, max) = if opcode == Opcode::FcvtToSint {
+                   (-(2.0f64.powi(bits - 1)), 2.0f64.powi(bits - 1))
+               } else {
+                   (0.0, 2.0f64.powi(bits))
+               };
+               if t < min || t >= max {
+                   return Err(Error::Trap(TrapCode::IntegerOverflow));
+               }
+               if opcode == Opcode::FcvtToSint {
+                   DataValue::int(rty, t as i64)
+               } else {
+                   DataValue::int(rty, t as u64 as i64)
+               }
+           }
+           Opcode::FcvtFromSint | Opcode::FcvtFromUint => {
+               let signed = opcode == Opcode::FcvtFromSint;
+               match rty {
+                   types::F32 if signed => DataValue::F32(x.as_i64() as f32),
+                   types::F32 => DataValue::F32(x.as_u64() as f32),
+                   _ if signed => DataValue::F64(x.as_i64() as f64),
+                   _ => DataValue::F64(x.as_u64() as f64),
+               }
+           }
+           _ => return Err(Error::Unsupported(opcode.to_string())),
+       })
+}
+
+// Compare two integers.
+fn icmp(cond: IntCC, x: DataValue, y: DataValue) -> bool {
+    let (a, b) = (x.as_i64(), y.as_i64());
+    let (ua, ub) = (x.as_u64(), y.as_u64());
+    match cond {
+        IntCC::Equal => a == b,
+        IntCC::NotEqual => a != b,
+        IntCC::SignedLessThan => a < b,
+        IntCC::SignedGreaterThanOrEqual => a >= b,
+        IntCC::SignedGreaterThan => a > b,
+        IntCC::SignedLessThanOrEqual => a <= b,
+        IntCC::UnsignedLessThan => ua < ub,
+        IntCC::UnsignedGreaterThanOrEqual => ua >= ub,
+        IntCC::UnsignedGreaterThan => ua > ub,
+        IntCC::UnsignedLessThanOrEqual => ua <= ub,
+    }
+}
+
+// Compare two floats. Both `f32` and `f64` are exactly representable as `f64`.
+fn fcmp(cond: FloatCC, x: DataValue, y: DataValue) -> bool {
+    let (a, b) = match (x, y) {
+        (DataValue::F32(a), DataValue::F32(b)) => (a as f64, b as f64),
+        _ => (x.as_f64(), y.as_f64()),
+    };
+    let un = a.is_nan() || b.is_nan();
+    let (eq, lt, gt) = (a == b, a < b, a > b);
+    match cond {
+        FloatCC::Ordered => !un,
+        FloatCC::Unordered => un,
+        FloatCC::Equal => eq,
+        FloatCC::NotEqual => !eq,
+        FloatCC::OrderedNotEqual => lt || gt,
+        FloatCC::UnorderedOrEqual => un || eq,
+        FloatCC::LessThan => lt,
+        FloatCC::LessThanOrEqual => lt || eq,
+        FloatCC::GreaterThan => gt,
+        FloatCC::GreaterThanOrEqual => gt || eq,
+        FloatCC::UnorderedOrLessThan => un || lt,
+        FloatCC::UnorderedOrLessThanOrEqual => un || lt || eq,
+        FloatCC::UnorderedOrGreaterThan => un || gt,
+        FloatCC::UnorderedOrGreaterThanOrEqual => un || gt || eq,
+    }
+}
+
+// Add two integers and a carry, returning the sum and the carry out.
+fn add_with_carry(x: DataValue, y: DataValue, cin: bool) -> (DataValue, bool) {
+    let ty = match x {
+        DataValue::Int(ty, _) => ty,
+        _ => panic!("bad integer operand {:?}", x),
+    };
+    let (s1, c1) = x.as_u64().overflowing_add(y.as_u64());
+    let (s2, c2) = s1.overflowing_add(cin as u64);
+    let carry = if ty.bits() == 64 {
+        c1 || c2
+    } else {
+        s2 >> ty.bits() != 0
+    };
+    (DataValue::int(ty, s2 as i64), carry)
+}
+
+// Subtract an integer and a borrow, returning the difference and the borrow out.
+fn sub_with_borrow(x: DataValue, y: DataValue, bin: bool) -> (DataValue, bool) {
+    let ty = match x {
+        DataValue::Int(ty, _) => ty,
+        _ => panic!("bad integer operand {:?}", x),
+    };
+    let (d1, b1) = x.as_u64().overflowing_sub(y.as_u64());
+    let (d2, b2) = d1.overflowing_sub(bin as u64);
+    (DataValue::int(ty, d2 as i64), b1 || b2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Interpreter, Error};
+    use cretonne::ir::{Function, TrapCode, types};
+    use cton_reader::parse_functions;
+    use value::DataValue;
+
+    fn parse(text: &str) -> Vec<Function> {
+        parse_functions(text).unwrap()
+    }
+
+    fn i32(x: i32) -> DataValue {
+        DataValue::int(types::I32, x as i64)
+    }
+
+    #[test]
+    fn arithmetic() {
+        let funcs = parse("
+            function add(i32, i32) -> i32 {
+            ebb0(v0: i32, v1: i32):
+                v2 = iadd v0, v1
+                v3 = imul_imm v2, 3
+                v4 = irsub_imm v3, 1
+                return v4
+            }
+
+            function div(i32, i32) -> i32 {
+            ebb0(v0: i32, v1: i32):
+                v2 = sdiv v0, v1
+                return v2
+            }
+
+            function wide(i64) -> i32, i32 {
+            ebb0(v0: i64):
+                v1, v2 = isplit v0
+                return v1, v2
+            }");
+        let mut interp = Interpreter::new();
+        assert_eq!(interp.call(&funcs[0], &[i32(2), i32(3)]), Ok(vec![i32(-14)]));
+        assert_eq!(interp.call(&funcs[1], &[i32(-7), i32(2)]), Ok(vec![i32(-3)]));
+        assert_eq!(interp.call(&funcs[1], &[i32(1), i32(0)]),
+                   Err(Error::Trap(TrapCode::IntegerDivisionByZero)));
+        assert_eq!(interp.call(&funcs[1], &[i32(i32::min_value()), i32(-1)]),
+                   Err(Error::Trap(TrapCode::IntegerOverflow)));
+        assert_eq!(interp.call(&funcs[2], &[DataValue::int(types::I64, -2)]),
+                   Ok(vec![i32(-2), i32(-1)]));
+        assert!(interp.call(&funcs[0], &[i32(1)]).is_err());
+    }
+
+    #[test]
+    fn control_flow() {
+        let funcs = parse("
+            function fib(i32) -> i32 {
+            ebb0(v0: i32):
+                v1 = iconst.i32 0
+                v2 = iconst.i32 1
+                jump ebb1(v0, v1, v2)
+
+            ebb1(v3: i32, v4: i32, v5: i32):
+                brz v3, ebb2
+                v6 = iadd v4, v5
+                v7 = iadd_imm v3, -1
+                jump ebb1(v7, v5, v6)
+
+            ebb2:
+                return v4
+            }
+
+            function table(i32) -> i32 {
+                jt0 = jump_table ebb1, 0, ebb2
+
+            ebb0(v0: i32):
+                br_table v0, jt0
+                v1 = iconst.i32 -1
+                return v1
+
+            ebb1:
+                v2 = iconst.i32 10
+                return v2
+
+            ebb2:
+                v3 = iconst.i32 20
+                return v3
+            }");
+        let mut interp = Interpreter::new();
+        assert_eq!(interp.call(&funcs[0], &[i32(10)]), Ok(vec![i32(55)]));
+        assert_eq!(interp.call(&funcs[1], &[i32(0)]), Ok(vec![i32(10)]));
+        assert_eq!(interp.call(&funcs[1], &[i32(1)]), Ok(vec![i32(-1)]));
+        assert_eq!(interp.call(&funcs[1], &[i32(2)]), Ok(vec![i32(20)]));
+        assert_eq!(interp.call(&funcs[1], &[i32(3)]), Ok(vec![i32(-1)]));
+
+        interp.set_fuel(Some(20));
+        assert_eq!(interp.call(&funcs[0], &[i32(10)]), Err(Error::OutOfFuel));
+    }
+
+    #[test]
+    fn memory() {
+        let funcs = parse("
+            function mem(i32) -> i32, i32 {
+                ss0 = stack_slot 8
+
+            ebb0(v0: i32):
+                v1 = iconst.i32 -2
+                istore8 v1, v0+3
+                v2 = uload8.i32 v0+3
+                stack_store v1, ss0+4
+                v3 = stack_load.i32 ss0+4
+                return v2, v3
+            }
+
+            function oob(i32) -> i32 {
+            ebb0(v0: i32):
+                v1 = heap_load.i32 v0+2
+                return v1
+            }");
+        let mut interp = Interpreter::with_heap_size(16);
+        assert_eq!(interp.call(&funcs[0], &[i32(4)]), Ok(vec![i32(0xfe), i32(-2)]));
+        assert_eq!(interp.heap()[7], 0xfe);
+        assert_eq!(interp.call(&funcs[0], &[i32(100)]),
+                   Err(Error::Trap(TrapCode::HeapOutOfBounds)));
+
+        interp.heap_mut()[12] = 1;
+        assert_eq!(interp.call(&funcs[1], &[i32(10)]), Ok(vec![i32(1)]));
+        assert_eq!(interp.call(&funcs[1], &[i32(11)]),
+                   Err(Error::Trap(TrapCode::HeapOutOfBounds)));
+    }
+
+    #[test]
+    fn floats() {
+        let funcs = parse("
+            function conv(f64) -> i32, b1 {
+            ebb0(v0: f64):
+                v1 = fcvt_to_sint.i32 v0
+                v2 = f64const 0x1.0p1
+                v3 = fcmp lt v0, v2
+                return v1, v3
+            }");
+        let mut interp = Interpreter::new();
+        assert_eq!(interp.call(&funcs[0], &[DataValue::F64(-1.5)]),
+                   Ok(vec![i32(-1), DataValue::Bool(true)]));
+        assert_eq!(interp.call(&funcs[0], &[DataValue::F64(1e10)]),
+                   Err(Error::Trap(TrapCode::IntegerOverflow)));
+        assert_eq!(interp.call(&funcs[0], &[DataValue::F64(0.0 / 0.0)]),
+                   Err(Error::Trap(TrapCode::BadConversionToInteger)));
+    }
+
+    #[test]
+    fn calls() {
+        let funcs = parse("
+            function square(i32) -> i32 {
+            ebb0(v0: i32):
+                v1 = imul v0, v0
+                return v1
+            }
+
+            function caller(i32) -> i32 {
+                fn0 = function square(i32) -> i32
+
+            ebb0(v0: i32):
+                v1 = call fn0(v0)
+                v2 = call fn0(v1)
+                return v2
+            }
+
+            function forever() {
+                fn0 = function forever()
+
+            ebb0:
+                call fn0()
+                return
+            }");
+        let mut interp = Interpreter::new();
+        for func in &funcs {
+            interp.add_function(func);
+        }
+        assert_eq!(interp.call_by_name("caller", &[i32(3)]), Ok(vec![i32(81)]));
+        assert_eq!(interp.call_by_name("forever", &[]),
+                   Err(Error::Trap(TrapCode::StackOverflow)));
+        assert_eq!(interp.call_by_name("missing", &[]),
+                   Err(Error::UnknownFunction("missing".to_string())));
+    }
+
+    #[test]
+    fn random_functions() {
+        use cretonne::randfunc::{random_function, Config};
+
+        let mut config = Config::default();
+        let mut interp = Interpreter::new();
+        for seed in 0..100 {
+            config.loops = seed % 2 == 1;
+            interp.set_fuel(Some(1000));
+            let func = random_function(seed, &config);
+            let args: Vec<DataValue> = func.signature
+                .argument_types
+                .iter()
+                .map(|arg| DataValue::int(arg.value_type, seed as i64))
+                .collect();
+            match interp.call(&func, &args) {
+                Ok(results) => assert_eq!(results.len(), 1),
+                Err(Error::OutOfFuel) if config.loops => {}
+                Err(e) => panic!("seed {}: {}\n{}", seed, e, func.display(None)),
+            }
+        }
+    }
+}
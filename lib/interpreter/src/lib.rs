@@ -0,0 +1,22 @@
+//! Cretonne IL interpreter.
+//!
+//! This crate executes Cretonne IL functions directly without compiling them. It is slow, but it
+//! doesn't depend on the code generator, so its results can be compared against the results of
+//! running the compiled code to catch miscompilations.
+//!
+//! The `Interpreter` supports the scalar integer, boolean, and floating point instructions, direct
+//! calls, and memory accesses to a sandboxed heap and stack slots. Traps are reported as
+//! `Error::Trap` with the trap code the compiled code would produce.
+
+#![deny(missing_docs)]
+
+extern crate cretonne;
+
+#[cfg(test)]
+extern crate cton_reader;
+
+pub use interpreter::{Interpreter, Error, Result};
+pub use value::DataValue;
+
+mod interpreter;
+mod value;
@@ -0,0 +1,170 @@
+//! Runtime values manipulated by the interpreter.
+
+use cretonne::ir::Type;
+use cretonne::ir::immediates::{Imm64, Ieee32, Ieee64};
+use std::fmt::{self, Display, Formatter};
+
+/// A value computed by the interpreter.
+///
+/// Integers of all widths are kept sign-extended to 64 bits along with their type, so the same
+/// bits can be reinterpreted as signed or unsigned as needed. Floating point values are compared
+/// bitwise, so a NaN is equal to the same NaN.
+#[derive(Clone, Copy, Debug)]
+pub enum DataValue {
+    /// A boolean of any width.
+    Bool(bool),
+    /// An integer of type `ty`, sign-extended to 64 bits.
+    Int(Type, i64),
+    /// A 32-bit float.
+    F32(f32),
+    /// A 64-bit float.
+    F64(f64),
+}
+
+impl DataValue {
+    /// Create an integer of type `ty` from the low bits of `x`.
+    pub fn int(ty: Type, x: i64) -> DataValue {
+        let shift = 64 - ty.bits() as u32;
+        DataValue::Int(ty, x.wrapping_shl(shift).wrapping_shr(shift))
+    }
+
+    /// Get the value of an integer, sign-extended to 64 bits.
+    ///
+    /// Booleans are converted to 0 or 1. Panics if this is a float.
+    pub fn as_i64(self) -> i64 {
+        match self {
+            DataValue::Int(_, x) => x,
+            DataValue::Bool(b) => b as i64,
+            _ => panic!("{:?} is not an integer", self),
+        }
+    }
+
+    /// Get the value of an integer, zero-extended to 64 bits.
+    pub fn as_u64(self) -> u64 {
+        match self {
+            DataValue::Int(ty, x) if ty.bits() < 64 => x as u64 & ((1 << ty.bits()) - 1),
+            _ => self.as_i64() as u64,
+        }
+    }
+
+    /// Get the value of a boolean, treating any non-zero integer as true.
+    pub fn as_bool(self) -> bool {
+        match self {
+            DataValue::Bool(b) => b,
+            DataValue::Int(_, x) => x != 0,
+            _ => panic!("{:?} is not a boolean", self),
+        }
+    }
+
+    /// Get the value of an `f32`. Panics if this is some other kind of value.
+    pub fn as_f32(self) -> f32 {
+        match self {
+            DataValue::F32(x) => x,
+            _ => panic!("{:?} is not an f32", self),
+        }
+    }
+
+    /// Get the value of an `f64`. Panics if this is some other kind of value.
+    pub fn as_f64(self) -> f64 {
+        match self {
+            DataValue::F64(x) => x,
+            _ => panic!("{:?} is not an f64", self),
+        }
+    }
+
+    /// Get the bits of this value as they would be stored in memory, zero-extended to 64 bits.
+    pub fn to_bits(self) -> u64 {
+        match self {
+            DataValue::F32(x) => x.to_bits() as u64,
+            DataValue::F64(x) => x.to_bits(),
+            _ => self.as_u64(),
+        }
+    }
+
+    /// Create a value of type `ty` from its bitwise representation in memory.
+    pub fn from_bits(ty: Type, bits: u64) -> DataValue {
+        if ty.is_float() {
+            if ty.bits() == 32 {
+                DataValue::F32(f32::from_bits(bits as u32))
+            } else {
+                DataValue::F64(f64::from_bits(bits))
+            }
+        } else if ty.is_bool() {
+            DataValue::Bool(bits != 0)
+        } else {
+            DataValue::int(ty, bits as i64)
+        }
+    }
+
+    /// Parse a value of type `ty` using the same syntax as the immediate operands in `.cton`
+    /// files.
+    pub fn parse(text: &str, ty: Type) -> Result<DataValue, String> {
+        if ty.is_bool() {
+            match text {
+                "true" => Ok(DataValue::Bool(true)),
+                "false" => Ok(DataValue::Bool(false)),
+                _ => Err(format!("expected a boolean: {}", text)),
+            }
+        } else if ty.is_int() {
+            let imm: Imm64 = text.parse()?;
+            Ok(DataValue::int(ty, imm.into()))
+        } else if ty.is_float() && ty.bits() == 32 {
+            let imm: Ieee32 = text.parse()?;
+            Ok(DataValue::F32(f32::from_bits(imm.bits())))
+        } else if ty.is_float() {
+            let imm: Ieee64 = text.parse()?;
+            Ok(DataValue::F64(f64::from_bits(imm.bits())))
+        } else {
+            Err(format!("unsupported type {}", ty))
+        }
+    }
+}
+
+impl PartialEq for DataValue {
+    fn eq(&self, other: &DataValue) -> bool {
+        match (*self, *other) {
+            (DataValue::Bool(a), DataValue::Bool(b)) => a == b,
+            (DataValue::Int(ta, a), DataValue::Int(tb, b)) => ta == tb && a == b,
+            (DataValue::F32(a), DataValue::F32(b)) => a.to_bits() == b.to_bits(),
+            (DataValue::F64(a), DataValue::F64(b)) => a.to_bits() == b.to_bits(),
+            _ => false,
+        }
+    }
+}
+
+impl Display for DataValue {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            DataValue::Bool(b) => write!(f, "{}", b),
+            DataValue::Int(_, x) => write!(f, "{}", x),
+            DataValue::F32(x) => write!(f, "{}", Ieee32::from_bits(x.to_bits())),
+            DataValue::F64(x) => write!(f, "{}", Ieee64::from_bits(x.to_bits())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DataValue;
+    use cretonne::ir::types;
+
+    #[test]
+    fn ints() {
+        let x = DataValue::int(types::I8, 0x1ff);
+        assert_eq!(x.as_i64(), -1);
+        assert_eq!(x.as_u64(), 0xff);
+        assert_eq!(x.to_string(), "-1");
+        assert_eq!(DataValue::int(types::I64, -1).as_u64(), !0);
+        assert_eq!(DataValue::from_bits(types::I16, 0x8000).as_i64(), -0x8000);
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(DataValue::parse("0x7f", types::I8), Ok(DataValue::int(types::I8, 127)));
+        assert_eq!(DataValue::parse("true", types::B1), Ok(DataValue::Bool(true)));
+        assert_eq!(DataValue::parse("0x1.8p1", types::F64), Ok(DataValue::F64(3.0)));
+        assert_eq!(DataValue::parse("-0.0", types::F32).unwrap().to_string(),
+                   "-0.0");
+        assert!(DataValue::parse("yes", types::B1).is_err());
+    }
+}
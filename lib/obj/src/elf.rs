@@ -0,0 +1,757 @@
+//! Relocatable ELF object files.
+//!
+//! The `ElfWriter` collects the code for many functions in a single `.text` section. Each function
+//! is defined as a global function symbol, and the external functions and symbols referenced by
+//! relocations become undefined global symbols to be resolved by the linker.
+//!
+//! The Cretonne relocation kinds are ISA-specific, and they are mapped to the corresponding ELF
+//! relocation types by name. Targets that use `REL` relocations without an explicit addend have
+//! the addend stored in the relocated instruction instead. The file and the implicit addends are
+//! written in the byte order of the target ISA.
+
+use cretonne::binemit::{self, RelocRecord, RelocTarget};
+use cretonne::ir::Function;
+use cretonne::isa::{TargetIsa, Endianness};
+use std::collections::HashMap;
+use std::io::{self, Write};
+use Result;
+
+// ELF machine numbers.
+const EM_386: u16 = 3;
+const EM_ARM: u16 = 40;
+const EM_X86_64: u16 = 62;
+const EM_AARCH64: u16 = 183;
+const EM_RISCV: u16 = 243;
+
+// Section header types.
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHT_RELA: u32 = 4;
+const SHT_REL: u32 = 9;
+
+// Section header flags.
+const SHF_ALLOC: u64 = 0x2;
+const SHF_EXECINSTR: u64 = 0x4;
+const SHF_INFO_LINK: u64 = 0x40;
+
+// Symbol bindings and types.
+const STB_LOCAL: u8 = 0;
+const STB_GLOBAL: u8 = 1;
+const STT_NOTYPE: u8 = 0;
+const STT_FUNC: u8 = 2;
+const STT_SECTION: u8 = 3;
+const STT_TLS: u8 = 6;
+
+// Section indexes in the object file.
+const TEXT_SECTION: u16 = 1;
+const RELOC_SECTION: u32 = 2;
+const SYMTAB_SECTION: u32 = 4;
+const STRTAB_SECTION: u32 = 5;
+const SHSTRTAB_SECTION: u16 = 6;
+const NUM_SECTIONS: u16 = 7;
+
+// The RISC-V relocation referring to the `auipc` instruction computing the high part.
+const R_RISCV_PCREL_LO12_I: u32 = 24;
+
+/// Get the ELF relocation type for the Cretonne relocation kind `name` on `machine`, and whether
+/// it refers to a thread-local symbol.
+fn reloc_type(machine: u16, name: &str) -> Option<(u32, bool)> {
+    Some(match (machine, name) {
+             (EM_X86_64, "PCRel4") => (2, false),
+             (EM_X86_64, "PLTRel4") => (4, false),
+             (EM_X86_64, "GOTPCRel4") => (9, false),
+             (EM_X86_64, "Abs4") => (10, false),
+             (EM_X86_64, "Abs8") => (1, false),
+             (EM_X86_64, "GOTTPOFF4") => (22, true),
+             (EM_X86_64, "TLSGD4") => (19, true),
+             (EM_386, "PCRel4") => (2, false),
+             (EM_386, "PLTRel4") => (4, false),
+             (EM_386, "Abs4") => (1, false),
+             (EM_386, "TLSIE4") => (15, true),
+             (EM_ARM, "Call") => (28, false),
+             (EM_ARM, "ThumbCall") => (10, false),
+             (EM_AARCH64, "Call26") => (283, false),
+             (EM_RISCV, "Call") => (17, false),
+             (EM_RISCV, "CallPLT") => (19, false),
+             (EM_RISCV, "GotPCRelHi20") => (20, false),
+             (EM_RISCV, "TlsIEPCRelHi20") => (21, true),
+             (EM_RISCV, "TlsGDPCRelHi20") => (22, true),
+             (EM_RISCV, "PCRelLo12I") => (R_RISCV_PCREL_LO12_I, false),
+             (EM_RISCV, "Hi20") => (26, false),
+             (EM_RISCV, "Lo12I") => (27, false),
+             _ => return None,
+         })
+}
+
+/// Get the ELF machine number, the word size, whether relocations have explicit addends, and the
+/// header flags for an object file containing code for `isa`.
+///
+/// The RISC-V floating point ABI recorded in the file header is determined by the `enable_float`
+/// setting.
+fn file_type(isa: &TargetIsa) -> Result<(u16, bool, bool, u32)> {
+    let is_64bit = isa.flags().is_64bit();
+    Ok(match (isa.name(), is_64bit) {
+           ("intel", true) => (EM_X86_64, true, true, 0),
+           ("intel", false) => (EM_386, false, false, 0),
+           // EABI version 5 with the hard-float calling convention.
+           ("arm32", _) => (EM_ARM, false, false, 0x0500_0400),
+           ("arm64", _) => (EM_AARCH64, true, true, 0),
+           // The double-precision floating point ABI.
+           ("riscv", _) if isa.flags().enable_float() => (EM_RISCV, is_64bit, true, 0x4),
+           ("riscv", _) => (EM_RISCV, is_64bit, true, 0),
+           (name, _) => return Err(format!("ELF objects are not supported for {}", name)),
+       })
+}
+
+/// A global symbol.
+struct Symbol {
+    name: String,
+    /// Offset into `.text` and size of a defined function, or `None` for an undefined symbol.
+    definition: Option<(u64, u64)>,
+    /// Is this a thread-local variable?
+    tls: bool,
+}
+
+/// The symbol referenced by a relocation.
+#[derive(Clone, Copy)]
+enum SymbolRef {
+    /// A local label at an offset into `.text`, indexing `ElfWriter::labels`.
+    Label(usize),
+    /// A global symbol, indexing `ElfWriter::symbols`.
+    Global(usize),
+}
+
+/// A relocation in the `.text` section.
+struct Relocation {
+    offset: u64,
+    symbol: SymbolRef,
+    rtype: u32,
+    addend: i64,
+}
+
+/// A writer for a relocatable ELF object file containing functions compiled by Cretonne.
+///
+/// The functions must be compiled for the ISA that was used to create the writer.
+pub struct ElfWriter {
+    machine: u16,
+    is_64bit: bool,
+    endianness: Endianness,
+    rela: bool,
+    flags: u32,
+    reloc_names: &'static [&'static str],
+    text_align: u64,
+    text: Vec<u8>,
+    labels: Vec<u64>,
+    symbols: Vec<Symbol>,
+    symbol_map: HashMap<String, usize>,
+    relocs: Vec<Relocation>,
+}
+
+impl ElfWriter {
+    /// Create a writer for an object file containing code for `isa`.
+    pub fn new(isa: &TargetIsa) -> Result<ElfWriter> {
+        let (machine, is_64bit, rela, flags) = file_type(isa)?;
+        Ok(ElfWriter {
+               machine: machine,
+               is_64bit: is_64bit,
+               endianness: isa.endianness(),
+               rela: rela,
+               flags: flags,
+               reloc_names: isa.reloc_names(),
+               text_align: binemit::function_alignment(isa) as u64,
+               text: Vec::new(),
+               labels: Vec::new(),
+               symbols: Vec::new(),
+               symbol_map: HashMap::new(),
+               relocs: Vec::new(),
+           })
+    }
+
+    /// Can functions compiled for `isa` be added to this writer?
+    ///
+    /// This is the case when `isa` produces the same kind of object file as the ISA that was used
+    /// to create the writer, even if other settings differ.
+    pub fn accepts(&self, isa: &TargetIsa) -> bool {
+        file_type(isa) == Ok((self.machine, self.is_64bit, self.rela, self.flags)) &&
+        isa.endianness() == self.endianness
+    }
+
+    /// Get the index of the global symbol `name`, declaring it as undefined if necessary.
+    fn symbol(&mut self, name: &str) -> usize {
+        if let Some(&index) = self.symbol_map.get(name) {
+            return index;
+        }
+        let index = self.symbols.len();
+        self.symbols
+            .push(Symbol {
+                      name: name.to_string(),
+                      definition: None,
+                      tls: false,
+                  });
+        self.symbol_map.insert(name.to_string(), index);
+        index
+    }
+
+    /// Add the machine code for `func` as the global function symbol `name`.
+    ///
+    /// The `code` and `relocs` are the output of `Context::emit_to_memory()` after
+    /// `Context::compile()`. The function is placed at the next offset in the `.text`
+    /// section that satisfies the function alignment.
+    pub fn define_function(&mut self,
+                           name: &str,
+                           func: &Function,
+                           code: &[u8],
+                           relocs: &[RelocRecord])
+                           -> Result<()> {
+        while self.text.len() as u64 % self.text_align != 0 {
+            self.text.push(0);
+        }
+        let base = self.text.len() as u64;
+
+        let sym = self.symbol(name);
+        if self.symbols[sym].definition.is_some() {
+            return Err(format!("Duplicate definition of {}", name));
+        }
+        self.symbols[sym].definition = Some((base, code.len() as u64));
+        self.text.extend_from_slice(code);
+
+        for reloc in relocs {
+            // Jump tables are emitted right after the code, so the displacements referring to
+            // them are already resolved.
+            if let RelocTarget::JumpTable(_) = reloc.target {
+                continue;
+            }
+            let kind = self.reloc_names
+                .get(reloc.kind.0 as usize)
+                .cloned()
+                .unwrap_or("unknown");
+            let (rtype, tls) = match reloc_type(self.machine, kind) {
+                Some(rt) => rt,
+                None => return Err(format!("Unsupported {} relocation in {}", kind, name)),
+            };
+            let offset = base + reloc.offset as u64;
+            let (symbol, mut addend) = match reloc.target {
+                RelocTarget::Func(fref) => {
+                    let target = self.symbol(func.dfg.ext_funcs[fref].name.as_str());
+                    (target, reloc.addend)
+                }
+                RelocTarget::External(ref target) => (self.symbol(target.as_str()), reloc.addend),
+                RelocTarget::Ebb(ebb) => (sym, reloc.addend + func.offsets[ebb] as i64),
+                RelocTarget::JumpTable(_) => unreachable!(),
+            };
+            if tls {
+                self.symbols[symbol].tls = true;
+            }
+            let mut symbol = SymbolRef::Global(symbol);
+
+            // The low part of a RISC-V PC-relative address refers to a label at the `auipc`
+            // instruction computing the high part. It immediately precedes this instruction.
+            if self.machine == EM_RISCV && rtype == R_RISCV_PCREL_LO12_I {
+                self.labels.push(offset - 4);
+                symbol = SymbolRef::Label(self.labels.len() - 1);
+                addend = 0;
+            }
+
+            if !self.rela {
+                self.put_implicit_addend(offset as usize, kind, addend);
+            }
+            self.relocs
+                .push(Relocation {
+                          offset: offset,
+                          symbol: symbol,
+                          rtype: rtype,
+                          addend: addend,
+                      });
+        }
+
+        Ok(())
+    }
+
+    /// Store the `addend` of a `kind` relocation at `offset` in the relocated instruction.
+    fn put_implicit_addend(&mut self, offset: usize, kind: &str, addend: i64) {
+        let e = self.endianness;
+        let bytes = &mut self.text[offset..];
+        match kind {
+            // The 24-bit word offset of an A32 `bl` instruction.
+            "Call" if self.machine == EM_ARM => {
+                let word = read_u32(bytes, e) & 0xff00_0000 | (addend >> 2) as u32 & 0x00ff_ffff;
+                write_u32(bytes, word, e);
+            }
+            // The offset of a T32 `bl` instruction is split across its two halfwords.
+            "ThumbCall" => {
+                let value = (addend >> 1) as u32;
+                let s = (value >> 23) & 1;
+                let j1 = !((value >> 22) ^ s) & 1;
+                let j2 = !((value >> 21) ^ s) & 1;
+                let hw1 = read_u16(bytes, e) & 0xf800 | (s << 10) as u16 |
+                          ((value >> 11) & 0x3ff) as u16;
+                let hw2 = read_u16(&bytes[2..], e) & 0xd000 | (j1 << 13) as u16 |
+                          (j2 << 11) as u16 | (value & 0x7ff) as u16;
+                write_u16(bytes, hw1, e);
+                write_u16(&mut bytes[2..], hw2, e);
+            }
+            // Everything else is a 32-bit field.
+            _ => {
+                let word = read_u32(bytes, e).wrapping_add(addend as u32);
+                write_u32(bytes, word, e);
+            }
+        }
+    }
+
+    /// Write the object file to `out`.
+    pub fn write(&self, out: &mut Write) -> io::Result<()> {
+        out.write_all(&self.to_bytes())
+    }
+
+    /// Get the contents of the object file.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut shstrtab = StringTable::new();
+        let mut strtab = StringTable::new();
+
+        // The symbol table has the null symbol, the `.text` section symbol, and the local labels
+        // before the global symbols.
+        let first_global = 2 + self.labels.len();
+        let mut symtab = Buffer::new(self.is_64bit, self.endianness);
+        symtab.symbol(0, 0, 0, 0, 0);
+        symtab.symbol(0, 0, 0, STT_SECTION, TEXT_SECTION);
+        for (num, &offset) in self.labels.iter().enumerate() {
+            let name = strtab.add(&format!(".Lpcrel_hi{}", num));
+            symtab.symbol(name, offset, 0, STB_LOCAL << 4 | STT_NOTYPE, TEXT_SECTION);
+        }
+        for sym in &self.symbols {
+            let name = strtab.add(&sym.name);
+            let stype = if sym.tls { STT_TLS } else { STT_NOTYPE };
+            match sym.definition {
+                Some((offset, size)) => {
+                    symtab.symbol(name, offset, size, STB_GLOBAL << 4 | STT_FUNC, TEXT_SECTION)
+                }
+                None => symtab.symbol(name, 0, 0, STB_GLOBAL << 4 | stype, 0),
+            }
+        }
+
+        let mut reltab = Buffer::new(self.is_64bit, self.endianness);
+        for reloc in &self.relocs {
+            let symbol = match reloc.symbol {
+                SymbolRef::Label(num) => 2 + num,
+                SymbolRef::Global(num) => first_global + num,
+            } as u64;
+            reltab.word(reloc.offset);
+            if self.is_64bit {
+                reltab.u64(symbol << 32 | reloc.rtype as u64);
+            } else {
+                reltab.u32((symbol << 8) as u32 | reloc.rtype);
+            }
+            if self.rela {
+                reltab.word(reloc.addend as u64);
+            }
+        }
+
+        let (word_size, header_size, section_header_size) = if self.is_64bit {
+            (8, 64, 64)
+        } else {
+            (4, 52, 40)
+        };
+        let symbol_size = if self.is_64bit { 24 } else { 16 };
+        let reloc_size = match (self.is_64bit, self.rela) {
+            (true, true) => 24,
+            (true, false) => 16,
+            (false, true) => 12,
+            (false, false) => 8,
+        };
+        let reloc_name = if self.rela { ".rela.text" } else { ".rel.text" };
+        let reloc_type = if self.rela { SHT_RELA } else { SHT_REL };
+
+        // The section contents follow the ELF header.
+        let mut body = Buffer::new(self.is_64bit, self.endianness);
+        body.data.resize(header_size, 0);
+        let mut sections = Vec::new();
+        body.align(self.text_align as usize);
+        sections.push((shstrtab.add(".text"),
+                       SHT_PROGBITS,
+                       SHF_ALLOC | SHF_EXECINSTR,
+                       body.append(&self.text),
+                       0,
+                       0,
+                       self.text_align,
+                       0));
+        body.align(word_size);
+        sections.push((shstrtab.add(reloc_name),
+                       reloc_type,
+                       SHF_INFO_LINK,
+                       body.append(&reltab.data),
+                       SYMTAB_SECTION,
+                       TEXT_SECTION as u32,
+                       word_size as u64,
+                       reloc_size));
+        // An empty `.note.GNU-stack` section indicates that the code doesn't need an executable
+        // stack.
+        sections.push((shstrtab.add(".note.GNU-stack"),
+                       SHT_PROGBITS,
+                       0,
+                       (body.data.len() as u64, 0),
+                       0,
+                       0,
+                       1,
+                       0));
+        body.align(word_size);
+        sections.push((shstrtab.add(".symtab"),
+                       SHT_SYMTAB,
+                       0,
+                       body.append(&symtab.data),
+                       STRTAB_SECTION,
+                       first_global as u32,
+                       word_size as u64,
+                       symbol_size));
+        sections.push((shstrtab.add(".strtab"),
+                       SHT_STRTAB,
+                       0,
+                       body.append(&strtab.data),
+                       0,
+                       0,
+                       1,
+                       0));
+        let shstrtab_name = shstrtab.add(".shstrtab");
+        sections.push((shstrtab_name,
+                       SHT_STRTAB,
+                       0,
+                       body.append(&shstrtab.data),
+                       0,
+                       0,
+                       1,
+                       0));
+        assert_eq!(sections.len() + 1, NUM_SECTIONS as usize);
+        assert_eq!(RELOC_SECTION, 2);
+
+        // The section headers come last.
+        body.align(word_size);
+        let section_headers = body.data.len() as u64;
+        body.data.resize(body.data.len() + section_header_size, 0);
+        for (name, stype, flags, (offset, size), link, info, align, entsize) in sections {
+            body.u32(name);
+            body.u32(stype);
+            body.word(flags);
+            body.word(0);
+            body.word(offset);
+            body.word(size);
+            body.u32(link);
+            body.u32(info);
+            body.word(align);
+            body.word(entsize);
+        }
+
+        // Finally, fill in the ELF header.
+        let mut header = Buffer::new(self.is_64bit, self.endianness);
+        header.data.extend_from_slice(b"\x7fELF");
+        header.u8(if self.is_64bit { 2 } else { 1 });
+        // The byte order, current version, System V ABI.
+        header.u8(match self.endianness {
+                      Endianness::Little => 1,
+                      Endianness::Big => 2,
+                  });
+        header.u8(1);
+        header.data.resize(16, 0);
+        // A relocatable file.
+        header.u16(1);
+        header.u16(self.machine);
+        header.u32(1);
+        // No entry point or program headers.
+        header.word(0);
+        header.word(0);
+        header.word(section_headers);
+        header.u32(self.flags);
+        header.u16(header_size as u16);
+        header.u16(0);
+        header.u16(0);
+        header.u16(section_header_size as u16);
+        header.u16(NUM_SECTIONS);
+        header.u16(SHSTRTAB_SECTION);
+        assert_eq!(header.data.len(), header_size);
+        body.data[0..header_size].copy_from_slice(&header.data);
+
+        body.data
+    }
+}
+
+/// A string table section.
+struct StringTable {
+    data: Vec<u8>,
+}
+
+impl StringTable {
+    /// Create a string table with the empty string at offset 0.
+    fn new() -> StringTable {
+        StringTable { data: vec![0] }
+    }
+
+    /// Add `s` to the table and return its offset.
+    fn add(&mut self, s: &str) -> u32 {
+        let offset = self.data.len() as u32;
+        self.data.extend_from_slice(s.as_bytes());
+        self.data.push(0);
+        offset
+    }
+}
+
+/// A byte buffer for ELF data structures in the byte order of the target.
+struct Buffer {
+    data: Vec<u8>,
+    is_64bit: bool,
+    endianness: Endianness,
+}
+
+impl Buffer {
+    fn new(is_64bit: bool, endianness: Endianness) -> Buffer {
+        Buffer {
+            data: Vec::new(),
+            is_64bit: is_64bit,
+            endianness: endianness,
+        }
+    }
+
+    fn u8(&mut self, x: u8) {
+        self.data.push(x);
+    }
+
+    fn u16(&mut self, x: u16) {
+        let mut bytes = [0; 2];
+        write_u16(&mut bytes, x, self.endianness);
+        self.data.extend_from_slice(&bytes);
+    }
+
+    fn u32(&mut self, x: u32) {
+        let mut bytes = [0; 4];
+        write_u32(&mut bytes, x, self.endianness);
+        self.data.extend_from_slice(&bytes);
+    }
+
+    fn u64(&mut self, x: u64) {
+        let (first, second) = match self.endianness {
+            Endianness::Little => (x as u32, (x >> 32) as u32),
+            Endianness::Big => ((x >> 32) as u32, x as u32),
+        };
+        self.u32(first);
+        self.u32(second);
+    }
+
+    /// Add an address-sized word.
+    fn word(&mut self, x: u64) {
+        if self.is_64bit {
+            self.u64(x);
+        } else {
+            self.u32(x as u32);
+        }
+    }
+
+    /// Add zero bytes until the size is a multiple of `align`.
+    fn align(&mut self, align: usize) {
+        while self.data.len() % align != 0 {
+            self.data.push(0);
+        }
+    }
+
+    /// Append the contents of a section, returning its offset and size.
+    fn append(&mut self, data: &[u8]) -> (u64, u64) {
+        let offset = self.data.len() as u64;
+        self.data.extend_from_slice(data);
+        (offset, data.len() as u64)
+    }
+
+    /// Add a symbol table entry.
+    fn symbol(&mut self, name: u32, value: u64, size: u64, info: u8, shndx: u16) {
+        self.u32(name);
+        if self.is_64bit {
+            self.u8(info);
+            self.u8(0);
+            self.u16(shndx);
+            self.u64(value);
+            self.u64(size);
+        } else {
+            self.u32(value as u32);
+            self.u32(size as u32);
+            self.u8(info);
+            self.u8(0);
+            self.u16(shndx);
+        }
+    }
+}
+
+fn read_u16(bytes: &[u8], endianness: Endianness) -> u16 {
+    match endianness {
+        Endianness::Little => bytes[0] as u16 | (bytes[1] as u16) << 8,
+        Endianness::Big => (bytes[0] as u16) << 8 | bytes[1] as u16,
+    }
+}
+
+fn read_u32(bytes: &[u8], endianness: Endianness) -> u32 {
+    let (low, high) = match endianness {
+        Endianness::Little => (read_u16(bytes, endianness), read_u16(&bytes[2..], endianness)),
+        Endianness::Big => (read_u16(&bytes[2..], endianness), read_u16(bytes, endianness)),
+    };
+    low as u32 | (high as u32) << 16
+}
+
+fn write_u16(bytes: &mut [u8], x: u16, endianness: Endianness) {
+    let (first, second) = match endianness {
+        Endianness::Little => (x as u8, (x >> 8) as u8),
+        Endianness::Big => ((x >> 8) as u8, x as u8),
+    };
+    bytes[0] = first;
+    bytes[1] = second;
+}
+
+fn write_u32(bytes: &mut [u8], x: u32, endianness: Endianness) {
+    let (first, second) = match endianness {
+        Endianness::Little => (x as u16, (x >> 16) as u16),
+        Endianness::Big => ((x >> 16) as u16, x as u16),
+    };
+    write_u16(bytes, first, endianness);
+    write_u16(&mut bytes[2..], second, endianness);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ElfWriter, Buffer, read_u16, read_u32};
+    use cretonne::Context;
+    use cretonne::binemit::{Reloc, RelocRecord, RelocTarget, TrapRecord};
+    use cretonne::entity_map::EntityRef;
+    use cretonne::isa::Endianness;
+    use cretonne::ir::FuncRef;
+    use cton_reader::parse_test;
+
+    /// Compile all the functions in `source` and return the object file contents.
+    fn compile(source: &str) -> Vec<u8> {
+        let testfile = parse_test(source).unwrap();
+        let isa = testfile.isa_spec.unique_isa().unwrap();
+        let mut writer = ElfWriter::new(isa).unwrap();
+        let mut ctx = Context::new();
+        for (func, _) in testfile.functions {
+            ctx.func = func;
+            let size = ctx.compile(isa).unwrap();
+            let mut code = vec![0; size as usize];
+            let mut relocs: Vec<RelocRecord> = Vec::new();
+            let mut traps: Vec<TrapRecord> = Vec::new();
+            ctx.emit_to_memory(&mut code, &mut relocs, &mut traps, isa)
+                .unwrap();
+            writer
+                .define_function(ctx.func.name.as_str(), &ctx.func, &code, &relocs)
+                .unwrap();
+        }
+        writer.to_bytes()
+    }
+
+    #[test]
+    fn intel64() {
+        let obj = compile("
+            set is_64bit
+            isa intel
+            function caller() {
+                fn0 = function callee()
+            ebb0:
+                call fn0()
+                return
+            }
+            function callee() {
+            ebb0:
+                return
+            }");
+
+        // ELF64, little-endian, relocatable x86-64.
+        assert_eq!(&obj[0..6], b"\x7fELF\x02\x01");
+        assert_eq!(read_u16(&obj[16..], Endianness::Little), 1);
+        assert_eq!(read_u16(&obj[18..], Endianness::Little), 62);
+        assert_eq!(read_u16(&obj[60..], Endianness::Little), 7);
+
+        // The symbol names are in the string table.
+        let text = String::from_utf8_lossy(&obj);
+        assert!(text.contains("\0caller\0callee\0"));
+        assert!(text.contains("\0.rela.text\0"));
+    }
+
+    #[test]
+    fn arm32_implicit_addend() {
+        let testfile = parse_test("
+            isa arm32
+            function caller() {
+                fn0 = function callee()
+            ebb0:
+                call fn0()
+                return
+            }")
+                .unwrap();
+        let isa = testfile.isa_spec.unique_isa().unwrap();
+        let func = &testfile.functions[0].0;
+        let mut writer = ElfWriter::new(isa).unwrap();
+
+        // bl callee; bx lr
+        let code = [0x00, 0x00, 0x00, 0xeb, 0x1e, 0xff, 0x2f, 0xe1];
+        let relocs = [RelocRecord {
+                          offset: 0,
+                          kind: Reloc(0),
+                          target: RelocTarget::Func(FuncRef::new(0)),
+                          addend: -8,
+                      }];
+        writer
+            .define_function("caller", func, &code, &relocs)
+            .unwrap();
+        let obj = writer.to_bytes();
+
+        // ELF32 with `REL` relocations and the EABI version in the flags.
+        assert_eq!(&obj[0..6], b"\x7fELF\x01\x01");
+        assert_eq!(read_u16(&obj[18..], Endianness::Little), 40);
+        assert_eq!(read_u32(&obj[36..], Endianness::Little), 0x0500_0400);
+        let text = String::from_utf8_lossy(&obj);
+        assert!(text.contains("\0.rel.text\0"));
+
+        // The addend is stored in the `bl` instruction.
+        assert!(obj.windows(4).any(|w| w == [0xfe, 0xff, 0xff, 0xeb]));
+    }
+
+    #[test]
+    fn duplicate_definition() {
+        let testfile = parse_test("isa riscv\nfunction f() {\nebb0:\n    return\n}").unwrap();
+        let isa = testfile.isa_spec.unique_isa().unwrap();
+        let mut writer = ElfWriter::new(isa).unwrap();
+        let func = &testfile.functions[0].0;
+        assert_eq!(writer.define_function("f", func, &[0x67, 0x80, 0, 0], &[]),
+                   Ok(()));
+        assert_eq!(writer.define_function("f", func, &[0x67, 0x80, 0, 0], &[]),
+                   Err("Duplicate definition of f".to_string()));
+    }
+
+    #[test]
+    fn byte_order() {
+        let mut little = Buffer::new(true, Endianness::Little);
+        little.u16(0x0102);
+        little.u32(0x0304_0506);
+        little.word(0x0708_090a_0b0c_0d0e);
+        assert_eq!(little.data,
+                   [0x02, 0x01, 0x06, 0x05, 0x04, 0x03, 0x0e, 0x0d, 0x0c, 0x0b, 0x0a, 0x09, 0x08,
+                    0x07]);
+        assert_eq!(read_u32(&little.data[2..], Endianness::Little), 0x0304_0506);
+
+        let mut big = Buffer::new(true, Endianness::Big);
+        big.u16(0x0102);
+        big.u32(0x0304_0506);
+        big.word(0x0708_090a_0b0c_0d0e);
+        assert_eq!(big.data,
+                   [0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d,
+                    0x0e]);
+        assert_eq!(read_u16(&big.data, Endianness::Big), 0x0102);
+        assert_eq!(read_u32(&big.data[2..], Endianness::Big), 0x0304_0506);
+    }
+
+    #[test]
+    fn accepts() {
+        let intel64 = parse_test("set is_64bit\nset opt_level=best\nisa intel").unwrap();
+        let intel64_fast = parse_test("set is_64bit\nisa intel").unwrap();
+        let intel32 = parse_test("isa intel").unwrap();
+        let riscv = parse_test("isa riscv").unwrap();
+        let writer = ElfWriter::new(intel64.isa_spec.unique_isa().unwrap()).unwrap();
+
+        // Other settings don't change the object file.
+        assert!(writer.accepts(intel64_fast.isa_spec.unique_isa().unwrap()));
+        assert!(!writer.accepts(intel32.isa_spec.unique_isa().unwrap()));
+        assert!(!writer.accepts(riscv.isa_spec.unique_isa().unwrap()));
+    }
+}
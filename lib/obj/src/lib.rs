@@ -0,0 +1,21 @@
+//! Cretonne object file writer.
+//!
+//! Cretonne compiles one function at a time. This crate collects the machine code and relocations
+//! emitted for many functions and writes them as a relocatable object file that can be passed
+//! directly to a system linker for ahead-of-time compilation.
+
+#![deny(missing_docs)]
+
+extern crate cretonne;
+
+#[cfg(test)]
+extern crate cton_reader;
+
+pub use elf::ElfWriter;
+
+mod elf;
+
+use std::result;
+
+/// The result of an object file operation. Errors are reported as messages.
+pub type Result<T> = result::Result<T, String>;
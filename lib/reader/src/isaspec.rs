@@ -6,10 +6,9 @@
 //! If a test case file contains `isa` commands, the tests will only be run against the specified
 //! ISAs. If the file contains no `isa` commands, the tests will be run against all supported ISAs.
 
-use cretonne::settings::{Flags, Configurable, Error as SetError};
+use cretonne::settings::{Flags, Configurable, OptionError};
 use cretonne::isa::TargetIsa;
 use error::{Result, Location};
-use testcommand::TestOption;
 
 /// The ISA specifications in a `.cton` file.
 pub enum IsaSpec {
@@ -38,23 +37,9 @@ impl IsaSpec {
 pub fn parse_options<'a, I>(iter: I, config: &mut Configurable, loc: &Location) -> Result<()>
     where I: Iterator<Item = &'a str>
 {
-    for opt in iter.map(TestOption::new) {
-        match opt {
-            TestOption::Flag(name) => {
-                match config.set_bool(name, true) {
-                    Ok(_) => {}
-                    Err(SetError::BadName) => return err!(loc, "unknown flag '{}'", opt),
-                    Err(_) => return err!(loc, "not a boolean flag: '{}'", opt),
-                }
-            }
-            TestOption::Value(name, value) => {
-                match config.set(name, value) {
-                    Ok(_) => {}
-                    Err(SetError::BadName) => return err!(loc, "unknown setting '{}'", opt),
-                    Err(SetError::BadType) => return err!(loc, "invalid setting type: '{}'", opt),
-                    Err(SetError::BadValue) => return err!(loc, "invalid setting value: '{}'", opt),
-                }
-            }
+    for opt in iter {
+        if let Err(e) = config.enable(opt) {
+            return err!(loc, "{}", OptionError::new(opt, e));
         }
     }
     Ok(())
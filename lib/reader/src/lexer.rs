@@ -38,10 +38,13 @@ pub enum Token<'a> {
     Ebb(Ebb), // ebb3
     StackSlot(u32), // ss3
     JumpTable(u32), // jt2
+    GlobalVar(u32), // gv2
+    Table(u32), // table2
     FuncRef(u32), // fn2
     SigRef(u32), // sig2
     Name(&'a str), // %9arbitrary_alphanum, %x3, %0, %function ...
     HexSequence(&'a str), // #89AF
+    SourceLoc(&'a str), // @00c7
     Identifier(&'a str), // Unrecognized identifier (opcode, enumerator, ...)
 }
 
@@ -310,6 +313,8 @@ impl<'a> Lexer<'a> {
             "ebb" => Ebb::with_number(number).map(|ebb| Token::Ebb(ebb)),
             "ss" => Some(Token::StackSlot(number)),
             "jt" => Some(Token::JumpTable(number)),
+            "gv" => Some(Token::GlobalVar(number)),
+            "table" => Some(Token::Table(number)),
             "fn" => Some(Token::FuncRef(number)),
             "sig" => Some(Token::SigRef(number)),
             _ => None,
@@ -381,6 +386,22 @@ impl<'a> Lexer<'a> {
         token(Token::HexSequence(&self.source[begin..end]), loc)
     }
 
+    fn scan_srcloc(&mut self) -> Result<LocatedToken<'a>, LocatedError> {
+        let loc = self.loc();
+        let begin = self.pos + 1;
+
+        assert!(self.lookahead == Some('@'));
+
+        while let Some(c) = self.next_ch() {
+            if !char::is_digit(c, 16) {
+                break;
+            }
+        }
+
+        let end = self.pos;
+        token(Token::SourceLoc(&self.source[begin..end]), loc)
+    }
+
     /// Get the next token or a lexical error.
     ///
     /// Return None when the end of the source is encountered.
@@ -412,6 +433,7 @@ impl<'a> Lexer<'a> {
                        Some(ch) if ch.is_alphabetic() => Some(self.scan_word()),
                        Some('%') => Some(self.scan_name()),
                        Some('#') => Some(self.scan_hex_sequence()),
+                       Some('@') => Some(self.scan_srcloc()),
                        Some(ch) if ch.is_whitespace() => {
                 self.next_ch();
                 continue;
@@ -556,6 +578,17 @@ mod tests {
         assert_eq!(lex.next(), token(Token::HexSequence("789"), 1));
     }
 
+    #[test]
+    fn lex_srclocs() {
+        let mut lex = Lexer::new("@0 @DEADbeef123 @789 @");
+
+        assert_eq!(lex.next(), token(Token::SourceLoc("0"), 1));
+        assert_eq!(lex.next(), token(Token::SourceLoc("DEADbeef123"), 1));
+        assert_eq!(lex.next(), token(Token::SourceLoc("789"), 1));
+        assert_eq!(lex.next(), token(Token::SourceLoc(""), 1));
+        assert_eq!(lex.next(), None);
+    }
+
     #[test]
     fn lex_names() {
         let mut lex = Lexer::new("%0 %x3 %function %123_abc %ss0 %v3 %ebb11 %_");
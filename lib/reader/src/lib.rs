@@ -8,7 +8,7 @@
 extern crate cretonne;
 
 pub use error::{Location, Result, Error};
-pub use parser::{parse_functions, parse_test};
+pub use parser::{parse_functions, parse_test, OLDEST_IL_VERSION};
 pub use testcommand::{TestCommand, TestOption};
 pub use testfile::{TestFile, Details, Comment};
 pub use isaspec::IsaSpec;
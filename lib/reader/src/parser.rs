@@ -5,18 +5,20 @@
 //
 // ====--------------------------------------------------------------------------------------====//
 
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::str::FromStr;
 use std::{u16, u32};
 use std::mem;
-use cretonne::ir::{Function, Ebb, Opcode, Value, Type, FunctionName, StackSlotData, JumpTable,
-                   JumpTableData, Signature, ArgumentType, ArgumentExtension, ExtFuncData, SigRef,
-                   FuncRef, StackSlot, ValueLoc, ArgumentLoc, MemFlags};
+use cretonne::IL_VERSION;
+use cretonne::ir::{Function, Ebb, Opcode, Value, Type, FunctionName, StackSlotData, StackSlotKind,
+                   JumpTable, JumpTableData, GlobalVar, GlobalVarData, Table, TableData,
+                   Signature, ArgumentType, ArgumentExtension, ExtFuncData, SigRef, FuncRef,
+                   StackSlot, ValueLoc, ArgumentLoc, MemFlags, SourceLoc};
 use cretonne::ir::types::VOID;
 use cretonne::ir::immediates::{Imm64, Offset32, Uoffset32, Ieee32, Ieee64};
 use cretonne::ir::entities::AnyEntity;
 use cretonne::ir::instructions::{InstructionFormat, InstructionData, VariableArgs};
-use cretonne::isa::{self, TargetIsa, Encoding};
+use cretonne::isa::{self, TargetIsa, Encoding, RegUnit};
 use cretonne::settings::{self, Configurable};
 use testfile::{TestFile, Details, Comment};
 use error::{Location, Error, Result};
@@ -25,6 +27,12 @@ use testcommand::TestCommand;
 use isaspec;
 use sourcemap::{SourceMap, MutableSourceMap};
 
+/// The oldest version of the IL text format that can still be parsed.
+///
+/// Files with a `version` line are accepted if the version is between this and
+/// `cretonne::IL_VERSION`. Files without a `version` line are parsed as the current version.
+pub const OLDEST_IL_VERSION: u32 = 1;
+
 /// Parse the entire `text` into a list of functions.
 ///
 /// Any test commands or ISA declarations are ignored.
@@ -45,12 +53,14 @@ pub fn parse_test<'a>(text: &'a str) -> Result<TestFile<'a>> {
     // Gather the preamble comments as 'Function'.
     parser.gather_comments(AnyEntity::Function);
 
+    let version = parser.parse_version()?;
     let commands = parser.parse_test_commands();
     let isa_spec = parser.parse_isa_specs()?;
     let preamble_comments = parser.take_comments();
     let functions = parser.parse_function_list(isa_spec.unique_isa())?;
 
     Ok(TestFile {
+           version: version,
            commands: commands,
            isa_spec: isa_spec,
            preamble_comments: preamble_comments,
@@ -84,8 +94,9 @@ pub struct Parser<'a> {
 struct Context<'a> {
     function: Function,
     map: SourceMap,
-    // Store aliases until the values can be reliably looked up.
-    aliases: HashMap<Value, (Value, Location)>,
+    // Store aliases until the values can be reliably looked up. They are ordered by source number
+    // so the alias values are created in the same order every time.
+    aliases: BTreeMap<Value, (Value, Location)>,
 
     // Reference to the unique_isa for things like parsing ISA-specific instruction encoding
     // information. This is only `Some` if exactly one set of `isa` directives were found in the
@@ -99,7 +110,7 @@ impl<'a> Context<'a> {
         Context {
             function: f,
             map: SourceMap::new(),
-            aliases: HashMap::new(),
+            aliases: BTreeMap::new(),
             unique_isa: unique_isa,
         }
     }
@@ -174,6 +185,34 @@ impl<'a> Context<'a> {
         }
     }
 
+    // Allocate a new global variable and add a mapping number -> GlobalVar.
+    fn add_gv(&mut self, number: u32, data: GlobalVarData, loc: &Location) -> Result<()> {
+        self.map
+            .def_gv(number, self.function.global_vars.push(data), loc)
+    }
+
+    // Resolve a reference to a global variable.
+    fn get_gv(&self, number: u32, loc: &Location) -> Result<GlobalVar> {
+        match self.map.get_gv(number) {
+            Some(gv) => Ok(gv),
+            None => err!(loc, "undefined global variable gv{}", number),
+        }
+    }
+
+    // Allocate a new table and add a mapping number -> Table.
+    fn add_table(&mut self, number: u32, data: TableData, loc: &Location) -> Result<()> {
+        self.map
+            .def_table(number, self.function.tables.push(data), loc)
+    }
+
+    // Resolve a reference to a table.
+    fn get_table(&self, number: u32, loc: &Location) -> Result<Table> {
+        match self.map.get_table(number) {
+            Some(table) => Ok(table),
+            None => err!(loc, "undefined table table{}", number),
+        }
+    }
+
     // Allocate a new EBB and add a mapping src_ebb -> Ebb.
     fn add_ebb(&mut self, src_ebb: Ebb, loc: &Location) -> Result<Ebb> {
         let ebb = self.function.dfg.make_ebb();
@@ -201,11 +240,10 @@ impl<'a> Context<'a> {
                                 source_to);
                 }
             };
-            let dest_loc = self.map
-                .location(AnyEntity::from(ir_to))
-                .expect(&*format!("Error in looking up location of IR destination value alias \
-                                   for {}",
-                                  ir_to));
+            let dest_loc = match self.map.location(AnyEntity::from(ir_to)) {
+                Some(loc) => loc,
+                None => return err!(source_loc, "no location for IR value alias {}", ir_to),
+            };
             let ir_from = self.function.dfg.make_value_alias(ir_to);
             self.map.def_value(source_from, ir_from, &dest_loc)?;
         }
@@ -383,6 +421,26 @@ impl<'a> Parser<'a> {
         }
     }
 
+    // Match and consume a global variable reference.
+    fn match_gv(&mut self, err_msg: &str) -> Result<u32> {
+        if let Some(Token::GlobalVar(gv)) = self.token() {
+            self.consume();
+            Ok(gv)
+        } else {
+            err!(self.loc, err_msg)
+        }
+    }
+
+    // Match and consume a table reference.
+    fn match_table(&mut self, err_msg: &str) -> Result<u32> {
+        if let Some(Token::Table(table)) = self.token() {
+            self.consume();
+            Ok(table)
+        } else {
+            err!(self.loc, err_msg)
+        }
+    }
+
     // Match and consume an ebb reference.
     fn match_ebb(&mut self, err_msg: &str) -> Result<Ebb> {
         if let Some(Token::Ebb(ebb)) = self.token() {
@@ -451,6 +509,20 @@ impl<'a> Parser<'a> {
         }
     }
 
+    // Match and consume a u64 immediate.
+    // This is used for table sizes.
+    fn match_uimm64(&mut self, err_msg: &str) -> Result<u64> {
+        if let Some(Token::Integer(text)) = self.token() {
+            self.consume();
+            // Lexer just gives us raw text that looks like an integer.
+            // Parse it as a u64 to check for overflow and other issues.
+            text.parse()
+                .map_err(|_| self.error("expected u64 decimal immediate"))
+        } else {
+            err!(self.loc, err_msg)
+        }
+    }
+
     // Match and consume an optional offset32 immediate.
     //
     // Note that that this will match an empty string as an empty offset, and that if an offset is
@@ -540,6 +612,29 @@ impl<'a> Parser<'a> {
         }
     }
 
+    // Match and consume a register unit name: %x10.
+    //
+    // Register names are looked up in `isa`. Without an ISA, only numbered register units like
+    // `%10` can be parsed.
+    fn match_regunit(&mut self, isa: Option<&TargetIsa>) -> Result<RegUnit> {
+        if let Some(Token::Name(name)) = self.token() {
+            self.consume();
+            match isa {
+                Some(isa) => {
+                    isa.register_info()
+                        .parse_regunit(name)
+                        .ok_or(self.error("invalid register name"))
+                }
+                None => {
+                    name.parse()
+                        .map_err(|_| self.error("invalid register number"))
+                }
+            }
+        } else {
+            err!(self.loc, "expected register unit")
+        }
+    }
+
     // Match and consume a HexSequence that fits into a u16.
     // This is used for instruction encodings.
     fn match_hex16(&mut self, err_msg: &str) -> Result<u16> {
@@ -555,6 +650,44 @@ impl<'a> Parser<'a> {
         }
     }
 
+    // Match and consume an optional source location.
+    fn optional_srcloc(&mut self) -> Result<SourceLoc> {
+        if let Some(Token::SourceLoc(text)) = self.token() {
+            match u32::from_str_radix(text, 16) {
+                Ok(num) => {
+                    self.consume();
+                    Ok(SourceLoc::new(num))
+                }
+                _ => err!(self.loc, "invalid source location: {}", text),
+            }
+        } else {
+            Ok(Default::default())
+        }
+    }
+
+    /// Parse an optional `version` line and check that the version is supported.
+    pub fn parse_version(&mut self) -> Result<Option<u32>> {
+        if self.token() != Some(Token::Identifier("version")) {
+            return Ok(None);
+        }
+        self.consume();
+        let loc = self.loc;
+        let version = self.match_uimm32("expected IL version number")?;
+        if version > IL_VERSION {
+            return err!(loc,
+                        "IL version {} is newer than version {} supported by this parser",
+                        version,
+                        IL_VERSION);
+        }
+        if version < OLDEST_IL_VERSION {
+            return err!(loc,
+                        "IL version {} is no longer supported, the oldest supported version is {}",
+                        version,
+                        OLDEST_IL_VERSION);
+        }
+        Ok(Some(version))
+    }
+
     /// Parse a list of test commands.
     pub fn parse_test_commands(&mut self) -> Vec<TestCommand<'a>> {
         let mut list = Vec::new();
@@ -605,14 +738,18 @@ impl<'a> Parser<'a> {
                         Some(w) => w,
                     };
                     let mut isa_builder = match isa::lookup(isa_name) {
-                        None => return err!(loc, "unknown ISA '{}'", isa_name),
-                        Some(b) => b,
+                        Err(_) => return err!(loc, "unknown ISA '{}'", isa_name),
+                        Ok(b) => b,
                     };
                     // Apply the ISA-specific settings to `isa_builder`.
                     isaspec::parse_options(words, &mut isa_builder, &self.loc)?;
 
+                    // A target triple can imply shared settings which only apply to this ISA.
+                    let mut isa_flag_builder = flag_builder.clone();
+                    isa_builder.configure_shared(&mut isa_flag_builder);
+
                     // Construct a trait object with the aggregrate settings.
-                    isas.push(isa_builder.finish(settings::Flags::new(&flag_builder)));
+                    isas.push(isa_builder.finish(settings::Flags::new(&isa_flag_builder)));
                 }
                 _ => break,
             }
@@ -740,7 +877,16 @@ impl<'a> Parser<'a> {
             sig.compute_argument_bytes();
         }
 
-        // TBD: calling convention.
+        // The calling convention is optional.
+        if let Some(Token::Identifier(text)) = self.token() {
+            match text.parse() {
+                Ok(cc) => {
+                    self.consume();
+                    sig.call_conv = cc;
+                }
+                _ => return err!(self.loc, "unknown calling convention: {}", text),
+            }
+        }
 
         Ok(sig)
     }
@@ -786,8 +932,16 @@ impl<'a> Parser<'a> {
         }
 
         // arg ::= type { flag } * [ argumentloc ]
+        let loc = self.loc;
         arg.location = self.parse_argument_location(unique_isa)?;
 
+        // The end of a stack argument must be representable for `compute_argument_bytes()`.
+        if let ArgumentLoc::Stack(offset) = arg.location {
+            if offset.checked_add(arg.value_type.bytes()).is_none() {
+                return err!(loc, "stack argument offset too large");
+            }
+        }
+
         Ok(arg)
     }
 
@@ -836,6 +990,8 @@ impl<'a> Parser<'a> {
     //                   * function-decl
     //                   * signature-decl
     //                   * jump-table-decl
+    //                   * global-var-decl
+    //                   * table-decl
     //
     // The parsed decls are added to `ctx` rather than returned.
     fn parse_preamble(&mut self, ctx: &mut Context) -> Result<()> {
@@ -843,8 +999,9 @@ impl<'a> Parser<'a> {
             match self.token() {
                 Some(Token::StackSlot(..)) => {
                     self.gather_comments(ctx.function.stack_slots.next_key());
+                    let loc = self.loc;
                     self.parse_stack_slot_decl()
-                        .and_then(|(num, dat)| ctx.add_ss(num, dat, &self.loc))
+                        .and_then(|(num, dat)| ctx.add_ss(num, dat, &loc))
                 }
                 Some(Token::SigRef(..)) => {
                     self.gather_comments(ctx.function.dfg.signatures.next_key());
@@ -861,6 +1018,16 @@ impl<'a> Parser<'a> {
                     self.parse_jump_table_decl()
                         .and_then(|(num, dat)| ctx.add_jt(num, dat, &self.loc))
                 }
+                Some(Token::GlobalVar(..)) => {
+                    self.gather_comments(ctx.function.global_vars.next_key());
+                    self.parse_global_var_decl()
+                        .and_then(|(num, dat)| ctx.add_gv(num, dat, &self.loc))
+                }
+                Some(Token::Table(..)) => {
+                    self.gather_comments(ctx.function.tables.next_key());
+                    self.parse_table_decl(ctx)
+                        .and_then(|(num, dat)| ctx.add_table(num, dat, &self.loc))
+                }
                 // More to come..
                 _ => return Ok(()),
             }?;
@@ -869,13 +1036,14 @@ impl<'a> Parser<'a> {
 
     // Parse a stack slot decl.
     //
-    // stack-slot-decl ::= * StackSlot(ss) "=" "stack_slot" Bytes {"," stack-slot-flag}
+    // stack-slot-decl ::= * StackSlot(ss) "=" stack-slot-kind Bytes {"," stack-slot-flag}
+    // stack-slot-kind ::= "stack_slot" | "spill_slot" | "outgoing_arg" | "incoming_arg"
     fn parse_stack_slot_decl(&mut self) -> Result<(u32, StackSlotData)> {
         let number = self.match_ss("expected stack slot number: ss«n»")?;
         self.match_token(Token::Equal, "expected '=' in stack_slot decl")?;
-        self.match_identifier("stack_slot", "expected 'stack_slot'")?;
+        let kind: StackSlotKind = self.match_enum("expected stack slot kind")?;
 
-        // stack-slot-decl ::= StackSlot(ss) "=" "stack_slot" * Bytes {"," stack-slot-flag}
+        // stack-slot-decl ::= StackSlot(ss) "=" stack-slot-kind * Bytes {"," stack-slot-flag}
         let bytes: i64 = self.match_imm64("expected byte-size in stack_slot decl")?
             .into();
         if bytes < 0 {
@@ -884,9 +1052,18 @@ impl<'a> Parser<'a> {
         if bytes > u32::MAX as i64 {
             return err!(self.loc, "stack slot too large");
         }
-        let data = StackSlotData::new(bytes as u32);
+        let mut data = StackSlotData::new(kind, bytes as u32);
 
-        // TBD: stack-slot-decl ::= StackSlot(ss) "=" "stack_slot" Bytes * {"," stack-slot-flag}
+        // stack-slot-decl ::= StackSlot(ss) "=" stack-slot-kind Bytes * {"," stack-slot-flag}
+        while self.optional(Token::Comma) {
+            // stack-slot-flag ::= "offset" Bytes
+            self.match_identifier("offset", "expected stack slot flag")?;
+            let offset: i64 = self.match_imm64("expected stack slot offset")?.into();
+            if offset < i32::MIN as i64 || offset > i32::MAX as i64 {
+                return err!(self.loc, "stack slot offset out of range");
+            }
+            data.offset = offset as i32;
+        }
         Ok((number, data))
     }
 
@@ -906,8 +1083,8 @@ impl<'a> Parser<'a> {
     //
     // Two variants:
     //
-    // function-decl ::= FuncRef(fnref) "=" function-spec
-    //                   FuncRef(fnref) "=" SigRef(sig) name
+    // function-decl ::= FuncRef(fnref) "=" ["colocated"] function-spec
+    //                   FuncRef(fnref) "=" ["colocated"] SigRef(sig) name
     //
     // The first variant allocates a new signature reference. The second references an existing
     // signature which must be declared first.
@@ -915,17 +1092,17 @@ impl<'a> Parser<'a> {
     fn parse_function_decl(&mut self, ctx: &mut Context) -> Result<(u32, ExtFuncData)> {
         let number = self.match_fn("expected function number: fn«n»")?;
         self.match_token(Token::Equal, "expected '=' in function decl")?;
+        let colocated = self.optional(Token::Identifier("colocated"));
 
         let data = match self.token() {
             Some(Token::Identifier("function")) => {
                 let (loc, name, sig) = self.parse_function_spec(ctx.unique_isa)?;
                 let sigref = ctx.function.dfg.signatures.push(sig);
-                ctx.map
-                    .def_entity(sigref.into(), &loc)
-                    .expect("duplicate SigRef entities created");
+                ctx.map.def_entity(sigref.into(), &loc)?;
                 ExtFuncData {
                     name: name,
                     signature: sigref,
+                    colocated: colocated,
                 }
             }
             Some(Token::SigRef(sig_src)) => {
@@ -935,6 +1112,7 @@ impl<'a> Parser<'a> {
                 ExtFuncData {
                     name: name,
                     signature: sig,
+                    colocated: colocated,
                 }
             }
             _ => return err!(self.loc, "expected 'function' or sig«n» in function decl"),
@@ -984,6 +1162,42 @@ impl<'a> Parser<'a> {
         }
     }
 
+    // Parse a global variable decl.
+    //
+    // global-var-decl ::= * GlobalVar(gv) "=" "global" ["tls"] name
+    fn parse_global_var_decl(&mut self) -> Result<(u32, GlobalVarData)> {
+        let number = self.match_gv("expected global variable number: gv«n»")?;
+        self.match_token(Token::Equal, "expected '=' in global variable decl")?;
+        self.match_identifier("global", "expected 'global'")?;
+        let tls = self.optional(Token::Identifier("tls"));
+        let name = self.parse_function_name()?;
+        Ok((number, GlobalVarData::new(name, tls)))
+    }
+
+    // Parse a table decl.
+    //
+    // table-decl ::= * Table(table) "=" "table" GlobalVar(base) "," "bound" Bound ","
+    //                  "element_size" Bytes
+    fn parse_table_decl(&mut self, ctx: &Context) -> Result<(u32, TableData)> {
+        let number = self.match_table("expected table number: table«n»")?;
+        self.match_token(Token::Equal, "expected '=' in table decl")?;
+        self.match_identifier("table", "expected 'table'")?;
+        let base = self.match_gv("expected table base global variable: gv«n»")
+            .and_then(|num| ctx.get_gv(num, &self.loc))?;
+        self.match_token(Token::Comma, "expected ',' after table base")?;
+        self.match_identifier("bound", "expected 'bound'")?;
+        let bound = self.match_uimm64("expected table bound")?;
+        self.match_token(Token::Comma, "expected ',' after table bound")?;
+        self.match_identifier("element_size", "expected 'element_size'")?;
+        let element_size = self.match_uimm64("expected table element size in bytes")?;
+        let data = TableData {
+            base: base,
+            bound: bound,
+            element_size: element_size,
+        };
+        Ok((number, data))
+    }
+
     // Parse a function body, add contents to `ctx`.
     //
     // function-body ::= * { extended-basic-block }
@@ -998,7 +1212,7 @@ impl<'a> Parser<'a> {
     // Parse an extended basic block, add contents to `ctx`.
     //
     // extended-basic-block ::= * ebb-header { instruction }
-    // ebb-header           ::= Ebb(ebb) [ebb-args] ":"
+    // ebb-header           ::= Ebb(ebb) [ebb-args] ["cold"] ":"
     //
     fn parse_extended_basic_block(&mut self, ctx: &mut Context) -> Result<()> {
         let ebb_num = self.match_ebb("expected EBB header")?;
@@ -1006,9 +1220,16 @@ impl<'a> Parser<'a> {
         self.gather_comments(ebb);
 
         if !self.optional(Token::Colon) {
-            // ebb-header ::= Ebb(ebb) [ * ebb-args ] ":"
-            self.parse_ebb_args(ctx, ebb)?;
-            self.match_token(Token::Colon, "expected ':' after EBB arguments")?;
+            // ebb-header ::= Ebb(ebb) [ * ebb-args ] ["cold"] ":"
+            if self.token() != Some(Token::Identifier("cold")) {
+                self.parse_ebb_args(ctx, ebb)?;
+            }
+            // ebb-header ::= Ebb(ebb) [ebb-args] [ * "cold"] ":"
+            if self.token() == Some(Token::Identifier("cold")) {
+                self.consume();
+                ctx.function.set_cold(ebb);
+            }
+            self.match_token(Token::Colon, "expected ':' after EBB header")?;
         }
 
         // extended-basic-block ::= ebb-header * { instruction }
@@ -1016,8 +1237,10 @@ impl<'a> Parser<'a> {
                   Some(Token::Value(_)) => true,
                   Some(Token::Identifier(_)) => true,
                   Some(Token::LBracket) => true,
+                  Some(Token::SourceLoc(_)) => true,
                   _ => false,
               } {
+            let srcloc = self.optional_srcloc()?;
             let (encoding, result_locations) = self.parse_instruction_encoding(ctx)?;
 
             // We need to parse instruction results here because they are shared
@@ -1033,10 +1256,12 @@ impl<'a> Parser<'a> {
                 }
                 Some(Token::Equal) => {
                     self.consume();
-                    self.parse_instruction(results, encoding, result_locations, ctx, ebb)?;
+                    self.parse_instruction(results, srcloc, encoding, result_locations, ctx, ebb)?;
                 }
                 _ if results.len() != 0 => return err!(self.loc, "expected -> or ="),
-                _ => self.parse_instruction(results, encoding, result_locations, ctx, ebb)?,
+                _ => {
+                    self.parse_instruction(results, srcloc, encoding, result_locations, ctx, ebb)?
+                }
             }
         }
 
@@ -1097,11 +1322,36 @@ impl<'a> Parser<'a> {
             }
             Some(Token::Name(name)) => {
                 self.consume();
+                // A register pair is written as two adjacent registers: `%r0:%r1`.
+                let hi_name = if self.optional(Token::Colon) {
+                    match self.token() {
+                        Some(Token::Name(hi_name)) => {
+                            self.consume();
+                            Some(hi_name)
+                        }
+                        _ => return err!(self.loc, "expected second register of register pair"),
+                    }
+                } else {
+                    None
+                };
                 if let Some(isa) = ctx.unique_isa {
-                    isa.register_info()
+                    let reginfo = isa.register_info();
+                    let lo = reginfo
                         .parse_regunit(name)
-                        .map(ValueLoc::Reg)
-                        .ok_or(self.error("invalid register value location"))
+                        .ok_or(self.error("invalid register value location"))?;
+                    match hi_name {
+                        None => Ok(ValueLoc::Reg(lo)),
+                        Some(hi_name) => {
+                            let hi = reginfo
+                                .parse_regunit(hi_name)
+                                .ok_or(self.error("invalid register value location"))?;
+                            if hi == lo + 1 {
+                                Ok(ValueLoc::RegPair(lo))
+                            } else {
+                                err!(self.loc, "register pair must be two adjacent registers")
+                            }
+                        }
+                    }
                 } else {
                     // For convenience we ignore value locations when no unique ISA is specified
                     Ok(ValueLoc::Unassigned)
@@ -1199,6 +1449,7 @@ impl<'a> Parser<'a> {
     //
     fn parse_instruction(&mut self,
                          results: Vec<Value>,
+                         srcloc: SourceLoc,
                          encoding: Option<Encoding>,
                          result_locations: Option<Vec<ValueLoc>>,
                          ctx: &mut Context,
@@ -1239,9 +1490,11 @@ impl<'a> Parser<'a> {
         let inst = ctx.function.dfg.make_inst(inst_data);
         let num_results = ctx.function.dfg.make_inst_results(inst, ctrl_typevar);
         ctx.function.layout.append_inst(inst, ebb);
-        ctx.map
-            .def_entity(inst.into(), &opcode_loc)
-            .expect("duplicate inst references created");
+        ctx.map.def_entity(inst.into(), &opcode_loc)?;
+
+        if !srcloc.is_default() {
+            *ctx.function.srclocs.ensure(inst) = srcloc;
+        }
 
         if let Some(encoding) = encoding {
             *ctx.function.encodings.ensure(inst) = encoding;
@@ -1435,6 +1688,14 @@ impl<'a> Parser<'a> {
                     imm: self.match_ieee32("expected immediate 32-bit float operand")?,
                 }
             }
+            InstructionFormat::UnaryGlobalVar => {
+                let global_var = self.match_gv("expected global variable reference")
+                    .and_then(|num| ctx.get_gv(num, &self.loc))?;
+                InstructionData::UnaryGlobalVar {
+                    opcode: opcode,
+                    global_var: global_var,
+                }
+            }
             InstructionFormat::UnaryIeee64 => {
                 InstructionData::UnaryIeee64 {
                     opcode: opcode,
@@ -1598,6 +1859,14 @@ impl<'a> Parser<'a> {
                     args: args.into_value_list(&[callee], &mut ctx.function.dfg.value_lists),
                 }
             }
+            InstructionFormat::FuncAddr => {
+                let func_ref = self.match_fn("expected function reference")
+                    .and_then(|num| ctx.get_fn(num, &self.loc))?;
+                InstructionData::FuncAddr {
+                    opcode: opcode,
+                    func_ref: func_ref,
+                }
+            }
             InstructionFormat::BranchTable => {
                 let arg = self.match_value("expected SSA value operand")?;
                 self.match_token(Token::Comma, "expected ',' between operands")?;
@@ -1609,6 +1878,30 @@ impl<'a> Parser<'a> {
                     table: table,
                 }
             }
+            InstructionFormat::BranchTableBase => {
+                let table = self.match_jt()
+                    .and_then(|num| ctx.get_jt(num, &self.loc))?;
+                InstructionData::BranchTableBase {
+                    opcode: opcode,
+                    table: table,
+                }
+            }
+            InstructionFormat::BranchTableEntry => {
+                let index = self.match_value("expected SSA value operand")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let base = self.match_value("expected SSA value operand")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let imm = self.match_uimm8("expected entry size")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let table = self.match_jt()
+                    .and_then(|num| ctx.get_jt(num, &self.loc))?;
+                InstructionData::BranchTableEntry {
+                    opcode: opcode,
+                    args: [index, base],
+                    imm: imm,
+                    table: table,
+                }
+            }
             InstructionFormat::StackLoad => {
                 let ss = self.match_ss("expected stack slot number: ss«n»")
                     .and_then(|num| ctx.get_ss(num, &self.loc))?;
@@ -1652,6 +1945,19 @@ impl<'a> Parser<'a> {
                     offset: offset,
                 }
             }
+            InstructionFormat::TableAddr => {
+                let table = self.match_table("expected table reference")
+                    .and_then(|num| ctx.get_table(num, &self.loc))?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let arg = self.match_value("expected SSA value table index")?;
+                let offset = self.optional_offset32()?;
+                InstructionData::TableAddr {
+                    opcode: opcode,
+                    table: table,
+                    arg: arg,
+                    offset: offset,
+                }
+            }
             InstructionFormat::Load => {
                 let flags = self.optional_memflags();
                 let addr = self.match_value("expected SSA value address")?;
@@ -1676,6 +1982,59 @@ impl<'a> Parser<'a> {
                     offset: offset,
                 }
             }
+            InstructionFormat::AtomicLoad => {
+                let order = self.match_enum("expected memory ordering")?;
+                let addr = self.match_value("expected SSA value address")?;
+                InstructionData::AtomicLoad {
+                    opcode: opcode,
+                    order: order,
+                    arg: addr,
+                }
+            }
+            InstructionFormat::AtomicRmw => {
+                let order = self.match_enum("expected memory ordering")?;
+                let arg = self.match_value("expected SSA value operand")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let addr = self.match_value("expected SSA value address")?;
+                InstructionData::AtomicRmw {
+                    opcode: opcode,
+                    order: order,
+                    args: [arg, addr],
+                }
+            }
+            InstructionFormat::AtomicCas => {
+                let order = self.match_enum("expected memory ordering")?;
+                let expected = self.match_value("expected SSA value operand")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let arg = self.match_value("expected SSA value operand")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let addr = self.match_value("expected SSA value address")?;
+                InstructionData::AtomicCas {
+                    opcode: opcode,
+                    order: order,
+                    args: [expected, arg, addr],
+                }
+            }
+            InstructionFormat::Fence => {
+                let order = self.match_enum("expected memory ordering")?;
+                InstructionData::Fence {
+                    opcode: opcode,
+                    order: order,
+                }
+            }
+            InstructionFormat::RegMove => {
+                let arg = self.match_value("expected SSA value operand")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let src = self.match_regunit(ctx.unique_isa)?;
+                self.match_token(Token::Arrow, "expected '->' between register units")?;
+                let dst = self.match_regunit(ctx.unique_isa)?;
+                InstructionData::RegMove {
+                    opcode: opcode,
+                    arg: arg,
+                    src: src,
+                    dst: dst,
+                }
+            }
         };
         Ok(idata)
     }
@@ -1684,7 +2043,8 @@ impl<'a> Parser<'a> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use cretonne::ir::{ArgumentExtension, ArgumentPurpose};
+    use cretonne::ir::{ArgumentExtension, ArgumentPurpose, CallConv};
+    use cretonne::write_version;
     use cretonne::ir::types;
     use cretonne::ir::entities::AnyEntity;
     use testfile::{Details, Comment};
@@ -1727,6 +2087,29 @@ mod tests {
         }
     }
 
+    #[test]
+    fn alias_order() {
+        // The alias values are numbered in the order of their source numbers, not in the
+        // iteration order of a hash map.
+        let (_, details) = Parser::new("function qux() {
+                                        ebb0:
+                                          v0 = iconst.i8 6
+                                          v9 -> v0
+                                          v5 -> v0
+                                          v7 -> v0
+                                          v2 -> v0
+                                          v8 -> v0
+                                          return
+                                        }")
+                .parse_function(None)
+                .unwrap();
+        let aliases: Vec<String> = ["v2", "v5", "v7", "v8", "v9"]
+            .iter()
+            .map(|src| details.map.lookup_str(src).unwrap().to_string())
+            .collect();
+        assert_eq!(aliases, ["v1", "v2", "v3", "v4", "v5"]);
+    }
+
     #[test]
     fn signature() {
         let sig = Parser::new("()").parse_signature(None).unwrap();
@@ -1755,6 +2138,22 @@ mod tests {
                        .unwrap_err()
                        .to_string(),
                    "1: expected ')' after function arguments");
+        assert_eq!(Parser::new("(i32 [4294967293])")
+                       .parse_signature(None)
+                       .unwrap_err()
+                       .to_string(),
+                   "1: stack argument offset too large");
+
+        let sig3 = Parser::new("(i64, f64) -> i64 windows_fastcall")
+            .parse_signature(None)
+            .unwrap();
+        assert_eq!(sig3.call_conv, CallConv::WindowsFastcall);
+        assert_eq!(sig3.to_string(), "(i64, f64) -> i64 windows_fastcall");
+        assert_eq!(Parser::new("() pascal")
+                       .parse_signature(None)
+                       .unwrap_err()
+                       .to_string(),
+                   "1: unknown calling convention: pascal");
     }
 
     #[test]
@@ -1786,11 +2185,91 @@ mod tests {
                    "3: duplicate stack slot: ss1");
     }
 
+    #[test]
+    fn stack_slot_kinds() {
+        let (func, _) = Parser::new("function foo() {
+                                       ss0 = stack_slot 13
+                                       ss1 = spill_slot 1
+                                       ss2 = outgoing_arg 4, offset 8
+                                     }")
+                .parse_function(None)
+                .unwrap();
+        let mut iter = func.stack_slots.keys();
+        let ss0 = iter.next().unwrap();
+        assert_eq!(func.stack_slots[ss0].kind, StackSlotKind::Local);
+        let ss1 = iter.next().unwrap();
+        assert_eq!(func.stack_slots[ss1].kind, StackSlotKind::SpillSlot);
+        assert_eq!(func.stack_slots[ss1].size, 1);
+        let ss2 = iter.next().unwrap();
+        assert_eq!(func.stack_slots[ss2].kind, StackSlotKind::OutgoingArg);
+        assert_eq!(func.stack_slots[ss2].offset, 8);
+        assert_eq!(iter.next(), None);
+
+        assert_eq!(Parser::new("function bar() {
+                                    ss1  = stack 13
+                                }")
+                           .parse_function(None)
+                           .unwrap_err()
+                           .to_string(),
+                   "2: expected stack slot kind");
+    }
+
+    #[test]
+    fn global_var_decl() {
+        let (func, _) = Parser::new("function foo() {
+                                       gv1 = global counter
+                                       gv3 = global tls errno
+                                     }")
+                .parse_function(None)
+                .unwrap();
+        let mut iter = func.global_vars.keys();
+        let gv0 = iter.next().unwrap();
+        assert_eq!(gv0.to_string(), "gv0");
+        assert_eq!(func.global_vars[gv0].to_string(), "global counter");
+        let gv1 = iter.next().unwrap();
+        assert_eq!(func.global_vars[gv1].to_string(), "global tls errno");
+        assert_eq!(iter.next(), None);
+
+        assert_eq!(Parser::new("function bar() {
+                                    gv0 = tls errno
+                                }")
+                           .parse_function(None)
+                           .unwrap_err()
+                           .to_string(),
+                   "2: expected 'global'");
+    }
+
+    #[test]
+    fn table_decl() {
+        let (func, _) = Parser::new("function foo() {
+                                       gv0 = global tls wasm_table_0
+                                       table2 = table gv0, bound 10, element_size 16
+                                     }")
+                .parse_function(None)
+                .unwrap();
+        let mut iter = func.tables.keys();
+        let table0 = iter.next().unwrap();
+        assert_eq!(table0.to_string(), "table0");
+        assert_eq!(func.tables[table0].to_string(),
+                   "table gv0, bound 10, element_size 16");
+        assert_eq!(iter.next(), None);
+
+        assert_eq!(Parser::new("function bar() {
+                                    table0 = table gv0, bound 10, element_size 16
+                                }")
+                           .parse_function(None)
+                           .unwrap_err()
+                           .to_string(),
+                   "2: undefined global variable gv0");
+    }
+
     #[test]
     fn ebb_header() {
         let (func, _) = Parser::new("function ebbs() {
                                      ebb0:
                                      ebb4(v3: i32):
+                                     ebb1 cold:
+                                     ebb2(v5: i32) cold:
                                      }")
                 .parse_function(None)
                 .unwrap();
@@ -1805,6 +2284,15 @@ mod tests {
         let ebb4_args = func.dfg.ebb_args(ebb4);
         assert_eq!(ebb4_args.len(), 1);
         assert_eq!(func.dfg.value_type(ebb4_args[0]), types::I32);
+        assert!(!func.is_cold(ebb0) && !func.is_cold(ebb4));
+
+        let ebb1 = ebbs.next().unwrap();
+        assert_eq!(func.dfg.ebb_args(ebb1), &[]);
+        assert!(func.is_cold(ebb1));
+
+        let ebb2 = ebbs.next().unwrap();
+        assert_eq!(func.dfg.ebb_args(ebb2).len(), 1);
+        assert!(func.is_cold(ebb2));
     }
 
     #[test]
@@ -1869,6 +2357,35 @@ mod tests {
         assert_eq!(tf.functions[0].0.name.to_string(), "comment");
     }
 
+    #[test]
+    fn version() {
+        let mut text = String::new();
+        write_version(&mut text).unwrap();
+        text.push_str("test verify\nfunction f() {}");
+        let tf = parse_test(&text).unwrap();
+        assert_eq!(tf.version, Some(IL_VERSION));
+        assert_eq!(tf.commands.len(), 1);
+        assert_eq!(parse_test("function f() {}").unwrap().version, None);
+
+        let newer = format!("version {}\nfunction f() {{}}", IL_VERSION + 1);
+        assert_eq!(parse_test(&newer).err().unwrap().to_string(),
+                   format!("1: IL version {} is newer than version {} supported by this parser",
+                           IL_VERSION + 1,
+                           IL_VERSION));
+        assert_eq!(parse_test("version 0\nfunction f() {}")
+                       .err()
+                       .unwrap()
+                       .to_string(),
+                   format!("1: IL version 0 is no longer supported, the oldest supported \
+                            version is {}",
+                           OLDEST_IL_VERSION));
+        assert_eq!(parse_test("version\nfunction f() {}")
+                       .err()
+                       .unwrap()
+                       .to_string(),
+                   "2: expected IL version number");
+    }
+
     #[test]
     fn isa_spec() {
         assert!(parse_test("isa
@@ -1891,5 +2408,71 @@ mod tests {
                 assert_eq!(v[0].name(), "riscv");
             }
         }
+
+        // Target triples imply shared settings for their own ISA only.
+        match parse_test("isa x86_64-unknown-linux-gnu
+                          isa intel
+                          function foo() {}")
+                      .unwrap()
+                      .isa_spec {
+            IsaSpec::None(_) => panic!("Expected some ISA"),
+            IsaSpec::Some(v) => {
+                assert_eq!(v.len(), 2);
+                assert_eq!(v[0].name(), "intel");
+                assert!(v[0].flags().is_64bit());
+                assert_eq!(v[1].name(), "intel");
+                assert!(!v[1].flags().is_64bit());
+            }
+        }
+    }
+
+    #[test]
+    fn register_pairs() {
+        let tf = parse_test("isa riscv
+                             function pairs() {
+                             ebb0:
+                                 [-,%x10:%x11] v1 = iconst.i64 0
+                                 [-,%x12]      v2 = iconst.i32 0
+                                 return
+                             }")
+                .unwrap();
+        let func = &tf.functions[0].0;
+        let ebb = func.layout.entry_block().unwrap();
+        let mut insts = func.layout.ebb_insts(ebb);
+        let v1 = func.dfg.first_result(insts.next().unwrap());
+        let v2 = func.dfg.first_result(insts.next().unwrap());
+        assert_eq!(func.locations[v1].unwrap_reg_pair(), (10, 11));
+        assert_eq!(func.locations[v2].unwrap_reg(), 12);
+
+        assert!(parse_test("isa riscv
+                            function pairs() {
+                            ebb0:
+                                [-,%x10:%x12] v1 = iconst.i64 0
+                                return
+                            }")
+                        .is_err());
+    }
+
+    #[test]
+    fn srclocs() {
+        let tf = parse_test("function srclocs() {
+                             ebb0:
+                                 @0012 v1 = iconst.i32 0
+                                       v2 = iconst.i32 1
+                                 @abc  return
+                             }")
+                .unwrap();
+        let func = &tf.functions[0].0;
+        let ebb = func.layout.entry_block().unwrap();
+        let insts: Vec<_> = func.layout.ebb_insts(ebb).collect();
+        assert_eq!(func.srclocs[insts[0]], SourceLoc::new(0x12));
+        assert!(func.srclocs[insts[1]].is_default());
+        assert_eq!(func.srclocs[insts[2]], SourceLoc::new(0xabc));
+
+        assert!(parse_test("function srclocs() {
+                            ebb0:
+                                @ return
+                            }")
+                        .is_err());
     }
 }
@@ -8,7 +8,7 @@
 //! clients.
 
 use std::collections::HashMap;
-use cretonne::ir::{StackSlot, JumpTable, Ebb, Value, SigRef, FuncRef};
+use cretonne::ir::{StackSlot, JumpTable, GlobalVar, Table, Ebb, Value, SigRef, FuncRef};
 use cretonne::ir::entities::AnyEntity;
 use error::{Result, Location};
 use lexer::split_entity_name;
@@ -22,6 +22,8 @@ pub struct SourceMap {
     signatures: HashMap<u32, SigRef>, // sigNN
     functions: HashMap<u32, FuncRef>, // fnNN
     jump_tables: HashMap<u32, JumpTable>, // jtNN
+    global_vars: HashMap<u32, GlobalVar>, // gvNN
+    tables: HashMap<u32, Table>, // tableNN
 
     // Store locations for entities, including instructions.
     locations: HashMap<AnyEntity, Location>,
@@ -59,6 +61,16 @@ impl SourceMap {
         self.jump_tables.get(&src_num).cloned()
     }
 
+    /// Look up a global variable entity by its source number.
+    pub fn get_gv(&self, src_num: u32) -> Option<GlobalVar> {
+        self.global_vars.get(&src_num).cloned()
+    }
+
+    /// Look up a table entity by its source number.
+    pub fn get_table(&self, src_num: u32) -> Option<Table> {
+        self.tables.get(&src_num).cloned()
+    }
+
     /// Look up an entity by source name.
     /// Returns the entity reference corresponding to `name`, if it exists.
     pub fn lookup_str(&self, name: &str) -> Option<AnyEntity> {
@@ -77,6 +89,10 @@ impl SourceMap {
                                              "sig" => self.get_sig(num).map(AnyEntity::SigRef),
                                              "fn" => self.get_fn(num).map(AnyEntity::FuncRef),
                                              "jt" => self.get_jt(num).map(AnyEntity::JumpTable),
+                                             "gv" => self.get_gv(num).map(AnyEntity::GlobalVar),
+                                             "table" => {
+                                                 self.get_table(num).map(AnyEntity::Table)
+                                             }
                                              _ => None,
                                          })
     }
@@ -140,12 +156,21 @@ pub trait MutableSourceMap {
     fn def_sig(&mut self, src_num: u32, entity: SigRef, loc: &Location) -> Result<()>;
     fn def_fn(&mut self, src_num: u32, entity: FuncRef, loc: &Location) -> Result<()>;
     fn def_jt(&mut self, src_num: u32, entity: JumpTable, loc: &Location) -> Result<()>;
+    fn def_gv(&mut self, src_num: u32, entity: GlobalVar, loc: &Location) -> Result<()>;
+    fn def_table(&mut self, src_num: u32, entity: Table, loc: &Location) -> Result<()>;
 
     /// Define an entity without an associated source number. This can be used for instructions
     /// whose numbers never appear in source, or implicitly defined signatures.
     fn def_entity(&mut self, entity: AnyEntity, loc: &Location) -> Result<()>;
 }
 
+/// An empty source map, for functions that were not parsed from text.
+impl Default for SourceMap {
+    fn default() -> SourceMap {
+        MutableSourceMap::new()
+    }
+}
+
 impl MutableSourceMap for SourceMap {
     fn new() -> SourceMap {
         SourceMap {
@@ -155,6 +180,8 @@ impl MutableSourceMap for SourceMap {
             signatures: HashMap::new(),
             functions: HashMap::new(),
             jump_tables: HashMap::new(),
+            global_vars: HashMap::new(),
+            tables: HashMap::new(),
             locations: HashMap::new(),
         }
     }
@@ -207,6 +234,22 @@ impl MutableSourceMap for SourceMap {
         }
     }
 
+    fn def_gv(&mut self, src_num: u32, entity: GlobalVar, loc: &Location) -> Result<()> {
+        if self.global_vars.insert(src_num, entity).is_some() {
+            err!(loc, "duplicate global variable: gv{}", src_num)
+        } else {
+            self.def_entity(entity.into(), loc)
+        }
+    }
+
+    fn def_table(&mut self, src_num: u32, entity: Table, loc: &Location) -> Result<()> {
+        if self.tables.insert(src_num, entity).is_some() {
+            err!(loc, "duplicate table: table{}", src_num)
+        } else {
+            self.def_entity(entity.into(), loc)
+        }
+    }
+
     fn def_entity(&mut self, entity: AnyEntity, loc: &Location) -> Result<()> {
         if self.locations.insert(entity, loc.clone()).is_some() {
             err!(loc, "duplicate entity: {}", entity)
@@ -16,6 +16,8 @@ use error::Location;
 /// This is the result of parsing a `.cton` file which contains a number of test commands and ISA
 /// specs followed by the functions that should be tested.
 pub struct TestFile<'a> {
+    /// Version from the `version` line, if present.
+    pub version: Option<u32>,
     /// `test foo ...` lines.
     pub commands: Vec<TestCommand<'a>>,
     /// `isa bar ...` lines.
@@ -0,0 +1,44 @@
+//! Parser regression tests.
+//!
+//! The files in `tests/regressions` are invalid inputs that the parser must reject with an error
+//! instead of panicking, typically because a number overflows. Each file contains a single
+//! invalid construct, and the line where the error is expected is marked with a `; error`
+//! comment.
+
+extern crate cton_reader;
+
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+#[test]
+fn regressions() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/regressions");
+    let mut count = 0;
+    for entry in fs::read_dir(&dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().map_or(true, |ext| ext != "cton") {
+            continue;
+        }
+        let mut text = String::new();
+        fs::File::open(&path)
+            .and_then(|mut file| file.read_to_string(&mut text))
+            .unwrap();
+        let want = match text.lines().position(|line| line.contains("; error")) {
+            Some(idx) => idx + 1,
+            None => panic!("{}: no '; error' line", path.display()),
+        };
+        match cton_reader::parse_test(&text) {
+            Ok(_) => panic!("{}: expected a parse error", path.display()),
+            Err(e) => {
+                assert_eq!(e.location.line_number,
+                           want,
+                           "{}: wrong error: {}",
+                           path.display(),
+                           e)
+            }
+        }
+        count += 1;
+    }
+    assert!(count > 0, "no regression tests in {}", dir.display());
+}
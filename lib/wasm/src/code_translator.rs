@@ -0,0 +1,833 @@
+//! Translation of WebAssembly operators to Cretonne IL.
+//!
+//! Each operator is translated by `translate_operator()`, which updates the value stack and the
+//! control stack in the `TranslationState`. The structured control flow of WebAssembly maps to
+//! EBBs:
+//!
+//! - A `block` gets a destination EBB following its `end`, whose arguments are the results of the
+//!   block.
+//! - A `loop` also gets a header EBB, which is the target of the branches to the loop.
+//! - An `if` branches to an `else` EBB when its condition is zero.
+//!
+//! Code following an unconditional branch is unreachable until the `end` of the current frame,
+//! and it is skipped.
+
+use cretonne::entity_map::EntityRef;
+use cretonne::ir::{self, InstBuilder, MemFlags, JumpTableData, ArgumentPurpose};
+use cretonne::ir::condcodes::{IntCC, FloatCC};
+use cretonne::ir::immediates::{Ieee32, Ieee64};
+use cretonne::ir::types::*;
+use cton_frontend::{FunctionBuilder, Variable};
+use environ::{FuncEnvironment, GlobalValue};
+use state::{TranslationState, ControlKind};
+use translation_utils::{block_results, reader_error, WasmResult};
+use wasmparser::{Operator, MemoryImmediate};
+use std::collections::HashMap;
+use std::i32;
+
+/// Translate a WebAssembly operator and update the translation state.
+pub fn translate_operator<FE: FuncEnvironment + ?Sized>(op: Operator,
+                                                        builder: &mut FunctionBuilder,
+                                                        state: &mut TranslationState,
+                                                        environ: &mut FE)
+                                                        -> WasmResult<()> {
+    if !state.reachable {
+        return translate_unreachable_operator(op, builder, state);
+    }
+
+    match op {
+        // Control flow.
+        Operator::Nop => {}
+        Operator::Unreachable => {
+            builder.ins().trap();
+            state.reachable = false;
+        }
+        Operator::Block { ty } => {
+            let destination = create_destination(builder, &block_results(ty)?);
+            let num_results = builder.ebb_args(destination).len();
+            state.push_frame(ControlKind::Block, destination, num_results);
+        }
+        Operator::Loop { ty } => {
+            let destination = create_destination(builder, &block_results(ty)?);
+            let num_results = builder.ebb_args(destination).len();
+            let header = builder.create_ebb();
+            builder.ins().jump(header, &[]);
+            builder.switch_to_block(header);
+            state.push_frame(ControlKind::Loop { header: header }, destination, num_results);
+        }
+        Operator::If { ty } => {
+            let cond = state.pop1()?;
+            let destination = create_destination(builder, &block_results(ty)?);
+            let num_results = builder.ebb_args(destination).len();
+            let else_ebb = builder.create_ebb();
+            builder.ins().brz(cond, else_ebb, &[]);
+            state.push_frame(ControlKind::If {
+                                 else_ebb: else_ebb,
+                                 has_else: false,
+                             },
+                             destination,
+                             num_results);
+        }
+        Operator::Else => translate_else(builder, state)?,
+        Operator::End => translate_end(builder, state)?,
+        Operator::Br { relative_depth } => {
+            let (ebb, num_args) = branch_target(state, relative_depth)?;
+            builder.ins().jump(ebb, state.peekn(num_args)?);
+            state.reachable = false;
+        }
+        Operator::BrIf { relative_depth } => {
+            let cond = state.pop1()?;
+            let (ebb, num_args) = branch_target(state, relative_depth)?;
+            builder.ins().brnz(cond, ebb, state.peekn(num_args)?);
+        }
+        Operator::BrTable { table } => {
+            let mut depths = Vec::new();
+            for target in table.targets() {
+                let (depth, _is_default) = target.map_err(reader_error)?;
+                depths.push(depth);
+            }
+            translate_br_table(&depths, builder, state)?;
+        }
+        Operator::Return => {
+            let num_results = state.control_stack[0].num_results;
+            builder.ins().return_(state.peekn(num_results)?);
+            state.reachable = false;
+        }
+
+        // Calls.
+        Operator::Call { function_index } => {
+            let index = function_index as usize;
+            let fref = match state.direct_funcs.get(&index) {
+                Some(&fref) => fref,
+                None => {
+                    let fref = environ.make_direct_func(builder.func, index);
+                    state.direct_funcs.insert(index, fref);
+                    fref
+                }
+            };
+            let sig = builder.func.dfg.ext_funcs[fref].signature;
+            let args = call_args(&builder.func.dfg.signatures[sig], state)?;
+            let call = builder.ins().call(fref, &args);
+            state.stack.extend_from_slice(builder.func.dfg.inst_results(call));
+        }
+        Operator::CallIndirect { index, table_index } => {
+            let index = index as usize;
+            let sig_ref = match state.indirect_sigs.get(&index) {
+                Some(&sig_ref) => sig_ref,
+                None => {
+                    let sig_ref = environ.make_indirect_sig(builder.func, index);
+                    state.indirect_sigs.insert(index, sig_ref);
+                    sig_ref
+                }
+            };
+            let callee_index = state.pop1()?;
+            let args = call_args(&builder.func.dfg.signatures[sig_ref], state)?;
+            let (callee, sig_id) =
+                environ.translate_table_entry(builder, table_index as usize, callee_index);
+
+            // Calling a function with the wrong signature traps.
+            let expected = environ.signature_id(index);
+            let mismatch = builder
+                .ins()
+                .icmp_imm(IntCC::NotEqual, sig_id, expected as i64);
+            builder.ins().trapnz(mismatch);
+
+            let call = builder.ins().call_indirect(sig_ref, callee, &args);
+            state.stack.extend_from_slice(builder.func.dfg.inst_results(call));
+        }
+
+        // Parametric operators.
+        Operator::Drop => {
+            state.pop1()?;
+        }
+        Operator::Select |
+        Operator::TypedSelect { .. } => {
+            let (x, y, cond) = state.pop3()?;
+            state.push1(builder.ins().select(cond, x, y));
+        }
+
+        // Variables.
+        Operator::LocalGet { local_index } => {
+            state.push1(builder.use_var(Variable::new(local_index as usize)));
+        }
+        Operator::LocalSet { local_index } => {
+            let val = state.pop1()?;
+            builder.def_var(Variable::new(local_index as usize), val);
+        }
+        Operator::LocalTee { local_index } => {
+            let val = state.peekn(1)?[0];
+            builder.def_var(Variable::new(local_index as usize), val);
+        }
+        Operator::GlobalGet { global_index } => {
+            let val = match get_global(global_index, builder, state, environ) {
+                GlobalValue::Const { ty, bits } => {
+                    match ty {
+                        F32 => builder.ins().f32const(Ieee32::from_bits(bits as u32)),
+                        F64 => builder.ins().f64const(Ieee64::from_bits(bits)),
+                        _ => builder.ins().iconst(ty, bits as i64),
+                    }
+                }
+                GlobalValue::Var { gv, ty } => {
+                    let addr = builder.ins().tls_value(environ.native_pointer(), gv);
+                    builder.ins().load(ty, MemFlags::new(), addr, 0)
+                }
+                GlobalValue::VmCtx { offset, ty } => {
+                    let vmctx = get_vmctx(state)?;
+                    builder.ins().load(ty, MemFlags::new(), vmctx, offset)
+                }
+            };
+            state.push1(val);
+        }
+        Operator::GlobalSet { global_index } => {
+            let val = state.pop1()?;
+            match get_global(global_index, builder, state, environ) {
+                GlobalValue::Const { .. } => {
+                    return Err(format!("global {} is immutable", global_index));
+                }
+                GlobalValue::Var { gv, .. } => {
+                    let addr = builder.ins().tls_value(environ.native_pointer(), gv);
+                    builder.ins().store(MemFlags::new(), val, addr, 0);
+                }
+                GlobalValue::VmCtx { offset, .. } => {
+                    let vmctx = get_vmctx(state)?;
+                    builder.ins().store(MemFlags::new(), val, vmctx, offset);
+                }
+            }
+        }
+
+        // Memory.
+        Operator::I32Load { memarg } => translate_load(I32, 4, memarg, builder, state, environ)?,
+        Operator::I64Load { memarg } => translate_load(I64, 8, memarg, builder, state, environ)?,
+        Operator::F32Load { memarg } => translate_load(F32, 4, memarg, builder, state, environ)?,
+        Operator::F64Load { memarg } => translate_load(F64, 8, memarg, builder, state, environ)?,
+        Operator::I32Load8S { memarg } => {
+            let (addr, offset) = translate_address(memarg, 1, builder, state, environ)?;
+            state.push1(builder.ins().sload8(I32, MemFlags::new(), addr, offset));
+        }
+        Operator::I32Load8U { memarg } => {
+            let (addr, offset) = translate_address(memarg, 1, builder, state, environ)?;
+            state.push1(builder.ins().uload8(I32, MemFlags::new(), addr, offset));
+        }
+        Operator::I32Load16S { memarg } => {
+            let (addr, offset) = translate_address(memarg, 2, builder, state, environ)?;
+            state.push1(builder.ins().sload16(I32, MemFlags::new(), addr, offset));
+        }
+        Operator::I32Load16U { memarg } => {
+            let (addr, offset) = translate_address(memarg, 2, builder, state, environ)?;
+            state.push1(builder.ins().uload16(I32, MemFlags::new(), addr, offset));
+        }
+        Operator::I64Load8S { memarg } => {
+            let (addr, offset) = translate_address(memarg, 1, builder, state, environ)?;
+            state.push1(builder.ins().sload8(I64, MemFlags::new(), addr, offset));
+        }
+        Operator::I64Load8U { memarg } => {
+            let (addr, offset) = translate_address(memarg, 1, builder, state, environ)?;
+            state.push1(builder.ins().uload8(I64, MemFlags::new(), addr, offset));
+        }
+        Operator::I64Load16S { memarg } => {
+            let (addr, offset) = translate_address(memarg, 2, builder, state, environ)?;
+            state.push1(builder.ins().sload16(I64, MemFlags::new(), addr, offset));
+        }
+        Operator::I64Load16U { memarg } => {
+            let (addr, offset) = translate_address(memarg, 2, builder, state, environ)?;
+            state.push1(builder.ins().uload16(I64, MemFlags::new(), addr, offset));
+        }
+        Operator::I64Load32S { memarg } => {
+            let (addr, offset) = translate_address(memarg, 4, builder, state, environ)?;
+            state.push1(builder.ins().sload32(I64, MemFlags::new(), addr, offset));
+        }
+        Operator::I64Load32U { memarg } => {
+            let (addr, offset) = translate_address(memarg, 4, builder, state, environ)?;
+            state.push1(builder.ins().uload32(I64, MemFlags::new(), addr, offset));
+        }
+        Operator::I32Store { memarg } |
+        Operator::F32Store { memarg } => translate_store(4, memarg, builder, state, environ)?,
+        Operator::I64Store { memarg } |
+        Operator::F64Store { memarg } => translate_store(8, memarg, builder, state, environ)?,
+        Operator::I32Store8 { memarg } |
+        Operator::I64Store8 { memarg } => {
+            let val = state.pop1()?;
+            let (addr, offset) = translate_address(memarg, 1, builder, state, environ)?;
+            builder.ins().istore8(MemFlags::new(), val, addr, offset);
+        }
+        Operator::I32Store16 { memarg } |
+        Operator::I64Store16 { memarg } => {
+            let val = state.pop1()?;
+            let (addr, offset) = translate_address(memarg, 2, builder, state, environ)?;
+            builder.ins().istore16(MemFlags::new(), val, addr, offset);
+        }
+        Operator::I64Store32 { memarg } => {
+            let val = state.pop1()?;
+            let (addr, offset) = translate_address(memarg, 4, builder, state, environ)?;
+            builder.ins().istore32(MemFlags::new(), val, addr, offset);
+        }
+        Operator::MemorySize { mem, .. } => {
+            let val = environ.translate_current_memory(builder, mem as usize);
+            state.push1(val);
+        }
+        Operator::MemoryGrow { mem, .. } => {
+            let delta = state.pop1()?;
+            let val = environ.translate_grow_memory(builder, mem as usize, delta);
+            state.push1(val);
+        }
+
+        // Constants.
+        Operator::I32Const { value } => state.push1(builder.ins().iconst(I32, value as i64)),
+        Operator::I64Const { value } => state.push1(builder.ins().iconst(I64, value)),
+        Operator::F32Const { value } => {
+            state.push1(builder.ins().f32const(Ieee32::from_bits(value.bits())));
+        }
+        Operator::F64Const { value } => {
+            state.push1(builder.ins().f64const(Ieee64::from_bits(value.bits())));
+        }
+
+        // Comparisons.
+        Operator::I32Eqz |
+        Operator::I64Eqz => {
+            let arg = state.pop1()?;
+            let cond = builder.ins().icmp_imm(IntCC::Equal, arg, 0);
+            state.push1(builder.ins().bint(I32, cond));
+        }
+        Operator::I32Eq | Operator::I64Eq => translate_icmp(IntCC::Equal, builder, state)?,
+        Operator::I32Ne | Operator::I64Ne => translate_icmp(IntCC::NotEqual, builder, state)?,
+        Operator::I32LtS |
+        Operator::I64LtS => translate_icmp(IntCC::SignedLessThan, builder, state)?,
+        Operator::I32LtU |
+        Operator::I64LtU => translate_icmp(IntCC::UnsignedLessThan, builder, state)?,
+        Operator::I32GtS |
+        Operator::I64GtS => translate_icmp(IntCC::SignedGreaterThan, builder, state)?,
+        Operator::I32GtU |
+        Operator::I64GtU => translate_icmp(IntCC::UnsignedGreaterThan, builder, state)?,
+        Operator::I32LeS |
+        Operator::I64LeS => translate_icmp(IntCC::SignedLessThanOrEqual, builder, state)?,
+        Operator::I32LeU |
+        Operator::I64LeU => translate_icmp(IntCC::UnsignedLessThanOrEqual, builder, state)?,
+        Operator::I32GeS |
+        Operator::I64GeS => translate_icmp(IntCC::SignedGreaterThanOrEqual, builder, state)?,
+        Operator::I32GeU |
+        Operator::I64GeU => translate_icmp(IntCC::UnsignedGreaterThanOrEqual, builder, state)?,
+        Operator::F32Eq | Operator::F64Eq => translate_fcmp(FloatCC::Equal, builder, state)?,
+        Operator::F32Ne | Operator::F64Ne => translate_fcmp(FloatCC::NotEqual, builder, state)?,
+        Operator::F32Lt | Operator::F64Lt => translate_fcmp(FloatCC::LessThan, builder, state)?,
+        Operator::F32Gt | Operator::F64Gt => translate_fcmp(FloatCC::GreaterThan, builder, state)?,
+        Operator::F32Le |
+        Operator::F64Le => translate_fcmp(FloatCC::LessThanOrEqual, builder, state)?,
+        Operator::F32Ge |
+        Operator::F64Ge => translate_fcmp(FloatCC::GreaterThanOrEqual, builder, state)?,
+
+        // Integer arithmetic.
+        Operator::I32Clz | Operator::I64Clz => {
+            let arg = state.pop1()?;
+            state.push1(builder.ins().clz(arg));
+        }
+        Operator::I32Ctz | Operator::I64Ctz => {
+            let arg = state.pop1()?;
+            state.push1(builder.ins().ctz(arg));
+        }
+        Operator::I32Popcnt |
+        Operator::I64Popcnt => {
+            let arg = state.pop1()?;
+            state.push1(builder.ins().popcnt(arg));
+        }
+        Operator::I32Add | Operator::I64Add => {
+            let (a, b) = state.pop2()?;
+            state.push1(builder.ins().iadd(a, b));
+        }
+        Operator::I32Sub | Operator::I64Sub => {
+            let (a, b) = state.pop2()?;
+            state.push1(builder.ins().isub(a, b));
+        }
+        Operator::I32Mul | Operator::I64Mul => {
+            let (a, b) = state.pop2()?;
+            state.push1(builder.ins().imul(a, b));
+        }
+        Operator::I32DivS |
+        Operator::I64DivS => {
+            let (a, b) = state.pop2()?;
+            state.push1(builder.ins().sdiv(a, b));
+        }
+        Operator::I32DivU |
+        Operator::I64DivU => {
+            let (a, b) = state.pop2()?;
+            state.push1(builder.ins().udiv(a, b));
+        }
+        Operator::I32RemS |
+        Operator::I64RemS => {
+            let (a, b) = state.pop2()?;
+            state.push1(builder.ins().srem(a, b));
+        }
+        Operator::I32RemU |
+        Operator::I64RemU => {
+            let (a, b) = state.pop2()?;
+            state.push1(builder.ins().urem(a, b));
+        }
+        Operator::I32And | Operator::I64And => {
+            let (a, b) = state.pop2()?;
+            state.push1(builder.ins().band(a, b));
+        }
+        Operator::I32Or | Operator::I64Or => {
+            let (a, b) = state.pop2()?;
+            state.push1(builder.ins().bor(a, b));
+        }
+        Operator::I32Xor | Operator::I64Xor => {
+            let (a, b) = state.pop2()?;
+            state.push1(builder.ins().bxor(a, b));
+        }
+        Operator::I32Shl | Operator::I64Shl => {
+            let (a, b) = state.pop2()?;
+            state.push1(builder.ins().ishl(a, b));
+        }
+        Operator::I32ShrS |
+        Operator::I64ShrS => {
+            let (a, b) = state.pop2()?;
+            state.push1(builder.ins().sshr(a, b));
+        }
+        Operator::I32ShrU |
+        Operator::I64ShrU => {
+            let (a, b) = state.pop2()?;
+            state.push1(builder.ins().ushr(a, b));
+        }
+        Operator::I32Rotl |
+        Operator::I64Rotl => {
+            let (a, b) = state.pop2()?;
+            state.push1(builder.ins().rotl(a, b));
+        }
+        Operator::I32Rotr |
+        Operator::I64Rotr => {
+            let (a, b) = state.pop2()?;
+            state.push1(builder.ins().rotr(a, b));
+        }
+
+        // Floating point arithmetic.
+        Operator::F32Abs | Operator::F64Abs => {
+            let arg = state.pop1()?;
+            state.push1(builder.ins().fabs(arg));
+        }
+        Operator::F32Neg | Operator::F64Neg => {
+            let arg = state.pop1()?;
+            state.push1(builder.ins().fneg(arg));
+        }
+        Operator::F32Ceil |
+        Operator::F64Ceil => {
+            let arg = state.pop1()?;
+            state.push1(builder.ins().ceil(arg));
+        }
+        Operator::F32Floor |
+        Operator::F64Floor => {
+            let arg = state.pop1()?;
+            state.push1(builder.ins().floor(arg));
+        }
+        Operator::F32Trunc |
+        Operator::F64Trunc => {
+            let arg = state.pop1()?;
+            state.push1(builder.ins().trunc(arg));
+        }
+        Operator::F32Nearest |
+        Operator::F64Nearest => {
+            let arg = state.pop1()?;
+            state.push1(builder.ins().nearest(arg));
+        }
+        Operator::F32Sqrt |
+        Operator::F64Sqrt => {
+            let arg = state.pop1()?;
+            state.push1(builder.ins().sqrt(arg));
+        }
+        Operator::F32Add | Operator::F64Add => {
+            let (a, b) = state.pop2()?;
+            state.push1(builder.ins().fadd(a, b));
+        }
+        Operator::F32Sub | Operator::F64Sub => {
+            let (a, b) = state.pop2()?;
+            state.push1(builder.ins().fsub(a, b));
+        }
+        Operator::F32Mul | Operator::F64Mul => {
+            let (a, b) = state.pop2()?;
+            state.push1(builder.ins().fmul(a, b));
+        }
+        Operator::F32Div | Operator::F64Div => {
+            let (a, b) = state.pop2()?;
+            state.push1(builder.ins().fdiv(a, b));
+        }
+        Operator::F32Min | Operator::F64Min => {
+            let (a, b) = state.pop2()?;
+            state.push1(builder.ins().fmin(a, b));
+        }
+        Operator::F32Max | Operator::F64Max => {
+            let (a, b) = state.pop2()?;
+            state.push1(builder.ins().fmax(a, b));
+        }
+        Operator::F32Copysign |
+        Operator::F64Copysign => {
+            let (a, b) = state.pop2()?;
+            state.push1(builder.ins().fcopysign(a, b));
+        }
+
+        // Conversions.
+        Operator::I32WrapI64 => {
+            let arg = state.pop1()?;
+            state.push1(builder.ins().ireduce(I32, arg));
+        }
+        Operator::I64ExtendI32S => {
+            let arg = state.pop1()?;
+            state.push1(builder.ins().sextend(I64, arg));
+        }
+        Operator::I64ExtendI32U => {
+            let arg = state.pop1()?;
+            state.push1(builder.ins().uextend(I64, arg));
+        }
+        Operator::I32Extend8S => translate_sign_extend(I32, I8, builder, state)?,
+        Operator::I32Extend16S => translate_sign_extend(I32, I16, builder, state)?,
+        Operator::I64Extend8S => translate_sign_extend(I64, I8, builder, state)?,
+        Operator::I64Extend16S => translate_sign_extend(I64, I16, builder, state)?,
+        Operator::I64Extend32S => translate_sign_extend(I64, I32, builder, state)?,
+        Operator::I32TruncF32S |
+        Operator::I32TruncF64S => {
+            let arg = state.pop1()?;
+            state.push1(builder.ins().fcvt_to_sint(I32, arg));
+        }
+        Operator::I32TruncF32U |
+        Operator::I32TruncF64U => {
+            let arg = state.pop1()?;
+            state.push1(builder.ins().fcvt_to_uint(I32, arg));
+        }
+        Operator::I64TruncF32S |
+        Operator::I64TruncF64S => {
+            let arg = state.pop1()?;
+            state.push1(builder.ins().fcvt_to_sint(I64, arg));
+        }
+        Operator::I64TruncF32U |
+        Operator::I64TruncF64U => {
+            let arg = state.pop1()?;
+            state.push1(builder.ins().fcvt_to_uint(I64, arg));
+        }
+        Operator::F32ConvertI32S |
+        Operator::F32ConvertI64S => {
+            let arg = state.pop1()?;
+            state.push1(builder.ins().fcvt_from_sint(F32, arg));
+        }
+        Operator::F32ConvertI32U |
+        Operator::F32ConvertI64U => {
+            let arg = state.pop1()?;
+            state.push1(builder.ins().fcvt_from_uint(F32, arg));
+        }
+        Operator::F64ConvertI32S |
+        Operator::F64ConvertI64S => {
+            let arg = state.pop1()?;
+            state.push1(builder.ins().fcvt_from_sint(F64, arg));
+        }
+        Operator::F64ConvertI32U |
+        Operator::F64ConvertI64U => {
+            let arg = state.pop1()?;
+            state.push1(builder.ins().fcvt_from_uint(F64, arg));
+        }
+        Operator::F32DemoteF64 => {
+            let arg = state.pop1()?;
+            state.push1(builder.ins().fdemote(F32, arg));
+        }
+        Operator::F64PromoteF32 => {
+            let arg = state.pop1()?;
+            state.push1(builder.ins().fpromote(F64, arg));
+        }
+        Operator::I32ReinterpretF32 => translate_bitcast(I32, builder, state)?,
+        Operator::I64ReinterpretF64 => translate_bitcast(I64, builder, state)?,
+        Operator::F32ReinterpretI32 => translate_bitcast(F32, builder, state)?,
+        Operator::F64ReinterpretI64 => translate_bitcast(F64, builder, state)?,
+
+        _ => return Err(format!("unsupported operator {:?}", op)),
+    }
+    Ok(())
+}
+
+// Get the location of the global `index`, declaring it in the function on first use.
+fn get_global<FE: FuncEnvironment + ?Sized>(index: u32,
+                                            builder: &mut FunctionBuilder,
+                                            state: &mut TranslationState,
+                                            environ: &mut FE)
+                                            -> GlobalValue {
+    let index = index as usize;
+    *state
+         .globals
+         .entry(index)
+         .or_insert_with(|| environ.make_global(builder.func, index))
+}
+
+fn get_vmctx(state: &TranslationState) -> WasmResult<ir::Value> {
+    state
+        .vmctx
+        .ok_or_else(|| "the function has no vmctx argument".to_string())
+}
+
+// Pop the arguments of a call with the signature `sig`. The `vmctx` argument of the current
+// function is passed on to a callee that has one.
+fn call_args(sig: &ir::Signature, state: &mut TranslationState) -> WasmResult<Vec<ir::Value>> {
+    let num_args = sig.argument_types
+        .iter()
+        .filter(|arg| arg.purpose == ArgumentPurpose::Normal)
+        .count();
+    let mut wasm_args = state.popn(num_args)?.into_iter();
+    let mut args = Vec::with_capacity(sig.argument_types.len());
+    for arg in &sig.argument_types {
+        match arg.purpose {
+            ArgumentPurpose::Normal => args.push(wasm_args.next().unwrap()),
+            ArgumentPurpose::VMContext => args.push(get_vmctx(state)?),
+            _ => return Err(format!("unsupported argument {} in call signature", arg)),
+        }
+    }
+    Ok(args)
+}
+
+// Translate an operator in unreachable code. Only the control structure is tracked until the
+// `end` or `else` of the frame that became unreachable.
+fn translate_unreachable_operator(op: Operator,
+                                  builder: &mut FunctionBuilder,
+                                  state: &mut TranslationState)
+                                  -> WasmResult<()> {
+    match op {
+        Operator::Block { .. } |
+        Operator::Loop { .. } |
+        Operator::If { .. } => state.unreachable_depth += 1,
+        Operator::Else => {
+            if state.unreachable_depth == 0 {
+                translate_else(builder, state)?;
+            }
+        }
+        Operator::End => {
+            if state.unreachable_depth == 0 {
+                translate_end(builder, state)?;
+            } else {
+                state.unreachable_depth -= 1;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+// Create the destination EBB of a frame with the given result types.
+fn create_destination(builder: &mut FunctionBuilder, results: &[ir::Type]) -> ir::Ebb {
+    let ebb = builder.create_ebb();
+    for &ty in results {
+        builder.append_ebb_arg(ebb, ty);
+    }
+    ebb
+}
+
+// Get the EBB and the number of arguments of a branch to the frame `relative_depth`.
+fn branch_target(state: &mut TranslationState,
+                 relative_depth: u32)
+                 -> WasmResult<(ir::Ebb, usize)> {
+    let frame = state.frame_mut(relative_depth)?;
+    // Branches to a loop go to its header, not past its end.
+    match frame.kind {
+        ControlKind::Loop { .. } => {}
+        _ => frame.reachable_end = true,
+    }
+    Ok(frame.branch_target())
+}
+
+fn translate_else(builder: &mut FunctionBuilder, state: &mut TranslationState) -> WasmResult<()> {
+    let reachable = state.reachable;
+    let frame = match state.control_stack.last_mut() {
+        Some(frame) => frame,
+        None => return Err("else without a frame".to_string()),
+    };
+    let else_ebb = match frame.kind {
+        ControlKind::If {
+            else_ebb,
+            ref mut has_else,
+        } => {
+            *has_else = true;
+            else_ebb
+        }
+        _ => return Err("else without an if".to_string()),
+    };
+    if reachable {
+        let len = state.stack.len();
+        if frame.num_results > len {
+            return Err("WebAssembly value stack underflow".to_string());
+        }
+        builder
+            .ins()
+            .jump(frame.destination, &state.stack[len - frame.num_results..]);
+        frame.reachable_end = true;
+    }
+    state.stack.truncate(frame.stack_size);
+    builder.switch_to_block(else_ebb);
+    builder.seal_block(else_ebb);
+    state.reachable = true;
+    Ok(())
+}
+
+fn translate_end(builder: &mut FunctionBuilder, state: &mut TranslationState) -> WasmResult<()> {
+    let mut frame = match state.control_stack.pop() {
+        Some(frame) => frame,
+        None => return Err("end without a frame".to_string()),
+    };
+    if state.reachable {
+        builder
+            .ins()
+            .jump(frame.destination, state.peekn(frame.num_results)?);
+        frame.reachable_end = true;
+    }
+    state.stack.truncate(frame.stack_size);
+    match frame.kind {
+        ControlKind::Loop { header } => builder.seal_block(header),
+        ControlKind::If {
+            else_ebb,
+            has_else: false,
+        } => {
+            // An `if` without `else` has no results, and its `else` EBB falls through.
+            builder.switch_to_block(else_ebb);
+            builder.seal_block(else_ebb);
+            builder.ins().jump(frame.destination, &[]);
+            frame.reachable_end = true;
+        }
+        _ => {}
+    }
+    builder.switch_to_block(frame.destination);
+    builder.seal_block(frame.destination);
+    state
+        .stack
+        .extend_from_slice(builder.ebb_args(frame.destination));
+    state.reachable = frame.reachable_end;
+    Ok(())
+}
+
+// Translate a `br_table` with the relative depths `depths`, the last of which is the default.
+fn translate_br_table(depths: &[u32],
+                      builder: &mut FunctionBuilder,
+                      state: &mut TranslationState)
+                      -> WasmResult<()> {
+    let index = state.pop1()?;
+    let (default, targets) = match depths.split_last() {
+        Some(split) => split,
+        None => return Err("br_table without a default".to_string()),
+    };
+    let num_args = branch_target(state, *default)?.1;
+
+    if num_args == 0 {
+        let mut data = JumpTableData::new();
+        for (idx, &depth) in targets.iter().enumerate() {
+            data.set_entry(idx, branch_target(state, depth)?.0);
+        }
+        let jt = builder.create_jump_table(data);
+        builder.ins().br_table(index, jt);
+        let ebb = branch_target(state, *default)?.0;
+        builder.ins().jump(ebb, &[]);
+    } else {
+        // Jump tables can't pass arguments, so go through an intermediate EBB per target.
+        let mut edges: HashMap<u32, ir::Ebb> = HashMap::new();
+        let mut data = JumpTableData::new();
+        for (idx, &depth) in targets.iter().enumerate() {
+            let ebb = *edges.entry(depth).or_insert_with(|| builder.create_ebb());
+            data.set_entry(idx, ebb);
+        }
+        let default_ebb = *edges.entry(*default).or_insert_with(|| builder.create_ebb());
+        let jt = builder.create_jump_table(data);
+        builder.ins().br_table(index, jt);
+        builder.ins().jump(default_ebb, &[]);
+
+        let mut edges: Vec<(u32, ir::Ebb)> = edges.into_iter().collect();
+        edges.sort_by_key(|&(_, ebb)| ebb.index());
+        for (depth, edge) in edges {
+            builder.switch_to_block(edge);
+            builder.seal_block(edge);
+            let (ebb, num_args) = branch_target(state, depth)?;
+            builder.ins().jump(ebb, state.peekn(num_args)?);
+        }
+    }
+    state.reachable = false;
+    Ok(())
+}
+
+// Pop the index of a memory access of `size` bytes, and compute its address. Return the address
+// and the offset to add to it.
+fn translate_address<FE: FuncEnvironment + ?Sized>(memarg: MemoryImmediate,
+                                                   size: u32,
+                                                   builder: &mut FunctionBuilder,
+                                                   state: &mut TranslationState,
+                                                   environ: &mut FE)
+                                                   -> WasmResult<(ir::Value, i32)> {
+    if memarg.offset > u32::max_value() as u64 {
+        return Err(format!("memory offset {} is too large", memarg.offset));
+    }
+    let offset = memarg.offset as u32;
+    let index = state.pop1()?;
+    let addr = environ.translate_memory_address(builder,
+                                                memarg.memory as usize,
+                                                index,
+                                                offset,
+                                                size);
+    // The offset immediate of loads and stores is signed.
+    if offset > i32::MAX as u32 {
+        Ok((builder.ins().iadd_imm(addr, offset as i64), 0))
+    } else {
+        Ok((addr, offset as i32))
+    }
+}
+
+fn translate_load<FE: FuncEnvironment + ?Sized>(ty: ir::Type,
+                                                size: u32,
+                                                memarg: MemoryImmediate,
+                                                builder: &mut FunctionBuilder,
+                                                state: &mut TranslationState,
+                                                environ: &mut FE)
+                                                -> WasmResult<()> {
+    let (addr, offset) = translate_address(memarg, size, builder, state, environ)?;
+    state.push1(builder.ins().load(ty, MemFlags::new(), addr, offset));
+    Ok(())
+}
+
+fn translate_store<FE: FuncEnvironment + ?Sized>(size: u32,
+                                                 memarg: MemoryImmediate,
+                                                 builder: &mut FunctionBuilder,
+                                                 state: &mut TranslationState,
+                                                 environ: &mut FE)
+                                                 -> WasmResult<()> {
+    let val = state.pop1()?;
+    let (addr, offset) = translate_address(memarg, size, builder, state, environ)?;
+    builder.ins().store(MemFlags::new(), val, addr, offset);
+    Ok(())
+}
+
+// WebAssembly comparisons produce an `i32` which is 0 or 1.
+fn translate_icmp(cc: IntCC,
+                  builder: &mut FunctionBuilder,
+                  state: &mut TranslationState)
+                  -> WasmResult<()> {
+    let (a, b) = state.pop2()?;
+    let cond = builder.ins().icmp(cc, a, b);
+    state.push1(builder.ins().bint(I32, cond));
+    Ok(())
+}
+
+fn translate_fcmp(cc: FloatCC,
+                  builder: &mut FunctionBuilder,
+                  state: &mut TranslationState)
+                  -> WasmResult<()> {
+    let (a, b) = state.pop2()?;
+    let cond = builder.ins().fcmp(cc, a, b);
+    state.push1(builder.ins().bint(I32, cond));
+    Ok(())
+}
+
+// Sign-extend the low `from` bits of the top value to `to`.
+fn translate_sign_extend(to: ir::Type,
+                         from: ir::Type,
+                         builder: &mut FunctionBuilder,
+                         state: &mut TranslationState)
+                         -> WasmResult<()> {
+    let arg = state.pop1()?;
+    let narrow = builder.ins().ireduce(from, arg);
+    state.push1(builder.ins().sextend(to, narrow));
+    Ok(())
+}
+
+fn translate_bitcast(ty: ir::Type,
+                     builder: &mut FunctionBuilder,
+                     state: &mut TranslationState)
+                     -> WasmResult<()> {
+    let arg = state.pop1()?;
+    state.push1(builder.ins().bitcast(ty, arg));
+    Ok(())
+}
@@ -0,0 +1,314 @@
+//! A minimal translation environment for testing.
+
+use cretonne::ir::{self, InstBuilder, FunctionName, ExtFuncData, GlobalVarData, MemFlags,
+                   TableData, ArgumentType, ArgumentPurpose, types};
+use cretonne::settings;
+use cton_frontend::FunctionBuilder;
+use environ::{FuncEnvironment, ModuleEnvironment, GlobalValue};
+use func_translator::FuncTranslator;
+use translation_utils::{FunctionIndex, GlobalIndex, MemoryIndex, SignatureIndex, TableIndex,
+                        Global, GlobalInit, Memory, Table, WasmResult};
+use std::collections::HashMap;
+
+/// The name of the function `index` as seen by the Cretonne IL.
+fn get_func_name(index: FunctionIndex) -> FunctionName {
+    FunctionName::new(format!("wasm_{}", index))
+}
+
+/// The module declarations collected by the `DummyEnvironment`.
+pub struct DummyModuleInfo {
+    /// Compilation settings.
+    pub flags: settings::Flags,
+
+    /// Pass a `vmctx` argument to the functions, and keep the mutable globals in the instance
+    /// data it points to instead of in thread-local variables.
+    pub use_vmctx: bool,
+
+    /// The signatures from the type section.
+    pub signatures: Vec<ir::Signature>,
+
+    /// Module and field names of the imported functions.
+    pub imported_funcs: Vec<(String, String)>,
+
+    /// The signature of each function, imported or defined.
+    pub functions: Vec<SignatureIndex>,
+
+    /// The translated functions defined in the module.
+    pub function_bodies: Vec<ir::Function>,
+
+    /// The global variables.
+    pub globals: Vec<Global>,
+
+    /// The linear memories.
+    pub memories: Vec<Memory>,
+
+    /// The tables.
+    pub tables: Vec<Table>,
+
+    /// The exported functions and their names.
+    pub exports: Vec<(FunctionIndex, String)>,
+
+    /// The start function.
+    pub start_func: Option<FunctionIndex>,
+}
+
+impl DummyModuleInfo {
+    /// Create an empty module description.
+    pub fn with_flags(flags: settings::Flags) -> DummyModuleInfo {
+        DummyModuleInfo {
+            flags: flags,
+            use_vmctx: false,
+            signatures: Vec::new(),
+            imported_funcs: Vec::new(),
+            functions: Vec::new(),
+            function_bodies: Vec::new(),
+            globals: Vec::new(),
+            memories: Vec::new(),
+            tables: Vec::new(),
+            exports: Vec::new(),
+            start_func: None,
+        }
+    }
+
+    /// Get the type of native pointers on the target.
+    pub fn native_pointer(&self) -> ir::Type {
+        if self.flags.is_64bit() {
+            types::I64
+        } else {
+            types::I32
+        }
+    }
+}
+
+/// A `ModuleEnvironment` that translates the function bodies with a `DummyFuncEnvironment`.
+///
+/// This environment is used by the test tools to translate WebAssembly modules without a real
+/// runtime.
+pub struct DummyEnvironment {
+    /// The module declarations and the translated functions.
+    pub info: DummyModuleInfo,
+
+    trans: FuncTranslator,
+}
+
+impl DummyEnvironment {
+    /// Create a new environment with default settings.
+    pub fn new() -> DummyEnvironment {
+        DummyEnvironment::with_flags(settings::Flags::new(&settings::builder()))
+    }
+
+    /// Create a new environment with the given settings.
+    pub fn with_flags(flags: settings::Flags) -> DummyEnvironment {
+        DummyEnvironment {
+            info: DummyModuleInfo::with_flags(flags),
+            trans: FuncTranslator::new(),
+        }
+    }
+
+    /// Create a new environment with the given settings, where the globals are accessed through
+    /// a `vmctx` argument.
+    pub fn with_vmctx(flags: settings::Flags) -> DummyEnvironment {
+        let mut env = DummyEnvironment::with_flags(flags);
+        env.info.use_vmctx = true;
+        env
+    }
+}
+
+/// A `FuncEnvironment` for the functions in a `DummyModuleInfo`.
+///
+/// - Immutable globals with a constant initializer are translated to constants. The other globals
+///   are accessed through thread-local variables named after the global index, or they are
+///   stored 8 bytes apart in the instance data when the module uses a `vmctx` argument.
+/// - Memory is accessed with `heap_addr` instructions.
+/// - The memory size is the initial size of the memory, and it can't grow.
+/// - Tables are accessed with `table_addr` instructions. The base address of a table is held in
+///   a thread-local variable named after the table index. A table element is a function pointer
+///   followed by a 32-bit signature id, padded to two pointers. The tables have their initial
+///   size.
+pub struct DummyFuncEnvironment<'a> {
+    /// The module the translated function belongs to.
+    pub mod_info: &'a DummyModuleInfo,
+
+    // The tables declared in the function.
+    tables: HashMap<TableIndex, ir::Table>,
+}
+
+impl<'a> DummyFuncEnvironment<'a> {
+    /// Create a function environment for translating functions in `mod_info`.
+    pub fn new(mod_info: &'a DummyModuleInfo) -> DummyFuncEnvironment<'a> {
+        DummyFuncEnvironment {
+            mod_info: mod_info,
+            tables: HashMap::new(),
+        }
+    }
+
+    // Get the table declared in `func` for the table `index`.
+    fn table(&mut self, func: &mut ir::Function, index: TableIndex) -> ir::Table {
+        if let Some(&table) = self.tables.get(&index) {
+            return table;
+        }
+        let base = declare_tls(func, format!("wasm_table_{}", index));
+        let table = func.tables
+            .push(TableData {
+                      base: base,
+                      bound: self.mod_info.tables[index].size as u64,
+                      element_size: 2 * self.native_pointer().bytes() as u64,
+                  });
+        self.tables.insert(index, table);
+        table
+    }
+}
+
+// Declare a thread-local variable named `name` in `func`.
+fn declare_tls(func: &mut ir::Function, name: String) -> ir::GlobalVar {
+    func.global_vars
+        .push(GlobalVarData::new(FunctionName::new(name), true))
+}
+
+impl<'a> FuncEnvironment for DummyFuncEnvironment<'a> {
+    fn native_pointer(&self) -> ir::Type {
+        self.mod_info.native_pointer()
+    }
+
+    fn make_direct_func(&mut self, func: &mut ir::Function, index: FunctionIndex) -> ir::FuncRef {
+        let sig_index = self.mod_info.functions[index];
+        let signature = func.dfg
+            .signatures
+            .push(self.mod_info.signatures[sig_index].clone());
+        func.dfg
+            .ext_funcs
+            .push(ExtFuncData {
+                      name: get_func_name(index),
+                      signature: signature,
+                      colocated: false,
+                  })
+    }
+
+    fn make_indirect_sig(&mut self, func: &mut ir::Function, index: SignatureIndex) -> ir::SigRef {
+        func.dfg
+            .signatures
+            .push(self.mod_info.signatures[index].clone())
+    }
+
+    fn translate_table_entry(&mut self,
+                             builder: &mut FunctionBuilder,
+                             table: TableIndex,
+                             index: ir::Value)
+                             -> (ir::Value, ir::Value) {
+        let pointer_type = self.native_pointer();
+        let pointer_bytes = pointer_type.bytes() as i32;
+        let table = self.table(builder.func, table);
+        let entry = builder.ins().table_addr(pointer_type, table, index, 0);
+        let callee = builder.ins().load(pointer_type, MemFlags::new(), entry, 0);
+        let sig_id = builder
+            .ins()
+            .load(types::I32, MemFlags::new(), entry, pointer_bytes);
+        (callee, sig_id)
+    }
+
+    fn make_global(&mut self, func: &mut ir::Function, index: GlobalIndex) -> GlobalValue {
+        let global = self.mod_info.globals[index];
+        if !global.mutability {
+            let bits = match global.initializer {
+                GlobalInit::I32Const(x) => Some(x as u32 as u64),
+                GlobalInit::I64Const(x) => Some(x as u64),
+                GlobalInit::F32Const(x) => Some(x as u64),
+                GlobalInit::F64Const(x) => Some(x),
+                GlobalInit::GlobalRef(_) |
+                GlobalInit::Import => None,
+            };
+            if let Some(bits) = bits {
+                return GlobalValue::Const {
+                           ty: global.ty,
+                           bits: bits,
+                       };
+            }
+        }
+        if self.mod_info.use_vmctx {
+            GlobalValue::VmCtx {
+                offset: index as i32 * 8,
+                ty: global.ty,
+            }
+        } else {
+            GlobalValue::Var {
+                gv: declare_tls(func, format!("wasm_global_{}", index)),
+                ty: global.ty,
+            }
+        }
+    }
+
+    fn translate_current_memory(&mut self,
+                                builder: &mut FunctionBuilder,
+                                memory: MemoryIndex)
+                                -> ir::Value {
+        let pages = self.mod_info.memories[memory].pages_count;
+        builder.ins().iconst(types::I32, pages as i64)
+    }
+
+    fn translate_grow_memory(&mut self,
+                             builder: &mut FunctionBuilder,
+                             _memory: MemoryIndex,
+                             _delta: ir::Value)
+                             -> ir::Value {
+        builder.ins().iconst(types::I32, -1)
+    }
+}
+
+impl ModuleEnvironment for DummyEnvironment {
+    fn declare_signature(&mut self, sig: &ir::Signature) {
+        let mut sig = sig.clone();
+        if self.info.use_vmctx {
+            let mut vmctx = ArgumentType::new(self.info.native_pointer());
+            vmctx.purpose = ArgumentPurpose::VMContext;
+            sig.argument_types.push(vmctx);
+        }
+        self.info.signatures.push(sig);
+    }
+
+    fn declare_func_import(&mut self, sig_index: SignatureIndex, module: &str, field: &str) {
+        assert_eq!(self.info.functions.len(),
+                   self.info.imported_funcs.len(),
+                   "Imported functions must be declared first");
+        self.info.functions.push(sig_index);
+        self.info
+            .imported_funcs
+            .push((module.to_string(), field.to_string()));
+    }
+
+    fn declare_func_type(&mut self, sig_index: SignatureIndex) {
+        self.info.functions.push(sig_index);
+    }
+
+    fn declare_global(&mut self, global: Global) {
+        self.info.globals.push(global);
+    }
+
+    fn declare_memory(&mut self, memory: Memory) {
+        self.info.memories.push(memory);
+    }
+
+    fn declare_table(&mut self, table: Table) {
+        self.info.tables.push(table);
+    }
+
+    fn declare_func_export(&mut self, func_index: FunctionIndex, name: &str) {
+        self.info.exports.push((func_index, name.to_string()));
+    }
+
+    fn declare_start_func(&mut self, func_index: FunctionIndex) {
+        self.info.start_func = Some(func_index);
+    }
+
+    fn define_function_body(&mut self, body_bytes: &[u8]) -> WasmResult<()> {
+        let func_index = self.info.imported_funcs.len() + self.info.function_bodies.len();
+        let sig = self.info.signatures[self.info.functions[func_index]].clone();
+        let mut func = ir::Function::with_name_signature(get_func_name(func_index), sig);
+        {
+            let mut func_environ = DummyFuncEnvironment::new(&self.info);
+            self.trans
+                .translate(body_bytes, &mut func, &mut func_environ)?;
+        }
+        self.info.function_bodies.push(func);
+        Ok(())
+    }
+}
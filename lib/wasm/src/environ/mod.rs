@@ -0,0 +1,7 @@
+//! Support for configurable translation environments.
+
+mod dummy;
+mod spec;
+
+pub use self::dummy::{DummyEnvironment, DummyModuleInfo, DummyFuncEnvironment};
+pub use self::spec::{FuncEnvironment, ModuleEnvironment, GlobalValue};
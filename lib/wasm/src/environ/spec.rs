@@ -0,0 +1,156 @@
+//! The traits implemented by the embedder to control the translation.
+//!
+//! The WebAssembly specification leaves the layout of instances to the runtime. The translator
+//! asks the environment how to access memories and globals, and how to call other functions.
+
+use cretonne::ir::{self, InstBuilder};
+use cton_frontend::FunctionBuilder;
+use translation_utils::{FunctionIndex, GlobalIndex, MemoryIndex, SignatureIndex, TableIndex,
+                        Global, Memory, Table, WasmResult};
+
+/// The location of a WebAssembly global variable, chosen by the environment.
+#[derive(Debug, Clone, Copy)]
+pub enum GlobalValue {
+    /// The global is immutable, and its value is the constant of type `ty` with the bit pattern
+    /// `bits`.
+    Const {
+        /// The type of the global.
+        ty: ir::Type,
+        /// The bits of the value, zero-extended to 64 bits.
+        bits: u64,
+    },
+
+    /// The global is stored in memory at the address of the thread-local variable `gv`.
+    Var {
+        /// The thread-local variable holding the global. Its address is computed with
+        /// `tls_value`.
+        gv: ir::GlobalVar,
+        /// The type of the global.
+        ty: ir::Type,
+    },
+
+    /// The global is stored in memory at `offset` bytes from the VM context pointer, which is the
+    /// `vmctx` argument of the function.
+    VmCtx {
+        /// The offset from the VM context pointer.
+        offset: i32,
+        /// The type of the global.
+        ty: ir::Type,
+    },
+}
+
+/// Environment affecting the translation of a single WebAssembly function.
+///
+/// A `FuncEnvironment` provides the information about the module that the function body
+/// translator needs, and it translates the operators whose implementation depends on the runtime.
+pub trait FuncEnvironment {
+    /// Get the type of native pointers on the target. Memory addresses have this type.
+    fn native_pointer(&self) -> ir::Type;
+
+    /// Declare the function `index` in `func` so it can be called directly.
+    ///
+    /// The signature of the returned `FuncRef` determines the arguments and return values of the
+    /// `call` instruction.
+    fn make_direct_func(&mut self, func: &mut ir::Function, index: FunctionIndex) -> ir::FuncRef;
+
+    /// Declare the signature `index` in `func` for indirect calls to functions with that
+    /// signature.
+    fn make_indirect_sig(&mut self, func: &mut ir::Function, index: SignatureIndex) -> ir::SigRef;
+
+    /// Get the signature id stored in the table entries of functions with the signature `index`.
+    ///
+    /// A runtime that shares tables between modules should give structurally equal signatures the
+    /// same id. By default, the id is the index of the signature in the module.
+    fn signature_id(&self, index: SignatureIndex) -> i32 {
+        index as i32
+    }
+
+    /// Load the element `index` of the table `table` for a `call_indirect`, where `index` is an
+    /// `i32` value. The load must trap if `index` is out of bounds.
+    ///
+    /// Return the address of the callee and its signature id, which is an `i32` value. The
+    /// translator checks the signature id before it calls the function.
+    fn translate_table_entry(&mut self,
+                             builder: &mut FunctionBuilder,
+                             table: TableIndex,
+                             index: ir::Value)
+                             -> (ir::Value, ir::Value);
+
+    /// Declare the global `index` in `func` and get its location.
+    ///
+    /// The translator calls this once per function for each global the function accesses.
+    fn make_global(&mut self, func: &mut ir::Function, index: GlobalIndex) -> GlobalValue;
+
+    /// Compute the native address of a `size`-byte access at `index + offset` in the linear
+    /// memory `memory`, where `index` is an `i32` value.
+    ///
+    /// Return the address corresponding to `index`. The caller adds `offset` when it accesses
+    /// the memory. The access must trap if it would be out of bounds.
+    ///
+    /// The default implementation uses a `heap_addr` instruction which is legalized by a
+    /// runtime-specific legalization hook.
+    fn translate_memory_address(&mut self,
+                                builder: &mut FunctionBuilder,
+                                memory: MemoryIndex,
+                                index: ir::Value,
+                                offset: u32,
+                                size: u32)
+                                -> ir::Value {
+        let _ = memory;
+        let pointer_type = self.native_pointer();
+        let index = if pointer_type == ir::types::I32 {
+            index
+        } else {
+            builder.ins().uextend(pointer_type, index)
+        };
+        // An access whose end doesn't fit in 32 bits is out of bounds anyway.
+        builder.ins().heap_addr(index, offset.saturating_add(size))
+    }
+
+    /// Translate a `memory.size` of `memory`, returning the size in pages as an `i32`.
+    fn translate_current_memory(&mut self,
+                                builder: &mut FunctionBuilder,
+                                memory: MemoryIndex)
+                                -> ir::Value;
+
+    /// Translate a `memory.grow` of `memory` by `delta` pages. Return the previous size in pages
+    /// as an `i32`, or -1 if the memory can't grow.
+    fn translate_grow_memory(&mut self,
+                             builder: &mut FunctionBuilder,
+                             memory: MemoryIndex,
+                             delta: ir::Value)
+                             -> ir::Value;
+}
+
+/// Environment receiving the declarations of a WebAssembly module.
+///
+/// The module translator calls these methods in the order the declarations appear in the
+/// module, and `define_function_body()` for each function body in the code section.
+pub trait ModuleEnvironment {
+    /// Declare a function signature from the type section.
+    fn declare_signature(&mut self, sig: &ir::Signature);
+
+    /// Declare an imported function with the signature `sig_index`.
+    fn declare_func_import(&mut self, sig_index: SignatureIndex, module: &str, field: &str);
+
+    /// Declare a function defined in the module with the signature `sig_index`.
+    fn declare_func_type(&mut self, sig_index: SignatureIndex);
+
+    /// Declare a global variable.
+    fn declare_global(&mut self, global: Global);
+
+    /// Declare a linear memory.
+    fn declare_memory(&mut self, memory: Memory);
+
+    /// Declare a table.
+    fn declare_table(&mut self, table: Table);
+
+    /// Declare that the function `func_index` is exported as `name`.
+    fn declare_func_export(&mut self, func_index: FunctionIndex, name: &str);
+
+    /// Declare the start function.
+    fn declare_start_func(&mut self, func_index: FunctionIndex);
+
+    /// Provide the binary code of the next function body defined in the module.
+    fn define_function_body(&mut self, body_bytes: &[u8]) -> WasmResult<()>;
+}
@@ -0,0 +1,212 @@
+//! Translation of WebAssembly function bodies.
+//!
+//! The `FuncTranslator` translates the binary code of a single WebAssembly function into
+//! Cretonne IL, using a `FuncEnvironment` to resolve the runtime-specific parts.
+
+use code_translator::translate_operator;
+use cretonne::entity_map::EntityRef;
+use cretonne::ir::{self, InstBuilder, ArgumentPurpose, types};
+use cretonne::ir::immediates::{Ieee32, Ieee64};
+use cton_frontend::{ILBuilder, FunctionBuilder, Variable};
+use environ::FuncEnvironment;
+use state::TranslationState;
+use translation_utils::{type_to_type, reader_error, WasmResult};
+use wasmparser::FunctionBody;
+
+/// WebAssembly to Cretonne IL function translator.
+///
+/// A `FuncTranslator` is used to translate the function bodies of a WebAssembly module. It
+/// should be reused between functions to avoid repeated memory allocation.
+pub struct FuncTranslator {
+    il_builder: ILBuilder,
+    state: TranslationState,
+}
+
+impl FuncTranslator {
+    /// Create a new translator.
+    pub fn new() -> FuncTranslator {
+        FuncTranslator {
+            il_builder: ILBuilder::new(),
+            state: TranslationState::new(),
+        }
+    }
+
+    /// Translate the binary WebAssembly function body in `code` into `func`.
+    ///
+    /// The `code` is the contents of a code section entry, starting with the local variable
+    /// declarations. The function `func` must be empty except for its name and signature. The
+    /// normal arguments of the signature are the WebAssembly function parameters, and it can have
+    /// a `vmctx` argument for the environment.
+    pub fn translate<FE: FuncEnvironment + ?Sized>(&mut self,
+                                                   code: &[u8],
+                                                   func: &mut ir::Function,
+                                                   environ: &mut FE)
+                                                   -> WasmResult<()> {
+        let body = FunctionBody::new(0, code);
+        let mut builder = FunctionBuilder::new(func, &mut self.il_builder);
+
+        let entry = builder.create_ebb();
+        builder.switch_to_block(entry);
+        builder.seal_block(entry);
+        builder.append_ebb_args_for_function_args(entry);
+        let num_params = declare_params(&mut builder, entry);
+        let vmctx = builder
+            .func
+            .signature
+            .argument_types
+            .iter()
+            .position(|arg| arg.purpose == ArgumentPurpose::VMContext)
+            .map(|idx| builder.ebb_args(entry)[idx]);
+
+        let mut locals = body.get_locals_reader().map_err(reader_error)?;
+        let mut next_local = num_params;
+        for _ in 0..locals.get_count() {
+            let (count, ty) = locals.read().map_err(reader_error)?;
+            let ty = type_to_type(ty)?;
+            for _ in 0..count {
+                declare_local(&mut builder, Variable::new(next_local), ty);
+                next_local += 1;
+            }
+        }
+
+        // The function body is a block whose results are returned.
+        let exit = builder.create_ebb();
+        let return_types: Vec<ir::Type> = builder
+            .func
+            .signature
+            .return_types
+            .iter()
+            .filter(|arg| arg.purpose == ArgumentPurpose::Normal)
+            .map(|arg| arg.value_type)
+            .collect();
+        for &ty in &return_types {
+            builder.append_ebb_arg(exit, ty);
+        }
+        self.state.initialize(exit, return_types.len(), vmctx);
+
+        let mut reader = body.get_operators_reader().map_err(reader_error)?;
+        while !self.state.control_stack.is_empty() {
+            let op = reader.read().map_err(reader_error)?;
+            translate_operator(op, &mut builder, &mut self.state, environ)?;
+        }
+        if !reader.eof() {
+            return Err("unexpected operators after the end of the function body".to_string());
+        }
+
+        // The final `end` left us in the exit EBB with the return values on the stack.
+        if self.state.reachable {
+            builder.ins().return_(&self.state.stack);
+        }
+        builder.finalize();
+        Ok(())
+    }
+}
+
+// Declare the WebAssembly parameters as the first local variables, initialized with the entry EBB
+// arguments. Return the number of parameters.
+fn declare_params(builder: &mut FunctionBuilder, entry: ir::Ebb) -> usize {
+    let params: Vec<(usize, ir::Type)> = builder
+        .func
+        .signature
+        .argument_types
+        .iter()
+        .enumerate()
+        .filter(|&(_, arg)| arg.purpose == ArgumentPurpose::Normal)
+        .map(|(idx, arg)| (idx, arg.value_type))
+        .collect();
+    for (local, &(idx, ty)) in params.iter().enumerate() {
+        let var = Variable::new(local);
+        builder.declare_var(var, ty);
+        let arg = builder.ebb_args(entry)[idx];
+        builder.def_var(var, arg);
+    }
+    params.len()
+}
+
+// Declare a local variable. WebAssembly locals are initialized to zero.
+fn declare_local(builder: &mut FunctionBuilder, var: Variable, ty: ir::Type) {
+    let zero = match ty {
+        types::I32 | types::I64 => builder.ins().iconst(ty, 0),
+        types::F32 => builder.ins().f32const(Ieee32::new(0.0)),
+        types::F64 => builder.ins().f64const(Ieee64::new(0.0)),
+        _ => panic!("unsupported local type {}", ty),
+    };
+    builder.declare_var(var, ty);
+    builder.def_var(var, zero);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FuncTranslator;
+    use cretonne::ir::{Function, FunctionName, Signature, ArgumentType, types};
+    use cretonne::verify_function;
+    use environ::{DummyModuleInfo, DummyFuncEnvironment};
+    use cretonne::settings;
+
+    // Translate a function body with signature `sig`.
+    fn translate(code: &[u8], sig: Signature) -> Function {
+        let info = DummyModuleInfo::with_flags(settings::Flags::new(&settings::builder()));
+        let mut trans = FuncTranslator::new();
+        let mut func = Function::with_name_signature(FunctionName::new("test"), sig);
+        trans
+            .translate(code, &mut func, &mut DummyFuncEnvironment::new(&info))
+            .unwrap();
+        if let Err(err) = verify_function(&func) {
+            panic!("{}\n{}", err, func.display(None));
+        }
+        func
+    }
+
+    #[test]
+    fn small1() {
+        // (func (param i32) (result i32)
+        //   get_local 0
+        //   i32.const 1
+        //   i32.add)
+        let code = [0x00, 0x20, 0x00, 0x41, 0x01, 0x6a, 0x0b];
+        let mut sig = Signature::new();
+        sig.argument_types.push(ArgumentType::new(types::I32));
+        sig.return_types.push(ArgumentType::new(types::I32));
+        let func = translate(&code, sig);
+        assert_eq!(func.layout.ebbs().count(), 2);
+    }
+
+    #[test]
+    fn infloop() {
+        // (func (result i32)
+        //   (local i32)
+        //   (loop
+        //     (set_local 0 (i32.add (get_local 0) (i32.const 1)))
+        //     (br 0))
+        //   unreachable)
+        let code = [0x01, 0x01, 0x7f, 0x03, 0x40, 0x20, 0x00, 0x41, 0x01, 0x6a, 0x21, 0x00,
+                    0x0c, 0x00, 0x0b, 0x00, 0x0b];
+        let mut sig = Signature::new();
+        sig.return_types.push(ArgumentType::new(types::I32));
+        let func = translate(&code, sig);
+
+        // The loop header gets an argument for the local.
+        let header = func.layout.ebbs().nth(1).unwrap();
+        assert_eq!(func.dfg.num_ebb_args(header), 1);
+    }
+
+    #[test]
+    fn malformed() {
+        let translate_err = |code: &[u8]| {
+            let info = DummyModuleInfo::with_flags(settings::Flags::new(&settings::builder()));
+            let mut func = Function::new();
+            FuncTranslator::new()
+                .translate(code, &mut func, &mut DummyFuncEnvironment::new(&info))
+                .unwrap_err()
+        };
+
+        // (func i32.add)
+        assert_eq!(translate_err(&[0x00, 0x6a, 0x0b]),
+                   "WebAssembly value stack underflow");
+        // (func else end)
+        assert_eq!(translate_err(&[0x00, 0x05, 0x0b]), "else without an if");
+        // (func br 1)
+        assert_eq!(translate_err(&[0x00, 0x0c, 0x01, 0x0b]),
+                   "branch depth 1 is out of range");
+    }
+}
@@ -0,0 +1,33 @@
+//! Cretonne WebAssembly translator.
+//!
+//! This crate translates WebAssembly modules in binary format to Cretonne IL. The function bodies
+//! are translated with the `FuncTranslator`, which relies on a `FuncEnvironment` for the parts
+//! of the translation that depend on the runtime, such as the layout of globals and linear
+//! memories.
+//!
+//! The `translate_module()` function parses a whole module and passes its declarations to a
+//! `ModuleEnvironment`. The `DummyEnvironment` is a minimal implementation for testing the
+//! translator without a runtime.
+
+#![deny(missing_docs)]
+
+extern crate wasmparser;
+extern crate cretonne;
+extern crate cton_frontend;
+
+#[cfg(test)]
+extern crate wat;
+
+pub use environ::{FuncEnvironment, ModuleEnvironment, GlobalValue, DummyEnvironment,
+                  DummyModuleInfo, DummyFuncEnvironment};
+pub use func_translator::FuncTranslator;
+pub use module_translator::translate_module;
+pub use translation_utils::{FunctionIndex, GlobalIndex, MemoryIndex, SignatureIndex, TableIndex,
+                            Global, GlobalInit, Memory, Table, WasmResult};
+
+mod code_translator;
+mod environ;
+mod func_translator;
+mod module_translator;
+mod state;
+mod translation_utils;
@@ -0,0 +1,260 @@
+//! Translation of whole WebAssembly modules.
+//!
+//! The module translator parses the sections of a WebAssembly module, passes the declarations to
+//! a `ModuleEnvironment`, and hands it the function bodies to translate.
+
+use cretonne::ir::{Signature, ArgumentType};
+use environ::ModuleEnvironment;
+use translation_utils::{type_to_type, reader_error, Global, GlobalInit, Memory, Table,
+                        WasmResult};
+use wasmparser::{Parser, Payload, TypeDef, ImportSectionEntryType, ExternalKind, Operator,
+                 GlobalType, MemoryType, TableType};
+
+/// Translate the WebAssembly module in `data`.
+///
+/// The declarations of the module are passed to `environ` in the order they appear, followed by
+/// the function bodies. Data segments and element segments are ignored.
+pub fn translate_module(data: &[u8], environ: &mut ModuleEnvironment) -> WasmResult<()> {
+    for payload in Parser::new(0).parse_all(data) {
+        match payload.map_err(reader_error)? {
+            Payload::TypeSection(types) => {
+                for ty in types {
+                    match ty.map_err(reader_error)? {
+                        TypeDef::Func(func_type) => {
+                            let mut sig = Signature::new();
+                            for &ty in func_type.params.iter() {
+                                sig.argument_types
+                                    .push(ArgumentType::new(type_to_type(ty)?));
+                            }
+                            for &ty in func_type.returns.iter() {
+                                sig.return_types.push(ArgumentType::new(type_to_type(ty)?));
+                            }
+                            environ.declare_signature(&sig);
+                        }
+                        _ => return Err("only function types are supported".to_string()),
+                    }
+                }
+            }
+            Payload::ImportSection(imports) => {
+                for import in imports {
+                    let import = import.map_err(reader_error)?;
+                    match import.ty {
+                        ImportSectionEntryType::Function(sig_index) => {
+                            environ.declare_func_import(sig_index as usize,
+                                                        import.module,
+                                                        import.field.unwrap_or(""));
+                        }
+                        ImportSectionEntryType::Memory(memory) => {
+                            environ.declare_memory(translate_memory(memory));
+                        }
+                        ImportSectionEntryType::Global(ty) => {
+                            environ.declare_global(translate_global(ty, GlobalInit::Import)?);
+                        }
+                        ImportSectionEntryType::Table(table) => {
+                            environ.declare_table(translate_table(table));
+                        }
+                        _ => return Err("unsupported import kind".to_string()),
+                    }
+                }
+            }
+            Payload::FunctionSection(functions) => {
+                for sig_index in functions {
+                    environ.declare_func_type(sig_index.map_err(reader_error)? as usize);
+                }
+            }
+            Payload::TableSection(tables) => {
+                for table in tables {
+                    environ.declare_table(translate_table(table.map_err(reader_error)?));
+                }
+            }
+            Payload::MemorySection(memories) => {
+                for memory in memories {
+                    environ.declare_memory(translate_memory(memory.map_err(reader_error)?));
+                }
+            }
+            Payload::GlobalSection(globals) => {
+                for global in globals {
+                    let global = global.map_err(reader_error)?;
+                    let mut init = global.init_expr.get_operators_reader();
+                    let initializer = match init.read().map_err(reader_error)? {
+                        Operator::I32Const { value } => GlobalInit::I32Const(value),
+                        Operator::I64Const { value } => GlobalInit::I64Const(value),
+                        Operator::F32Const { value } => GlobalInit::F32Const(value.bits()),
+                        Operator::F64Const { value } => GlobalInit::F64Const(value.bits()),
+                        Operator::GlobalGet { global_index } => {
+                            GlobalInit::GlobalRef(global_index as usize)
+                        }
+                        op => return Err(format!("unsupported global initializer {:?}", op)),
+                    };
+                    environ.declare_global(translate_global(global.ty, initializer)?);
+                }
+            }
+            Payload::ExportSection(exports) => {
+                for export in exports {
+                    let export = export.map_err(reader_error)?;
+                    if let ExternalKind::Function = export.kind {
+                        environ.declare_func_export(export.index as usize, export.field);
+                    }
+                }
+            }
+            Payload::StartSection { func, .. } => environ.declare_start_func(func as usize),
+            Payload::CodeSectionEntry(body) => {
+                let range = body.range();
+                environ.define_function_body(&data[range.start..range.end])?;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn translate_memory(memory: MemoryType) -> Memory {
+    Memory {
+        pages_count: memory.initial as usize,
+        maximum: memory.maximum.map(|max| max as usize),
+    }
+}
+
+fn translate_table(table: TableType) -> Table {
+    Table {
+        size: table.initial as usize,
+        maximum: table.maximum.map(|max| max as usize),
+    }
+}
+
+fn translate_global(ty: GlobalType, initializer: GlobalInit) -> WasmResult<Global> {
+    Ok(Global {
+           ty: type_to_type(ty.content_type)?,
+           mutability: ty.mutable,
+           initializer: initializer,
+       })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::translate_module;
+    use cretonne::settings::{self, Configurable};
+    use cretonne::verify_function;
+    use environ::DummyEnvironment;
+    use wat;
+
+    // Translate the module in WebAssembly text format and verify its functions.
+    fn translate(text: &str) -> DummyEnvironment {
+        translate_with(text, DummyEnvironment::new())
+    }
+
+    fn translate_with(text: &str, mut environ: DummyEnvironment) -> DummyEnvironment {
+        let data = wat::parse_str(text).unwrap();
+        translate_module(&data, &mut environ).unwrap();
+        for func in &environ.info.function_bodies {
+            if let Err(err) = verify_function(func) {
+                panic!("{}\n{}", err, func.display(None));
+            }
+        }
+        environ
+    }
+
+    #[test]
+    fn factorial() {
+        let environ = translate(r#"
+            (module
+              (func (export "fac") (param i64) (result i64)
+                (local i64)
+                (local.set 1 (i64.const 1))
+                (block
+                  (loop
+                    (br_if 1 (i64.eqz (local.get 0)))
+                    (local.set 1 (i64.mul (local.get 1) (local.get 0)))
+                    (local.set 0 (i64.sub (local.get 0) (i64.const 1)))
+                    (br 0)))
+                (local.get 1)))"#);
+        assert_eq!(environ.info.exports, [(0, "fac".to_string())]);
+        assert_eq!(environ.info.function_bodies[0].name.to_string(), "wasm_0");
+    }
+
+    #[test]
+    fn control_flow() {
+        translate(r#"
+            (module
+              (func (param i32) (result i32)
+                (if (result i32) (local.get 0)
+                  (then (i32.const 1))
+                  (else (i32.const 2))))
+              (func (param i32) (result i32)
+                (block (result i32)
+                  (drop
+                    (block (result i32)
+                      (br_table 0 1 0 (i32.const 10) (local.get 0))))
+                  (i32.const 30)))
+              (func (param i32) (result i32)
+                (block
+                  (block
+                    (br_table 0 1 0 (local.get 0)))
+                  (return (i32.const 1)))
+                (if (local.get 0) (then (unreachable)))
+                (i32.const 0)))"#);
+    }
+
+    #[test]
+    fn memory_and_globals() {
+        let environ = translate(r#"
+            (module
+              (import "env" "print" (func $print (param i32)))
+              (memory 1)
+              (global $g (mut i32) (i32.const 0))
+              (global $k i64 (i64.const 42))
+              (func $f (param i32) (result i64)
+                (i32.store offset=4 (local.get 0) (i32.load8_u (local.get 0)))
+                (global.set $g (i32.add (global.get $g) (memory.size)))
+                (call $print (global.get $g))
+                (i64.add (global.get $k) (i64.load32_s offset=8 (local.get 0))))
+              (start 2)
+              (func (drop (call $f (i32.const 0)))))"#);
+        assert_eq!(environ.info.imported_funcs,
+                   [("env".to_string(), "print".to_string())]);
+        assert_eq!(environ.info.function_bodies.len(), 2);
+        assert_eq!(environ.info.start_func, Some(2));
+        assert_eq!(environ.info.memories[0].pages_count, 1);
+    }
+
+    #[test]
+    fn call_indirect() {
+        let environ = translate(r#"
+            (module
+              (type $binop (func (param i32 i32) (result i32)))
+              (table 2 funcref)
+              (func (param i32) (result i32)
+                (call_indirect (type $binop) (i32.const 1) (i32.const 2) (local.get 0))))"#);
+        assert_eq!(environ.info.tables[0].size, 2);
+
+        let text = environ.info.function_bodies[0].display(None).to_string();
+        assert!(text.contains("icmp_imm ne"), "{}", text);
+        assert!(text.contains("trapnz"), "{}", text);
+        assert!(text.contains("call_indirect sig0"), "{}", text);
+    }
+
+    #[test]
+    fn vmctx_globals() {
+        let mut flag_builder = settings::builder();
+        flag_builder.set("is_64bit", "1").unwrap();
+        let flags = settings::Flags::new(&flag_builder);
+        let environ = translate_with(r#"
+            (module
+              (global $g (mut i64) (i64.const 0))
+              (global $h (mut i64) (i64.const 0))
+              (func $inc (param i64)
+                (global.set $h (i64.add (global.get $g) (local.get 0))))
+              (func (call $inc (i64.const 1))))"#,
+                                     DummyEnvironment::with_vmctx(flags));
+
+        let text = environ.info.function_bodies[0].display(None).to_string();
+        assert!(text.contains("(i64, i64 vmctx)"), "{}", text);
+        assert!(text.contains("load.i64 v1"), "{}", text);
+        assert!(text.contains("store v3, v1+8"), "{}", text);
+        assert!(!text.contains("tls_value"), "{}", text);
+
+        // The vmctx argument is passed on to the callee.
+        let text = environ.info.function_bodies[1].display(None).to_string();
+        assert!(text.contains("call fn0(v1, v0)"), "{}", text);
+    }
+}
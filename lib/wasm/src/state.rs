@@ -0,0 +1,185 @@
+//! The state of a WebAssembly function body translation.
+//!
+//! WebAssembly operators work on an implicit value stack, and the structured control flow is
+//! described by a stack of nested blocks. The translator keeps track of both while it generates
+//! Cretonne IL.
+
+use cretonne::ir::{Ebb, Value, FuncRef, SigRef};
+use std::collections::HashMap;
+use environ::GlobalValue;
+use translation_utils::{FunctionIndex, GlobalIndex, SignatureIndex, WasmResult};
+
+/// The kind of a control stack frame.
+#[derive(Debug, Clone, Copy)]
+pub enum ControlKind {
+    /// A `block`, or the function body itself.
+    Block,
+
+    /// A `loop`. Branches to a loop go to its header.
+    Loop {
+        /// The first EBB of the loop body.
+        header: Ebb,
+    },
+
+    /// An `if` with an optional `else`.
+    If {
+        /// The EBB the `if` branches to when its condition is false.
+        else_ebb: Ebb,
+        /// Has the `else` operator been seen?
+        has_else: bool,
+    },
+}
+
+/// A frame on the control stack.
+#[derive(Debug)]
+pub struct ControlFrame {
+    /// The kind of frame.
+    pub kind: ControlKind,
+
+    /// The EBB following the `end` of the frame. It takes the results of the frame as its
+    /// arguments.
+    pub destination: Ebb,
+
+    /// The number of results of the frame.
+    pub num_results: usize,
+
+    /// The height of the value stack when the frame was entered.
+    pub stack_size: usize,
+
+    /// Has a branch to the destination EBB been generated?
+    pub reachable_end: bool,
+}
+
+impl ControlFrame {
+    /// Get the EBB a branch to this frame goes to, and the number of values it passes.
+    pub fn branch_target(&self) -> (Ebb, usize) {
+        match self.kind {
+            ControlKind::Loop { header } => (header, 0),
+            _ => (self.destination, self.num_results),
+        }
+    }
+}
+
+/// The state of the translation of a function body.
+///
+/// The data structures are reused between functions.
+pub struct TranslationState {
+    /// The WebAssembly value stack.
+    pub stack: Vec<Value>,
+
+    /// The control stack. The bottom frame is the function body.
+    pub control_stack: Vec<ControlFrame>,
+
+    /// Is the current position reachable? Unreachable code is skipped.
+    pub reachable: bool,
+
+    /// The number of nested blocks entered in unreachable code.
+    pub unreachable_depth: usize,
+
+    /// The functions declared in the current function for direct calls.
+    pub direct_funcs: HashMap<FunctionIndex, FuncRef>,
+
+    /// The signatures declared in the current function for indirect calls.
+    pub indirect_sigs: HashMap<SignatureIndex, SigRef>,
+
+    /// The locations of the globals accessed by the current function.
+    pub globals: HashMap<GlobalIndex, GlobalValue>,
+
+    /// The `vmctx` argument of the current function, if it has one.
+    pub vmctx: Option<Value>,
+}
+
+impl TranslationState {
+    /// Create a new, empty translation state.
+    pub fn new() -> TranslationState {
+        TranslationState {
+            stack: Vec::new(),
+            control_stack: Vec::new(),
+            reachable: true,
+            unreachable_depth: 0,
+            direct_funcs: HashMap::new(),
+            indirect_sigs: HashMap::new(),
+            globals: HashMap::new(),
+            vmctx: None,
+        }
+    }
+
+    /// Reset the state for translating a function body whose return values go to `exit`, and
+    /// whose `vmctx` argument is `vmctx`.
+    pub fn initialize(&mut self, exit: Ebb, num_results: usize, vmctx: Option<Value>) {
+        self.stack.clear();
+        self.control_stack.clear();
+        self.reachable = true;
+        self.unreachable_depth = 0;
+        self.direct_funcs.clear();
+        self.indirect_sigs.clear();
+        self.globals.clear();
+        self.vmctx = vmctx;
+        self.push_frame(ControlKind::Block, exit, num_results);
+    }
+
+    /// Push a new control frame.
+    pub fn push_frame(&mut self, kind: ControlKind, destination: Ebb, num_results: usize) {
+        self.control_stack
+            .push(ControlFrame {
+                      kind: kind,
+                      destination: destination,
+                      num_results: num_results,
+                      stack_size: self.stack.len(),
+                      reachable_end: false,
+                  });
+    }
+
+    /// Get the control frame `depth` levels up from the innermost one.
+    pub fn frame_mut(&mut self, depth: u32) -> WasmResult<&mut ControlFrame> {
+        let len = self.control_stack.len();
+        if depth as usize >= len {
+            return Err(format!("branch depth {} is out of range", depth));
+        }
+        Ok(&mut self.control_stack[len - 1 - depth as usize])
+    }
+
+    /// Push a value on the value stack.
+    pub fn push1(&mut self, val: Value) {
+        self.stack.push(val);
+    }
+
+    /// Pop a value from the value stack.
+    pub fn pop1(&mut self) -> WasmResult<Value> {
+        self.stack
+            .pop()
+            .ok_or_else(|| "WebAssembly value stack underflow".to_string())
+    }
+
+    /// Pop two values from the value stack. Return them in the order they were pushed.
+    pub fn pop2(&mut self) -> WasmResult<(Value, Value)> {
+        let b = self.pop1()?;
+        let a = self.pop1()?;
+        Ok((a, b))
+    }
+
+    /// Pop three values from the value stack. Return them in the order they were pushed.
+    pub fn pop3(&mut self) -> WasmResult<(Value, Value, Value)> {
+        let c = self.pop1()?;
+        let (a, b) = self.pop2()?;
+        Ok((a, b, c))
+    }
+
+    /// Get the top `n` values on the value stack without popping them.
+    pub fn peekn(&self, n: usize) -> WasmResult<&[Value]> {
+        let len = self.stack.len();
+        if n > len {
+            return Err("WebAssembly value stack underflow".to_string());
+        }
+        Ok(&self.stack[len - n..])
+    }
+
+    /// Pop the top `n` values from the value stack.
+    pub fn popn(&mut self, n: usize) -> WasmResult<Vec<Value>> {
+        let len = self.stack.len();
+        if n > len {
+            return Err("WebAssembly value stack underflow".to_string());
+        }
+        Ok(self.stack.split_off(len - n))
+    }
+}
@@ -0,0 +1,93 @@
+//! Helper types and functions for the WebAssembly translation.
+
+use cretonne::ir::{self, types};
+use wasmparser::{self, BinaryReaderError, TypeOrFuncType};
+
+/// The result of a WebAssembly translation. Errors are reported as messages.
+pub type WasmResult<T> = Result<T, String>;
+
+/// Index of a function, imported or defined, in the WebAssembly module.
+pub type FunctionIndex = usize;
+
+/// Index of a global variable in the WebAssembly module.
+pub type GlobalIndex = usize;
+
+/// Index of a linear memory in the WebAssembly module.
+pub type MemoryIndex = usize;
+
+/// Index of a table in the WebAssembly module.
+pub type TableIndex = usize;
+
+/// Index of a function signature in the type section of the WebAssembly module.
+pub type SignatureIndex = usize;
+
+/// A WebAssembly global variable.
+#[derive(Debug, Clone, Copy)]
+pub struct Global {
+    /// The Cretonne type of the global value.
+    pub ty: ir::Type,
+    /// Can the global be modified with `global.set`?
+    pub mutability: bool,
+    /// The initial value of the global.
+    pub initializer: GlobalInit,
+}
+
+/// The initial value of a WebAssembly global variable.
+#[derive(Debug, Clone, Copy)]
+pub enum GlobalInit {
+    /// An `i32.const` initializer.
+    I32Const(i32),
+    /// An `i64.const` initializer.
+    I64Const(i64),
+    /// An `f32.const` initializer, given as its bit pattern.
+    F32Const(u32),
+    /// An `f64.const` initializer, given as its bit pattern.
+    F64Const(u64),
+    /// The value of an imported global.
+    GlobalRef(GlobalIndex),
+    /// The global is imported, and its value is provided by the embedder.
+    Import,
+}
+
+/// A WebAssembly linear memory.
+#[derive(Debug, Clone, Copy)]
+pub struct Memory {
+    /// The initial size of the memory in 64 KB pages.
+    pub pages_count: usize,
+    /// The maximum size of the memory in pages, if any.
+    pub maximum: Option<usize>,
+}
+
+/// A WebAssembly table of functions for `call_indirect`.
+#[derive(Debug, Clone, Copy)]
+pub struct Table {
+    /// The initial number of elements in the table.
+    pub size: usize,
+    /// The maximum number of elements in the table, if any.
+    pub maximum: Option<usize>,
+}
+
+/// Convert a WebAssembly value type to the corresponding Cretonne type.
+pub fn type_to_type(ty: wasmparser::Type) -> WasmResult<ir::Type> {
+    match ty {
+        wasmparser::Type::I32 => Ok(types::I32),
+        wasmparser::Type::I64 => Ok(types::I64),
+        wasmparser::Type::F32 => Ok(types::F32),
+        wasmparser::Type::F64 => Ok(types::F64),
+        _ => Err(format!("unsupported value type {:?}", ty)),
+    }
+}
+
+/// Get the result types of a block, loop, or if with type `ty`.
+pub fn block_results(ty: TypeOrFuncType) -> WasmResult<Vec<ir::Type>> {
+    match ty {
+        TypeOrFuncType::Type(wasmparser::Type::EmptyBlockType) => Ok(Vec::new()),
+        TypeOrFuncType::Type(ty) => Ok(vec![type_to_type(ty)?]),
+        TypeOrFuncType::FuncType(_) => Err("multi-value blocks are not supported".to_string()),
+    }
+}
+
+/// Convert a `wasmparser` error to an error message.
+pub fn reader_error(err: BinaryReaderError) -> String {
+    format!("{} at offset {}", err.message(), err.offset())
+}
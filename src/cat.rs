@@ -1,39 +1,99 @@
 //! The `cat` sub-command.
 //!
 //! Read a sequence of Cretonne IL files and print them again to stdout. This has the effect of
-//! normalizing formatting and removing comments.
+//! normalizing formatting and removing comments. The output begins with a `version` line.
+//!
+//! The instruction encodings and value locations in the input are removed unless the
+//! `--with-encodings` or `--with-locations` options are given, so a plain `cat` prints
+//! ISA-independent IL. The `--cfg` option adds a comment
+//! after each EBB header listing the predecessors and successors of the EBB. Encodings and
+//! locations are printed with ISA-specific names, so they require a file with a unique ISA.
 
 use std::borrow::Cow;
+use std::fmt;
+use cretonne::flowgraph::ControlFlowGraph;
 use cretonne::ir::Function;
-use cton_reader::{parse_functions, TestCommand};
+use cretonne::isa::TargetIsa;
+use cretonne::{write_function, write_function_with_cfg, write_version};
+use cton_reader::{parse_test, TestCommand};
 use CommandResult;
 use utils::read_to_string;
 use filetest::subtest::{self, SubTest, Context, Result as STResult};
 
-pub fn run(files: Vec<String>) -> CommandResult {
+/// Which annotations to print with the functions.
+pub struct CatOptions {
+    /// Print the instruction encodings.
+    pub encodings: bool,
+    /// Print the value locations of instruction results.
+    pub locations: bool,
+    /// Print the predecessors and successors of each EBB.
+    pub cfg: bool,
+}
+
+pub fn run(files: Vec<String>, opts: CatOptions) -> CommandResult {
+    let mut version = String::new();
+    write_version(&mut version).map_err(|e| e.to_string())?;
+    print!("{}", version);
     for (i, f) in files.into_iter().enumerate() {
         if i != 0 {
             println!("");
         }
-        cat_one(f)?
+        cat_one(f, &opts)?
     }
     Ok(())
 }
 
-fn cat_one(filename: String) -> CommandResult {
+fn cat_one(filename: String, opts: &CatOptions) -> CommandResult {
     let buffer = read_to_string(&filename).map_err(|e| format!("{}: {}", filename, e))?;
-    let items = parse_functions(&buffer).map_err(|e| format!("{}: {}", filename, e))?;
+    let testfile = parse_test(&buffer).map_err(|e| format!("{}: {}", filename, e))?;
+    let isa = testfile.isa_spec.unique_isa();
+    if (opts.encodings || opts.locations) && isa.is_none() {
+        return Err(format!("{}: printing encodings or locations requires a unique ISA",
+                           filename));
+    }
 
-    for (idx, func) in items.into_iter().enumerate() {
+    for (idx, (mut func, _)) in testfile.functions.into_iter().enumerate() {
         if idx != 0 {
             println!("");
         }
-        print!("{}", func);
+        let text = cat_function(&mut func, isa, opts)
+            .map_err(|e| format!("{}: {}", filename, e))?;
+        print!("{}", text);
     }
 
     Ok(())
 }
 
+/// Print `func` with the annotations selected by `opts`.
+fn cat_function(func: &mut Function,
+                isa: Option<&TargetIsa>,
+                opts: &CatOptions)
+                -> Result<String, fmt::Error> {
+    select_annotations(func, opts);
+    let mut text = String::new();
+    if opts.cfg {
+        let cfg = ControlFlowGraph::with_function(func);
+        write_function_with_cfg(&mut text, func, isa, &cfg)?;
+    } else {
+        write_function(&mut text, func, isa)?;
+    }
+    Ok(text)
+}
+
+/// Remove the annotations in `func` that weren't selected by `opts`.
+fn select_annotations(func: &mut Function, opts: &CatOptions) {
+    if !opts.locations {
+        func.locations.clear();
+    }
+    if !opts.encodings {
+        func.encodings.clear();
+        // Value locations are printed in the encoding annotation, so keep it with a `-` encoding.
+        if !func.locations.is_empty() {
+            func.encodings.resize(func.dfg.num_insts());
+        }
+    }
+}
+
 /// Object implementing the `test cat` sub-test.
 ///
 /// This command is used for testing the parser and function printer. It simply parses a function
@@ -64,3 +124,89 @@ impl SubTest for TestCat {
         subtest::run_filecheck(&func.display(context.isa).to_string(), context)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{cat_function, CatOptions};
+    use cton_reader::parse_test;
+
+    const SOURCE: &'static str = "isa riscv
+                                  function f(i32) {
+                                  ebb0(v0: i32):
+                                      [R#10c, %x10] v1 = iadd v0, v0
+                                      [SBzero#18] brz v1, ebb1
+                                      [Iret#19] return
+                                  ebb1:
+                                      [Iret#19] return
+                                  }";
+
+    fn cat(encodings: bool, locations: bool, cfg: bool) -> String {
+        let testfile = parse_test(SOURCE).unwrap();
+        let isa = testfile.isa_spec.unique_isa();
+        let mut func = testfile.functions.into_iter().next().unwrap().0;
+        let opts = CatOptions {
+            encodings: encodings,
+            locations: locations,
+            cfg: cfg,
+        };
+        cat_function(&mut func, isa, &opts).unwrap()
+    }
+
+    #[test]
+    fn plain() {
+        assert_eq!(cat(false, false, false),
+                   "function f(i32) {\n\
+                    ebb0(v0: i32):\n    \
+                    v1 = iadd v0, v0\n    \
+                    brz v1, ebb1\n    \
+                    return\n\
+                    \n\
+                    ebb1:\n    \
+                    return\n\
+                    }\n");
+    }
+
+    #[test]
+    fn with_encodings() {
+        assert_eq!(cat(true, false, false),
+                   "function f(i32) {\n                    \
+                    ebb0(v0: i32):\n\
+                    [R#10c]                 v1 = iadd v0, v0\n\
+                    [SBzero#18]             brz v1, ebb1\n\
+                    [Iret#19]               return\n\
+                    \n                    \
+                    ebb1:\n\
+                    [Iret#19]               return\n\
+                    }\n");
+    }
+
+    #[test]
+    fn with_locations() {
+        assert_eq!(cat(false, true, false),
+                   "function f(i32) {\n                    \
+                    ebb0(v0: i32):\n\
+                    [-,%x10]                v1 = iadd v0, v0\n\
+                    [-]                     brz v1, ebb1\n\
+                    [-]                     return\n\
+                    \n                    \
+                    ebb1:\n\
+                    [-]                     return\n\
+                    }\n");
+    }
+
+    #[test]
+    fn with_cfg() {
+        assert_eq!(cat(false, false, true),
+                   "function f(i32) {\n\
+                    ebb0(v0: i32):\n    \
+                    ; preds: none; succs: ebb1\n    \
+                    v1 = iadd v0, v0\n    \
+                    brz v1, ebb1\n    \
+                    return\n\
+                    \n\
+                    ebb1:\n    \
+                    ; preds: ebb0:inst1; succs: none\n    \
+                    return\n\
+                    }\n");
+    }
+}
@@ -0,0 +1,186 @@
+//! The `compile` sub-command.
+//!
+//! Read a series of Cretonne IL files and compile all their functions for the ISA given by the
+//! `isa` command in each file. With the `--target` option, the functions are compiled for the
+//! target ISA or triple given on the command line instead, configured by any `--set` options.
+//!
+//! With the `-p` option, the annotated IL of each function is printed after compilation along
+//! with its code size, and the `-x` option adds a hex dump of the machine code. With the `-o`
+//! option, the compiled functions from all the files are written to a relocatable ELF object file.
+//! All the files must use the same ISA.
+
+use CommandResult;
+use cretonne::{CompiledFunction, Context};
+use cretonne::isa::{self, TargetIsa};
+use cretonne::opcode_stats::OpcodeStats;
+use cretonne::print_errors::pretty_error;
+use cton_obj::ElfWriter;
+use cton_reader::parse_test;
+use std::fmt::Write;
+use std::fs::File;
+use std::io::{self, Write as IoWrite};
+use utils::read_to_string;
+
+/// What to print for each compiled function.
+pub struct PrintOptions {
+    /// Print a one-line summary of the compiled function.
+    pub verbose: bool,
+    /// Print the annotated IL and the code size.
+    pub print: bool,
+    /// Print a hex dump of the machine code.
+    pub hex: bool,
+    /// Print the time spent in each pass after compiling all the files.
+    pub time_passes: bool,
+    /// Print the memory used by the compilation context after compiling each function, and the
+    /// instruction counts by opcode before and after each pass after compiling all the files.
+    pub stats: bool,
+}
+
+pub fn run(files: Vec<String>,
+           output: Option<String>,
+           target: Option<String>,
+           set: Vec<String>,
+           passes: Option<String>,
+           opts: PrintOptions)
+           -> CommandResult {
+    let target_isa = match target {
+        Some(name) => Some(build_isa(&name, &set)?),
+        None if !set.is_empty() => return Err("--set requires --target".to_string()),
+        None => None,
+    };
+
+    let mut writer = None;
+    let mut comp_ctx = Context::new();
+    if let Some(passes) = passes {
+        comp_ctx.pipeline = Some(passes.parse()?);
+    }
+    if opts.stats {
+        comp_ctx.opcode_stats = Some(OpcodeStats::new());
+    }
+    for filename in files {
+        compile_one(&filename,
+                    &mut comp_ctx,
+                    target_isa.as_ref().map(|isa| &**isa),
+                    &mut writer,
+                    output.is_some(),
+                    &opts)
+                .map_err(|e| format!("{}: {}", filename, e))?;
+    }
+
+    if opts.time_passes {
+        print!("{}", comp_ctx.timing);
+    }
+    if let Some(ref stats) = comp_ctx.opcode_stats {
+        println!("Instruction counts:");
+        print!("{}", stats);
+    }
+
+    if let Some(path) = output {
+        let writer = match writer {
+            Some(w) => w,
+            None => return Err("No functions to write".to_string()),
+        };
+        let mut file = File::create(&path).map_err(|e| format!("{}: {}", path, e))?;
+        writer
+            .write(&mut file)
+            .map_err(|e| format!("{}: {}", path, e))?;
+    }
+    Ok(())
+}
+
+/// Build the `TargetIsa` named by `target` which is either an ISA name or a target triple.
+///
+/// The `set` options are `name=value` settings or boolean flag names, and each of them can be a
+/// comma-separated list of options. They are applied to the ISA-independent settings if possible,
+/// and otherwise to the ISA-specific settings.
+pub fn build_isa(target: &str, set: &[String]) -> Result<Box<TargetIsa>, String> {
+    isa::build(target, &set.join(",")).map_err(|e| e.to_string())
+}
+
+fn compile_one(filename: &str,
+               comp_ctx: &mut Context,
+               target_isa: Option<&TargetIsa>,
+               writer: &mut Option<ElfWriter>,
+               want_object: bool,
+               opts: &PrintOptions)
+               -> CommandResult {
+    let buffer = read_to_string(filename).map_err(|e| e.to_string())?;
+    let testfile = parse_test(&buffer).map_err(|e| e.to_string())?;
+    let isa = match target_isa.or(testfile.isa_spec.unique_isa()) {
+        Some(isa) => isa,
+        None => return Err("compile needs exactly one ISA or a --target".to_string()),
+    };
+
+    if want_object {
+        match *writer {
+            Some(ref w) if !w.accepts(isa) => {
+                return Err(format!("{} doesn't match the ISA of the object file, all the files \
+                                    must use the same ISA",
+                                   isa.name()))
+            }
+            Some(_) => {}
+            None => *writer = Some(ElfWriter::new(isa)?),
+        }
+    }
+
+    for (func, _) in testfile.functions {
+        comp_ctx.clear();
+        comp_ctx.func = func;
+        let compiled = compile_function(comp_ctx, isa)?;
+        for diag in &comp_ctx.diagnostics {
+            writeln!(io::stderr(), "{}: {}", comp_ctx.func.name, diag)
+                .map_err(|e| e.to_string())?;
+        }
+
+        if opts.verbose {
+            println!("{}: {} bytes, {} relocations, {} trap sites",
+                     comp_ctx.func.name,
+                     compiled.code.len(),
+                     compiled.relocs.len(),
+                     compiled.traps.len());
+        }
+        if opts.print {
+            print!("{}", comp_ctx.func.display(isa));
+            println!("; code size: {} bytes", compiled.code.len());
+        }
+        if opts.hex {
+            print!("{}", hex_dump(&compiled.code));
+        }
+        if opts.stats {
+            println!("Memory usage after compiling {}:", comp_ctx.func.name);
+            print!("{}", comp_ctx.mem_usage());
+        }
+
+        if let Some(ref mut w) = *writer {
+            w.define_function(comp_ctx.func.name.as_str(),
+                              &comp_ctx.func,
+                              &compiled.code,
+                              &compiled.relocs)?;
+        }
+    }
+    Ok(())
+}
+
+/// Format `code` as lines of 16 hexadecimal bytes, each prefixed by its offset.
+fn hex_dump(code: &[u8]) -> String {
+    let mut text = String::new();
+    for (line, chunk) in code.chunks(16).enumerate() {
+        write!(text, "{:08x}:", line * 16).unwrap();
+        for byte in chunk {
+            write!(text, " {:02x}", byte).unwrap();
+        }
+        text.push('\n');
+    }
+    text
+}
+
+/// Compile the function in `comp_ctx` for `isa` and emit it to memory.
+///
+/// Errors are reported with the IL surrounding the offending instruction.
+pub fn compile_function(comp_ctx: &mut Context,
+                        isa: &TargetIsa)
+                        -> Result<CompiledFunction, String> {
+    comp_ctx
+        .compile_and_emit(isa)
+        .map_err(|e| pretty_error(&comp_ctx.func, Some(isa), e))
+}
@@ -1,13 +1,22 @@
-#[macro_use(dbg)]
+#[macro_use(error, info)]
 extern crate cretonne;
 extern crate cton_reader;
+extern crate cton_obj;
+extern crate cton_wasm;
+extern crate cton_interpreter;
 extern crate docopt;
 extern crate rustc_serialize;
 extern crate filecheck;
+#[cfg(unix)]
+extern crate libc;
 extern crate num_cpus;
+extern crate wat;
+#[cfg(feature = "disas")]
+extern crate capstone;
 
 use cretonne::VERSION;
 use docopt::Docopt;
+use std::env;
 use std::io::{self, Write};
 use std::process;
 
@@ -15,23 +24,57 @@ mod utils;
 mod filetest;
 mod cat;
 mod print_cfg;
+mod compile;
+mod disasm;
+mod run;
+mod difftest;
+mod reduce;
 mod rsfilecheck;
 
 const USAGE: &'static str = "
 Cretonne code generator utility
 
 Usage:
-    cton-util test [-v] <file>...
-    cton-util cat <file>...
+    cton-util test [-v] [--log <spec>] <file>...
+    cton-util cat [--with-encodings] [--with-locations] [--cfg] <file>...
     cton-util filecheck [-v] <file>
     cton-util print-cfg <file>...
+    cton-util compile [-vpxT] [--stats] [--target <isa>] [--set <flag>]... [--passes <list>] [--log <spec>] [-o <output>] <file>...
+    cton-util disasm <file>...
+    cton-util run [-v] [--set <flag>]... <file>...
+    cton-util difftest [-v] [--set <flag>]... [--seed <n>] [--count <n>]
+    cton-util reduce [-v] [--target <isa>] [--set <flag>]... <predicate> <file>...
     cton-util --help | --version
 
 Options:
     -v, --verbose  be more verbose
+    -p, --print    print the annotated IL and code size of compiled functions
+    -x, --hex      print a hex dump of the generated machine code
+    --with-encodings
+                   print the instruction encodings in files with a unique ISA, which cat
+                   strips by default
+    --with-locations
+                   print the value locations in files with a unique ISA, which cat strips
+                   by default
+    --cfg          print the predecessors and successors of each EBB
+    --stats        print the memory used by each compiled function and the compiler, and
+                   the instruction counts by opcode after each pass
+    -T, --time-passes
+                   print the time spent in each compiler pass
+    -o, --output <output>
+                   write an ELF object file
+    --target <isa>
+                   compile for an ISA name or target triple
+    --set <flag>   configure the target with settings like opt_level=best,is_pic
+    --passes <list>
+                   run a comma-separated list of passes instead of the default
+    --seed <n>     the first random seed to test [default: 0]
+    --count <n>    the number of random functions to test [default: 1000]
+    --log <spec>   log to cretonne.log.* files, overriding CRETONNE_LOG
     -h, --help     print this help message
     --version      print the Cretonne version
 
+A <file> named - is read from standard input.
 ";
 
 #[derive(RustcDecodable, Debug)]
@@ -40,8 +83,28 @@ struct Args {
     cmd_cat: bool,
     cmd_filecheck: bool,
     cmd_print_cfg: bool,
+    cmd_compile: bool,
+    cmd_disasm: bool,
+    cmd_run: bool,
+    cmd_difftest: bool,
+    cmd_reduce: bool,
+    arg_predicate: String,
     arg_file: Vec<String>,
     flag_verbose: bool,
+    flag_print: bool,
+    flag_hex: bool,
+    flag_time_passes: bool,
+    flag_stats: bool,
+    flag_with_encodings: bool,
+    flag_with_locations: bool,
+    flag_cfg: bool,
+    flag_output: Option<String>,
+    flag_target: Option<String>,
+    flag_set: Vec<String>,
+    flag_passes: Option<String>,
+    flag_seed: u64,
+    flag_count: u64,
+    flag_log: Option<String>,
 }
 
 /// A command either succeeds or fails with an error message.
@@ -58,15 +121,50 @@ fn cton_util() -> CommandResult {
                   })
         .unwrap_or_else(|e| e.exit());
 
+    // The log filter is read from the environment when the first message is logged.
+    if let Some(ref spec) = args.flag_log {
+        env::set_var("CRETONNE_LOG", spec);
+    }
+
     // Find the sub-command to execute.
     if args.cmd_test {
         filetest::run(args.flag_verbose, args.arg_file)
     } else if args.cmd_cat {
-        cat::run(args.arg_file)
+        cat::run(args.arg_file,
+                 cat::CatOptions {
+                     encodings: args.flag_with_encodings,
+                     locations: args.flag_with_locations,
+                     cfg: args.flag_cfg,
+                 })
     } else if args.cmd_filecheck {
         rsfilecheck::run(args.arg_file, args.flag_verbose)
     } else if args.cmd_print_cfg {
         print_cfg::run(args.arg_file)
+    } else if args.cmd_compile {
+        compile::run(args.arg_file,
+                     args.flag_output,
+                     args.flag_target,
+                     args.flag_set,
+                     args.flag_passes,
+                     compile::PrintOptions {
+                         verbose: args.flag_verbose,
+                         print: args.flag_print,
+                         hex: args.flag_hex,
+                         time_passes: args.flag_time_passes,
+                         stats: args.flag_stats,
+                     })
+    } else if args.cmd_disasm {
+        disasm::run(args.arg_file)
+    } else if args.cmd_run {
+        run::run(args.arg_file, args.flag_set, args.flag_verbose)
+    } else if args.cmd_difftest {
+        difftest::run(args.flag_set, args.flag_seed, args.flag_count, args.flag_verbose)
+    } else if args.cmd_reduce {
+        reduce::run(args.arg_predicate,
+                    args.arg_file,
+                    args.flag_target,
+                    args.flag_set,
+                    args.flag_verbose)
     } else {
         // Debugging / shouldn't happen with proper command line handling above.
         Err(format!("Unhandled args: {:?}", args))
@@ -0,0 +1,178 @@
+//! The `difftest` sub-command.
+//!
+//! Generate random functions without arguments, and run each of them twice: once in the IL
+//! interpreter, and once compiled for the host ISA. Any difference between the two results is a
+//! bug in either the code generator or the interpreter.
+//!
+//! The compiled code runs in a forked child process so a trap or a crash can be reported instead
+//! of killing the harness, and a compiler panic is reported as a failure too. Each function is
+//! named after its seed, and failing functions are printed so they can be saved to a file and
+//! investigated with `cton-util run` or `reduce`.
+
+use CommandResult;
+use compile::{build_isa, compile_function};
+use cretonne::Context;
+use cretonne::ir::{Function, Type};
+use cretonne::isa::TargetIsa;
+use cretonne::randfunc::{random_function, Config};
+use cton_interpreter::{Interpreter, Error, DataValue};
+use run::{host_triple, runnable_type, CodeMemory};
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+/// Fuel given to the interpreter for each function.
+const FUEL: u64 = 100_000;
+
+pub fn run(set: Vec<String>, first: u64, count: u64, verbose: bool) -> CommandResult {
+    let isa = build_isa(host_triple()?, &set)?;
+    let mut comp_ctx = Context::new();
+    let config = Config {
+        max_args: 0,
+        ..Config::default()
+    };
+
+    let mut skipped = 0;
+    let mut failures = 0;
+    for seed in first..first + count {
+        let func = random_function(seed, &config);
+        match check_function(func, &mut comp_ctx, &*isa) {
+            Ok(Some(outcome)) => {
+                if verbose {
+                    println!("{}: {}", comp_ctx.func.name, outcome);
+                }
+            }
+            Ok(None) => skipped += 1,
+            Err(msg) => {
+                println!("{}", msg);
+                failures += 1;
+            }
+        }
+    }
+    if verbose {
+        println!("{} functions tested, {} skipped", count - skipped, skipped);
+    }
+    if failures > 0 {
+        return Err(format!("{} of {} functions failed", failures, count));
+    }
+    Ok(())
+}
+
+/// The observable result of running a function.
+#[derive(PartialEq, Debug)]
+enum Outcome {
+    /// The function returned a value.
+    Value(DataValue),
+    /// The function trapped.
+    Trap,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Outcome::Value(value) => write!(f, "{}", value),
+            Outcome::Trap => f.write_str("trap"),
+        }
+    }
+}
+
+/// Run `func` in the interpreter and on the host, and compare the outcomes.
+///
+/// Returns `None` if the function can't be compared because it doesn't terminate in the
+/// interpreter.
+fn check_function(func: Function,
+                  comp_ctx: &mut Context,
+                  isa: &TargetIsa)
+                  -> Result<Option<Outcome>, String> {
+    let ty = match runnable_type(&func) {
+        Ok(Some(ty)) => ty,
+        Ok(None) => return Err(format!("{}: no return value", func.name)),
+        Err(reason) => return Err(format!("{}: {}", func.name, reason)),
+    };
+
+    let expected = {
+        let mut interp = Interpreter::new();
+        interp.set_fuel(Some(FUEL));
+        match interp.call(&func, &[]) {
+            Ok(results) => Outcome::Value(results[0]),
+            Err(Error::Trap(_)) => Outcome::Trap,
+            Err(Error::OutOfFuel) => return Ok(None),
+            Err(e) => return Err(failure(&func, &format!("interpreter: {}", e))),
+        }
+    };
+
+    comp_ctx.clear();
+    // Keep a copy of the function since the compiler modifies it in place.
+    comp_ctx.func = func.clone();
+    let compiled = panic::catch_unwind(AssertUnwindSafe(|| compile_function(comp_ctx, isa)))
+        .unwrap_or_else(|_| Err("compiler panicked".to_string()))
+        .map_err(|e| failure(&func, &e))?;
+    if !compiled.relocs.is_empty() {
+        return Err(failure(&func, "can't run code with relocations"));
+    }
+    let code = CodeMemory::new(&compiled.code)?;
+    let actual = run_isolated(&code, ty)
+        .map_err(|e| failure(&func, &e))?;
+
+    if actual != expected {
+        return Err(failure(&func,
+                           &format!("interpreter returned {}, compiled code returned {}",
+                                    expected,
+                                    actual)));
+    }
+    Ok(Some(actual))
+}
+
+/// Format a failure message for `func`, including the function itself.
+fn failure(func: &Function, msg: &str) -> String {
+    format!("{}: {}\n{}", func.name, msg, func.display(None))
+}
+
+/// Call the compiled code in a child process, returning `Trap` if it is killed by a signal.
+#[cfg(unix)]
+fn run_isolated(code: &CodeMemory, ty: Type) -> Result<Outcome, String> {
+    use libc;
+    use std::mem;
+
+    unsafe {
+        let mut fds = [0; 2];
+        if libc::pipe(fds.as_mut_ptr()) != 0 {
+            return Err("failed to create pipe".to_string());
+        }
+        let pid = libc::fork();
+        if pid < 0 {
+            return Err("failed to fork".to_string());
+        }
+
+        if pid == 0 {
+            // This is the child process. Send the result bits through the pipe and exit without
+            // running any destructors belonging to the parent.
+            let bits = code.call(Some(ty)).map_or(0, DataValue::to_bits);
+            let bytes: [u8; 8] = mem::transmute(bits);
+            libc::write(fds[1], bytes.as_ptr() as *const libc::c_void, bytes.len());
+            libc::_exit(0);
+        }
+
+        libc::close(fds[1]);
+        let mut bytes = [0u8; 8];
+        let len = libc::read(fds[0], bytes.as_mut_ptr() as *mut libc::c_void, bytes.len());
+        libc::close(fds[0]);
+        let mut status = 0;
+        if libc::waitpid(pid, &mut status, 0) != pid {
+            return Err("failed to wait for child process".to_string());
+        }
+
+        if libc::WIFSIGNALED(status) {
+            Ok(Outcome::Trap)
+        } else if len == bytes.len() as isize {
+            let bits: u64 = mem::transmute(bytes);
+            Ok(Outcome::Value(DataValue::from_bits(ty, bits)))
+        } else {
+            Err(format!("child process failed with status {}", status))
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn run_isolated(_code: &CodeMemory, _ty: Type) -> Result<Outcome, String> {
+    Err("running code is only supported on Unix hosts".to_string())
+}
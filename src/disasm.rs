@@ -0,0 +1,151 @@
+//! The `disasm` sub-command and disassembly support.
+//!
+//! Read a series of Cretonne IL files, compile all their functions for the ISA given by the `isa`
+//! command in each file, and print a disassembly of the generated machine code.
+//!
+//! The disassembler is only available when `cton-util` is built with the `disas` feature which
+//! pulls in the Capstone disassembly engine. It is also used to annotate `test binemit` failures.
+
+use CommandResult;
+use compile::compile_function;
+use cretonne::Context;
+use cretonne::isa::TargetIsa;
+use cton_reader::parse_test;
+use utils::read_to_string;
+
+pub fn run(files: Vec<String>) -> CommandResult {
+    let mut comp_ctx = Context::new();
+    for filename in files {
+        disasm_one(&filename, &mut comp_ctx).map_err(|e| format!("{}: {}", filename, e))?;
+    }
+    Ok(())
+}
+
+fn disasm_one(filename: &str, comp_ctx: &mut Context) -> CommandResult {
+    let buffer = read_to_string(filename).map_err(|e| e.to_string())?;
+    let testfile = parse_test(&buffer).map_err(|e| e.to_string())?;
+    let isa = match testfile.isa_spec.unique_isa() {
+        Some(isa) => isa,
+        None => return Err("disasm needs exactly one ISA".to_string()),
+    };
+
+    for (func, _) in testfile.functions {
+        comp_ctx.func = func;
+        let compiled = compile_function(comp_ctx, isa)?;
+
+        // Don't disassemble the jump tables following the code.
+        let func = &comp_ctx.func;
+        let code_size = func.jump_tables
+            .keys()
+            .filter(|&jt| !func.jump_tables[jt].is_empty())
+            .map(|jt| func.jt_offsets[jt] as usize)
+            .min()
+            .unwrap_or(compiled.code.len());
+
+        println!("{}:", func.name);
+        print!("{}", disassemble(isa, &compiled.code[0..code_size])?);
+    }
+    Ok(())
+}
+
+/// Disassemble the machine code in `mem` for `isa`.
+///
+/// Returns one line of text per instruction, or an error if the disassembler doesn't support
+/// `isa`.
+#[cfg(feature = "disas")]
+pub fn disassemble(isa: &TargetIsa, mem: &[u8]) -> Result<String, String> {
+    use capstone::prelude::*;
+    use std::fmt::Write;
+
+    let cs = match isa.name() {
+            "intel" => {
+                let mode = if isa.flags().is_64bit() {
+                    arch::x86::ArchMode::Mode64
+                } else {
+                    arch::x86::ArchMode::Mode32
+                };
+                Capstone::new().x86().mode(mode).build()
+            }
+            // Compressed code uses the Thumb-2 encodings. The ISA also uses them when the CPU
+            // doesn't have the ARM instruction set, so ask the ISA instead of the shared flags.
+            "arm32" => {
+                let mode = if isa.uses_compressed_code() {
+                    arch::arm::ArchMode::Thumb
+                } else {
+                    arch::arm::ArchMode::Arm
+                };
+                Capstone::new().arm().mode(mode).build()
+            }
+            "arm64" => {
+                Capstone::new()
+                    .arm64()
+                    .mode(arch::arm64::ArchMode::Arm)
+                    .build()
+            }
+            name => return Err(format!("no disassembler available for {}", name)),
+        }
+        .map_err(|e| e.to_string())?;
+
+    let insns = cs.disasm_all(mem, 0).map_err(|e| e.to_string())?;
+    let mut text = String::new();
+    for insn in insns.iter() {
+        let bytes: Vec<String> = insn.bytes().iter().map(|b| format!("{:02x}", b)).collect();
+        writeln!(text,
+                 "{:6x}:  {:24} {} {}",
+                 insn.address(),
+                 bytes.join(" "),
+                 insn.mnemonic().unwrap_or(""),
+                 insn.op_str().unwrap_or(""))
+                .unwrap();
+    }
+    Ok(text)
+}
+
+/// Disassemble the machine code in `mem` for `isa`.
+///
+/// Without the `disas` feature, no disassembler is available.
+#[cfg(not(feature = "disas"))]
+pub fn disassemble(_isa: &TargetIsa, _mem: &[u8]) -> Result<String, String> {
+    Err("cton-util was built without the disas feature".to_string())
+}
+
+/// Convert the hexadecimal machine code written by `test binemit` into bytes.
+///
+/// The hex digit groups are the values passed to the `CodeSink::put*` methods, so they are
+/// converted to little-endian byte order. Relocation annotations are ignored.
+pub fn parse_hex_code(text: &str) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for word in text.split_whitespace() {
+        if word.len() % 2 != 0 || word.len() > 16 {
+            continue;
+        }
+        if let Ok(mut x) = u64::from_str_radix(word, 16) {
+            for _ in 0..word.len() / 2 {
+                bytes.push(x as u8);
+                x >>= 8;
+            }
+        }
+    }
+    bytes
+}
+
+/// Describe a `test binemit` mismatch between the `want` and `have` machine code.
+///
+/// Returns the disassembly of both, or an empty string if they can't be disassembled.
+pub fn describe_mismatch(isa: &TargetIsa, want: &str, have: &str) -> String {
+    let want_disas = disassemble(isa, &parse_hex_code(want));
+    let have_disas = disassemble(isa, &parse_hex_code(have));
+    match (want_disas, have_disas) {
+        (Ok(w), Ok(h)) => format!("\nWant disassembly:\n{}Got disassembly:\n{}", w, h),
+        _ => String::new(),
+    }
+}
+
+#[test]
+fn test_parse_hex_code() {
+    assert_eq!(parse_hex_code("48 89 e5"), [0x48, 0x89, 0xe5]);
+    assert_eq!(parse_hex_code("e8 PCRel4(%foo-4) 00000000"),
+               [0xe8, 0, 0, 0, 0]);
+    assert_eq!(parse_hex_code("0123 89abcdef"),
+               [0x23, 0x01, 0xef, 0xcd, 0xab, 0x89]);
+}
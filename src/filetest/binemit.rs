@@ -2,15 +2,28 @@
 //!
 //! The `binemit` test command generates binary machine code for every instruction in the input
 //! functions and compares the results to the expected output.
+//!
+//! A `bin:` directive on an instruction checks its machine code, and a `bin:` directive on an EBB
+//! header checks the padding emitted before it. An `align:` directive on an EBB header checks the
+//! final alignment of the EBB in bytes, and an `align:` directive on the function checks the
+//! alignment of its entry point. A `rodata:` directive on the function checks the read-only data
+//! following the code, such as jump tables.
+//!
+//! A `trap:` directive on an instruction checks the trap sites recorded while emitting it. Each
+//! trap site is written as the trap code followed by its offset from the start of the instruction,
+//! like `heap_oob@0`.
 
 use std::borrow::Cow;
 use std::collections::HashMap;
-use std::fmt::Write;
+use std::fmt::{Display, Write};
 use cretonne::binemit;
+use cretonne::binemit::{CodeSink, CodeOffset};
 use cretonne::ir;
 use cretonne::ir::entities::AnyEntity;
 use cretonne::isa::TargetIsa;
+use cretonne::regalloc::RegDiversions;
 use cton_reader::TestCommand;
+use disasm;
 use filetest::subtest::{SubTest, Context, Result};
 use utils::match_directive;
 
@@ -30,6 +43,7 @@ struct TextSink {
     rnames: &'static [&'static str],
     offset: binemit::CodeOffset,
     text: String,
+    traps: Vec<(binemit::CodeOffset, ir::TrapCode)>,
 }
 
 impl TextSink {
@@ -39,11 +53,59 @@ impl TextSink {
             rnames: isa.reloc_names(),
             offset: 0,
             text: String::new(),
+            traps: Vec::new(),
+        }
+    }
+
+    /// Write a relocation of `target` with an optional addend.
+    fn reloc_target<T: Display>(&mut self,
+                                reloc: binemit::Reloc,
+                                target: T,
+                                addend: binemit::Addend) {
+        write!(self.text, "{}({}", self.rnames[reloc.0 as usize], target).unwrap();
+        if addend != 0 {
+            write!(self.text, "{:+}", addend).unwrap();
         }
+        self.text.push_str(") ");
+    }
+
+    /// Describe the trap sites recorded since `start`, relative to `start`.
+    fn traps_since(&self, start: binemit::CodeOffset) -> String {
+        self.traps
+            .iter()
+            .filter(|&&(offset, _)| offset >= start)
+            .map(|&(offset, code)| format!("{}@{}", code, offset - start))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+// Relocation sink that ignores all relocations.
+struct NullRelocSink;
+
+impl binemit::RelocSink for NullRelocSink {
+    fn reloc_ebb(&mut self, _: binemit::CodeOffset, _: binemit::Reloc, _: ir::Ebb) {}
+    fn reloc_func(&mut self,
+                  _: binemit::CodeOffset,
+                  _: binemit::Reloc,
+                  _: ir::FuncRef,
+                  _: binemit::Addend) {
+    }
+    fn reloc_external(&mut self,
+                      _: binemit::CodeOffset,
+                      _: binemit::Reloc,
+                      _: &ir::FunctionName,
+                      _: binemit::Addend) {
     }
+    fn reloc_jt(&mut self, _: binemit::CodeOffset, _: binemit::Reloc, _: ir::JumpTable) {}
 }
 
+/// A trap sink that discards the trap sites.
+struct NullTrapSink;
 
+impl binemit::TrapSink for NullTrapSink {
+    fn trap(&mut self, _: binemit::CodeOffset, _: ir::TrapCode) {}
+}
 
 impl binemit::CodeSink for TextSink {
     fn offset(&self) -> binemit::CodeOffset {
@@ -74,13 +136,28 @@ impl binemit::CodeSink for TextSink {
         write!(self.text, "{}({}) ", self.rnames[reloc.0 as usize], ebb).unwrap();
     }
 
-    fn reloc_func(&mut self, reloc: binemit::Reloc, fref: ir::FuncRef) {
-        write!(self.text, "{}({}) ", self.rnames[reloc.0 as usize], fref).unwrap();
+    fn reloc_func(&mut self, reloc: binemit::Reloc, fref: ir::FuncRef, addend: binemit::Addend) {
+        self.reloc_target(reloc, fref, addend);
+    }
+
+    fn reloc_external(&mut self,
+                      reloc: binemit::Reloc,
+                      name: &ir::FunctionName,
+                      addend: binemit::Addend) {
+        self.reloc_target(reloc, name, addend);
     }
 
     fn reloc_jt(&mut self, reloc: binemit::Reloc, jt: ir::JumpTable) {
         write!(self.text, "{}({}) ", self.rnames[reloc.0 as usize], jt).unwrap();
     }
+
+    fn trap(&mut self, code: ir::TrapCode) {
+        self.traps.push((self.offset, code));
+    }
+
+    fn begin_rodata(&mut self) {
+        self.text.clear();
+    }
 }
 
 impl SubTest for TestBinEmit {
@@ -119,37 +196,110 @@ impl SubTest for TestBinEmit {
             }
         }
 
-        // Relax branches and compute EBB offsets based on the encodings.
-        binemit::relax_branches(&mut func, isa);
+        // Relax branches and compute EBB offsets based on the encodings. The estimate is an upper
+        // bound computed without changing the function.
+        let estimate = func.code_size_estimate(isa);
+        let code_size = binemit::relax_branches(&mut func, isa);
+        if code_size > estimate {
+            return Err(format!("Code size {} exceeds the estimate of {} bytes",
+                               code_size,
+                               estimate));
+        }
+        let func_align = binemit::function_alignment(isa);
 
-        // Collect all of the 'bin:' directives on instructions.
+        // Collect all of the 'bin:' and 'trap:' directives on instructions and EBBs, and check the
+        // 'align:' directives.
         let mut bins = HashMap::new();
+        let mut traps = HashMap::new();
+        let mut pads = HashMap::new();
+        let mut rodata = None;
         for comment in &context.details.comments {
+            if let Some(want) = match_directive(comment.text, "rodata:") {
+                if comment.entity != AnyEntity::Function {
+                    return Err(format!("'rodata:' directive on {}: {}",
+                                       comment.entity,
+                                       comment.text));
+                }
+                rodata = Some(want);
+            }
             if let Some(want) = match_directive(comment.text, "bin:") {
-                match comment.entity {
-                    AnyEntity::Inst(inst) => {
-                        if let Some(prev) = bins.insert(inst, want) {
-                            return Err(format!("multiple 'bin:' directives on {}: '{}' and '{}'",
-                                               func.dfg.display_inst(inst),
-                                               prev,
-                                               want));
-                        }
+                let prev = match comment.entity {
+                    AnyEntity::Inst(inst) => bins.insert(inst, want),
+                    AnyEntity::Ebb(ebb) => pads.insert(ebb, want),
+                    _ => {
+                        return Err(format!("'bin:' directive on {}: {}",
+                                           comment.entity,
+                                           comment.text))
+                    }
+                };
+                if let Some(prev) = prev {
+                    return Err(format!("multiple 'bin:' directives on {}: '{}' and '{}'",
+                                       comment.entity,
+                                       prev,
+                                       want));
+                }
+            }
+            if let Some(want) = match_directive(comment.text, "trap:") {
+                let inst = match comment.entity {
+                    AnyEntity::Inst(inst) => inst,
+                    _ => {
+                        return Err(format!("'trap:' directive on {}: {}",
+                                           comment.entity,
+                                           comment.text))
                     }
+                };
+                if let Some(prev) = traps.insert(inst, want) {
+                    return Err(format!("multiple 'trap:' directives on {}: '{}' and '{}'",
+                                       comment.entity,
+                                       prev,
+                                       want));
+                }
+            }
+            if let Some(want) = match_directive(comment.text, "align:") {
+                let have = match comment.entity {
+                    AnyEntity::Function => func_align,
+                    AnyEntity::Ebb(ebb) => ebb_alignment(func.offsets[ebb], func_align),
                     _ => {
-                        return Err(format!("'bin:' directive on non-inst {}: {}",
+                        return Err(format!("'align:' directive on {}: {}",
                                            comment.entity,
                                            comment.text))
                     }
+                };
+                if want != have.to_string() {
+                    return Err(format!("Bad alignment for {}: want {}, got {}",
+                                       comment.entity,
+                                       want,
+                                       have));
                 }
             }
         }
-        if bins.is_empty() {
+        if bins.is_empty() && pads.is_empty() && traps.is_empty() && rodata.is_none() {
             return Err("No 'bin:' directives found".to_string());
         }
 
         // Now emit all instructions.
         let mut sink = TextSink::new(isa);
+        let mut divert = RegDiversions::new();
+        let mut all_legal = true;
         for ebb in func.layout.ebbs() {
+            divert.clear();
+
+            // Emit the padding before an aligned EBB header.
+            sink.text.clear();
+            if sink.offset < func.offsets[ebb] {
+                let bytes = func.offsets[ebb] - sink.offset;
+                isa.emit_padding(bytes, &mut sink);
+            }
+            if let Some(want) = pads.remove(&ebb) {
+                let have = sink.text.trim();
+                if have != want {
+                    return Err(format!("Bad padding before {}\nWant: {}\nGot:  {}",
+                                       ebb,
+                                       want,
+                                       have));
+                }
+            }
+
             // Correct header offsets should have been computed by `relax_branches()`.
             assert_eq!(sink.offset,
                        func.offsets[ebb],
@@ -157,12 +307,13 @@ impl SubTest for TestBinEmit {
                        ebb);
             for inst in func.layout.ebb_insts(ebb) {
                 sink.text.clear();
-                let enc = func.encodings.get(inst).cloned().unwrap_or_default();
+                let enc = func.inst_encoding(inst);
+                let before = sink.offset;
 
                 // Send legal encodings into the emitter.
+                all_legal &= enc.is_legal();
                 if enc.is_legal() {
-                    let before = sink.offset;
-                    isa.emit_inst(&func, inst, &mut sink);
+                    isa.emit_inst(&func, inst, &mut divert, &mut sink);
                     let emitted = sink.offset - before;
                     // Verify the encoding recipe sizes against the ISAs emit_inst implementation.
                     assert_eq!(emitted,
@@ -181,7 +332,25 @@ impl SubTest for TestBinEmit {
                     }
                     let have = sink.text.trim();
                     if have != want {
-                        return Err(format!("Bad machine code for {}: {}\nWant: {}\nGot:  {}",
+                        return Err(format!("Bad machine code for {}: {}\nWant: {}\nGot:  {}{}",
+                                           inst,
+                                           func.dfg.display_inst(inst),
+                                           want,
+                                           have,
+                                           disasm::describe_mismatch(isa, want, have)));
+                    }
+                }
+
+                // Check against trap: directives.
+                if let Some(want) = traps.remove(&inst) {
+                    if !enc.is_legal() {
+                        return Err(format!("{} can't be encoded: {}",
+                                           inst,
+                                           func.dfg.display_inst(inst)));
+                    }
+                    let have = sink.traps_since(before);
+                    if have != want {
+                        return Err(format!("Bad trap sites for {}: {}\nWant: {}\nGot:  {}",
                                            inst,
                                            func.dfg.display_inst(inst),
                                            want,
@@ -191,6 +360,47 @@ impl SubTest for TestBinEmit {
             }
         }
 
+        // The read-only data follows the code.
+        binemit::emit_jump_tables(&func, &mut sink);
+        if let Some(want) = rodata {
+            let have = sink.text.trim();
+            if have != want {
+                return Err(format!("Bad read-only data\nWant: {}\nGot:  {}", want, have));
+            }
+        }
+
+        if sink.offset != code_size {
+            return Err(format!("Expected code size {}, got {}", code_size, sink.offset));
+        }
+
+        // Emitting the whole function into memory should produce the same amount of code. This is
+        // only possible when all instructions have legal encodings.
+        if !all_legal {
+            return Ok(());
+        }
+        let mut mem = vec![0; code_size as usize];
+        let mut relocs = NullRelocSink;
+        let mut traps = NullTrapSink;
+        let mut mem_sink = binemit::MemoryCodeSink::with_endianness(&mut mem,
+                                                                    &mut relocs,
+                                                                    &mut traps,
+                                                                    isa.endianness());
+        isa.emit_function(&func, &mut mem_sink);
+        if mem_sink.offset() != code_size {
+            return Err(format!("Expected code size {}, emitted {} bytes to memory",
+                               code_size,
+                               mem_sink.offset()));
+        }
+
         Ok(())
     }
 }
+
+/// Get the alignment of an EBB header at `offset` in a function aligned to `func_align` bytes.
+fn ebb_alignment(offset: CodeOffset, func_align: CodeOffset) -> CodeOffset {
+    if offset == 0 {
+        func_align
+    } else {
+        (1 << offset.trailing_zeros()).min(func_align)
+    }
+}
@@ -10,7 +10,7 @@ use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
 use num_cpus;
-use filetest::{TestResult, runone};
+use filetest::{TestResult, TestStats, runone};
 
 // Request sent to worker threads contains jobid and path.
 struct Request(usize, PathBuf);
@@ -18,7 +18,11 @@ struct Request(usize, PathBuf);
 /// Reply from worker thread,
 pub enum Reply {
     Starting { jobid: usize, thread_num: usize },
-    Done { jobid: usize, result: TestResult },
+    Done {
+        jobid: usize,
+        result: TestResult,
+        stats: TestStats,
+    },
     Tick,
 }
 
@@ -126,26 +130,34 @@ fn worker_thread(thread_num: usize,
                           })
                     .unwrap();
 
-                let result = catch_unwind(|| runone::run(path.as_path())).unwrap_or_else(|e| {
-                    // The test panicked, leaving us a `Box<Any>`.
-                    // Panics are usually strings.
-                    if let Some(msg) = e.downcast_ref::<String>() {
-                        Err(format!("panicked in worker #{}: {}", thread_num, msg))
-                    } else if let Some(msg) = e.downcast_ref::<&'static str>() {
-                        Err(format!("panicked in worker #{}: {}", thread_num, msg))
-                    } else {
-                        Err(format!("panicked in worker #{}", thread_num))
-                    }
-                });
+                let (result, stats) = catch_unwind(|| runone::run(path.as_path()))
+                    .unwrap_or_else(|e| {
+                        // The test panicked, leaving us a `Box<Any>`.
+                        // Panics are usually strings.
+                        let msg = if let Some(msg) = e.downcast_ref::<String>() {
+                            format!("panicked in worker #{}: {}", thread_num, msg)
+                        } else if let Some(msg) = e.downcast_ref::<&'static str>() {
+                            format!("panicked in worker #{}: {}", thread_num, msg)
+                        } else {
+                            format!("panicked in worker #{}", thread_num)
+                        };
+                        // The statistics of the subtests run before the panic are lost.
+                        let stats = TestStats {
+                            failed: 1,
+                            ..TestStats::default()
+                        };
+                        (Err(msg), stats)
+                    });
 
                 if let &Err(ref msg) = &result {
-                    dbg!("FAIL: {}", msg);
+                    error!("FAIL: {}", msg);
                 }
 
                 replies
                     .send(Reply::Done {
                               jobid: jobid,
                               result: result,
+                              stats: stats,
                           })
                     .unwrap();
             }
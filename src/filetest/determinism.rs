@@ -0,0 +1,92 @@
+//! Test command for checking that code generation is deterministic.
+//!
+//! The `test determinism` test command compiles each function three times and checks that the
+//! machine code, relocations, and trap sites are identical every time. The function is compiled
+//! in two new compilation contexts, and then once more in the first context after clearing it with
+//! `Context::clear()`, reusing the memory left over from the first compilation.
+//!
+//! Reproducible builds and caches of compiled code both depend on this property.
+
+use compile::compile_function;
+use cretonne::CompiledFunction;
+use cretonne::ir::Function;
+use cretonne::isa::TargetIsa;
+use cretonne;
+use cton_reader::TestCommand;
+use filetest::subtest::{SubTest, Context, Result};
+use std::borrow::Cow;
+
+struct TestDeterminism;
+
+pub fn subtest(parsed: &TestCommand) -> Result<Box<SubTest>> {
+    assert_eq!(parsed.command, "determinism");
+    if !parsed.options.is_empty() {
+        Err(format!("No options allowed on {}", parsed))
+    } else {
+        Ok(Box::new(TestDeterminism))
+    }
+}
+
+impl SubTest for TestDeterminism {
+    fn name(&self) -> Cow<str> {
+        Cow::from("determinism")
+    }
+
+    fn needs_isa(&self) -> bool {
+        true
+    }
+
+    fn run(&self, func: Cow<Function>, context: &Context) -> Result<()> {
+        let isa = context.isa.expect("determinism needs an ISA");
+
+        let mut first_ctx = cretonne::Context::new();
+        let first = compile(&mut first_ctx, &func, isa)?;
+
+        let mut second_ctx = cretonne::Context::new();
+        let second = compile(&mut second_ctx, &func, isa)?;
+        compare(&first, &second, "compiling in a new context")?;
+
+        first_ctx.clear();
+        let reused = compile(&mut first_ctx, &func, isa)?;
+        compare(&first, &reused, "reusing the context")
+    }
+}
+
+/// Compile a copy of `func` in `comp_ctx`.
+fn compile(comp_ctx: &mut cretonne::Context,
+           func: &Function,
+           isa: &TargetIsa)
+           -> Result<CompiledFunction> {
+    comp_ctx.func = func.clone();
+    compile_function(comp_ctx, isa)
+}
+
+/// Check that two compilations of the same function produced the same output.
+fn compare(expected: &CompiledFunction, actual: &CompiledFunction, when: &str) -> Result<()> {
+    if let Some(offset) = expected
+           .code
+           .iter()
+           .zip(&actual.code)
+           .position(|(a, b)| a != b) {
+        return Err(format!("machine code differs at offset {} when {}", offset, when));
+    }
+    if expected.code.len() != actual.code.len() {
+        return Err(format!("code size changed from {} to {} bytes when {}",
+                           expected.code.len(),
+                           actual.code.len(),
+                           when));
+    }
+    if expected.relocs != actual.relocs {
+        return Err(format!("relocations differ when {}:\n{:?}\n{:?}",
+                           when,
+                           expected.relocs,
+                           actual.relocs));
+    }
+    if expected.traps != actual.traps {
+        return Err(format!("trap sites differ when {}:\n{:?}\n{:?}",
+                           when,
+                           expected.traps,
+                           actual.traps));
+    }
+    Ok(())
+}
@@ -0,0 +1,104 @@
+//! Test command for running functions in the IL interpreter.
+//!
+//! The `test interpret` test command looks for `run:` annotations anywhere in a function:
+//!
+//!     ; run: 10, 3 -> 3
+//!     ; run: 10, 0 -> trap int_divz
+//!
+//! Each annotation calls the function with the comma-separated arguments on the left of the arrow
+//! and checks that it returns the values on the right, or traps with the given trap code.
+
+use cretonne::ir::{Function, Type};
+use cton_interpreter::{Interpreter, DataValue, Error};
+use cton_reader::TestCommand;
+use filetest::subtest::{SubTest, Context, Result};
+use std::borrow::{Borrow, Cow};
+use utils::match_directive;
+
+struct TestInterpret;
+
+pub fn subtest(parsed: &TestCommand) -> Result<Box<SubTest>> {
+    assert_eq!(parsed.command, "interpret");
+    if !parsed.options.is_empty() {
+        Err(format!("No options allowed on {}", parsed))
+    } else {
+        Ok(Box::new(TestInterpret))
+    }
+}
+
+impl SubTest for TestInterpret {
+    fn name(&self) -> Cow<str> {
+        Cow::from("interpret")
+    }
+
+    fn run(&self, func: Cow<Function>, context: &Context) -> Result<()> {
+        let func: &Function = func.borrow();
+        let arg_types: Vec<Type> = func.signature
+            .argument_types
+            .iter()
+            .map(|arg| arg.value_type)
+            .collect();
+        let ret_types: Vec<Type> = func.signature
+            .return_types
+            .iter()
+            .map(|arg| arg.value_type)
+            .collect();
+
+        for comment in &context.details.comments {
+            let run = match match_directive(comment.text, "run:") {
+                Some(run) => run,
+                None => continue,
+            };
+            let arrow = match run.find("->") {
+                Some(pos) => pos,
+                None => return Err(format!("missing '->' in run: {}", run)),
+            };
+            let args = parse_values(&run[0..arrow], &arg_types)?;
+            let want = run[arrow + 2..].trim();
+
+            let (got, pass) = match Interpreter::new().call(func, &args) {
+                Ok(values) => {
+                    let pass = !want.starts_with("trap") &&
+                               values == parse_values(want, &ret_types)?;
+                    (format_values(&values), pass)
+                }
+                Err(Error::Trap(code)) => {
+                    let got = format!("trap {}", code);
+                    let pass = got == want;
+                    (got, pass)
+                }
+                Err(e) => return Err(format!("run: {}: {}", run, e)),
+            };
+            if !pass {
+                return Err(format!("run: {}\nexpected: {}\n     got: {}", run, want, got));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Parse a comma-separated list of values with the given types.
+fn parse_values(text: &str, types: &[Type]) -> Result<Vec<DataValue>> {
+    let text = text.trim();
+    let words: Vec<&str> = if text.is_empty() {
+        Vec::new()
+    } else {
+        text.split(',').map(str::trim).collect()
+    };
+    if words.len() != types.len() {
+        return Err(format!("expected {} values: {}", types.len(), text));
+    }
+    words
+        .iter()
+        .zip(types)
+        .map(|(word, &ty)| DataValue::parse(word, ty))
+        .collect()
+}
+
+fn format_values(values: &[DataValue]) -> String {
+    values
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -1,24 +1,37 @@
 //! Test command for checking the IL legalizer.
 //!
 //! The `test legalizer` test command runs each function through `legalize_function()` and sends
-//! the result to filecheck.
+//! the result to filecheck. The warnings produced by the legalizer follow the function as
+//! `; warning: ...` comments.
+//!
+//! With the `trace` option, the legalization action that transformed each instruction is also
+//! printed as a comment after the function:
+//!
+//!     test legalizer trace
 
 use std::borrow::Cow;
+use std::fmt::Write;
 use cretonne::{self, write_function};
 use cretonne::ir::Function;
-use cton_reader::TestCommand;
+use cretonne::print_errors::pretty_error;
+use cretonne::LegalizeTrace;
+use cton_reader::{TestCommand, TestOption};
 use filetest::subtest::{SubTest, Context, Result, run_filecheck};
-use utils::pretty_error;
 
-struct TestLegalizer;
+struct TestLegalizer {
+    trace: bool,
+}
 
 pub fn subtest(parsed: &TestCommand) -> Result<Box<SubTest>> {
     assert_eq!(parsed.command, "legalizer");
-    if !parsed.options.is_empty() {
-        Err(format!("No options allowed on {}", parsed))
-    } else {
-        Ok(Box::new(TestLegalizer))
+    let mut trace = false;
+    for opt in &parsed.options {
+        match *opt {
+            TestOption::Flag("trace") => trace = true,
+            _ => return Err(format!("Unknown option on {}", parsed)),
+        }
     }
+    Ok(Box::new(TestLegalizer { trace: trace }))
 }
 
 impl SubTest for TestLegalizer {
@@ -38,13 +51,24 @@ impl SubTest for TestLegalizer {
         let mut comp_ctx = cretonne::Context::new();
         comp_ctx.func = func.into_owned();
         let isa = context.isa.expect("legalizer needs an ISA");
+        if self.trace {
+            comp_ctx.legalize_trace = Some(LegalizeTrace::new());
+        }
 
         comp_ctx.flowgraph();
         comp_ctx.legalize(isa)
-            .map_err(|e| pretty_error(&comp_ctx.func, e))?;
+            .map_err(|e| pretty_error(&comp_ctx.func, Some(isa), e))?;
 
         let mut text = String::new();
         write_function(&mut text, &comp_ctx.func, Some(isa)).map_err(|e| e.to_string())?;
+        for diag in &comp_ctx.diagnostics {
+            writeln!(text, "; {}", diag).map_err(|e| e.to_string())?;
+        }
+        if let Some(ref trace) = comp_ctx.legalize_trace {
+            for step in trace.steps() {
+                writeln!(text, "; {}", step).map_err(|e| e.to_string())?;
+            }
+        }
         run_filecheck(&text, context)
     }
 }
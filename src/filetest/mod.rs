@@ -10,35 +10,69 @@ use CommandResult;
 use cat;
 use print_cfg;
 use filetest::runner::TestRunner;
+use utils::is_stdin;
 
 pub mod subtest;
 
 mod binemit;
 mod concurrent;
+mod determinism;
 mod domtree;
+mod interpret;
 mod legalizer;
+mod pipeline;
+mod prologue_epilogue;
 mod regalloc;
 mod runner;
 mod runone;
 mod verifier;
+mod wasmfile;
 
 /// The result of running the test in a file.
 pub type TestResult = Result<time::Duration, String>;
 
+/// Counts and timing of the subtests run for a file.
+///
+/// Each subtest is run once per function and ISA in the file. The first failure ends the test of
+/// the file, and the remaining subtest runs are counted as skipped.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TestStats {
+    /// Time spent in each subtest by name, in the order the subtests were first run.
+    pub subtests: Vec<(String, time::Duration)>,
+    /// Number of subtest runs that passed.
+    pub passed: usize,
+    /// Number of subtest runs that failed, or 1 if the file itself failed to load.
+    pub failed: usize,
+    /// Number of subtest runs skipped after a failure.
+    pub skipped: usize,
+}
+
+impl TestStats {
+    /// Add `elapsed` to the time spent in the subtest `name`.
+    pub fn add_time(&mut self, name: &str, elapsed: time::Duration) {
+        match self.subtests.iter().position(|&(ref n, _)| n == name) {
+            Some(idx) => self.subtests[idx].1 += elapsed,
+            None => self.subtests.push((name.to_string(), elapsed)),
+        }
+    }
+}
+
 /// Main entry point for `cton-util test`.
 ///
-/// Take a list of filenames which can be either `.cton` files or directories.
+/// Take a list of filenames which can be either `.cton` or `.wat` files or directories.
 ///
 /// Files are interpreted as test cases and executed immediately.
 ///
-/// Directories are scanned recursively for test cases ending in `.cton`. These test cases are
-/// executed on background threads.
+/// Directories are scanned recursively for test cases ending in `.cton` or `.wat`. These test
+/// cases are executed on background threads.
+///
+/// The file name `-` reads a `.cton` test case from standard input.
 ///
 pub fn run(verbose: bool, files: Vec<String>) -> CommandResult {
     let mut runner = TestRunner::new(verbose);
 
     for path in files.iter().map(Path::new) {
-        if path.is_file() {
+        if path.is_file() || is_stdin(path) {
             runner.push_test(path);
         } else {
             runner.push_dir(path);
@@ -60,8 +94,12 @@ fn new_subtest(parsed: &TestCommand) -> subtest::Result<Box<subtest::SubTest>> {
         "domtree" => domtree::subtest(parsed),
         "verifier" => verifier::subtest(parsed),
         "legalizer" => legalizer::subtest(parsed),
+        "pipeline" => pipeline::subtest(parsed),
         "regalloc" => regalloc::subtest(parsed),
+        "prologue-epilogue" => prologue_epilogue::subtest(parsed),
         "binemit" => binemit::subtest(parsed),
+        "determinism" => determinism::subtest(parsed),
+        "interpret" => interpret::subtest(parsed),
         _ => Err(format!("unknown test command '{}'", parsed.command)),
     }
 }
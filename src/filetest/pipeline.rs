@@ -0,0 +1,61 @@
+//! Test command for running a sequence of passes.
+//!
+//! The `test pipeline` test command runs each function through the compilation pipeline and sends
+//! the result to filecheck. The passes to run can be given as an option:
+//!
+//!     test pipeline passes=dce,legalize
+//!
+//! Otherwise the default pipeline for the `opt_level` setting is used.
+
+use cretonne::ir::Function;
+use cretonne::pipeline::Pipeline;
+use cretonne::{self, write_function};
+use cretonne::print_errors::pretty_error;
+use cton_reader::{TestCommand, TestOption};
+use filetest::subtest::{SubTest, Context, Result, run_filecheck};
+use std::borrow::Cow;
+
+struct TestPipeline {
+    pipeline: Option<Pipeline>,
+}
+
+pub fn subtest(parsed: &TestCommand) -> Result<Box<SubTest>> {
+    assert_eq!(parsed.command, "pipeline");
+    let mut pipeline = None;
+    for opt in &parsed.options {
+        match *opt {
+            TestOption::Value("passes", passes) => pipeline = Some(passes.parse()?),
+            _ => return Err(format!("Unknown option on {}", parsed)),
+        }
+    }
+    Ok(Box::new(TestPipeline { pipeline: pipeline }))
+}
+
+impl SubTest for TestPipeline {
+    fn name(&self) -> Cow<str> {
+        Cow::from("pipeline")
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn needs_isa(&self) -> bool {
+        true
+    }
+
+    fn run(&self, func: Cow<Function>, context: &Context) -> Result<()> {
+        let isa = context.isa.expect("pipeline needs an ISA");
+        let mut comp_ctx = cretonne::Context::new();
+        comp_ctx.func = func.into_owned();
+        comp_ctx.pipeline = self.pipeline.clone();
+
+        comp_ctx
+            .run_pipeline(isa)
+            .map_err(|e| pretty_error(&comp_ctx.func, Some(isa), e))?;
+
+        let mut text = String::new();
+        write_function(&mut text, &comp_ctx.func, Some(isa)).map_err(|e| e.to_string())?;
+        run_filecheck(&text, context)
+    }
+}
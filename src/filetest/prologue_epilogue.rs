@@ -0,0 +1,57 @@
+//! Test command for testing the prologue and epilogue insertion.
+//!
+//! The `prologue-epilogue` test command runs each function through the register allocator and then
+//! inserts the prologue and epilogue code for the target.
+//!
+//! The resulting function is sent to `filecheck`.
+
+use cretonne::ir::Function;
+use cretonne::{self, write_function};
+use cretonne::print_errors::pretty_error;
+use cton_reader::TestCommand;
+use filetest::subtest::{SubTest, Context, Result, run_filecheck};
+use std::borrow::Cow;
+
+struct TestPrologueEpilogue;
+
+pub fn subtest(parsed: &TestCommand) -> Result<Box<SubTest>> {
+    assert_eq!(parsed.command, "prologue-epilogue");
+    if !parsed.options.is_empty() {
+        Err(format!("No options allowed on {}", parsed))
+    } else {
+        Ok(Box::new(TestPrologueEpilogue))
+    }
+}
+
+impl SubTest for TestPrologueEpilogue {
+    fn name(&self) -> Cow<str> {
+        Cow::from("prologue-epilogue")
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn needs_isa(&self) -> bool {
+        true
+    }
+
+    fn run(&self, func: Cow<Function>, context: &Context) -> Result<()> {
+        let isa = context.isa.expect("prologue-epilogue needs an ISA");
+
+        let mut comp_ctx = cretonne::Context::new();
+        comp_ctx.func = func.into_owned();
+
+        comp_ctx.flowgraph();
+        comp_ctx.legalize(isa)
+            .map_err(|e| pretty_error(&comp_ctx.func, Some(isa), e))?;
+        comp_ctx.regalloc(isa)
+            .map_err(|e| pretty_error(&comp_ctx.func, Some(isa), e))?;
+        comp_ctx.prologue_epilogue(isa)
+            .map_err(|e| pretty_error(&comp_ctx.func, Some(isa), e))?;
+
+        let mut text = String::new();
+        write_function(&mut text, &comp_ctx.func, Some(isa)).map_err(|e| e.to_string())?;
+        run_filecheck(&text, context)
+    }
+}
@@ -7,10 +7,10 @@
 
 use cretonne::ir::Function;
 use cretonne::{self, write_function};
+use cretonne::print_errors::pretty_error;
 use cton_reader::TestCommand;
 use filetest::subtest::{SubTest, Context, Result, run_filecheck};
 use std::borrow::Cow;
-use utils::pretty_error;
 
 struct TestRegalloc;
 
@@ -46,9 +46,9 @@ impl SubTest for TestRegalloc {
         comp_ctx.flowgraph();
         // TODO: Should we have an option to skip legalization?
         comp_ctx.legalize(isa)
-            .map_err(|e| pretty_error(&comp_ctx.func, e))?;
+            .map_err(|e| pretty_error(&comp_ctx.func, Some(isa), e))?;
         comp_ctx.regalloc(isa)
-            .map_err(|e| pretty_error(&comp_ctx.func, e))?;
+            .map_err(|e| pretty_error(&comp_ctx.func, Some(isa), e))?;
 
         let mut text = String::new();
         write_function(&mut text, &comp_ctx.func, Some(isa)).map_err(|e| e.to_string())?;
@@ -7,7 +7,8 @@ use std::error::Error;
 use std::fmt::{self, Display};
 use std::ffi::OsStr;
 use std::path::{Path, PathBuf};
-use filetest::{TestResult, runone};
+use std::time::{Duration, Instant};
+use filetest::{TestResult, TestStats, runone};
 use filetest::concurrent::{ConcurrentRunner, Reply};
 use CommandResult;
 
@@ -17,9 +18,13 @@ const TIMEOUT_PANIC: usize = 10;
 // Timeout for reporting slow tests without panicking.
 const TIMEOUT_SLOW: usize = 3;
 
+// Number of slowest test files to list in the summary.
+const SLOWEST_FILES: usize = 5;
+
 struct QueueEntry {
     path: PathBuf,
     state: State,
+    stats: TestStats,
 }
 
 #[derive(PartialEq, Eq, Debug)]
@@ -40,19 +45,25 @@ impl Display for QueueEntry {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let p = self.path.to_string_lossy();
         match self.state {
-            State::Done(Ok(dur)) => {
-                write!(f,
-                       "{}.{:03} {}",
-                       dur.as_secs(),
-                       dur.subsec_nanos() / 1000000,
-                       p)
-            }
+            State::Done(Ok(dur)) => write!(f, "{} {}", DisplayDuration(dur), p),
             State::Done(Err(ref e)) => write!(f, "FAIL {}: {}", p, e),
             _ => write!(f, "{}", p),
         }
     }
 }
 
+/// Display a duration in seconds with millisecond precision.
+struct DisplayDuration(Duration);
+
+impl Display for DisplayDuration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "{}.{:03}",
+               self.0.as_secs(),
+               self.0.subsec_nanos() / 1000000)
+    }
+}
+
 pub struct TestRunner {
     verbose: bool,
 
@@ -108,6 +119,7 @@ impl TestRunner {
             .push(QueueEntry {
                       path: file.into(),
                       state: State::New,
+                      stats: TestStats::default(),
                   });
     }
 
@@ -123,7 +135,7 @@ impl TestRunner {
         // This recursive search tries to minimize statting in a directory hierarchy containing
         // mostly test cases.
         //
-        // - Directory entries with a "cton" extension are presumed to be test case files.
+        // - Directory entries with a "cton" or "wat" extension are presumed to be test case files.
         // - Directory entries with no extension are presumed to be subdirectories.
         // - Anything else is ignored.
         //
@@ -156,7 +168,7 @@ impl TestRunner {
                                 // Recognize directories and tests by extension.
                                 // Yes, this means we ignore directories with '.' in their name.
                                 match path.extension().and_then(OsStr::to_str) {
-                                    Some("cton") => self.push_test(path),
+                                    Some("cton") | Some("wat") => self.push_test(path),
                                     Some(_) => {}
                                     None => self.push_dir(path),
                                 }
@@ -200,8 +212,8 @@ impl TestRunner {
             } else {
                 // Run test synchronously.
                 self.tests[jobid].state = State::Running;
-                let result = runone::run(self.tests[jobid].path());
-                self.finish_job(jobid, result);
+                let (result, stats) = runone::run(self.tests[jobid].path());
+                self.finish_job(jobid, result, stats);
             }
             self.new_tests = jobid + 1;
         }
@@ -215,12 +227,13 @@ impl TestRunner {
     }
 
     /// Report the end of a job.
-    fn finish_job(&mut self, jobid: usize, result: TestResult) {
+    fn finish_job(&mut self, jobid: usize, result: TestResult, stats: TestStats) {
         assert_eq!(self.tests[jobid].state, State::Running);
         if result.is_err() {
             self.errors += 1;
         }
         self.tests[jobid].state = State::Done(result);
+        self.tests[jobid].stats = stats;
 
         // Rports jobs in order.
         while self.report_job() {
@@ -235,9 +248,13 @@ impl TestRunner {
                 assert_eq!(self.tests[jobid].state, State::Queued);
                 self.tests[jobid].state = State::Running;
             }
-            Reply::Done { jobid, result } => {
+            Reply::Done {
+                jobid,
+                result,
+                stats,
+            } => {
                 self.ticks_since_progress = 0;
-                self.finish_job(jobid, result)
+                self.finish_job(jobid, result, stats)
             }
             Reply::Tick => {
                 self.ticks_since_progress += 1;
@@ -274,56 +291,56 @@ impl TestRunner {
         }
     }
 
-    /// Print out a report of slow tests.
+    /// Print out the slowest tests with the time spent in each of their subtests.
     fn report_slow_tests(&self) {
-        // Collect runtimes of succeeded tests.
-        let mut times = self.tests
+        let mut done = self.tests
             .iter()
-            .filter_map(|entry| match *entry {
-                            QueueEntry { state: State::Done(Ok(dur)), .. } => Some(dur),
+            .filter_map(|entry| match entry.state {
+                            State::Done(Ok(dur)) => Some((dur, entry)),
                             _ => None,
                         })
             .collect::<Vec<_>>();
-
-        // Get me some real data, kid.
-        let len = times.len();
-        if len < 4 {
-            return;
-        }
-
-        // Compute quartiles.
-        times.sort();
-        let qlen = len / 4;
-        let q1 = times[qlen];
-        let q3 = times[len - 1 - qlen];
-        // Inter-quartile range.
-        let iqr = q3 - q1;
-
-        // Cut-off for what we consider a 'slow' test: 1.5 IQR from the 75% quartile.
-        // These are the data points that would be plotted as outliers outside a box plot.
-        let cut = q3 + iqr * 2 / 3;
-        if cut > *times.last().unwrap() {
+        if done.len() <= SLOWEST_FILES {
             return;
         }
 
-        for t in self.tests
+        done.sort_by_key(|&(dur, _)| dur);
+        println!("slowest:");
+        for &(_, entry) in done.iter().rev().take(SLOWEST_FILES) {
+            let subtests = entry
+                .stats
+                .subtests
                 .iter()
-                .filter(|entry| match **entry {
-                            QueueEntry { state: State::Done(Ok(dur)), .. } => dur > cut,
-                            _ => false,
-                        }) {
-            println!("slow: {}", t)
+                .map(|&(ref name, dur)| format!("{} {}", name, DisplayDuration(dur)))
+                .collect::<Vec<_>>();
+            println!("    {} ({})", entry, subtests.join(", "));
         }
+    }
 
+    /// Print the number of subtests passed, failed, and skipped, and the total time.
+    fn report_summary(&self, elapsed: Duration) {
+        let (mut passed, mut failed, mut skipped) = (0, 0, 0);
+        for entry in &self.tests {
+            passed += entry.stats.passed;
+            failed += entry.stats.failed;
+            skipped += entry.stats.skipped;
+        }
+        println!("{} tests: {} subtests passed, {} failed, {} skipped in {}s",
+                 self.tests.len(),
+                 passed,
+                 failed,
+                 skipped,
+                 DisplayDuration(elapsed));
     }
 
     /// Scan pushed directories for tests and run them.
     pub fn run(&mut self) -> CommandResult {
+        let started = Instant::now();
         self.scan_dirs();
         self.schedule_jobs();
         self.drain_threads();
         self.report_slow_tests();
-        println!("{} tests", self.tests.len());
+        self.report_summary(started.elapsed());
         match self.errors {
             0 => Ok(()),
             1 => Err("1 failure".to_string()),
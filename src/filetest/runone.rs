@@ -1,29 +1,55 @@
 //! Run the tests in a single test file.
 
 use std::borrow::Cow;
+use std::ffi::OsStr;
 use std::path::Path;
 use std::time;
 use cretonne::ir::Function;
 use cretonne::isa::TargetIsa;
 use cretonne::settings::Flags;
 use cretonne::verify_function;
+use cretonne::verifier::verify_features;
+use cretonne::print_errors::pretty_verifier_error;
 use cton_reader::parse_test;
 use cton_reader::IsaSpec;
-use utils::{read_to_string, pretty_verifier_error};
-use filetest::{TestResult, new_subtest};
+use utils::read_to_string;
+use filetest::{TestResult, TestStats, new_subtest};
+use filetest::wasmfile;
 use filetest::subtest::{SubTest, Context, Result};
 
 /// Load `path` and run the test in it.
 ///
+/// Return the result along with the counts and timing of the subtests that were run. A file that
+/// fails before any subtest fails, for example because it can't be parsed, is counted as one
+/// failed subtest.
 /// If running this test causes a panic, it will propagate as normal.
-pub fn run(path: &Path) -> TestResult {
-    dbg!("---\nFile: {}", path.to_string_lossy());
+pub fn run(path: &Path) -> (TestResult, TestStats) {
+    let mut stats = TestStats::default();
+    let result = run_file(path, &mut stats);
+    if result.is_err() && stats.failed == 0 {
+        stats.failed = 1;
+    }
+    (result, stats)
+}
+
+fn run_file(path: &Path, stats: &mut TestStats) -> TestResult {
+    info!("File: {}", path.to_string_lossy());
     let started = time::Instant::now();
     let buffer = read_to_string(path).map_err(|e| e.to_string())?;
-    let testfile = parse_test(&buffer).map_err(|e| e.to_string())?;
-    if testfile.functions.is_empty() {
-        return Err("no functions found".to_string());
-    }
+
+    // A `.wat` file has its test commands in comments, and the functions are translated from
+    // WebAssembly once the settings are known.
+    let wat_header;
+    let mut wat_directives = None;
+    let header = if path.extension() == Some(OsStr::new("wat")) {
+        let comments = wasmfile::extract_comments(&buffer);
+        wat_header = comments.header;
+        wat_directives = Some(comments.functions);
+        &wat_header
+    } else {
+        &buffer
+    };
+    let mut testfile = parse_test(header).map_err(|e| e.to_string())?;
 
     // Parse the test commands.
     let mut tests = testfile.commands
@@ -38,6 +64,16 @@ pub fn run(path: &Path) -> TestResult {
         IsaSpec::Some(ref v) => v.last().expect("Empty ISA list").flags(),
     };
 
+    if let Some(directives) = wat_directives {
+        if !testfile.functions.is_empty() {
+            return Err("unexpected IL functions in .wat file".to_string());
+        }
+        testfile.functions = wasmfile::translate(&buffer, flags, directives)?;
+    }
+    if testfile.functions.is_empty() {
+        return Err("no functions found".to_string());
+    }
+
     // Sort the tests so the mutators are at the end, and those that don't need the verifier are at
     // the front.
     tests.sort_by_key(|st| (st.is_mutating(), st.needs_verifier()));
@@ -52,6 +88,7 @@ pub fn run(path: &Path) -> TestResult {
         Some(t) => t,
     };
 
+    let total = (tuples.len() + 1) * testfile.functions.len();
     for (func, details) in testfile.functions {
         let mut context = Context {
             preamble_comments: &testfile.preamble_comments,
@@ -62,18 +99,38 @@ pub fn run(path: &Path) -> TestResult {
         };
 
         for tuple in &tuples {
-            run_one_test(*tuple, Cow::Borrowed(&func), &mut context)?;
+            timed_test(*tuple, Cow::Borrowed(&func), &mut context, stats, total)?;
         }
         // Run the last test with an owned function which means it won't need to clone it before
         // mutating.
-        run_one_test(last_tuple, Cow::Owned(func), &mut context)?;
+        timed_test(last_tuple, Cow::Owned(func), &mut context, stats, total)?;
     }
 
-
-    // TODO: Actually run the tests.
     Ok(started.elapsed())
 }
 
+/// Run a single test like `run_one_test()` and record the outcome in `stats`.
+///
+/// The `total` number of subtest runs in the file is used to count the skipped runs on failure.
+fn timed_test<'a>(tuple: (&'a SubTest, &'a Flags, Option<&'a TargetIsa>),
+                  func: Cow<Function>,
+                  context: &mut Context<'a>,
+                  stats: &mut TestStats,
+                  total: usize)
+                  -> Result<()> {
+    let started = time::Instant::now();
+    let result = run_one_test(tuple, func, context);
+    stats.add_time(&tuple.0.name(), started.elapsed());
+    match result {
+        Ok(()) => stats.passed += 1,
+        Err(_) => {
+            stats.failed += 1;
+            stats.skipped = total - stats.passed - stats.failed;
+        }
+    }
+    result
+}
+
 // Given a slice of tests, generate a vector of (test, flags, isa) tuples.
 fn test_tuples<'a>(tests: &'a [Box<SubTest>],
                    isa_spec: &'a IsaSpec,
@@ -109,14 +166,16 @@ fn run_one_test<'a>(tuple: (&'a SubTest, &'a Flags, Option<&'a TargetIsa>),
                     -> Result<()> {
     let (test, flags, isa) = tuple;
     let name = format!("{}({})", test.name(), func.name);
-    dbg!("Test: {} {}", name, isa.map(TargetIsa::name).unwrap_or("-"));
+    info!("Test: {} {}", name, isa.map(TargetIsa::name).unwrap_or("-"));
 
     context.flags = flags;
     context.isa = isa;
 
     // Should we run the verifier before this test?
     if !context.verified && test.needs_verifier() {
-        verify_function(&func).map_err(|e| pretty_verifier_error(&func, e))?;
+        verify_function(&func)
+            .and_then(|_| verify_features(&func, flags))
+            .map_err(|e| pretty_verifier_error(&func, isa, e))?;
         context.verified = true;
     }
 
@@ -6,10 +6,21 @@
 //!
 //! This annotation means that the verifier is expected to given an error for the jump instruction
 //! containing the substring "jump to non-existent EBB".
+//!
+//! The function is also checked against the features enabled by the `enable_float`,
+//! `enable_simd`, and `enable_atomics` settings.
+//!
+//! When the test file has a unique ISA and the function has value locations, the locations are
+//! verified against the encoding constraints of the ISA, including the registers clobbered by
+//! calls and other call-like instructions.
 
 use std::borrow::{Borrow, Cow};
 use cretonne::verify_function;
+use cretonne::verifier::{self, verify_features, verify_locations};
+use cretonne::flowgraph::ControlFlowGraph;
 use cretonne::ir::Function;
+use cretonne::isa::TargetIsa;
+use cretonne::regalloc::liveness::Liveness;
 use cton_reader::TestCommand;
 use filetest::subtest::{SubTest, Context, Result};
 use utils::match_directive;
@@ -51,7 +62,13 @@ impl SubTest for TestVerifier {
             }
         }
 
-        match verify_function(func) {
+        let result = verify_function(func)
+            .and_then(|_| verify_features(func, context.flags))
+            .and_then(|_| match context.isa {
+                          Some(isa) if !func.locations.is_empty() => check_locations(isa, func),
+                          _ => Ok(()),
+                      });
+        match result {
             Ok(_) => {
                 match expected {
                     None => Ok(()),
@@ -76,3 +93,11 @@ impl SubTest for TestVerifier {
         }
     }
 }
+
+/// Verify the value locations of `func`, including the clobbered registers.
+fn check_locations(isa: &TargetIsa, func: &Function) -> verifier::Result {
+    let cfg = ControlFlowGraph::with_function(func);
+    let mut liveness = Liveness::new();
+    liveness.compute(isa, func, &cfg);
+    verify_locations(isa, func, Some(&liveness))
+}
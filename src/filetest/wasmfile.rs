@@ -0,0 +1,108 @@
+//! Support for `.wat` test files.
+//!
+//! A `.wat` test file is a WebAssembly module in text format. It is translated to Cretonne IL with
+//! the dummy environment of `cton_wasm`, and the test commands run on the translated functions.
+//!
+//! The test commands and filecheck directives are written in `;;` comments. The comments before
+//! the first function form the preamble like in a `.cton` file:
+//!
+//! ```text
+//! ;; test verifier
+//! ;; set is_64bit
+//! ;; check: heap_addr
+//! (module
+//!   (memory 1)
+//!   (func (param i32) (result i32)
+//!     ;; check: load.i32
+//!     (i32.load (local.get 0))))
+//! ```
+//!
+//! The comments following the first line of a function definition apply to that function only. A
+//! function definition is recognized by a line starting with `(func`.
+
+use cretonne::ir::Function;
+use cretonne::ir::entities::AnyEntity;
+use cretonne::settings::Flags;
+use cton_reader::{Details, Comment, Location, SourceMap};
+use cton_wasm::{translate_module, DummyEnvironment};
+use filetest::subtest::Result;
+use wat;
+
+/// The comments of a `.wat` test file.
+pub struct WatComments<'a> {
+    /// The test header in the `.cton` format, with the test commands, ISA specs, and preamble
+    /// directives.
+    pub header: String,
+
+    /// The directives of each function defined in the module, in order.
+    pub functions: Vec<Vec<&'a str>>,
+}
+
+/// Extract the comments from the `.wat` test file `text`.
+pub fn extract_comments(text: &str) -> WatComments {
+    let mut header = String::new();
+    let mut functions: Vec<Vec<&str>> = Vec::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.starts_with("(func") {
+            functions.push(Vec::new());
+        }
+        let pos = match line.find(";;") {
+            Some(pos) => pos,
+            None => continue,
+        };
+        // Keep a single `;` so the directives look like `.cton` comments.
+        let comment = &line[pos + 1..];
+        match functions.last_mut() {
+            Some(directives) => directives.push(comment),
+            None => {
+                let command = comment[1..].trim();
+                if command.starts_with("test ") || command.starts_with("isa ") ||
+                   command.starts_with("set ") {
+                    header.push_str(command);
+                } else {
+                    header.push_str(comment);
+                }
+                header.push('\n');
+            }
+        }
+    }
+    WatComments {
+        header: header,
+        functions: functions,
+    }
+}
+
+/// Translate the WebAssembly module in `text` to Cretonne IL functions with the settings in
+/// `flags`. The `directives` of each function become the comments in its details.
+pub fn translate<'a>(text: &str,
+                     flags: &Flags,
+                     directives: Vec<Vec<&'a str>>)
+                     -> Result<Vec<(Function, Details<'a>)>> {
+    let data = wat::parse_str(text).map_err(|e| e.to_string())?;
+    let mut environ = DummyEnvironment::with_flags(flags.clone());
+    translate_module(&data, &mut environ)?;
+
+    let mut directives = directives.into_iter();
+    let mut functions = Vec::new();
+    for func in environ.info.function_bodies {
+        let comments = directives
+            .next()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|text| {
+                     Comment {
+                         entity: AnyEntity::Function,
+                         text: text,
+                     }
+                 })
+            .collect();
+        let details = Details {
+            location: Location::default(),
+            comments: comments,
+            map: SourceMap::default(),
+        };
+        functions.push((func, details));
+    }
+    Ok(functions)
+}
@@ -0,0 +1,485 @@
+//! The `reduce` sub-command.
+//!
+//! Read a Cretonne IL file containing a function that triggers a bug, and repeatedly simplify the
+//! function for as long as the bug persists. The bug to preserve is selected by a predicate:
+//!
+//! - `verifier`: The verifier rejects the function with the same error message.
+//! - `compile`: Compiling the function fails or panics with the same message.
+//! - `miscompare`: The function takes no arguments, and running it in the IL interpreter gives a
+//!   different result than compiling it for the host and running the machine code.
+//!
+//! The function is simplified by replacing terminators with traps, removing instructions and
+//! unreachable EBBs, replacing computed values with zeros, and halving constants. The
+//! reduced functions are printed on stdout.
+
+use CommandResult;
+use compile::{build_isa, compile_function};
+use cretonne::{CompiledFunction, Context};
+use cretonne::dominator_tree::DominatorTree;
+use cretonne::flowgraph::ControlFlowGraph;
+use cretonne::ir::{Function, Inst, InstBuilder, InstructionData, Opcode, Value, types};
+use cretonne::ir::immediates::{Ieee32, Ieee64};
+use cretonne::isa::TargetIsa;
+use cretonne::verifier::verify_function;
+use cton_interpreter::{Interpreter, DataValue};
+use cton_reader::parse_test;
+use run::{host_triple, runnable_type, CodeMemory};
+use std::any::Any;
+use std::collections::HashSet;
+use std::panic::{self, AssertUnwindSafe};
+use utils::read_to_string;
+
+/// Number of instructions the interpreter may execute before a function is considered to loop.
+const FUEL: u64 = 1_000_000;
+
+/// The kind of failure to preserve while reducing.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Predicate {
+    Verifier,
+    Compile,
+    Miscompare,
+}
+
+pub fn run(predicate: String,
+           files: Vec<String>,
+           target: Option<String>,
+           set: Vec<String>,
+           verbose: bool)
+           -> CommandResult {
+    let predicate = match predicate.as_str() {
+        "verifier" => Predicate::Verifier,
+        "compile" => Predicate::Compile,
+        "miscompare" => Predicate::Miscompare,
+        _ => {
+            return Err(format!("unknown predicate '{}', expected verifier, compile, or miscompare",
+                               predicate))
+        }
+    };
+
+    let target_isa = if predicate == Predicate::Miscompare {
+        if target.is_some() {
+            return Err("miscompare always runs code on the host, --target is not allowed"
+                           .to_string());
+        }
+        Some(build_isa(host_triple()?, &set)?)
+    } else if let Some(name) = target {
+        Some(build_isa(&name, &set)?)
+    } else if !set.is_empty() {
+        return Err("--set requires --target".to_string());
+    } else {
+        None
+    };
+
+    for (i, filename) in files.iter().enumerate() {
+        if i != 0 {
+            println!("");
+        }
+        reduce_file(filename, predicate, target_isa.as_ref().map(|isa| &**isa), verbose)
+            .map_err(|e| format!("{}: {}", filename, e))?;
+    }
+    Ok(())
+}
+
+fn reduce_file(filename: &str,
+               predicate: Predicate,
+               target_isa: Option<&TargetIsa>,
+               verbose: bool)
+               -> CommandResult {
+    let buffer = read_to_string(filename).map_err(|e| e.to_string())?;
+    let testfile = parse_test(&buffer).map_err(|e| e.to_string())?;
+    let isa = target_isa.or(testfile.isa_spec.unique_isa());
+    if predicate == Predicate::Compile && isa.is_none() {
+        return Err("compile needs exactly one ISA or a --target".to_string());
+    }
+
+    let reducer = Reducer {
+        predicate: predicate,
+        isa: isa,
+        verbose: verbose,
+    };
+    let mut reduced = 0;
+    for (func, _) in testfile.functions {
+        let failure = match reducer.check(&func) {
+            Some(failure) => failure,
+            None => {
+                if verbose {
+                    println!("; {}: skipped, the predicate doesn't hold", func.name);
+                }
+                continue;
+            }
+        };
+        if verbose {
+            println!("; {}: {}", func.name, failure);
+        }
+
+        let func = reducer.reduce(func, &failure);
+        if reduced != 0 {
+            println!("");
+        }
+        print!("{}", func);
+        reduced += 1;
+    }
+
+    if reduced == 0 {
+        Err("no functions satisfy the predicate".to_string())
+    } else {
+        Ok(())
+    }
+}
+
+/// A simplification of a single instruction.
+///
+/// Returns `false` if the mutation doesn't apply to the instruction.
+type Mutation = fn(&mut Function, Inst) -> bool;
+
+/// The mutations to try, in order. The coarsest simplifications come first.
+const MUTATIONS: &'static [(&'static str, Mutation)] =
+    &[("replaced terminator with trap", trap_terminator),
+      ("removed", remove_inst),
+      ("replaced with zero", replace_with_zero),
+      ("halved constant", halve_const)];
+
+struct Reducer<'a> {
+    predicate: Predicate,
+    isa: Option<&'a TargetIsa>,
+    verbose: bool,
+}
+
+impl<'a> Reducer<'a> {
+    /// Simplify `func` as long as it keeps failing in the same way as `failure`.
+    fn reduce(&self, mut func: Function, failure: &str) -> Function {
+        loop {
+            let mut progress = false;
+
+            let mut candidate = func.clone();
+            if remove_unreachable_ebbs(&mut candidate) && self.fails(&candidate, failure) {
+                if self.verbose {
+                    println!("; removed unreachable EBBs");
+                }
+                func = candidate;
+                progress = true;
+            }
+
+            for &(what, mutate) in MUTATIONS {
+                let insts: Vec<Inst> = func.layout
+                    .ebbs()
+                    .flat_map(|ebb| func.layout.ebb_insts(ebb))
+                    .collect();
+                for inst in insts {
+                    if func.layout.inst_ebb(inst).is_none() {
+                        continue;
+                    }
+                    let mut candidate = func.clone();
+                    if mutate(&mut candidate, inst) && self.fails(&candidate, failure) {
+                        if self.verbose {
+                            println!("; {}: {}", what, func.dfg.display_inst(inst));
+                        }
+                        func = candidate;
+                        progress = true;
+                    }
+                }
+            }
+
+            if !progress {
+                return func;
+            }
+        }
+    }
+
+    /// Does `func` still fail in the same way as `failure`?
+    fn fails(&self, func: &Function, failure: &str) -> bool {
+        match self.check(func) {
+            // The values computed by a miscompiled function change as it is reduced.
+            Some(f) => self.predicate == Predicate::Miscompare || f == failure,
+            None => false,
+        }
+    }
+
+    /// Check if the predicate holds for `func`, and describe the failure.
+    fn check(&self, func: &Function) -> Option<String> {
+        let verified = verify_function(func);
+        if self.predicate == Predicate::Verifier {
+            return verified.err().map(|e| e.to_string());
+        }
+        // The other predicates are only meaningful for valid functions. Otherwise the reducer
+        // would happily turn a code generator bug into an invalid input.
+        if verified.is_err() {
+            return None;
+        }
+
+        let isa = self.isa.expect("compile predicates need an ISA");
+        match self.predicate {
+            Predicate::Verifier => unreachable!(),
+            Predicate::Compile => compile_catching_panics(func, isa).err(),
+            Predicate::Miscompare => miscompare(func, isa),
+        }
+    }
+}
+
+/// Compile `func`, turning both errors and panics into an error message.
+///
+/// Only the first line of an error is returned since the rest is the pretty-printed function
+/// which changes as it is reduced.
+fn compile_catching_panics(func: &Function,
+                           isa: &TargetIsa)
+                           -> Result<CompiledFunction, String> {
+    let mut comp_ctx = Context::new();
+    comp_ctx.func = func.clone();
+
+    // Don't spam stderr with the panic messages we're expecting.
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let result = panic::catch_unwind(AssertUnwindSafe(|| compile_function(&mut comp_ctx, isa)));
+    panic::set_hook(hook);
+
+    match result {
+        Ok(Ok(compiled)) => Ok(compiled),
+        Ok(Err(msg)) => Err(msg.lines().next().unwrap_or("").to_string()),
+        Err(payload) => Err(format!("panic: {}", panic_message(&*payload))),
+    }
+}
+
+/// Get the message from a panic payload.
+fn panic_message(payload: &Any) -> &str {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        msg
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg
+    } else {
+        "unknown panic"
+    }
+}
+
+/// Run `func` in the interpreter and as native code, and describe any difference in results.
+fn miscompare(func: &Function, isa: &TargetIsa) -> Option<String> {
+    let ret_type = match runnable_type(func) {
+        Ok(ty) => ty,
+        Err(_) => return None,
+    };
+
+    // Functions that trap or loop in the interpreter can't be run natively.
+    let mut interpreter = Interpreter::new();
+    interpreter.set_fuel(Some(FUEL));
+    let expected = match interpreter.call(func, &[]) {
+        Ok(values) => values,
+        Err(_) => return None,
+    };
+
+    let compiled = match compile_catching_panics(func, isa) {
+        Ok(compiled) => compiled,
+        Err(_) => return None,
+    };
+    if !compiled.relocs.is_empty() {
+        return None;
+    }
+    let code = match CodeMemory::new(&compiled.code) {
+        Ok(code) => code,
+        Err(_) => return None,
+    };
+    let got: Vec<DataValue> = unsafe { code.call(ret_type) }.into_iter().collect();
+
+    if got == expected {
+        None
+    } else {
+        Some(format!("interpreter returned [{}], native code returned [{}]",
+                     format_values(&expected),
+                     format_values(&got)))
+    }
+}
+
+fn format_values(values: &[DataValue]) -> String {
+    values
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Remove all EBBs that can't be reached from the entry block.
+///
+/// Returns `false` if there were no unreachable EBBs.
+fn remove_unreachable_ebbs(func: &mut Function) -> bool {
+    let cfg = ControlFlowGraph::with_function(func);
+    let domtree = DominatorTree::with_function(func, &cfg);
+    let unreachable: Vec<_> = func.layout
+        .ebbs()
+        .filter(|&ebb| !domtree.is_reachable(ebb))
+        .collect();
+
+    for &ebb in &unreachable {
+        while let Some(inst) = func.layout.last_inst(ebb) {
+            func.layout.remove_inst(inst);
+        }
+        func.layout.remove_ebb(ebb);
+    }
+
+    // Jump tables may still refer to the removed EBBs, but they can only be used by unreachable
+    // `br_table` instructions.
+    let tables: Vec<_> = func.jump_tables.keys().collect();
+    for jt in tables {
+        let table = &mut func.jump_tables[jt];
+        for idx in 0..table.len() {
+            if table
+                   .get_entry(idx)
+                   .map_or(false, |ebb| unreachable.contains(&ebb)) {
+                table.clear_entry(idx);
+            }
+        }
+    }
+
+    !unreachable.is_empty()
+}
+
+/// Replace a terminator other than `trap` with a `trap` instruction.
+fn trap_terminator(func: &mut Function, inst: Inst) -> bool {
+    let opcode = func.dfg[inst].opcode();
+    if !opcode.is_terminator() || opcode == Opcode::Trap {
+        return false;
+    }
+    func.dfg.replace(inst).trap();
+    true
+}
+
+/// Remove a non-terminator instruction whose results are unused.
+fn remove_inst(func: &mut Function, inst: Inst) -> bool {
+    if func.dfg[inst].opcode().is_terminator() {
+        return false;
+    }
+    let used = used_values(func);
+    if func.dfg
+           .inst_results(inst)
+           .iter()
+           .any(|v| used.contains(v)) {
+        return false;
+    }
+    func.layout.remove_inst(inst);
+    true
+}
+
+/// Get the set of values used as instruction arguments.
+fn used_values(func: &Function) -> HashSet<Value> {
+    let mut used = HashSet::new();
+    for ebb in func.layout.ebbs() {
+        for inst in func.layout.ebb_insts(ebb) {
+            used.extend(func.dfg
+                            .inst_args(inst)
+                            .iter()
+                            .map(|&arg| func.dfg.resolve_aliases(arg)));
+        }
+    }
+    used
+}
+
+/// Replace an instruction computing a single integer or float value with a zero constant.
+fn replace_with_zero(func: &mut Function, inst: Inst) -> bool {
+    let is_zero = match func.dfg[inst] {
+        InstructionData::UnaryImm { imm, .. } => {
+            let x: i64 = imm.into();
+            x == 0
+        }
+        InstructionData::UnaryIeee32 { imm, .. } => imm.bits() == 0,
+        InstructionData::UnaryIeee64 { imm, .. } => imm.bits() == 0,
+        _ => false,
+    };
+    if is_zero || func.dfg.inst_results(inst).len() != 1 {
+        return false;
+    }
+    let ty = func.dfg.value_type(func.dfg.first_result(inst));
+    if ty.is_int() {
+        func.dfg.replace(inst).iconst(ty, 0);
+    } else if ty == types::F32 {
+        func.dfg.replace(inst).f32const(Ieee32::from_bits(0));
+    } else if ty == types::F64 {
+        func.dfg.replace(inst).f64const(Ieee64::from_bits(0));
+    } else {
+        return false;
+    }
+    true
+}
+
+/// Halve an integer constant that `replace_with_zero` couldn't get rid of.
+fn halve_const(func: &mut Function, inst: Inst) -> bool {
+    let x: i64 = match func.dfg[inst] {
+        InstructionData::UnaryImm { opcode: Opcode::Iconst, imm } => imm.into(),
+        _ => return false,
+    };
+    // `x.abs()` would overflow for `i64::MIN`.
+    if x >= -1 && x <= 1 {
+        return false;
+    }
+    let ty = func.dfg.value_type(func.dfg.first_result(inst));
+    func.dfg.replace(inst).iconst(ty, x / 2);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{halve_const, Predicate, Reducer};
+    use cretonne::ir::{Function, Cursor, InstBuilder, InstructionData, types};
+    use cton_reader::parse_functions;
+    use std::i64;
+
+    #[test]
+    fn halve() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let insts = {
+            let dfg = &mut func.dfg;
+            let pos = &mut Cursor::new(&mut func.layout);
+            pos.insert_ebb(ebb0);
+            [i64::MIN, 7, -1, 1]
+                .iter()
+                .map(|&x| {
+                         let v = dfg.ins(pos).iconst(types::I64, x);
+                         dfg.value_def(v).unwrap_inst()
+                     })
+                .collect::<Vec<_>>()
+        };
+        let imm = |func: &Function, i: usize| -> i64 {
+            match func.dfg[insts[i]] {
+                InstructionData::UnaryImm { imm, .. } => imm.into(),
+                _ => panic!("not an iconst"),
+            }
+        };
+
+        assert!(halve_const(&mut func, insts[0]));
+        assert_eq!(imm(&func, 0), i64::MIN / 2);
+        assert!(halve_const(&mut func, insts[1]));
+        assert_eq!(imm(&func, 1), 3);
+        assert!(!halve_const(&mut func, insts[2]));
+        assert!(!halve_const(&mut func, insts[3]));
+    }
+
+    #[test]
+    fn reduce_verifier() {
+        let func = parse_functions("function f(i32) {
+                                    ebb0(v0: i32):
+                                        v1 = iconst.i32 100
+                                        v2 = iadd v0, v1
+                                        v3 = iconst.i32 -9
+                                        brz v3, ebb1
+                                        v4 = iconst.i64 5
+                                        v5 = iadd v2, v4
+                                        return
+                                    ebb1:
+                                        return
+                                    }")
+                .unwrap()
+                .remove(0);
+        let reducer = Reducer {
+            predicate: Predicate::Verifier,
+            isa: None,
+            verbose: false,
+        };
+        let failure = reducer.check(&func).expect("the verifier should reject the function");
+        let func = reducer.reduce(func, &failure);
+        assert_eq!(func.to_string(),
+                   "function f(i32) {\n\
+                    ebb0(v0: i32):\n    \
+                    v2 = iconst.i32 0\n    \
+                    v4 = iconst.i64 0\n    \
+                    v5 = iadd v2, v4\n    \
+                    trap\n\
+                    }\n");
+    }
+}
@@ -0,0 +1,225 @@
+//! The `run` sub-command.
+//!
+//! Read a series of Cretonne IL files and compile their functions for the host ISA. Functions
+//! without arguments are then mapped into executable memory and called, and their return value is
+//! printed. This is a quick way of sanity-checking the generated code without an embedder.
+//!
+//! The `isa` and `set` commands in the files are ignored. The host ISA is configured with the
+//! `--set` options on the command line instead.
+
+use CommandResult;
+use compile::{build_isa, compile_function};
+use cretonne::Context;
+use cretonne::ir::{Function, ArgumentPurpose, Type, types};
+use cretonne::isa::TargetIsa;
+use cton_interpreter::DataValue;
+use cton_reader::parse_test;
+use utils::read_to_string;
+
+pub fn run(files: Vec<String>, set: Vec<String>, verbose: bool) -> CommandResult {
+    let isa = build_isa(host_triple()?, &set)?;
+    let mut comp_ctx = Context::new();
+    for filename in files {
+        run_one(&filename, &mut comp_ctx, &*isa, verbose)
+            .map_err(|e| format!("{}: {}", filename, e))?;
+    }
+    Ok(())
+}
+
+fn run_one(filename: &str,
+           comp_ctx: &mut Context,
+           isa: &TargetIsa,
+           verbose: bool)
+           -> CommandResult {
+    let buffer = read_to_string(filename).map_err(|e| e.to_string())?;
+    run_source(&buffer, comp_ctx, isa, verbose)
+}
+
+/// Compile and run the functions in `source`.
+fn run_source(source: &str,
+              comp_ctx: &mut Context,
+              isa: &TargetIsa,
+              verbose: bool)
+              -> CommandResult {
+    let testfile = parse_test(source).map_err(|e| e.to_string())?;
+
+    for (func, _) in testfile.functions {
+        let ret_type = match runnable_type(&func) {
+            Ok(ty) => ty,
+            Err(reason) => {
+                if verbose {
+                    println!("{}: skipped, {}", func.name, reason);
+                }
+                continue;
+            }
+        };
+
+        comp_ctx.clear();
+        comp_ctx.func = func;
+        let compiled = compile_function(comp_ctx, isa)?;
+        if !compiled.relocs.is_empty() {
+            return Err(format!("{}: can't run code with relocations", comp_ctx.func.name));
+        }
+        if verbose {
+            println!("{}: {} bytes", comp_ctx.func.name, compiled.code.len());
+        }
+
+        let code = CodeMemory::new(&compiled.code)?;
+        match unsafe { code.call(ret_type) } {
+            Some(value) => println!("{}: {}", comp_ctx.func.name, value),
+            None => println!("{}: ok", comp_ctx.func.name),
+        }
+    }
+    Ok(())
+}
+
+/// Get the target triple architecture to use for the host.
+pub fn host_triple() -> Result<&'static str, String> {
+    if cfg!(target_arch = "x86_64") {
+        Ok("x86_64")
+    } else if cfg!(target_arch = "x86") {
+        Ok("i686")
+    } else {
+        Err("the host ISA is not supported".to_string())
+    }
+}
+
+/// Check that `func` can be called without arguments, and get the type of its return value.
+pub fn runnable_type(func: &Function) -> Result<Option<Type>, &'static str> {
+    let sig = &func.signature;
+    if sig.argument_types
+           .iter()
+           .any(|arg| arg.purpose == ArgumentPurpose::Normal) {
+        return Err("function has arguments");
+    }
+    match sig.return_types.len() {
+        0 => Ok(None),
+        1 => {
+            match sig.return_types[0].value_type {
+                ty @ types::I8 | ty @ types::I16 | ty @ types::I32 | ty @ types::I64 |
+                ty @ types::B1 | ty @ types::F32 | ty @ types::F64 => Ok(Some(ty)),
+                _ => Err("unsupported return type"),
+            }
+        }
+        _ => Err("function has multiple return values"),
+    }
+}
+
+/// Machine code mapped into executable memory.
+pub struct CodeMemory {
+    ptr: *mut u8,
+    len: usize,
+}
+
+impl CodeMemory {
+    /// Copy `code` into a new executable mapping.
+    #[cfg(unix)]
+    pub fn new(code: &[u8]) -> Result<CodeMemory, String> {
+        use libc;
+        use std::ptr;
+
+        // Even an empty function needs a non-empty mapping.
+        let len = code.len().max(1);
+        unsafe {
+            let ptr = libc::mmap(ptr::null_mut(),
+                                 len,
+                                 libc::PROT_READ | libc::PROT_WRITE,
+                                 libc::MAP_PRIVATE | libc::MAP_ANON,
+                                 -1,
+                                 0);
+            if ptr == libc::MAP_FAILED {
+                return Err("failed to map code memory".to_string());
+            }
+            let mem = CodeMemory {
+                ptr: ptr as *mut u8,
+                len: len,
+            };
+            ptr::copy_nonoverlapping(code.as_ptr(), mem.ptr, code.len());
+            if libc::mprotect(ptr, len, libc::PROT_READ | libc::PROT_EXEC) != 0 {
+                return Err("failed to make code memory executable".to_string());
+            }
+            Ok(mem)
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn new(_code: &[u8]) -> Result<CodeMemory, String> {
+        Err("running code is only supported on Unix hosts".to_string())
+    }
+
+    /// Call the function at the start of the mapping, interpreting its return value as `ty`.
+    ///
+    /// This is unsafe because the code can do anything. The caller must ensure that it is a
+    /// function without arguments, returning `ty` in the native calling convention.
+    pub unsafe fn call(&self, ty: Option<Type>) -> Option<DataValue> {
+        use std::mem::transmute;
+
+        let ptr = self.ptr as *const u8;
+        let bits = match ty {
+            None => {
+                transmute::<_, extern "C" fn()>(ptr)();
+                return None;
+            }
+            Some(types::I8) => transmute::<_, extern "C" fn() -> i8>(ptr)() as u64,
+            Some(types::I16) => transmute::<_, extern "C" fn() -> i16>(ptr)() as u64,
+            Some(types::I32) => transmute::<_, extern "C" fn() -> i32>(ptr)() as u64,
+            Some(types::I64) => transmute::<_, extern "C" fn() -> i64>(ptr)() as u64,
+            Some(types::B1) => (transmute::<_, extern "C" fn() -> u8>(ptr)() & 1) as u64,
+            Some(types::F32) => transmute::<_, extern "C" fn() -> f32>(ptr)().to_bits() as u64,
+            Some(types::F64) => transmute::<_, extern "C" fn() -> f64>(ptr)().to_bits(),
+            Some(ty) => panic!("unsupported return type {}", ty),
+        };
+        ty.map(|ty| DataValue::from_bits(ty, bits))
+    }
+}
+
+impl Drop for CodeMemory {
+    #[cfg(unix)]
+    fn drop(&mut self) {
+        unsafe {
+            ::libc::munmap(self.ptr as *mut ::libc::c_void, self.len);
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn drop(&mut self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{host_triple, run_source};
+    use compile::build_isa;
+    use cretonne::Context;
+
+    #[test]
+    fn two_functions() {
+        let isa = match host_triple() {
+            Ok(triple) => build_isa(triple, &[]).unwrap(),
+            Err(_) => return,
+        };
+        let mut comp_ctx = Context::new();
+        run_source("function f() -> i32 {
+                    ebb0:
+                        v0 = iconst.i32 1
+                        brz v0, ebb1
+                        return v0
+                    ebb1:
+                        v1 = iconst.i32 2
+                        return v1
+                    }
+
+                    function g() -> i32 {
+                    ebb0:
+                        v0 = iconst.i32 3
+                        brnz v0, ebb1
+                        return v0
+                    ebb1:
+                        v1 = iconst.i32 4
+                        return v1
+                    }",
+                   &mut comp_ctx,
+                   &*isa,
+                   false)
+                .unwrap();
+    }
+}
@@ -1,21 +1,27 @@
 //! Utility functions.
 
-use cretonne::ir::entities::AnyEntity;
-use cretonne::{ir, verifier, write_function};
-use cretonne::result::CtonError;
-use std::fmt::Write;
 use std::fs::File;
-use std::io::{Result, Read};
+use std::io::{self, Result, Read};
 use std::path::Path;
 
 /// Read an entire file into a string.
+///
+/// The path `-` reads standard input instead, so IL can be piped into the commands.
 pub fn read_to_string<P: AsRef<Path>>(path: P) -> Result<String> {
-    let mut file = File::open(path)?;
     let mut buffer = String::new();
-    file.read_to_string(&mut buffer)?;
+    if is_stdin(&path) {
+        io::stdin().read_to_string(&mut buffer)?;
+    } else {
+        File::open(path)?.read_to_string(&mut buffer)?;
+    }
     Ok(buffer)
 }
 
+/// Does `path` name standard input?
+pub fn is_stdin<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref() == Path::new("-")
+}
+
 /// Look for a directive in a comment string.
 /// The directive is of the form "foo:" and should follow the leading `;` in the comment:
 ///
@@ -33,28 +39,6 @@ pub fn match_directive<'a>(comment: &'a str, directive: &str) -> Option<&'a str>
     }
 }
 
-/// Pretty-print a verifier error.
-pub fn pretty_verifier_error(func: &ir::Function, err: verifier::Error) -> String {
-    let mut msg = err.to_string();
-    match err.location {
-        AnyEntity::Inst(inst) => {
-            write!(msg, "\n{}: {}\n\n", inst, func.dfg.display_inst(inst)).unwrap()
-        }
-        _ => msg.push('\n'),
-    }
-    write_function(&mut msg, func, None).unwrap();
-    msg
-}
-
-/// Pretty-print a Cretonne error.
-pub fn pretty_error(func: &ir::Function, err: CtonError) -> String {
-    if let CtonError::Verifier(e) = err {
-        pretty_verifier_error(func, e)
-    } else {
-        err.to_string()
-    }
-}
-
 #[test]
 fn test_match_directive() {
     assert_eq!(match_directive("; foo: bar  ", "foo:"), Some("bar"));
@@ -7,6 +7,7 @@
 # - Make a debug build of all crates.
 # - Make a release build of cton-util.
 # - Run file-level tests with the release build of cton-util.
+# - Compare compiled random functions with the interpreter on Intel hosts.
 #
 # All tests run by this script should be passing at all times.
 
@@ -59,7 +60,7 @@ if [ -n "$needcheck" ]; then
     touch $tsfile || echo no target directory
 fi
 
-PKGS="cretonne cretonne-reader cretonne-tools filecheck"
+PKGS="cretonne cretonne-reader cretonne-obj cretonne-frontend cretonne-wasm cretonne-interpreter cretonne-c cretonne-tools filecheck"
 cd "$topdir"
 for PKG in $PKGS
 do
@@ -81,4 +82,10 @@ cd "$topdir"
 banner "File tests"
 "$CTONUTIL" test filetests docs
 
+# The differential tests run the generated code on the host.
+if [ "$(uname -m)" = x86_64 ]; then
+    banner "Differential tests"
+    "$CTONUTIL" difftest
+fi
+
 banner "OK"
@@ -0,0 +1,48 @@
+//! Check that compiling the benchmark corpus is deterministic.
+//!
+//! The `test determinism` filetests compile an already parsed function several times. This test
+//! also parses the source text anew for each compilation, so iteration over hash maps in the
+//! parser or in any of the passes would show up as different output between the two runs.
+
+extern crate cretonne;
+extern crate cton_reader;
+
+use cretonne::isa::TargetIsa;
+use cretonne::settings::{self, Configurable};
+use cretonne::{isa, CompiledFunction, Context};
+
+const CORPUS: &'static str = include_str!("../benches/corpus.cton");
+
+/// The annotated IL, machine code, relocations, and trap sites of a compiled function.
+type Output = (String, CompiledFunction);
+
+/// Parse and compile all the functions in the corpus.
+fn compile_corpus(isa: &TargetIsa) -> Vec<Output> {
+    let mut ctx = Context::new();
+    let mut outputs = Vec::new();
+    for func in cton_reader::parse_functions(CORPUS).unwrap() {
+        ctx.clear();
+        ctx.func = func;
+        let compiled = ctx.compile_and_emit(isa)
+            .unwrap_or_else(|e| panic!("{}: {}", ctx.func.name, e));
+        outputs.push((ctx.func.display(isa).to_string(), compiled));
+    }
+    outputs
+}
+
+#[test]
+fn corpus_twice() {
+    let mut flag_builder = settings::builder();
+    flag_builder.enable("is_64bit").unwrap();
+    flag_builder.set("opt_level", "best").unwrap();
+    let isa = isa::lookup("intel")
+        .expect("the Intel ISA is not available")
+        .finish(settings::Flags::new(&flag_builder));
+
+    let first = compile_corpus(&*isa);
+    let second = compile_corpus(&*isa);
+    assert_eq!(first.len(), second.len());
+    for (a, b) in first.iter().zip(&second) {
+        assert_eq!(a, b, "different output for:\n{}", a.0);
+    }
+}